@@ -1,3 +1,39 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Public half of the vendor's Ed25519 license key, as 64 hex digits. The
+// vendor hands it to whoever builds releases; it is not kept in the source.
+const LICENSE_KEY_VAR: &str = "TRUCKORE_LICENSE_PUBLIC_KEY";
+
+fn license_public_key() -> Option<[u8; 32]> {
+    println!("cargo:rerun-if-env-changed={}", LICENSE_KEY_VAR);
+    let Ok(hex) = env::var(LICENSE_KEY_VAR) else {
+        // Debug builds without the key run on the trial only
+        if env::var("PROFILE").as_deref() == Ok("release") {
+            panic!(
+                "{} must be set to the vendor's license public key for release builds",
+                LICENSE_KEY_VAR
+            );
+        }
+        return None;
+    };
+    let hex = hex.trim();
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .filter_map(|i| hex.get(i..i + 2))
+        .map(|pair| u8::from_str_radix(pair, 16))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|_| panic!("{} is not hex", LICENSE_KEY_VAR));
+    let key = bytes
+        .try_into()
+        .unwrap_or_else(|_| panic!("{} must be 32 bytes (64 hex digits)", LICENSE_KEY_VAR));
+    Some(key)
+}
+
 fn main() {
+    let key = license_public_key();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("license_public_key.rs");
+    fs::write(out, format!("{:?}", key)).unwrap();
     tauri_build::build()
 }
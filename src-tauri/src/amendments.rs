@@ -0,0 +1,336 @@
+// Weighment amendment workflow for Truckore Pro
+// Operators request corrections; only a supervisor's approval (via the approval
+// queue) changes the ticket.
+// Requests are immutable once decided (enforced by triggers in schema.sql).
+
+use crate::approvals::{self, Approval, NewApproval};
+use crate::command_audit;
+use crate::currency;
+use crate::db;
+use crate::deductions;
+use crate::inventory;
+use crate::roles::{self, Role};
+use crate::signatures;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// Fields an amendment may change, and whether they hold weights/amounts
+const AMENDABLE_FIELDS: &[(&str, bool)] = &[
+    ("vehicle_no", false),
+    ("party_name", false),
+    ("product_name", false),
+    ("remarks", false),
+    ("gross_weight", true),
+    ("tare_weight", true),
+    ("charges_minor", true),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Amendment {
+    pub id: i64,
+    pub weighment_id: String,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub reason: String,
+    pub requested_by: String,
+    pub requested_at: String,
+    pub status: String,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<String>,
+    pub decision_comment: Option<String>,
+}
+
+fn is_numeric_field(field: &str) -> Result<bool, String> {
+    AMENDABLE_FIELDS
+        .iter()
+        .find(|(name, _)| *name == field)
+        .map(|(_, numeric)| *numeric)
+        .ok_or_else(|| format!("Field cannot be amended: {}", field))
+}
+
+// A numeric field's new value; amounts (`*_minor`) are whole paise
+fn number(field: &str, value: &str) -> Result<rusqlite::types::Value, String> {
+    if field.ends_with("_minor") {
+        value
+            .parse::<i64>()
+            .map(rusqlite::types::Value::Integer)
+            .map_err(|_| format!("{} must be a whole number of paise", field))
+    } else {
+        value
+            .parse::<f64>()
+            .map(rusqlite::types::Value::Real)
+            .map_err(|_| format!("{} must be a number", field))
+    }
+}
+
+// Current value of a weighment field rendered as text
+fn current_value(
+    conn: &Connection,
+    weighment_id: &str,
+    field: &str,
+) -> Result<Option<String>, String> {
+    is_numeric_field(field)?;
+    let sql = format!(
+        "SELECT CAST({} AS TEXT) FROM weighments WHERE id = ?1",
+        field
+    );
+    conn.query_row(&sql, [weighment_id], |row| row.get::<_, Option<String>>(0))
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Weighment {} not found", weighment_id))
+}
+
+fn load_amendment(conn: &Connection, id: i64) -> Result<Amendment, String> {
+    conn.query_row(
+        "SELECT id, weighment_id, field, old_value, new_value, reason, requested_by,
+                requested_at, status, decided_by, decided_at, decision_comment
+         FROM weighment_amendments WHERE id = ?1",
+        [id],
+        row_to_amendment,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Amendment {} not found", id))
+}
+
+fn row_to_amendment(row: &rusqlite::Row) -> rusqlite::Result<Amendment> {
+    Ok(Amendment {
+        id: row.get(0)?,
+        weighment_id: row.get(1)?,
+        field: row.get(2)?,
+        old_value: row.get(3)?,
+        new_value: row.get(4)?,
+        reason: row.get(5)?,
+        requested_by: row.get(6)?,
+        requested_at: row.get(7)?,
+        status: row.get(8)?,
+        decided_by: row.get(9)?,
+        decided_at: row.get(10)?,
+        decision_comment: row.get(11)?,
+    })
+}
+
+// Submit a correction request for one field of a weighment.
+// Queues a supervisor approval; returns the amendment id.
+#[tauri::command]
+pub fn request_amendment(
+    app: AppHandle,
+    weighment_id: String,
+    field: String,
+    new_value: Option<String>,
+    reason: String,
+    user_id: String,
+) -> Result<i64, String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is required for an amendment".to_string());
+    }
+    let mut conn = db::open(&app)?;
+    roles::user_role(&conn, &user_id)?;
+
+    if is_numeric_field(&field)? {
+        if let Some(v) = &new_value {
+            number(&field, v)?;
+        }
+    }
+    let old_value = current_value(&conn, &weighment_id, &field)?;
+    let ticket_no: String = conn
+        .query_row(
+            "SELECT ticket_no FROM weighments WHERE id = ?1",
+            [&weighment_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let summary = format!(
+        "Ticket {}: change {} from '{}' to '{}' ({})",
+        ticket_no,
+        field,
+        old_value.as_deref().unwrap_or(""),
+        new_value.as_deref().unwrap_or(""),
+        reason
+    );
+    let approval = approvals::submit(
+        &tx,
+        NewApproval {
+            kind: "amendment",
+            entity: "weighment",
+            entity_id: &weighment_id,
+            summary: &summary,
+            payload: None,
+            approver_role: Role::Admin,
+            requested_by: &user_id,
+        },
+    )?;
+    tx.execute(
+        "INSERT INTO weighment_amendments
+            (weighment_id, field, old_value, new_value, reason, requested_by, approval_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            weighment_id,
+            field,
+            old_value,
+            new_value,
+            reason,
+            user_id,
+            approval.id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = tx.last_insert_rowid();
+    tx.commit().map_err(|e| e.to_string())?;
+
+    approvals::announce(&app, &conn, &approval)?;
+    Ok(id)
+}
+
+// Approval handler: record the decision and, when approved, apply the change.
+// Runs inside the approval decision transaction.
+pub fn apply_decision(
+    conn: &Connection,
+    approval: &Approval,
+    approved: bool,
+) -> Result<(), String> {
+    let id: i64 = conn
+        .query_row(
+            "SELECT id FROM weighment_amendments WHERE approval_id = ?1",
+            [approval.id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let amendment = load_amendment(conn, id)?;
+
+    if approved {
+        // Refuse to overwrite a value that changed after the request was made
+        let current = current_value(conn, &amendment.weighment_id, &amendment.field)?;
+        if current != amendment.old_value {
+            return Err(format!(
+                "{} changed since the amendment was requested; submit a new request",
+                amendment.field
+            ));
+        }
+
+        let value: rusqlite::types::Value =
+            match (&amendment.new_value, is_numeric_field(&amendment.field)?) {
+                (None, _) => rusqlite::types::Value::Null,
+                (Some(v), true) => number(&amendment.field, v)?,
+                (Some(v), false) => rusqlite::types::Value::Text(v.clone()),
+            };
+        conn.execute(
+            &format!(
+                "UPDATE weighments SET {} = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                amendment.field
+            ),
+            params![value, amendment.weighment_id],
+        )
+        .map_err(|e| e.to_string())?;
+
+        if amendment.field == "gross_weight" || amendment.field == "tare_weight" {
+            conn.execute(
+                "UPDATE weighments SET net_weight = gross_weight - tare_weight
+                 WHERE id = ?1 AND gross_weight IS NOT NULL AND tare_weight IS NOT NULL",
+                [&amendment.weighment_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        signatures::sign(conn, &amendment.weighment_id)?;
+        // Weights, party or material may change which deductions apply
+        deductions::reapply(conn, &amendment.weighment_id)?;
+        inventory::post_weighment(conn, &amendment.weighment_id)?;
+        currency::bill_weighment(conn, &amendment.weighment_id)?;
+    }
+
+    conn.execute(
+        "UPDATE weighment_amendments
+         SET status = ?1, decided_by = ?2, decided_at = ?3, decision_comment = ?4
+         WHERE id = ?5",
+        params![
+            approval.status,
+            approval.decided_by,
+            approval.decided_at,
+            approval.comment,
+            id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn approval_id_for(app: &AppHandle, id: i64) -> Result<i64, String> {
+    let conn = db::open(app)?;
+    conn.query_row(
+        "SELECT approval_id FROM weighment_amendments WHERE id = ?1",
+        [id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Amendment {} not found", id))
+}
+
+// Approve an amendment by its own id (shortcut for approve_request)
+#[tauri::command]
+pub fn approve_amendment(
+    app: AppHandle,
+    id: i64,
+    user_id: String,
+    comment: Option<String>,
+) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "approve_amendment",
+        &user_id,
+        serde_json::json!({ "id": id, "comment": comment }),
+        || {
+            let approval_id = approval_id_for(&app, id)?;
+            approvals::decide(&app, approval_id, true, &user_id, comment).map(|_| ())
+        },
+    )
+}
+
+// Reject an amendment by its own id (shortcut for reject_request)
+#[tauri::command]
+pub fn reject_amendment(
+    app: AppHandle,
+    id: i64,
+    user_id: String,
+    comment: Option<String>,
+) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "reject_amendment",
+        &user_id,
+        serde_json::json!({ "id": id, "comment": comment }),
+        || {
+            let approval_id = approval_id_for(&app, id)?;
+            approvals::decide(&app, approval_id, false, &user_id, comment).map(|_| ())
+        },
+    )
+}
+
+// List amendments, optionally for one weighment and/or status
+#[tauri::command]
+pub fn list_amendments(
+    app: AppHandle,
+    weighment_id: Option<String>,
+    status: Option<String>,
+) -> Result<Vec<Amendment>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, weighment_id, field, old_value, new_value, reason, requested_by,
+                    requested_at, status, decided_by, decided_at, decision_comment
+             FROM weighment_amendments
+             WHERE (?1 IS NULL OR weighment_id = ?1) AND (?2 IS NULL OR status = ?2)
+             ORDER BY id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![weighment_id, status], row_to_amendment)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
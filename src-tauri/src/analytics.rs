@@ -0,0 +1,642 @@
+// Weighment analytics for Truckore Pro
+// Flags statistically unusual tickets into a review queue, and reports how
+// busy the weighbridge is per day and per shift, leaving logged downtime
+// (scale_maintenance.rs) out
+
+use crate::auditor;
+use crate::db::{self, DateRange};
+use crate::scale_maintenance;
+use crate::training;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+// A vehicle needs this many prior tares before deviations are meaningful
+const MIN_TARE_HISTORY: i64 = 3;
+const TARE_DEVIATION_SIGMA: f64 = 3.0;
+// Ignore deviations smaller than this even on vehicles with very stable tares
+const TARE_DEVIATION_MIN_KG: f64 = 200.0;
+const MIN_MATERIAL_SAMPLES: i64 = 10;
+const NET_OUTLIER_SIGMA: f64 = 3.0;
+// Two weighings of the same truck closer together than this are not plausible
+const MIN_TURNAROUND_SECS: f64 = 120.0;
+// Tare drift report defaults: compare the latest tares against the earlier baseline
+const DEFAULT_RECENT_TARES: usize = 5;
+const DEFAULT_DRIFT_THRESHOLD_KG: f64 = 200.0;
+
+// Mean / standard deviation of a weight series
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Stats {
+    pub count: i64,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl Stats {
+    // Compute directly from a list of values
+    pub fn of(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Stats {
+                count: 0,
+                mean: 0.0,
+                std_dev: 0.0,
+            };
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let mean_sq = values.iter().map(|v| v * v).sum::<f64>() / n;
+        Stats::from_moments(values.len() as i64, mean, mean_sq)
+    }
+
+    // Build from SQL aggregates COUNT(x), AVG(x) and AVG(x * x)
+    pub fn from_moments(count: i64, mean: f64, mean_sq: f64) -> Self {
+        let variance = (mean_sq - mean * mean).max(0.0);
+        Stats {
+            count,
+            mean,
+            std_dev: variance.sqrt(),
+        }
+    }
+
+    // Statistics of the same series with one observation left out, so a
+    // ticket is judged against the others rather than against itself
+    pub fn without(&self, value: f64) -> Self {
+        if self.count <= 1 {
+            return Stats {
+                count: 0,
+                mean: 0.0,
+                std_dev: 0.0,
+            };
+        }
+        let n = self.count as f64;
+        let mean_sq = self.std_dev * self.std_dev + self.mean * self.mean;
+        Stats::from_moments(
+            self.count - 1,
+            (self.mean * n - value) / (n - 1.0),
+            (mean_sq * n - value * value) / (n - 1.0),
+        )
+    }
+
+    // Number of standard deviations `value` lies from the mean
+    pub fn z_score(&self, value: f64) -> f64 {
+        if self.std_dev > 0.0 {
+            (value - self.mean) / self.std_dev
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub id: i64,
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    pub product_name: String,
+    pub kind: String,
+    pub details: String,
+    pub score: f64,
+    pub status: String,
+    pub detected_at: String,
+    pub weighed_at: String,
+}
+
+struct Candidate {
+    weighment_id: String,
+    kind: &'static str,
+    details: String,
+    score: f64,
+}
+
+fn grouped_stats(
+    conn: &Connection,
+    group_col: &str,
+    value_col: &str,
+) -> Result<HashMap<String, Stats>, String> {
+    let sql = format!(
+        "SELECT {g}, COUNT({v}), AVG({v}), AVG({v} * {v}) FROM weighments
+         WHERE {v} IS NOT NULL AND id NOT IN (SELECT weighment_id FROM ticket_voids)
+           AND {p}
+         GROUP BY {g}",
+        g = group_col,
+        v = value_col,
+        p = training::exclude_practice("id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Stats::from_moments(row.get(1)?, row.get(2)?, row.get(3)?),
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut result = HashMap::new();
+    for row in rows {
+        let (key, stats) = row.map_err(|e| e.to_string())?;
+        result.insert(key, stats);
+    }
+    Ok(result)
+}
+
+fn detect(conn: &Connection, range: &DateRange) -> Result<Vec<Candidate>, String> {
+    let tare_stats = grouped_stats(conn, "vehicle_no", "tare_weight")?;
+    let net_stats = grouped_stats(conn, "product_name", "net_weight")?;
+
+    let sql = format!(
+        "SELECT id, vehicle_no, product_name, tare_weight, net_weight, first_weight_type,
+                (julianday(second_weight_timestamp) - julianday(created_at)) * 86400.0
+         FROM weighments
+         WHERE {} BETWEEN ?1 AND ?2 AND {}",
+        db::local_date("created_at"),
+        training::exclude_practice("id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query(params![range.from, range.to])
+        .map_err(|e| e.to_string())?;
+
+    let mut candidates = Vec::new();
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let id: String = row.get(0).map_err(|e| e.to_string())?;
+        let vehicle_no: String = row.get(1).map_err(|e| e.to_string())?;
+        let product_name: String = row.get(2).map_err(|e| e.to_string())?;
+        let tare: Option<f64> = row.get(3).map_err(|e| e.to_string())?;
+        let net: Option<f64> = row.get(4).map_err(|e| e.to_string())?;
+        let first_weight_type: Option<String> = row.get(5).map_err(|e| e.to_string())?;
+        let turnaround: Option<f64> = row.get(6).map_err(|e| e.to_string())?;
+
+        if let (Some(tare), Some(all)) = (tare, tare_stats.get(&vehicle_no)) {
+            let stats = all.without(tare);
+            let z = stats.z_score(tare);
+            if stats.count >= MIN_TARE_HISTORY
+                && z.abs() > TARE_DEVIATION_SIGMA
+                && (tare - stats.mean).abs() > TARE_DEVIATION_MIN_KG
+            {
+                candidates.push(Candidate {
+                    weighment_id: id.clone(),
+                    kind: "TARE_DEVIATION",
+                    details: format!(
+                        "Tare {:.0} kg vs historical mean {:.0} kg (σ {:.0} kg, {} tickets)",
+                        tare, stats.mean, stats.std_dev, stats.count
+                    ),
+                    score: z.abs(),
+                });
+            }
+        }
+
+        if let (Some(net), Some(all)) = (net, net_stats.get(&product_name)) {
+            let stats = all.without(net);
+            let z = stats.z_score(net);
+            if stats.count >= MIN_MATERIAL_SAMPLES && z.abs() > NET_OUTLIER_SIGMA {
+                candidates.push(Candidate {
+                    weighment_id: id.clone(),
+                    kind: "NET_OUTLIER",
+                    details: format!(
+                        "Net {:.0} kg vs {} mean {:.0} kg (σ {:.0} kg)",
+                        net, product_name, stats.mean, stats.std_dev
+                    ),
+                    score: z.abs(),
+                });
+            }
+        }
+
+        let two_pass = matches!(first_weight_type.as_deref(), Some("gross") | Some("tare"));
+        if let (true, Some(secs)) = (two_pass, turnaround) {
+            if (0.0..MIN_TURNAROUND_SECS).contains(&secs) {
+                candidates.push(Candidate {
+                    weighment_id: id.clone(),
+                    kind: "FAST_TURNAROUND",
+                    details: format!(
+                        "Second weighing {:.0}s after the first (minimum {:.0}s)",
+                        secs, MIN_TURNAROUND_SECS
+                    ),
+                    score: MIN_TURNAROUND_SECS / secs.max(1.0),
+                });
+            }
+        }
+    }
+
+    Ok(candidates)
+}
+
+// Scan weighments in the range and queue any new anomalies for review.
+// Returns the number of newly flagged anomalies; re-scans are idempotent.
+#[tauri::command]
+pub fn scan_anomalies(app: AppHandle, range: DateRange) -> Result<usize, String> {
+    let conn = db::open(&app)?;
+    let candidates = detect(&conn, &range)?;
+
+    let mut inserted = 0;
+    for c in candidates {
+        inserted += conn
+            .execute(
+                "INSERT OR IGNORE INTO weighment_anomalies (weighment_id, kind, details, score)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![c.weighment_id, c.kind, c.details, c.score],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(inserted)
+}
+
+// List queued anomalies for tickets weighed within the range
+#[tauri::command]
+pub fn list_anomalies(
+    app: AppHandle,
+    range: DateRange,
+    status: Option<String>,
+) -> Result<Vec<Anomaly>, String> {
+    let conn = db::open(&app)?;
+
+    let sql = format!(
+        "SELECT a.id, a.weighment_id, w.ticket_no, w.vehicle_no, w.product_name,
+                a.kind, a.details, a.score, a.status, a.detected_at, w.created_at
+         FROM weighment_anomalies a
+         JOIN weighments w ON w.id = a.weighment_id
+         WHERE {} BETWEEN ?1 AND ?2 AND (?3 IS NULL OR a.status = ?3)
+         ORDER BY a.score DESC",
+        db::local_date("w.created_at")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to, status], |row| {
+            Ok(Anomaly {
+                id: row.get(0)?,
+                weighment_id: row.get(1)?,
+                ticket_no: row.get(2)?,
+                vehicle_no: row.get(3)?,
+                product_name: row.get(4)?,
+                kind: row.get(5)?,
+                details: row.get(6)?,
+                score: row.get(7)?,
+                status: row.get(8)?,
+                detected_at: row.get(9)?,
+                weighed_at: row.get(10)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Record the reviewer's decision on a queued anomaly
+#[tauri::command]
+pub fn review_anomaly(
+    app: AppHandle,
+    id: i64,
+    status: String,
+    reviewed_by: String,
+    note: Option<String>,
+) -> Result<(), String> {
+    if !matches!(status.as_str(), "CONFIRMED" | "DISMISSED") {
+        return Err(format!("Invalid review status: {}", status));
+    }
+
+    let conn = db::open(&app)?;
+    let updated = conn
+        .execute(
+            "UPDATE weighment_anomalies
+             SET status = ?1, reviewed_by = ?2, review_note = ?3, reviewed_at = CURRENT_TIMESTAMP
+             WHERE id = ?4",
+            params![status, reviewed_by, note, id],
+        )
+        .map_err(|e| e.to_string())?;
+
+    if updated == 0 {
+        return Err(format!("Anomaly {} not found", id));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VehicleTareStats {
+    pub vehicle_no: String,
+    pub overall: Stats,
+    pub baseline: Stats,
+    pub recent: Stats,
+    pub min_tare: f64,
+    pub max_tare: f64,
+    pub last_weighed_at: String,
+    pub drift_kg: f64,
+    pub drifting: bool,
+}
+
+// Per-vehicle tare mean / std-dev over the range, highlighting vehicles whose
+// most recent tares drift from their earlier baseline by more than the threshold
+#[tauri::command]
+pub fn tare_statistics_report(
+    app: AppHandle,
+    range: DateRange,
+    recent_count: Option<usize>,
+    drift_threshold_kg: Option<f64>,
+) -> Result<Vec<VehicleTareStats>, String> {
+    let recent_count = recent_count.unwrap_or(DEFAULT_RECENT_TARES).max(1);
+    let threshold = drift_threshold_kg.unwrap_or(DEFAULT_DRIFT_THRESHOLD_KG);
+    let conn = db::open(&app)?;
+
+    let sql = format!(
+        "SELECT vehicle_no, tare_weight, created_at FROM weighments
+         WHERE tare_weight IS NOT NULL AND {} BETWEEN ?1 AND ?2
+           AND id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}
+         ORDER BY vehicle_no, created_at",
+        db::local_date("created_at"),
+        training::exclude_practice("id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows: Vec<(String, f64, String)> = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    // Rows are ordered by vehicle, so each vehicle is one contiguous run
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for i in 1..=rows.len() {
+        if i == rows.len() || rows[i].0 != rows[start].0 {
+            groups.push(&rows[start..i]);
+            start = i;
+        }
+    }
+
+    let mut report = Vec::new();
+    for group in groups {
+        let tares: Vec<f64> = group.iter().map(|r| r.1).collect();
+        let split = tares.len().saturating_sub(recent_count);
+        let baseline = Stats::of(&tares[..split]);
+        let recent = Stats::of(&tares[split..]);
+        // Without an earlier baseline there is nothing to drift from
+        let drift_kg = if baseline.count > 0 {
+            recent.mean - baseline.mean
+        } else {
+            0.0
+        };
+
+        report.push(VehicleTareStats {
+            vehicle_no: group[0].0.clone(),
+            overall: Stats::of(&tares),
+            baseline,
+            recent,
+            min_tare: tares.iter().cloned().fold(f64::INFINITY, f64::min),
+            max_tare: tares.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            last_weighed_at: group[group.len() - 1].2.clone(),
+            drift_kg,
+            drifting: drift_kg.abs() > threshold,
+        });
+    }
+
+    // Drifting vehicles first, largest drift at the top
+    report.sort_by(|a, b| {
+        b.drifting
+            .cmp(&a.drifting)
+            .then(b.drift_kg.abs().total_cmp(&a.drift_kg.abs()))
+    });
+    Ok(report)
+}
+
+// Gaps between bridge weighings longer than this count as idle time
+const DEFAULT_IDLE_GAP_MINUTES: f64 = 15.0;
+
+// One weighing on the bridge: a first weighing, or a second one closing a
+// two-pass ticket
+struct BridgeEvent {
+    date: String,
+    hour: usize,
+    secs: f64,
+    // Weigh-in to weigh-out, on second weighings
+    cycle_secs: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Utilization {
+    // Weighings on the bridge, first and second
+    pub weighings: i64,
+    // Two-pass tickets weighed out
+    pub completed: i64,
+    // The period less downtime
+    pub span_secs: f64,
+    pub downtime_secs: f64,
+    pub weighings_per_hour: f64,
+    pub avg_cycle_secs: Option<f64>,
+    pub idle_gaps: i64,
+    pub idle_secs: f64,
+    pub longest_idle_secs: f64,
+    // Share of the span not lost to idle gaps
+    pub utilization_pct: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DayUtilization {
+    pub date: String,
+    // Weighings in each local hour, 0..23
+    pub hourly: Vec<i64>,
+    pub busiest_hour: Option<usize>,
+    #[serde(flatten)]
+    pub utilization: Utilization,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShiftUtilization {
+    pub shift_id: i64,
+    pub operator_id: String,
+    pub opened_at: String,
+    pub closed_at: Option<String>,
+    #[serde(flatten)]
+    pub utilization: Utilization,
+}
+
+// Bridge weighings between two julianday instants in seconds, oldest first
+fn bridge_events(conn: &Connection, start: f64, end: f64) -> Result<Vec<BridgeEvent>, String> {
+    let filter = format!(
+        "id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}",
+        training::exclude_practice("id")
+    );
+    let sql = format!(
+        "SELECT date(at, 'localtime'), CAST(strftime('%H', at, 'localtime') AS INTEGER),
+                julianday(at) * 86400.0, cycle_secs
+         FROM (SELECT created_at AS at, NULL AS cycle_secs FROM weighments WHERE {f}
+               UNION ALL
+               SELECT second_weight_timestamp,
+                      (julianday(second_weight_timestamp) - julianday(created_at)) * 86400.0
+               FROM weighments
+               WHERE second_weight_timestamp IS NOT NULL
+                 AND first_weight_type IN ('gross', 'tare') AND {f})
+         WHERE julianday(at) * 86400.0 >= ?1 AND julianday(at) * 86400.0 < ?2
+         ORDER BY julianday(at)",
+        f = filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![start, end], |row| {
+            Ok(BridgeEvent {
+                date: row.get(0)?,
+                hour: row.get::<_, i64>(1)?.clamp(0, 23) as usize,
+                secs: row.get(2)?,
+                cycle_secs: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Figures for the events of one period, `window` being its start and end.
+// Waits before the first and after the last weighing count as gaps too;
+// time in `downtime` counts neither toward the span nor as idle.
+fn utilization(
+    events: &[&BridgeEvent],
+    window: (f64, f64),
+    downtime: &[(f64, f64)],
+    idle_gap_secs: f64,
+) -> Utilization {
+    let (start, end) = window;
+    let downtime_secs = scale_maintenance::overlap(downtime, start, end);
+    let span_secs = (end - start - downtime_secs).max(0.0);
+    let mut instants = vec![start];
+    instants.extend(events.iter().map(|e| e.secs));
+    instants.push(end);
+    let gaps: Vec<f64> = instants
+        .windows(2)
+        .map(|pair| pair[1] - pair[0] - scale_maintenance::overlap(downtime, pair[0], pair[1]))
+        .filter(|gap| *gap > idle_gap_secs)
+        .collect();
+    let idle_secs = gaps.iter().sum::<f64>();
+    let cycles: Vec<f64> = events
+        .iter()
+        .filter_map(|e| e.cycle_secs)
+        .filter(|secs| *secs >= 0.0)
+        .collect();
+    let hours = span_secs / 3600.0;
+    Utilization {
+        weighings: events.len() as i64,
+        completed: cycles.len() as i64,
+        span_secs,
+        downtime_secs,
+        weighings_per_hour: if hours > 0.0 {
+            events.len() as f64 / hours
+        } else {
+            0.0
+        },
+        avg_cycle_secs: (!cycles.is_empty()).then(|| Stats::of(&cycles).mean),
+        idle_gaps: gaps.len() as i64,
+        idle_secs,
+        longest_idle_secs: gaps.iter().cloned().fold(0.0, f64::max),
+        utilization_pct: if span_secs > 0.0 {
+            (1.0 - idle_secs / span_secs).max(0.0) * 100.0
+        } else {
+            0.0
+        },
+    }
+}
+
+fn idle_gap_secs(idle_gap_minutes: Option<f64>) -> Result<f64, String> {
+    let minutes = idle_gap_minutes.unwrap_or(DEFAULT_IDLE_GAP_MINUTES);
+    if !minutes.is_finite() || minutes <= 0.0 {
+        return Err("Idle gap must be a positive number of minutes".to_string());
+    }
+    Ok(minutes * 60.0)
+}
+
+// Bridge utilization for each day with weighings in the range. A day's span
+// runs from its first weighing to its last, so closed hours are not idle,
+// less any downtime within it.
+#[tauri::command]
+pub fn bridge_utilization_report(
+    app: AppHandle,
+    range: DateRange,
+    idle_gap_minutes: Option<f64>,
+) -> Result<Vec<DayUtilization>, String> {
+    let idle_gap = idle_gap_secs(idle_gap_minutes)?;
+    let conn = db::open(&app)?;
+    let (start, end): (f64, f64) = conn
+        .query_row(
+            "SELECT julianday(?1, 'utc') * 86400.0, julianday(?2, '+1 day', 'utc') * 86400.0",
+            params![range.from, range.to],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let events = bridge_events(&conn, start, end)?;
+    let downtime = scale_maintenance::downtime_windows(&conn, start, end)?;
+
+    let mut report = Vec::new();
+    let mut day_start = 0;
+    for i in 1..=events.len() {
+        if i < events.len() && events[i].date == events[day_start].date {
+            continue;
+        }
+        let day: Vec<&BridgeEvent> = events[day_start..i].iter().collect();
+        let mut hourly = vec![0; 24];
+        for event in &day {
+            hourly[event.hour] += 1;
+        }
+        let busiest_hour = (0..24)
+            .max_by_key(|hour| (hourly[*hour], std::cmp::Reverse(*hour)))
+            .filter(|hour| hourly[*hour] > 0);
+        let window = (day[0].secs, day[day.len() - 1].secs);
+        report.push(DayUtilization {
+            date: day[0].date.clone(),
+            hourly,
+            busiest_hour,
+            utilization: utilization(&day, window, &downtime, idle_gap),
+        });
+        day_start = i;
+    }
+    Ok(report)
+}
+
+// Bridge utilization for each operator shift opened in the range, over the
+// shift from opening to closing (to now for an open shift) less downtime
+#[tauri::command]
+pub fn shift_utilization_report(
+    app: AppHandle,
+    range: DateRange,
+    idle_gap_minutes: Option<f64>,
+) -> Result<Vec<ShiftUtilization>, String> {
+    let idle_gap = idle_gap_secs(idle_gap_minutes)?;
+    let conn = db::open(&app)?;
+    let sql = format!(
+        "SELECT id, operator_id, opened_at, closed_at, julianday(opened_at) * 86400.0,
+                julianday(COALESCE(closed_at, CURRENT_TIMESTAMP)) * 86400.0
+         FROM shifts WHERE {} BETWEEN ?1 AND ?2 AND {} ORDER BY opened_at, id",
+        db::local_date("opened_at"),
+        auditor::exclude_auditors("operator_id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let shifts: Vec<(i64, String, String, Option<String>, f64, f64)> = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut report = Vec::new();
+    for (shift_id, operator_id, opened_at, closed_at, start, end) in shifts {
+        let events = bridge_events(&conn, start, end)?;
+        let events: Vec<&BridgeEvent> = events.iter().collect();
+        let downtime = scale_maintenance::downtime_windows(&conn, start, end)?;
+        report.push(ShiftUtilization {
+            shift_id,
+            operator_id,
+            opened_at,
+            closed_at,
+            utilization: utilization(&events, (start, end), &downtime, idle_gap),
+        });
+    }
+    Ok(report)
+}
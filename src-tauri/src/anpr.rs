@@ -0,0 +1,618 @@
+// Number plate recognition for Truckore Pro
+// Plates are read from the front and rear plate snapshots by a cloud ANPR
+// service, which the site's DSL link often cannot reach. Every plate
+// snapshot linked to a ticket is queued in anpr_requests and the worker
+// works through the queue, so a lookup that fails is retried with backoff
+// until the service answers. When the cloud is unreachable a local
+// recognizer (an OpenALPR-style executable printing JSON) is tried instead,
+// if one is configured. A recognised plate is then backfilled: it fills the
+// ticket's vehicle number when the operator left it blank, and is checked
+// against it otherwise. Gated by the "anpr" feature flag.
+
+use crate::attachments;
+use crate::audit_log;
+use crate::bandwidth;
+use crate::command_audit;
+use crate::db;
+use crate::feature_flags;
+use crate::license;
+use crate::network;
+use crate::period_lock;
+use crate::roles::{self, Role};
+use crate::settings;
+use crate::shutdown;
+use crate::signatures;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const FLAG: &str = "anpr";
+// Premium feature a license must cover (license.rs)
+const LICENSE_FEATURE: &str = "anpr";
+const SETTINGS_CONFIG_KEY: &str = "anpr_settings";
+// Last ticket_snapshots id looked at for plates to queue
+const QUEUED_THROUGH_CONFIG_KEY: &str = "anpr_queued_through";
+const API_KEY_SECRET: &str = "anpr_api_key";
+const PLATE_ROLES: &[&str] = &["front_plate", "rear_plate"];
+const WORKER_INTERVAL: Duration = Duration::from_secs(15);
+// Requests looked up in one pass
+const BATCH: usize = 10;
+const LOCAL_TIMEOUT: Duration = Duration::from_secs(10);
+// Backoff doubles per attempt up to this many minutes
+const MAX_BACKOFF_MINUTES: i64 = 60;
+// About two days of hourly retries before a request is given up
+const MAX_ATTEMPTS: i64 = 48;
+
+fn default_timeout_secs() -> u64 {
+    8
+}
+
+fn default_min_confidence() -> f64 {
+    0.8
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnprSettings {
+    // Recognition endpoint; the snapshot is POSTed as the request body
+    pub cloud_url: Option<String>,
+    // Sent as a bearer token. Never sent back to the UI; None keeps the
+    // stored key and an empty one removes it.
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+    #[serde(default, skip_deserializing)]
+    pub has_api_key: bool,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    // Local recognizer, run with `local_args` and the image path, e.g.
+    // "alpr" with ["-j", "-c", "in"]
+    pub local_command: Option<String>,
+    #[serde(default)]
+    pub local_args: Vec<String>,
+    // Plates read with less confidence (0 to 1) are recorded but not applied
+    #[serde(default = "default_min_confidence")]
+    pub min_confidence: f64,
+}
+
+impl Default for AnprSettings {
+    fn default() -> Self {
+        AnprSettings {
+            cloud_url: None,
+            api_key: None,
+            has_api_key: false,
+            timeout_secs: default_timeout_secs(),
+            local_command: None,
+            local_args: Vec::new(),
+            min_confidence: default_min_confidence(),
+        }
+    }
+}
+
+impl AnprSettings {
+    fn cloud_url(&self) -> Option<&str> {
+        self.cloud_url
+            .as_deref()
+            .map(str::trim)
+            .filter(|u| !u.is_empty())
+    }
+
+    fn local_command(&self) -> Option<&str> {
+        self.local_command
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlateReading {
+    pub plate: String,
+    // 0 to 1
+    pub confidence: f64,
+    // CLOUD or LOCAL
+    pub source: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnprRequest {
+    pub id: i64,
+    pub snapshot_id: i64,
+    pub weighment_id: String,
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: Option<String>,
+    pub plate: Option<String>,
+    pub confidence: Option<f64>,
+    pub source: Option<String>,
+    pub outcome: Option<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub resolved_at: Option<String>,
+}
+
+enum Lookup {
+    Found(PlateReading),
+    NoPlate,
+    // Worth retrying later: unreachable, refused or unreadable
+    Unavailable(String),
+}
+
+fn load_settings(app: &AppHandle, conn: &Connection) -> Result<AnprSettings, String> {
+    let mut settings: AnprSettings = match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+        None => AnprSettings::default(),
+    };
+    settings.api_key = settings::secret(app, conn, API_KEY_SECRET)?;
+    settings.has_api_key = settings.api_key.is_some();
+    Ok(settings)
+}
+
+// Upper case letters and digits only, as plates are compared
+fn normalize_plate(plate: &str) -> String {
+    plate
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+// The best plate of a response, either {"plate", "confidence"} or
+// {"results": [{"plate", "confidence" | "score"}, ...]}. Percentages are
+// scaled to 0 to 1.
+fn best_plate(json: &Value) -> Option<(String, f64)> {
+    let candidates: Vec<&Value> = match json.get("results").and_then(Value::as_array) {
+        Some(results) => results.iter().collect(),
+        None => vec![json],
+    };
+    candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let plate = normalize_plate(candidate.get("plate")?.as_str()?);
+            let confidence = candidate
+                .get("confidence")
+                .or_else(|| candidate.get("score"))
+                .and_then(Value::as_f64)
+                .unwrap_or(0.0);
+            let confidence = if confidence > 1.0 {
+                confidence / 100.0
+            } else {
+                confidence
+            };
+            (!plate.is_empty()).then_some((plate, confidence))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+fn reading(json: &Value, source: &str) -> Lookup {
+    match best_plate(json) {
+        Some((plate, confidence)) => Lookup::Found(PlateReading {
+            plate,
+            confidence,
+            source: source.to_string(),
+        }),
+        None => Lookup::NoPlate,
+    }
+}
+
+fn cloud_lookup(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &AnprSettings,
+    url: &str,
+    mime: &str,
+    bytes: &[u8],
+) -> Lookup {
+    if let Err(e) = bandwidth::reserve(app, conn, bytes.len() as u64) {
+        return Lookup::Unavailable(e);
+    }
+    let timeout = Duration::from_secs(settings.timeout_secs.max(1));
+    let agent = match network::http_agent(conn, url, timeout) {
+        Ok(agent) => agent,
+        Err(e) => return Lookup::Unavailable(e),
+    };
+    let mut request = agent.post(url).set("Content-Type", mime);
+    if let Some(key) = settings.api_key.as_deref() {
+        request = request.set("Authorization", &format!("Bearer {}", key));
+    }
+    match request.send_bytes(bytes) {
+        Ok(response) => match response.into_json::<Value>() {
+            Ok(json) => reading(&json, "CLOUD"),
+            Err(e) => Lookup::Unavailable(format!("Unreadable ANPR response: {}", e)),
+        },
+        Err(e) => Lookup::Unavailable(format!("ANPR service unreachable: {}", e)),
+    }
+}
+
+fn local_lookup(settings: &AnprSettings, command: &str, image: &Path) -> Lookup {
+    let child = Command::new(command)
+        .args(&settings.local_args)
+        .arg(image)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => return Lookup::Unavailable(format!("Local ANPR failed to start: {}", e)),
+    };
+    let Some(mut stdout) = child.stdout.take() else {
+        return Lookup::Unavailable("Local ANPR output unavailable".to_string());
+    };
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + LOCAL_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Lookup::Unavailable(format!(
+                    "Local ANPR gave no answer within {} s",
+                    LOCAL_TIMEOUT.as_secs()
+                ));
+            }
+            Err(e) => return Lookup::Unavailable(e.to_string()),
+        }
+    }
+    let output = match reader.join() {
+        Ok(Ok(output)) => output,
+        _ => return Lookup::Unavailable("Local ANPR output unreadable".to_string()),
+    };
+    match serde_json::from_str::<Value>(output.trim()) {
+        Ok(json) => reading(&json, "LOCAL"),
+        Err(e) => Lookup::Unavailable(format!("Local ANPR output is not JSON: {}", e)),
+    }
+}
+
+// Cloud first, then the local recognizer. While the cloud is configured, a
+// local answer it would not apply leaves the lookup to the cloud.
+fn recognize(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &AnprSettings,
+    image: &str,
+) -> Result<Lookup, String> {
+    let cloud_error = match settings.cloud_url() {
+        Some(url) => {
+            let (mime, bytes) = attachments::read(app, conn, image)?;
+            match cloud_lookup(app, conn, settings, url, &mime, &bytes) {
+                Lookup::Unavailable(e) => Some(e),
+                answered => return Ok(answered),
+            }
+        }
+        None => None,
+    };
+    let Some(command) = settings.local_command() else {
+        return Ok(Lookup::Unavailable(
+            cloud_error.unwrap_or_else(|| "No ANPR service is configured".to_string()),
+        ));
+    };
+    let local = local_lookup(settings, command, &attachments::path_of(app, image)?);
+    Ok(match (local, cloud_error) {
+        (Lookup::Found(reading), _) if reading.confidence >= settings.min_confidence => {
+            Lookup::Found(reading)
+        }
+        (_, Some(e)) => Lookup::Unavailable(e),
+        (local, None) => local,
+    })
+}
+
+// What a recognised plate does to its ticket
+fn backfill(
+    conn: &Connection,
+    settings: &AnprSettings,
+    weighment_id: &str,
+    reading: &PlateReading,
+) -> Result<&'static str, String> {
+    if reading.confidence < settings.min_confidence {
+        return Ok("UNSURE");
+    }
+    let vehicle_no: Option<String> = conn
+        .query_row(
+            "SELECT vehicle_no FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some(vehicle_no) = vehicle_no else {
+        return Ok("MISSING");
+    };
+    if !vehicle_no.trim().is_empty() {
+        return Ok(match normalize_plate(&vehicle_no) == reading.plate {
+            true => "MATCHED",
+            false => "MISMATCH",
+        });
+    }
+    if period_lock::is_weighment_locked(conn, weighment_id)? {
+        return Ok("LOCKED");
+    }
+    let sql = "UPDATE weighments SET vehicle_no = ?2, updated_at = CURRENT_TIMESTAMP
+               WHERE id = ?1 AND trim(vehicle_no) = ''";
+    let rows = conn
+        .execute(sql, params![weighment_id, reading.plate])
+        .map_err(|e| e.to_string())?;
+    audit_log::record(
+        conn,
+        "anpr_backfill",
+        Some("weighments"),
+        sql,
+        &json!([weighment_id, reading.plate]),
+        rows,
+        None,
+    )?;
+    signatures::sign(conn, weighment_id)?;
+    Ok("FILLED")
+}
+
+fn resolve(
+    conn: &Connection,
+    settings: &AnprSettings,
+    id: i64,
+    weighment_id: &str,
+    reading: Option<&PlateReading>,
+) -> Result<(), String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let outcome = match reading {
+        Some(reading) => Some(backfill(&tx, settings, weighment_id, reading)?),
+        None => None,
+    };
+    tx.execute(
+        "UPDATE anpr_requests
+         SET status = ?2, plate = ?3, confidence = ?4, source = ?5, outcome = ?6,
+             error = NULL, attempts = attempts + 1, next_attempt_at = NULL,
+             resolved_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![
+            id,
+            if reading.is_some() {
+                "RECOGNIZED"
+            } else {
+                "NO_PLATE"
+            },
+            reading.map(|r| &r.plate),
+            reading.map(|r| r.confidence),
+            reading.map(|r| &r.source),
+            outcome,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// Try again later, waiting twice as long each time
+fn postpone(conn: &Connection, id: i64, error: &str) -> Result<(), String> {
+    conn.execute(
+        "UPDATE anpr_requests
+         SET attempts = attempts + 1, error = ?2,
+             status = CASE WHEN attempts + 1 >= ?3 THEN 'FAILED' ELSE 'PENDING' END,
+             next_attempt_at = datetime('now', '+' || min(1 << attempts, ?4) || ' minutes')
+         WHERE id = ?1",
+        params![id, error, MAX_ATTEMPTS, MAX_BACKOFF_MINUTES],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Queue the plate snapshots taken since the last pass. The first pass only
+// notes where the snapshots stand, so switching ANPR on does not send the
+// site's whole history.
+fn queue_new(conn: &Connection) -> Result<(), String> {
+    let latest: i64 = conn
+        .query_row(
+            "SELECT COALESCE(MAX(id), 0) FROM ticket_snapshots",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let queued_through =
+        db::get_config(conn, QUEUED_THROUGH_CONFIG_KEY)?.and_then(|v| v.parse::<i64>().ok());
+    if let Some(queued_through) = queued_through {
+        let roles = PLATE_ROLES
+            .iter()
+            .map(|r| format!("'{}'", r))
+            .collect::<Vec<_>>()
+            .join(", ");
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO anpr_requests (snapshot_id, weighment_id)
+                 SELECT id, weighment_id FROM ticket_snapshots
+                 WHERE id > ?1 AND id <= ?2 AND camera_role IN ({})
+                 ORDER BY id",
+                roles
+            ),
+            params![queued_through, latest],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    db::set_config(conn, QUEUED_THROUGH_CONFIG_KEY, &latest.to_string())
+}
+
+// One pass of the worker; the number of requests resolved
+fn work(app: &AppHandle, conn: &Connection) -> Result<usize, String> {
+    if !feature_flags::is_enabled(conn, FLAG)? {
+        return Ok(0);
+    }
+    // Lookups wait in the queue until a license covers them again
+    if license::covers(app, LICENSE_FEATURE).is_err() {
+        return Ok(0);
+    }
+    let settings = load_settings(app, conn)?;
+    if settings.cloud_url().is_none() && settings.local_command().is_none() {
+        return Ok(0);
+    }
+    queue_new(conn)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.id, r.weighment_id, s.image FROM anpr_requests r
+             JOIN ticket_snapshots s ON s.id = r.snapshot_id
+             WHERE r.status = 'PENDING' AND r.next_attempt_at <= CURRENT_TIMESTAMP
+             ORDER BY r.next_attempt_at, r.id LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let due = stmt
+        .query_map([BATCH as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut resolved = 0;
+    for (id, weighment_id, image) in due {
+        let lookup = recognize(app, conn, &settings, &image).unwrap_or_else(Lookup::Unavailable);
+        match lookup {
+            Lookup::Found(reading) => resolve(conn, &settings, id, &weighment_id, Some(&reading))?,
+            Lookup::NoPlate => resolve(conn, &settings, id, &weighment_id, None)?,
+            Lookup::Unavailable(e) => {
+                postpone(conn, id, &e)?;
+                // The rest would wait on the same dead link
+                break;
+            }
+        }
+        resolved += 1;
+    }
+    Ok(resolved)
+}
+
+pub fn start_worker(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            if let Ok(conn) = db::open(&app) {
+                if let Err(e) = work(&app, &conn) {
+                    tracing::warn!(error = %e, "ANPR pass failed");
+                }
+            }
+            std::thread::sleep(WORKER_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_anpr_settings(app: AppHandle) -> Result<AnprSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&app, &conn)
+}
+
+// Configure the cloud service and local recognizer (admins)
+#[tauri::command]
+pub fn set_anpr_settings(
+    app: AppHandle,
+    settings: AnprSettings,
+    user_id: String,
+) -> Result<AnprSettings, String> {
+    let args = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_anpr_settings", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if !(0.0..=1.0).contains(&settings.min_confidence) {
+            return Err("Minimum confidence must be between 0 and 1".to_string());
+        }
+        if let Some(url) = settings.cloud_url() {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(format!("Invalid ANPR service URL: {}", url));
+            }
+        }
+        match settings.api_key.as_deref().map(str::trim) {
+            Some("") => settings::delete_secret_value(&conn, API_KEY_SECRET)?,
+            Some(key) => settings::set_secret_value(&app, &conn, API_KEY_SECRET, key)?,
+            None => {}
+        }
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)?;
+        // Plates are read from the snapshots taken from now on
+        queue_new(&conn)?;
+        load_settings(&app, &conn)
+    })
+}
+
+// Read the plate of a snapshot (an attachment reference) while the ticket
+// is being made. When no service answers, the operator types the plate; the
+// snapshot is still read once it is linked to the saved ticket.
+#[tauri::command]
+pub async fn recognize_plate(app: AppHandle, image: String) -> Result<PlateReading, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&app)?;
+        feature_flags::require_enabled(&conn, FLAG)?;
+        license::covers(&app, LICENSE_FEATURE)?;
+        let settings = load_settings(&app, &conn)?;
+        match recognize(&app, &conn, &settings, &image)? {
+            Lookup::Found(reading) => Ok(reading),
+            Lookup::NoPlate => Err("No number plate found in the image".to_string()),
+            Lookup::Unavailable(e) => Err(format!(
+                "Plate recognition unavailable, it will be retried after the ticket is saved: {}",
+                e
+            )),
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Recent lookups, newest first, by status and/or ticket
+#[tauri::command]
+pub fn list_anpr_requests(
+    app: AppHandle,
+    status: Option<String>,
+    weighment_id: Option<String>,
+) -> Result<Vec<AnprRequest>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, snapshot_id, weighment_id, status, attempts, next_attempt_at, plate,
+                    confidence, source, outcome, error, created_at, resolved_at
+             FROM anpr_requests
+             WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR weighment_id = ?2)
+             ORDER BY id DESC LIMIT 500",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![status, weighment_id], |row| {
+            Ok(AnprRequest {
+                id: row.get(0)?,
+                snapshot_id: row.get(1)?,
+                weighment_id: row.get(2)?,
+                status: row.get(3)?,
+                attempts: row.get(4)?,
+                next_attempt_at: row.get(5)?,
+                plate: row.get(6)?,
+                confidence: row.get(7)?,
+                source: row.get(8)?,
+                outcome: row.get(9)?,
+                error: row.get(10)?,
+                created_at: row.get(11)?,
+                resolved_at: row.get(12)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Look up pending and given-up requests again on the next pass, all of them
+// or those in `ids`; the number requeued
+#[tauri::command]
+pub fn retry_anpr_requests(app: AppHandle, ids: Option<Vec<i64>>) -> Result<usize, String> {
+    let conn = db::open(&app)?;
+    let ids = ids.map(|ids| serde_json::to_string(&ids).unwrap_or_default());
+    conn.execute(
+        "UPDATE anpr_requests
+         SET status = 'PENDING', attempts = 0, next_attempt_at = CURRENT_TIMESTAMP
+         WHERE status IN ('PENDING', 'FAILED')
+           AND (?1 IS NULL OR id IN (SELECT value FROM json_each(?1)))",
+        params![ids],
+    )
+    .map_err(|e| e.to_string())
+}
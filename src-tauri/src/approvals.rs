@@ -0,0 +1,242 @@
+// Approval queue for Truckore Pro
+// Generic pending items (amendments, voids, overrides) decided by an approver role.
+// Each kind registers a handler in `apply_decision` that runs inside the decision transaction.
+
+use crate::command_audit;
+use crate::db;
+use crate::notifications;
+use crate::roles::{self, Role};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Approval {
+    pub id: i64,
+    pub kind: String,
+    pub entity: String,
+    pub entity_id: String,
+    pub summary: String,
+    pub payload: Option<String>,
+    pub approver_role: String,
+    pub requested_by: String,
+    pub requested_at: String,
+    pub status: String,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<String>,
+    pub comment: Option<String>,
+}
+
+pub struct NewApproval<'a> {
+    pub kind: &'a str,
+    pub entity: &'a str,
+    pub entity_id: &'a str,
+    pub summary: &'a str,
+    pub payload: Option<serde_json::Value>,
+    pub approver_role: Role,
+    pub requested_by: &'a str,
+}
+
+const APPROVAL_COLUMNS: &str = "id, kind, entity, entity_id, summary, payload, approver_role,
+    requested_by, requested_at, status, decided_by, decided_at, comment";
+
+fn row_to_approval(row: &rusqlite::Row) -> rusqlite::Result<Approval> {
+    Ok(Approval {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        entity: row.get(2)?,
+        entity_id: row.get(3)?,
+        summary: row.get(4)?,
+        payload: row.get(5)?,
+        approver_role: row.get(6)?,
+        requested_by: row.get(7)?,
+        requested_at: row.get(8)?,
+        status: row.get(9)?,
+        decided_by: row.get(10)?,
+        decided_at: row.get(11)?,
+        comment: row.get(12)?,
+    })
+}
+
+pub fn load(conn: &Connection, id: i64) -> Result<Approval, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM approvals WHERE id = ?1", APPROVAL_COLUMNS),
+        [id],
+        row_to_approval,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Approval {} not found", id))
+}
+
+// Queue a new pending item. Call `announce` once the surrounding transaction commits.
+pub fn submit(conn: &Connection, new: NewApproval) -> Result<Approval, String> {
+    conn.execute(
+        "INSERT INTO approvals (kind, entity, entity_id, summary, payload, approver_role, requested_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            new.kind,
+            new.entity,
+            new.entity_id,
+            new.summary,
+            new.payload.map(|p| p.to_string()),
+            new.approver_role.as_str(),
+            new.requested_by
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    load(conn, conn.last_insert_rowid())
+}
+
+// Notify approvers about a newly queued item
+pub fn announce(app: &AppHandle, conn: &Connection, approval: &Approval) -> Result<(), String> {
+    notifications::notify(
+        app,
+        conn,
+        &approval.approver_role,
+        &format!("Approval needed: {}", approval.kind),
+        &approval.summary,
+        Some(("approval", &approval.id.to_string())),
+    )?;
+    let _ = app.emit_all("approval-requested", approval);
+    Ok(())
+}
+
+// Domain side effects of a decision, per approval kind
+fn apply_decision(conn: &Connection, approval: &Approval, approved: bool) -> Result<(), String> {
+    match approval.kind.as_str() {
+        "amendment" => crate::amendments::apply_decision(conn, approval, approved),
+        "void" => crate::voids::apply_decision(conn, approval, approved),
+        other => Err(format!(
+            "No handler registered for approval kind: {}",
+            other
+        )),
+    }
+}
+
+// Approve or reject a pending item. The decider must hold the approver role.
+pub fn decide(
+    app: &AppHandle,
+    id: i64,
+    approved: bool,
+    user_id: &str,
+    comment: Option<String>,
+) -> Result<Approval, String> {
+    let mut conn = db::open(app)?;
+    let approval = load(&conn, id)?;
+    roles::require_role(&conn, user_id, Role::parse(&approval.approver_role)?)?;
+    if approval.status != "PENDING" {
+        return Err(format!("Approval {} is already {}", id, approval.status));
+    }
+    if approval.requested_by == user_id {
+        return Err(format!(
+            "{}: requests cannot be approved by the person who made them",
+            command_audit::DENIED_PREFIX
+        ));
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE approvals
+         SET status = ?1, decided_by = ?2, decided_at = CURRENT_TIMESTAMP, comment = ?3
+         WHERE id = ?4",
+        params![
+            if approved { "APPROVED" } else { "REJECTED" },
+            user_id,
+            comment,
+            id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let decided = load(&tx, id)?;
+    apply_decision(&tx, &decided, approved)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let _ = app.emit_all("approval-decided", &decided);
+    Ok(decided)
+}
+
+// Pending items the given role may decide, oldest first
+#[tauri::command]
+pub fn list_pending_approvals(
+    app: AppHandle,
+    role: String,
+    kind: Option<String>,
+) -> Result<Vec<Approval>, String> {
+    let role = Role::parse(&role)?;
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM approvals
+             WHERE status = 'PENDING' AND (?1 IS NULL OR kind = ?1)
+             ORDER BY requested_at, id",
+            APPROVAL_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![kind], row_to_approval)
+        .map_err(|e| e.to_string())?;
+
+    let mut pending = Vec::new();
+    for row in rows {
+        let approval = row.map_err(|e| e.to_string())?;
+        if Role::parse(&approval.approver_role)? <= role {
+            pending.push(approval);
+        }
+    }
+    Ok(pending)
+}
+
+#[tauri::command]
+pub fn approve_request(
+    app: AppHandle,
+    id: i64,
+    user_id: String,
+    comment: Option<String>,
+) -> Result<Approval, String> {
+    command_audit::audited(
+        &app,
+        "approve_request",
+        &user_id,
+        serde_json::json!({ "id": id, "comment": comment }),
+        || decide(&app, id, true, &user_id, comment),
+    )
+}
+
+#[tauri::command]
+pub fn reject_request(
+    app: AppHandle,
+    id: i64,
+    user_id: String,
+    comment: Option<String>,
+) -> Result<Approval, String> {
+    command_audit::audited(
+        &app,
+        "reject_request",
+        &user_id,
+        serde_json::json!({ "id": id, "comment": comment }),
+        || decide(&app, id, false, &user_id, comment),
+    )
+}
+
+// Decision history for an entity (e.g. all approvals touching one weighment)
+#[tauri::command]
+pub fn list_approvals_for(
+    app: AppHandle,
+    entity: String,
+    entity_id: String,
+) -> Result<Vec<Approval>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM approvals WHERE entity = ?1 AND entity_id = ?2 ORDER BY id",
+            APPROVAL_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![entity, entity_id], row_to_approval)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,443 @@
+// Ticket archive for Truckore Pro
+// Years of tickets slow the live database down. archive_before moves closed
+// tickets weighed before a date, with the rows that belong to them, into an
+// archive database next to the live one (truckore_archive.db for
+// production). The archive is attached to a live connection and the copy
+// and the delete run in one transaction; a cut-short move leaves copies in
+// both that the next move or restore overwrites. query_archive searches the
+// archived tickets and restore_from_archive brings tickets back.
+//
+// Tickets stay live while head office has not acknowledged them, while
+// they are billed but not fully paid (credit balances are summed from live
+// tickets) and while a dispute is open. Moving is not deleting: no
+// deletions are sent to head office. The archive is not part of backups.
+
+use crate::attachments;
+use crate::command_audit;
+use crate::db;
+use crate::encryption;
+use crate::money;
+use crate::profiles::{self, Profile};
+use crate::roles::{self, Role};
+use crate::shutdown;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+const SCHEMA: &str = "archive";
+const DEFAULT_LIMIT: i64 = 200;
+
+const BY_WEIGHMENT: &str = "weighment_id IN (SELECT weighment_id FROM main.archive_moves)";
+
+// Tables moved with their tickets, as (table, rows of the tickets listed in
+// archive_moves). {s} is the schema being moved from. Stock, invoices and
+// shift payments stay live: their totals must not change.
+const MOVED: &[(&str, &str)] = &[
+    (
+        "weighments",
+        "id IN (SELECT weighment_id FROM main.archive_moves)",
+    ),
+    ("weighment_signatures", BY_WEIGHMENT),
+    ("weighment_anomalies", BY_WEIGHMENT),
+    ("fraud_flags", BY_WEIGHMENT),
+    ("practice_tickets", BY_WEIGHMENT),
+    ("weighment_amendments", BY_WEIGHMENT),
+    ("weighment_sequences", BY_WEIGHMENT),
+    ("weighment_steps", BY_WEIGHMENT),
+    ("weighment_net_adjustments", BY_WEIGHMENT),
+    ("weighment_deductions", BY_WEIGHMENT),
+    ("weighment_transport", BY_WEIGHMENT),
+    ("weighment_parties", BY_WEIGHMENT),
+    ("weighment_direction", BY_WEIGHMENT),
+    ("weighment_billing", BY_WEIGHMENT),
+    ("credit_limit_breaches", BY_WEIGHMENT),
+    ("out_of_hours_activity", BY_WEIGHMENT),
+    ("stale_ticket_flags", BY_WEIGHMENT),
+    ("charge_revisions", BY_WEIGHMENT),
+    ("ticket_snapshots", BY_WEIGHMENT),
+    ("disputes", BY_WEIGHMENT),
+    ("driver_signatures", BY_WEIGHMENT),
+    (
+        "dispute_attachments",
+        "dispute_id IN (SELECT id FROM {s}.disputes WHERE weighment_id IN
+             (SELECT weighment_id FROM main.archive_moves))",
+    ),
+    (
+        "entity_attachments",
+        "entity = 'weighment' AND entity_id IN (SELECT weighment_id FROM main.archive_moves)",
+    ),
+];
+
+// Copied for reference but kept live: stock and credit figures leave voided
+// tickets out by it
+const COPIED: &[(&str, &str)] = &[("ticket_voids", BY_WEIGHMENT)];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveReport {
+    pub archived: usize,
+    // Rows moved with the tickets
+    pub related_rows: usize,
+    // Closed tickets of the period kept live, see the top of this file
+    pub kept_live: usize,
+    pub archive_file: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub restored: usize,
+    pub related_rows: usize,
+    // Requested ids that are not in the archive
+    pub not_found: Vec<String>,
+}
+
+fn archive_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let file = match profiles::active(app) {
+        Profile::Production => "truckore_archive.db".to_string(),
+        profile => profile.db_file().replace(".db", "_archive.db"),
+    };
+    Ok(crate::get_db_path(app)?.with_file_name(file))
+}
+
+// Archives set aside beside the archive under a longer name (e.g.
+// truckore_archive_2023.db for a year rotated out), then the archive itself
+pub fn archive_files(app: &AppHandle) -> Result<Vec<PathBuf>, String> {
+    let current = archive_path(app)?;
+    let stem = current
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let mut files: Vec<PathBuf> = match current.parent().map(std::fs::read_dir) {
+        Some(Ok(entries)) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(&format!("{}_", stem)) && n.ends_with(".db"))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    files.sort();
+    if current.exists() {
+        files.push(current);
+    }
+    Ok(files)
+}
+
+// Run `f` with the archive attached as `archive`, its ticket charges in
+// paise like the live table's (money.rs). Pooled connections are reused,
+// so it is always detached again.
+fn with_archive<T>(
+    app: &AppHandle,
+    f: impl FnOnce(&mut Connection) -> Result<T, String>,
+) -> Result<T, String> {
+    let path = archive_path(app)?;
+    let mut conn = db::open(app)?;
+    encryption::attach(app, &conn, &path, SCHEMA).map_err(|e| e.to_string())?;
+    let result = money::convert_charges(&conn, SCHEMA).and_then(|()| f(&mut conn));
+    let _ = conn.execute_batch(&format!("DETACH DATABASE {}", SCHEMA));
+    result
+}
+
+fn columns(conn: &Connection, schema: &str, table: &str) -> Result<Vec<(String, String)>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA {}.table_info({})", schema, table))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Create the archive copy of a live table, or add the columns the live one
+// gained since. Returns the live column list.
+fn ensure_table(conn: &Connection, table: &str) -> Result<String, String> {
+    let live = columns(conn, "main", table)?;
+    let archived = columns(conn, SCHEMA, table)?;
+    if archived.is_empty() {
+        let sql: String = conn
+            .query_row(
+                "SELECT sql FROM main.sqlite_master WHERE type = 'table' AND name = ?1",
+                [table],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("No table {}: {}", table, e))?;
+        let definition = sql
+            .find('(')
+            .map(|start| &sql[start..])
+            .ok_or_else(|| format!("Cannot read the definition of {}", table))?;
+        conn.execute_batch(&format!("CREATE TABLE {}.{} {}", SCHEMA, table, definition))
+            .map_err(|e| e.to_string())?;
+    } else {
+        for (name, kind) in &live {
+            if !archived.iter().any(|(archived, _)| archived == name) {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {}.{} ADD COLUMN {} {}",
+                    SCHEMA, table, name, kind
+                ))
+                .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok(live
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+// Copy the listed tickets' rows from `from` to `to`, then delete them from
+// `from`, all on the caller's transaction. Returns the related rows moved.
+fn move_rows(conn: &Connection, from: &str, to: &str) -> Result<usize, String> {
+    let mut related = 0;
+    for (table, rows) in MOVED.iter().chain(COPIED) {
+        let columns = ensure_table(conn, table)?;
+        let rows = rows.replace("{s}", from);
+        if from == "main" {
+            attachments::retain_for_archive(conn, table, &rows)?;
+        }
+        // The live copy of a COPIED table was never removed
+        if to == "main" && COPIED.iter().any(|(copied, _)| copied == table) {
+            continue;
+        }
+        let copied = conn
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO {to}.{t} ({c}) SELECT {c} FROM {from}.{t} WHERE {r}",
+                    to = to,
+                    from = from,
+                    t = table,
+                    c = columns,
+                    r = rows
+                ),
+                [],
+            )
+            .map_err(|e| e.to_string())?;
+        if *table != "weighments" {
+            related += copied;
+        }
+    }
+    // Dependent rows go first: dispute attachments are found through disputes
+    let removed = MOVED
+        .iter()
+        .rev()
+        .chain(COPIED.iter().filter(|_| from == SCHEMA));
+    for (table, rows) in removed {
+        conn.execute(
+            &format!(
+                "DELETE FROM {}.{} WHERE {}",
+                from,
+                table,
+                rows.replace("{s}", from)
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(related)
+}
+
+fn archive(conn: &mut Connection, date: &str) -> Result<(usize, usize, usize), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM archive_moves", [])
+        .map_err(|e| e.to_string())?;
+    let closed = format!(
+        "FROM weighments w WHERE w.status != 'OPEN' AND {} < ?1",
+        db::local_date("w.created_at")
+    );
+    let period: usize = tx
+        .query_row(&format!("SELECT COUNT(*) {}", closed), [date], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    let archived = tx
+        .execute(
+            &format!(
+                "INSERT INTO archive_moves (weighment_id) SELECT w.id {}
+                   AND NOT EXISTS (SELECT 1 FROM sync_watermarks s
+                                   WHERE s.target NOT LIKE 'pull:%' AND s.version < w.row_version)
+                   AND COALESCE((SELECT base_amount_minor FROM weighment_billing b
+                                 WHERE b.weighment_id = w.id), 0)
+                       <= (SELECT COALESCE(SUM(amount_minor), 0) FROM shift_payments p
+                           WHERE p.weighment_id = w.id AND p.method != 'CREDIT')
+                   AND w.id NOT IN (SELECT weighment_id FROM disputes WHERE status = 'OPEN')",
+                closed
+            ),
+            [date],
+        )
+        .map_err(|e| e.to_string())?;
+    let related = move_rows(&tx, "main", SCHEMA)?;
+    // The delete triggers left tombstones that would reach head office
+    for table in ["sync_tombstones", "sync_change_log"] {
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE table_name = 'weighments'
+                 AND row_key IN (SELECT weighment_id FROM archive_moves)",
+                table
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute("DELETE FROM archive_moves", [])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok((archived, related, period - archived))
+}
+
+// Move closed tickets weighed before `date` (YYYY-MM-DD) to the archive
+#[tauri::command]
+pub async fn archive_before(
+    app: AppHandle,
+    date: String,
+    user_id: String,
+) -> Result<ArchiveReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let args = serde_json::json!({ "date": date });
+        command_audit::audited(&app, "archive_before", &user_id, args, || {
+            {
+                let conn = db::open(&app)?;
+                roles::require_role(&conn, &user_id, Role::Admin)?;
+                let valid: bool = conn
+                    .query_row("SELECT date(?1) IS ?1", [&date], |row| row.get(0))
+                    .map_err(|e| e.to_string())?;
+                if !valid {
+                    return Err(format!("{} is not a YYYY-MM-DD date", date));
+                }
+            }
+            let _operation = shutdown::begin(&app, "archive", None)?;
+            let (archived, related_rows, kept_live) =
+                with_archive(&app, |conn| archive(conn, &date))?;
+            Ok(ArchiveReport {
+                archived,
+                related_rows,
+                kept_live,
+                archive_file: archive_path(&app)?.to_string_lossy().into_owned(),
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Archived tickets, newest first, with `text` anywhere in the vehicle,
+// party, product or ticket number and weighed between `from` and `to`
+#[tauri::command]
+pub fn query_archive(
+    app: AppHandle,
+    text: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<Value>, String> {
+    if !archive_path(&app)?.exists() {
+        return Ok(Vec::new());
+    }
+    with_archive(&app, |conn| {
+        let archived: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM archive.sqlite_master
+                 WHERE type = 'table' AND name = 'weighments'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !archived {
+            return Ok(Vec::new());
+        }
+        let text = text.filter(|t| !t.trim().is_empty());
+        let day = db::local_date("created_at");
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT * FROM archive.weighments
+                 WHERE (?1 IS NULL OR vehicle_no LIKE '%' || ?1 || '%'
+                        OR party_name LIKE '%' || ?1 || '%'
+                        OR product_name LIKE '%' || ?1 || '%'
+                        OR ticket_no LIKE '%' || ?1 || '%')
+                   AND (?2 IS NULL OR {day} >= ?2) AND (?3 IS NULL OR {day} <= ?3)
+                 ORDER BY created_at DESC LIMIT ?4",
+                day = day
+            ))
+            .map_err(|e| e.to_string())?;
+        let names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let rows = stmt
+            .query_map(
+                rusqlite::params![text, from, to, limit.unwrap_or(DEFAULT_LIMIT).max(1)],
+                |row| {
+                    let mut map = serde_json::Map::new();
+                    for (i, name) in names.iter().enumerate() {
+                        map.insert(name.clone(), crate::sql_to_json_value(row.get_ref(i)?));
+                    }
+                    Ok(Value::Object(map))
+                },
+            )
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(rows
+            .into_iter()
+            .map(|mut row| {
+                attachments::inline_references(&app, conn, &mut row);
+                row
+            })
+            .collect())
+    })
+}
+
+// Bring archived tickets back into the live database
+#[tauri::command]
+pub fn restore_from_archive(
+    app: AppHandle,
+    ids: Vec<String>,
+    user_id: String,
+) -> Result<RestoreReport, String> {
+    let args = serde_json::json!({ "ids": ids });
+    command_audit::audited(&app, "restore_from_archive", &user_id, args, || {
+        {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+        }
+        if !archive_path(&app)?.exists() {
+            return Err("There is no archive to restore from".to_string());
+        }
+        let _operation = shutdown::begin(&app, "archive", None)?;
+        with_archive(&app, |conn| {
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM archive_moves", [])
+                .map_err(|e| e.to_string())?;
+            let mut ids = ids.clone();
+            ids.sort();
+            ids.dedup();
+            let mut not_found = Vec::new();
+            for id in &ids {
+                let found = tx
+                    .execute(
+                        "INSERT OR IGNORE INTO archive_moves (weighment_id)
+                         SELECT id FROM archive.weighments WHERE id = ?1",
+                        [id],
+                    )
+                    .map_err(|e| e.to_string())?;
+                if found == 0 {
+                    not_found.push(id.clone());
+                }
+            }
+            let restored = ids.len() - not_found.len();
+            let related_rows = match restored {
+                0 => 0,
+                _ => move_rows(&tx, SCHEMA, "main")?,
+            };
+            tx.execute("DELETE FROM archive_moves", [])
+                .map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+            Ok(RestoreReport {
+                restored,
+                related_rows,
+                not_found,
+            })
+        })
+    })
+}
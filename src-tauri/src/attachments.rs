@@ -0,0 +1,516 @@
+// Content-addressed attachment store for Truckore Pro
+// Camera images used to be stored inline, as base64 data URLs in every
+// ticket row. Stored images live as files named by their SHA-256 under
+// attachments/ in the app data directory, and rows hold an
+// `attachment:sha256:<hash>` reference, so the same photo is kept once.
+// execute_query turns references back into data URLs, so screens are
+// unchanged.
+// Operators can also attach files of their own (delivery challans, photos)
+// to tickets and masters; those are listed in entity_attachments, and
+// deleting the last one pointing at a file removes the file.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+pub const REF_PREFIX: &str = "attachment:sha256:";
+
+// Columns that hold images or references, as (table, key column, column)
+const IMAGE_COLUMNS: &[(&str, &str, &str)] = &[
+    ("weighments", "id", "front_camera_image"),
+    ("weighments", "id", "back_camera_image"),
+    ("open_tickets", "id", "camera_image"),
+    ("ticket_snapshots", "id", "image"),
+    ("dispute_attachments", "id", "attachment"),
+    ("entity_attachments", "id", "attachment"),
+    ("driver_signatures", "weighment_id", "attachment"),
+];
+
+// Records files can be attached to, as (entity, table keyed by id)
+const ENTITIES: &[(&str, &str)] = &[
+    ("weighment", "weighments"),
+    ("open_ticket", "open_tickets"),
+    ("vehicle", "vehicles"),
+    ("party", "parties"),
+    ("product", "products"),
+];
+
+const MAX_ATTACHMENT_BYTES: usize = 20 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntityAttachment {
+    pub id: i64,
+    pub entity: String,
+    pub entity_id: String,
+    pub name: String,
+    pub mime: String,
+    pub size_bytes: i64,
+    pub added_by: String,
+    pub added_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachmentContent {
+    pub attachment: EntityAttachment,
+    // Data URL
+    pub data: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DedupReport {
+    pub dry_run: bool,
+    pub inline_images: i64,
+    pub unique_images: i64,
+    pub inline_bytes: i64,
+    // Bytes the images take once each is stored once
+    pub stored_bytes: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub files: i64,
+    pub unreferenced: Vec<String>,
+    pub reclaimable_bytes: i64,
+}
+
+pub struct DataUrl {
+    pub mime: String,
+    pub bytes: Vec<u8>,
+}
+
+pub fn parse_data_url(value: &str) -> Option<DataUrl> {
+    let rest = value.strip_prefix("data:")?;
+    let (mime, data) = rest.split_once(";base64,")?;
+    let bytes = general_purpose::STANDARD.decode(data.trim()).ok()?;
+    Some(DataUrl {
+        mime: mime.to_string(),
+        bytes,
+    })
+}
+
+fn store_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(crate::get_db_path(app)?
+        .parent()
+        .ok_or("Failed to resolve the data directory")?
+        .join("attachments"))
+}
+
+fn file_path(dir: &std::path::Path, hash: &str) -> PathBuf {
+    dir.join(&hash[..2]).join(hash)
+}
+
+fn hash_of(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Store a data URL and return its reference. Identical content is written once.
+pub fn store(app: &AppHandle, conn: &Connection, value: &str) -> Result<String, String> {
+    let data = parse_data_url(value).ok_or("Attachment is not a base64 data URL")?;
+    store_bytes(app, conn, &data.mime, &data.bytes)
+}
+
+pub fn store_bytes(
+    app: &AppHandle,
+    conn: &Connection,
+    mime: &str,
+    bytes: &[u8],
+) -> Result<String, String> {
+    let hash = hash_of(bytes);
+    let path = file_path(&store_dir(app)?, &hash);
+    if !path.exists() {
+        fs::create_dir_all(path.parent().ok_or("Invalid attachment path")?)
+            .map_err(|e| e.to_string())?;
+        // Written under a temporary name so a crash never leaves a truncated file
+        let partial = path.with_extension("partial");
+        fs::write(&partial, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+    }
+    conn.execute(
+        "INSERT OR IGNORE INTO attachments (hash, mime, size_bytes) VALUES (?1, ?2, ?3)",
+        params![hash, mime, bytes.len() as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(format!("{}{}", REF_PREFIX, hash))
+}
+
+// Store `value` when it is an inline image; other values pass through
+pub fn store_if_inline(
+    app: &AppHandle,
+    conn: &Connection,
+    value: Option<String>,
+) -> Result<Option<String>, String> {
+    match value {
+        Some(v) if v.starts_with("data:") => store(app, conn, &v).map(Some),
+        other => Ok(other),
+    }
+}
+
+// Replace attachment references in a query result with data URLs
+pub fn inline_references(app: &AppHandle, conn: &Connection, value: &mut serde_json::Value) {
+    let serde_json::Value::Object(map) = value else {
+        return;
+    };
+    for field in map.values_mut() {
+        let Some(hash) = field.as_str().and_then(|s| s.strip_prefix(REF_PREFIX)) else {
+            continue;
+        };
+        if let Ok(url) = load(app, conn, hash) {
+            *field = serde_json::Value::String(url);
+        }
+    }
+}
+
+// File of a stored attachment, by reference or bare hash
+pub fn path_of(app: &AppHandle, reference: &str) -> Result<PathBuf, String> {
+    let hash = reference.strip_prefix(REF_PREFIX).unwrap_or(reference);
+    if hash.len() < 2 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("Invalid attachment reference".to_string());
+    }
+    Ok(file_path(&store_dir(app)?, hash))
+}
+
+// MIME type and bytes of a stored attachment, by reference or bare hash
+pub fn read(
+    app: &AppHandle,
+    conn: &Connection,
+    reference: &str,
+) -> Result<(String, Vec<u8>), String> {
+    let hash = reference.strip_prefix(REF_PREFIX).unwrap_or(reference);
+    let bytes = fs::read(path_of(app, hash)?).map_err(|e| e.to_string())?;
+    let mime: String = conn
+        .query_row(
+            "SELECT mime FROM attachments WHERE hash = ?1",
+            [hash],
+            |row| row.get(0),
+        )
+        .unwrap_or_else(|_| "application/octet-stream".to_string());
+    Ok((mime, bytes))
+}
+
+// Data URL for a stored attachment, by reference or bare hash
+pub fn load(app: &AppHandle, conn: &Connection, reference: &str) -> Result<String, String> {
+    let (mime, bytes) = read(app, conn, reference)?;
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+const ENTITY_COLUMNS: &str = "id, entity, entity_id, name, mime, size_bytes, added_by, added_at";
+
+fn entity_attachment(row: &rusqlite::Row) -> rusqlite::Result<EntityAttachment> {
+    Ok(EntityAttachment {
+        id: row.get(0)?,
+        entity: row.get(1)?,
+        entity_id: row.get(2)?,
+        name: row.get(3)?,
+        mime: row.get(4)?,
+        size_bytes: row.get(5)?,
+        added_by: row.get(6)?,
+        added_at: row.get(7)?,
+    })
+}
+
+fn find_attachment(conn: &Connection, id: i64) -> Result<(EntityAttachment, String), String> {
+    conn.query_row(
+        &format!(
+            "SELECT {}, attachment FROM entity_attachments WHERE id = ?1",
+            ENTITY_COLUMNS
+        ),
+        [id],
+        |row| Ok((entity_attachment(row)?, row.get(8)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Attachment {} not found", id))
+}
+
+// Check the record exists before anything is attached to it
+fn require_entity(conn: &Connection, entity: &str, entity_id: &str) -> Result<(), String> {
+    let (_, table) = ENTITIES
+        .iter()
+        .find(|(name, _)| *name == entity)
+        .ok_or_else(|| format!("Files cannot be attached to {}", entity))?;
+    let exists: bool = conn
+        .query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1)", table),
+            [entity_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    match exists {
+        true => Ok(()),
+        false => Err(format!("{} {} not found", entity, entity_id)),
+    }
+}
+
+// Attach a file, sent as base64, to a ticket or master record
+#[tauri::command]
+pub fn save_attachment(
+    app: AppHandle,
+    entity: String,
+    entity_id: String,
+    name: String,
+    mime: String,
+    data: String,
+    user_id: String,
+) -> Result<EntityAttachment, String> {
+    let conn = db::open(&app)?;
+    roles::user_role(&conn, &user_id)?;
+    require_entity(&conn, &entity, &entity_id)?;
+    if name.trim().is_empty() || mime.trim().is_empty() {
+        return Err("An attachment needs a file name and type".to_string());
+    }
+    let bytes = general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| format!("Attachment is not valid base64: {}", e))?;
+    if bytes.is_empty() || bytes.len() > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "Attachments must be between 1 byte and {} MB",
+            MAX_ATTACHMENT_BYTES / (1024 * 1024)
+        ));
+    }
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let reference = store_bytes(&app, &tx, mime.trim(), &bytes)?;
+    tx.execute(
+        "INSERT INTO entity_attachments
+             (entity, entity_id, name, mime, size_bytes, attachment, added_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            entity,
+            entity_id,
+            name.trim(),
+            mime.trim(),
+            bytes.len() as i64,
+            reference,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = tx.last_insert_rowid();
+    tx.commit().map_err(|e| e.to_string())?;
+    find_attachment(&conn, id).map(|(attachment, _)| attachment)
+}
+
+// Files attached to a record, oldest first, without their content
+#[tauri::command]
+pub fn list_attachments(
+    app: AppHandle,
+    entity: String,
+    entity_id: String,
+) -> Result<Vec<EntityAttachment>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM entity_attachments
+             WHERE entity = ?1 AND entity_id = ?2 ORDER BY id",
+            ENTITY_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![entity, entity_id], entity_attachment)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rows)
+}
+
+#[tauri::command]
+pub fn read_attachment(app: AppHandle, id: i64) -> Result<AttachmentContent, String> {
+    let conn = db::open(&app)?;
+    let (attachment, reference) = find_attachment(&conn, id)?;
+    let data = load(&app, &conn, &reference)?;
+    Ok(AttachmentContent { attachment, data })
+}
+
+// Remove an attachment (whoever added it, or an admin). Its file goes too
+// unless something else still refers to the same content.
+#[tauri::command]
+pub fn delete_attachment(app: AppHandle, id: i64, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "id": id });
+    command_audit::audited(&app, "delete_attachment", &user_id, args, || {
+        let conn = db::open(&app)?;
+        let (attachment, reference) = find_attachment(&conn, id)?;
+        if attachment.added_by != user_id {
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+        }
+        conn.execute("DELETE FROM entity_attachments WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+        let hash = reference.strip_prefix(REF_PREFIX).unwrap_or(&reference);
+        if !referenced_hashes(&conn)?.contains(hash) {
+            let path = path_of(&app, hash)?;
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+            conn.execute("DELETE FROM attachments WHERE hash = ?1", [hash])
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    })
+}
+
+// Move inline images into the store, or with `dry_run` only report what that
+// would save. Each table is converted in one transaction.
+#[tauri::command]
+pub fn deduplicate_attachments(
+    app: AppHandle,
+    dry_run: bool,
+    user_id: String,
+) -> Result<DedupReport, String> {
+    let args = serde_json::json!({ "dry_run": dry_run });
+    command_audit::audited(&app, "deduplicate_attachments", &user_id, args, || {
+        let mut conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut report = DedupReport {
+            dry_run,
+            ..Default::default()
+        };
+        let mut seen: HashMap<String, i64> = HashMap::new();
+
+        for (table, key, column) in IMAGE_COLUMNS {
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            let rows: Vec<(String, String)> = {
+                let mut stmt = tx
+                    .prepare(&format!(
+                        "SELECT CAST({} AS TEXT), {} FROM {} WHERE {} LIKE 'data:%'",
+                        key, column, table, column
+                    ))
+                    .map_err(|e| e.to_string())?;
+                let rows = stmt
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                    .map_err(|e| e.to_string())?
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| e.to_string())?;
+                rows
+            };
+            for (id, value) in rows {
+                let Some(data) = parse_data_url(&value) else {
+                    continue;
+                };
+                report.inline_images += 1;
+                report.inline_bytes += value.len() as i64;
+                seen.entry(hash_of(&data.bytes))
+                    .or_insert(data.bytes.len() as i64);
+                if !dry_run {
+                    let reference = store(&app, &tx, &value)?;
+                    tx.execute(
+                        &format!("UPDATE {} SET {} = ?2 WHERE {} = ?1", table, column, key),
+                        params![id, reference],
+                    )
+                    .map_err(|e| e.to_string())?;
+                }
+            }
+            tx.commit().map_err(|e| e.to_string())?;
+        }
+        report.unique_images = seen.len() as i64;
+        report.stored_bytes = seen.values().sum();
+        Ok(report)
+    })
+}
+
+// Keep the files of rows about to move to the archive database, which
+// garbage collection cannot see into. `rows` is a condition on `table`.
+pub fn retain_for_archive(conn: &Connection, table: &str, rows: &str) -> Result<(), String> {
+    for (_, _, column) in IMAGE_COLUMNS.iter().filter(|(t, _, _)| *t == table) {
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO archived_attachments (hash)
+                 SELECT DISTINCT substr({0}, {1}) FROM main.{2} WHERE {3} AND {0} LIKE '{4}%'",
+                column,
+                REF_PREFIX.len() + 1,
+                table,
+                rows,
+                REF_PREFIX
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn referenced_hashes(conn: &Connection) -> Result<HashSet<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT hash FROM archived_attachments")
+        .map_err(|e| e.to_string())?;
+    let mut referenced = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (table, _, column) in IMAGE_COLUMNS {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT DISTINCT {0} FROM {1} WHERE {0} LIKE '{2}%'",
+                column, table, REF_PREFIX
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| e.to_string())?;
+        for value in rows {
+            let value = value.map_err(|e| e.to_string())?;
+            referenced.insert(value[REF_PREFIX.len()..].to_string());
+        }
+    }
+    Ok(referenced)
+}
+
+// Remove stored files no ticket refers to any more, or with `dry_run` list them
+#[tauri::command]
+pub fn collect_attachment_garbage(
+    app: AppHandle,
+    dry_run: bool,
+    user_id: String,
+) -> Result<GcReport, String> {
+    let args = serde_json::json!({ "dry_run": dry_run });
+    command_audit::audited(&app, "collect_attachment_garbage", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let referenced = referenced_hashes(&conn)?;
+        let mut report = GcReport {
+            dry_run,
+            ..Default::default()
+        };
+
+        let Ok(buckets) = fs::read_dir(store_dir(&app)?) else {
+            return Ok(report);
+        };
+        for bucket in buckets.flatten() {
+            let Ok(files) = fs::read_dir(bucket.path()) else {
+                continue;
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                let name = file.file_name().to_string_lossy().to_string();
+                report.files += 1;
+                // Leftovers of an interrupted write are always garbage
+                let orphan = name.ends_with(".partial") || !referenced.contains(&name);
+                if !orphan {
+                    continue;
+                }
+                report.reclaimable_bytes += file.metadata().map(|m| m.len() as i64).unwrap_or(0);
+                if !dry_run {
+                    fs::remove_file(&path).map_err(|e| e.to_string())?;
+                    conn.execute("DELETE FROM attachments WHERE hash = ?1", [&name])
+                        .map_err(|e| e.to_string())?;
+                }
+                report.unreferenced.push(name);
+            }
+        }
+        Ok(report)
+    })
+}
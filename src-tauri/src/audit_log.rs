@@ -0,0 +1,251 @@
+// Data mutation audit for Truckore Pro
+// Every write made through execute_non_query, execute_transaction and
+// execute_batch is recorded in audit_log with the statement, a digest of
+// its parameters, who ran it (the signed-in user of the session token, never
+// a user id the caller sends) and how many rows it touched. Entries are
+// written inside the write's own transaction and the table is append-only.
+
+use crate::command_audit;
+use crate::db::{self, DateRange};
+use crate::export;
+use crate::masking::Masks;
+use crate::roles::{self, Role};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub command: String,
+    pub table_name: Option<String>,
+    pub sql: String,
+    pub params_digest: String,
+    pub rows_affected: i64,
+    pub user_id: Option<String>,
+    pub logged_at: String,
+}
+
+// Record one write on the connection (or transaction) that made it.
+// `table` is the table it wrote, as SQLite resolved it for raw SQL
+// (authorization::prepare_raw_write).
+pub fn record(
+    conn: &Connection,
+    command: &str,
+    table: Option<&str>,
+    sql: &str,
+    params: &serde_json::Value,
+    rows_affected: usize,
+    user_id: Option<&str>,
+) -> Result<(), String> {
+    let digest = Sha256::digest(params.to_string().as_bytes());
+    let params_digest: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    conn.execute(
+        "INSERT INTO audit_log (command, table_name, sql, params_digest, rows_affected, user_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            command,
+            table,
+            sql,
+            params_digest,
+            rows_affected as i64,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Search the data audit log, newest first
+#[tauri::command]
+pub fn query_audit_log(
+    app: AppHandle,
+    table_name: Option<String>,
+    user_id: Option<String>,
+    command: Option<String>,
+    range: Option<DateRange>,
+    limit: Option<i64>,
+) -> Result<Vec<AuditEntry>, String> {
+    let conn = db::open(&app)?;
+    let sql = format!(
+        "SELECT id, command, table_name, sql, params_digest, rows_affected, user_id, logged_at
+         FROM audit_log
+         WHERE (?1 IS NULL OR table_name = ?1)
+           AND (?2 IS NULL OR user_id = ?2)
+           AND (?3 IS NULL OR command = ?3)
+           AND (?4 IS NULL OR {date} >= ?4)
+           AND (?5 IS NULL OR {date} <= ?5)
+         ORDER BY id DESC LIMIT ?6",
+        date = db::local_date("logged_at")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                table_name,
+                user_id,
+                command,
+                range.as_ref().map(|r| &r.from),
+                range.as_ref().map(|r| &r.to),
+                limit.unwrap_or(500)
+            ],
+            |row| {
+                Ok(AuditEntry {
+                    id: row.get(0)?,
+                    command: row.get(1)?,
+                    table_name: row.get(2)?,
+                    sql: row.get(3)?,
+                    params_digest: row.get(4)?,
+                    rows_affected: row.get(5)?,
+                    user_id: row.get(6)?,
+                    logged_at: row.get(7)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Write the audit log to a .csv or .xlsx file for auditors (supervisors
+// only). Returns the number of entries written.
+#[tauri::command]
+pub fn export_audit_log(
+    app: AppHandle,
+    path: String,
+    range: Option<DateRange>,
+    user_id: String,
+) -> Result<usize, String> {
+    let args = serde_json::json!({ "path": path, "range": range });
+    command_audit::audited(&app, "export_audit_log", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        export::write_query(
+            &conn,
+            "audit_log",
+            &path,
+            range.as_ref(),
+            &Masks::default(),
+            &mut |_| Ok(()),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization;
+    use crate::sql_values;
+    use serde_json::{json, Value};
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        conn
+    }
+
+    // One raw write and its entry, the way execute_non_query makes them
+    fn raw_write(
+        conn: &mut Connection,
+        role: Role,
+        sql: &str,
+        params: Value,
+    ) -> Result<usize, String> {
+        let values = params.as_array().cloned().unwrap_or_default();
+        db::write_on(conn, &mut |tx| {
+            let (mut stmt, table) =
+                authorization::prepare_raw_write(tx, role, sql).map_err(|e| e.to_string())?;
+            let rows = stmt
+                .execute(rusqlite::params_from_iter(
+                    sql_values::params(&values)?.iter(),
+                ))
+                .map_err(|e| e.to_string())?;
+            record(
+                tx,
+                "execute_non_query",
+                table.as_deref(),
+                sql,
+                &params,
+                rows,
+                Some("u1"),
+            )?;
+            Ok(rows)
+        })
+    }
+
+    fn entries(conn: &Connection) -> Vec<(Option<String>, String, i64, Option<String>)> {
+        let mut stmt = conn
+            .prepare("SELECT table_name, params_digest, rows_affected, user_id FROM audit_log ORDER BY id")
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn raw_writes_are_recorded_with_their_table_and_user() {
+        let mut conn = conn();
+        let params = json!(["w1", "KA01AB1234"]);
+        let sql = "INSERT INTO \"Weighments\" (id, bill_no, ticket_no, vehicle_no, party_name,
+                   product_name, status) VALUES (?1, ?1, ?1, ?2, 'Acme', 'Sand', 'OPEN')";
+        assert_eq!(
+            raw_write(&mut conn, Role::Operator, sql, params.clone()).unwrap(),
+            1
+        );
+        raw_write(
+            &mut conn,
+            Role::Operator,
+            "UPDATE weighments SET remarks = 'x' WHERE id = 'none'",
+            json!([]),
+        )
+        .unwrap();
+
+        let digest = format!("{:x}", Sha256::digest(params.to_string().as_bytes()));
+        assert_eq!(
+            entries(&conn),
+            vec![
+                (Some("weighments".into()), digest, 1, Some("u1".into())),
+                (
+                    Some("weighments".into()),
+                    format!("{:x}", Sha256::digest(b"[]")),
+                    0,
+                    Some("u1".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn refused_or_failed_writes_leave_no_entry() {
+        let mut conn = conn();
+        assert!(raw_write(&mut conn, Role::Operator, "DELETE FROM users", json!([])).is_err());
+        // NOT NULL columns missing: the statement fails and the entry rolls back with it
+        assert!(raw_write(
+            &mut conn,
+            Role::Admin,
+            "INSERT INTO weighments (id) VALUES ('w1')",
+            json!([])
+        )
+        .is_err());
+        assert!(entries(&conn).is_empty());
+    }
+
+    #[test]
+    fn entries_cannot_be_changed_or_removed() {
+        let mut conn = conn();
+        raw_write(&mut conn, Role::Admin, "DELETE FROM weighments", json!([])).unwrap();
+        for sql in [
+            "UPDATE audit_log SET user_id = 'u2'",
+            "DELETE FROM audit_log",
+        ] {
+            let error = conn.execute(sql, []).unwrap_err();
+            assert!(error.to_string().contains("append-only"), "{}", sql);
+        }
+        assert_eq!(entries(&conn).len(), 1);
+    }
+}
@@ -0,0 +1,248 @@
+// Auditor sessions for Truckore Pro
+// Inspectors from the weights and measures department, and the company's
+// own auditors, need to look through the tickets without being able to
+// change them, and the site needs to know afterwards what they looked at.
+// Users with the auditor role sign in like anyone else, but the guard lets
+// them invoke only reads (get_*, list_* and the commands below), their raw
+// queries and cursors run on a read-only connection, and every command they
+// invoke is logged in auditor_views with its arguments, along with the
+// records their queries returned. Slips they reprint carry an AUDIT VIEW
+// watermark. Auditors are left out of operator statistics.
+
+use crate::auth;
+use crate::command_audit::DENIED_PREFIX;
+use crate::db::{self, DateRange};
+use crate::roles::Role;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+// Reads auditors may invoke besides get_* and list_*
+const READ_COMMANDS: &[&str] = &[
+    "close_cursor",
+    "execute_query",
+    "execute_query_encoded",
+    "execute_query_with_columns",
+    "fetch_next",
+    "format_amount_in_words",
+    "open_query_cursor",
+    "query_archive",
+    "query_audit_log",
+    "query_security_log",
+    "render_slip_layout",
+    "render_slip_png",
+    "run_named_query",
+    "run_report_definition",
+    "search",
+    "verify_all",
+    "verify_weighment",
+    "weighment_feed",
+    "weighment_history",
+    "weighment_slip_pdf",
+    "weighment_summary",
+    "weighment_summary_pdf",
+];
+
+// Reads above the operator role that an audit needs
+const AUDIT_READS: &[&str] = &["list_overrides", "query_audit_log", "query_security_log"];
+
+// Columns whose values identify the records a query returned
+const RECORD_COLUMNS: &[&str] = &["id", "ticket_no", "weighment_id"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditorView {
+    pub id: i64,
+    pub user_id: String,
+    pub command: String,
+    // Arguments of the command, without the session token
+    pub args: Option<Value>,
+    // Ids of the records a query returned
+    pub records: Option<Vec<Value>>,
+    pub viewed_at: String,
+}
+
+// Refuse anything but a read in an auditor's session
+pub fn require_read(command: &str) -> Result<(), String> {
+    let read = command.starts_with("get_")
+        || command.starts_with("list_")
+        || READ_COMMANDS.contains(&command);
+    match read {
+        true => Ok(()),
+        false => Err(format!("{}: auditor sessions are read-only", DENIED_PREFIX)),
+    }
+}
+
+// Whether an auditor may run `command` above the operator role
+pub fn may_read_above_operator(command: &str) -> bool {
+    AUDIT_READS.contains(&command)
+}
+
+fn log(
+    app: &AppHandle,
+    user_id: &str,
+    command: &str,
+    args: Option<&Value>,
+    records: Option<&[Value]>,
+) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let args = args.map(Value::to_string);
+    let records = records
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO auditor_views (user_id, command, args, records) VALUES (?1, ?2, ?3, ?4)",
+        params![user_id, command, args, records],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Record an auditor's invoke before it runs; a read that cannot be logged
+// is refused
+pub fn log_invoke(
+    app: &AppHandle,
+    user_id: &str,
+    command: &str,
+    payload: &Value,
+) -> Result<(), String> {
+    let mut args = payload.clone();
+    if let Some(args) = args.as_object_mut() {
+        args.remove("sessionToken");
+    }
+    log(app, user_id, command, Some(&args), None)
+}
+
+// Record the records among `rows` (result rows keyed by column) an
+// auditor's query returned
+pub fn log_rows(
+    app: &AppHandle,
+    user_id: &str,
+    command: &str,
+    rows: &[Value],
+) -> Result<(), String> {
+    let records: Vec<Value> = rows
+        .iter()
+        .filter_map(|row| {
+            RECORD_COLUMNS
+                .iter()
+                .find_map(|column| row.get(*column).filter(|v| !v.is_null()))
+                .cloned()
+        })
+        .collect();
+    if records.is_empty() {
+        return Ok(());
+    }
+    log(app, user_id, command, None, Some(&records))
+}
+
+// The auditor signed in with `token`, None for anyone else
+pub fn session_auditor(app: &AppHandle, token: Option<&str>) -> Result<Option<String>, String> {
+    let Some(token) = token else {
+        return Ok(None);
+    };
+    let user = auth::session_user(app, token)?;
+    Ok((user.role == Role::Auditor.as_str()).then_some(user.id))
+}
+
+// SQL condition leaving auditors out of per-operator figures
+pub fn exclude_auditors(user_column: &str) -> String {
+    format!(
+        "{} NOT IN (SELECT id FROM users WHERE role = 'auditor')",
+        user_column
+    )
+}
+
+// The users table allowed only three roles; rebuild it with the auditor's.
+// Its columns (including those added by migrations), indexes and triggers
+// are kept, since the new table is made from its own stored definition.
+pub fn migrate(conn: &rusqlite::Connection) -> Result<(), String> {
+    let old = "CHECK(role IN ('super_admin', 'admin', 'operator'))";
+    let sql: String = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'users'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if !sql.contains(old) {
+        return Ok(());
+    }
+    let rebuilt = sql
+        .replacen(
+            old,
+            "CHECK(role IN ('super_admin', 'admin', 'operator', 'auditor'))",
+            1,
+        )
+        .replacen("CREATE TABLE users", "CREATE TABLE users_rebuilt", 1);
+    let mut stmt = conn
+        .prepare(
+            "SELECT sql FROM sqlite_master
+             WHERE tbl_name = 'users' AND type IN ('index', 'trigger') AND sql IS NOT NULL",
+        )
+        .map_err(|e| e.to_string())?;
+    let dependents = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    // Renaming with the legacy behaviour leaves the references in other
+    // tables, which already name users, alone
+    conn.execute_batch(&format!(
+        "{};
+         INSERT INTO users_rebuilt SELECT * FROM users;
+         DROP TABLE users;
+         PRAGMA legacy_alter_table = ON;
+         ALTER TABLE users_rebuilt RENAME TO users;
+         PRAGMA legacy_alter_table = OFF;",
+        rebuilt
+    ))
+    .map_err(|e| e.to_string())?;
+    for sql in dependents {
+        conn.execute_batch(&sql).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// What auditors viewed, newest first (admin only)
+#[tauri::command]
+pub fn list_auditor_views(
+    app: AppHandle,
+    auditor_id: Option<String>,
+    range: Option<DateRange>,
+    limit: Option<i64>,
+) -> Result<Vec<AuditorView>, String> {
+    let conn = db::open(&app)?;
+    let sql = format!(
+        "SELECT id, user_id, command, args, records, viewed_at FROM auditor_views
+         WHERE (?1 IS NULL OR user_id = ?1) AND (?2 IS NULL OR {} BETWEEN ?2 AND ?3)
+         ORDER BY id DESC LIMIT ?4",
+        db::local_date("viewed_at")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                auditor_id,
+                range.as_ref().map(|r| &r.from),
+                range.as_ref().map(|r| &r.to),
+                limit.unwrap_or(500)
+            ],
+            |row| {
+                let args: Option<String> = row.get(3)?;
+                let records: Option<String> = row.get(4)?;
+                Ok(AuditorView {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    command: row.get(2)?,
+                    args: args.and_then(|a| serde_json::from_str(&a).ok()),
+                    records: records.and_then(|r| serde_json::from_str(&r).ok()),
+                    viewed_at: row.get(5)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
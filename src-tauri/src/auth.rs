@@ -0,0 +1,408 @@
+// Authentication for Truckore Pro
+// Passwords were checked in the frontend, where editing the JS was enough to
+// sign in as anyone. Logins, new users and password changes now go through
+// these commands: the hash never leaves Rust, a successful login issues a
+// session token held in managed state, and the user's role comes from the
+// database with it. Repeated failures lock the account for 30 minutes, as
+// the frontend did. A session belongs to the database it signed in to; once
+// the profile or company points elsewhere it has to sign in again.
+// New passwords are hashed with Argon2id. Bcrypt hashes from the frontend era
+// still verify and are replaced with Argon2 at the user's next login.
+
+use crate::db;
+use crate::roles::{self, Role};
+use crate::security;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+// Checked against when the username is unknown, so that refusal takes as
+// long as a wrong password for a real user
+const NO_USER_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$dHJ1Y2tvcmUtbm8tdXNlcg$dUovkt5vgR3LR/arFPiQSOK2czthNYQyoAjMWApwMF4";
+const MAX_FAILED_ATTEMPTS: i64 = 5;
+const LOCK_MINUTES: i64 = 30;
+// A session unused this long (about a shift) has to sign in again
+const SESSION_IDLE: Duration = Duration::from_secs(10 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionUser {
+    pub id: String,
+    pub username: String,
+    pub role: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginResult {
+    pub token: String,
+    pub user: SessionUser,
+}
+
+struct Session {
+    user: SessionUser,
+    // Database file the user signed in to
+    db_path: PathBuf,
+    last_used: Instant,
+}
+
+// Managed state: signed-in sessions by token
+#[derive(Default)]
+pub struct Sessions(Mutex<HashMap<String, Session>>);
+
+// Hash a new password after checking it is long enough
+pub fn hash_password(password: &str) -> Result<String, String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    hash_secret(password)
+}
+
+// Argon2id hash of a password or PIN, without the length check
+pub fn hash_secret(secret: &str) -> Result<String, String> {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = SaltString::encode_b64(&salt).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+// Check a password or PIN against an Argon2 hash or a legacy bcrypt one
+pub fn verify_secret(secret: &str, hash: &str) -> bool {
+    if is_legacy_hash(hash) {
+        return bcrypt::verify(secret, hash).unwrap_or(false);
+    }
+    PasswordHash::new(hash)
+        .map(|parsed| {
+            Argon2::default()
+                .verify_password(secret.as_bytes(), &parsed)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+fn is_legacy_hash(hash: &str) -> bool {
+    hash.starts_with("$2")
+}
+
+// User behind a session token, refreshing its idle timer. A session for
+// another database than the active one is ended.
+pub fn session_user(app: &AppHandle, token: &str) -> Result<SessionUser, String> {
+    let db_path = crate::get_db_path(app)?;
+    let sessions = app.state::<Sessions>();
+    let mut open = sessions.0.lock().map_err(|e| e.to_string())?;
+    open.retain(|_, session| session.last_used.elapsed() < SESSION_IDLE);
+    let expired = "Session expired; sign in again".to_string();
+    let session = open.get_mut(token).ok_or_else(|| expired.clone())?;
+    if session.db_path != db_path {
+        open.remove(token);
+        return Err(expired);
+    }
+    session.last_used = Instant::now();
+    Ok(session.user.clone())
+}
+
+// Whether anyone is signed in
+pub fn signed_in(sessions: &Sessions) -> Result<bool, String> {
+    let mut open = sessions.0.lock().map_err(|e| e.to_string())?;
+    open.retain(|_, session| session.last_used.elapsed() < SESSION_IDLE);
+    Ok(!open.is_empty())
+}
+
+struct StoredUser {
+    id: String,
+    username: String,
+    role: String,
+    password_hash: String,
+    is_active: bool,
+    locked: bool,
+}
+
+fn load_user(conn: &Connection, column: &str, value: &str) -> Result<Option<StoredUser>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT id, username, role, password_hash, is_active,
+                    COALESCE(julianday(locked_until) > julianday('now'), 0)
+             FROM users WHERE {} = ?1",
+            column
+        ),
+        params![value],
+        |row| {
+            Ok(StoredUser {
+                id: row.get(0)?,
+                username: row.get(1)?,
+                role: row.get(2)?,
+                password_hash: row.get(3)?,
+                is_active: row.get(4)?,
+                locked: row.get(5)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Count a failed attempt; returns whether the account is now locked
+fn record_failure(conn: &Connection, user: &StoredUser) -> Result<bool, String> {
+    conn.execute(
+        "UPDATE users SET failed_login_attempts = failed_login_attempts + 1,
+                locked_until = CASE WHEN failed_login_attempts + 1 >= ?2
+                    THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?3) ELSE locked_until END
+         WHERE id = ?1",
+        params![
+            user.id,
+            MAX_FAILED_ATTEMPTS,
+            format!("+{} minutes", LOCK_MINUTES)
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let attempts: i64 = conn
+        .query_row(
+            "SELECT failed_login_attempts FROM users WHERE id = ?1",
+            params![user.id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(attempts >= MAX_FAILED_ATTEMPTS)
+}
+
+// Sign everyone out, e.g. when the database behind the sessions changes
+pub fn end_all(sessions: &Sessions) -> Result<(), String> {
+    sessions.0.lock().map_err(|e| e.to_string())?.clear();
+    Ok(())
+}
+
+// Check a username and password and open a session
+#[tauri::command]
+pub fn verify_login(
+    app: AppHandle,
+    sessions: State<'_, Sessions>,
+    username: String,
+    password: String,
+) -> Result<LoginResult, String> {
+    login(&app, &sessions, &username, &password)
+}
+
+// verify_login, also used by LAN terminals (lan_terminal.rs)
+pub fn login(
+    app: &AppHandle,
+    sessions: &Sessions,
+    username: &str,
+    password: &str,
+) -> Result<LoginResult, String> {
+    let conn = db::open(app)?;
+    // The same message for every refusal, so usernames cannot be probed
+    let refused = "Invalid username or password".to_string();
+    let Some(user) = load_user(&conn, "username", username.trim())? else {
+        verify_secret(password, NO_USER_HASH);
+        security::log_event(
+            &conn,
+            None,
+            "LOGIN_FAILED",
+            &format!("Unknown user {}", username),
+        )?;
+        return Err(refused);
+    };
+    if !user.is_active {
+        verify_secret(password, NO_USER_HASH);
+        security::log_event(&conn, Some(&user.id), "LOGIN_FAILED", "Account inactive")?;
+        return Err(refused);
+    }
+    if user.locked {
+        security::log_event(&conn, Some(&user.id), "LOGIN_FAILED", "Account locked")?;
+        return Err(format!(
+            "Account locked after {} failed attempts; try again later",
+            MAX_FAILED_ATTEMPTS
+        ));
+    }
+    // Rows with a role the backend does not know cannot sign in
+    Role::parse(&user.role)?;
+    if !verify_secret(password, &user.password_hash) {
+        let locked = record_failure(&conn, &user)?;
+        security::log_event(&conn, Some(&user.id), "LOGIN_FAILED", "Wrong password")?;
+        if locked {
+            security::log_event(
+                &conn,
+                Some(&user.id),
+                "ACCOUNT_LOCKED",
+                &format!("Locked for {} minutes", LOCK_MINUTES),
+            )?;
+        }
+        return Err(refused);
+    }
+
+    conn.execute(
+        "UPDATE users SET failed_login_attempts = 0, locked_until = NULL,
+                last_login_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![user.id],
+    )
+    .map_err(|e| e.to_string())?;
+    if is_legacy_hash(&user.password_hash) {
+        conn.execute(
+            "UPDATE users SET password_hash = ?2 WHERE id = ?1",
+            params![user.id, hash_secret(password)?],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    security::log_event(&conn, Some(&user.id), "LOGIN_SUCCESS", &user.username)?;
+    let session_user = SessionUser {
+        id: user.id,
+        username: user.username,
+        role: user.role,
+    };
+    let token = uuid::Uuid::new_v4().to_string();
+    sessions.0.lock().map_err(|e| e.to_string())?.insert(
+        token.clone(),
+        Session {
+            user: session_user.clone(),
+            db_path: crate::get_db_path(app)?,
+            last_used: Instant::now(),
+        },
+    );
+    Ok(LoginResult {
+        token,
+        user: session_user,
+    })
+}
+
+// The signed-in user, e.g. after a page reload
+#[tauri::command]
+pub fn current_session(app: AppHandle, token: String) -> Result<SessionUser, String> {
+    session_user(&app, &token)
+}
+
+#[tauri::command]
+pub fn logout(sessions: State<'_, Sessions>, token: String) -> Result<(), String> {
+    end(&sessions, &token)
+}
+
+pub fn end(sessions: &Sessions, token: &str) -> Result<(), String> {
+    sessions.0.lock().map_err(|e| e.to_string())?.remove(token);
+    Ok(())
+}
+
+// Add a user (admins). Only a super admin can create another super admin.
+// Returns the new user's id.
+#[tauri::command]
+pub fn create_user(
+    app: AppHandle,
+    token: String,
+    username: String,
+    password: String,
+    email: Option<String>,
+    role: String,
+) -> Result<String, String> {
+    let actor = session_user(&app, &token)?;
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &actor.id, Role::Admin)?;
+    let role = Role::parse(&role)?;
+    if role > roles::user_role(&conn, &actor.id)? {
+        return Err(format!(
+            "{}: cannot create a {} user",
+            crate::command_audit::DENIED_PREFIX,
+            role.as_str()
+        ));
+    }
+    let username = username.trim();
+    if username.is_empty() {
+        return Err("Username is required".to_string());
+    }
+    let hash = hash_password(&password)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO users (id, username, email, password_hash, role)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, username, email, hash, role.as_str()],
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            format!("Username {} is taken", username)
+        }
+        other => other.to_string(),
+    })?;
+    security::log_event(
+        &conn,
+        Some(&actor.id),
+        "USER_CREATED",
+        &format!("{} ({})", username, role.as_str()),
+    )?;
+    Ok(id)
+}
+
+// Change the signed-in user's own password. Their other sessions are ended.
+#[tauri::command]
+pub fn change_password(
+    app: AppHandle,
+    sessions: State<'_, Sessions>,
+    token: String,
+    current_password: String,
+    new_password: String,
+) -> Result<(), String> {
+    let actor = session_user(&app, &token)?;
+    let conn = db::open(&app)?;
+    let user = load_user(&conn, "id", &actor.id)?.ok_or("User no longer exists")?;
+    if !verify_secret(&current_password, &user.password_hash) {
+        security::log_event(
+            &conn,
+            Some(&actor.id),
+            "LOGIN_FAILED",
+            "Wrong current password when changing password",
+        )?;
+        return Err("Current password is wrong".to_string());
+    }
+    let hash = hash_password(&new_password)?;
+    conn.execute(
+        "UPDATE users SET password_hash = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+        params![actor.id, hash],
+    )
+    .map_err(|e| e.to_string())?;
+    security::log_event(&conn, Some(&actor.id), "PASSWORD_CHANGED", &actor.username)?;
+    sessions
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .retain(|other, session| *other == token || session.user.id != actor.id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn argon2_and_legacy_bcrypt_hashes_verify() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_secret("correct horse", &hash));
+        assert!(!verify_secret("wrong horse", &hash));
+        let legacy = bcrypt::hash("correct horse", 4).unwrap();
+        assert!(is_legacy_hash(&legacy));
+        assert!(verify_secret("correct horse", &legacy));
+        assert!(!verify_secret("wrong horse", &legacy));
+        assert!(hash_password("short").is_err());
+    }
+
+    #[test]
+    fn unknown_users_pay_for_a_real_argon2_check() {
+        // A hash that failed to parse would return at once
+        let parsed = PasswordHash::new(NO_USER_HASH).unwrap();
+        assert_eq!(parsed.algorithm.as_str(), "argon2id");
+        assert!(verify_secret("no such user", NO_USER_HASH));
+        assert!(!verify_secret("anything", NO_USER_HASH));
+    }
+}
@@ -0,0 +1,526 @@
+// Command authorization for Truckore Pro
+// Any page in the webview could invoke any command, passing any user_id, and
+// execute_non_query let an operator run arbitrary SQL. Every invoke now goes
+// through guard(): apart from the commands used before signing in, it needs
+// the `sessionToken` issued by verify_login for the active database (a
+// profile or company switch once signed in is for admins), the session's
+// role must meet the command's minimum and a `userId` argument must be the
+// signed-in user.
+// An operator's raw writes are limited to INSERT, UPDATE and DELETE on
+// OPERATOR_TABLES, checked by SQLite as they are prepared
+// (prepare_raw_write), which also keeps columns masked for the session's
+// role (masking.rs) from being copied elsewhere by a write. Lane commands
+// also need the lane to be in the user's operator profile
+// (operator_profiles.rs). Raw SQL commands can be switched off entirely, see
+// query_registry.rs. Premium commands also
+// need a trial or license (license.rs). Auditors may only read, and their
+// reads are logged (auditor.rs). Invokes are timed for runtime_metrics.rs
+// here too.
+
+use crate::auditor;
+use crate::auth::{self, SessionUser, Sessions};
+use crate::command_audit::{self, DENIED_PREFIX};
+use crate::db;
+use crate::errors::{AppError, ErrorCode};
+use crate::export;
+use crate::license;
+use crate::masking;
+use crate::operator_profiles;
+use crate::query_registry;
+use crate::roles::Role;
+use crate::runtime_metrics;
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::{Connection, Statement};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Invoke, InvokeMessage, Manager, Wry};
+
+// Needed before anyone can sign in: startup, company and profile choice,
+// unlocking or repairing the database, signing in and the setup wizard's
+// first steps
+const PUBLIC_COMMANDS: &[&str] = &[
+    "init_database",
+    "check_database_health",
+    "list_companies",
+    "create_initial_admin",
+    "current_session",
+    "detect_hardware",
+    "get_active_profile",
+    "get_database_encryption",
+    "get_license_status",
+    "get_schema_version",
+    "get_setup_status",
+    "list_profiles",
+    "logout",
+    "recover_database",
+    "recovery_report",
+    "select_profile",
+    "set_database_key",
+    "switch_company",
+    "test_printer",
+    "test_scale_connection",
+    "unlock_remembered_database",
+    "verify_login",
+];
+
+// Public only while nobody is signed in; afterwards they need the role in
+// COMMAND_ROLES, so a session cannot be carried to another database
+const SIGNED_OUT_COMMANDS: &[&str] = &["select_profile", "switch_company"];
+
+// Commands above the operator role. Supervisors hold the admin role.
+// Everything else needs a signed-in operator.
+const COMMAND_ROLES: &[(&str, Role)] = &[
+    ("activate_license", Role::Admin),
+    ("activate_script", Role::Admin),
+    ("add_exchange_rate", Role::Admin),
+    ("adjust_stock", Role::Admin),
+    ("apply_remote_flags", Role::Admin),
+    ("archive_before", Role::Admin),
+    ("backup_database", Role::Admin),
+    ("bind_tag_to_vehicle", Role::Admin),
+    ("cancel_bulk_job", Role::Admin),
+    ("cancel_task", Role::Admin),
+    ("check_schema_drift", Role::Admin),
+    ("close_purchase_order", Role::Admin),
+    ("close_day", Role::Admin),
+    ("close_shift", Role::Admin),
+    ("collect_attachment_garbage", Role::Admin),
+    ("complete_setup", Role::Admin),
+    ("create_company", Role::Admin),
+    ("create_party_token", Role::Admin),
+    ("create_purchase_order", Role::Admin),
+    ("create_user", Role::Admin),
+    ("deduplicate_attachments", Role::Admin),
+    ("delete_operator_profile", Role::Admin),
+    ("delete_report_definition", Role::Admin),
+    ("delete_secret", Role::Admin),
+    ("export_audit_log", Role::Admin),
+    ("export_configuration", Role::Admin),
+    ("export_logs", Role::Admin),
+    ("forget_database_key", Role::Admin),
+    ("generate_consolidated_invoices", Role::Admin),
+    ("import_configuration", Role::SuperAdmin),
+    ("install_ca_certificate", Role::Admin),
+    ("list_auditor_views", Role::Admin),
+    ("list_deleted_records", Role::Admin),
+    ("list_overrides", Role::Admin),
+    ("list_support_queries", Role::Admin),
+    ("migrate_to_encrypted", Role::Admin),
+    ("prepare_update", Role::Admin),
+    ("preview_report_definition", Role::Admin),
+    ("publish_configuration", Role::SuperAdmin),
+    ("pull_configuration", Role::Admin),
+    ("purge_practice_data", Role::Admin),
+    ("query_audit_log", Role::Admin),
+    ("query_security_log", Role::Admin),
+    ("re_rate_tickets", Role::Admin),
+    ("reindex", Role::Admin),
+    ("remember_database_key", Role::Admin),
+    ("remove_ca_certificate", Role::Admin),
+    ("remove_lane_camera", Role::Admin),
+    ("remove_weighing_sequence", Role::Admin),
+    ("repair_database", Role::Admin),
+    ("reset_sync_watermark", Role::Admin),
+    ("resolve_dispute", Role::Admin),
+    ("resolve_stale_tickets", Role::Admin),
+    ("restore_database", Role::Admin),
+    ("restore_from_archive", Role::Admin),
+    ("restore_from_cloud", Role::Admin),
+    ("review_sync_conflict", Role::Admin),
+    ("revoke_party_token", Role::Admin),
+    ("rollback_configuration", Role::Admin),
+    ("save_operator_profile", Role::Admin),
+    ("save_print_template", Role::Admin),
+    ("save_report_definition", Role::Admin),
+    ("save_script", Role::Admin),
+    ("seed_default_tariffs", Role::Admin),
+    ("select_profile", Role::Admin),
+    ("send_heartbeat", Role::Admin),
+    ("send_test_email", Role::Admin),
+    ("set_anpr_settings", Role::Admin),
+    ("set_backup_schedule", Role::Admin),
+    ("set_bandwidth_settings", Role::Admin),
+    ("set_base_currency", Role::SuperAdmin),
+    ("set_billing_instruction", Role::Admin),
+    ("set_capture_rule", Role::Admin),
+    ("set_company_profile", Role::Admin),
+    ("set_cloud_backup_settings", Role::Admin),
+    ("set_crash_upload_opt_in", Role::Admin),
+    ("set_credit_limit", Role::Admin),
+    ("set_deduction_rule", Role::Admin),
+    ("set_export_destination", Role::Admin),
+    ("set_feature_flag", Role::Admin),
+    ("set_fraud_rules", Role::Admin),
+    ("set_headless_settings", Role::Admin),
+    ("set_heartbeat_settings", Role::Admin),
+    ("set_lan_server_tls", Role::Admin),
+    ("set_lane", Role::Admin),
+    ("set_lane_camera", Role::Admin),
+    ("set_masking_policy", Role::Admin),
+    ("set_mining_portal_settings", Role::Admin),
+    ("set_movement_rule", Role::Admin),
+    ("set_number_format", Role::Admin),
+    ("set_positioning_settings", Role::Admin),
+    ("set_printer_profile", Role::Admin),
+    ("set_profile_settings", Role::Admin),
+    ("set_proxy_settings", Role::Admin),
+    ("set_relay_board", Role::Admin),
+    ("set_relay_sequences", Role::Admin),
+    ("set_report_schedule", Role::Admin),
+    ("set_rfid_reader", Role::Admin),
+    ("set_rounding_rules", Role::Admin),
+    ("set_scale_config", Role::Admin),
+    ("set_secret", Role::Admin),
+    ("set_stability_settings", Role::Admin),
+    ("set_stale_ticket_hours", Role::Admin),
+    ("set_supervisor_pin", Role::Admin),
+    ("set_sync_settings", Role::Admin),
+    ("set_tariff", Role::Admin),
+    ("set_telemetry_opt_in", Role::Admin),
+    ("set_thermal_printer", Role::Admin),
+    ("set_ticket_numbering", Role::Admin),
+    ("set_training_mode", Role::Admin),
+    ("set_update_channel", Role::SuperAdmin),
+    ("set_update_manifest", Role::SuperAdmin),
+    ("set_vehicle_class", Role::Admin),
+    ("set_vehicle_class_validation", Role::Admin),
+    ("set_watermark_settings", Role::Admin),
+    ("set_weighing_sequence", Role::Admin),
+    ("start_bulk_job", Role::Admin),
+    ("start_lan_server", Role::Admin),
+    ("stop_lan_server", Role::Admin),
+    ("support_query", Role::Admin),
+    ("switch_company", Role::Admin),
+    ("unbind_tag", Role::Admin),
+    ("undelete_with_dependencies", Role::Admin),
+    ("unlock_period", Role::Admin),
+    ("upload_backup_to_cloud", Role::Admin),
+    ("vacuum_database", Role::Admin),
+];
+
+// Commands whose `userId` is a search filter rather than the caller
+const USER_FILTER_COMMANDS: &[&str] = &["query_audit_log", "query_security_log"];
+
+// Tables operators may write with raw SQL: the tickets and masters the
+// operator screens edit. Every other table is changed through its own
+// commands, which check roles themselves, so a table added later is
+// protected without being listed here.
+const OPERATOR_TABLES: &[&str] = &[
+    "open_tickets",
+    "parties",
+    "products",
+    "stored_tares",
+    "vehicles",
+    "weighments",
+];
+
+fn required_role(command: &str) -> Role {
+    COMMAND_ROLES
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map_or(Role::Operator, |(_, role)| *role)
+}
+
+// Role and user id of the session behind `token`, for raw writes and their
+// audit_log entries. Without one the operator's limits apply, so a write
+// that lost its token is not allowed more, and no user is recorded.
+pub fn session_writer(
+    app: &AppHandle,
+    token: Option<&str>,
+) -> Result<(Role, Option<String>), String> {
+    match token {
+        Some(token) => {
+            let user = auth::session_user(app, token)?;
+            Ok((Role::parse(&user.role)?, Some(user.id)))
+        }
+        None => Ok((Role::Operator, None)),
+    }
+}
+
+// Prepare a raw write for a session with `role`, returning the statement
+// and the first table it writes (for audit_log). SQLite names every table
+// the statement touches to an authorizer while compiling it, so below the
+// admin role a write to a table outside OPERATOR_TABLES, or anything other
+// than reading and INSERT, UPDATE or DELETE (PRAGMA, ATTACH, DDL), fails
+// however the SQL spells the table. For any role, a column masked from it
+// may not be read, so `SET remarks = charges_minor` cannot copy it into
+// one that is not. What the schema's triggers do is theirs and allowed.
+// The authorizer is removed again before the connection is reused.
+pub fn prepare_raw_write<'c>(
+    conn: &'c Connection,
+    role: Role,
+    sql: &str,
+) -> Result<(Statement<'c>, Option<String>), AppError> {
+    let restricted = role < Role::Admin;
+    let masks = masking::for_role(conn, role.as_str())?.for_raw_sql();
+    // The first table written and the reason for a refusal
+    let seen: Arc<Mutex<(Option<String>, Option<String>)>> = Arc::default();
+    let noted = Arc::clone(&seen);
+    conn.authorizer(Some(move |context: AuthContext<'_>| {
+        if context.accessor.is_some() {
+            return Authorization::Allow;
+        }
+        let Ok(mut noted) = noted.lock() else {
+            return Authorization::Deny;
+        };
+        let table = match context.action {
+            AuthAction::Insert { table_name }
+            | AuthAction::Update { table_name, .. }
+            | AuthAction::Delete { table_name } => table_name.to_lowercase(),
+            AuthAction::Read { column_name, .. } if masks.hides(column_name) => {
+                noted.1.get_or_insert_with(|| {
+                    format!("{} is masked and cannot be read in a write", column_name)
+                });
+                return Authorization::Deny;
+            }
+            AuthAction::Select
+            | AuthAction::Read { .. }
+            | AuthAction::Function { .. }
+            | AuthAction::Recursive => return Authorization::Allow,
+            _ if restricted => {
+                noted.1.get_or_insert_with(|| {
+                    "operators may only run INSERT, UPDATE and DELETE".to_string()
+                });
+                return Authorization::Deny;
+            }
+            _ => return Authorization::Allow,
+        };
+        if restricted && !OPERATOR_TABLES.contains(&table.as_str()) {
+            noted
+                .1
+                .get_or_insert_with(|| format!("operators may not write to {}", table));
+            return Authorization::Deny;
+        }
+        noted.0.get_or_insert(table);
+        Authorization::Allow
+    }));
+    let statement = conn.prepare(sql);
+    conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+    let (table, refusal) = std::mem::take(&mut *seen.lock().map_err(|e| e.to_string())?);
+    if let Some(refusal) = refusal {
+        return Err(AppError::new(
+            ErrorCode::PermissionDenied,
+            format!("{}: {}", DENIED_PREFIX, refusal),
+        ));
+    }
+    Ok((statement?, table))
+}
+
+// Check one invoke. A refusal carries the signed-in user's id when there
+// is a session.
+fn authorize(message: &InvokeMessage<Wry>) -> Result<(), (Option<String>, String)> {
+    let command = message.command();
+    let app = message.window_ref().app_handle();
+    if PUBLIC_COMMANDS.contains(&command) {
+        let signed_out_only = SIGNED_OUT_COMMANDS.contains(&command);
+        if !signed_out_only || !auth::signed_in(&app.state::<Sessions>()).map_err(|e| (None, e))? {
+            return Ok(());
+        }
+    }
+    let payload = message.payload();
+    let token = payload["sessionToken"]
+        .as_str()
+        .ok_or((None, "Not signed in".to_string()))?;
+    let user = auth::session_user(&app, token).map_err(|e| (None, e))?;
+    check(&app, &user, command, payload).map_err(|reason| (Some(user.id.clone()), reason))
+}
+
+// The checks for a signed-in user running `command` with `payload`, also
+// made for LAN terminals (lan_terminal.rs)
+pub fn check(
+    app: &AppHandle,
+    user: &SessionUser,
+    command: &str,
+    payload: &Value,
+) -> Result<(), String> {
+    let role = Role::parse(&user.role)?;
+    if role == Role::Auditor {
+        auditor::require_read(command)?;
+    }
+    let required = required_role(command);
+    let audit_read = role == Role::Auditor
+        && (required == Role::Operator || auditor::may_read_above_operator(command));
+    if role < required && !audit_read {
+        return Err(format!(
+            "{}: requires {} role",
+            DENIED_PREFIX,
+            required.as_str()
+        ));
+    }
+    if let Some(claimed) = payload["userId"].as_str() {
+        if claimed != user.id && !USER_FILTER_COMMANDS.contains(&command) {
+            return Err(format!(
+                "{}: userId is not the signed-in user",
+                DENIED_PREFIX
+            ));
+        }
+    }
+    license::require(app, command)?;
+    let raw_export = query_registry::RAW_SQL_EXPORTS.contains(&command)
+        && !export::is_entity(payload["queryOrEntity"].as_str().unwrap_or_default());
+    if query_registry::RAW_SQL_COMMANDS.contains(&command) || raw_export {
+        query_registry::require_raw_sql(app)?;
+    }
+    if operator_profiles::LANE_COMMANDS.contains(&command) {
+        if let Some(lane_id) = payload["laneId"].as_str() {
+            let conn = db::open(app)?;
+            operator_profiles::require_lane(&conn, &user.id, lane_id)?;
+        }
+    }
+    if role == Role::Auditor {
+        auditor::log_invoke(app, &user.id, command, payload)?;
+    }
+    Ok(())
+}
+
+// Wrap the generated command handler, timing every invoke. Refusals of a
+// signed-in user are recorded in the command audit log; a missing or
+// expired session is only rejected. Either way the page gets a structured
+// permission_denied error (errors.rs).
+pub fn guard(
+    handler: impl Fn(Invoke<Wry>) + Send + Sync + 'static,
+) -> impl Fn(Invoke<Wry>) + Send + Sync + 'static {
+    move |invoke: Invoke<Wry>| {
+        runtime_metrics::begin(&invoke.message);
+        match authorize(&invoke.message) {
+            Ok(()) => handler(invoke),
+            Err((user_id, reason)) => {
+                if let Some(user_id) = user_id {
+                    let mut args = invoke.message.payload().clone();
+                    if let Some(args) = args.as_object_mut() {
+                        args.remove("sessionToken");
+                    }
+                    let app = invoke.message.window_ref().app_handle();
+                    let _ = command_audit::audited(
+                        &app,
+                        invoke.message.command(),
+                        &user_id,
+                        args,
+                        || Err::<(), _>(reason.clone()),
+                    );
+                }
+                invoke
+                    .resolver
+                    .reject(AppError::new(ErrorCode::PermissionDenied, reason))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        conn
+    }
+
+    fn refused(conn: &Connection, role: Role, sql: &str) -> bool {
+        match prepare_raw_write(conn, role, sql) {
+            Ok(_) => false,
+            Err(e) => {
+                assert_eq!(e.code, ErrorCode::PermissionDenied, "{}: {}", sql, e);
+                true
+            }
+        }
+    }
+
+    #[test]
+    fn operators_write_only_operator_tables_however_they_are_spelled() {
+        let conn = conn();
+        for sql in [
+            "UPDATE users SET role = 'super_admin'",
+            "UPDATE main . users SET role = 'super_admin'",
+            "UPDATE \"users\" SET role = 'super_admin'",
+            "update [main].[USERS] set role = 'super_admin'",
+            "WITH x AS (SELECT 1) DELETE FROM audit_log",
+            "INSERT INTO archive_moves (weighment_id) VALUES ('w1')",
+            "INSERT INTO practice_tickets (weighment_id) VALUES ('w1')",
+            "REPLACE INTO app_config (key, value) VALUES ('k', 'v')",
+            "PRAGMA writable_schema = ON",
+            "DROP TABLE weighments",
+            "CREATE TEMP TRIGGER t AFTER INSERT ON weighments BEGIN DELETE FROM users; END",
+            "ATTACH DATABASE ':memory:' AS other",
+        ] {
+            assert!(refused(&conn, Role::Operator, sql), "{}", sql);
+        }
+        let (_, table) = prepare_raw_write(
+            &conn,
+            Role::Operator,
+            "UPDATE main.weighments SET remarks = 'x' WHERE id = 'w1'",
+        )
+        .unwrap();
+        assert_eq!(table.as_deref(), Some("weighments"));
+    }
+
+    #[test]
+    fn operators_may_not_read_masked_columns_in_a_write() {
+        let conn = conn();
+        let policy = masking::MaskingPolicy {
+            enabled: true,
+            ..Default::default()
+        };
+        db::set_config(
+            &conn,
+            "masking_policy",
+            &serde_json::to_string(&policy).unwrap(),
+        )
+        .unwrap();
+        for sql in [
+            "UPDATE weighments SET remarks = charges_minor",
+            "UPDATE weighments SET remarks = 'x' WHERE charges_minor > 100",
+            "INSERT INTO parties (id, party_name) SELECT id, charges_minor FROM weighments",
+        ] {
+            assert!(refused(&conn, Role::Operator, sql), "{}", sql);
+        }
+        prepare_raw_write(
+            &conn,
+            Role::Operator,
+            "UPDATE weighments SET remarks = 'x' WHERE id = 'w1'",
+        )
+        .unwrap();
+        prepare_raw_write(
+            &conn,
+            Role::Admin,
+            "UPDATE weighments SET remarks = charges_minor",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn admins_may_write_any_table() {
+        let conn = conn();
+        let (_, table) = prepare_raw_write(
+            &conn,
+            Role::Admin,
+            "UPDATE \"users\" SET is_active = 1 WHERE id = 'u1'",
+        )
+        .unwrap();
+        assert_eq!(table.as_deref(), Some("users"));
+    }
+
+    #[test]
+    fn triggers_still_write_their_own_tables() {
+        let conn = conn();
+        db::set_config(&conn, "training_mode", "true").unwrap();
+        let (mut stmt, _) = prepare_raw_write(
+            &conn,
+            Role::Operator,
+            "INSERT INTO weighments
+                 (id, bill_no, ticket_no, vehicle_no, party_name, product_name, status)
+             VALUES ('w1', 'w1', 'T1', 'KA01AB1234', 'Party', 'Sand', 'OPEN')",
+        )
+        .unwrap();
+        assert_eq!(stmt.execute([]).unwrap(), 1);
+        let practice: i64 = conn
+            .query_row("SELECT COUNT(*) FROM practice_tickets", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(practice, 1);
+        // The authorizer is gone once the statement is prepared
+        conn.execute_batch("PRAGMA user_version = 1").unwrap();
+    }
+}
@@ -0,0 +1,330 @@
+// Backup, restore and backup verification for Truckore Pro
+// Backups use SQLite's online backup API, so they are consistent even while
+// tickets are being written. A backup is only trusted after it has been
+// restored somewhere and checked.
+
+use crate::command_audit;
+use crate::db;
+use crate::encryption;
+use crate::migrations;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::snapshots;
+use rusqlite::backup::Backup;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+// Pages copied per step; pausing between steps lets ticket writes through
+const PAGES_PER_STEP: std::os::raw::c_int = 256;
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+// Tables every Truckore database has
+const REQUIRED_TABLES: &[&str] = &["users", "weighments", "app_config"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableComparison {
+    pub table: String,
+    pub live_rows: i64,
+    // None when the table is missing from the backup
+    pub backup_rows: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub restored_from: String,
+    // Copy of the database as it was before the restore, and its snapshot tag
+    // for rollback_to_snapshot (snapshots.rs)
+    pub safety_backup: String,
+    pub snapshot_tag: String,
+    pub schema_version: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupVerification {
+    pub id: i64,
+    pub backup_path: String,
+    pub ok: bool,
+    pub integrity: String,
+    pub tables: Vec<TableComparison>,
+    pub error: Option<String>,
+    pub verified_at: String,
+}
+
+fn user_tables(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .map_err(|e| e.to_string())?;
+    let names = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(names)
+}
+
+fn row_count(conn: &Connection, table: &str) -> Result<i64, String> {
+    // Table names come from sqlite_master, quoting guards odd identifiers
+    let sql = format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\""));
+    conn.query_row(&sql, [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+// Integrity result plus per-table row counts of the restored copy against the live DB
+fn check_restored(
+    app: &AppHandle,
+    live: &Connection,
+    restored: &Path,
+) -> Result<(String, Vec<TableComparison>), String> {
+    let conn = encryption::open_file(app, restored, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    let backup_tables = user_tables(&conn)?;
+    let mut tables = Vec::new();
+    for table in user_tables(live)? {
+        let backup_rows = if backup_tables.contains(&table) {
+            Some(row_count(&conn, &table)?)
+        } else {
+            None
+        };
+        tables.push(TableComparison {
+            live_rows: row_count(live, &table)?,
+            table,
+            backup_rows,
+        });
+    }
+    Ok((integrity, tables))
+}
+
+// Restore a backup file into a temporary location, run integrity checks and
+// compare row counts with the live database. The result is recorded either way.
+#[tauri::command]
+pub fn verify_backup(app: AppHandle, path: String) -> Result<BackupVerification, String> {
+    let live = db::open(&app)?;
+    let restored =
+        std::env::temp_dir().join(format!("truckore-verify-{}.db", uuid::Uuid::new_v4()));
+
+    let outcome = fs::copy(&path, &restored)
+        .map_err(|e| format!("Failed to restore backup: {}", e))
+        .and_then(|_| check_restored(&app, &live, &restored));
+    let _ = fs::remove_file(&restored);
+
+    // Backups are older than the live DB, so fewer rows is expected; a missing
+    // table or a failed integrity check means the backup can't be relied on
+    let (integrity, tables, error) = match outcome {
+        Ok((integrity, tables)) => (integrity, tables, None),
+        Err(e) => (String::new(), Vec::new(), Some(e)),
+    };
+    let ok = error.is_none() && integrity == "ok" && tables.iter().all(|t| t.backup_rows.is_some());
+
+    let tables_json = serde_json::to_string(&tables).map_err(|e| e.to_string())?;
+    live.execute(
+        "INSERT INTO backup_verifications (backup_path, ok, integrity, tables, error)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![path, ok, integrity, tables_json, error],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = live.last_insert_rowid();
+
+    live.query_row(
+        "SELECT verified_at FROM backup_verifications WHERE id = ?1",
+        [id],
+        |row| row.get(0),
+    )
+    .map(|verified_at| BackupVerification {
+        id,
+        backup_path: path,
+        ok,
+        integrity,
+        tables,
+        error,
+        verified_at,
+    })
+    .map_err(|e| e.to_string())
+}
+
+// Past verification results, newest first
+#[tauri::command]
+pub fn list_backup_verifications(app: AppHandle) -> Result<Vec<BackupVerification>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, backup_path, ok, integrity, tables, error, verified_at
+             FROM backup_verifications ORDER BY id DESC LIMIT 100",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            let tables: String = row.get(4)?;
+            Ok(BackupVerification {
+                id: row.get(0)?,
+                backup_path: row.get(1)?,
+                ok: row.get(2)?,
+                integrity: row.get(3)?,
+                tables: serde_json::from_str(&tables).unwrap_or_default(),
+                error: row.get(5)?,
+                verified_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Copy `source` into the database file at `dest` with the online backup API.
+// The copy is written next to `dest` and renamed into place when complete,
+// keyed like the live database when it is encrypted.
+pub fn online_copy(app: &AppHandle, source: &Connection, dest: &Path) -> Result<(), String> {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let _ = fs::remove_file(&partial);
+    let result = (|| {
+        let mut target = encryption::open_file(app, &partial, OpenFlags::default())
+            .map_err(|e| e.to_string())?;
+        Backup::new(source, &mut target)
+            .and_then(|backup| backup.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None))
+            .map_err(|e| e.to_string())?;
+        drop(target);
+        fs::rename(&partial, dest).map_err(|e| e.to_string())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+// Check a file is an intact Truckore database this version can open
+fn validate(app: &AppHandle, path: &Path) -> Result<(), String> {
+    let conn = encryption::open_file(app, path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("Not a valid database: {}", e))?;
+    if integrity != "ok" {
+        return Err(format!("Backup failed its integrity check: {}", integrity));
+    }
+    let tables = user_tables(&conn)?;
+    if let Some(missing) = REQUIRED_TABLES
+        .iter()
+        .find(|t| !tables.iter().any(|n| n == *t))
+    {
+        return Err(format!("Not a Truckore database (no {} table)", missing));
+    }
+    if tables.iter().any(|t| t == "schema_version") {
+        let version = migrations::current(&conn)?;
+        if version > migrations::latest() {
+            return Err(format!(
+                "Backup is from a newer release (schema {}, this release supports {})",
+                version,
+                migrations::latest()
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Back up the live database to `dest_path` while the app keeps running, then
+// verify the copy (admin only)
+#[tauri::command]
+pub fn backup_database(
+    app: AppHandle,
+    dest_path: String,
+    user_id: String,
+) -> Result<BackupVerification, String> {
+    let args = serde_json::json!({ "dest_path": dest_path });
+    command_audit::audited(&app, "backup_database", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let _operation = shutdown::begin(&app, "backup", Some(&dest_path))?;
+        online_copy(&app, &conn, Path::new(&dest_path))
+            .map_err(|e| format!("Backup failed: {}", e))?;
+        drop(conn);
+        verify_backup(app.clone(), dest_path.clone())
+    })
+}
+
+// Replace the live database with the database file at `source`. The current
+// database is first snapshotted as `operation`, then the file is copied in
+// with the online backup API and migrated to this release's schema. Refused
+// while other operations are running.
+pub fn restore_from(
+    app: &AppHandle,
+    source: &Path,
+    operation: &str,
+    detail: Option<String>,
+    user_id: &str,
+) -> Result<RestoreReport, String> {
+    let busy = shutdown::in_flight(app);
+    if !busy.is_empty() {
+        return Err(format!(
+            "{} operation(s) still running; try again when they finish",
+            busy.len()
+        ));
+    }
+    let source_name = source.to_string_lossy().to_string();
+    let _operation = shutdown::begin(app, "restore", Some(&source_name))?;
+    validate(app, source)?;
+
+    let db_path = crate::get_db_path(app)?;
+    let mut live =
+        encryption::open_file(app, &db_path, OpenFlags::default()).map_err(|e| e.to_string())?;
+    // Pruned only once the restore is done, so `source` may be the oldest
+    // snapshot kept
+    let safety = snapshots::take_unpruned(app, &live, operation, detail, user_id)
+        .map_err(|e| format!("Safety backup failed: {}", e))?;
+
+    // Pooled connections would keep statements prepared against the old
+    // schema; they reconnect to the restored content
+    app.state::<db::DbPool>().close_all();
+    let restored = encryption::open_file(app, source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+    Backup::new(&restored, &mut live)
+        .and_then(|backup| backup.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None))
+        .map_err(|e| format!("Restore failed: {}", e))?;
+    drop(restored);
+
+    live.busy_timeout(Duration::from_secs(5))
+        .map_err(|e| e.to_string())?;
+    live.execute_batch(db::SCHEMA).map_err(|e| e.to_string())?;
+    migrations::run(&live)?;
+    let schema_version = migrations::current(&live)?;
+    drop(live);
+    app.state::<db::DbPool>().close_all();
+    snapshots::prune(app)?;
+
+    Ok(RestoreReport {
+        restored_from: source_name,
+        safety_backup: safety.path,
+        snapshot_tag: safety.tag,
+        schema_version,
+    })
+}
+
+// Replace the live database with a backup (admin only), see restore_from
+#[tauri::command]
+pub fn restore_database(
+    app: AppHandle,
+    src_path: String,
+    user_id: String,
+) -> Result<RestoreReport, String> {
+    let args = serde_json::json!({ "src_path": src_path });
+    command_audit::audited(&app, "restore_database", &user_id, args, || {
+        {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+        }
+        let detail = Some(format!("Restored {}", src_path));
+        restore_from(&app, Path::new(&src_path), "restore", detail, &user_id)
+    })
+}
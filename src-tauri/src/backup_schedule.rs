@@ -0,0 +1,313 @@
+// Scheduled automatic backups for Truckore Pro
+// A background thread backs the live database up once a day at the configured
+// local time, optionally on selected weekdays only, into a folder that keeps
+// the newest `keep_last` copies. A run missed while the app was closed happens
+// as soon as it starts later that same day. When cloud uploads are enabled
+// each verified copy is also sent off-site (cloud_backup.rs).
+
+use crate::backup::{self, BackupVerification};
+use crate::cloud_backup::{self, CloudUpload};
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const SCHEDULE_CONFIG_KEY: &str = "backup_schedule";
+const LAST_RUN_CONFIG_KEY: &str = "backup_last_run";
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupSchedule {
+    pub enabled: bool,
+    // Local time of day, "HH:MM"
+    pub time: String,
+    // Days to run on, 0 = Sunday .. 6 = Saturday; empty means every day
+    pub weekdays: Vec<u8>,
+    pub keep_last: u32,
+    // Defaults to backups/scheduled next to the database
+    pub directory: Option<String>,
+}
+
+impl Default for BackupSchedule {
+    fn default() -> Self {
+        BackupSchedule {
+            enabled: false,
+            time: "02:00".to_string(),
+            weekdays: Vec::new(),
+            keep_last: 7,
+            directory: None,
+        }
+    }
+}
+
+// Outcome of the latest scheduled run, also the payload of the
+// `backup-completed` and `backup-failed` events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledBackup {
+    // Local date of the run, "YYYY-MM-DD"
+    pub date: String,
+    pub path: Option<String>,
+    pub verification: Option<BackupVerification>,
+    // Older copies deleted to stay within keep_last
+    pub removed: Vec<String>,
+    pub error: Option<String>,
+    // Off-site copy, when uploads are enabled; a failed upload leaves the
+    // run successful, since the local copy is good
+    pub uploaded: Option<CloudUpload>,
+    pub upload_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupScheduleStatus {
+    pub schedule: BackupSchedule,
+    pub last_run: Option<ScheduledBackup>,
+}
+
+pub fn load_schedule(conn: &Connection) -> Result<BackupSchedule, String> {
+    match db::get_config(conn, SCHEDULE_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(BackupSchedule::default()),
+    }
+}
+
+fn last_run(conn: &Connection) -> Result<Option<ScheduledBackup>, String> {
+    db::get_config(conn, LAST_RUN_CONFIG_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+// A local time of day as "HH:MM"
+pub fn valid_time(time: &str) -> bool {
+    match time.split_once(':') {
+        Some((h, m)) if h.len() == 2 && m.len() == 2 => {
+            matches!((h.parse::<u8>(), m.parse::<u8>()), (Ok(h), Ok(m)) if h < 24 && m < 60)
+        }
+        _ => false,
+    }
+}
+
+fn validate(schedule: &BackupSchedule) -> Result<(), String> {
+    if !valid_time(&schedule.time) {
+        return Err(format!("Backup time must be HH:MM, got {}", schedule.time));
+    }
+    if let Some(day) = schedule.weekdays.iter().find(|d| **d > 6) {
+        return Err(format!(
+            "Unknown weekday {} (0 = Sunday .. 6 = Saturday)",
+            day
+        ));
+    }
+    if schedule.keep_last == 0 {
+        return Err("At least one backup must be kept".to_string());
+    }
+    Ok(())
+}
+
+// Today's local date when a daily run at `time` (HH:MM) on `weekdays` (all
+// when empty) is due now and none ran on `last_run_date`, None otherwise.
+// Shared with the report scheduler.
+pub fn due_today(
+    conn: &Connection,
+    time: &str,
+    weekdays: &[u8],
+    last_run_date: Option<&str>,
+) -> Result<Option<String>, String> {
+    let (date, now, weekday): (String, String, u8) = conn
+        .query_row(
+            "SELECT date('now', 'localtime'), strftime('%H:%M', 'now', 'localtime'),
+                    CAST(strftime('%w', 'now', 'localtime') AS INTEGER)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let today = weekdays.is_empty() || weekdays.contains(&weekday);
+    let already_ran = last_run_date == Some(date.as_str());
+    Ok((today && now.as_str() >= time && !already_ran).then_some(date))
+}
+
+// Today's local date when a backup is due now, None otherwise
+fn due(conn: &Connection, schedule: &BackupSchedule) -> Result<Option<String>, String> {
+    if !schedule.enabled {
+        return Ok(None);
+    }
+    let last = last_run(conn)?;
+    due_today(
+        conn,
+        &schedule.time,
+        &schedule.weekdays,
+        last.as_ref().map(|run| run.date.as_str()),
+    )
+}
+
+pub fn backup_dir(app: &AppHandle, schedule: &BackupSchedule) -> Result<PathBuf, String> {
+    match &schedule.directory {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => Ok(crate::get_db_path(app)?
+            .parent()
+            .ok_or("Failed to resolve the data directory")?
+            .join("backups")
+            .join("scheduled")),
+    }
+}
+
+// Delete the oldest copies of this database beyond `keep_last`
+fn rotate(dir: &PathBuf, prefix: &str, keep_last: u32) -> Result<Vec<String>, String> {
+    let mut copies: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(prefix) && n.ends_with(".db"))
+        })
+        .collect();
+    // Names carry a sortable timestamp, newest last
+    copies.sort();
+    let excess = copies.len().saturating_sub(keep_last as usize);
+    let mut removed = Vec::new();
+    for path in copies.into_iter().take(excess) {
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+        removed.push(path.to_string_lossy().to_string());
+    }
+    Ok(removed)
+}
+
+// Copy, verify and rotate; fails without rotating when the copy is bad
+pub fn run_backup(
+    app: &AppHandle,
+    schedule: &BackupSchedule,
+    date: &str,
+) -> Result<ScheduledBackup, String> {
+    let dir = backup_dir(app, schedule)?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let db_path = crate::get_db_path(app)?;
+    let prefix = format!(
+        "{}-",
+        db_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("truckore")
+    );
+
+    let conn = db::open(app)?;
+    let stamp: String = conn
+        .query_row(
+            "SELECT strftime('%Y%m%d-%H%M%S', 'now', 'localtime')",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let path = dir.join(format!("{}{}.db", prefix, stamp));
+    let path_text = path.to_string_lossy().to_string();
+    let _operation = shutdown::begin(app, "backup", Some(&path_text))?;
+    backup::online_copy(app, &conn, &path).map_err(|e| format!("Backup failed: {}", e))?;
+    drop(conn);
+
+    let verification = backup::verify_backup(app.clone(), path_text.clone())?;
+    if !verification.ok {
+        return Err(format!(
+            "Backup {} failed verification: {}",
+            path_text,
+            verification
+                .error
+                .clone()
+                .unwrap_or_else(|| verification.integrity.clone())
+        ));
+    }
+    // Only rotate once the new copy is known to be good
+    let removed = rotate(&dir, &prefix, schedule.keep_last)?;
+    let (mut uploaded, mut upload_error) = (None, None);
+    let conn = db::open(app)?;
+    let uploads = cloud_backup::load_settings(&conn)?.enabled;
+    drop(conn);
+    if uploads {
+        match cloud_backup::upload(app, &path) {
+            Ok(upload) => uploaded = Some(upload),
+            Err(error) => upload_error = Some(error),
+        }
+    }
+    Ok(ScheduledBackup {
+        date: date.to_string(),
+        path: Some(path_text),
+        verification: Some(verification),
+        removed,
+        error: None,
+        uploaded,
+        upload_error,
+    })
+}
+
+// Run the backup when due, record the outcome and tell the windows
+fn tick(app: &AppHandle) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let schedule = load_schedule(&conn)?;
+    let Some(date) = due(&conn, &schedule)? else {
+        return Ok(());
+    };
+    drop(conn);
+
+    let (event, outcome) = match run_backup(app, &schedule, &date) {
+        Ok(run) => ("backup-completed", run),
+        Err(error) => (
+            "backup-failed",
+            ScheduledBackup {
+                date,
+                path: None,
+                verification: None,
+                removed: Vec::new(),
+                error: Some(error),
+                uploaded: None,
+                upload_error: None,
+            },
+        ),
+    };
+    // Recorded on failure too, so a broken target is not retried every tick
+    let conn = db::open(app)?;
+    let json = serde_json::to_string(&outcome).map_err(|e| e.to_string())?;
+    db::set_config(&conn, LAST_RUN_CONFIG_KEY, &json)?;
+    let _ = app.emit_all(event, &outcome);
+    Ok(())
+}
+
+// Check the schedule in the background for the life of the app
+pub fn start_scheduler(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            let _ = tick(&app);
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_backup_schedule(app: AppHandle) -> Result<BackupScheduleStatus, String> {
+    let conn = db::open(&app)?;
+    Ok(BackupScheduleStatus {
+        schedule: load_schedule(&conn)?,
+        last_run: last_run(&conn)?,
+    })
+}
+
+// Supervisors only
+#[tauri::command]
+pub fn set_backup_schedule(
+    app: AppHandle,
+    schedule: BackupSchedule,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&schedule).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_backup_schedule", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        validate(&schedule)?;
+        let json = serde_json::to_string(&schedule).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SCHEDULE_CONFIG_KEY, &json)
+    })
+}
@@ -0,0 +1,219 @@
+// Bandwidth limits for background transfers in Truckore Pro
+// Cloud backups and sync share a thin DSL link with the ANPR cloud lookup.
+// Background transfers are held to a configurable rate, with a separate
+// (usually lower, or zero to pause) rate during business hours. Upload
+// chunks are metered in read_upload_chunk; the sync layer reserves its other
+// payloads with reserve_bandwidth before sending them. A changed limit
+// applies to the next transfer, not after the current booking runs out.
+
+use crate::db;
+use crate::roles::{self, Role};
+use crate::settings_events;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const SETTINGS_CONFIG_KEY: &str = "transfer_bandwidth";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthSettings {
+    // KiB per second outside business hours; None for no limit
+    pub limit_kib_per_sec: Option<u32>,
+    // KiB per second during business hours; 0 pauses transfers, None uses
+    // the off-hours limit
+    pub business_limit_kib_per_sec: Option<u32>,
+    // Local times, "HH:MM"; a window ending before it starts spans midnight
+    pub business_start: String,
+    pub business_end: String,
+    // 0 = Sunday .. 6 = Saturday; empty means every day
+    pub business_weekdays: Vec<u8>,
+}
+
+impl Default for BandwidthSettings {
+    fn default() -> Self {
+        BandwidthSettings {
+            limit_kib_per_sec: None,
+            business_limit_kib_per_sec: None,
+            business_start: "08:00".to_string(),
+            business_end: "20:00".to_string(),
+            business_weekdays: vec![1, 2, 3, 4, 5, 6],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BandwidthStatus {
+    pub business_hours: bool,
+    // Rate in force now; None for no limit
+    pub limit_kib_per_sec: Option<u32>,
+    pub paused: bool,
+    // Until the next transfer may start
+    pub wait_ms: u64,
+}
+
+// Managed state: when the link is free again after the transfers already
+// let through
+#[derive(Default)]
+pub struct TransferBudget(Mutex<Option<Instant>>);
+
+pub fn load_settings(conn: &Connection) -> Result<BandwidthSettings, String> {
+    match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(BandwidthSettings::default()),
+    }
+}
+
+pub fn valid_time(time: &str) -> bool {
+    match time.split_once(':') {
+        Some((h, m)) if h.len() == 2 && m.len() == 2 => {
+            matches!((h.parse::<u8>(), m.parse::<u8>()), (Ok(h), Ok(m)) if h < 24 && m < 60)
+        }
+        _ => false,
+    }
+}
+
+fn in_business_hours(conn: &Connection, settings: &BandwidthSettings) -> Result<bool, String> {
+    let (time, weekday): (String, u8) = conn
+        .query_row(
+            "SELECT strftime('%H:%M', 'now', 'localtime'),
+                    CAST(strftime('%w', 'now', 'localtime') AS INTEGER)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let (start, end) = (
+        settings.business_start.as_str(),
+        settings.business_end.as_str(),
+    );
+    let (open, day) = match start <= end {
+        true => (time.as_str() >= start && time.as_str() < end, weekday),
+        // After midnight the window still belongs to the day it opened on
+        false if time.as_str() >= start => (true, weekday),
+        false => (time.as_str() < end, (weekday + 6) % 7),
+    };
+    Ok(
+        open && (settings.business_weekdays.is_empty()
+            || settings.business_weekdays.contains(&day)),
+    )
+}
+
+// Rate in force now, and whether it is business hours
+fn current_limit(conn: &Connection) -> Result<(bool, Option<u32>), String> {
+    let settings = load_settings(conn)?;
+    let business = in_business_hours(conn, &settings)?;
+    let limit = match business {
+        true => settings
+            .business_limit_kib_per_sec
+            .or(settings.limit_kib_per_sec),
+        false => settings.limit_kib_per_sec,
+    };
+    Ok((business, limit))
+}
+
+// Until the link is free for the next transfer
+pub fn wait(app: &AppHandle) -> Result<Duration, String> {
+    let budget = app.state::<TransferBudget>();
+    let free_at = *budget.0.lock().map_err(|e| e.to_string())?;
+    Ok(free_at.map_or(Duration::ZERO, |at| {
+        at.saturating_duration_since(Instant::now())
+    }))
+}
+
+// Let `bytes` through if the link is free, booking it for as long as they
+// take at the current rate. Refused while paused or still busy, with the
+// time to wait in the message.
+pub fn reserve(app: &AppHandle, conn: &Connection, bytes: u64) -> Result<(), String> {
+    let (business, limit) = current_limit(conn)?;
+    let rate = match limit {
+        None => return Ok(()),
+        Some(0) if business => {
+            return Err(format!(
+                "Background transfers are paused until {} (business hours)",
+                load_settings(conn)?.business_end
+            ));
+        }
+        Some(0) => return Err("Background transfers are switched off".to_string()),
+        Some(kib) => kib as f64 * 1024.0,
+    };
+    let budget = app.state::<TransferBudget>();
+    let mut free_at = budget.0.lock().map_err(|e| e.to_string())?;
+    let now = Instant::now();
+    if let Some(at) = *free_at {
+        if at > now {
+            return Err(format!(
+                "Bandwidth limit reached; retry in {} ms",
+                (at - now).as_millis()
+            ));
+        }
+    }
+    *free_at = Some(now + Duration::from_secs_f64(bytes as f64 / rate));
+    Ok(())
+}
+
+// Drop the booking made at the old rate when the limits change
+pub fn watch_settings(app: AppHandle) {
+    let handle = app.clone();
+    app.listen_global(settings_events::EVENT, move |event| {
+        if settings_events::parse(&event).is_some_and(|c| c.contains(SETTINGS_CONFIG_KEY)) {
+            if let Ok(mut free_at) = handle.state::<TransferBudget>().0.lock() {
+                *free_at = None;
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_bandwidth_settings(app: AppHandle) -> Result<BandwidthSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+#[tauri::command]
+pub fn set_bandwidth_settings(
+    app: AppHandle,
+    settings: BandwidthSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    for time in [&settings.business_start, &settings.business_end] {
+        if !valid_time(time) {
+            return Err(format!("Business hours must be HH:MM, got {}", time));
+        }
+    }
+    if let Some(day) = settings.business_weekdays.iter().find(|d| **d > 6) {
+        return Err(format!(
+            "Unknown weekday {} (0 = Sunday .. 6 = Saturday)",
+            day
+        ));
+    }
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)
+}
+
+// Called by the sync layer to decide when to send next
+#[tauri::command]
+pub fn get_bandwidth_status(app: AppHandle) -> Result<BandwidthStatus, String> {
+    let conn = db::open(&app)?;
+    let (business_hours, limit) = current_limit(&conn)?;
+    let paused = limit == Some(0);
+    Ok(BandwidthStatus {
+        business_hours,
+        limit_kib_per_sec: limit,
+        paused,
+        wait_ms: match limit {
+            None => 0,
+            _ => wait(&app)?.as_millis() as u64,
+        },
+    })
+}
+
+// Book a sync payload of `bytes` before sending it
+#[tauri::command]
+pub fn reserve_bandwidth(app: AppHandle, bytes: u64) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    reserve(&app, &conn, bytes)
+}
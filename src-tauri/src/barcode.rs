@@ -0,0 +1,35 @@
+// Barcode decoding for Truckore Pro
+// Reads Code128 / QR codes from camera snapshots or scanned delivery notes
+
+use rxing::{BarcodeFormat, DecodeHints, Exceptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecodedBarcode {
+    pub format: String,
+    pub value: String,
+}
+
+// Decode the first Code128 or QR code found in an image file.
+// Returns None when the image contains no readable barcode.
+#[tauri::command]
+pub fn decode_barcode(image_path: String) -> Result<Option<DecodedBarcode>, String> {
+    let mut hints = DecodeHints {
+        PossibleFormats: Some(HashSet::from([
+            BarcodeFormat::CODE_128,
+            BarcodeFormat::QR_CODE,
+        ])),
+        TryHarder: Some(true),
+        ..Default::default()
+    };
+
+    match rxing::helpers::detect_in_file_with_hints(&image_path, None, &mut hints) {
+        Ok(result) => Ok(Some(DecodedBarcode {
+            format: result.getBarcodeFormat().to_string(),
+            value: result.getText().to_string(),
+        })),
+        Err(Exceptions::NotFoundException(_)) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,377 @@
+// Bulk ticket operations for Truckore Pro
+// Voids, party reassignment and re-rating over many tickets run on a
+// background thread. Each ticket is handled in its own transaction and its
+// outcome recorded; progress is pushed to windows as `bulk_job_progress`
+// events and a running job can be cancelled between tickets.
+
+use crate::command_audit;
+use crate::currency;
+use crate::db;
+use crate::deductions;
+use crate::money;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::signatures;
+use crate::voids;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, State};
+
+// Managed state: cancel flags of running jobs
+#[derive(Default)]
+pub struct BulkJobs(Mutex<HashMap<i64, Arc<AtomicBool>>>);
+
+impl BulkJobs {
+    // Ask every running job to stop after its current ticket
+    pub fn cancel_all(&self) {
+        if let Ok(running) = self.0.lock() {
+            for cancel in running.values() {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BulkOperation {
+    Void { reason: String },
+    ReassignParty { party_name: String, reason: String },
+    // Refresh freight rates from the transporters' current tariffs and re-bill
+    Rerate,
+}
+
+impl BulkOperation {
+    fn kind(&self) -> &'static str {
+        match self {
+            BulkOperation::Void { .. } => "void",
+            BulkOperation::ReassignParty { .. } => "reassign_party",
+            BulkOperation::Rerate => "rerate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkJob {
+    pub id: i64,
+    pub kind: String,
+    // RUNNING, COMPLETED, CANCELLED or FAILED
+    pub status: String,
+    pub total: i64,
+    pub processed: i64,
+    pub succeeded: i64,
+    pub skipped: i64,
+    pub failed: i64,
+    pub requested_by: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkJobItem {
+    pub weighment_id: String,
+    // OK, SKIPPED or FAILED
+    pub outcome: String,
+    pub message: String,
+}
+
+fn load_job(conn: &Connection, job_id: i64) -> Result<BulkJob, String> {
+    conn.query_row(
+        "SELECT id, kind, status, total, processed, succeeded, skipped, failed, requested_by,
+                started_at, finished_at, summary
+         FROM bulk_jobs WHERE id = ?1",
+        [job_id],
+        |row| {
+            Ok(BulkJob {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                status: row.get(2)?,
+                total: row.get(3)?,
+                processed: row.get(4)?,
+                succeeded: row.get(5)?,
+                skipped: row.get(6)?,
+                failed: row.get(7)?,
+                requested_by: row.get(8)?,
+                started_at: row.get(9)?,
+                finished_at: row.get(10)?,
+                summary: row.get(11)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Bulk job {} not found", job_id))
+}
+
+// Apply the operation to one ticket, returning its outcome and a message
+fn apply(
+    conn: &Connection,
+    operation: &BulkOperation,
+    weighment_id: &str,
+    user_id: &str,
+) -> Result<(&'static str, String), String> {
+    let (party_name, status): (String, String) = conn
+        .query_row(
+            "SELECT party_name, status FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Ticket not found".to_string())?;
+    if voids::is_voided(conn, weighment_id)? {
+        return Ok(("SKIPPED", "Ticket is voided".to_string()));
+    }
+
+    match operation {
+        BulkOperation::Void { reason } => {
+            voids::record_void(conn, weighment_id, reason, user_id, None)?;
+            Ok(("OK", "Voided".to_string()))
+        }
+        BulkOperation::ReassignParty {
+            party_name: new_party,
+            ..
+        } => {
+            if &party_name == new_party {
+                return Ok(("SKIPPED", format!("Already billed to {}", new_party)));
+            }
+            conn.execute(
+                "UPDATE weighments SET party_name = ?2, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?1",
+                params![weighment_id, new_party],
+            )
+            .map_err(|e| e.to_string())?;
+            signatures::sign(conn, weighment_id)?;
+            deductions::reapply(conn, weighment_id)?;
+            if status != "OPEN" {
+                currency::bill_weighment(conn, weighment_id)?;
+            }
+            Ok(("OK", format!("{} -> {}", party_name, new_party)))
+        }
+        BulkOperation::Rerate => {
+            if status == "OPEN" {
+                return Ok(("SKIPPED", "Ticket is still open".to_string()));
+            }
+            let refreshed = conn
+                .execute(
+                    "UPDATE weighment_transport
+                     SET rate_type = (SELECT rate_type FROM transporters t
+                                      WHERE t.id = weighment_transport.transporter_id),
+                         rate_minor = (SELECT rate_minor FROM transporters t
+                                       WHERE t.id = weighment_transport.transporter_id)
+                     WHERE weighment_id = ?1",
+                    [weighment_id],
+                )
+                .map_err(|e| e.to_string())?;
+            let billing = currency::bill_weighment(conn, weighment_id)?;
+            Ok((
+                "OK",
+                format!(
+                    "Billed {} {}{}",
+                    billing.currency,
+                    money::format_minor(billing.billed_amount_minor),
+                    if refreshed > 0 {
+                        ", freight re-rated"
+                    } else {
+                        ""
+                    }
+                ),
+            ))
+        }
+    }
+}
+
+fn run(
+    app: &AppHandle,
+    job_id: i64,
+    operation: &BulkOperation,
+    weighment_ids: &[String],
+    user_id: &str,
+    cancel: &AtomicBool,
+) -> Result<(), String> {
+    let mut conn = db::open(app)?;
+    let mut cancelled = false;
+    for id in weighment_ids {
+        if cancel.load(Ordering::Relaxed) {
+            cancelled = true;
+            break;
+        }
+        let (outcome, message) = {
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            match apply(&tx, operation, id, user_id) {
+                Ok(result) => {
+                    tx.commit().map_err(|e| e.to_string())?;
+                    result
+                }
+                // Dropping the transaction rolls this ticket back
+                Err(e) => ("FAILED", e),
+            }
+        };
+        conn.execute(
+            "INSERT INTO bulk_job_items (job_id, weighment_id, outcome, message)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![job_id, id, outcome, message],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE bulk_jobs SET processed = processed + 1,
+                    succeeded = succeeded + (?2 = 'OK'),
+                    skipped = skipped + (?2 = 'SKIPPED'),
+                    failed = failed + (?2 = 'FAILED')
+             WHERE id = ?1",
+            params![job_id, outcome],
+        )
+        .map_err(|e| e.to_string())?;
+        let _ = app.emit_all("bulk_job_progress", load_job(&conn, job_id)?);
+    }
+
+    let job = load_job(&conn, job_id)?;
+    let summary = serde_json::json!({
+        "kind": operation.kind(),
+        "operation": operation,
+        "requested": weighment_ids.len(),
+        "processed": job.processed,
+        "succeeded": job.succeeded,
+        "skipped": job.skipped,
+        "failed": job.failed,
+        "cancelled": cancelled,
+    });
+    conn.execute(
+        "UPDATE bulk_jobs SET status = ?2, summary = ?3, finished_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![
+            job_id,
+            if cancelled { "CANCELLED" } else { "COMPLETED" },
+            summary.to_string()
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let _ = app.emit_all("bulk_job_progress", load_job(&conn, job_id)?);
+    Ok(())
+}
+
+// Start a bulk operation over the given tickets (supervisors only). Returns
+// the job id at once; follow progress via events or get_bulk_job.
+#[tauri::command]
+pub fn start_bulk_job(
+    app: AppHandle,
+    jobs: State<'_, BulkJobs>,
+    operation: BulkOperation,
+    weighment_ids: Vec<String>,
+    user_id: String,
+) -> Result<i64, String> {
+    let args = serde_json::json!({ "operation": operation, "count": weighment_ids.len() });
+    command_audit::audited(&app, "start_bulk_job", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if weighment_ids.is_empty() {
+            return Err("No tickets selected".to_string());
+        }
+        let reason = match &operation {
+            BulkOperation::Void { reason } | BulkOperation::ReassignParty { reason, .. } => {
+                Some(reason.trim())
+            }
+            BulkOperation::Rerate => None,
+        };
+        if reason == Some("") {
+            return Err("A reason is required".to_string());
+        }
+
+        conn.execute(
+            "INSERT INTO bulk_jobs (kind, total, requested_by, reason) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                operation.kind(),
+                weighment_ids.len() as i64,
+                user_id,
+                reason
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        let job_id = conn.last_insert_rowid();
+        let operation_guard = shutdown::begin(&app, "bulk_job", Some(&job_id.to_string()))?;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        jobs.0
+            .lock()
+            .map_err(|e| e.to_string())?
+            .insert(job_id, cancel.clone());
+
+        let app = app.clone();
+        let operation = operation.clone();
+        let weighment_ids = weighment_ids.clone();
+        let user_id = user_id.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = run(&app, job_id, &operation, &weighment_ids, &user_id, &cancel) {
+                if let Ok(conn) = db::open(&app) {
+                    let _ = conn.execute(
+                        "UPDATE bulk_jobs SET status = 'FAILED', summary = ?2,
+                                finished_at = CURRENT_TIMESTAMP
+                         WHERE id = ?1",
+                        params![job_id, e],
+                    );
+                    if let Ok(job) = load_job(&conn, job_id) {
+                        let _ = app.emit_all("bulk_job_progress", job);
+                    }
+                }
+            }
+            if let Ok(mut running) = app.state::<BulkJobs>().0.lock() {
+                running.remove(&job_id);
+            }
+            drop(operation_guard);
+        });
+        Ok(job_id)
+    })
+}
+
+// Ask a running job to stop after the ticket in progress
+#[tauri::command]
+pub fn cancel_bulk_job(
+    app: AppHandle,
+    jobs: State<'_, BulkJobs>,
+    job_id: i64,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    match jobs.0.lock().map_err(|e| e.to_string())?.get(&job_id) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        None => Err(format!("Bulk job {} is not running", job_id)),
+    }
+}
+
+#[tauri::command]
+pub fn get_bulk_job(app: AppHandle, job_id: i64) -> Result<BulkJob, String> {
+    let conn = db::open(&app)?;
+    load_job(&conn, job_id)
+}
+
+// Per-ticket outcomes of a job, in processing order
+#[tauri::command]
+pub fn list_bulk_job_items(app: AppHandle, job_id: i64) -> Result<Vec<BulkJobItem>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT weighment_id, outcome, message FROM bulk_job_items
+             WHERE job_id = ?1 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([job_id], |row| {
+            Ok(BulkJobItem {
+                weighment_id: row.get(0)?,
+                outcome: row.get(1)?,
+                message: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
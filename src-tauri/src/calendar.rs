@@ -0,0 +1,367 @@
+// Working calendar for Truckore Pro
+// Sites weigh within set hours, and a ticket taken on a Sunday, a holiday or
+// at 2 a.m. is worth a second look. The calendar holds the working hours,
+// the weekly offs and a list of holidays. Enforcement is OFF, WARN (the
+// weighment goes ahead and is recorded as out-of-hours activity) or BLOCK
+// (it needs a supervisor override, overrides.rs). Both weighings of a
+// ticket are checked, and the summary report lists what was recorded.
+
+use crate::bandwidth;
+use crate::db::{self, DateRange};
+use crate::overrides;
+use crate::roles::{self, Role};
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const SETTINGS_CONFIG_KEY: &str = "working_calendar";
+const ENFORCEMENTS: &[&str] = &["OFF", "WARN", "BLOCK"];
+
+// The weighings checked
+pub const FIRST_WEIGHT: &str = "FIRST_WEIGHT";
+pub const SECOND_WEIGHT: &str = "SECOND_WEIGHT";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalendarSettings {
+    // OFF, WARN or BLOCK
+    pub enforcement: String,
+    // Local times, "HH:MM"; hours ending before they start span midnight
+    pub start: String,
+    pub end: String,
+    // 0 = Sunday .. 6 = Saturday
+    pub weekly_offs: Vec<u8>,
+}
+
+impl Default for CalendarSettings {
+    fn default() -> Self {
+        CalendarSettings {
+            enforcement: "OFF".to_string(),
+            start: "08:00".to_string(),
+            end: "20:00".to_string(),
+            weekly_offs: vec![0],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Holiday {
+    // YYYY-MM-DD
+    pub date: String,
+    pub name: String,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+// Why now is outside the working calendar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Closure {
+    // HOLIDAY, WEEKLY_OFF or OUTSIDE_HOURS
+    pub reason: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CalendarStatus {
+    pub enforcement: String,
+    // None while the site is working
+    pub closure: Option<Closure>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutOfHoursActivity {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    // FIRST_WEIGHT or SECOND_WEIGHT
+    pub event: String,
+    pub reason: String,
+    pub detail: String,
+    // WARNED or OVERRIDDEN
+    pub outcome: String,
+    pub override_id: Option<i64>,
+    // Local time, as the calendar is kept
+    pub occurred_at: String,
+}
+
+pub fn load_settings(conn: &Connection) -> Result<CalendarSettings, String> {
+    match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(CalendarSettings::default()),
+    }
+}
+
+// Now against the calendar; the hours after midnight of a window that spans
+// it belong to the day it opened on
+fn closure(conn: &Connection, settings: &CalendarSettings) -> Result<Option<Closure>, String> {
+    let (time, today, yesterday, weekday): (String, String, String, u8) = conn
+        .query_row(
+            "SELECT strftime('%H:%M', 'now', 'localtime'), date('now', 'localtime'),
+                    date('now', 'localtime', '-1 day'),
+                    CAST(strftime('%w', 'now', 'localtime') AS INTEGER)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let (start, end) = (settings.start.as_str(), settings.end.as_str());
+    let (open, date, day) = match start <= end {
+        true => (
+            time.as_str() >= start && time.as_str() < end,
+            today,
+            weekday,
+        ),
+        false if time.as_str() >= start => (true, today, weekday),
+        false if time.as_str() < end => (true, yesterday, (weekday + 6) % 7),
+        false => (false, today, weekday),
+    };
+
+    let holiday: Option<String> = conn
+        .query_row(
+            "SELECT name FROM holidays WHERE date = ?1",
+            [&date],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let closure = if let Some(name) = holiday {
+        Some(("HOLIDAY", format!("on {} ({})", name, date)))
+    } else if settings.weekly_offs.contains(&day) {
+        Some(("WEEKLY_OFF", format!("on the weekly off ({})", date)))
+    } else if !open {
+        Some((
+            "OUTSIDE_HOURS",
+            format!("outside working hours ({} to {})", start, end),
+        ))
+    } else {
+        None
+    };
+    Ok(closure.map(|(reason, detail)| Closure {
+        reason: reason.to_string(),
+        detail,
+    }))
+}
+
+// True when a weighment now needs an out-of-hours override
+pub fn blocks(conn: &Connection) -> Result<bool, String> {
+    let settings = load_settings(conn)?;
+    Ok(settings.enforcement == "BLOCK" && closure(conn, &settings)?.is_some())
+}
+
+// Check a weighing of a ticket against the calendar. Outside it, records the
+// activity and returns it; BLOCK refuses unless `override_id` carries a
+// granted override. Practice tickets are left alone.
+pub fn check(
+    conn: &Connection,
+    weighment_id: &str,
+    event: &str,
+    override_id: Option<i64>,
+) -> Result<Option<OutOfHoursActivity>, String> {
+    let settings = load_settings(conn)?;
+    if settings.enforcement == "OFF" || training::is_practice(conn, weighment_id)? {
+        return Ok(None);
+    }
+    let Some(closure) = closure(conn, &settings)? else {
+        return Ok(None);
+    };
+
+    let outcome = match (settings.enforcement.as_str(), override_id) {
+        ("BLOCK", None) => {
+            return Err(format!(
+                "Weighments are not permitted {}. A supervisor override is required.",
+                closure.detail
+            ))
+        }
+        ("BLOCK", Some(_)) => "OVERRIDDEN",
+        _ => "WARNED",
+    };
+    let override_id = override_id.filter(|_| outcome == "OVERRIDDEN");
+    conn.execute(
+        "INSERT OR REPLACE INTO out_of_hours_activity
+             (weighment_id, event, reason, detail, outcome, override_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            weighment_id,
+            event,
+            closure.reason,
+            closure.detail,
+            outcome,
+            override_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    if let Some(id) = override_id {
+        overrides::mark_used(conn, id)?;
+    }
+
+    conn.query_row(
+        &format!(
+            "{} WHERE a.weighment_id = ?1 AND a.event = ?2",
+            ACTIVITY_SELECT
+        ),
+        params![weighment_id, event],
+        row_to_activity,
+    )
+    .map(Some)
+    .map_err(|e| e.to_string())
+}
+
+const ACTIVITY_SELECT: &str = "SELECT a.weighment_id, w.ticket_no, w.vehicle_no, a.event,
+            a.reason, a.detail, a.outcome, a.override_id, datetime(a.occurred_at, 'localtime')
+     FROM out_of_hours_activity a JOIN weighments w ON w.id = a.weighment_id";
+
+fn row_to_activity(row: &rusqlite::Row) -> rusqlite::Result<OutOfHoursActivity> {
+    Ok(OutOfHoursActivity {
+        weighment_id: row.get(0)?,
+        ticket_no: row.get(1)?,
+        vehicle_no: row.get(2)?,
+        event: row.get(3)?,
+        reason: row.get(4)?,
+        detail: row.get(5)?,
+        outcome: row.get(6)?,
+        override_id: row.get(7)?,
+        occurred_at: row.get(8)?,
+    })
+}
+
+// Out-of-hours weighings in the range, oldest first; voided tickets included
+pub fn activity(conn: &Connection, range: &DateRange) -> Result<Vec<OutOfHoursActivity>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} WHERE {} BETWEEN ?1 AND ?2 ORDER BY a.occurred_at, a.id",
+            ACTIVITY_SELECT,
+            db::local_date("a.occurred_at")
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], row_to_activity)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_working_calendar(app: AppHandle) -> Result<CalendarSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+#[tauri::command]
+pub fn set_working_calendar(
+    app: AppHandle,
+    settings: CalendarSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let mut settings = settings;
+    settings.enforcement = settings.enforcement.trim().to_ascii_uppercase();
+    if !ENFORCEMENTS.contains(&settings.enforcement.as_str()) {
+        return Err(format!(
+            "Unknown enforcement {} (OFF, WARN or BLOCK)",
+            settings.enforcement
+        ));
+    }
+    for time in [&settings.start, &settings.end] {
+        if !bandwidth::valid_time(time) {
+            return Err(format!("Working hours must be HH:MM, got {}", time));
+        }
+    }
+    if settings.start == settings.end {
+        return Err("Working hours must not start and end at the same time".to_string());
+    }
+    if let Some(day) = settings.weekly_offs.iter().find(|d| **d > 6) {
+        return Err(format!(
+            "Unknown weekday {} (0 = Sunday .. 6 = Saturday)",
+            day
+        ));
+    }
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)
+}
+
+// Whether weighments are permitted right now
+#[tauri::command]
+pub fn working_calendar_status(app: AppHandle) -> Result<CalendarStatus, String> {
+    let conn = db::open(&app)?;
+    let settings = load_settings(&conn)?;
+    Ok(CalendarStatus {
+        closure: closure(&conn, &settings)?,
+        enforcement: settings.enforcement,
+    })
+}
+
+#[tauri::command]
+pub fn list_holidays(app: AppHandle, range: Option<DateRange>) -> Result<Vec<Holiday>, String> {
+    let conn = db::open(&app)?;
+    let (from, to) = range.map(|r| (r.from, r.to)).unzip();
+    let mut stmt = conn
+        .prepare(
+            "SELECT date, name, created_by, created_at FROM holidays
+             WHERE (?1 IS NULL OR date >= ?1) AND (?2 IS NULL OR date <= ?2)
+             ORDER BY date",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![from, to], |row| {
+            Ok(Holiday {
+                date: row.get(0)?,
+                name: row.get(1)?,
+                created_by: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Add a holiday, or rename the one on that date
+#[tauri::command]
+pub fn set_holiday(
+    app: AppHandle,
+    date: String,
+    name: String,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let valid: bool = conn
+        .query_row("SELECT date(?1) IS ?1", [&date], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if !valid {
+        return Err(format!("Holiday dates must be YYYY-MM-DD, got {}", date));
+    }
+    if name.trim().is_empty() {
+        return Err("A holiday name is required".to_string());
+    }
+    conn.execute(
+        "INSERT INTO holidays (date, name, created_by) VALUES (?1, ?2, ?3)
+         ON CONFLICT(date) DO UPDATE SET name = excluded.name",
+        params![date, name.trim(), user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_holiday(app: AppHandle, date: String, user_id: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    match conn
+        .execute("DELETE FROM holidays WHERE date = ?1", [&date])
+        .map_err(|e| e.to_string())?
+    {
+        0 => Err(format!("No holiday on {}", date)),
+        _ => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub fn list_out_of_hours_activity(
+    app: AppHandle,
+    range: DateRange,
+) -> Result<Vec<OutOfHoursActivity>, String> {
+    let conn = db::open(&app)?;
+    activity(&conn, &range)
+}
@@ -0,0 +1,588 @@
+// Lane cameras for Truckore Pro
+// A lane can have a front plate, a rear plate and a load top-view camera.
+// When the lane captures a weight, every camera is asked for a snapshot at
+// the same time; the images go to the attachment store and are linked to
+// the ticket, with their camera role, when it is saved. A ticket saved
+// without them is snapshotted afterwards, and capture_snapshots takes them
+// again on demand; the front and rear images also fill the ticket's own
+// image columns.
+// Cameras are reached over plain HTTP snapshot URLs with Basic auth, or over
+// RTSP through ffmpeg; PTZ cameras are first moved to their ONVIF preset.
+
+use crate::attachments;
+use crate::command_audit;
+use crate::db;
+use crate::onvif;
+use crate::roles::{self, Role};
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+const ROLES: &[&str] = &["front_plate", "rear_plate", "load_top"];
+// Time for a PTZ camera to reach its preset before the snapshot
+const PTZ_SETTLE: Duration = Duration::from_secs(2);
+// RTSP streams are read by ffmpeg, found on the PATH
+const FFMPEG: &str = "ffmpeg";
+// Ticket image columns filled from the plate cameras
+const IMAGE_COLUMNS: &[(&str, &str)] = &[
+    ("front_plate", "front_camera_image"),
+    ("rear_plate", "back_camera_image"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaneCamera {
+    #[serde(default)]
+    pub id: i64,
+    pub lane_id: String,
+    // "front_plate", "rear_plate" or "load_top"
+    pub role: String,
+    pub snapshot_url: String,
+    pub username: Option<String>,
+    // Never sent back to the UI; None keeps the stored password
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+    // ONVIF PTZ service, media profile and preset recalled before each snapshot
+    #[serde(default)]
+    pub ptz_url: Option<String>,
+    #[serde(default)]
+    pub ptz_profile: Option<String>,
+    #[serde(default)]
+    pub ptz_preset: Option<String>,
+}
+
+// Result of one camera at capture time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraSnapshot {
+    pub role: String,
+    // Attachment reference, None when the camera failed
+    pub image: Option<String>,
+    pub error: Option<String>,
+    pub captured_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketSnapshot {
+    pub id: i64,
+    pub camera_role: String,
+    // Data URL
+    pub image: String,
+    pub captured_at: String,
+}
+
+// Minimal HTTP/1.0 request; the connection closes after the response
+pub(crate) fn http_request(
+    method: &str,
+    url: &str,
+    auth: Option<(&str, &str)>,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+) -> Result<(u16, String, Vec<u8>), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or("Only http:// camera URLs are supported")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let socket = address
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Cannot resolve {}", authority))?;
+    let mut stream = TcpStream::connect_timeout(&socket, timeout).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+    stream
+        .set_write_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+
+    let mut request = format!(
+        "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        authority,
+        body.len()
+    );
+    if let Some((user, password)) = auth {
+        let token = general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        request.push_str(&format!("Authorization: Basic {}\r\n", token));
+    }
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream
+        .write_all(request.as_bytes())
+        .and_then(|_| stream.write_all(body))
+        .map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .map_err(|e| e.to_string())?;
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("Malformed HTTP response")?;
+    let head = String::from_utf8_lossy(&response[..split]).to_string();
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or("Malformed HTTP status line")?;
+    Ok((status, head, response[split + 4..].to_vec()))
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+// RTSP URL with the camera's credentials, unless it carries its own
+fn rtsp_url(camera: &LaneCamera) -> String {
+    let rest = &camera.snapshot_url["rtsp://".len()..];
+    let authority = rest.split('/').next().unwrap_or(rest);
+    match camera.username.as_deref() {
+        Some(user) if !authority.contains('@') => {
+            let escape = |s: &str| {
+                s.chars()
+                    .map(|c| match c {
+                        c if c.is_ascii_alphanumeric() || "-._~".contains(c) => c.to_string(),
+                        c => c
+                            .to_string()
+                            .bytes()
+                            .map(|b| format!("%{:02X}", b))
+                            .collect(),
+                    })
+                    .collect::<String>()
+            };
+            let password = camera.password.as_deref().unwrap_or("");
+            format!("rtsp://{}:{}@{}", escape(user), escape(password), rest)
+        }
+        _ => camera.snapshot_url.clone(),
+    }
+}
+
+// One JPEG frame from an RTSP stream
+fn rtsp_frame(camera: &LaneCamera) -> Result<(String, Vec<u8>), String> {
+    let mut child = Command::new(FFMPEG)
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-rtsp_transport",
+            "tcp",
+            "-i",
+        ])
+        .arg(rtsp_url(camera))
+        .args(["-frames:v", "1", "-f", "image2", "-c:v", "mjpeg", "pipe:1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("RTSP cameras need ffmpeg installed: {}", e))?;
+    let mut stdout = child.stdout.take().ok_or("ffmpeg output unavailable")?;
+    let reader = std::thread::spawn(move || {
+        let mut frame = Vec::new();
+        stdout.read_to_end(&mut frame).map(|_| frame)
+    });
+    let deadline = Instant::now() + SNAPSHOT_TIMEOUT;
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(50)),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "Camera stream sent no frame within {} s",
+                    SNAPSHOT_TIMEOUT.as_secs()
+                ));
+            }
+        }
+    };
+    let frame = reader
+        .join()
+        .map_err(|_| "ffmpeg reader panicked".to_string())?
+        .map_err(|e| e.to_string())?;
+    if !status.success() || frame.is_empty() {
+        let mut detail = String::new();
+        if let Some(mut stderr) = child.stderr.take() {
+            let _ = stderr.read_to_string(&mut detail);
+        }
+        return Err(format!("Camera stream unreadable: {}", detail.trim()));
+    }
+    Ok(("image/jpeg".to_string(), frame))
+}
+
+// Fetch one snapshot as (mime, bytes)
+pub(crate) fn fetch(camera: &LaneCamera) -> Result<(String, Vec<u8>), String> {
+    if camera.snapshot_url.starts_with("rtsp://") {
+        return rtsp_frame(camera);
+    }
+    let auth = camera
+        .username
+        .as_deref()
+        .map(|u| (u, camera.password.as_deref().unwrap_or("")));
+    let (status, head, body) = http_request(
+        "GET",
+        &camera.snapshot_url,
+        auth,
+        &[],
+        &[],
+        SNAPSHOT_TIMEOUT,
+    )?;
+    if status == 401 && header(&head, "WWW-Authenticate").is_some_and(|v| v.starts_with("Digest")) {
+        return Err(
+            "Camera requires digest authentication; enable basic auth on the camera".to_string(),
+        );
+    }
+    if status != 200 {
+        return Err(format!("Camera answered HTTP {}", status));
+    }
+    let mime = header(&head, "Content-Type")
+        .unwrap_or("image/jpeg")
+        .split(';')
+        .next()
+        .unwrap_or("image/jpeg")
+        .trim()
+        .to_string();
+    if !mime.starts_with("image/") {
+        return Err(format!("Camera returned {} instead of an image", mime));
+    }
+    if body.is_empty() {
+        return Err("Camera returned an empty snapshot".to_string());
+    }
+    Ok((mime, body))
+}
+
+// Recall the camera's PTZ preset, if any, then fetch the snapshot
+fn snapshot(camera: &LaneCamera) -> Result<(String, Vec<u8>), String> {
+    if onvif::goto_preset(camera).map_err(|e| format!("PTZ preset failed: {}", e))? {
+        std::thread::sleep(PTZ_SETTLE);
+    }
+    fetch(camera)
+}
+
+pub fn load_cameras(conn: &Connection, lane_id: &str) -> Result<Vec<LaneCamera>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, lane_id, role, snapshot_url, username, password,
+                    ptz_url, ptz_profile, ptz_preset
+             FROM lane_cameras WHERE lane_id = ?1 ORDER BY role",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([lane_id], |row| {
+            Ok(LaneCamera {
+                id: row.get(0)?,
+                lane_id: row.get(1)?,
+                role: row.get(2)?,
+                snapshot_url: row.get(3)?,
+                username: row.get(4)?,
+                password: row.get(5)?,
+                ptz_url: row.get(6)?,
+                ptz_profile: row.get(7)?,
+                ptz_preset: row.get(8)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Snapshot every camera of a lane concurrently. A failing camera is reported
+// in its entry and does not hold up the others.
+pub fn capture_all(app: &AppHandle, lane_id: &str) -> Result<Vec<CameraSnapshot>, String> {
+    let conn = db::open(app)?;
+    let cameras = load_cameras(&conn, lane_id)?;
+    let captured_at: String = conn
+        .query_row("SELECT strftime('%Y-%m-%dT%H:%M:%fZ', 'now')", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    let results: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = cameras
+            .iter()
+            .map(|camera| scope.spawn(move || snapshot(camera)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| {
+                h.join()
+                    .unwrap_or_else(|_| Err("Snapshot thread panicked".to_string()))
+            })
+            .collect()
+    });
+
+    let mut snapshots = Vec::with_capacity(cameras.len());
+    for (camera, result) in cameras.iter().zip(results) {
+        let stored =
+            result.and_then(|(mime, bytes)| attachments::store_bytes(app, &conn, &mime, &bytes));
+        let (image, error) = match stored {
+            Ok(reference) => (Some(reference), None),
+            Err(e) => (None, Some(e)),
+        };
+        snapshots.push(CameraSnapshot {
+            role: camera.role.clone(),
+            image,
+            error,
+            captured_at: captured_at.clone(),
+        });
+    }
+    Ok(snapshots)
+}
+
+// Link capture-time snapshots to a saved ticket. Only stored attachment
+// references are accepted.
+pub fn link(
+    conn: &Connection,
+    weighment_id: &str,
+    snapshots: &[CameraSnapshot],
+) -> Result<(), String> {
+    for snapshot in snapshots {
+        let Some(image) = snapshot.image.as_deref() else {
+            continue;
+        };
+        if !image.starts_with(attachments::REF_PREFIX) {
+            return Err("Snapshot image must be an attachment reference".to_string());
+        }
+        conn.execute(
+            "INSERT INTO ticket_snapshots (weighment_id, camera_role, image, captured_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![weighment_id, snapshot.role, image, snapshot.captured_at],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Snapshot a saved ticket now: link the images and fill its empty image
+// columns
+fn capture_for(
+    app: &AppHandle,
+    weighment_id: &str,
+    lane_id: &str,
+) -> Result<Vec<CameraSnapshot>, String> {
+    let snapshots = capture_all(app, lane_id)?;
+    let conn = db::open(app)?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    link(&tx, weighment_id, &snapshots)?;
+    for (role, column) in IMAGE_COLUMNS {
+        let image = snapshots
+            .iter()
+            .find(|s| s.role == *role)
+            .and_then(|s| s.image.as_deref());
+        if let Some(image) = image {
+            tx.execute(
+                &format!(
+                    "UPDATE weighments SET {c} = ?2 WHERE id = ?1 AND {c} IS NULL",
+                    c = column
+                ),
+                params![weighment_id, image],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(snapshots)
+}
+
+// Lanes with at least one camera
+pub fn camera_lanes(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT lane_id FROM lane_cameras ORDER BY lane_id")
+        .map_err(|e| e.to_string())?;
+    let lanes = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(lanes)
+}
+
+// Snapshot a ticket that was saved without capture-time images, in the
+// background. Only when a single lane has cameras, as the ticket does not
+// say which lane weighed it.
+pub fn capture_after_save(app: &AppHandle, weighment_id: &str) {
+    let app = app.clone();
+    let weighment_id = weighment_id.to_string();
+    std::thread::spawn(move || {
+        let lanes = db::open(&app).and_then(|conn| camera_lanes(&conn));
+        if let Ok([lane]) = lanes.as_deref() {
+            let _ = capture_for(&app, &weighment_id, lane);
+        }
+    });
+}
+
+// Take a saved ticket's snapshots again, from `lane_id` or the only lane
+// with cameras
+#[tauri::command]
+pub async fn capture_snapshots(
+    app: AppHandle,
+    weighment_id: String,
+    lane_id: Option<String>,
+) -> Result<Vec<CameraSnapshot>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&app)?;
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM weighments WHERE id = ?1)",
+                [&weighment_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            return Err(format!("Unknown weighment {}", weighment_id));
+        }
+        let lane = match lane_id {
+            Some(lane) => lane,
+            None => match camera_lanes(&conn)?.as_slice() {
+                [lane] => lane.clone(),
+                [] => return Err("No lane has cameras".to_string()),
+                _ => return Err("Several lanes have cameras; choose one".to_string()),
+            },
+        };
+        drop(conn);
+        capture_for(&app, &weighment_id, &lane)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// One stored snapshot, for the slip
+#[tauri::command]
+pub fn get_snapshot(app: AppHandle, id: i64) -> Result<TicketSnapshot, String> {
+    let conn = db::open(&app)?;
+    let (camera_role, image, captured_at): (String, String, String) = conn
+        .query_row(
+            "SELECT camera_role, image, captured_at FROM ticket_snapshots WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Unknown snapshot {}", id))?;
+    Ok(TicketSnapshot {
+        id,
+        camera_role,
+        image: attachments::load(&app, &conn, &image)?,
+        captured_at,
+    })
+}
+
+#[tauri::command]
+pub fn list_lane_cameras(app: AppHandle, lane_id: String) -> Result<Vec<LaneCamera>, String> {
+    let conn = db::open(&app)?;
+    load_cameras(&conn, &lane_id)
+}
+
+// Add or replace the camera for a role on a lane (admin only)
+#[tauri::command]
+pub fn set_lane_camera(app: AppHandle, camera: LaneCamera, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "lane_id": camera.lane_id, "role": camera.role });
+    command_audit::audited(&app, "set_lane_camera", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if !ROLES.contains(&camera.role.as_str()) {
+            return Err(format!("Unknown camera role {}", camera.role));
+        }
+        if !["http://", "rtsp://"]
+            .iter()
+            .any(|scheme| camera.snapshot_url.starts_with(scheme))
+        {
+            return Err("Snapshot URLs must start with http:// or rtsp://".to_string());
+        }
+        conn.execute(
+            "INSERT INTO lane_cameras (lane_id, role, snapshot_url, username, password,
+                 ptz_url, ptz_profile, ptz_preset)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(lane_id, role) DO UPDATE SET snapshot_url = excluded.snapshot_url,
+                 username = excluded.username,
+                 password = COALESCE(excluded.password, lane_cameras.password),
+                 ptz_url = excluded.ptz_url,
+                 ptz_profile = excluded.ptz_profile,
+                 ptz_preset = excluded.ptz_preset",
+            params![
+                camera.lane_id,
+                camera.role,
+                camera.snapshot_url,
+                camera.username,
+                camera.password,
+                camera.ptz_url,
+                camera.ptz_profile,
+                camera.ptz_preset
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn remove_lane_camera(
+    app: AppHandle,
+    lane_id: String,
+    role: String,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "lane_id": lane_id, "role": role });
+    command_audit::audited(&app, "remove_lane_camera", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        conn.execute(
+            "DELETE FROM lane_cameras WHERE lane_id = ?1 AND role = ?2",
+            params![lane_id, role],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+// Snapshots of a ticket as data URLs, in camera role order
+#[tauri::command]
+pub fn list_ticket_snapshots(
+    app: AppHandle,
+    weighment_id: String,
+) -> Result<Vec<TicketSnapshot>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, camera_role, image, captured_at FROM ticket_snapshots
+             WHERE weighment_id = ?1 ORDER BY captured_at, camera_role",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(i64, String, String, String)> = stmt
+        .query_map([&weighment_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    rows.into_iter()
+        .map(|(id, camera_role, image, captured_at)| {
+            Ok(TicketSnapshot {
+                id,
+                camera_role,
+                image: attachments::load(&app, &conn, &image)?,
+                captured_at,
+            })
+        })
+        .collect()
+}
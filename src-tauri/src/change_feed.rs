@@ -0,0 +1,144 @@
+// Table change feed for Truckore Pro
+// Row changes made through pooled connections are collected by SQLite update
+// hooks and emitted as `data://changed` events once their transaction
+// commits, so list screens refresh when data changes instead of polling.
+// Rolled-back changes are dropped, and a row written several times in one
+// transaction is reported once, with its net effect: a row inserted and
+// then updated is an insert, one inserted and deleted again is not reported
+// at all. Each event carries the data version of its commit
+// (data_version.rs). Commits that touch app_config also wake the settings
+// watcher.
+
+use crate::data_version;
+use crate::settings_events;
+use rusqlite::hooks::Action;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+
+pub const EVENT: &str = "data://changed";
+// Above this many rows in one transaction a single table-level event is sent
+const MAX_ROW_EVENTS: usize = 500;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub table: String,
+    // None for a table-level event covering a large write
+    pub rowid: Option<i64>,
+    // insert, update, delete, or bulk for a table-level event
+    pub op: &'static str,
+    pub data_version: u64,
+}
+
+fn op_name(action: Action) -> Option<&'static str> {
+    match action {
+        Action::SQLITE_INSERT => Some("insert"),
+        Action::SQLITE_UPDATE => Some("update"),
+        Action::SQLITE_DELETE => Some("delete"),
+        _ => None,
+    }
+}
+
+// Net operation of a row after `earlier` then `later`; None when the row
+// came and went within the transaction
+fn net_op(earlier: Option<&'static str>, later: &'static str) -> Option<&'static str> {
+    match (earlier, later) {
+        (Some("insert"), "update") => Some("insert"),
+        (Some("insert"), "delete") => None,
+        (Some("delete"), "insert") => Some("update"),
+        (_, later) => Some(later),
+    }
+}
+
+// One change per row, in the order rows were first written
+fn coalesce(changes: Vec<Change>) -> Vec<Change> {
+    let mut rows: Vec<(Change, Option<&'static str>)> = Vec::with_capacity(changes.len());
+    let mut seen: HashMap<(String, i64), usize> = HashMap::new();
+    for change in changes {
+        let Some(rowid) = change.rowid else {
+            let op = Some(change.op);
+            rows.push((change, op));
+            continue;
+        };
+        match seen.get(&(change.table.clone(), rowid)) {
+            Some(&i) => rows[i].1 = net_op(rows[i].1, change.op),
+            None => {
+                seen.insert((change.table.clone(), rowid), rows.len());
+                let op = Some(change.op);
+                rows.push((change, op));
+            }
+        }
+    }
+    rows.into_iter()
+        .filter_map(|(change, op)| op.map(|op| Change { op, ..change }))
+        .collect()
+}
+
+fn flush(app: &AppHandle, changes: Vec<Change>) {
+    let mut changes = coalesce(changes);
+    if changes.is_empty() {
+        return;
+    }
+    let version = data_version::advance(app);
+    for change in &mut changes {
+        change.data_version = version;
+    }
+    if changes.iter().any(|c| c.table == "app_config") {
+        settings_events::notify(app);
+    }
+    if changes.len() <= MAX_ROW_EVENTS {
+        for change in changes {
+            let _ = app.emit_all(EVENT, change);
+        }
+        return;
+    }
+    let mut tables: Vec<String> = changes.into_iter().map(|c| c.table).collect();
+    tables.sort();
+    tables.dedup();
+    for table in tables {
+        let _ = app.emit_all(
+            EVENT,
+            Change {
+                table,
+                rowid: None,
+                op: "bulk",
+                data_version: version,
+            },
+        );
+    }
+}
+
+// Install the hooks on a newly opened connection
+pub fn attach(app: &AppHandle, conn: &Connection) {
+    let pending: Arc<Mutex<Vec<Change>>> = Arc::default();
+
+    let buffer = pending.clone();
+    conn.update_hook(Some(move |action, _db: &str, table: &str, rowid| {
+        if let (Some(op), Ok(mut buffer)) = (op_name(action), buffer.lock()) {
+            buffer.push(Change {
+                table: table.to_string(),
+                rowid: Some(rowid),
+                op,
+                data_version: 0,
+            });
+        }
+    }));
+
+    let buffer = pending.clone();
+    let app = app.clone();
+    conn.commit_hook(Some(move || {
+        if let Ok(mut buffer) = buffer.lock() {
+            flush(&app, std::mem::take(&mut *buffer));
+        }
+        // false lets the commit proceed
+        false
+    }));
+
+    conn.rollback_hook(Some(move || {
+        if let Ok(mut buffer) = pending.lock() {
+            buffer.clear();
+        }
+    }));
+}
@@ -0,0 +1,873 @@
+// Off-site backups for Truckore Pro
+// A scheduled backup (backup_schedule.rs) sits on the same disk as the
+// database, so a dead disk takes both. Once a scheduled copy has verified it
+// is also uploaded, when enabled, to an S3-compatible bucket (AWS, MinIO,
+// Wasabi, ...) or a Google Drive folder. Each upload is gzipped and sealed
+// with ChaCha20-Poly1305 under a key derived from a backup passphrase, so
+// the provider only ever holds ciphertext. The newest `keep_last` uploads of
+// this database are kept. Credentials live in the secure settings store
+// (settings.rs) under the names below; the passphrase must also be kept off
+// this PC, since restoring onto a replacement needs it stored again there.
+
+use crate::backup::{self, RestoreReport};
+use crate::command_audit;
+use crate::db;
+use crate::license;
+use crate::network;
+use crate::roles::{self, Role};
+use crate::settings;
+use crate::shutdown;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const SETTINGS_CONFIG_KEY: &str = "cloud_backup";
+// Premium feature a license must cover (license.rs)
+const LICENSE_FEATURE: &str = "cloud_backup";
+// Secret names in the settings store
+const SECRET_KEY_SECRET: &str = "cloud_backup.secret_key";
+const CLIENT_SECRET_SECRET: &str = "cloud_backup.client_secret";
+const REFRESH_TOKEN_SECRET: &str = "cloud_backup.refresh_token";
+const PASSPHRASE_SECRET: &str = "cloud_backup.passphrase";
+// Uploaded file: MAGIC, salt, nonce, then the sealed gzip of the database
+const MAGIC: &[u8] = b"TKBK\x01";
+const SALT_LEN: usize = 16;
+const PBKDF2_ROUNDS: u32 = 200_000;
+const SUFFIX: &str = ".db.gz.enc";
+// Whole databases go up and down in one request
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const DRIVE_FILES_URL: &str = "https://www.googleapis.com/drive/v3/files";
+const DRIVE_UPLOAD_URL: &str = "https://www.googleapis.com/upload/drive/v3/files";
+const DRIVE_FIELDS: &str = "id,name,size,modifiedTime";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CloudBackupSettings {
+    // Upload every verified scheduled backup
+    pub enabled: bool,
+    // s3 or gdrive
+    pub provider: String,
+    // S3: service URL, e.g. https://s3.ap-south-1.amazonaws.com
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    // Address the bucket as <endpoint>/<bucket> rather than
+    // <bucket>.<endpoint>; most self-hosted stores need this
+    pub path_style: bool,
+    // S3: key prefix the uploads go under
+    pub prefix: String,
+    // Drive: OAuth client of the refresh token, and the target folder (the
+    // drive's root when empty)
+    pub client_id: String,
+    pub folder_id: String,
+    pub keep_last: u32,
+}
+
+impl Default for CloudBackupSettings {
+    fn default() -> Self {
+        CloudBackupSettings {
+            enabled: false,
+            provider: "s3".to_string(),
+            endpoint: String::new(),
+            region: "us-east-1".to_string(),
+            bucket: String::new(),
+            access_key_id: String::new(),
+            path_style: true,
+            prefix: "truckore/".to_string(),
+            client_id: String::new(),
+            folder_id: String::new(),
+            keep_last: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloudBackup {
+    // S3 object key or Drive file id
+    pub id: String,
+    pub name: String,
+    pub size_bytes: u64,
+    // As the provider reports it, UTC
+    pub modified_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloudUpload {
+    pub backup: CloudBackup,
+    // Older uploads deleted to stay within keep_last
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloudBackupStatus {
+    pub settings: CloudBackupSettings,
+    // Secrets the provider needs that are not stored yet
+    pub missing_secrets: Vec<String>,
+}
+
+pub fn load_settings(conn: &Connection) -> Result<CloudBackupSettings, String> {
+    match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(CloudBackupSettings::default()),
+    }
+}
+
+fn required_secrets(settings: &CloudBackupSettings) -> Vec<&'static str> {
+    match settings.provider.as_str() {
+        "gdrive" => vec![
+            CLIENT_SECRET_SECRET,
+            REFRESH_TOKEN_SECRET,
+            PASSPHRASE_SECRET,
+        ],
+        _ => vec![SECRET_KEY_SECRET, PASSPHRASE_SECRET],
+    }
+}
+
+fn secret(app: &AppHandle, conn: &Connection, name: &str) -> Result<String, String> {
+    settings::secret(app, conn, name)?.ok_or_else(|| format!("Store the secret {} first", name))
+}
+
+fn validate(settings: &mut CloudBackupSettings) -> Result<(), String> {
+    settings.endpoint = settings.endpoint.trim().trim_end_matches('/').to_string();
+    settings.prefix = settings.prefix.trim().trim_start_matches('/').to_string();
+    if !settings.prefix.is_empty() && !settings.prefix.ends_with('/') {
+        settings.prefix.push('/');
+    }
+    match settings.provider.as_str() {
+        "s3" => {
+            if !settings.endpoint.starts_with("https://")
+                && !settings.endpoint.starts_with("http://")
+            {
+                return Err("S3 endpoint must start with https:// or http://".to_string());
+            }
+            if settings
+                .endpoint
+                .split("://")
+                .nth(1)
+                .unwrap_or("")
+                .contains('/')
+            {
+                return Err("S3 endpoint must not have a path".to_string());
+            }
+            for (field, value) in [
+                ("region", &settings.region),
+                ("bucket", &settings.bucket),
+                ("access key id", &settings.access_key_id),
+            ] {
+                if value.trim().is_empty() {
+                    return Err(format!("S3 {} is required", field));
+                }
+            }
+        }
+        "gdrive" => {
+            if settings.client_id.trim().is_empty() {
+                return Err("Google Drive client id is required".to_string());
+            }
+        }
+        other => return Err(format!("Unknown cloud provider {} (s3 or gdrive)", other)),
+    }
+    if settings.keep_last == 0 {
+        return Err("At least one upload must be kept".to_string());
+    }
+    Ok(())
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| e.to_string())?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+// RFC 3986 percent-encoding, as SigV4 canonicalises it
+fn uri_encode(text: &str, keep_slash: bool) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if keep_slash => "/".to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn http_error(e: ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, response) => format!(
+            "Cloud storage answered HTTP {}: {}",
+            code,
+            response.into_string().unwrap_or_default()
+        ),
+        other => format!("Cloud storage unreachable: {}", other),
+    }
+}
+
+fn read_body(response: ureq::Response) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Download failed: {}", e))?;
+    Ok(bytes)
+}
+
+fn key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey, String> {
+    let rounds = NonZeroU32::new(PBKDF2_ROUNDS).ok_or("Invalid key derivation rounds")?;
+    let mut bytes = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        rounds,
+        salt,
+        passphrase.as_bytes(),
+        &mut bytes,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+        .map_err(|_| "Failed to derive the backup key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+// Compress, then encrypt, a database file for upload
+fn seal(database: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(database).map_err(|e| e.to_string())?;
+    let mut data = encoder.finish().map_err(|e| e.to_string())?;
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    key(passphrase, &salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut data,
+        )
+        .map_err(|_| "Failed to encrypt the backup".to_string())?;
+    let mut sealed = MAGIC.to_vec();
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend(data);
+    Ok(sealed)
+}
+
+fn unseal(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let header = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if sealed.len() < header || !sealed.starts_with(MAGIC) {
+        return Err("Not a Truckore cloud backup".to_string());
+    }
+    let salt = &sealed[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = Nonce::try_assume_unique_for_key(&sealed[MAGIC.len() + SALT_LEN..header])
+        .map_err(|_| "Not a Truckore cloud backup".to_string())?;
+    let mut data = sealed[header..].to_vec();
+    let compressed = key(passphrase, salt)?
+        .open_in_place(nonce, Aad::from(MAGIC), &mut data)
+        .map_err(|_| "The backup passphrase does not open this backup".to_string())?;
+    let mut database = Vec::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_end(&mut database)
+        .map_err(|e| format!("Backup does not decompress: {}", e))?;
+    Ok(database)
+}
+
+// Text of the first <name> element in `xml`
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(&xml[start..end])
+}
+
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let body = &rest[start + open.len()..];
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        found.push(&body[..end]);
+        rest = &body[end + close.len()..];
+    }
+    found
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+struct S3 {
+    agent: ureq::Agent,
+    scheme: String,
+    // host[:port] of the endpoint
+    host: String,
+    region: String,
+    bucket: String,
+    access_key_id: String,
+    secret_key: String,
+    path_style: bool,
+    prefix: String,
+}
+
+impl S3 {
+    // A request signed with AWS Signature Version 4
+    fn request(
+        &self,
+        conn: &Connection,
+        method: &str,
+        key: &str,
+        query: &[(&str, String)],
+        body: &[u8],
+    ) -> Result<ureq::Response, String> {
+        let (host, path) = match self.path_style {
+            true => (
+                self.host.clone(),
+                format!("/{}/{}", self.bucket, uri_encode(key, true)),
+            ),
+            false => (
+                format!("{}.{}", self.bucket, self.host),
+                format!("/{}", uri_encode(key, true)),
+            ),
+        };
+        let mut pairs: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, false), uri_encode(v, false)))
+            .collect();
+        pairs.sort();
+        let query = pairs
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+        let (amz_date, date): (String, String) = conn
+            .query_row(
+                "SELECT strftime('%Y%m%dT%H%M%SZ', 'now'), strftime('%Y%m%d', 'now')",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        let payload_hash = hex(&Sha256::digest(body));
+        let canonical = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, amz_date, SIGNED_HEADERS, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical.as_bytes()))
+        );
+        let mut signing_key = hmac_sha256(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            date.as_bytes(),
+        )?;
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes())?;
+        }
+        let signature = hex(&hmac_sha256(&signing_key, to_sign.as_bytes())?);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, SIGNED_HEADERS, signature
+        );
+
+        let mut url = format!("{}://{}{}", self.scheme, host, path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        let request = self
+            .agent
+            .request(method, &url)
+            .set("x-amz-date", &amz_date)
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("Authorization", &authorization);
+        match body.is_empty() {
+            true => request.call(),
+            false => request.send_bytes(body),
+        }
+        .map_err(http_error)
+    }
+
+    fn list(&self, conn: &Connection) -> Result<Vec<CloudBackup>, String> {
+        let mut backups = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2".to_string()),
+                ("prefix", self.prefix.clone()),
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.clone()));
+            }
+            let xml = self
+                .request(conn, "GET", "", &query, &[])?
+                .into_string()
+                .map_err(|e| e.to_string())?;
+            for item in elements(&xml, "Contents") {
+                let key = unescape(element(item, "Key").unwrap_or_default());
+                backups.push(CloudBackup {
+                    name: key.strip_prefix(&self.prefix).unwrap_or(&key).to_string(),
+                    size_bytes: element(item, "Size")
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(0),
+                    modified_at: element(item, "LastModified").map(str::to_string),
+                    id: key,
+                });
+            }
+            token = match element(&xml, "IsTruncated") {
+                Some("true") => element(&xml, "NextContinuationToken").map(unescape),
+                _ => None,
+            };
+            if token.is_none() {
+                return Ok(backups);
+            }
+        }
+    }
+}
+
+struct Drive {
+    agent: ureq::Agent,
+    access_token: String,
+    folder_id: String,
+}
+
+fn drive_file(file: &Value) -> CloudBackup {
+    let text = |field: &str| file.get(field).and_then(Value::as_str).map(str::to_string);
+    CloudBackup {
+        id: text("id").unwrap_or_default(),
+        name: text("name").unwrap_or_default(),
+        // Drive reports sizes as strings
+        size_bytes: text("size").and_then(|s| s.parse().ok()).unwrap_or(0),
+        modified_at: text("modifiedTime"),
+    }
+}
+
+impl Drive {
+    fn bearer(&self) -> String {
+        format!("Bearer {}", self.access_token)
+    }
+
+    fn list(&self) -> Result<Vec<CloudBackup>, String> {
+        let mut q = format!("name contains '{}' and trashed = false", SUFFIX);
+        if !self.folder_id.is_empty() {
+            q.push_str(&format!(" and '{}' in parents", self.folder_id));
+        }
+        let mut backups = Vec::new();
+        let mut page: Option<String> = None;
+        loop {
+            let mut request = self
+                .agent
+                .get(DRIVE_FILES_URL)
+                .set("Authorization", &self.bearer())
+                .query("q", &q)
+                .query("pageSize", "1000")
+                .query("fields", &format!("nextPageToken,files({})", DRIVE_FIELDS));
+            if let Some(page) = &page {
+                request = request.query("pageToken", page);
+            }
+            let body: Value = request
+                .call()
+                .map_err(http_error)?
+                .into_json()
+                .map_err(|e| e.to_string())?;
+            if let Some(files) = body.get("files").and_then(Value::as_array) {
+                backups.extend(files.iter().map(drive_file));
+            }
+            page = body
+                .get("nextPageToken")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            if page.is_none() {
+                return Ok(backups);
+            }
+        }
+    }
+
+    // Multipart upload: the file's metadata, then its content
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<CloudBackup, String> {
+        let mut metadata = serde_json::json!({ "name": name });
+        if !self.folder_id.is_empty() {
+            metadata["parents"] = serde_json::json!([self.folder_id]);
+        }
+        let boundary = format!("truckore-{}", uuid::Uuid::new_v4().simple());
+        let mut body = format!(
+            "--{b}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{}\r\n--{b}\r\nContent-Type: application/octet-stream\r\n\r\n",
+            metadata,
+            b = boundary
+        )
+        .into_bytes();
+        body.extend_from_slice(bytes);
+        body.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+        let file: Value = self
+            .agent
+            .post(DRIVE_UPLOAD_URL)
+            .set("Authorization", &self.bearer())
+            .set(
+                "Content-Type",
+                &format!("multipart/related; boundary={}", boundary),
+            )
+            .query("uploadType", "multipart")
+            .query("fields", DRIVE_FIELDS)
+            .send_bytes(&body)
+            .map_err(http_error)?
+            .into_json()
+            .map_err(|e| e.to_string())?;
+        Ok(drive_file(&file))
+    }
+}
+
+// An S3-compatible bucket outside cloud backups, e.g. an export destination
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Bucket {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub path_style: bool,
+}
+
+// Upload `bytes` as `key` to `bucket`
+pub fn put_object(
+    conn: &Connection,
+    bucket: &S3Bucket,
+    secret_key: &str,
+    key: &str,
+    bytes: &[u8],
+) -> Result<(), String> {
+    let endpoint = bucket.endpoint.trim_end_matches('/');
+    let (scheme, host) = endpoint
+        .split_once("://")
+        .ok_or("S3 endpoint must start with https:// or http://")?;
+    let s3 = S3 {
+        agent: network::http_agent(conn, endpoint, TRANSFER_TIMEOUT)?,
+        scheme: scheme.to_string(),
+        host: host.to_string(),
+        region: bucket.region.clone(),
+        bucket: bucket.bucket.clone(),
+        access_key_id: bucket.access_key_id.clone(),
+        secret_key: secret_key.to_string(),
+        path_style: bucket.path_style,
+        prefix: String::new(),
+    };
+    s3.request(conn, "PUT", key, &[], bytes).map(|_| ())
+}
+
+enum Remote {
+    S3(Box<S3>),
+    Drive(Drive),
+}
+
+impl Remote {
+    fn connect(
+        app: &AppHandle,
+        conn: &Connection,
+        settings: &CloudBackupSettings,
+    ) -> Result<Remote, String> {
+        match settings.provider.as_str() {
+            "s3" => {
+                let (scheme, host) = settings
+                    .endpoint
+                    .split_once("://")
+                    .ok_or("Cloud backup is not configured")?;
+                Ok(Remote::S3(Box::new(S3 {
+                    agent: network::http_agent(conn, &settings.endpoint, TRANSFER_TIMEOUT)?,
+                    scheme: scheme.to_string(),
+                    host: host.to_string(),
+                    region: settings.region.clone(),
+                    bucket: settings.bucket.clone(),
+                    access_key_id: settings.access_key_id.clone(),
+                    secret_key: secret(app, conn, SECRET_KEY_SECRET)?,
+                    path_style: settings.path_style,
+                    prefix: settings.prefix.clone(),
+                })))
+            }
+            "gdrive" => {
+                let agent = network::http_agent(conn, DRIVE_FILES_URL, TRANSFER_TIMEOUT)?;
+                let token: Value = agent
+                    .post(GOOGLE_TOKEN_URL)
+                    .send_form(&[
+                        ("client_id", settings.client_id.as_str()),
+                        ("client_secret", &secret(app, conn, CLIENT_SECRET_SECRET)?),
+                        ("refresh_token", &secret(app, conn, REFRESH_TOKEN_SECRET)?),
+                        ("grant_type", "refresh_token"),
+                    ])
+                    .map_err(http_error)?
+                    .into_json()
+                    .map_err(|e| e.to_string())?;
+                let access_token = token
+                    .get("access_token")
+                    .and_then(Value::as_str)
+                    .ok_or("Google did not grant an access token")?
+                    .to_string();
+                Ok(Remote::Drive(Drive {
+                    agent,
+                    access_token,
+                    folder_id: settings.folder_id.trim().to_string(),
+                }))
+            }
+            other => Err(format!("Unknown cloud provider {}", other)),
+        }
+    }
+
+    // Uploaded backups of any database, newest first
+    fn list(&self, conn: &Connection) -> Result<Vec<CloudBackup>, String> {
+        let mut backups = match self {
+            Remote::S3(s3) => s3.list(conn)?,
+            Remote::Drive(drive) => drive.list()?,
+        };
+        backups.retain(|b| b.name.ends_with(SUFFIX) && !b.name.contains('/'));
+        // Names carry a sortable timestamp
+        backups.sort_by(|a, b| b.name.cmp(&a.name));
+        Ok(backups)
+    }
+
+    fn put(&self, conn: &Connection, name: &str, bytes: &[u8]) -> Result<CloudBackup, String> {
+        match self {
+            Remote::S3(s3) => {
+                let key = format!("{}{}", s3.prefix, name);
+                s3.request(conn, "PUT", &key, &[], bytes)?;
+                Ok(CloudBackup {
+                    id: key,
+                    name: name.to_string(),
+                    size_bytes: bytes.len() as u64,
+                    modified_at: None,
+                })
+            }
+            Remote::Drive(drive) => drive.put(name, bytes),
+        }
+    }
+
+    fn get(&self, conn: &Connection, id: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Remote::S3(s3) => read_body(s3.request(conn, "GET", id, &[], &[])?),
+            Remote::Drive(drive) => read_body(
+                drive
+                    .agent
+                    .get(&format!("{}/{}", DRIVE_FILES_URL, uri_encode(id, false)))
+                    .set("Authorization", &drive.bearer())
+                    .query("alt", "media")
+                    .call()
+                    .map_err(http_error)?,
+            ),
+        }
+    }
+
+    fn delete(&self, conn: &Connection, id: &str) -> Result<(), String> {
+        match self {
+            Remote::S3(s3) => s3.request(conn, "DELETE", id, &[], &[]).map(|_| ()),
+            Remote::Drive(drive) => drive
+                .agent
+                .delete(&format!("{}/{}", DRIVE_FILES_URL, uri_encode(id, false)))
+                .set("Authorization", &drive.bearer())
+                .call()
+                .map(|_| ())
+                .map_err(http_error),
+        }
+    }
+}
+
+// "<database>-", the start of every upload of the live database
+fn database_prefix(app: &AppHandle) -> Result<String, String> {
+    let db_path = crate::get_db_path(app)?;
+    Ok(format!(
+        "{}-",
+        db_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("truckore")
+    ))
+}
+
+fn work_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = crate::get_db_path(app)?
+        .parent()
+        .ok_or("Failed to resolve the data directory")?
+        .join("backups")
+        .join("cloud");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+// Seal and upload the verified backup at `path`, then delete the oldest
+// uploads of this database beyond keep_last
+pub fn upload(app: &AppHandle, path: &Path) -> Result<CloudUpload, String> {
+    // Scheduled backups upload too, without going through the guard
+    license::covers(app, LICENSE_FEATURE)?;
+    let conn = db::open(app)?;
+    let settings = load_settings(&conn)?;
+    let passphrase = secret(app, &conn, PASSPHRASE_SECRET)?;
+    let remote = Remote::connect(app, &conn, &settings)?;
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or("Backup file has no name")?;
+    let name = format!("{}{}", stem, SUFFIX);
+    let _operation = shutdown::begin(app, "cloud_upload", Some(&name))?;
+
+    let database = fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let backup = remote.put(&conn, &name, &seal(&database, &passphrase)?)?;
+    tracing::info!(name = %backup.name, "Backup uploaded");
+
+    let prefix = database_prefix(app)?;
+    let mut removed = Vec::new();
+    let uploads = remote
+        .list(&conn)
+        .map_err(|e| format!("Uploaded {}, but could not apply retention: {}", name, e))?;
+    let ours: Vec<CloudBackup> = uploads
+        .into_iter()
+        .filter(|b| b.name.starts_with(&prefix))
+        .collect();
+    for old in ours.into_iter().skip(settings.keep_last as usize) {
+        remote.delete(&conn, &old.id).map_err(|e| {
+            format!(
+                "Uploaded {}, but could not delete {}: {}",
+                name, old.name, e
+            )
+        })?;
+        removed.push(old.name);
+    }
+    Ok(CloudUpload { backup, removed })
+}
+
+#[tauri::command]
+pub fn get_cloud_backup_settings(app: AppHandle) -> Result<CloudBackupStatus, String> {
+    let conn = db::open(&app)?;
+    let settings = load_settings(&conn)?;
+    let mut missing_secrets = Vec::new();
+    for name in required_secrets(&settings) {
+        if !settings::has_secret(&conn, name)? {
+            missing_secrets.push(name.to_string());
+        }
+    }
+    Ok(CloudBackupStatus {
+        settings,
+        missing_secrets,
+    })
+}
+
+// Credentials and the passphrase are stored separately with set_secret
+// (admin only)
+#[tauri::command]
+pub fn set_cloud_backup_settings(
+    app: AppHandle,
+    settings: CloudBackupSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_cloud_backup_settings", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut settings = settings;
+        validate(&mut settings)?;
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)
+    })
+}
+
+// Back up now and upload the copy, whether or not uploads are scheduled
+// (admin only)
+#[tauri::command]
+pub fn upload_backup_to_cloud(app: AppHandle, user_id: String) -> Result<CloudUpload, String> {
+    command_audit::audited(
+        &app,
+        "upload_backup_to_cloud",
+        &user_id,
+        Value::Null,
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            let stamp: String = conn
+                .query_row(
+                    "SELECT strftime('%Y%m%d-%H%M%S', 'now', 'localtime')",
+                    [],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            let path = work_dir(&app)?.join(format!("{}{}.db", database_prefix(&app)?, stamp));
+            let path_text = path.to_string_lossy().to_string();
+            let result = backup::online_copy(&app, &conn, &path)
+                .map_err(|e| format!("Backup failed: {}", e))
+                .and_then(|_| {
+                    drop(conn);
+                    let verification = backup::verify_backup(app.clone(), path_text.clone())?;
+                    if !verification.ok {
+                        return Err(format!(
+                            "Backup failed verification: {}",
+                            verification.error.unwrap_or(verification.integrity)
+                        ));
+                    }
+                    upload(&app, &path)
+                });
+            let _ = fs::remove_file(&path);
+            result
+        },
+    )
+}
+
+// Uploaded backups, newest first
+#[tauri::command]
+pub fn list_cloud_backups(app: AppHandle) -> Result<Vec<CloudBackup>, String> {
+    let conn = db::open(&app)?;
+    let settings = load_settings(&conn)?;
+    Remote::connect(&app, &conn, &settings)?.list(&conn)
+}
+
+// Download, decrypt and restore an uploaded backup (admin only), see
+// backup::restore_from. The passphrase in the settings store must be the
+// one the backup was sealed with.
+#[tauri::command]
+pub fn restore_from_cloud(
+    app: AppHandle,
+    id: String,
+    user_id: String,
+) -> Result<RestoreReport, String> {
+    let args = serde_json::json!({ "id": id });
+    command_audit::audited(&app, "restore_from_cloud", &user_id, args, || {
+        let path = {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            let settings = load_settings(&conn)?;
+            let passphrase = secret(&app, &conn, PASSPHRASE_SECRET)?;
+            let remote = Remote::connect(&app, &conn, &settings)?;
+            let backup = remote
+                .list(&conn)?
+                .into_iter()
+                .find(|b| b.id == id)
+                .ok_or_else(|| format!("Cloud backup {} not found", id))?;
+            let _operation = shutdown::begin(&app, "cloud_download", Some(&backup.name))?;
+            let database = unseal(&remote.get(&conn, &backup.id)?, &passphrase)?;
+            let path = work_dir(&app)?.join("cloud-restore.db");
+            fs::write(&path, database).map_err(|e| e.to_string())?;
+            path
+        };
+        let detail = Some(format!("Restored cloud backup {}", id));
+        let report = backup::restore_from(&app, &path, "restore", detail, &user_id);
+        let _ = fs::remove_file(&path);
+        report
+    })
+}
@@ -0,0 +1,126 @@
+// Command-level authorization audit for Truckore Pro
+// Every privileged command invocation is recorded with who ran it, a digest of
+// its arguments, whether it was allowed and how long it took. Kept apart from
+// the data audit trail (security_logs) and append-only.
+
+use crate::crash_reports;
+use crate::db::{self, DateRange};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Instant;
+use tauri::AppHandle;
+
+// Error prefix that marks a refusal rather than a failure of an allowed call
+pub const DENIED_PREFIX: &str = "Permission denied";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandAuditEntry {
+    pub id: i64,
+    pub user_id: Option<String>,
+    pub command: String,
+    pub args_digest: String,
+    pub decision: String,
+    pub error: Option<String>,
+    pub duration_ms: i64,
+    pub invoked_at: String,
+}
+
+fn is_denial(error: &str) -> bool {
+    error.starts_with(DENIED_PREFIX) || error.starts_with("Unknown or inactive user")
+}
+
+// Run a privileged command body and record the invocation. Arguments are only
+// stored as a SHA-256 digest so secrets and reasons don't leak into the log.
+pub fn audited<T>(
+    app: &AppHandle,
+    command: &str,
+    user_id: &str,
+    args: serde_json::Value,
+    run: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    crash_reports::breadcrumb(command);
+    let started = Instant::now();
+    let result = run();
+    let duration_ms = started.elapsed().as_millis() as i64;
+
+    let digest = Sha256::digest(args.to_string().as_bytes());
+    let args_digest: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    let error = result.as_ref().err();
+    let decision = match error {
+        Some(e) if is_denial(e) => "DENY",
+        _ => "ALLOW",
+    };
+    match error {
+        Some(e) if decision == "DENY" => {
+            tracing::warn!(command, user_id, error = %e, "Command denied")
+        }
+        Some(e) => tracing::warn!(command, user_id, error = %e, "Command failed"),
+        None => tracing::info!(command, user_id, duration_ms, "Command allowed"),
+    }
+
+    // Written on its own connection so it survives a rolled-back command;
+    // recording is best effort and never changes the command's result
+    let _ = db::open(app).and_then(|conn| {
+        conn.execute(
+            "INSERT INTO command_audit_log (user_id, command, args_digest, decision, error, duration_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![user_id, command, args_digest, decision, error, duration_ms],
+        )
+        .map_err(|e| e.to_string())
+    });
+
+    result
+}
+
+// Search the command audit log, newest first
+#[tauri::command]
+pub fn query_security_log(
+    app: AppHandle,
+    user_id: Option<String>,
+    command: Option<String>,
+    decision: Option<String>,
+    range: Option<DateRange>,
+    limit: Option<i64>,
+) -> Result<Vec<CommandAuditEntry>, String> {
+    let conn = db::open(&app)?;
+    let sql = format!(
+        "SELECT id, user_id, command, args_digest, decision, error, duration_ms, invoked_at
+         FROM command_audit_log
+         WHERE (?1 IS NULL OR user_id = ?1)
+           AND (?2 IS NULL OR command = ?2)
+           AND (?3 IS NULL OR decision = ?3)
+           AND (?4 IS NULL OR {date} >= ?4)
+           AND (?5 IS NULL OR {date} <= ?5)
+         ORDER BY id DESC LIMIT ?6",
+        date = db::local_date("invoked_at")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                user_id,
+                command,
+                decision,
+                range.as_ref().map(|r| &r.from),
+                range.as_ref().map(|r| &r.to),
+                limit.unwrap_or(500)
+            ],
+            |row| {
+                Ok(CommandAuditEntry {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    command: row.get(2)?,
+                    args_digest: row.get(3)?,
+                    decision: row.get(4)?,
+                    error: row.get(5)?,
+                    duration_ms: row.get(6)?,
+                    invoked_at: row.get(7)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,354 @@
+// Communication log for Truckore Pro
+// Mail, SMS, WhatsApp messages and webhooks used to leave no trace beyond
+// the day-close row, so nobody could tell whether a party was ever sent
+// their slip. Every outbound message now gets a row in communications
+// before it is sent: the channel, where it went, a short summary and the
+// ticket or party it concerns, then whether it was delivered. Messages that
+// can be rebuilt keep their request so a failed one can be sent again from
+// the log. SMS, WhatsApp and webhook gateways are plain HTTP endpoints the
+// backend posts to with send_communication; mail goes through smtp.rs.
+
+use crate::db;
+use crate::network;
+use crate::profiles;
+use crate::report_schedule;
+use crate::settings;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const HTTP_CHANNELS: &[&str] = &["SMS", "WHATSAPP", "WEBHOOK"];
+const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+// Characters kept of a message's summary
+const SUMMARY_LIMIT: usize = 200;
+const DEFAULT_LIMIT: i64 = 200;
+
+// What it takes to send a message again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Request {
+    // JSON body posted to a gateway, with a stored secret as bearer token
+    Http {
+        url: String,
+        body: Value,
+        auth_secret: Option<String>,
+    },
+    // The daily register mail for a date (report_schedule.rs)
+    Register {
+        report_date: String,
+    },
+}
+
+pub struct Outbound<'a> {
+    // EMAIL, SMS, WHATSAPP or WEBHOOK
+    pub channel: &'a str,
+    pub destination: String,
+    pub summary: String,
+    pub weighment_id: Option<String>,
+    pub party_name: Option<String>,
+    pub request: Option<Request>,
+    pub created_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Communication {
+    pub id: i64,
+    pub channel: String,
+    pub destination: String,
+    pub summary: String,
+    pub weighment_id: Option<String>,
+    pub party_name: Option<String>,
+    pub status: String,
+    pub attempts: i64,
+    pub error: Option<String>,
+    // Whether retry_communication can send it again
+    pub retryable: bool,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    pub last_attempt_at: Option<String>,
+    pub sent_at: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CommunicationFilter {
+    pub channel: Option<String>,
+    pub status: Option<String>,
+    pub weighment_id: Option<String>,
+    pub party_name: Option<String>,
+    pub limit: Option<i64>,
+}
+
+// A message for an SMS, WhatsApp or webhook gateway
+#[derive(Debug, Deserialize)]
+pub struct OutboundMessage {
+    pub channel: String,
+    // Phone number or address the gateway delivers to, as shown in the log
+    pub destination: String,
+    pub summary: String,
+    pub weighment_id: Option<String>,
+    pub party_name: Option<String>,
+    pub url: String,
+    pub body: Value,
+    // Name of a stored secret sent as the bearer token
+    pub auth_secret: Option<String>,
+}
+
+fn record(conn: &Connection, outbound: &Outbound) -> Result<i64, String> {
+    let request = outbound
+        .request
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO communications
+             (channel, destination, summary, weighment_id, party_name, request, created_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            outbound.channel,
+            outbound.destination,
+            outbound
+                .summary
+                .chars()
+                .take(SUMMARY_LIMIT)
+                .collect::<String>(),
+            outbound.weighment_id,
+            outbound.party_name,
+            request,
+            outbound.created_by,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn attempted(conn: &Connection, id: i64, result: &Result<(), String>) -> Result<(), String> {
+    conn.execute(
+        "UPDATE communications
+         SET attempts = attempts + 1, last_attempt_at = CURRENT_TIMESTAMP,
+             status = ?2, error = ?3,
+             sent_at = CASE WHEN ?2 = 'SENT' THEN CURRENT_TIMESTAMP ELSE sent_at END
+         WHERE id = ?1",
+        params![
+            id,
+            if result.is_ok() { "SENT" } else { "FAILED" },
+            result.as_ref().err(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Log a message, send it and record whether it went
+pub fn logged(
+    conn: &Connection,
+    outbound: Outbound,
+    send: impl FnOnce() -> Result<(), String>,
+) -> Result<(), String> {
+    let id = record(conn, &outbound)?;
+    let result = send();
+    attempted(conn, id, &result)?;
+    result
+}
+
+// Outbound messages are off in the training and demo profiles
+pub fn require_outbound(app: &AppHandle) -> Result<(), String> {
+    match profiles::current(app)?.settings.outbound_integrations {
+        true => Ok(()),
+        false => Err("Outbound messages are switched off in this profile".to_string()),
+    }
+}
+
+fn post(
+    app: &AppHandle,
+    conn: &Connection,
+    url: &str,
+    body: &Value,
+    auth_secret: Option<&str>,
+) -> Result<(), String> {
+    let agent = network::http_agent(conn, url, HTTP_TIMEOUT)?;
+    let mut request = agent.post(url).set("Content-Type", "application/json");
+    if let Some(name) = auth_secret {
+        let token = settings::secret(app, conn, name)?
+            .ok_or_else(|| format!("No secret named {} is stored", name))?;
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    request
+        .send_string(&body.to_string())
+        .map(|_| ())
+        .map_err(|e| match e {
+            ureq::Error::Status(code, response) => format!(
+                "Gateway answered HTTP {}: {}",
+                code,
+                response.into_string().unwrap_or_default()
+            ),
+            other => format!("Gateway unreachable: {}", other),
+        })
+}
+
+fn deliver(app: &AppHandle, conn: &Connection, request: &Request) -> Result<(), String> {
+    match request {
+        Request::Http {
+            url,
+            body,
+            auth_secret,
+        } => post(app, conn, url, body, auth_secret.as_deref()),
+        Request::Register { report_date } => {
+            let schedule = report_schedule::load_schedule(conn)?;
+            report_schedule::send_register(app, conn, &schedule, report_date)
+        }
+    }
+}
+
+const COMMUNICATION_COLUMNS: &str = "id, channel, destination, summary, weighment_id, party_name,
+     status, attempts, error, request IS NOT NULL, created_by, created_at, last_attempt_at,
+     sent_at";
+
+fn row_to_communication(row: &rusqlite::Row) -> rusqlite::Result<Communication> {
+    Ok(Communication {
+        id: row.get(0)?,
+        channel: row.get(1)?,
+        destination: row.get(2)?,
+        summary: row.get(3)?,
+        weighment_id: row.get(4)?,
+        party_name: row.get(5)?,
+        status: row.get(6)?,
+        attempts: row.get(7)?,
+        error: row.get(8)?,
+        retryable: row.get(9)?,
+        created_by: row.get(10)?,
+        created_at: row.get(11)?,
+        last_attempt_at: row.get(12)?,
+        sent_at: row.get(13)?,
+    })
+}
+
+fn find(conn: &Connection, id: i64) -> Result<Communication, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM communications WHERE id = ?1",
+            COMMUNICATION_COLUMNS
+        ),
+        [id],
+        row_to_communication,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Unknown communication {}", id))
+}
+
+// Send a message through an SMS, WhatsApp or webhook gateway and log it;
+// the logged entry is returned whether or not it was delivered
+#[tauri::command]
+pub async fn send_communication(
+    app: AppHandle,
+    message: OutboundMessage,
+    user_id: String,
+) -> Result<Communication, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let channel = message.channel.trim().to_ascii_uppercase();
+        let channel = HTTP_CHANNELS
+            .iter()
+            .find(|c| **c == channel)
+            .ok_or_else(|| format!("Unknown gateway channel: {}", message.channel))?;
+        if !message.url.starts_with("https://") && !message.url.starts_with("http://") {
+            return Err(format!("Invalid gateway URL: {}", message.url));
+        }
+        if message.destination.trim().is_empty() {
+            return Err("A destination is required".to_string());
+        }
+        require_outbound(&app)?;
+        let conn = db::open(&app)?;
+        let request = Request::Http {
+            url: message.url,
+            body: message.body,
+            auth_secret: message.auth_secret,
+        };
+        let id = record(
+            &conn,
+            &Outbound {
+                channel,
+                destination: message.destination.trim().to_string(),
+                summary: message.summary,
+                weighment_id: message.weighment_id,
+                party_name: message.party_name,
+                request: Some(request.clone()),
+                created_by: Some(user_id),
+            },
+        )?;
+        attempted(&conn, id, &deliver(&app, &conn, &request))?;
+        find(&conn, id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Send a failed message again; returns the entry with the new outcome
+#[tauri::command]
+pub async fn retry_communication(
+    app: AppHandle,
+    id: i64,
+    user_id: String,
+) -> Result<Communication, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&app)?;
+        let (status, request): (String, Option<String>) = conn
+            .query_row(
+                "SELECT status, request FROM communications WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Unknown communication {}", id))?;
+        if status == "SENT" {
+            return Err(format!("Communication {} was already sent", id));
+        }
+        let request: Request = match request {
+            Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            None => return Err(format!("Communication {} cannot be sent again", id)),
+        };
+        require_outbound(&app)?;
+        tracing::info!(communication = id, user = %user_id, "Retrying communication");
+        attempted(&conn, id, &deliver(&app, &conn, &request))?;
+        find(&conn, id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Newest first, by channel, status, ticket and/or party
+#[tauri::command]
+pub fn list_communications(
+    app: AppHandle,
+    filter: Option<CommunicationFilter>,
+) -> Result<Vec<Communication>, String> {
+    let filter = filter.unwrap_or_default();
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM communications
+             WHERE (?1 IS NULL OR channel = ?1) AND (?2 IS NULL OR status = ?2)
+               AND (?3 IS NULL OR weighment_id = ?3) AND (?4 IS NULL OR party_name = ?4)
+             ORDER BY id DESC LIMIT ?5",
+            COMMUNICATION_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                filter.channel.map(|c| c.to_ascii_uppercase()),
+                filter.status.map(|s| s.to_ascii_uppercase()),
+                filter.weighment_id,
+                filter.party_name,
+                filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, 1000),
+            ],
+            row_to_communication,
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
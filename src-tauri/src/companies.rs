@@ -0,0 +1,227 @@
+// Client companies for Truckore Pro
+// One PC can run the weighbridges of several client companies. Each company
+// has its own data folder, and with it its own databases (one per
+// environment profile), attachments and backups. The companies are listed
+// in companies.json under app data; the first keeps the original `data`
+// folder, later ones get companies/<id>. The active company is held in
+// managed state and get_db_path routes every connection to it.
+//
+// Users belong to a company's database, so switching company signs everyone
+// out and forgets the database key.
+
+use crate::auth::{self, Sessions};
+use crate::command_audit;
+use crate::db;
+use crate::encryption;
+use crate::roles::{self, Role};
+use crate::settings_events;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+const SETTINGS_FILE: &str = "companies.json";
+pub const DEFAULT_COMPANY: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Company {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SettingsFile {
+    selected: Option<String>,
+    companies: Vec<Company>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompanyInfo {
+    pub id: String,
+    pub name: String,
+    pub data_dir: String,
+    pub active: bool,
+}
+
+// Active company, managed by Tauri
+pub struct ActiveCompany(Mutex<String>);
+
+impl Default for ActiveCompany {
+    fn default() -> Self {
+        ActiveCompany(Mutex::new(DEFAULT_COMPANY.to_string()))
+    }
+}
+
+fn app_data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| "Failed to get app data directory".to_string())
+}
+
+// The company list, always starting with the original company
+fn load_settings(app: &AppHandle) -> Result<SettingsFile, String> {
+    let path = app_data_dir(app)?.join(SETTINGS_FILE);
+    let mut settings: SettingsFile = match path.exists() {
+        true => {
+            let json = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            serde_json::from_str(&json).map_err(|e| e.to_string())?
+        }
+        false => SettingsFile::default(),
+    };
+    if !settings.companies.iter().any(|c| c.id == DEFAULT_COMPANY) {
+        settings.companies.insert(
+            0,
+            Company {
+                id: DEFAULT_COMPANY.to_string(),
+                name: "Default company".to_string(),
+            },
+        );
+    }
+    Ok(settings)
+}
+
+fn save_settings(app: &AppHandle, settings: &SettingsFile) -> Result<(), String> {
+    let dir = app_data_dir(app)?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(dir.join(SETTINGS_FILE), json).map_err(|e| e.to_string())
+}
+
+// Folder holding a company's databases
+pub fn data_dir(app: &AppHandle, company: &str) -> Result<PathBuf, String> {
+    let base = app_data_dir(app)?;
+    Ok(match company {
+        DEFAULT_COMPANY => base.join("data"),
+        id => base.join("companies").join(id),
+    })
+}
+
+pub fn active(app: &AppHandle) -> String {
+    app.state::<ActiveCompany>()
+        .0
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_else(|_| DEFAULT_COMPANY.to_string())
+}
+
+// Pick the saved company; called from the Tauri setup hook. A company that
+// was removed from the file falls back to the original one.
+pub fn init(app: &AppHandle) -> Result<(), String> {
+    let settings = load_settings(app)?;
+    let company = settings
+        .selected
+        .filter(|id| settings.companies.iter().any(|c| c.id == *id))
+        .unwrap_or_else(|| DEFAULT_COMPANY.to_string());
+    *app.state::<ActiveCompany>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())? = company;
+    Ok(())
+}
+
+fn info(app: &AppHandle, company: &Company, active: &str) -> Result<CompanyInfo, String> {
+    Ok(CompanyInfo {
+        id: company.id.clone(),
+        name: company.name.clone(),
+        data_dir: data_dir(app, &company.id)?.to_string_lossy().into_owned(),
+        active: company.id == active,
+    })
+}
+
+// Folder-safe id from a company name: "Sri Ram Traders" -> "sri-ram-traders"
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[tauri::command]
+pub fn list_companies(app: AppHandle) -> Result<Vec<CompanyInfo>, String> {
+    let active = active(&app);
+    load_settings(&app)?
+        .companies
+        .iter()
+        .map(|company| info(&app, company, &active))
+        .collect()
+}
+
+// Add a company (admins). Its database is created when it is first
+// switched to and initialised.
+#[tauri::command]
+pub fn create_company(
+    app: AppHandle,
+    name: String,
+    user_id: String,
+) -> Result<CompanyInfo, String> {
+    let args = serde_json::json!({ "name": name });
+    command_audit::audited(&app, "create_company", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Company name is required".to_string());
+        }
+        let mut settings = load_settings(&app)?;
+        if settings
+            .companies
+            .iter()
+            .any(|c| c.name.eq_ignore_ascii_case(name))
+        {
+            return Err(format!("A company named {} already exists", name));
+        }
+        let base = match slug(name) {
+            slug if slug.is_empty() => "company".to_string(),
+            slug => slug,
+        };
+        let mut id = base.clone();
+        let mut n = 2;
+        while settings.companies.iter().any(|c| c.id == id) {
+            id = format!("{}-{}", base, n);
+            n += 1;
+        }
+        let company = Company {
+            id,
+            name: name.to_string(),
+        };
+        fs::create_dir_all(data_dir(&app, &company.id)?).map_err(|e| e.to_string())?;
+        settings.companies.push(company.clone());
+        save_settings(&app, &settings)?;
+        info(&app, &company, &active(&app))
+    })
+}
+
+// Switch company and remember it for the next start. Everyone is signed
+// out; the frontend re-runs init_database and shows the sign-in screen.
+#[tauri::command]
+pub fn switch_company(
+    app: AppHandle,
+    sessions: State<'_, Sessions>,
+    id: String,
+) -> Result<CompanyInfo, String> {
+    let mut settings = load_settings(&app)?;
+    let company = settings
+        .companies
+        .iter()
+        .find(|c| c.id == id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown company {}", id))?;
+    settings.selected = Some(company.id.clone());
+    save_settings(&app, &settings)?;
+    *app.state::<ActiveCompany>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())? = company.id.clone();
+    auth::end_all(&sessions)?;
+    encryption::forget_key(&app);
+    // Running subsystems pick up the new company's settings
+    settings_events::notify(&app);
+    info(&app, &company, &company.id)
+}
@@ -0,0 +1,54 @@
+// Compressed IPC payloads for Truckore Pro
+// Multi-megabyte JSON results make the webview stutter while it parses them.
+// Callers that can decode gzip say so per call; results over THRESHOLD bytes
+// then come back as base64 gzip of the JSON, smaller ones as the JSON text.
+// The webview decodes with DecompressionStream("gzip").
+
+use base64::{engine::general_purpose, Engine as _};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+// Below this the JSON crosses the bridge as is
+pub const THRESHOLD: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncodedPayload {
+    // "identity" (JSON text) or "gzip" (base64 of gzipped JSON)
+    pub encoding: String,
+    pub data: String,
+    // Bytes of JSON before compression
+    pub original_size: usize,
+}
+
+// Serialize `value`, gzipping it when it is large and the caller accepts gzip
+pub fn encode<T: Serialize>(
+    value: &T,
+    accept_encoding: &[String],
+) -> Result<EncodedPayload, String> {
+    let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    let original_size = json.len();
+    let gzip = accept_encoding
+        .iter()
+        .any(|e| e.trim().eq_ignore_ascii_case("gzip"));
+    if !gzip || original_size < THRESHOLD {
+        return Ok(EncodedPayload {
+            encoding: "identity".to_string(),
+            data: json,
+            original_size,
+        });
+    }
+
+    // Fast level: the point is to spare the bridge, not to archive
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder
+        .write_all(json.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let compressed = encoder.finish().map_err(|e| e.to_string())?;
+    Ok(EncodedPayload {
+        encoding: "gzip".to_string(),
+        data: general_purpose::STANDARD.encode(compressed),
+        original_size,
+    })
+}
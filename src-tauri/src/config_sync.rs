@@ -0,0 +1,211 @@
+// Head-office configuration publishing for Truckore Pro
+// Head office exports a versioned package of its configuration (tariffs,
+// templates, rules, settings). Sites pull packages from the sync drop
+// location and apply them atomically. The configuration a package replaced
+// is kept, so the last update can be rolled back.
+
+use crate::command_audit;
+use crate::configuration::{self, ConfigurationBundle, ConfigurationSummary};
+use crate::db;
+use crate::idempotency;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tauri::AppHandle;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigPackage {
+    pub version: i64,
+    pub publisher: String,
+    pub published_at: String,
+    // sha256 of the serialized bundle, checked before anything is applied
+    pub digest: String,
+    pub bundle: ConfigurationBundle,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigVersion {
+    pub id: i64,
+    pub version: i64,
+    pub publisher: String,
+    pub published_at: String,
+    pub applied_at: String,
+    // APPLIED or ROLLED_BACK
+    pub status: String,
+    pub rolled_back_at: Option<String>,
+}
+
+fn bundle_digest(bundle: &ConfigurationBundle) -> Result<String, String> {
+    Ok(idempotency::digest(
+        &serde_json::to_value(bundle).map_err(|e| e.to_string())?,
+    ))
+}
+
+fn current_version(conn: &Connection) -> Result<Option<i64>, String> {
+    conn.query_row(
+        "SELECT version FROM config_versions WHERE status = 'APPLIED' ORDER BY id DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Write this install's configuration as package `version` (head office)
+#[tauri::command]
+pub fn publish_configuration(
+    app: AppHandle,
+    path: String,
+    version: i64,
+    user_id: String,
+) -> Result<ConfigurationSummary, String> {
+    let args = serde_json::json!({ "path": path, "version": version });
+    command_audit::audited(&app, "publish_configuration", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::SuperAdmin)?;
+        let bundle = configuration::build_bundle(&conn)?;
+        let package = ConfigPackage {
+            version,
+            publisher: user_id.clone(),
+            published_at: bundle.exported_at.clone(),
+            digest: bundle_digest(&bundle)?,
+            bundle,
+        };
+        let json = serde_json::to_string_pretty(&package).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| e.to_string())?;
+        Ok(ConfigurationSummary {
+            settings: package.bundle.settings.len(),
+            tables: package
+                .bundle
+                .tables
+                .iter()
+                .map(|(t, rows)| (t.clone(), rows.len()))
+                .collect(),
+        })
+    })
+}
+
+// Apply a published package if it is newer than the current version. The
+// previous configuration and the version record are written in the same
+// transaction as the update.
+pub fn apply_package(conn: &mut Connection, package: &ConfigPackage) -> Result<bool, String> {
+    if bundle_digest(&package.bundle)? != package.digest {
+        return Err("Configuration package is corrupt (digest mismatch)".to_string());
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    if current_version(&tx)?.is_some_and(|v| v >= package.version) {
+        return Ok(false);
+    }
+    let previous = configuration::build_bundle(&tx)?;
+    configuration::apply_bundle(&tx, &package.bundle)?;
+    tx.execute(
+        "INSERT INTO config_versions (version, publisher, published_at, bundle, previous)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            package.version,
+            package.publisher,
+            package.published_at,
+            serde_json::to_string(&package.bundle).map_err(|e| e.to_string())?,
+            serde_json::to_string(&previous).map_err(|e| e.to_string())?
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+// Pull a package from the sync drop location (site). Returns false when the
+// package is not newer than what is already applied.
+#[tauri::command]
+pub fn pull_configuration(app: AppHandle, path: String, user_id: String) -> Result<bool, String> {
+    let args = serde_json::json!({ "path": path });
+    command_audit::audited(&app, "pull_configuration", &user_id, args, || {
+        let mut conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let _operation = shutdown::begin(&app, "sync", Some(&path))?;
+        let json = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        let package: ConfigPackage = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid configuration package: {}", e))?;
+        apply_package(&mut conn, &package)
+    })
+}
+
+// Restore the configuration the latest applied package replaced
+#[tauri::command]
+pub fn rollback_configuration(app: AppHandle, user_id: String) -> Result<ConfigVersion, String> {
+    command_audit::audited(
+        &app,
+        "rollback_configuration",
+        &user_id,
+        serde_json::json!({}),
+        || {
+            let mut conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            let (id, previous): (i64, String) = tx
+                .query_row(
+                    "SELECT id, previous FROM config_versions WHERE status = 'APPLIED'
+                     ORDER BY id DESC LIMIT 1",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| "No configuration update to roll back".to_string())?;
+            let previous: ConfigurationBundle =
+                serde_json::from_str(&previous).map_err(|e| e.to_string())?;
+            configuration::apply_bundle(&tx, &previous)?;
+            tx.execute(
+                "UPDATE config_versions SET status = 'ROLLED_BACK',
+                        rolled_back_at = CURRENT_TIMESTAMP, rolled_back_by = ?2
+                 WHERE id = ?1",
+                params![id, user_id],
+            )
+            .map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+            load_version(&conn, id)
+        },
+    )
+}
+
+fn load_version(conn: &Connection, id: i64) -> Result<ConfigVersion, String> {
+    conn.query_row(
+        "SELECT id, version, publisher, published_at, applied_at, status, rolled_back_at
+         FROM config_versions WHERE id = ?1",
+        [id],
+        row_to_version,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn row_to_version(row: &rusqlite::Row) -> rusqlite::Result<ConfigVersion> {
+    Ok(ConfigVersion {
+        id: row.get(0)?,
+        version: row.get(1)?,
+        publisher: row.get(2)?,
+        published_at: row.get(3)?,
+        applied_at: row.get(4)?,
+        status: row.get(5)?,
+        rolled_back_at: row.get(6)?,
+    })
+}
+
+// Applied and rolled back packages, newest first
+#[tauri::command]
+pub fn list_config_versions(app: AppHandle) -> Result<Vec<ConfigVersion>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, version, publisher, published_at, applied_at, status, rolled_back_at
+             FROM config_versions ORDER BY id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_version)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,316 @@
+// Configuration import/export for Truckore Pro
+// Bundles slip templates, printer profiles, tariffs, material and contract
+// rules and general settings into one JSON file so a dealer can set up one
+// weighbridge and copy the result to the next. Secrets and per-site state
+// (site id, counters, lock dates, hardware ports) are never exported.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::snapshots;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use tauri::AppHandle;
+
+const BUNDLE_FORMAT: &str = "truckore-configuration";
+const BUNDLE_VERSION: i64 = 1;
+
+// app_config keys that belong to one install
+const SITE_KEYS: &[&str] = &[
+    "site_id",
+    "setup_completed",
+    "serial_number",
+    "period_lock_date",
+    "training_mode",
+    "scale_config",
+    "installed_version",
+    "crash_upload_opt_in",
+    "telemetry_opt_in",
+    "telemetry_install_id",
+    "indicator_model",
+    "clean_shutdown_at",
+    "storage_alert_at",
+    "master_data_version",
+    "snapshot_watermark",
+    "backup_schedule",
+    "backup_last_run",
+    "thermal_printer",
+    "weighment_signing_key",
+    "rfid_reader",
+    "relay_board",
+    "report_last_run",
+    "anpr_queued_through",
+    "license_key",
+    "license_trial",
+    "lan_server_tls",
+];
+
+// Tables copied whole. Rows from AUTOINCREMENT tables get fresh ids on import.
+const TABLES: &[(&str, Option<&str>)] = &[
+    ("printer_profiles", None),
+    ("tariffs", None),
+    ("material_capture_rules", None),
+    ("deduction_rules", None),
+    ("material_movement_rules", None),
+    ("feature_flags", Some("source = 'local'")),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigurationBundle {
+    pub format: String,
+    pub version: i64,
+    pub exported_at: String,
+    pub settings: BTreeMap<String, String>,
+    pub tables: BTreeMap<String, Vec<serde_json::Map<String, serde_json::Value>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigurationSummary {
+    pub settings: usize,
+    // Rows per table
+    pub tables: BTreeMap<String, usize>,
+}
+
+fn is_secret(key: &str) -> bool {
+    ["secret", "token", "password", "api_key"]
+        .iter()
+        .any(|s| key.contains(s))
+}
+
+fn exportable(key: &str) -> bool {
+    !SITE_KEYS.contains(&key) && !is_secret(key)
+}
+
+// The numbering scheme is shared, the running counter is not
+fn strip_counter(value: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(serde_json::Value::Object(mut config)) => {
+            config.remove("currentCounter");
+            serde_json::Value::Object(config).to_string()
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn merge_counter(conn: &Connection, incoming: &str) -> Result<String, String> {
+    let current = db::get_config(conn, "serial_number_config")?
+        .and_then(|v| serde_json::from_str::<serde_json::Value>(&v).ok())
+        .and_then(|v| v.get("currentCounter").cloned());
+    match (serde_json::from_str::<serde_json::Value>(incoming), current) {
+        (Ok(serde_json::Value::Object(mut config)), Some(counter)) => {
+            config.insert("currentCounter".to_string(), counter);
+            Ok(serde_json::Value::Object(config).to_string())
+        }
+        _ => Ok(incoming.to_string()),
+    }
+}
+
+fn column_names(conn: &Connection, table: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([table], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn is_autoincrement(conn: &Connection, table: &str) -> Result<bool, String> {
+    let sql: String = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(sql.to_ascii_uppercase().contains("AUTOINCREMENT"))
+}
+
+pub fn build_bundle(conn: &Connection) -> Result<ConfigurationBundle, String> {
+    let mut settings = BTreeMap::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT key, value FROM app_config ORDER BY key")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (key, value) = row.map_err(|e| e.to_string())?;
+            if !exportable(&key) {
+                continue;
+            }
+            let value = if key == "serial_number_config" {
+                strip_counter(&value)
+            } else {
+                value
+            };
+            settings.insert(key, value);
+        }
+    }
+
+    let mut tables = BTreeMap::new();
+    for (table, filter) in TABLES {
+        let sql = format!("SELECT * FROM {} WHERE {}", table, filter.unwrap_or("1"));
+        let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let rows = stmt
+            .query_map([], |row| {
+                let mut map = serde_json::Map::new();
+                for (i, name) in columns.iter().enumerate() {
+                    map.insert(name.clone(), crate::sql_to_json_value(row.get_ref(i)?));
+                }
+                Ok(map)
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        tables.insert(table.to_string(), rows);
+    }
+
+    let exported_at = conn
+        .query_row("SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now')", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(ConfigurationBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        exported_at,
+        settings,
+        tables,
+    })
+}
+
+// Replace this install's configuration with the bundle's. Run inside a
+// transaction so a bad file changes nothing.
+pub fn apply_bundle(
+    tx: &Connection,
+    bundle: &ConfigurationBundle,
+) -> Result<ConfigurationSummary, String> {
+    if bundle.format != BUNDLE_FORMAT {
+        return Err("Not a Truckore configuration file".to_string());
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(format!(
+            "Configuration file version {} is newer than this install supports",
+            bundle.version
+        ));
+    }
+
+    let mut summary = ConfigurationSummary {
+        settings: 0,
+        tables: BTreeMap::new(),
+    };
+    for (key, value) in &bundle.settings {
+        // A hand-edited file must not smuggle in site state or secrets
+        if !exportable(key) {
+            continue;
+        }
+        let value = if key == "serial_number_config" {
+            merge_counter(tx, value)?
+        } else {
+            value.clone()
+        };
+        db::set_config(tx, key, &value)?;
+        summary.settings += 1;
+    }
+
+    for (table, filter) in TABLES {
+        let Some(rows) = bundle.tables.get(*table) else {
+            continue;
+        };
+        let known = column_names(tx, table)?;
+        let fresh_ids = is_autoincrement(tx, table)?;
+        tx.execute(
+            &format!("DELETE FROM {} WHERE {}", table, filter.unwrap_or("1")),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        for row in rows {
+            // Columns this install doesn't have are dropped
+            let columns: Vec<&String> = row
+                .keys()
+                .filter(|c| known.contains(c) && !(fresh_ids && c.as_str() == "id"))
+                .collect();
+            if columns.is_empty() {
+                continue;
+            }
+            let sql = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                columns
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            let values: Vec<rusqlite::types::Value> = columns
+                .iter()
+                .map(|c| crate::json_to_sql_value(&row[c.as_str()]))
+                .collect();
+            tx.execute(&sql, rusqlite::params_from_iter(values.iter()))
+                .map_err(|e| format!("{}: {}", table, e))?;
+        }
+        summary.tables.insert(table.to_string(), rows.len());
+    }
+    Ok(summary)
+}
+
+// Write this install's configuration to `path`
+#[tauri::command]
+pub fn export_configuration(
+    app: AppHandle,
+    path: String,
+    user_id: String,
+) -> Result<ConfigurationSummary, String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let bundle = build_bundle(&conn)?;
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| e.to_string())?;
+    Ok(ConfigurationSummary {
+        settings: bundle.settings.len(),
+        tables: bundle
+            .tables
+            .iter()
+            .map(|(t, rows)| (t.clone(), rows.len()))
+            .collect(),
+    })
+}
+
+// Load a configuration file exported from another install (super admin only)
+#[tauri::command]
+pub fn import_configuration(
+    app: AppHandle,
+    file: String,
+    user_id: String,
+) -> Result<ConfigurationSummary, String> {
+    let args = serde_json::json!({ "file": file });
+    command_audit::audited(&app, "import_configuration", &user_id, args, || {
+        let mut conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::SuperAdmin)?;
+        snapshots::take(
+            &app,
+            &conn,
+            "import",
+            Some(format!("Configuration {}", file)),
+            &user_id,
+        )?;
+        let _operation = shutdown::begin(&app, "import", Some(&file))?;
+        let json = fs::read_to_string(&file).map_err(|e| e.to_string())?;
+        let bundle: ConfigurationBundle = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid configuration file: {}", e))?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let summary = apply_bundle(&tx, &bundle)?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(summary)
+    })
+}
@@ -0,0 +1,287 @@
+// Crash reporting for Truckore Pro
+// Backend panics and fatal errors are written to the local crash log (one
+// JSON file each, in crashes/ under the app data directory) with the app
+// version, OS and the last privileged commands run. Files are written
+// directly because the database may be what failed. On the next start they
+// are indexed in crash_reports; sites that opt in queue them for upload,
+// and the sync loop (sync_engine.rs) delivers them to the sync server.
+
+use crate::db;
+use crate::encryption;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+
+const OPT_IN_CONFIG_KEY: &str = "crash_upload_opt_in";
+const BREADCRUMB_LIMIT: usize = 20;
+
+static BREADCRUMBS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    // PANIC or FATAL
+    pub kind: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub thread: Option<String>,
+    pub app_version: String,
+    pub os: String,
+    pub last_commands: Vec<String>,
+    pub backtrace: Option<String>,
+    // Unix seconds
+    pub occurred_at: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CrashReportEntry {
+    pub id: i64,
+    pub file_name: String,
+    pub report: CrashReport,
+    // LOCAL, PENDING_UPLOAD or UPLOADED
+    pub status: String,
+    pub collected_at: String,
+}
+
+// Remember a command for crash context; called for every audited command
+pub fn breadcrumb(command: &str) {
+    if let Ok(mut crumbs) = BREADCRUMBS.lock() {
+        if crumbs.len() == BREADCRUMB_LIMIT {
+            crumbs.pop_front();
+        }
+        crumbs.push_back(command.to_string());
+    }
+}
+
+fn crash_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("crashes"))
+}
+
+fn build_report(
+    app_version: &str,
+    kind: &str,
+    message: String,
+    location: Option<String>,
+) -> CrashReport {
+    CrashReport {
+        kind: kind.to_string(),
+        message,
+        location,
+        thread: std::thread::current().name().map(|n| n.to_string()),
+        app_version: app_version.to_string(),
+        os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        // A poisoned lock still holds the breadcrumbs, which is what we want here
+        last_commands: match BREADCRUMBS.lock() {
+            Ok(crumbs) => crumbs.iter().cloned().collect(),
+            Err(poisoned) => poisoned.into_inner().iter().cloned().collect(),
+        },
+        backtrace: Some(std::backtrace::Backtrace::force_capture().to_string()),
+        occurred_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    }
+}
+
+fn write_report(dir: &PathBuf, report: &CrashReport) {
+    // Nothing can be done if even this fails; never panic inside the hook
+    let _ = fs::create_dir_all(dir);
+    let name = format!(
+        "crash-{}-{}.json",
+        report.occurred_at,
+        uuid::Uuid::new_v4().simple()
+    );
+    if let Ok(json) = serde_json::to_string_pretty(report) {
+        let _ = fs::write(dir.join(name), json);
+    }
+}
+
+// Install the panic hook. The default hook still runs so stderr output is unchanged.
+pub fn install(app: &AppHandle) {
+    let Some(dir) = crash_dir(app) else {
+        return;
+    };
+    let app_version = app.package_info().version.to_string();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panic".to_string());
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+        write_report(
+            &dir,
+            &build_report(&app_version, "PANIC", message, location),
+        );
+        default_hook(info);
+    }));
+}
+
+// Record an error the backend could not recover from
+pub fn report_fatal(app: &AppHandle, context: &str, error: &str) {
+    tracing::error!(context, error, "Fatal error");
+    if let Some(dir) = crash_dir(app) {
+        let version = app.package_info().version.to_string();
+        let report = build_report(&version, "FATAL", format!("{}: {}", context, error), None);
+        write_report(&dir, &report);
+    }
+}
+
+fn opted_in(conn: &Connection) -> Result<bool, String> {
+    Ok(db::get_config(conn, OPT_IN_CONFIG_KEY)?.as_deref() == Some("true"))
+}
+
+// Index crash files written since the last start. Called on startup.
+pub fn collect(app: &AppHandle) -> Result<usize, String> {
+    let Some(dir) = crash_dir(app) else {
+        return Ok(0);
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(0);
+    };
+    let db_path = crate::get_db_path(app)?;
+    if !db_path.exists() {
+        return Ok(0);
+    }
+    let conn =
+        encryption::open_file(app, &db_path, OpenFlags::default()).map_err(|e| e.to_string())?;
+    conn.execute_batch(db::SCHEMA).map_err(|e| e.to_string())?;
+    let status = if opted_in(&conn)? {
+        "PENDING_UPLOAD"
+    } else {
+        "LOCAL"
+    };
+
+    let mut collected = 0;
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !file_name.ends_with(".json") {
+            continue;
+        }
+        // Unreadable files are left for support to inspect by hand
+        let Ok(json) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        if serde_json::from_str::<CrashReport>(&json).is_err() {
+            continue;
+        }
+        collected += conn
+            .execute(
+                "INSERT OR IGNORE INTO crash_reports (file_name, report, status) VALUES (?1, ?2, ?3)",
+                params![file_name, json, status],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(collected)
+}
+
+fn row_to_entry(row: &rusqlite::Row) -> rusqlite::Result<CrashReportEntry> {
+    let json: String = row.get(2)?;
+    Ok(CrashReportEntry {
+        id: row.get(0)?,
+        file_name: row.get(1)?,
+        report: serde_json::from_str(&json).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        status: row.get(3)?,
+        collected_at: row.get(4)?,
+    })
+}
+
+// Collected crash reports, newest first
+#[tauri::command]
+pub fn list_crash_reports(app: AppHandle) -> Result<Vec<CrashReportEntry>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, file_name, report, status, collected_at
+             FROM crash_reports ORDER BY id DESC LIMIT 100",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_entry)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_crash_upload_opt_in(app: AppHandle) -> Result<bool, String> {
+    let conn = db::open(&app)?;
+    opted_in(&conn)
+}
+
+// Opting in queues reports already collected; opting out withdraws queued ones
+#[tauri::command]
+pub fn set_crash_upload_opt_in(app: AppHandle, enabled: bool) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    db::set_config(
+        &conn,
+        OPT_IN_CONFIG_KEY,
+        if enabled { "true" } else { "false" },
+    )?;
+    let (from, to) = if enabled {
+        ("LOCAL", "PENDING_UPLOAD")
+    } else {
+        ("PENDING_UPLOAD", "LOCAL")
+    };
+    conn.execute(
+        "UPDATE crash_reports SET status = ?2 WHERE status = ?1",
+        params![from, to],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Reports queued for upload, oldest first
+pub fn pending(conn: &Connection) -> Result<Vec<CrashReportEntry>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, file_name, report, status, collected_at
+             FROM crash_reports WHERE status = 'PENDING_UPLOAD' ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_entry)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+pub fn mark_uploaded(conn: &Connection, ids: &[i64]) -> Result<(), String> {
+    for id in ids {
+        conn.execute(
+            "UPDATE crash_reports SET status = 'UPLOADED', uploaded_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND status = 'PENDING_UPLOAD'",
+            [id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Reports queued for upload, for the sync layer
+#[tauri::command]
+pub fn pending_crash_uploads(app: AppHandle) -> Result<Vec<CrashReportEntry>, String> {
+    let conn = db::open(&app)?;
+    pending(&conn)
+}
+
+// Called by the sync layer once reports have been delivered
+#[tauri::command]
+pub fn mark_crash_reports_uploaded(app: AppHandle, ids: Vec<i64>) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    mark_uploaded(&conn, &ids)
+}
@@ -0,0 +1,320 @@
+// Party credit limits for Truckore Pro
+// A party's outstanding balance is what its closed tickets were billed (in
+// base currency paise) less the payments taken against them; CREDIT payments
+// are sales on account and do not reduce it. Limits are checked when a
+// ticket is completed.
+
+use crate::command_audit;
+use crate::db::{self, DateRange};
+use crate::masking;
+use crate::money;
+use crate::overrides;
+use crate::roles::{self, Role};
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+const MODES: &[&str] = &["WARN", "BLOCK"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreditLimit {
+    pub party_name: String,
+    pub limit_minor: i64,
+    // "WARN" or "BLOCK"
+    pub mode: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartyCredit {
+    pub party_name: String,
+    pub limit: Option<CreditLimit>,
+    pub outstanding_minor: i64,
+    // Negative when the party is already over its limit
+    pub available_minor: Option<i64>,
+}
+
+// A ticket that took its party over the limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreditBreach {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub party_name: String,
+    pub limit_minor: i64,
+    pub outstanding_minor: i64,
+    pub charge_minor: i64,
+    // "WARNED" or "OVERRIDDEN"
+    pub outcome: String,
+    pub override_id: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OverLimitReport {
+    // Parties over their limit right now
+    pub parties: Vec<PartyCredit>,
+    // Tickets completed over the limit within the range
+    pub breaches: Vec<CreditBreach>,
+}
+
+pub fn load_limit(conn: &Connection, party_name: &str) -> Result<Option<CreditLimit>, String> {
+    conn.query_row(
+        "SELECT party_name, limit_minor, mode FROM party_credit_limits WHERE party_name = ?1",
+        [party_name],
+        |row| {
+            Ok(CreditLimit {
+                party_name: row.get(0)?,
+                limit_minor: row.get(1)?,
+                mode: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Billed minus paid for the party's tickets, leaving out `except_weighment`
+pub fn outstanding(
+    conn: &Connection,
+    party_name: &str,
+    except_weighment: Option<&str>,
+) -> Result<i64, String> {
+    conn.query_row(
+        &format!(
+            "SELECT
+                (SELECT COALESCE(SUM(b.base_amount_minor), 0)
+                 FROM weighment_billing b JOIN weighments w ON w.id = b.weighment_id
+                 WHERE w.party_name = ?1 AND w.id IS NOT ?2
+                   AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {practice})
+              - (SELECT COALESCE(SUM(p.amount_minor), 0)
+                 FROM shift_payments p JOIN weighments w ON w.id = p.weighment_id
+                 WHERE w.party_name = ?1 AND p.method != 'CREDIT'
+                   AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {practice})",
+            practice = training::exclude_practice("w.id")
+        ),
+        params![party_name, except_weighment],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// True when completing this ticket may need a credit override
+pub fn blocks(conn: &Connection, weighment_id: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM weighments w
+         JOIN party_credit_limits l ON l.party_name = w.party_name
+         WHERE w.id = ?1 AND l.mode = 'BLOCK'",
+        [weighment_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Check a ticket being completed against its party's limit. Returns the
+// breach when the outstanding balance plus `charge_minor` exceeds it; a
+// BLOCK limit refuses unless `override_id` carries a granted override.
+pub fn check(
+    conn: &Connection,
+    weighment_id: &str,
+    party_name: &str,
+    charge_minor: i64,
+    override_id: Option<i64>,
+) -> Result<Option<CreditBreach>, String> {
+    if training::is_practice(conn, weighment_id)? {
+        return Ok(None);
+    }
+    let Some(limit) = load_limit(conn, party_name)? else {
+        return Ok(None);
+    };
+    let outstanding_minor = outstanding(conn, party_name, Some(weighment_id))?;
+    if outstanding_minor + charge_minor <= limit.limit_minor {
+        return Ok(None);
+    }
+
+    let outcome = match (limit.mode.as_str(), override_id) {
+        ("BLOCK", None) => {
+            return Err(format!(
+                "{} is over its credit limit: outstanding {} + this ticket {} exceeds {}. \
+                 A supervisor override is required.",
+                party_name,
+                money::format_minor(outstanding_minor),
+                money::format_minor(charge_minor),
+                money::format_minor(limit.limit_minor)
+            ))
+        }
+        ("BLOCK", Some(_)) => "OVERRIDDEN",
+        _ => "WARNED",
+    };
+    let override_id = override_id.filter(|_| outcome == "OVERRIDDEN");
+    conn.execute(
+        "INSERT OR REPLACE INTO credit_limit_breaches
+             (weighment_id, party_name, limit_minor, outstanding_minor, charge_minor,
+              outcome, override_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            weighment_id,
+            party_name,
+            limit.limit_minor,
+            outstanding_minor,
+            charge_minor,
+            outcome,
+            override_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    if let Some(id) = override_id {
+        overrides::mark_used(conn, id)?;
+    }
+
+    conn.query_row(
+        "SELECT b.weighment_id, w.ticket_no, b.party_name, b.limit_minor, b.outstanding_minor,
+                b.charge_minor, b.outcome, b.override_id, b.created_at
+         FROM credit_limit_breaches b JOIN weighments w ON w.id = b.weighment_id
+         WHERE b.weighment_id = ?1",
+        [weighment_id],
+        row_to_breach,
+    )
+    .map(Some)
+    .map_err(|e| e.to_string())
+}
+
+fn row_to_breach(row: &rusqlite::Row) -> rusqlite::Result<CreditBreach> {
+    Ok(CreditBreach {
+        weighment_id: row.get(0)?,
+        ticket_no: row.get(1)?,
+        party_name: row.get(2)?,
+        limit_minor: row.get(3)?,
+        outstanding_minor: row.get(4)?,
+        charge_minor: row.get(5)?,
+        outcome: row.get(6)?,
+        override_id: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+fn party_credit(conn: &Connection, party_name: &str) -> Result<PartyCredit, String> {
+    let limit = load_limit(conn, party_name)?;
+    let outstanding_minor = outstanding(conn, party_name, None)?;
+    Ok(PartyCredit {
+        party_name: party_name.to_string(),
+        available_minor: limit.as_ref().map(|l| l.limit_minor - outstanding_minor),
+        limit,
+        outstanding_minor,
+    })
+}
+
+#[tauri::command]
+pub fn list_credit_limits(app: AppHandle) -> Result<Vec<CreditLimit>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT party_name, limit_minor, mode FROM party_credit_limits ORDER BY party_name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(CreditLimit {
+                party_name: row.get(0)?,
+                limit_minor: row.get(1)?,
+                mode: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Set or, with `limit` omitted, remove a party's credit limit (supervisors only)
+#[tauri::command]
+pub fn set_credit_limit(
+    app: AppHandle,
+    party_name: String,
+    limit: Option<CreditLimit>,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "party_name": party_name, "limit": limit });
+    command_audit::audited(&app, "set_credit_limit", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let Some(limit) = limit else {
+            conn.execute(
+                "DELETE FROM party_credit_limits WHERE party_name = ?1",
+                [&party_name],
+            )
+            .map_err(|e| e.to_string())?;
+            return Ok(());
+        };
+        if !MODES.contains(&limit.mode.as_str()) {
+            return Err(format!("Unknown credit limit mode: {}", limit.mode));
+        }
+        if limit.limit_minor < 0 {
+            return Err("Credit limit cannot be negative".to_string());
+        }
+        conn.execute(
+            "INSERT INTO party_credit_limits (party_name, limit_minor, mode, updated_by)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(party_name) DO UPDATE SET limit_minor = excluded.limit_minor,
+                 mode = excluded.mode, updated_by = excluded.updated_by,
+                 updated_at = CURRENT_TIMESTAMP",
+            params![party_name, limit.limit_minor, limit.mode, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+// Masked for the caller's role, as is over_limit_report
+#[tauri::command]
+pub fn get_party_credit(
+    app: AppHandle,
+    party_name: String,
+    session_token: Option<String>,
+) -> Result<Value, String> {
+    let conn = db::open(&app)?;
+    masking::for_session(&app, session_token.as_deref())?.masked(&party_credit(&conn, &party_name)?)
+}
+
+// Parties currently over their limit, and tickets completed over the limit
+// within the range
+#[tauri::command]
+pub fn over_limit_report(
+    app: AppHandle,
+    range: DateRange,
+    session_token: Option<String>,
+) -> Result<Value, String> {
+    let conn = db::open(&app)?;
+    let names: Vec<String> = conn
+        .prepare("SELECT party_name FROM party_credit_limits ORDER BY party_name")
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut parties = Vec::new();
+    for name in names {
+        let credit = party_credit(&conn, &name)?;
+        if credit.available_minor.is_some_and(|a| a < 0) {
+            parties.push(credit);
+        }
+    }
+
+    let sql = format!(
+        "SELECT b.weighment_id, w.ticket_no, b.party_name, b.limit_minor, b.outstanding_minor,
+                b.charge_minor, b.outcome, b.override_id, b.created_at
+         FROM credit_limit_breaches b JOIN weighments w ON w.id = b.weighment_id
+         WHERE {} BETWEEN ?1 AND ?2
+         ORDER BY b.created_at DESC",
+        db::local_date("b.created_at")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let breaches = stmt
+        .query_map(params![range.from, range.to], row_to_breach)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    masking::for_session(&app, session_token.as_deref())?
+        .masked(&OverLimitReport { parties, breaches })
+}
@@ -0,0 +1,424 @@
+// CSV import for Truckore Pro
+// Loads data exported by other weighbridge programs into vehicles, parties,
+// products, transporters or weighments. The caller maps CSV headers to
+// columns; each row is checked against the table's column types and
+// constraints and inserted inside one transaction. Rows that fail are
+// reported by line and, unless the import is strict, skipped.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::snapshots;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use tauri::{AppHandle, Manager};
+
+// Tables legacy data may be loaded into
+const TARGETS: &[&str] = &[
+    "parties",
+    "products",
+    "transporters",
+    "vehicles",
+    "weighments",
+];
+
+// Progress is reported every this many rows
+const PROGRESS_EVERY: usize = 500;
+
+// Row errors kept in the result; the count covers all of them
+const MAX_ERRORS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    // CSV header
+    pub source: String,
+    // Column in the target table
+    pub column: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvImportRequest {
+    pub path: String,
+    pub table: String,
+    pub mapping: Vec<ColumnMapping>,
+    // Value for columns the file lacks, e.g. status = CLOSED
+    #[serde(default)]
+    pub fixed: HashMap<String, String>,
+    // Defaults to ','
+    pub delimiter: Option<char>,
+    // Roll back everything when any row fails
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvRowError {
+    // 1-based line of the record in the file, header included
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvImportProgress {
+    pub path: String,
+    pub rows_read: usize,
+    pub imported: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvImportResult {
+    pub table: String,
+    pub rows_read: usize,
+    pub imported: usize,
+    pub failed: usize,
+    // First MAX_ERRORS failures
+    pub errors: Vec<CsvRowError>,
+    // Nothing was kept: the import was strict and a row failed
+    pub rolled_back: bool,
+}
+
+// Reads RFC 4180 records, including quoted fields spanning lines
+struct CsvReader<R> {
+    reader: R,
+    delimiter: char,
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    // Next record and the line it started on
+    fn next_record(&mut self) -> Result<Option<(usize, Vec<String>)>, String> {
+        let start = self.line + 1;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut read_any = false;
+        loop {
+            let mut raw = Vec::new();
+            let read = self
+                .reader
+                .read_until(b'\n', &mut raw)
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                if quoted {
+                    return Err(format!("Line {}: unterminated quoted field", start));
+                }
+                if !read_any {
+                    return Ok(None);
+                }
+                fields.push(field);
+                return Ok(Some((start, fields)));
+            }
+            read_any = true;
+            self.line += 1;
+            // Old exports are often not UTF-8; bad bytes become U+FFFD
+            let text = String::from_utf8_lossy(&raw);
+            let text = if self.line == 1 {
+                text.trim_start_matches('\u{feff}')
+            } else {
+                &text
+            };
+            let mut chars = text.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' if quoted => quoted = false,
+                    '"' if field.is_empty() => quoted = true,
+                    c if quoted => field.push(c),
+                    c if c == self.delimiter => fields.push(std::mem::take(&mut field)),
+                    '\r' | '\n' => {}
+                    c => field.push(c),
+                }
+            }
+            if !quoted {
+                fields.push(field);
+                return Ok(Some((start, fields)));
+            }
+        }
+    }
+}
+
+enum Source {
+    Field(usize),
+    Fixed(String),
+}
+
+struct TargetColumn {
+    name: String,
+    declared_type: String,
+    required: bool,
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<(TargetColumn, bool)>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            let not_null: bool = row.get(3)?;
+            let default: Option<String> = row.get(4)?;
+            let primary_key: i64 = row.get(5)?;
+            Ok((
+                TargetColumn {
+                    name: row.get(1)?,
+                    declared_type: row.get::<_, String>(2)?.to_uppercase(),
+                    required: not_null && default.is_none() && primary_key == 0,
+                },
+                primary_key > 0,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// "DD/MM/YYYY", "DD-MM-YYYY" or ISO, with an optional "HH:MM[:SS]", as
+// "YYYY-MM-DD HH:MM:SS"
+fn normalize_datetime(text: &str) -> Option<String> {
+    let (date, time) = match text.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time.trim())),
+        None => (text, None),
+    };
+    let parts: Vec<&str> = date.split(['/', '-', '.']).collect();
+    let [a, b, c] = parts.as_slice() else {
+        return None;
+    };
+    let number = |s: &str| s.parse::<u32>().ok();
+    let (year, month, day) = if a.len() == 4 {
+        (number(a)?, number(b)?, number(c)?)
+    } else if c.len() == 4 {
+        (number(c)?, number(b)?, number(a)?)
+    } else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut out = format!("{:04}-{:02}-{:02}", year, month, day);
+    if let Some(time) = time.filter(|t| !t.is_empty()) {
+        let time = time.split('.').next().unwrap_or(time);
+        let parts: Vec<u32> = time
+            .split(':')
+            .map(|p| p.parse().ok())
+            .collect::<Option<_>>()?;
+        let (hour, minute, second) = match parts.as_slice() {
+            [h, m] => (*h, *m, 0),
+            [h, m, s] => (*h, *m, *s),
+            _ => return None,
+        };
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        out.push_str(&format!(" {:02}:{:02}:{:02}", hour, minute, second));
+    }
+    Some(out)
+}
+
+// CSV text as a value for `column`; empty fields are NULL
+fn convert(column: &TargetColumn, text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return match column.required {
+            true => Err(format!("{} is required", column.name)),
+            false => Ok(Value::Null),
+        };
+    }
+    let kind = column.declared_type.as_str();
+    // Thousands separators are common in exported weights
+    let number = || text.replace(',', "").parse::<f64>().ok();
+    if kind.contains("INT") {
+        return match number() {
+            Some(n) if n.fract() == 0.0 => Ok(Value::Integer(n as i64)),
+            _ => Err(format!("{} must be a whole number: {}", column.name, text)),
+        };
+    }
+    if kind.contains("REAL") {
+        return number()
+            .map(Value::Real)
+            .ok_or_else(|| format!("{} must be a number: {}", column.name, text));
+    }
+    if kind.contains("DATE") || kind.contains("TIME") {
+        return normalize_datetime(text)
+            .map(Value::Text)
+            .ok_or_else(|| format!("{} is not a date: {}", column.name, text));
+    }
+    Ok(Value::Text(text.to_string()))
+}
+
+// Run an import, calling `on_progress` every PROGRESS_EVERY rows and once at
+// the end; an error from it aborts the import and rolls it back
+pub fn import_file(
+    conn: &mut Connection,
+    request: &CsvImportRequest,
+    on_progress: &mut dyn FnMut(&CsvImportProgress) -> Result<(), String>,
+) -> Result<CsvImportResult, String> {
+    if !TARGETS.contains(&request.table.as_str()) {
+        return Err(format!("Cannot import into {}", request.table));
+    }
+    let columns = table_columns(conn, &request.table)?;
+    let file = File::open(&request.path).map_err(|e| e.to_string())?;
+    let mut reader = CsvReader {
+        reader: BufReader::new(file),
+        delimiter: request.delimiter.unwrap_or(','),
+        line: 0,
+    };
+    let Some((_, header)) = reader.next_record()? else {
+        return Err("The file is empty".to_string());
+    };
+
+    let mut sources = Vec::new();
+    for mapping in &request.mapping {
+        let index = header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(mapping.source.trim()))
+            .ok_or_else(|| format!("Column {} is not in the file", mapping.source))?;
+        sources.push((&mapping.column, Source::Field(index)));
+    }
+    for (column, value) in &request.fixed {
+        sources.push((column, Source::Fixed(value.clone())));
+    }
+    let mut mapped: Vec<(Source, &TargetColumn)> = Vec::with_capacity(sources.len());
+    for (name, source) in sources {
+        let column = columns
+            .iter()
+            .map(|(c, _)| c)
+            .find(|c| c.name == *name)
+            .ok_or_else(|| format!("{} has no column {}", request.table, name))?;
+        if mapped.iter().any(|(_, c)| c.name == column.name) {
+            return Err(format!("{} is mapped twice", column.name));
+        }
+        mapped.push((source, column));
+    }
+    if mapped.is_empty() {
+        return Err("No columns mapped".to_string());
+    }
+    if let Some((missing, _)) = columns
+        .iter()
+        .filter(|(c, _)| c.required)
+        .find(|(c, _)| !mapped.iter().any(|(_, m)| m.name == c.name))
+    {
+        return Err(format!("{} must be mapped", missing.name));
+    }
+    // Text ids are generated when the file has none
+    let generate_id = columns
+        .iter()
+        .any(|(c, key)| *key && c.name == "id" && c.declared_type == "TEXT")
+        && !mapped.iter().any(|(_, c)| c.name == "id");
+
+    let mut names: Vec<&str> = mapped.iter().map(|(_, c)| c.name.as_str()).collect();
+    if generate_id {
+        names.push("id");
+    }
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        request.table,
+        names.join(", "),
+        vec!["?"; names.len()].join(", ")
+    );
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut result = CsvImportResult {
+        table: request.table.clone(),
+        rows_read: 0,
+        imported: 0,
+        failed: 0,
+        errors: Vec::new(),
+        rolled_back: false,
+    };
+    {
+        let mut stmt = tx.prepare(&sql).map_err(|e| e.to_string())?;
+        while let Some((line, record)) = reader.next_record()? {
+            if record.iter().all(|f| f.trim().is_empty()) {
+                continue;
+            }
+            result.rows_read += 1;
+            let values = mapped
+                .iter()
+                .map(|(source, column)| match source {
+                    Source::Field(index) => convert(column, record.get(*index).map_or("", |f| f)),
+                    Source::Fixed(value) => convert(column, value),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|mut values| {
+                    if generate_id {
+                        values.push(Value::Text(uuid::Uuid::new_v4().to_string()));
+                    }
+                    // A failed insert only undoes that statement, not the
+                    // transaction
+                    stmt.execute(params_from_iter(values))
+                        .map_err(|e| e.to_string())
+                });
+            match values {
+                Ok(_) => result.imported += 1,
+                Err(message) => {
+                    result.failed += 1;
+                    if result.errors.len() < MAX_ERRORS {
+                        result.errors.push(CsvRowError { line, message });
+                    }
+                }
+            }
+            if result.rows_read % PROGRESS_EVERY == 0 {
+                on_progress(&progress(request, &result))?;
+            }
+        }
+    }
+    on_progress(&progress(request, &result))?;
+
+    if request.strict && result.failed > 0 {
+        result.rolled_back = true;
+        result.imported = 0;
+        return Ok(result);
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+fn progress(request: &CsvImportRequest, result: &CsvImportResult) -> CsvImportProgress {
+    CsvImportProgress {
+        path: request.path.clone(),
+        rows_read: result.rows_read,
+        imported: result.imported,
+        failed: result.failed,
+    }
+}
+
+// Audited import as a supervisor, shared by the command and background tasks
+pub fn run_import(
+    app: &AppHandle,
+    request: &CsvImportRequest,
+    user_id: &str,
+    on_progress: &mut dyn FnMut(&CsvImportProgress) -> Result<(), String>,
+) -> Result<CsvImportResult, String> {
+    let args = serde_json::to_value(request).map_err(|e| e.to_string())?;
+    command_audit::audited(app, "import_csv", user_id, args, || {
+        let mut conn = db::open(app)?;
+        roles::require_role(&conn, user_id, Role::Admin)?;
+        let detail = Some(format!("{} into {}", request.path, request.table));
+        snapshots::take(app, &conn, "import", detail, user_id)?;
+        let _operation = shutdown::begin(app, "import", Some(&request.path))?;
+        import_file(&mut conn, request, on_progress)
+    })
+}
+
+// Import legacy CSV data (supervisors only). Progress is pushed to windows as
+// `csv_import_progress` events.
+#[tauri::command]
+pub fn import_csv(
+    app: AppHandle,
+    request: CsvImportRequest,
+    user_id: String,
+) -> Result<CsvImportResult, String> {
+    run_import(&app, &request, &user_id, &mut |progress| {
+        let _ = app.emit_all("csv_import_progress", progress);
+        Ok(())
+    })
+}
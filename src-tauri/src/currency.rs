@@ -0,0 +1,340 @@
+// Multi-currency billing for Truckore Pro
+// Charges are entered in the base currency (INR unless configured). Parties
+// can be billed in another currency; the exchange rate in force on the day a
+// ticket is completed is copied onto the ticket so later rate entries don't
+// rewrite past bills.
+
+use crate::command_audit;
+use crate::db::{self, DateRange};
+use crate::masking;
+use crate::roles::{self, Role};
+use crate::rounding;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+const BASE_CURRENCY_KEY: &str = "base_currency";
+const DEFAULT_BASE_CURRENCY: &str = "INR";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub id: i64,
+    pub currency: String,
+    // Base currency units per one unit of `currency`
+    pub rate: f64,
+    pub effective_from: String,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeighmentBilling {
+    pub weighment_id: String,
+    pub currency: String,
+    pub exchange_rate: f64,
+    // Paise of the base currency
+    pub base_amount_minor: i64,
+    // Minor units of the billing currency
+    pub billed_amount_minor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartyStatementLine {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub date: String,
+    pub product_name: String,
+    pub net_weight: Option<f64>,
+    pub exchange_rate: f64,
+    pub base_amount_minor: i64,
+    pub billed_amount_minor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartyStatement {
+    pub party_name: String,
+    pub currency: String,
+    pub lines: Vec<PartyStatementLine>,
+    pub total_base_minor: i64,
+    pub total_billed_minor: i64,
+}
+
+pub fn base_currency(conn: &Connection) -> Result<String, String> {
+    Ok(db::get_config(conn, BASE_CURRENCY_KEY)?
+        .unwrap_or_else(|| DEFAULT_BASE_CURRENCY.to_string()))
+}
+
+fn normalize(code: &str) -> Result<String, String> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("Invalid currency code: {}", code));
+    }
+    Ok(code)
+}
+
+// Currency a party is billed in; the base currency unless configured
+pub fn party_currency(conn: &Connection, party_name: &str) -> Result<String, String> {
+    let configured: Option<String> = conn
+        .query_row(
+            "SELECT currency FROM party_billing_currency WHERE party_name = ?1",
+            [party_name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match configured {
+        Some(currency) => Ok(currency),
+        None => base_currency(conn),
+    }
+}
+
+// Rate in force for `currency` on `date` ("YYYY-MM-DD")
+pub fn rate_on(conn: &Connection, currency: &str, date: &str) -> Result<f64, String> {
+    if currency == base_currency(conn)? {
+        return Ok(1.0);
+    }
+    conn.query_row(
+        "SELECT rate FROM exchange_rates
+         WHERE currency = ?1 AND effective_from <= ?2
+         ORDER BY effective_from DESC, id DESC LIMIT 1",
+        params![currency, date],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("No {} exchange rate in force on {}", currency, date))
+}
+
+// Convert the ticket's charges to the party's billing currency and store the result
+pub fn bill_weighment(conn: &Connection, weighment_id: &str) -> Result<WeighmentBilling, String> {
+    let (party_name, charges_minor, date): (String, i64, String) = conn
+        .query_row(
+            &format!(
+                "SELECT party_name, charges_minor, {} FROM weighments WHERE id = ?1",
+                db::local_date("COALESCE(closed_at, created_at)")
+            ),
+            [weighment_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Weighment {} not found", weighment_id))?;
+
+    let currency = party_currency(conn, &party_name)?;
+    let exchange_rate = rate_on(conn, &currency, &date)?;
+    let rounding = rounding::load_rules(conn)?;
+    let base_amount_minor = rounding.amount(charges_minor as f64);
+    let billing = WeighmentBilling {
+        weighment_id: weighment_id.to_string(),
+        currency,
+        exchange_rate,
+        base_amount_minor,
+        billed_amount_minor: rounding.amount(base_amount_minor as f64 / exchange_rate),
+    };
+    conn.execute(
+        "INSERT OR REPLACE INTO weighment_billing
+             (weighment_id, currency, exchange_rate, base_amount_minor, billed_amount_minor)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            billing.weighment_id,
+            billing.currency,
+            billing.exchange_rate,
+            billing.base_amount_minor,
+            billing.billed_amount_minor
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(billing)
+}
+
+pub fn load_billing(
+    conn: &Connection,
+    weighment_id: &str,
+) -> Result<Option<WeighmentBilling>, String> {
+    conn.query_row(
+        "SELECT weighment_id, currency, exchange_rate, base_amount_minor, billed_amount_minor
+         FROM weighment_billing WHERE weighment_id = ?1",
+        [weighment_id],
+        |row| {
+            Ok(WeighmentBilling {
+                weighment_id: row.get(0)?,
+                currency: row.get(1)?,
+                exchange_rate: row.get(2)?,
+                base_amount_minor: row.get(3)?,
+                billed_amount_minor: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_base_currency(app: AppHandle) -> Result<String, String> {
+    let conn = db::open(&app)?;
+    base_currency(&conn)
+}
+
+#[tauri::command]
+pub fn set_base_currency(app: AppHandle, currency: String, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "currency": currency });
+    command_audit::audited(&app, "set_base_currency", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::SuperAdmin)?;
+        db::set_config(&conn, BASE_CURRENCY_KEY, &normalize(&currency)?)
+    })
+}
+
+#[tauri::command]
+pub fn list_exchange_rates(
+    app: AppHandle,
+    currency: Option<String>,
+) -> Result<Vec<ExchangeRate>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, currency, rate, effective_from, created_by, created_at
+             FROM exchange_rates WHERE ?1 IS NULL OR currency = ?1
+             ORDER BY currency, effective_from DESC, id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([currency], |row| {
+            Ok(ExchangeRate {
+                id: row.get(0)?,
+                currency: row.get(1)?,
+                rate: row.get(2)?,
+                effective_from: row.get(3)?,
+                created_by: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Enter a rate effective from a date (admin only). Returns its id.
+#[tauri::command]
+pub fn add_exchange_rate(
+    app: AppHandle,
+    currency: String,
+    rate: f64,
+    effective_from: String,
+    user_id: String,
+) -> Result<i64, String> {
+    let args = serde_json::json!({
+        "currency": currency,
+        "rate": rate,
+        "effective_from": effective_from,
+    });
+    command_audit::audited(&app, "add_exchange_rate", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let currency = normalize(&currency)?;
+        if currency == base_currency(&conn)? {
+            return Err(format!("{} is the base currency", currency));
+        }
+        if rate.is_nan() || rate <= 0.0 {
+            return Err("Exchange rate must be positive".to_string());
+        }
+        conn.execute(
+            "INSERT INTO exchange_rates (currency, rate, effective_from, created_by)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![currency, rate, effective_from, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+#[tauri::command]
+pub fn get_party_currency(app: AppHandle, party_name: String) -> Result<String, String> {
+    let conn = db::open(&app)?;
+    party_currency(&conn, &party_name)
+}
+
+// Set the billing currency for a party; None returns it to the base currency
+#[tauri::command]
+pub fn set_party_currency(
+    app: AppHandle,
+    party_name: String,
+    currency: Option<String>,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    match currency {
+        Some(code) => conn.execute(
+            "INSERT INTO party_billing_currency (party_name, currency) VALUES (?1, ?2)
+             ON CONFLICT(party_name) DO UPDATE SET currency = excluded.currency",
+            params![party_name, normalize(&code)?],
+        ),
+        None => conn.execute(
+            "DELETE FROM party_billing_currency WHERE party_name = ?1",
+            [&party_name],
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_weighment_billing(
+    app: AppHandle,
+    weighment_id: String,
+    session_token: Option<String>,
+) -> Result<Value, String> {
+    let conn = db::open(&app)?;
+    masking::for_session(&app, session_token.as_deref())?
+        .masked(&load_billing(&conn, &weighment_id)?)
+}
+
+// Billed tickets for a party in the range, in the party's billing currency,
+// masked for the caller's role
+#[tauri::command]
+pub fn party_billing_statement(
+    app: AppHandle,
+    party_name: String,
+    range: DateRange,
+    session_token: Option<String>,
+) -> Result<Value, String> {
+    let conn = db::open(&app)?;
+    let currency = party_currency(&conn, &party_name)?;
+    let sql = format!(
+        "SELECT w.id, w.ticket_no, {day}, w.product_name, w.net_weight,
+                b.exchange_rate, b.base_amount_minor, b.billed_amount_minor
+         FROM weighments w JOIN weighment_billing b ON b.weighment_id = w.id
+         WHERE w.party_name = ?1 AND b.currency = ?2 AND {day} BETWEEN ?3 AND ?4
+           AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {practice}
+         ORDER BY w.created_at",
+        day = db::local_date("w.created_at"),
+        practice = training::exclude_practice("w.id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let lines: Vec<PartyStatementLine> = stmt
+        .query_map(params![party_name, currency, range.from, range.to], |row| {
+            Ok(PartyStatementLine {
+                weighment_id: row.get(0)?,
+                ticket_no: row.get(1)?,
+                date: row.get(2)?,
+                product_name: row.get(3)?,
+                net_weight: row.get(4)?,
+                exchange_rate: row.get(5)?,
+                base_amount_minor: row.get(6)?,
+                billed_amount_minor: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    masking::for_session(&app, session_token.as_deref())?.masked(&PartyStatement {
+        total_base_minor: lines.iter().map(|l| l.base_amount_minor).sum(),
+        total_billed_minor: lines.iter().map(|l| l.billed_amount_minor).sum(),
+        party_name,
+        currency,
+        lines,
+    })
+}
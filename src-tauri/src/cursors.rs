@@ -0,0 +1,241 @@
+// Query cursors for Truckore Pro
+// execute_query builds every row before returning, which freezes history
+// screens on large weighment tables. A cursor keeps its statement open on a
+// thread of its own and hands rows over in pages as the UI asks for them;
+// all pages come from the same read snapshot. Cursors left idle for
+// IDLE_TIMEOUT are closed so they do not hold back WAL checkpoints.
+
+use crate::attachments;
+use crate::auditor;
+use crate::db;
+use crate::masking::{self, Masks};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const MAX_PAGE: usize = 10_000;
+
+type Page = Result<CursorPage, String>;
+
+// Page size and where to send the page
+type PageRequest = (usize, Sender<Page>);
+
+// Columns and total, once the statement is open
+type Opened = Result<(Vec<String>, Option<i64>), String>;
+
+// Managed state: open cursors, each a channel to its reader thread
+#[derive(Default)]
+pub struct QueryCursors(Mutex<HashMap<String, Sender<PageRequest>>>);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CursorInfo {
+    pub cursor_id: String,
+    pub columns: Vec<String>,
+    // Only counted when asked for
+    pub total: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CursorPage {
+    pub rows: Vec<serde_json::Value>,
+    // No rows left; the cursor has been closed
+    pub done: bool,
+}
+
+// Reader thread: opens the statement, reports its columns, then serves pages.
+// An auditor's cursor reads on a read-only connection and logs its pages.
+#[allow(clippy::too_many_arguments)]
+fn serve(
+    app: AppHandle,
+    query: String,
+    params: Vec<rusqlite::types::Value>,
+    count_total: bool,
+    masks: Masks,
+    auditor_id: Option<String>,
+    opened: Sender<Opened>,
+    requests: Receiver<PageRequest>,
+) {
+    let conn = match db::open_reader(&app, auditor_id.is_some()) {
+        Ok(conn) => conn,
+        Err(e) => {
+            let _ = opened.send(Err(e));
+            return;
+        }
+    };
+    let mut stmt = match masks.prepare(&conn, || conn.prepare(&query)) {
+        Ok(stmt) if stmt.readonly() => stmt,
+        Ok(_) => {
+            let _ = opened.send(Err("Cursors are for read-only queries".to_string()));
+            return;
+        }
+        Err(e) => {
+            let _ = opened.send(Err(e.to_string()));
+            return;
+        }
+    };
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let total = match count_total {
+        true => match masks
+            .prepare(&conn, || {
+                conn.prepare(&format!("SELECT COUNT(*) FROM ({})", query))
+            })
+            .and_then(|mut count| {
+                count.query_row(rusqlite::params_from_iter(params.iter()), |row| row.get(0))
+            }) {
+            Ok(total) => Some(total),
+            Err(e) => {
+                let _ = opened.send(Err(e.to_string()));
+                return;
+            }
+        },
+        false => None,
+    };
+    let mut rows = match stmt.query(rusqlite::params_from_iter(params.iter())) {
+        Ok(rows) => rows,
+        Err(e) => {
+            let _ = opened.send(Err(e.to_string()));
+            return;
+        }
+    };
+    if opened.send(Ok((columns.clone(), total))).is_err() {
+        return;
+    }
+
+    loop {
+        let (count, reply) = match requests.recv_timeout(IDLE_TIMEOUT) {
+            Ok(request) => request,
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return,
+        };
+        let mut page = CursorPage {
+            rows: Vec::with_capacity(count),
+            done: false,
+        };
+        let mut failed = None;
+        while page.rows.len() < count {
+            match rows.next() {
+                Ok(Some(row)) => {
+                    let mut map = serde_json::Map::new();
+                    for (i, name) in columns.iter().enumerate() {
+                        let value = row
+                            .get_ref(i)
+                            .map(crate::sql_to_json_value)
+                            .unwrap_or(serde_json::Value::Null);
+                        map.insert(name.clone(), value);
+                    }
+                    let mut value = serde_json::Value::Object(map);
+                    masks.apply(&mut value);
+                    attachments::inline_references(&app, &conn, &mut value);
+                    page.rows.push(value);
+                }
+                Ok(None) => {
+                    page.done = true;
+                    break;
+                }
+                Err(e) => {
+                    failed = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        if let (Some(auditor_id), None) = (&auditor_id, &failed) {
+            failed = auditor::log_rows(&app, auditor_id, "fetch_next", &page.rows).err();
+        }
+        let finished = page.done || failed.is_some();
+        let _ = reply.send(failed.map_or(Ok(page), Err));
+        if finished {
+            return;
+        }
+    }
+}
+
+// Open a cursor over a SELECT query. With `count_total` the result carries
+// the number of rows the query returns. Rows are masked for the session,
+// see masking.rs.
+#[tauri::command]
+pub fn open_query_cursor(
+    app: AppHandle,
+    cursors: State<'_, QueryCursors>,
+    session_token: Option<String>,
+    query: String,
+    params: Vec<serde_json::Value>,
+    count_total: Option<bool>,
+) -> Result<CursorInfo, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?.for_raw_sql();
+    let auditor_id = auditor::session_auditor(&app, session_token.as_deref())?;
+    let params = params.iter().map(crate::json_to_sql_value).collect();
+    let (opened_tx, opened_rx) = mpsc::channel();
+    let (requests_tx, requests_rx) = mpsc::channel();
+    let reader_app = app.clone();
+    std::thread::spawn(move || {
+        serve(
+            reader_app,
+            query,
+            params,
+            count_total.unwrap_or(false),
+            masks,
+            auditor_id,
+            opened_tx,
+            requests_rx,
+        )
+    });
+    let (columns, total) = opened_rx.recv().map_err(|e| e.to_string())??;
+
+    let cursor_id = uuid::Uuid::new_v4().to_string();
+    cursors
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(cursor_id.clone(), requests_tx);
+    Ok(CursorInfo {
+        cursor_id,
+        columns,
+        total,
+    })
+}
+
+// Up to `n` more rows. The cursor closes itself after its last page.
+#[tauri::command]
+pub fn fetch_next(
+    cursors: State<'_, QueryCursors>,
+    cursor_id: String,
+    n: usize,
+) -> Result<CursorPage, String> {
+    if !(1..=MAX_PAGE).contains(&n) {
+        return Err(format!("Page size must be between 1 and {}", MAX_PAGE));
+    }
+    let requests = cursors
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&cursor_id)
+        .cloned()
+        .ok_or_else(|| format!("Cursor {} is not open", cursor_id))?;
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let page = requests
+        .send((n, reply_tx))
+        .ok()
+        .and_then(|_| reply_rx.recv().ok())
+        .unwrap_or_else(|| Err(format!("Cursor {} expired", cursor_id)));
+    if !matches!(page, Ok(CursorPage { done: false, .. })) {
+        if let Ok(mut open) = cursors.0.lock() {
+            open.remove(&cursor_id);
+        }
+    }
+    page
+}
+
+// Release a cursor before reading all of it
+#[tauri::command]
+pub fn close_cursor(cursors: State<'_, QueryCursors>, cursor_id: String) -> Result<(), String> {
+    // Dropping the sender ends the reader thread
+    cursors
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .remove(&cursor_id);
+    Ok(())
+}
@@ -0,0 +1,176 @@
+// Dashboard figures for Truckore Pro
+// The dashboard used to pull every ticket in its range and add them up in
+// the window, which meant megabytes over IPC on a busy site. The figures are
+// now SQL aggregates over the same tickets the summary report counts
+// (closed, not voided, not practice), returned as one small struct, masked
+// for the caller's role (masking.rs). Results are cached per role and range
+// and kept only while the data version (data_version.rs) is unchanged, so any
+// committed write makes the next call recompute.
+
+use crate::data_version;
+use crate::db::{self, DateRange};
+use crate::masking;
+use crate::reports::{self, SummaryTotal};
+use crate::training;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+// Entries in each top-N list
+const TOP: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourlyTrend {
+    // Local hour of day, 0 - 23, summed over every day in the range
+    pub hour: u32,
+    pub trips: i64,
+    pub net_weight_kg: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardStats {
+    pub range: DateRange,
+    pub trips: i64,
+    pub net_weight_kg: f64,
+    pub amount_minor: i64,
+    // Trucks between first and second weighing now, whatever the range
+    pub open_tickets: i64,
+    pub top_materials: Vec<SummaryTotal>,
+    pub top_customers: Vec<SummaryTotal>,
+    // All 24 hours, empty ones included
+    pub hourly: Vec<HourlyTrend>,
+    // Data version the figures reflect
+    pub data_version: u64,
+}
+
+// Role, from and to
+type CacheKey = (String, String, String);
+
+// Managed state: masked figures by role and range, with the data version
+// they were computed at
+#[derive(Default)]
+pub struct DashboardCache(Mutex<HashMap<CacheKey, (u64, Value)>>);
+
+fn hourly(conn: &Connection, range: &DateRange) -> Result<Vec<HourlyTrend>, String> {
+    let sql = format!(
+        "SELECT CAST(strftime('%H', w.created_at, 'localtime') AS INTEGER), COUNT(*),
+                COALESCE(SUM(w.net_weight), 0)
+         FROM weighments w
+         WHERE w.net_weight IS NOT NULL AND {} BETWEEN ?1 AND ?2
+           AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}
+         GROUP BY 1",
+        db::local_date("w.created_at"),
+        training::exclude_practice("w.id")
+    );
+    let mut hours: Vec<HourlyTrend> = (0..24)
+        .map(|hour| HourlyTrend {
+            hour,
+            trips: 0,
+            net_weight_kg: 0.0,
+        })
+        .collect();
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (hour, trips, net_weight_kg) = row.map_err(|e| e.to_string())?;
+        if let Some(bucket) = hours.get_mut(hour as usize) {
+            bucket.trips = trips;
+            bucket.net_weight_kg = net_weight_kg;
+        }
+    }
+    Ok(hours)
+}
+
+pub fn stats(conn: &Connection, range: DateRange, version: u64) -> Result<DashboardStats, String> {
+    let sql = format!(
+        "SELECT COUNT(*), COALESCE(SUM(w.net_weight), 0), COALESCE(SUM(w.charges_minor), 0)
+         FROM weighments w
+         WHERE w.net_weight IS NOT NULL AND {} BETWEEN ?1 AND ?2
+           AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}",
+        db::local_date("w.created_at"),
+        training::exclude_practice("w.id")
+    );
+    let (trips, net_weight_kg, amount_minor) = conn
+        .query_row(&sql, params![range.from, range.to], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, f64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    let open_tickets = conn
+        .query_row(
+            &format!(
+                "SELECT COUNT(*) FROM weighments w WHERE w.status = 'OPEN' AND {}",
+                training::exclude_practice("w.id")
+            ),
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut top_materials = reports::totals(conn, "w.product_name", &range)?;
+    top_materials.truncate(TOP);
+    let mut top_customers = reports::totals(conn, "w.party_name", &range)?;
+    top_customers.truncate(TOP);
+    Ok(DashboardStats {
+        trips,
+        net_weight_kg,
+        amount_minor,
+        open_tickets,
+        top_materials,
+        top_customers,
+        hourly: hourly(conn, &range)?,
+        range,
+        data_version: version,
+    })
+}
+
+// Figures for `range`, today when omitted
+#[tauri::command]
+pub fn get_dashboard_stats(
+    app: AppHandle,
+    cache: State<'_, DashboardCache>,
+    range: Option<DateRange>,
+    session_token: Option<String>,
+) -> Result<Value, String> {
+    let conn = db::open(&app)?;
+    let role = masking::session_role(&app, session_token.as_deref())?;
+    let range = match range {
+        Some(range) => range,
+        None => {
+            let today: String = conn
+                .query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            DateRange {
+                from: today.clone(),
+                to: today,
+            }
+        }
+    };
+    let key = (role, range.from.clone(), range.to.clone());
+    // Read before querying: a write committed meanwhile leaves the entry stale
+    let version = data_version::current(&app);
+    if let Some((cached_version, cached)) = cache.0.lock().map_err(|e| e.to_string())?.get(&key) {
+        if *cached_version == version {
+            return Ok(cached.clone());
+        }
+    }
+    let stats = masking::for_role(&conn, &key.0)?.masked(&stats(&conn, range, version)?)?;
+    let mut entries = cache.0.lock().map_err(|e| e.to_string())?;
+    // Entries from older versions are of no further use
+    entries.retain(|_, (cached_version, _)| *cached_version == version);
+    entries.insert(key, (version, stats.clone()));
+    Ok(stats)
+}
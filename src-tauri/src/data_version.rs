@@ -0,0 +1,84 @@
+// Read-after-write consistency for Truckore Pro
+// Every commit that changes rows advances one data version for the app.
+// Write commands return it, and `data://changed` events carry it, so the
+// frontend can tag its cached lists with the version they reflect. Read
+// commands take it back as `min_version` and wait briefly until that
+// version is committed, so a refresh issued right after a write always
+// includes it. Versions start from the launch time in microseconds, so
+// those handed out by a previous run are never ahead.
+
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+// Longest a read waits for a version it was asked for
+const READ_WAIT: Duration = Duration::from_secs(2);
+
+// Managed state: the latest committed version
+pub struct DataVersion {
+    version: Mutex<u64>,
+    advanced: Condvar,
+}
+
+impl Default for DataVersion {
+    fn default() -> Self {
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as u64);
+        DataVersion {
+            version: Mutex::new(start),
+            advanced: Condvar::new(),
+        }
+    }
+}
+
+// Called by the change feed for each commit that changed rows
+pub fn advance(app: &AppHandle) -> u64 {
+    let Some(state) = app.try_state::<DataVersion>() else {
+        return 0;
+    };
+    let Ok(mut version) = state.version.lock() else {
+        return 0;
+    };
+    *version += 1;
+    state.advanced.notify_all();
+    *version
+}
+
+pub fn current(app: &AppHandle) -> u64 {
+    app.try_state::<DataVersion>()
+        .and_then(|state| state.version.lock().ok().map(|v| *v))
+        .unwrap_or(0)
+}
+
+// Hold a read until `min_version` is committed; reads without one go ahead
+pub fn wait_for(app: &AppHandle, min_version: Option<u64>) -> Result<(), String> {
+    let Some(min_version) = min_version else {
+        return Ok(());
+    };
+    let state = app.state::<DataVersion>();
+    let deadline = Instant::now() + READ_WAIT;
+    let mut version = state.version.lock().map_err(|e| e.to_string())?;
+    while *version < min_version {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(format!(
+                "Data version {} is not committed (now {}); reload the data",
+                min_version, *version
+            ));
+        }
+        version = state
+            .advanced
+            .wait_timeout(version, left)
+            .map_err(|e| e.to_string())?
+            .0;
+    }
+    Ok(())
+}
+
+// The latest committed version, for writes made by commands that do not
+// return one
+#[tauri::command]
+pub fn get_data_version(app: AppHandle) -> Result<u64, String> {
+    Ok(current(&app))
+}
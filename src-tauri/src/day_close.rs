@@ -0,0 +1,343 @@
+// End-of-day closing for Truckore Pro
+// close_day runs the closing checklist in order and stops at the first step
+// that fails: no ticket dated on or before the day may still be open (the
+// supervisor closes or voids them, or lets close_day do it with a reason),
+// a verified backup is taken, the day's register is saved as a PDF beside
+// the database and optionally printed and mailed, and the period is locked
+// up to the day. Printing and mailing only add warnings when they fail,
+// since the register is kept either way. The closing is recorded in
+// day_closings with who closed the day.
+
+use crate::backup_schedule;
+use crate::command_audit;
+use crate::db::{self, DateRange};
+use crate::formatting;
+use crate::period_lock;
+use crate::report_schedule;
+use crate::reports;
+use crate::roles::{self, Role};
+use crate::stale_tickets;
+use crate::thermal_printer;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CloseDayOptions {
+    // "CLOSE" or "VOID" the tickets still open; refused while any are when unset
+    pub resolve_open: Option<String>,
+    // Required with resolve_open
+    pub reason: Option<String>,
+    // Print the register totals on the receipt printer
+    pub print: bool,
+    // Mail the register to the report schedule's recipients
+    pub email: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenTicket {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DayClosing {
+    pub date: String,
+    pub closed_by: String,
+    pub closed_at: String,
+    pub tickets: i64,
+    pub net_weight_kg: f64,
+    pub amount_minor: i64,
+    // Open tickets close_day closed or voided
+    pub resolved_open: i64,
+    pub backup_path: Option<String>,
+    pub register_path: Option<String>,
+    pub printed: bool,
+    pub emailed_to: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DayCloseChecklist {
+    pub date: String,
+    pub closing: Option<DayClosing>,
+    pub open_tickets: Vec<OpenTicket>,
+    pub locked_up_to: Option<String>,
+    pub backup_directory: String,
+    pub printer_configured: bool,
+    pub email_recipients: Vec<String>,
+}
+
+const CLOSING_COLUMNS: &str = "date, closed_by, closed_at, tickets, net_weight_kg, amount_minor,
+     resolved_open, backup_path, register_path, printed, emailed_to, warnings";
+
+fn row_to_closing(row: &rusqlite::Row) -> rusqlite::Result<DayClosing> {
+    let emailed_to: String = row.get(10)?;
+    let warnings: String = row.get(11)?;
+    Ok(DayClosing {
+        date: row.get(0)?,
+        closed_by: row.get(1)?,
+        closed_at: row.get(2)?,
+        tickets: row.get(3)?,
+        net_weight_kg: row.get(4)?,
+        amount_minor: row.get(5)?,
+        resolved_open: row.get(6)?,
+        backup_path: row.get(7)?,
+        register_path: row.get(8)?,
+        printed: row.get(9)?,
+        emailed_to: serde_json::from_str(&emailed_to).unwrap_or_default(),
+        warnings: serde_json::from_str(&warnings).unwrap_or_default(),
+    })
+}
+
+fn load_closing(conn: &Connection, date: &str) -> Result<Option<DayClosing>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM day_closings WHERE date = ?1",
+            CLOSING_COLUMNS
+        ),
+        [date],
+        row_to_closing,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Tickets weighed in on or before `date` and never weighed out
+fn open_tickets(conn: &Connection, date: &str) -> Result<Vec<OpenTicket>, String> {
+    let sql = format!(
+        "SELECT id, ticket_no, vehicle_no, created_at FROM weighments
+         WHERE status = 'OPEN' AND {} <= ?1
+           AND id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}
+         ORDER BY created_at",
+        db::local_date("created_at"),
+        training::exclude_practice("id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([date], |row| {
+            Ok(OpenTicket {
+                weighment_id: row.get(0)?,
+                ticket_no: row.get(1)?,
+                vehicle_no: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn check_date(conn: &Connection, date: &str) -> Result<(), String> {
+    period_lock::validate_date(conn, date)?;
+    let future: bool = conn
+        .query_row("SELECT ?1 > date('now', 'localtime')", [date], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    if future {
+        return Err(format!("{} has not happened yet", date));
+    }
+    Ok(())
+}
+
+fn register_path(app: &AppHandle, date: &str) -> Result<PathBuf, String> {
+    Ok(crate::get_db_path(app)?
+        .parent()
+        .ok_or("Failed to resolve the data directory")?
+        .join("closings")
+        .join(format!("register-{}.pdf", date)))
+}
+
+// Clear the tickets still open, or refuse to close the day while they are
+fn resolve_open(
+    conn: &mut Connection,
+    date: &str,
+    options: &CloseDayOptions,
+    user_id: &str,
+) -> Result<i64, String> {
+    let open = open_tickets(conn, date)?;
+    if open.is_empty() {
+        return Ok(0);
+    }
+    let Some(action) = options.resolve_open.as_deref() else {
+        let numbers: Vec<&str> = open.iter().map(|t| t.ticket_no.as_str()).collect();
+        return Err(format!(
+            "{} ticket(s) are still open ({}); close or void them before closing the day",
+            open.len(),
+            numbers.join(", ")
+        ));
+    };
+    if action != "CLOSE" && action != "VOID" {
+        return Err(format!("Unknown action: {}", action));
+    }
+    let reason = options.reason.as_deref().unwrap_or("").trim();
+    if reason.is_empty() {
+        return Err("A reason is required to resolve open tickets".to_string());
+    }
+    for ticket in &open {
+        stale_tickets::resolve_one(conn, &ticket.weighment_id, action, reason, user_id)
+            .map_err(|e| format!("Ticket {}: {}", ticket.ticket_no, e))?;
+    }
+    Ok(open.len() as i64)
+}
+
+fn run_close(
+    app: &AppHandle,
+    date: &str,
+    options: &CloseDayOptions,
+    user_id: &str,
+) -> Result<DayClosing, String> {
+    let mut conn = db::open(app)?;
+    roles::require_role(&conn, user_id, Role::Admin)?;
+    check_date(&conn, date)?;
+    if load_closing(&conn, date)?.is_some() {
+        return Err(format!("{} is already closed", date));
+    }
+
+    let resolved_open = resolve_open(&mut conn, date, options, user_id)?;
+    drop(conn);
+
+    let schedule = {
+        let conn = db::open(app)?;
+        backup_schedule::load_schedule(&conn)?
+    };
+    let backup = backup_schedule::run_backup(app, &schedule, date)?;
+
+    let conn = db::open(app)?;
+    let range = DateRange {
+        from: date.to_string(),
+        to: date.to_string(),
+    };
+    let summary = reports::summary(&conn, range)?;
+    let path = register_path(app, date)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let format = formatting::load(&conn)?;
+    fs::write(&path, reports::summary_pdf(&summary, &format)).map_err(|e| e.to_string())?;
+
+    let mut warnings = Vec::new();
+    let mut printed = false;
+    if options.print {
+        let rows = vec![
+            ("Date".to_string(), formatting::date(date, &format)),
+            ("Tickets".to_string(), summary.total.tickets.to_string()),
+            (
+                "Net weight".to_string(),
+                formatting::weight(summary.total.net_weight_kg, &format),
+            ),
+            (
+                "Amount".to_string(),
+                formatting::amount(summary.total.amount_minor, &format),
+            ),
+        ];
+        let printer = thermal_printer::load_settings(&conn)?;
+        match thermal_printer::print_summary(&printer, "DAILY REGISTER", &rows) {
+            Ok(()) => printed = true,
+            Err(e) => warnings.push(format!("Register not printed: {}", e)),
+        }
+    }
+    let mut emailed_to = Vec::new();
+    if options.email {
+        let mail = report_schedule::load_schedule(&conn)?;
+        if mail.recipients.is_empty() {
+            warnings.push("Register not mailed: no report recipients are configured".to_string());
+        } else {
+            match report_schedule::mail_register(app, &conn, &mail, date) {
+                Ok(()) => emailed_to = mail.recipients.clone(),
+                Err(e) => warnings.push(format!("Register not mailed: {}", e)),
+            }
+        }
+    }
+
+    // The lock may already cover the day, e.g. when a later day closed first
+    if period_lock::current_lock(&conn)?.map_or(true, |lock| lock.as_str() < date) {
+        period_lock::lock_up_to(&conn, date, user_id)?;
+    }
+
+    let register = path.to_string_lossy().to_string();
+    conn.execute(
+        "INSERT INTO day_closings (date, closed_by, tickets, net_weight_kg, amount_minor,
+             resolved_open, backup_path, register_path, printed, emailed_to, warnings)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            date,
+            user_id,
+            summary.total.tickets,
+            summary.total.net_weight_kg,
+            summary.total.amount_minor,
+            resolved_open,
+            backup.path,
+            register,
+            printed,
+            serde_json::to_string(&emailed_to).map_err(|e| e.to_string())?,
+            serde_json::to_string(&warnings).map_err(|e| e.to_string())?,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    load_closing(&conn, date)?.ok_or_else(|| format!("Closing of {} was not recorded", date))
+}
+
+// What closing `date` involves: tickets still open, the lock, where the
+// backup goes and where the register can be sent
+#[tauri::command]
+pub fn day_close_checklist(app: AppHandle, date: String) -> Result<DayCloseChecklist, String> {
+    let conn = db::open(&app)?;
+    check_date(&conn, &date)?;
+    let backup = backup_schedule::load_schedule(&conn)?;
+    let printer = thermal_printer::load_settings(&conn)?;
+    Ok(DayCloseChecklist {
+        closing: load_closing(&conn, &date)?,
+        open_tickets: open_tickets(&conn, &date)?,
+        locked_up_to: period_lock::current_lock(&conn)?,
+        backup_directory: backup_schedule::backup_dir(&app, &backup)?
+            .to_string_lossy()
+            .to_string(),
+        printer_configured: thermal_printer::is_configured(&printer),
+        email_recipients: report_schedule::load_schedule(&conn)?.recipients,
+        date,
+    })
+}
+
+// Close `date` (supervisors only)
+#[tauri::command]
+pub async fn close_day(
+    app: AppHandle,
+    date: String,
+    options: Option<CloseDayOptions>,
+    user_id: String,
+) -> Result<DayClosing, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let options = options.unwrap_or_default();
+        let args = serde_json::json!({ "date": date, "options": options });
+        command_audit::audited(&app, "close_day", &user_id, args, || {
+            run_close(&app, &date, &options, &user_id)
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Closed days in the range, newest first
+#[tauri::command]
+pub fn list_day_closings(app: AppHandle, range: DateRange) -> Result<Vec<DayClosing>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM day_closings WHERE date BETWEEN ?1 AND ?2 ORDER BY date DESC",
+            CLOSING_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], row_to_closing)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,319 @@
+// Shared database helpers for backend modules
+// Writes that go through `write` are queued to one writer thread, and one
+// that still finds the database busy (another PC, or a module writing on its
+// own connection) is tried again with backoff rather than failing with
+// "database is locked". That covers the frontend's raw SQL writes
+// (execute_non_query, execute_batch, execute_transaction), ticket number
+// allocation, shift changes and restores. Other commands write on their own
+// pooled connection: they wait up to BUSY_TIMEOUT for the lock, ticket
+// writes take it up front with an immediate transaction, and a lock still
+// held after that comes back as a Busy error the frontend can retry.
+
+use crate::change_feed;
+use crate::encryption;
+use crate::errors::{AppError, ErrorCode};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+// Idle connections kept for reuse; more can be open while commands run
+const POOL_SIZE: usize = 8;
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+// The writer waits less per attempt and retries instead, doubling the pause
+// from WRITE_BACKOFF: about 6 s in all before it gives up
+const WRITER_BUSY_TIMEOUT: Duration = Duration::from_millis(250);
+const WRITE_ATTEMPTS: u32 = 7;
+const WRITE_BACKOFF: Duration = Duration::from_millis(50);
+
+// Inclusive calendar-date range ("YYYY-MM-DD") used by report style commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DateRange {
+    pub from: String,
+    pub to: String,
+}
+
+// Full schema, safe to re-apply (CREATE ... IF NOT EXISTS throughout)
+pub const SCHEMA: &str = include_str!("../../src/services/database/schema.sql");
+
+// Managed state: idle connections, tagged with the database file they belong
+// to so switching profiles never hands out a connection to the wrong one
+#[derive(Default)]
+pub struct DbPool(Mutex<Vec<(PathBuf, Connection)>>);
+
+impl DbPool {
+    // Close every idle connection, e.g. before the database file is replaced
+    pub fn close_all(&self) {
+        if let Ok(mut idle) = self.0.lock() {
+            idle.clear();
+        }
+    }
+}
+
+// A connection borrowed from the pool; returned to it when dropped
+pub struct PooledConnection {
+    app: AppHandle,
+    path: PathBuf,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection taken")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection taken")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        // A connection left inside a transaction is closed, which rolls it back
+        if !conn.is_autocommit() {
+            return;
+        }
+        if let Some(pool) = self.app.try_state::<DbPool>() {
+            if let Ok(mut idle) = pool.0.lock() {
+                if idle.len() < POOL_SIZE {
+                    idle.push((self.path.clone(), conn));
+                }
+            }
+        }
+    }
+}
+
+fn connect(app: &AppHandle, path: &Path) -> Result<Connection, String> {
+    let conn = encryption::open_file(app, path, OpenFlags::default()).map_err(|e| e.to_string())?;
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(|e| e.to_string())?;
+    // Readers no longer block the writer; the setting is stored in the file
+    conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))
+        .map_err(|e| e.to_string())?;
+    change_feed::attach(app, &conn);
+    Ok(conn)
+}
+
+// Connection to the active profile's database, reused from the pool when one
+// is idle. The database key, WAL mode, a busy timeout and the change feed
+// hooks are set once per connection.
+pub fn open(app: &AppHandle) -> Result<PooledConnection, String> {
+    let path = crate::get_db_path(app)?;
+    let reused = app.try_state::<DbPool>().and_then(|pool| {
+        let mut idle = pool.0.lock().ok()?;
+        let index = idle.iter().position(|(p, _)| *p == path)?;
+        Some(idle.swap_remove(index).1)
+    });
+    let conn = match reused {
+        Some(conn) => conn,
+        None => connect(app, &path)?,
+    };
+    Ok(PooledConnection {
+        app: app.clone(),
+        path,
+        conn: Some(conn),
+    })
+}
+
+// A connection that cannot write, for the reads of an auditor's session
+// (auditor.rs). Not pooled: it is opened for the read and closed after.
+pub fn open_read_only(app: &AppHandle) -> Result<Connection, String> {
+    let path = crate::get_db_path(app)?;
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let conn = encryption::open_file(app, &path, flags).map_err(|e| e.to_string())?;
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(|e| e.to_string())?;
+    conn.execute_batch("PRAGMA query_only = ON")
+        .map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+// A session's read connection: from the pool, or read-only for an auditor
+pub enum Reader {
+    Pooled(PooledConnection),
+    ReadOnly(Connection),
+}
+
+impl Deref for Reader {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            Reader::Pooled(conn) => conn,
+            Reader::ReadOnly(conn) => conn,
+        }
+    }
+}
+
+pub fn open_reader(app: &AppHandle, read_only: bool) -> Result<Reader, String> {
+    match read_only {
+        true => open_read_only(app).map(Reader::ReadOnly),
+        false => open(app).map(Reader::Pooled),
+    }
+}
+
+type WriteJob = Box<dyn FnOnce() + Send>;
+
+// Managed state: the queue into the writer thread, started on first use
+#[derive(Default)]
+pub struct WriteQueue(Mutex<Option<mpsc::Sender<WriteJob>>>);
+
+thread_local! {
+    // Set on the writer thread, where a nested write runs in place
+    static ON_WRITER: Cell<bool> = const { Cell::new(false) };
+}
+
+fn spawn_writer() -> Result<mpsc::Sender<WriteJob>, String> {
+    let (tx, rx) = mpsc::channel::<WriteJob>();
+    std::thread::Builder::new()
+        .name("db-writer".to_string())
+        .spawn(move || {
+            ON_WRITER.with(|on| on.set(true));
+            for job in rx {
+                // A panicking write loses its own answer, not the queue
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+        })
+        .map_err(|e| format!("Failed to start the database writer: {}", e))?;
+    Ok(tx)
+}
+
+fn is_busy(message: &str) -> bool {
+    AppError::classify(message).code == ErrorCode::Busy
+}
+
+// One attempt: `f` in an immediate transaction, committed when it succeeds
+fn attempt_write<T, E, F>(conn: &mut Connection, f: &mut F) -> Result<T, E>
+where
+    E: From<String>,
+    F: FnMut(&Transaction) -> Result<T, E>,
+{
+    conn.busy_timeout(WRITER_BUSY_TIMEOUT)
+        .map_err(|e| e.to_string())?;
+    let result = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|e| E::from(e.to_string()))
+        .and_then(|tx| {
+            let value = f(&tx)?;
+            tx.commit().map_err(|e| e.to_string())?;
+            Ok(value)
+        });
+    // Back in the pool with the usual timeout
+    let _ = conn.busy_timeout(BUSY_TIMEOUT);
+    result
+}
+
+fn run_write<T, E, F>(app: &AppHandle, f: &mut F) -> Result<T, E>
+where
+    E: From<String> + fmt::Display,
+    F: FnMut(&Transaction) -> Result<T, E>,
+{
+    let mut conn = open(app)?;
+    write_on(&mut conn, f)
+}
+
+// The writer's transaction and retries on a connection of the caller's
+pub(crate) fn write_on<T, E, F>(conn: &mut Connection, f: &mut F) -> Result<T, E>
+where
+    E: From<String> + fmt::Display,
+    F: FnMut(&Transaction) -> Result<T, E>,
+{
+    let mut pause = WRITE_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match attempt_write(conn, f) {
+            Err(e) if attempt < WRITE_ATTEMPTS && is_busy(&e.to_string()) => {
+                tracing::debug!(attempt, "Database busy, retrying the write");
+                std::thread::sleep(pause);
+                pause *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Run `f` as one transaction on the writer thread and hand its result back.
+// Only writes sent here are serialized; see the note at the top.
+// Writes run one at a time in the order they were queued; one that finds the
+// database busy is rolled back and run again, so `f` must be safe to repeat.
+// Must not be called while holding an open write transaction on another
+// connection, which the writer would wait on.
+pub fn write<T, E, F>(app: &AppHandle, f: F) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<String> + fmt::Display + Send + 'static,
+    F: FnMut(&Transaction) -> Result<T, E> + Send + 'static,
+{
+    let mut f = f;
+    let Some(queue) = app.try_state::<WriteQueue>() else {
+        return run_write(app, &mut f);
+    };
+    if ON_WRITER.with(|on| on.get()) {
+        return run_write(app, &mut f);
+    }
+    let (reply, answer) = mpsc::channel();
+    let job_app = app.clone();
+    let job: WriteJob = Box::new(move || {
+        let _ = reply.send(run_write(&job_app, &mut f));
+    });
+    {
+        let mut sender = queue.0.lock().map_err(|e| e.to_string())?;
+        let unsent = match sender.as_ref() {
+            Some(queue) => queue.send(job).err().map(|mpsc::SendError(job)| job),
+            None => Some(job),
+        };
+        // First use, or a writer that has gone away
+        if let Some(job) = unsent {
+            let queue = spawn_writer()?;
+            queue
+                .send(job)
+                .map_err(|_| "The database writer is not running".to_string())?;
+            *sender = Some(queue);
+        }
+    }
+    answer
+        .recv()
+        .map_err(|_| E::from("The database write was abandoned".to_string()))?
+}
+
+// SQL expression for the local calendar date of a stored timestamp column.
+// Timestamps are written as UTC (ISO strings or CURRENT_TIMESTAMP).
+pub fn local_date(column: &str) -> String {
+    format!("date({}, 'localtime')", column)
+}
+
+// Read a value from the app_config key/value table
+pub fn get_config(conn: &Connection, key: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT value FROM app_config WHERE key = ?1",
+        [key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Insert or replace a value in the app_config key/value table
+pub fn set_config(conn: &Connection, key: &str, value: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO app_config (key, value, updated_at) VALUES (?1, ?2, CURRENT_TIMESTAMP)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+        [key, value],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
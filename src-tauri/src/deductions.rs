@@ -0,0 +1,298 @@
+// Moisture, dust and quality deductions for Truckore Pro
+// Contract rules per party and/or material reduce the payable net weight when
+// a weighment is completed. The measured net stays on the weighment; the
+// adjusted net and every deduction line (with its reason) are recorded here.
+
+use crate::db;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeductionRule {
+    pub id: Option<i64>,
+    // None matches any party / any material
+    pub party_name: Option<String>,
+    pub product_name: Option<String>,
+    pub moisture_pct: f64,
+    pub dust_pct: f64,
+    pub quality_penalty_pct: f64,
+    pub quality_penalty_kg: f64,
+    pub description: Option<String>,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeductionLine {
+    pub kind: String,
+    pub rate_pct: Option<f64>,
+    pub amount_kg: f64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetAdjustment {
+    pub weighment_id: String,
+    pub original_net: f64,
+    pub adjusted_net: f64,
+    pub rule_id: Option<i64>,
+    pub lines: Vec<DeductionLine>,
+}
+
+const RULE_COLUMNS: &str = "id, party_name, product_name, moisture_pct, dust_pct,
+    quality_penalty_pct, quality_penalty_kg, description, active";
+
+fn row_to_rule(row: &rusqlite::Row) -> rusqlite::Result<DeductionRule> {
+    Ok(DeductionRule {
+        id: row.get(0)?,
+        party_name: row.get(1)?,
+        product_name: row.get(2)?,
+        moisture_pct: row.get(3)?,
+        dust_pct: row.get(4)?,
+        quality_penalty_pct: row.get(5)?,
+        quality_penalty_kg: row.get(6)?,
+        description: row.get(7)?,
+        active: row.get(8)?,
+    })
+}
+
+// Most specific active rule: party + material, then material, then party
+fn matching_rule(
+    conn: &Connection,
+    party_name: &str,
+    product_name: &str,
+) -> Result<Option<DeductionRule>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM deduction_rules
+             WHERE active = 1
+               AND (party_name IS NULL OR party_name = ?1)
+               AND (product_name IS NULL OR product_name = ?2)
+               AND (party_name IS NOT NULL OR product_name IS NOT NULL)
+             ORDER BY party_name IS NOT NULL AND product_name IS NOT NULL DESC,
+                      product_name IS NOT NULL DESC, id DESC
+             LIMIT 1",
+            RULE_COLUMNS
+        ),
+        params![party_name, product_name],
+        row_to_rule,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+fn rule_label(rule: &DeductionRule) -> String {
+    let scope = match (&rule.party_name, &rule.product_name) {
+        (Some(party), Some(product)) => format!("{} / {}", party, product),
+        (Some(party), None) => party.clone(),
+        (None, Some(product)) => product.clone(),
+        (None, None) => "all".to_string(),
+    };
+    format!("rule #{} ({})", rule.id.unwrap_or_default(), scope)
+}
+
+// Percentages are each taken on the measured net so the lines add up plainly
+fn deduction_lines(rule: &DeductionRule, net: f64) -> Vec<DeductionLine> {
+    let label = rule_label(rule);
+    let mut lines = Vec::new();
+    for (kind, name, pct) in [
+        ("MOISTURE", "Moisture", rule.moisture_pct),
+        ("DUST", "Dust", rule.dust_pct),
+        ("QUALITY", "Quality penalty", rule.quality_penalty_pct),
+    ] {
+        if pct > 0.0 {
+            lines.push(DeductionLine {
+                kind: kind.to_string(),
+                rate_pct: Some(pct),
+                amount_kg: net * pct / 100.0,
+                reason: format!("{} {}% per {}", name, pct, label),
+            });
+        }
+    }
+    if rule.quality_penalty_kg > 0.0 {
+        lines.push(DeductionLine {
+            kind: "QUALITY".to_string(),
+            rate_pct: None,
+            amount_kg: rule.quality_penalty_kg,
+            reason: format!(
+                "Quality penalty {:.0} kg per {}",
+                rule.quality_penalty_kg, label
+            ),
+        });
+    }
+    lines
+}
+
+// Apply the matching contract rule to a completed weighment's net weight,
+// replacing any earlier deductions. Returns None when no rule applies.
+pub fn apply(
+    conn: &Connection,
+    weighment_id: &str,
+    party_name: &str,
+    product_name: &str,
+    net: f64,
+) -> Result<Option<NetAdjustment>, String> {
+    for table in ["weighment_deductions", "weighment_net_adjustments"] {
+        conn.execute(
+            &format!("DELETE FROM {} WHERE weighment_id = ?1", table),
+            [weighment_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let Some(rule) = matching_rule(conn, party_name, product_name)? else {
+        return Ok(None);
+    };
+    let lines = deduction_lines(&rule, net);
+    if lines.is_empty() {
+        return Ok(None);
+    }
+
+    let total: f64 = lines.iter().map(|l| l.amount_kg).sum();
+    let adjusted = (net - total).max(0.0);
+    conn.execute(
+        "INSERT INTO weighment_net_adjustments
+             (weighment_id, original_net, adjusted_net, rule_id)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![weighment_id, net, adjusted, rule.id],
+    )
+    .map_err(|e| e.to_string())?;
+    for line in &lines {
+        conn.execute(
+            "INSERT INTO weighment_deductions (weighment_id, kind, rate_pct, amount_kg, reason, rule_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![weighment_id, line.kind, line.rate_pct, line.amount_kg, line.reason, rule.id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    Ok(Some(NetAdjustment {
+        weighment_id: weighment_id.to_string(),
+        original_net: net,
+        adjusted_net: adjusted,
+        rule_id: rule.id,
+        lines,
+    }))
+}
+
+// Recompute deductions after a completed weighment changed (e.g. an amendment)
+pub fn reapply(conn: &Connection, weighment_id: &str) -> Result<(), String> {
+    let ticket: Option<(String, String, Option<f64>)> = conn
+        .query_row(
+            "SELECT party_name, product_name, net_weight FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some((party_name, product_name, Some(net))) = ticket {
+        apply(conn, weighment_id, &party_name, &product_name, net)?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_deduction_rules(app: AppHandle) -> Result<Vec<DeductionRule>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM deduction_rules ORDER BY party_name, product_name",
+            RULE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], row_to_rule).map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Create a rule, or update it when `id` is set. Returns the rule id.
+#[tauri::command]
+pub fn set_deduction_rule(app: AppHandle, rule: DeductionRule) -> Result<i64, String> {
+    if rule.party_name.is_none() && rule.product_name.is_none() {
+        return Err("A deduction rule needs a party, a material or both".to_string());
+    }
+    let conn = db::open(&app)?;
+    let values = params![
+        rule.party_name,
+        rule.product_name,
+        rule.moisture_pct,
+        rule.dust_pct,
+        rule.quality_penalty_pct,
+        rule.quality_penalty_kg,
+        rule.description,
+        rule.active,
+        rule.id
+    ];
+    match rule.id {
+        Some(id) => {
+            conn.execute(
+                "UPDATE deduction_rules SET party_name = ?1, product_name = ?2,
+                     moisture_pct = ?3, dust_pct = ?4, quality_penalty_pct = ?5,
+                     quality_penalty_kg = ?6, description = ?7, active = ?8
+                 WHERE id = ?9",
+                values,
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(id)
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO deduction_rules (party_name, product_name, moisture_pct, dust_pct,
+                     quality_penalty_pct, quality_penalty_kg, description, active)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                &values[..8],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(conn.last_insert_rowid())
+        }
+    }
+}
+
+// Original and adjusted net with the deduction lines for one weighment
+#[tauri::command]
+pub fn get_weighment_deductions(
+    app: AppHandle,
+    weighment_id: String,
+) -> Result<Option<NetAdjustment>, String> {
+    let conn = db::open(&app)?;
+    let summary: Option<(f64, f64, Option<i64>)> = conn
+        .query_row(
+            "SELECT original_net, adjusted_net, rule_id FROM weighment_net_adjustments
+             WHERE weighment_id = ?1",
+            [&weighment_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((original_net, adjusted_net, rule_id)) = summary else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT kind, rate_pct, amount_kg, reason FROM weighment_deductions
+             WHERE weighment_id = ?1 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let lines = stmt
+        .query_map([&weighment_id], |row| {
+            Ok(DeductionLine {
+                kind: row.get(0)?,
+                rate_pct: row.get(1)?,
+                amount_kg: row.get(2)?,
+                reason: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(Some(NetAdjustment {
+        weighment_id,
+        original_net,
+        adjusted_net,
+        rule_id,
+        lines,
+    }))
+}
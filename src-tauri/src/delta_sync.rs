@@ -0,0 +1,298 @@
+// Delta sync for Truckore Pro
+// Tracked tables carry a row_version stamped by triggers from one global
+// clock (migration 0004), and deletes leave tombstones. The sync layer asks
+// for everything after its target's watermark, pushes it, then acknowledges
+// the version it reached, so each sync moves only rows written since the
+// last one. Practice tickets (training.rs) are never pushed.
+
+use crate::db;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const MIGRATION_SQL: &str =
+    include_str!("../../src/services/database/migrations/0004_row_versions.sql");
+
+// Tables with row_version columns, as created by migration 0004 and, for
+// operator_profiles, migration 0008
+pub const TRACKED_TABLES: &[&str] = &[
+    "weighments",
+    "open_tickets",
+    "vehicles",
+    "parties",
+    "products",
+    "transporters",
+    "operator_profiles",
+];
+
+const DEFAULT_LIMIT: i64 = 1000;
+
+// Add change tracking and give every existing row its own version, so the
+// first sync can be paged like any other. Schema migration 4; the caller
+// holds the transaction.
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(MIGRATION_SQL)
+        .map_err(|e| e.to_string())?;
+    // Numbering rewrites old tickets, which the period lock would refuse; the
+    // lock is lifted inside this transaction only
+    let lock = db::get_config(conn, "period_lock_date")?;
+    conn.execute("DELETE FROM app_config WHERE key = 'period_lock_date'", [])
+        .map_err(|e| e.to_string())?;
+    for table in TRACKED_TABLES {
+        conn.execute_batch(&format!(
+            "UPDATE {t} SET row_version = (SELECT version FROM sync_clock WHERE id = 1) + rowid;
+             UPDATE sync_clock
+             SET version = MAX(version, (SELECT COALESCE(MAX(row_version), 0) FROM {t}))
+             WHERE id = 1;",
+            t = table
+        ))
+        .map_err(|e| format!("Numbering {} rows failed: {}", table, e))?;
+    }
+    if let Some(lock) = lock {
+        db::set_config(conn, "period_lock_date", &lock)?;
+    }
+    Ok(())
+}
+
+// The row_version triggers migration 0004 gave its tables, for a table
+// tracked later; `table` must already have the row_version column
+pub fn track_versions(conn: &Connection, table: &str) -> Result<(), String> {
+    conn.execute_batch(&format!(
+        "CREATE INDEX IF NOT EXISTS idx_{t}_row_version ON {t}(row_version);
+         CREATE TRIGGER IF NOT EXISTS {t}_row_version_insert
+         AFTER INSERT ON {t}
+         BEGIN
+             UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+             UPDATE {t} SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+             WHERE rowid = NEW.rowid;
+             DELETE FROM sync_tombstones WHERE table_name = '{t}' AND row_key = CAST(NEW.id AS TEXT);
+         END;
+         CREATE TRIGGER IF NOT EXISTS {t}_row_version_update
+         AFTER UPDATE ON {t}
+         WHEN NEW.row_version = OLD.row_version
+         BEGIN
+             UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+             UPDATE {t} SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+             WHERE rowid = NEW.rowid;
+         END;
+         CREATE TRIGGER IF NOT EXISTS {t}_row_version_delete
+         AFTER DELETE ON {t}
+         BEGIN
+             UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+             INSERT OR REPLACE INTO sync_tombstones (table_name, row_key, row_version)
+             VALUES ('{t}', CAST(OLD.id AS TEXT), (SELECT version FROM sync_clock WHERE id = 1));
+         END;",
+        t = table
+    ))
+    .map_err(|e| format!("Row versions for {} failed: {}", table, e))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangedRow {
+    pub table: String,
+    pub row_version: i64,
+    pub row: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletedRow {
+    pub table: String,
+    pub row_key: String,
+    pub row_version: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncChanges {
+    pub target: String,
+    // Watermark the changes start after; 0 before the first sync
+    pub from_version: i64,
+    // Acknowledge this once the changes are stored remotely
+    pub to_version: i64,
+    pub rows: Vec<ChangedRow>,
+    pub deleted: Vec<DeletedRow>,
+    // More changes are waiting after to_version
+    pub has_more: bool,
+}
+
+pub fn watermark(conn: &Connection, target: &str) -> Result<i64, String> {
+    let version: Option<i64> = conn
+        .query_row(
+            "SELECT version FROM sync_watermarks WHERE target = ?1",
+            params![target],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(version.unwrap_or(0))
+}
+
+fn changed_rows(
+    conn: &Connection,
+    table: &str,
+    after: i64,
+    limit: i64,
+) -> Result<Vec<ChangedRow>, String> {
+    let practice = match table {
+        "weighments" => format!(" AND {}", training::exclude_practice("id")),
+        _ => String::new(),
+    };
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT * FROM {} WHERE row_version > ?1{} ORDER BY row_version LIMIT ?2",
+            table, practice
+        ))
+        .map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let rows = stmt
+        .query_map(params![after, limit], |row| {
+            let mut map = serde_json::Map::new();
+            let mut row_version = 0;
+            for (i, name) in columns.iter().enumerate() {
+                if name == "row_version" {
+                    row_version = row.get(i)?;
+                }
+                map.insert(name.clone(), crate::sql_to_json_value(row.get_ref(i)?));
+            }
+            Ok(ChangedRow {
+                table: table.to_string(),
+                row_version,
+                row: serde_json::Value::Object(map),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Rows and deletions of `selected` tables after the target's watermark,
+// oldest first, at most `limit` of them
+pub fn collect(
+    conn: &Connection,
+    target: &str,
+    selected: &[&str],
+    limit: i64,
+) -> Result<SyncChanges, String> {
+    let from_version = watermark(conn, target)?;
+
+    // One read transaction, so tables and tombstones agree with each other
+    conn.execute_batch("BEGIN").map_err(|e| e.to_string())?;
+    // One extra row per source tells whether more are waiting
+    let collected = (|| {
+        let mut rows = Vec::new();
+        for table in selected {
+            rows.extend(changed_rows(conn, table, from_version, limit + 1)?);
+        }
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT table_name, row_key, row_version FROM sync_tombstones
+                 WHERE row_version > ?1 AND table_name IN ({})
+                 ORDER BY row_version LIMIT ?2",
+                selected
+                    .iter()
+                    .map(|t| format!("'{}'", t))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .map_err(|e| e.to_string())?;
+        let deleted = stmt
+            .query_map(params![from_version, limit + 1], |row| {
+                Ok(DeletedRow {
+                    table: row.get(0)?,
+                    row_key: row.get(1)?,
+                    row_version: row.get(2)?,
+                })
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>((rows, deleted))
+    })();
+    let _ = conn.execute_batch("COMMIT");
+    let (mut rows, mut deleted) = collected?;
+
+    // Each write has its own clock value, so the first `limit` versions
+    // across all tables form a clean cut
+    let mut versions: Vec<i64> = rows
+        .iter()
+        .map(|r| r.row_version)
+        .chain(deleted.iter().map(|d| d.row_version))
+        .collect();
+    versions.sort_unstable();
+    let has_more = versions.len() as i64 > limit;
+    let cut = match has_more {
+        true => versions[limit as usize - 1],
+        false => versions.last().copied().unwrap_or(from_version),
+    };
+    rows.retain(|r| r.row_version <= cut);
+    rows.sort_by_key(|r| r.row_version);
+    deleted.retain(|d| d.row_version <= cut);
+
+    Ok(SyncChanges {
+        target: target.to_string(),
+        from_version,
+        to_version: cut,
+        rows,
+        deleted,
+        has_more,
+    })
+}
+
+// Rows and deletions after the target's watermark, oldest first, at most
+// `limit` of them (1000 by default)
+#[tauri::command]
+pub fn get_sync_changes(
+    app: AppHandle,
+    target: String,
+    tables: Option<Vec<String>>,
+    limit: Option<i64>,
+) -> Result<SyncChanges, String> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let selected: Vec<&str> = match &tables {
+        Some(tables) => {
+            for table in tables {
+                if !TRACKED_TABLES.contains(&table.as_str()) {
+                    return Err(format!("{} is not change-tracked", table));
+                }
+            }
+            tables.iter().map(|t| t.as_str()).collect()
+        }
+        None => TRACKED_TABLES.to_vec(),
+    };
+    let conn = db::open(&app)?;
+    collect(&conn, &target, &selected, limit)
+}
+
+// Move the target's watermark up to `version`, never backwards; returns
+// the watermark now in force
+pub fn ack(conn: &Connection, target: &str, version: i64) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO sync_watermarks (target, version) VALUES (?1, ?2)
+         ON CONFLICT(target) DO UPDATE SET version = MAX(version, excluded.version),
+                                           updated_at = CURRENT_TIMESTAMP",
+        params![target, version],
+    )
+    .map_err(|e| e.to_string())?;
+    watermark(conn, target)
+}
+
+// Record that `target` now holds every change up to `version`. The
+// watermark never moves backwards.
+#[tauri::command]
+pub fn ack_sync_changes(app: AppHandle, target: String, version: i64) -> Result<i64, String> {
+    let conn = db::open(&app)?;
+    ack(&conn, &target, version)
+}
+
+// Forget a target's watermark so its next sync sends every row again
+#[tauri::command]
+pub fn reset_sync_watermark(app: AppHandle, target: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    conn.execute(
+        "DELETE FROM sync_watermarks WHERE target = ?1",
+        params![target],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
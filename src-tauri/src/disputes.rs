@@ -0,0 +1,387 @@
+// Weighment disputes for Truckore Pro
+// A disputed ticket gets one open dispute at a time, with a reason and any
+// evidence (photos, scanned documents). Corrections still go through the
+// amendment workflow; the amendments are linked to the dispute, and a
+// dispute can only close as ADJUSTED once one of them was approved.
+
+use crate::attachments;
+use crate::command_audit;
+use crate::db::{self, DateRange};
+use crate::roles::{self, Role};
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// Operator of a ticket: whoever had a shift open when it was created, other
+// than an auditor
+const TICKET_OPERATOR: &str = "(SELECT s.operator_id FROM shifts s
+      WHERE s.opened_at <= w.created_at
+        AND (s.closed_at IS NULL OR s.closed_at >= w.created_at)
+        AND s.operator_id NOT IN (SELECT id FROM users WHERE role = 'auditor')
+      ORDER BY s.opened_at DESC LIMIT 1)";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dispute {
+    pub id: i64,
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub party_name: String,
+    pub reason: String,
+    pub raised_by: String,
+    pub raised_at: String,
+    pub status: String,
+    pub resolution: Option<String>,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<String>,
+    pub attachment_count: i64,
+    pub amendment_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewDisputeAttachment {
+    pub name: String,
+    // Base64 data URL
+    pub data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisputeAttachment {
+    pub id: i64,
+    pub name: String,
+    // Data URL
+    pub data: String,
+    pub added_by: String,
+    pub added_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisputeCount {
+    pub key: String,
+    pub tickets: i64,
+    pub disputes: i64,
+    pub open: i64,
+    pub adjusted: i64,
+    // Disputes per 100 tickets
+    pub rate: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisputeReport {
+    pub by_party: Vec<DisputeCount>,
+    pub by_operator: Vec<DisputeCount>,
+}
+
+fn load_dispute(conn: &Connection, id: i64) -> Result<Dispute, String> {
+    list(conn, "d.id = ?1", params![id])?
+        .pop()
+        .ok_or_else(|| format!("Dispute {} not found", id))
+}
+
+fn list(
+    conn: &Connection,
+    filter: &str,
+    args: impl rusqlite::Params,
+) -> Result<Vec<Dispute>, String> {
+    let sql = format!(
+        "SELECT d.id, d.weighment_id, w.ticket_no, w.party_name, d.reason, d.raised_by,
+                d.raised_at, d.status, d.resolution, d.resolved_by, d.resolved_at,
+                (SELECT COUNT(*) FROM dispute_attachments a WHERE a.dispute_id = d.id),
+                (SELECT GROUP_CONCAT(amendment_id) FROM dispute_amendments m
+                 WHERE m.dispute_id = d.id)
+         FROM disputes d JOIN weighments w ON w.id = d.weighment_id
+         WHERE {}
+         ORDER BY d.id DESC",
+        filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(args, |row| {
+            let amendments: Option<String> = row.get(12)?;
+            Ok(Dispute {
+                id: row.get(0)?,
+                weighment_id: row.get(1)?,
+                ticket_no: row.get(2)?,
+                party_name: row.get(3)?,
+                reason: row.get(4)?,
+                raised_by: row.get(5)?,
+                raised_at: row.get(6)?,
+                status: row.get(7)?,
+                resolution: row.get(8)?,
+                resolved_by: row.get(9)?,
+                resolved_at: row.get(10)?,
+                attachment_count: row.get(11)?,
+                amendment_ids: amendments
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter_map(|id| id.parse().ok())
+                    .collect(),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn require_open(conn: &Connection, dispute_id: i64) -> Result<Dispute, String> {
+    let dispute = load_dispute(conn, dispute_id)?;
+    if dispute.status != "OPEN" {
+        return Err(format!(
+            "Dispute {} is already {}",
+            dispute_id, dispute.status
+        ));
+    }
+    Ok(dispute)
+}
+
+fn attach(
+    app: &AppHandle,
+    conn: &Connection,
+    dispute_id: i64,
+    attachment: &NewDisputeAttachment,
+    user_id: &str,
+) -> Result<(), String> {
+    let reference = attachments::store(app, conn, &attachment.data)?;
+    conn.execute(
+        "INSERT INTO dispute_attachments (dispute_id, name, attachment, added_by)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![dispute_id, attachment.name, reference, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Mark a ticket disputed. Returns the dispute id.
+#[tauri::command]
+pub fn raise_dispute(
+    app: AppHandle,
+    weighment_id: String,
+    reason: String,
+    attachments: Vec<NewDisputeAttachment>,
+    user_id: String,
+) -> Result<i64, String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is required for a dispute".to_string());
+    }
+    let mut conn = db::open(&app)?;
+    roles::user_role(&conn, &user_id)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let open: Option<i64> = tx
+        .query_row(
+            "SELECT id FROM disputes WHERE weighment_id = ?1 AND status = 'OPEN'",
+            [&weighment_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(id) = open {
+        return Err(format!("Ticket already has open dispute {}", id));
+    }
+    tx.execute(
+        "INSERT INTO disputes (weighment_id, reason, raised_by) VALUES (?1, ?2, ?3)",
+        params![weighment_id, reason, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = tx.last_insert_rowid();
+    for attachment in &attachments {
+        attach(&app, &tx, id, attachment, &user_id)?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn add_dispute_attachment(
+    app: AppHandle,
+    dispute_id: i64,
+    attachment: NewDisputeAttachment,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::user_role(&conn, &user_id)?;
+    require_open(&conn, dispute_id)?;
+    attach(&app, &conn, dispute_id, &attachment, &user_id)
+}
+
+// Record that an amendment was requested to settle the dispute
+#[tauri::command]
+pub fn link_dispute_amendment(
+    app: AppHandle,
+    dispute_id: i64,
+    amendment_id: i64,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::user_role(&conn, &user_id)?;
+    let dispute = require_open(&conn, dispute_id)?;
+    let amended: Option<String> = conn
+        .query_row(
+            "SELECT weighment_id FROM weighment_amendments WHERE id = ?1",
+            [amendment_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match amended {
+        None => return Err(format!("Amendment {} not found", amendment_id)),
+        Some(id) if id != dispute.weighment_id => {
+            return Err(format!(
+                "Amendment {} is for a different ticket",
+                amendment_id
+            ))
+        }
+        Some(_) => {}
+    }
+    conn.execute(
+        "INSERT OR IGNORE INTO dispute_amendments (dispute_id, amendment_id) VALUES (?1, ?2)",
+        params![dispute_id, amendment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Close a dispute as RESOLVED (no change to the ticket) or ADJUSTED (settled
+// by an approved amendment). Admin only.
+#[tauri::command]
+pub fn resolve_dispute(
+    app: AppHandle,
+    dispute_id: i64,
+    status: String,
+    resolution: String,
+    user_id: String,
+) -> Result<Dispute, String> {
+    let args = serde_json::json!({ "dispute_id": dispute_id, "status": status });
+    command_audit::audited(&app, "resolve_dispute", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        require_open(&conn, dispute_id)?;
+        if resolution.trim().is_empty() {
+            return Err("A resolution is required".to_string());
+        }
+        match status.as_str() {
+            "RESOLVED" => {}
+            "ADJUSTED" => {
+                let approved: i64 = conn
+                    .query_row(
+                        "SELECT COUNT(*) FROM dispute_amendments m
+                         JOIN weighment_amendments a ON a.id = m.amendment_id
+                         WHERE m.dispute_id = ?1 AND a.status = 'APPROVED'",
+                        [dispute_id],
+                        |row| row.get(0),
+                    )
+                    .map_err(|e| e.to_string())?;
+                if approved == 0 {
+                    return Err(
+                        "An ADJUSTED dispute needs an approved amendment linked to it".to_string(),
+                    );
+                }
+            }
+            other => return Err(format!("Unknown dispute outcome {}", other)),
+        }
+        conn.execute(
+            "UPDATE disputes SET status = ?2, resolution = ?3, resolved_by = ?4,
+                 resolved_at = CURRENT_TIMESTAMP
+             WHERE id = ?1",
+            params![dispute_id, status, resolution, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        load_dispute(&conn, dispute_id)
+    })
+}
+
+// List disputes, optionally for one ticket and/or status
+#[tauri::command]
+pub fn list_disputes(
+    app: AppHandle,
+    weighment_id: Option<String>,
+    status: Option<String>,
+) -> Result<Vec<Dispute>, String> {
+    let conn = db::open(&app)?;
+    list(
+        &conn,
+        "(?1 IS NULL OR d.weighment_id = ?1) AND (?2 IS NULL OR d.status = ?2)",
+        params![weighment_id, status],
+    )
+}
+
+#[tauri::command]
+pub fn list_dispute_attachments(
+    app: AppHandle,
+    dispute_id: i64,
+) -> Result<Vec<DisputeAttachment>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, name, attachment, added_by, added_at FROM dispute_attachments
+             WHERE dispute_id = ?1 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(i64, String, String, String, String)> = stmt
+        .query_map([dispute_id], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    rows.into_iter()
+        .map(|(id, name, reference, added_by, added_at)| {
+            Ok(DisputeAttachment {
+                id,
+                name,
+                data: attachments::load(&app, &conn, &reference)?,
+                added_by,
+                added_at,
+            })
+        })
+        .collect()
+}
+
+fn counts(conn: &Connection, key: &str, range: &DateRange) -> Result<Vec<DisputeCount>, String> {
+    let sql = format!(
+        "SELECT COALESCE({key}, 'unknown') AS k, COUNT(DISTINCT w.id),
+                COUNT(d.id),
+                COALESCE(SUM(d.status = 'OPEN'), 0),
+                COALESCE(SUM(d.status = 'ADJUSTED'), 0)
+         FROM weighments w LEFT JOIN disputes d ON d.weighment_id = w.id
+         WHERE {date} BETWEEN ?1 AND ?2 AND {practice}
+         GROUP BY k
+         HAVING COUNT(d.id) > 0
+         ORDER BY COUNT(d.id) DESC, k",
+        key = key,
+        date = db::local_date("w.created_at"),
+        practice = training::exclude_practice("w.id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], |row| {
+            let (tickets, disputes): (i64, i64) = (row.get(1)?, row.get(2)?);
+            Ok(DisputeCount {
+                key: row.get(0)?,
+                tickets,
+                disputes,
+                open: row.get(3)?,
+                adjusted: row.get(4)?,
+                rate: disputes as f64 * 100.0 / tickets.max(1) as f64,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Dispute frequency per party and per operator for tickets weighed in the range
+#[tauri::command]
+pub fn dispute_report(app: AppHandle, range: DateRange) -> Result<DisputeReport, String> {
+    let conn = db::open(&app)?;
+    Ok(DisputeReport {
+        by_party: counts(&conn, "w.party_name", &range)?,
+        by_operator: counts(&conn, TICKET_OPERATOR, &range)?,
+    })
+}
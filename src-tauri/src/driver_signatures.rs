@@ -0,0 +1,297 @@
+// Driver signatures for Truckore Pro
+// A driver signs on delivery to acknowledge the weights, on a touchscreen
+// (the window sends the canvas as an image) or on a USB signature pad (the
+// window passes on the pen strokes the pad reports, which are drawn here).
+// Either way the signature is stored as a PNG in the attachment store
+// (attachments.rs), one per ticket, and the PDF slip and the printed ticket
+// carry it. Weighment records themselves are signed in signatures.rs; this
+// is the person's mark.
+
+use crate::attachments;
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use image::{GrayImage, ImageFormat, Luma};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tauri::AppHandle;
+
+const MAX_IMAGE_BYTES: usize = 2 * 1024 * 1024;
+// Canvas pad strokes are drawn on, and the pen width
+const CANVAS: (u32, u32) = (600, 200);
+const PEN_PX: i64 = 2;
+// Largest printed signature, in dots: the width of a 58 mm ticket
+pub const PRINT_MAX: (usize, usize) = (384, 128);
+
+#[derive(Debug, Deserialize)]
+pub struct SignatureCapture {
+    pub signer_name: Option<String>,
+    // Touchscreen capture: PNG or JPEG data URL, dark ink
+    pub image: Option<String>,
+    // Signature pad capture: pen strokes, each a list of [x, y] points in
+    // the pad's own coordinates
+    pub strokes: Option<Vec<Vec<[f64; 2]>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DriverSignature {
+    pub weighment_id: String,
+    pub signer_name: Option<String>,
+    // TOUCH or PAD
+    pub source: String,
+    pub captured_by: String,
+    pub captured_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DriverSignatureImage {
+    pub signature: DriverSignature,
+    // Data URL
+    pub data: String,
+}
+
+fn row_to_signature(row: &rusqlite::Row) -> rusqlite::Result<DriverSignature> {
+    Ok(DriverSignature {
+        weighment_id: row.get(0)?,
+        signer_name: row.get(1)?,
+        source: row.get(2)?,
+        captured_by: row.get(3)?,
+        captured_at: row.get(4)?,
+    })
+}
+
+fn find(
+    conn: &Connection,
+    weighment_id: &str,
+) -> Result<Option<(DriverSignature, String)>, String> {
+    conn.query_row(
+        "SELECT weighment_id, signer_name, source, captured_by, captured_at, attachment
+         FROM driver_signatures WHERE weighment_id = ?1",
+        [weighment_id],
+        |row| Ok((row_to_signature(row)?, row.get(5)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Pen strokes drawn black on white, scaled to fit CANVAS
+fn draw_strokes(strokes: &[Vec<[f64; 2]>]) -> Result<Vec<u8>, String> {
+    let points = || strokes.iter().flatten();
+    if points().next().is_none() {
+        return Err("The signature has no strokes".to_string());
+    }
+    if points().any(|[x, y]| !x.is_finite() || !y.is_finite()) {
+        return Err("Invalid stroke point".to_string());
+    }
+    let (min_x, max_x) = points().fold((f64::MAX, f64::MIN), |(lo, hi), p| {
+        (lo.min(p[0]), hi.max(p[0]))
+    });
+    let (min_y, max_y) = points().fold((f64::MAX, f64::MIN), |(lo, hi), p| {
+        (lo.min(p[1]), hi.max(p[1]))
+    });
+    let margin = 10.0;
+    let scale = ((CANVAS.0 as f64 - 2.0 * margin) / (max_x - min_x).max(1.0))
+        .min((CANVAS.1 as f64 - 2.0 * margin) / (max_y - min_y).max(1.0));
+    let to_canvas = |p: &[f64; 2]| {
+        (
+            margin + (p[0] - min_x) * scale,
+            margin + (p[1] - min_y) * scale,
+        )
+    };
+
+    let mut img = GrayImage::from_pixel(CANVAS.0, CANVAS.1, Luma([255]));
+    let mut dab = |x: f64, y: f64| {
+        let (cx, cy) = (x.round() as i64, y.round() as i64);
+        for dy in -PEN_PX / 2..=PEN_PX / 2 {
+            for dx in -PEN_PX / 2..=PEN_PX / 2 {
+                let (px, py) = (cx + dx, cy + dy);
+                if px >= 0 && py >= 0 && px < CANVAS.0 as i64 && py < CANVAS.1 as i64 {
+                    img.put_pixel(px as u32, py as u32, Luma([0]));
+                }
+            }
+        }
+    };
+    for stroke in strokes {
+        let mut previous: Option<(f64, f64)> = None;
+        for point in stroke {
+            let (x, y) = to_canvas(point);
+            let (from_x, from_y) = previous.unwrap_or((x, y));
+            // One dab per pixel along the segment
+            let steps = (x - from_x).abs().max((y - from_y).abs()).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                let t = step as f64 / steps as f64;
+                dab(from_x + (x - from_x) * t, from_y + (y - from_y) * t);
+            }
+            previous = Some((x, y));
+        }
+    }
+    let mut out = Vec::new();
+    img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+// Ink of a signature image as rows of dots, dark true: cropped to the ink
+// and shrunk to fit `max` (width, height), keeping thin strokes
+pub fn ink(bytes: &[u8], max: (usize, usize)) -> Result<Vec<Vec<bool>>, String> {
+    let img = image::load_from_memory(bytes)
+        .map_err(|e| format!("Unreadable signature image: {}", e))?
+        .to_luma_alpha8();
+    // Canvas captures are dark ink on a transparent background
+    let dark = |x: u32, y: u32| {
+        let [luma, alpha] = img.get_pixel(x, y).0;
+        alpha > 127 && luma < 128
+    };
+    let (width, height) = img.dimensions();
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            if dark(x, y) {
+                left = left.min(x);
+                right = right.max(x);
+                top = top.min(y);
+                bottom = bottom.max(y);
+            }
+        }
+    }
+    if left > right {
+        return Err("The signature is blank".to_string());
+    }
+    let (ink_w, ink_h) = ((right - left + 1) as usize, (bottom - top + 1) as usize);
+    let step = (ink_w as f64 / max.0 as f64)
+        .max(ink_h as f64 / max.1 as f64)
+        .max(1.0);
+    let (out_w, out_h) = (
+        ((ink_w as f64 / step).ceil() as usize).max(1),
+        ((ink_h as f64 / step).ceil() as usize).max(1),
+    );
+    // Source pixels under dot `i`, at least one
+    let span = |i: usize, len: usize| {
+        let start = ((i as f64 * step) as usize).min(len - 1);
+        start..(((i + 1) as f64 * step) as usize).clamp(start + 1, len)
+    };
+    // A dot is dark when any source pixel it covers is
+    Ok((0..out_h)
+        .map(|row| {
+            (0..out_w)
+                .map(|column| {
+                    span(row, ink_h)
+                        .any(|y| span(column, ink_w).any(|x| dark(left + x as u32, top + y as u32)))
+                })
+                .collect()
+        })
+        .collect())
+}
+
+// The signature of the ticket whose weighments `column` is `value`, as dots
+// for a slip or ticket; None when it has none
+pub fn for_print(
+    app: &AppHandle,
+    conn: &Connection,
+    column: &str,
+    value: &str,
+    max: (usize, usize),
+) -> Result<Option<Vec<Vec<bool>>>, String> {
+    let reference: Option<String> = conn
+        .query_row(
+            &format!(
+                "SELECT s.attachment FROM driver_signatures s
+                 JOIN weighments w ON w.id = s.weighment_id
+                 WHERE w.{} = ?1 ORDER BY w.created_at DESC LIMIT 1",
+                column
+            ),
+            [value],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some(reference) = reference else {
+        return Ok(None);
+    };
+    let (_, bytes) = attachments::read(app, conn, &reference)?;
+    ink(&bytes, max).map(Some)
+}
+
+// Record the driver's signature for a ticket. Replacing one already on
+// record takes an admin, since it is the driver's acknowledgement.
+#[tauri::command]
+pub fn capture_driver_signature(
+    app: AppHandle,
+    weighment_id: String,
+    capture: SignatureCapture,
+    user_id: String,
+) -> Result<DriverSignature, String> {
+    let args = serde_json::json!({ "weighment_id": weighment_id });
+    command_audit::audited(&app, "capture_driver_signature", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Operator)?;
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM weighments WHERE id = ?1",
+                [&weighment_id],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .is_some();
+        if !exists {
+            return Err(format!("Ticket {} not found", weighment_id));
+        }
+        if find(&conn, &weighment_id)?.is_some() {
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+        }
+
+        let (source, mime, bytes) = match (&capture.image, &capture.strokes) {
+            (Some(image), None) => {
+                let data = attachments::parse_data_url(image)
+                    .ok_or("Signature image is not a base64 data URL")?;
+                (String::from("TOUCH"), data.mime, data.bytes)
+            }
+            (None, Some(strokes)) => (
+                String::from("PAD"),
+                "image/png".to_string(),
+                draw_strokes(strokes)?,
+            ),
+            _ => return Err("Send either a signature image or pad strokes".to_string()),
+        };
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err("Signature image is too large".to_string());
+        }
+        // Refuse an image that is unreadable or holds no ink
+        ink(&bytes, PRINT_MAX)?;
+        let reference = attachments::store_bytes(&app, &conn, &mime, &bytes)?;
+        let signer_name = capture
+            .signer_name
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty());
+        conn.execute(
+            "INSERT INTO driver_signatures (weighment_id, signer_name, attachment, source, captured_by)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(weighment_id) DO UPDATE SET signer_name = excluded.signer_name,
+                 attachment = excluded.attachment, source = excluded.source,
+                 captured_by = excluded.captured_by, captured_at = CURRENT_TIMESTAMP",
+            params![weighment_id, signer_name, reference, source, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        find(&conn, &weighment_id)?
+            .map(|(signature, _)| signature)
+            .ok_or_else(|| "Failed to record the signature".to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_driver_signature(
+    app: AppHandle,
+    weighment_id: String,
+) -> Result<Option<DriverSignatureImage>, String> {
+    let conn = db::open(&app)?;
+    let Some((signature, reference)) = find(&conn, &weighment_id)? else {
+        return Ok(None);
+    };
+    Ok(Some(DriverSignatureImage {
+        data: attachments::load(&app, &conn, &reference)?,
+        signature,
+    }))
+}
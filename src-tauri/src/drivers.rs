@@ -0,0 +1,104 @@
+// Hardware driver registry for Truckore Pro
+// Every supported device model is a driver behind a small per-kind trait.
+// Settings store a driver id, and workflow code looks the driver up here
+// instead of naming models. A new model is a new driver type plus one line
+// in the matching list below.
+
+use crate::scale_protocol::{self, Framer, Reading};
+use crate::thermal_printer::{self, ThermalPrinter, TicketPayload};
+use serde::{Deserialize, Serialize};
+
+// Turns an indicator's output stream into weight readings
+pub trait ScaleDriver: Sync {
+    // Stored in settings; never change it once shipped
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    fn framer(&self) -> Framer;
+    fn parse(&self, frame: &[u8]) -> Result<Reading, String>;
+
+    // Request the indicator answers with its model/ID, where supported
+    fn identify_command(&self) -> Option<&'static [u8]> {
+        None
+    }
+
+    // Model/ID reply to `identify_command`
+    fn identify(&self, _frame: &[u8]) -> Option<String> {
+        None
+    }
+
+    // Accepts nearly any input, so stricter drivers win auto-detection ties
+    fn lenient(&self) -> bool {
+        false
+    }
+}
+
+// Turns a ticket into the bytes a printer model understands
+pub trait PrinterDriver: Sync {
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    fn render(&self, ticket: &TicketPayload, settings: &ThermalPrinter) -> Vec<u8>;
+
+    // Request the printer answers with one status byte, where supported
+    fn status_command(&self) -> Option<&'static [u8]> {
+        None
+    }
+
+    // What is wrong according to a reply to `status_command`; None when the
+    // printer is fine
+    fn status_fault(&self, _reply: u8) -> Option<String> {
+        None
+    }
+}
+
+static SCALE_DRIVERS: &[&dyn ScaleDriver] = &[
+    &scale_protocol::Ascii,
+    &scale_protocol::StGs,
+    &scale_protocol::Toledo,
+];
+
+static PRINTER_DRIVERS: &[&dyn PrinterDriver] = &[&thermal_printer::EscPos];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DriverInfo {
+    // "scale" or "printer"
+    pub kind: String,
+    pub id: String,
+    pub name: String,
+}
+
+pub fn scale_drivers() -> &'static [&'static dyn ScaleDriver] {
+    SCALE_DRIVERS
+}
+
+pub fn scale(id: &str) -> Result<&'static dyn ScaleDriver, String> {
+    SCALE_DRIVERS
+        .iter()
+        .copied()
+        .find(|driver| driver.id() == id)
+        .ok_or_else(|| format!("Unknown indicator protocol: {}", id))
+}
+
+pub fn printer(id: &str) -> Result<&'static dyn PrinterDriver, String> {
+    PRINTER_DRIVERS
+        .iter()
+        .copied()
+        .find(|driver| driver.id() == id)
+        .ok_or_else(|| format!("Unknown printer driver: {}", id))
+}
+
+// Every registered driver, for the settings screens' model pickers
+#[tauri::command]
+pub fn list_drivers() -> Result<Vec<DriverInfo>, String> {
+    let scales = SCALE_DRIVERS.iter().map(|d| ("scale", d.id(), d.name()));
+    let printers = PRINTER_DRIVERS
+        .iter()
+        .map(|d| ("printer", d.id(), d.name()));
+    Ok(scales
+        .chain(printers)
+        .map(|(kind, id, name)| DriverInfo {
+            kind: kind.to_string(),
+            id: id.to_string(),
+            name: name.to_string(),
+        })
+        .collect())
+}
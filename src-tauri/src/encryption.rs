@@ -0,0 +1,247 @@
+// Encrypted database support for Truckore Pro
+// Builds with the `sqlcipher` feature link SQLCipher instead of plain SQLite.
+// The key is entered once per run with set_database_key, unless an admin had
+// it remembered on this PC (settings.rs), and applied to every connection as
+// it is opened. An existing plaintext
+// database is converted once with migrate_to_encrypted.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::signatures;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+// First 16 bytes of every plaintext SQLite file
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+// Managed state: the key for this run, once entered
+#[derive(Default)]
+pub struct DatabaseKey(Mutex<Option<String>>);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptionStatus {
+    // False when this build has no SQLCipher
+    pub supported: bool,
+    pub encrypted: bool,
+    // A key has been entered this run
+    pub unlocked: bool,
+}
+
+fn supported() -> Result<(), String> {
+    if cfg!(feature = "sqlcipher") {
+        Ok(())
+    } else {
+        Err("This build does not include database encryption".to_string())
+    }
+}
+
+pub fn current_key(app: &AppHandle) -> Option<String> {
+    app.try_state::<DatabaseKey>()
+        .and_then(|state| state.0.lock().ok().and_then(|key| key.clone()))
+}
+
+fn store_key(app: &AppHandle, key: Option<String>) {
+    if let Some(state) = app.try_state::<DatabaseKey>() {
+        if let Ok(mut current) = state.0.lock() {
+            *current = key;
+        }
+    }
+}
+
+// Drop this run's key, e.g. when another company's database is selected
+pub fn forget_key(app: &AppHandle) {
+    store_key(app, None);
+}
+
+// True when the file exists and does not start with the plaintext header
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    match fs::File::open(path).and_then(|mut file| file.read_exact(&mut header)) {
+        Ok(()) => &header != PLAINTEXT_HEADER,
+        // Missing or too short to hold a header: a new, empty database
+        Err(_) => false,
+    }
+}
+
+// Open a database file with this run's key applied. Every connection to the
+// live database or its backups goes through here.
+pub fn open_file(app: &AppHandle, path: &Path, flags: OpenFlags) -> rusqlite::Result<Connection> {
+    let conn = Connection::open_with_flags(path, flags)?;
+    if let Some(key) = current_key(app) {
+        // Must be the first statement on the connection
+        conn.pragma_update(None, "key", key)?;
+    }
+    signatures::register(&conn)?;
+    Ok(conn)
+}
+
+// Attach another database file to `conn` as `schema`, with this run's key
+pub fn attach(
+    app: &AppHandle,
+    conn: &Connection,
+    path: &Path,
+    schema: &str,
+) -> rusqlite::Result<()> {
+    let path = path.to_string_lossy();
+    match current_key(app) {
+        Some(key) => conn.execute(
+            &format!("ATTACH DATABASE ?1 AS {} KEY ?2", schema),
+            rusqlite::params![path, key],
+        ),
+        None => conn.execute(&format!("ATTACH DATABASE ?1 AS {}", schema), [path]),
+    }
+    .map(|_| ())
+}
+
+fn check_readable(conn: &Connection) -> rusqlite::Result<()> {
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|_| ())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[tauri::command]
+pub fn get_database_encryption(app: AppHandle) -> Result<EncryptionStatus, String> {
+    let db_path = crate::get_db_path(&app)?;
+    Ok(EncryptionStatus {
+        supported: cfg!(feature = "sqlcipher"),
+        encrypted: is_encrypted(&db_path),
+        unlocked: current_key(&app).is_some(),
+    })
+}
+
+// Enter the key of an encrypted database for this run. Called before login,
+// since the users table is inside the encrypted file. On a fresh install the
+// database is created encrypted with this key.
+#[tauri::command]
+pub fn set_database_key(app: AppHandle, key: String) -> Result<(), String> {
+    supported()?;
+    if key.is_empty() {
+        return Err("Database key cannot be empty".to_string());
+    }
+    let db_path = crate::get_db_path(&app)?;
+    if db_path.exists() && !is_encrypted(&db_path) {
+        return Err("The database is not encrypted; use migrate_to_encrypted".to_string());
+    }
+    // Idle connections were opened with the previous key
+    app.state::<db::DbPool>().close_all();
+    store_key(&app, Some(key));
+    if db_path.exists() {
+        let checked =
+            open_file(&app, &db_path, OpenFlags::default()).and_then(|conn| check_readable(&conn));
+        if checked.is_err() {
+            store_key(&app, None);
+            return Err("Wrong database key".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Convert the plaintext database to an encrypted one keyed with `key` (admin
+// only). The encrypted copy is written beside the live file, checked, and
+// swapped in; the plaintext file is then deleted. Existing backups stay
+// plaintext. Refused while other operations are running.
+#[tauri::command]
+pub fn migrate_to_encrypted(app: AppHandle, key: String, user_id: String) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "migrate_to_encrypted",
+        &user_id,
+        serde_json::json!({}),
+        || {
+            supported()?;
+            {
+                let conn = db::open(&app)?;
+                roles::require_role(&conn, &user_id, Role::Admin)?;
+            }
+            if key.is_empty() {
+                return Err("Database key cannot be empty".to_string());
+            }
+            let db_path = crate::get_db_path(&app)?;
+            if is_encrypted(&db_path) {
+                return Err("The database is already encrypted".to_string());
+            }
+            let busy = shutdown::in_flight(&app);
+            if !busy.is_empty() {
+                return Err(format!(
+                    "{} operation(s) still running; try again when they finish",
+                    busy.len()
+                ));
+            }
+            let _operation = shutdown::begin(&app, "encrypt", None)?;
+
+            let encrypted = with_suffix(&db_path, ".encrypting");
+            let _ = fs::remove_file(&encrypted);
+            let result = (|| {
+                let plain = Connection::open(&db_path).map_err(|e| e.to_string())?;
+                // Fold the WAL into the main file so the export sees everything
+                plain
+                    .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+                    .map_err(|e| e.to_string())?;
+                plain
+                    .execute(
+                        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+                        [encrypted.to_string_lossy().as_ref(), key.as_str()],
+                    )
+                    .map_err(|e| e.to_string())?;
+                plain
+                    .query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+                    .map_err(|e| format!("Encryption failed: {}", e))?;
+                plain
+                    .execute_batch("DETACH DATABASE encrypted")
+                    .map_err(|e| e.to_string())?;
+                drop(plain);
+
+                let check = Connection::open(&encrypted).map_err(|e| e.to_string())?;
+                check
+                    .pragma_update(None, "key", &key)
+                    .map_err(|e| e.to_string())?;
+                let integrity: String = check
+                    .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+                    .map_err(|e| e.to_string())?;
+                if integrity != "ok" {
+                    return Err(format!(
+                        "Encrypted copy failed its integrity check: {}",
+                        integrity
+                    ));
+                }
+                Ok(())
+            })();
+            if result.is_err() {
+                let _ = fs::remove_file(&encrypted);
+            }
+            result?;
+
+            // Nothing may hold the plaintext file open across the swap
+            app.state::<db::DbPool>().close_all();
+            let plaintext = with_suffix(&db_path, ".plaintext");
+            fs::rename(&db_path, &plaintext).map_err(|e| e.to_string())?;
+            if let Err(e) = fs::rename(&encrypted, &db_path) {
+                let _ = fs::rename(&plaintext, &db_path);
+                return Err(format!("Failed to swap in the encrypted database: {}", e));
+            }
+            store_key(&app, Some(key.clone()));
+            for leftover in [
+                plaintext,
+                with_suffix(&db_path, "-wal"),
+                with_suffix(&db_path, "-shm"),
+            ] {
+                let _ = fs::remove_file(leftover);
+            }
+            Ok(())
+        },
+    )
+}
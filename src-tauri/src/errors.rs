@@ -0,0 +1,235 @@
+// Structured command errors for Truckore Pro
+// Commands used to reject with a bare message, so the UI could not tell a
+// duplicate ticket number from a full disk or a database another PC holds
+// locked. Every rejection now reaches the page as an AppError: a stable
+// `code`, the message, the SQLite extended result code when SQLite raised
+// it, the command or step it came from and whether trying again may help.
+// The database commands build it from the rusqlite error itself; messages
+// from the rest of the backend are classified by the responder
+// (runtime_metrics.rs), which recognises the text SQLite and this crate use.
+
+use crate::command_audit;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // UNIQUE, NOT NULL, FOREIGN KEY and CHECK failures
+    Constraint,
+    // Another connection or PC holds the write lock; worth retrying
+    Busy,
+    // A table is locked by this connection's own open statement
+    Locked,
+    DiskFull,
+    ReadOnly,
+    Corrupt,
+    // Not a SQLite file, or encrypted with another key
+    NotADatabase,
+    CannotOpen,
+    Cancelled,
+    Timeout,
+    PermissionDenied,
+    NotFound,
+    Io,
+    // SQL errors: syntax, unknown tables or columns, type mismatches
+    Sql,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppError {
+    pub code: ErrorCode,
+    pub message: String,
+    // SQLite extended result code, e.g. 2067 for SQLITE_CONSTRAINT_UNIQUE
+    pub sqlite_code: Option<i32>,
+    // The command or step that failed, e.g. "Statement 2"
+    pub context: Option<String>,
+    pub retryable: bool,
+    // The message in English when `message` was translated (messages.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub english: Option<String>,
+}
+
+impl AppError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        AppError {
+            code,
+            message: message.into(),
+            sqlite_code: None,
+            context: None,
+            retryable: matches!(
+                code,
+                ErrorCode::Busy | ErrorCode::Locked | ErrorCode::Timeout
+            ),
+            english: None,
+        }
+    }
+
+    fn sqlite(code: ErrorCode, message: impl Into<String>, sqlite_code: i32) -> Self {
+        AppError {
+            sqlite_code: Some(sqlite_code),
+            ..AppError::new(code, message)
+        }
+    }
+
+    // Prefix the message with `context` and keep it apart for the UI
+    pub fn context(mut self, context: impl Into<String>) -> Self {
+        let context = context.into();
+        self.message = format!("{}: {}", context, self.message);
+        self.context = Some(context);
+        self
+    }
+
+    // Best guess at the code behind a plain error message
+    pub fn classify(message: &str) -> Self {
+        let lower = message.to_ascii_lowercase();
+        let constraint = [
+            ("unique constraint failed", 2067),
+            ("not null constraint failed", 1299),
+            ("foreign key constraint failed", 787),
+            ("check constraint failed", 275),
+            ("primary key constraint failed", 1555),
+        ];
+        if let Some((_, code)) = constraint.iter().find(|(text, _)| lower.contains(text)) {
+            return AppError::sqlite(ErrorCode::Constraint, message, *code);
+        }
+        let sqlite = [
+            ("constraint failed", ErrorCode::Constraint, 19),
+            ("database is locked", ErrorCode::Busy, 5),
+            ("database table is locked", ErrorCode::Locked, 6),
+            ("database schema is locked", ErrorCode::Locked, 6),
+            ("database or disk is full", ErrorCode::DiskFull, 13),
+            (
+                "attempt to write a readonly database",
+                ErrorCode::ReadOnly,
+                8,
+            ),
+            ("database disk image is malformed", ErrorCode::Corrupt, 11),
+            ("file is not a database", ErrorCode::NotADatabase, 26),
+            ("unable to open database file", ErrorCode::CannotOpen, 14),
+            ("interrupted", ErrorCode::Cancelled, 9),
+        ];
+        if let Some((_, code, sqlite_code)) = sqlite.iter().find(|(text, ..)| lower.contains(text))
+        {
+            return AppError::sqlite(*code, message, *sqlite_code);
+        }
+        let code = if message.starts_with(command_audit::DENIED_PREFIX)
+            || message.starts_with("Unknown or inactive user")
+        {
+            ErrorCode::PermissionDenied
+        } else if message == "Query cancelled" {
+            ErrorCode::Cancelled
+        } else if message.starts_with("Query stopped after") {
+            ErrorCode::Timeout
+        } else if lower.starts_with("no such table")
+            || lower.starts_with("no such column")
+            || lower.contains("syntax error")
+        {
+            ErrorCode::Sql
+        } else if lower.contains("not found") || lower.contains("no such file") {
+            ErrorCode::NotFound
+        } else if lower.contains("permission denied") || lower.contains("access is denied") {
+            ErrorCode::PermissionDenied
+        } else {
+            ErrorCode::Other
+        };
+        AppError::new(code, message)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        use rusqlite::ErrorCode as Sqlite;
+        let message = e.to_string();
+        match &e {
+            rusqlite::Error::SqliteFailure(err, _) => {
+                let code = match err.code {
+                    Sqlite::ConstraintViolation => ErrorCode::Constraint,
+                    Sqlite::DatabaseBusy => ErrorCode::Busy,
+                    Sqlite::DatabaseLocked => ErrorCode::Locked,
+                    Sqlite::DiskFull => ErrorCode::DiskFull,
+                    Sqlite::ReadOnly => ErrorCode::ReadOnly,
+                    Sqlite::DatabaseCorrupt => ErrorCode::Corrupt,
+                    Sqlite::NotADatabase => ErrorCode::NotADatabase,
+                    Sqlite::CannotOpen => ErrorCode::CannotOpen,
+                    Sqlite::OperationInterrupted => ErrorCode::Cancelled,
+                    Sqlite::PermissionDenied => ErrorCode::PermissionDenied,
+                    Sqlite::SystemIoFailure => ErrorCode::Io,
+                    _ => ErrorCode::Sql,
+                };
+                AppError::sqlite(code, message, err.extended_code)
+            }
+            rusqlite::Error::QueryReturnedNoRows => AppError::new(ErrorCode::NotFound, message),
+            _ => AppError::new(ErrorCode::Sql, message),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        // ENOSPC on Unix, ERROR_DISK_FULL on Windows
+        let code = match (e.kind(), e.raw_os_error()) {
+            (_, Some(28)) if cfg!(unix) => ErrorCode::DiskFull,
+            (_, Some(112)) if cfg!(windows) => ErrorCode::DiskFull,
+            (std::io::ErrorKind::NotFound, _) => ErrorCode::NotFound,
+            (std::io::ErrorKind::PermissionDenied, _) => ErrorCode::PermissionDenied,
+            _ => ErrorCode::Io,
+        };
+        AppError::new(code, e.to_string())
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::classify(&message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::classify(message)
+    }
+}
+
+// Lets functions that still return String errors call ones that return AppError
+impl From<AppError> for String {
+    fn from(e: AppError) -> Self {
+        e.message
+    }
+}
+
+// The rejection sent to the page: plain messages become an AppError for
+// `command`, errors already structured pass through unchanged. `translate`
+// gives the message in the operator's language, the English kept beside it.
+pub fn to_response(
+    error: Value,
+    command: Option<&str>,
+    translate: impl Fn(&str) -> Option<String>,
+) -> Value {
+    let mut structured = match &error {
+        Value::String(message) => AppError::classify(message),
+        other => match serde_json::from_value::<AppError>(other.clone()) {
+            Ok(structured) => structured,
+            Err(_) => return error,
+        },
+    };
+    if structured.context.is_none() {
+        structured.context = command.map(str::to_string);
+    }
+    if structured.english.is_none() {
+        if let Some(translated) = translate(&structured.message) {
+            structured.english = Some(std::mem::replace(&mut structured.message, translated));
+        }
+    }
+    serde_json::to_value(&structured).unwrap_or(error)
+}
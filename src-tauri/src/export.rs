@@ -0,0 +1,637 @@
+// Data export for Truckore Pro
+// Writes entities or ad-hoc SELECT results straight to files for BI pipelines
+// and spreadsheets. CSV follows the site's number format: with a decimal
+// comma, fractions take a comma and fields are separated by semicolons.
+// The exporting session's masking rules apply (masking.rs).
+
+use crate::db::{self, DateRange};
+use crate::formatting;
+use crate::masking::{self, MaskMode, Masks};
+use crate::training;
+use crate::xlsx::{self, SheetWriter};
+use arrow_array::builder::{
+    Float64Builder, Int64Builder, StringBuilder, TimestampMillisecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use base64::{engine::general_purpose, Engine as _};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, Statement};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tauri::AppHandle;
+
+// Exportable entities with an explicit, stable column list and the column
+// used for date-range filtering
+pub struct EntityExport {
+    pub name: &'static str,
+    pub table: &'static str,
+    pub columns: &'static [&'static str],
+    pub date_column: &'static str,
+    // Practice tickets are never exported
+    pub excludes_practice: bool,
+}
+
+pub const ENTITIES: &[EntityExport] = &[
+    EntityExport {
+        name: "weighments",
+        table: "weighments",
+        columns: &[
+            "id",
+            "bill_no",
+            "ticket_no",
+            "vehicle_no",
+            "party_name",
+            "product_name",
+            "gross_weight",
+            "tare_weight",
+            "net_weight",
+            "charges_minor",
+            "status",
+            "first_weight_type",
+            "first_vehicle_status",
+            "second_vehicle_status",
+            "second_weight_timestamp",
+            "created_at",
+            "updated_at",
+            "closed_at",
+            "printed_at",
+            "remarks",
+        ],
+        date_column: "created_at",
+        excludes_practice: true,
+    },
+    EntityExport {
+        name: "open_tickets",
+        table: "open_tickets",
+        columns: &[
+            "id",
+            "ticket_no",
+            "vehicle_no",
+            "party_name",
+            "product_name",
+            "first_weight",
+            "first_weight_time",
+            "created_at",
+        ],
+        date_column: "created_at",
+        excludes_practice: false,
+    },
+    EntityExport {
+        name: "vehicles",
+        table: "vehicles",
+        columns: &["id", "vehicle_no", "source", "created_at"],
+        date_column: "created_at",
+        excludes_practice: false,
+    },
+    EntityExport {
+        name: "parties",
+        table: "parties",
+        columns: &["id", "party_name", "source", "created_at"],
+        date_column: "created_at",
+        excludes_practice: false,
+    },
+    EntityExport {
+        name: "products",
+        table: "products",
+        columns: &["id", "product_name", "source", "created_at"],
+        date_column: "created_at",
+        excludes_practice: false,
+    },
+    EntityExport {
+        name: "audit_log",
+        table: "audit_log",
+        columns: &[
+            "id",
+            "command",
+            "table_name",
+            "sql",
+            "params_digest",
+            "rows_affected",
+            "user_id",
+            "logged_at",
+        ],
+        date_column: "logged_at",
+        excludes_practice: false,
+    },
+];
+
+// Whether `query_or_entity` names an entity rather than being SQL text
+pub fn is_entity(query_or_entity: &str) -> bool {
+    ENTITIES.iter().any(|e| e.name == query_or_entity)
+}
+
+// Build the SELECT for an entity name, or validate an ad-hoc read-only query.
+// Ad-hoc queries may reference the range as :from / :to.
+fn export_sql(query_or_entity: &str, range: Option<&DateRange>) -> Result<String, String> {
+    if let Some(entity) = ENTITIES.iter().find(|e| e.name == query_or_entity) {
+        let mut conditions = Vec::new();
+        if range.is_some() {
+            conditions.push(format!(
+                "{} BETWEEN :from AND :to",
+                db::local_date(entity.date_column)
+            ));
+        }
+        if entity.excludes_practice {
+            conditions.push(training::exclude_practice("id"));
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        };
+        return Ok(format!(
+            "SELECT {} FROM {}{} ORDER BY {}",
+            entity.columns.join(", "),
+            entity.table,
+            filter,
+            entity.date_column
+        ));
+    }
+
+    let head = query_or_entity.trim_start().to_ascii_uppercase();
+    if head.starts_with("SELECT") || head.starts_with("WITH") {
+        Ok(query_or_entity.to_string())
+    } else {
+        Err(format!(
+            "Unknown export entity or non-SELECT query: {}",
+            query_or_entity
+        ))
+    }
+}
+
+// Prepare an export statement and bind the optional range parameters
+pub fn prepare_export<'c>(
+    conn: &'c Connection,
+    query_or_entity: &str,
+    range: Option<&DateRange>,
+    masks: &Masks,
+) -> Result<Statement<'c>, String> {
+    let sql = export_sql(query_or_entity, range)?;
+    let mut stmt = masks
+        .prepare(conn, || conn.prepare(&sql))
+        .map_err(|e| e.to_string())?;
+    if !stmt.readonly() {
+        return Err("Export queries must be read-only".to_string());
+    }
+
+    if let Some(range) = range {
+        for (name, value) in [(":from", &range.from), (":to", &range.to)] {
+            if let Some(idx) = stmt.parameter_index(name).map_err(|e| e.to_string())? {
+                stmt.raw_bind_parameter(idx, value)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok(stmt)
+}
+
+// The masks for exporting `query_or_entity`: entities are the app's own
+// SQL, anything else was written by the caller
+pub fn export_masks(masks: Masks, query_or_entity: &str) -> Masks {
+    match is_entity(query_or_entity) {
+        true => masks,
+        false => masks.for_raw_sql(),
+    }
+}
+
+// How each column is masked, None for columns shown as they are
+fn column_modes(masks: &Masks, column_names: &[String]) -> Vec<Option<MaskMode>> {
+    column_names.iter().map(|name| masks.mode(name)).collect()
+}
+
+// Plain value conversion for exports: text stays text, blobs become base64
+fn export_value(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => serde_json::json!(i),
+        ValueRef::Real(f) => serde_json::json!(f),
+        ValueRef::Text(t) => serde_json::Value::String(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => serde_json::Value::String(general_purpose::STANDARD.encode(b)),
+    }
+}
+
+// Write an entity or SELECT query to `path` as newline-delimited JSON
+pub fn write_jsonl(
+    conn: &Connection,
+    query_or_entity: &str,
+    path: &str,
+    range: Option<&DateRange>,
+    masks: &Masks,
+) -> Result<usize, String> {
+    let mut stmt = prepare_export(conn, query_or_entity, range, masks)?;
+    let column_names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let modes = column_modes(masks, &column_names);
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(file);
+
+    let mut rows = stmt.raw_query();
+    let mut count = 0;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let mut map = serde_json::Map::new();
+        for (i, name) in column_names.iter().enumerate() {
+            let value = row.get_ref(i).map_err(|e| e.to_string())?;
+            let masked = modes[i].map(|mode| masking::masked_cell(mode, value));
+            map.insert(
+                name.clone(),
+                export_value(masked.as_ref().map_or(value, ValueRef::from)),
+            );
+        }
+        serde_json::to_writer(&mut writer, &map).map_err(|e| e.to_string())?;
+        writer.write_all(b"\n").map_err(|e| e.to_string())?;
+        count += 1;
+    }
+
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+// Export an entity (weighments, vehicles, ...) or a SELECT query as
+// newline-delimited JSON. Returns the number of rows written.
+#[tauri::command]
+pub fn export_jsonl(
+    app: AppHandle,
+    session_token: Option<String>,
+    query_or_entity: String,
+    path: String,
+    range: Option<DateRange>,
+) -> Result<usize, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?;
+    let masks = export_masks(masks, &query_or_entity);
+    let conn = db::open(&app)?;
+    write_jsonl(&conn, &query_or_entity, &path, range.as_ref(), &masks)
+}
+
+// CSV field, quoted only when it has to be
+fn csv_field(value: ValueRef, decimal_comma: bool) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) if decimal_comma => f.to_string().replace('.', ","),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(t) => {
+            let text = String::from_utf8_lossy(t);
+            if text.contains([',', ';', '"', '\n', '\r']) {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            } else {
+                text.into_owned()
+            }
+        }
+        ValueRef::Blob(b) => general_purpose::STANDARD.encode(b),
+    }
+}
+
+fn sheet_cell(value: ValueRef) -> xlsx::Cell {
+    match value {
+        ValueRef::Null => xlsx::Cell::Empty,
+        ValueRef::Integer(i) => xlsx::Cell::Number(i as f64),
+        ValueRef::Real(f) => xlsx::Cell::Number(f),
+        ValueRef::Text(t) => xlsx::Cell::Text(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => xlsx::Cell::Text(general_purpose::STANDARD.encode(b)),
+    }
+}
+
+// Write an entity or SELECT query to a .csv or .xlsx file, calling `on_row`
+// with the running count after each row; an error from it stops the export
+pub fn write_query(
+    conn: &Connection,
+    query_or_entity: &str,
+    path: &str,
+    range: Option<&DateRange>,
+    masks: &Masks,
+    on_row: &mut dyn FnMut(usize) -> Result<(), String>,
+) -> Result<usize, String> {
+    let extension = Path::new(&path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let xlsx = match extension.as_deref() {
+        Some("csv") => false,
+        Some("xlsx") => true,
+        _ => return Err("Export path must end in .csv or .xlsx".to_string()),
+    };
+
+    let mut stmt = prepare_export(conn, query_or_entity, range, masks)?;
+    let column_names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let modes = column_modes(masks, &column_names);
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut rows = stmt.raw_query();
+
+    if xlsx {
+        let mut sheet = SheetWriter::create(file, &column_names)?;
+        let mut count = 0;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let mut cells = Vec::with_capacity(column_names.len());
+            for (i, mode) in modes.iter().enumerate() {
+                let value = row.get_ref(i).map_err(|e| e.to_string())?;
+                let masked = mode.map(|mode| masking::masked_cell(mode, value));
+                cells.push(sheet_cell(masked.as_ref().map_or(value, ValueRef::from)));
+            }
+            sheet.row(&cells)?;
+            count += 1;
+            on_row(count)?;
+        }
+        sheet.finish()?;
+        return Ok(count);
+    }
+
+    // CRLF line ends as RFC 4180 and Excel expect, and a byte order mark so
+    // Excel reads the file as UTF-8
+    let decimal_comma = formatting::load(conn)?.decimal_comma;
+    let separator = if decimal_comma { ";" } else { "," };
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all("\u{feff}".as_bytes())
+        .map_err(|e| e.to_string())?;
+    let header: Vec<String> = column_names
+        .iter()
+        .map(|name| csv_field(ValueRef::Text(name.as_bytes()), decimal_comma))
+        .collect();
+    write!(writer, "{}\r\n", header.join(separator)).map_err(|e| e.to_string())?;
+    let mut count = 0;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let mut fields = Vec::with_capacity(column_names.len());
+        for (i, mode) in modes.iter().enumerate() {
+            let value = row.get_ref(i).map_err(|e| e.to_string())?;
+            let masked = mode.map(|mode| masking::masked_cell(mode, value));
+            fields.push(csv_field(
+                masked.as_ref().map_or(value, ValueRef::from),
+                decimal_comma,
+            ));
+        }
+        write!(writer, "{}\r\n", fields.join(separator)).map_err(|e| e.to_string())?;
+        count += 1;
+        on_row(count)?;
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+// Export an entity or a SELECT query to a .csv or .xlsx file, picked by the
+// extension of `path`. Rows go straight to disk; in XLSX numbers stay numbers
+// and dates become date cells. Returns the number of rows written.
+#[tauri::command]
+pub fn export_query(
+    app: AppHandle,
+    session_token: Option<String>,
+    query_or_entity: String,
+    path: String,
+    range: Option<DateRange>,
+) -> Result<usize, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?;
+    let masks = export_masks(masks, &query_or_entity);
+    let conn = db::open(&app)?;
+    write_query(
+        &conn,
+        &query_or_entity,
+        &path,
+        range.as_ref(),
+        &masks,
+        &mut |_| Ok(()),
+    )
+}
+
+// Rows buffered per record batch when writing Parquet
+const PARQUET_BATCH_ROWS: usize = 8192;
+
+// Weighment columns exported to Parquet, in schema order
+const PARQUET_TEXT_COLUMNS: &[&str] = &[
+    "id",
+    "bill_no",
+    "ticket_no",
+    "vehicle_no",
+    "party_name",
+    "product_name",
+    "status",
+    "first_weight_type",
+    "remarks",
+];
+const PARQUET_WEIGHT_COLUMNS: &[&str] = &["gross_weight", "tare_weight", "net_weight"];
+// Paise
+const PARQUET_MINOR_COLUMNS: &[&str] = &["charges_minor"];
+const PARQUET_TIME_COLUMNS: &[&str] = &[
+    "created_at",
+    "second_weight_timestamp",
+    "closed_at",
+    "printed_at",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParquetPartition {
+    pub month: String,
+    pub path: String,
+    pub rows: usize,
+}
+
+fn weighment_schema() -> Arc<Schema> {
+    let mut fields = Vec::new();
+    for name in PARQUET_TEXT_COLUMNS {
+        fields.push(Field::new(*name, DataType::Utf8, *name != "id"));
+    }
+    for name in PARQUET_WEIGHT_COLUMNS {
+        fields.push(Field::new(*name, DataType::Float64, true));
+    }
+    for name in PARQUET_MINOR_COLUMNS {
+        fields.push(Field::new(*name, DataType::Int64, true));
+    }
+    for name in PARQUET_TIME_COLUMNS {
+        fields.push(Field::new(
+            *name,
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            *name != "created_at",
+        ));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+// Column builders for one record batch
+struct WeighmentBatch {
+    text: Vec<StringBuilder>,
+    weights: Vec<Float64Builder>,
+    minor: Vec<Int64Builder>,
+    times: Vec<TimestampMillisecondBuilder>,
+    len: usize,
+}
+
+impl WeighmentBatch {
+    fn new() -> Self {
+        WeighmentBatch {
+            text: PARQUET_TEXT_COLUMNS
+                .iter()
+                .map(|_| StringBuilder::new())
+                .collect(),
+            weights: PARQUET_WEIGHT_COLUMNS
+                .iter()
+                .map(|_| Float64Builder::new())
+                .collect(),
+            minor: PARQUET_MINOR_COLUMNS
+                .iter()
+                .map(|_| Int64Builder::new())
+                .collect(),
+            times: PARQUET_TIME_COLUMNS
+                .iter()
+                .map(|_| TimestampMillisecondBuilder::new().with_timezone("UTC"))
+                .collect(),
+            len: 0,
+        }
+    }
+
+    fn finish(&mut self, schema: &Arc<Schema>) -> Result<RecordBatch, String> {
+        let mut columns: Vec<ArrayRef> = Vec::new();
+        for b in &mut self.text {
+            columns.push(Arc::new(b.finish()));
+        }
+        for b in &mut self.weights {
+            columns.push(Arc::new(b.finish()));
+        }
+        for b in &mut self.minor {
+            columns.push(Arc::new(b.finish()));
+        }
+        for b in &mut self.times {
+            columns.push(Arc::new(b.finish()));
+        }
+        self.len = 0;
+        RecordBatch::try_new(schema.clone(), columns).map_err(|e| e.to_string())
+    }
+}
+
+struct PartitionWriter {
+    month: String,
+    path: String,
+    writer: ArrowWriter<File>,
+    batch: WeighmentBatch,
+    rows: usize,
+}
+
+impl PartitionWriter {
+    // Hive-style layout (month=YYYY-MM/) so Spark and DuckDB pick up the partition key
+    fn create(dir: &Path, month: &str, schema: &Arc<Schema>) -> Result<Self, String> {
+        let partition_dir = dir.join(format!("month={}", month));
+        fs::create_dir_all(&partition_dir).map_err(|e| e.to_string())?;
+        let path = partition_dir.join("weighments.parquet");
+        let file = File::create(&path).map_err(|e| e.to_string())?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer =
+            ArrowWriter::try_new(file, schema.clone(), Some(props)).map_err(|e| e.to_string())?;
+
+        Ok(PartitionWriter {
+            month: month.to_string(),
+            path: path.to_string_lossy().into_owned(),
+            writer,
+            batch: WeighmentBatch::new(),
+            rows: 0,
+        })
+    }
+
+    fn flush_batch(&mut self, schema: &Arc<Schema>) -> Result<(), String> {
+        if self.batch.len > 0 {
+            let batch = self.batch.finish(schema)?;
+            self.writer.write(&batch).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn close(mut self, schema: &Arc<Schema>) -> Result<ParquetPartition, String> {
+        self.flush_batch(schema)?;
+        self.writer.close().map_err(|e| e.to_string())?;
+        Ok(ParquetPartition {
+            month: self.month,
+            path: self.path,
+            rows: self.rows,
+        })
+    }
+}
+
+// Export weighments to typed Parquet files partitioned by month under `dir`
+#[tauri::command]
+pub fn export_parquet(
+    app: AppHandle,
+    dir: String,
+    range: Option<DateRange>,
+) -> Result<Vec<ParquetPartition>, String> {
+    let conn = db::open(&app)?;
+    let schema = weighment_schema();
+
+    // Timestamps are converted to Unix milliseconds by SQLite itself, which
+    // accepts both ISO strings and CURRENT_TIMESTAMP values
+    let time_exprs: Vec<String> = PARQUET_TIME_COLUMNS
+        .iter()
+        .map(|c| format!("CAST((julianday({}) - 2440587.5) * 86400000 AS INTEGER)", c))
+        .collect();
+    let sql = format!(
+        "SELECT strftime('%Y-%m', created_at, 'localtime'), {}, {}, {}, {}
+         FROM weighments
+         WHERE (?1 IS NULL OR {} BETWEEN ?1 AND ?2) AND {}
+         ORDER BY 1, created_at",
+        PARQUET_TEXT_COLUMNS.join(", "),
+        PARQUET_WEIGHT_COLUMNS.join(", "),
+        PARQUET_MINOR_COLUMNS.join(", "),
+        time_exprs.join(", "),
+        db::local_date("created_at"),
+        training::exclude_practice("id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query(params![
+            range.as_ref().map(|r| &r.from),
+            range.as_ref().map(|r| &r.to)
+        ])
+        .map_err(|e| e.to_string())?;
+
+    let dir = Path::new(&dir);
+    let mut partitions = Vec::new();
+    let mut current: Option<PartitionWriter> = None;
+
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let month: String = row.get(0).map_err(|e| e.to_string())?;
+        if current.as_ref().map(|p| p.month != month).unwrap_or(true) {
+            if let Some(done) = current.take() {
+                partitions.push(done.close(&schema)?);
+            }
+            current = Some(PartitionWriter::create(dir, &month, &schema)?);
+        }
+        let partition = current.as_mut().expect("partition writer initialized");
+
+        let mut col = 1;
+        for b in &mut partition.batch.text {
+            let v: Option<String> = row.get(col).map_err(|e| e.to_string())?;
+            b.append_option(v);
+            col += 1;
+        }
+        for b in &mut partition.batch.weights {
+            let v: Option<f64> = row.get(col).map_err(|e| e.to_string())?;
+            b.append_option(v);
+            col += 1;
+        }
+        for b in &mut partition.batch.minor {
+            let v: Option<i64> = row.get(col).map_err(|e| e.to_string())?;
+            b.append_option(v);
+            col += 1;
+        }
+        for b in &mut partition.batch.times {
+            let v: Option<i64> = row.get(col).map_err(|e| e.to_string())?;
+            b.append_option(v);
+            col += 1;
+        }
+        partition.batch.len += 1;
+        partition.rows += 1;
+
+        if partition.batch.len >= PARQUET_BATCH_ROWS {
+            partition.flush_batch(&schema)?;
+        }
+    }
+
+    if let Some(done) = current.take() {
+        partitions.push(done.close(&schema)?);
+    }
+    Ok(partitions)
+}
@@ -0,0 +1,770 @@
+// Export destinations for Truckore Pro
+// Each export used to write to a path the screen picked, and the report mail
+// had its own code. An export destination is a named place exports go: a
+// folder on this PC, a USB drive, mail recipients, an FTP server, an
+// S3-compatible bucket or a webhook. Any export (an entity or SELECT, a
+// saved report, the summary PDF) runs through one pipeline: it is rendered
+// to a file by the usual exporters, handed to the destination and recorded
+// in export_deliveries, delivered or not. Passwords and keys stay in the
+// secure settings store; a destination names the secret to use. Mail goes
+// through the report schedule's SMTP server. FTP is plain FTP, meant for a
+// server on the site network; over the internet use S3 or a webhook.
+
+use crate::cloud_backup::{self, S3Bucket};
+use crate::command_audit;
+use crate::communications::{self, Outbound};
+use crate::db::{self, DateRange};
+use crate::export;
+use crate::formatting;
+use crate::masking::Masks;
+use crate::network;
+use crate::report_builder;
+use crate::report_schedule;
+use crate::reports;
+use crate::roles::{self, Role};
+use crate::settings;
+use crate::smtp::{self, Attachment, Message};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const FTP_TIMEOUT: Duration = Duration::from_secs(30);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(120);
+// Deliveries returned by list_export_deliveries unless asked otherwise
+const DEFAULT_HISTORY: i64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum DestinationConfig {
+    // A folder on this PC or a mapped network share, created when missing
+    #[serde(rename = "FILE")]
+    File { directory: String },
+    // A folder on a removable drive; the drive must be plugged in, and the
+    // file is synced to it before the export counts as delivered
+    #[serde(rename = "USB")]
+    Usb { drive: String, directory: String },
+    #[serde(rename = "EMAIL")]
+    Email { recipients: Vec<String> },
+    #[serde(rename = "FTP")]
+    Ftp {
+        host: String,
+        port: u16,
+        username: String,
+        password_secret: Option<String>,
+        directory: String,
+    },
+    #[serde(rename = "S3")]
+    S3 {
+        #[serde(flatten)]
+        bucket: S3Bucket,
+        secret_key_secret: String,
+        prefix: String,
+    },
+    // The file is POSTed as the request body
+    #[serde(rename = "WEBHOOK")]
+    Webhook {
+        url: String,
+        auth_secret: Option<String>,
+    },
+}
+
+impl DestinationConfig {
+    fn kind(&self) -> &'static str {
+        match self {
+            DestinationConfig::File { .. } => "FILE",
+            DestinationConfig::Usb { .. } => "USB",
+            DestinationConfig::Email { .. } => "EMAIL",
+            DestinationConfig::Ftp { .. } => "FTP",
+            DestinationConfig::S3 { .. } => "S3",
+            DestinationConfig::Webhook { .. } => "WEBHOOK",
+        }
+    }
+
+    // Whether the export leaves this PC; those are off in profiles without
+    // outbound integrations
+    fn outbound(&self) -> bool {
+        !matches!(
+            self,
+            DestinationConfig::File { .. } | DestinationConfig::Usb { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportDestination {
+    pub name: String,
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub config: DestinationConfig,
+}
+
+// What to export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum ExportSource {
+    // An entity or SELECT, as export_query; format is csv, xlsx or jsonl
+    Query {
+        query_or_entity: String,
+        format: String,
+    },
+    // A saved report definition, as csv or xlsx
+    Report {
+        id: String,
+        format: String,
+        #[serde(default)]
+        include_archives: bool,
+    },
+    // The weighment summary PDF; needs a range
+    Summary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJob {
+    #[serde(flatten)]
+    pub source: ExportSource,
+    pub range: Option<DateRange>,
+    // File name without extension; derived from the source when missing
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportDelivery {
+    pub id: i64,
+    pub destination: String,
+    pub kind: String,
+    // The entity, query, report id or "summary"
+    pub source: String,
+    pub format: String,
+    pub filename: String,
+    pub rows: Option<i64>,
+    pub size_bytes: i64,
+    // DELIVERED or FAILED
+    pub status: String,
+    // Where the file ended up: a path, recipients, a URL or an object key
+    pub location: Option<String>,
+    pub error: Option<String>,
+    pub requested_by: Option<String>,
+    pub created_at: String,
+}
+
+// A rendered export, ready for any destination
+struct Rendered {
+    source: String,
+    format: String,
+    filename: String,
+    content_type: &'static str,
+    rows: Option<usize>,
+    bytes: Vec<u8>,
+}
+
+fn content_type(format: &str) -> &'static str {
+    match format {
+        "csv" => "text/csv; charset=utf-8",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "jsonl" => "application/x-ndjson",
+        _ => "application/pdf",
+    }
+}
+
+// Safe as a file name and an object key: letters, digits, '.', '_' and '-'
+fn clean_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "._-".contains(c) {
+            true => c,
+            false => '-',
+        })
+        .collect()
+}
+
+// Render through a temporary file, since the exporters write to a path
+fn through_file(
+    format: &str,
+    write: impl FnOnce(&str) -> Result<usize, String>,
+) -> Result<(usize, Vec<u8>), String> {
+    let path = std::env::temp_dir().join(format!(
+        "truckore-export-{}.{}",
+        uuid::Uuid::new_v4().simple(),
+        format
+    ));
+    let path_text = path.to_string_lossy().to_string();
+    let written = write(&path_text).and_then(|rows| {
+        fs::read(&path)
+            .map(|bytes| (rows, bytes))
+            .map_err(|e| e.to_string())
+    });
+    let _ = fs::remove_file(&path);
+    written
+}
+
+fn render(app: &AppHandle, conn: &Connection, job: &ExportJob) -> Result<Rendered, String> {
+    let range = job.range.as_ref();
+    let (source, format, rows, bytes) = match &job.source {
+        ExportSource::Query {
+            query_or_entity,
+            format,
+        } => {
+            let (rows, bytes) = match format.as_str() {
+                "jsonl" => through_file(format, |path| {
+                    export::write_jsonl(conn, query_or_entity, path, range, &Masks::default())
+                })?,
+                "csv" | "xlsx" => through_file(format, |path| {
+                    export::write_query(
+                        conn,
+                        query_or_entity,
+                        path,
+                        range,
+                        &Masks::default(),
+                        &mut |_| Ok(()),
+                    )
+                })?,
+                other => {
+                    return Err(format!(
+                        "Unknown export format {} (csv, xlsx or jsonl)",
+                        other
+                    ))
+                }
+            };
+            let source = match export::ENTITIES.iter().any(|e| e.name == query_or_entity) {
+                true => query_or_entity.clone(),
+                false => "query".to_string(),
+            };
+            (source, format.clone(), Some(rows), bytes)
+        }
+        ExportSource::Report {
+            id,
+            format,
+            include_archives,
+        } => {
+            if !matches!(format.as_str(), "csv" | "xlsx") {
+                return Err(format!("Reports export as csv or xlsx, not {}", format));
+            }
+            let (rows, bytes) = through_file(format, |path| {
+                report_builder::write_report(
+                    app,
+                    conn,
+                    id,
+                    path,
+                    range,
+                    *include_archives,
+                    &Masks::default(),
+                )
+            })?;
+            (id.clone(), format.clone(), Some(rows), bytes)
+        }
+        ExportSource::Summary => {
+            let range = range.ok_or("The summary needs a date range")?;
+            let summary = reports::summary(conn, range.clone())?;
+            let bytes = reports::summary_pdf(&summary, &formatting::load(conn)?);
+            ("summary".to_string(), "pdf".to_string(), None, bytes)
+        }
+    };
+    let stem = match (&job.filename, range) {
+        (Some(name), _) if !name.trim().is_empty() => name.trim().to_string(),
+        (_, Some(range)) if range.from == range.to => format!("{}-{}", source, range.from),
+        (_, Some(range)) => format!("{}-{}-to-{}", source, range.from, range.to),
+        _ => source.clone(),
+    };
+    Ok(Rendered {
+        filename: format!("{}.{}", clean_name(&stem), format),
+        content_type: content_type(&format),
+        source,
+        format,
+        rows,
+        bytes,
+    })
+}
+
+fn secret(app: &AppHandle, conn: &Connection, name: &str) -> Result<String, String> {
+    settings::secret(app, conn, name)?.ok_or_else(|| format!("No secret named {} is stored", name))
+}
+
+fn write_file(dir: &Path, file: &Rendered) -> Result<String, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let path = dir.join(&file.filename);
+    let mut out =
+        File::create(&path).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    out.write_all(&file.bytes)
+        .and_then(|_| out.sync_all())
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+// One reply, joining the lines of a multi-line one ("123-" .. "123 ")
+fn ftp_reply(reader: &mut BufReader<TcpStream>) -> Result<(u16, String), String> {
+    let mut text = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("FTP server closed the connection".to_string());
+        }
+        let line = line.trim_end().to_string();
+        text.push_str(&line);
+        let code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+        if let (Some(code), Some(' ') | None) = (code, line.chars().nth(3)) {
+            return Ok((code, text));
+        }
+        text.push('\n');
+    }
+}
+
+fn ftp_command(
+    reader: &mut BufReader<TcpStream>,
+    command: &str,
+    codes: &[u16],
+) -> Result<(u16, String), String> {
+    let stream = reader.get_mut();
+    stream
+        .write_all(format!("{}\r\n", command).as_bytes())
+        .map_err(|e| e.to_string())?;
+    let (code, text) = ftp_reply(reader)?;
+    match codes.contains(&code) {
+        true => Ok((code, text)),
+        false => {
+            let shown = match command.starts_with("PASS ") {
+                true => "PASS",
+                false => command,
+            };
+            Err(format!("FTP server refused {}: {}", shown, text))
+        }
+    }
+}
+
+fn connect(host: &str, port: u16) -> Result<TcpStream, String> {
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve {}: {}", host, e))?
+        .next()
+        .ok_or_else(|| format!("Cannot resolve {}", host))?;
+    let stream = TcpStream::connect_timeout(&address, FTP_TIMEOUT)
+        .map_err(|e| format!("FTP server {}:{} unreachable: {}", host, port, e))?;
+    stream
+        .set_read_timeout(Some(FTP_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(FTP_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+// Store `file` in `directory` on an FTP server, in passive binary mode. The
+// data connection goes to the control host, whatever address PASV names,
+// so a server behind NAT still works.
+fn ftp_put(
+    host: &str,
+    port: u16,
+    username: &str,
+    password: &str,
+    directory: &str,
+    file: &Rendered,
+) -> Result<(), String> {
+    let mut reader = BufReader::new(connect(host, port)?);
+    match ftp_reply(&mut reader)? {
+        (220, _) => {}
+        (_, text) => return Err(format!("FTP server refused the connection: {}", text)),
+    }
+    let (code, _) = ftp_command(&mut reader, &format!("USER {}", username), &[230, 331])?;
+    if code == 331 {
+        ftp_command(&mut reader, &format!("PASS {}", password), &[230, 202])?;
+    }
+    ftp_command(&mut reader, "TYPE I", &[200])?;
+    if !directory.trim().is_empty() {
+        ftp_command(&mut reader, &format!("CWD {}", directory.trim()), &[250])?;
+    }
+    let (_, text) = ftp_command(&mut reader, "PASV", &[227])?;
+    let numbers: Vec<u16> = text
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty())
+        .skip(1)
+        .filter_map(|n| n.parse().ok())
+        .collect();
+    let [.., high, low] = numbers[..] else {
+        return Err(format!(
+            "FTP server sent an unreadable PASV reply: {}",
+            text
+        ));
+    };
+    let mut data = connect(host, high * 256 + low)?;
+    ftp_command(&mut reader, &format!("STOR {}", file.filename), &[125, 150])?;
+    data.write_all(&file.bytes)
+        .map_err(|e| format!("FTP upload failed: {}", e))?;
+    drop(data);
+    match ftp_reply(&mut reader)? {
+        (226 | 250, _) => {}
+        (_, text) => return Err(format!("FTP upload failed: {}", text)),
+    }
+    let _ = ftp_command(&mut reader, "QUIT", &[221]);
+    Ok(())
+}
+
+fn webhook_error(e: ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, response) => format!(
+            "Webhook answered HTTP {}: {}",
+            code,
+            response.into_string().unwrap_or_default()
+        ),
+        other => format!("Webhook unreachable: {}", other),
+    }
+}
+
+// Hand the file to the destination; returns where it went
+fn deliver(
+    app: &AppHandle,
+    conn: &Connection,
+    destination: &ExportDestination,
+    file: &Rendered,
+    user_id: &str,
+) -> Result<String, String> {
+    if destination.config.outbound() {
+        communications::require_outbound(app)?;
+    }
+    match &destination.config {
+        DestinationConfig::File { directory } => write_file(Path::new(directory), file),
+        DestinationConfig::Usb { drive, directory } => {
+            let drive = PathBuf::from(drive);
+            if !drive.is_dir() {
+                return Err(format!("Drive {} is not connected", drive.display()));
+            }
+            write_file(&drive.join(directory), file)
+        }
+        DestinationConfig::Email { recipients } => {
+            let schedule = report_schedule::load_schedule(conn)?;
+            smtp::validate(&schedule.smtp)?;
+            let message = Message {
+                to: recipients.clone(),
+                subject: format!("Truckore Pro export {}", file.filename),
+                body: format!("Attached: {}\r\n", file.filename),
+                attachments: vec![Attachment {
+                    filename: file.filename.clone(),
+                    content_type: file.content_type.to_string(),
+                    data: file.bytes.clone(),
+                }],
+            };
+            let outbound = Outbound {
+                channel: "EMAIL",
+                destination: recipients.join(", "),
+                summary: message.subject.clone(),
+                weighment_id: None,
+                party_name: None,
+                request: None,
+                created_by: Some(user_id.to_string()),
+            };
+            communications::logged(conn, outbound, || {
+                let server = report_schedule::smtp_settings(app, conn, &schedule)?;
+                smtp::send(conn, &server, &message)
+            })?;
+            Ok(recipients.join(", "))
+        }
+        DestinationConfig::Ftp {
+            host,
+            port,
+            username,
+            password_secret,
+            directory,
+        } => {
+            let password = match password_secret {
+                Some(name) => secret(app, conn, name)?,
+                None => String::new(),
+            };
+            ftp_put(host, *port, username, &password, directory, file)?;
+            let directory = directory.trim().trim_matches('/');
+            Ok(match directory.is_empty() {
+                true => format!("ftp://{}:{}/{}", host, port, file.filename),
+                false => format!("ftp://{}:{}/{}/{}", host, port, directory, file.filename),
+            })
+        }
+        DestinationConfig::S3 {
+            bucket,
+            secret_key_secret,
+            prefix,
+        } => {
+            let key = format!("{}{}", prefix, file.filename);
+            let secret_key = secret(app, conn, secret_key_secret)?;
+            cloud_backup::put_object(conn, bucket, &secret_key, &key, &file.bytes)?;
+            Ok(format!("s3://{}/{}", bucket.bucket, key))
+        }
+        DestinationConfig::Webhook { url, auth_secret } => {
+            let agent = network::http_agent(conn, url, WEBHOOK_TIMEOUT)?;
+            let mut request = agent
+                .post(url)
+                .set("Content-Type", file.content_type)
+                .set("X-Export-Filename", &file.filename)
+                .set("X-Export-Source", &file.source);
+            if let Some(name) = auth_secret {
+                request = request.set(
+                    "Authorization",
+                    &format!("Bearer {}", secret(app, conn, name)?),
+                );
+            }
+            request.send_bytes(&file.bytes).map_err(webhook_error)?;
+            Ok(url.clone())
+        }
+    }
+}
+
+fn validate(destination: &mut ExportDestination) -> Result<(), String> {
+    destination.name = destination.name.trim().to_string();
+    if destination.name.is_empty() {
+        return Err("A destination needs a name".to_string());
+    }
+    let required = |field: &str, value: &str| match value.trim().is_empty() {
+        true => Err(format!("{} is required", field)),
+        false => Ok(()),
+    };
+    match &mut destination.config {
+        DestinationConfig::File { directory } => required("Folder", directory)?,
+        DestinationConfig::Usb { drive, .. } => required("Drive", drive)?,
+        DestinationConfig::Email { recipients } => {
+            if recipients.is_empty() {
+                return Err("The destination needs at least one recipient".to_string());
+            }
+            for address in recipients.iter_mut() {
+                *address = address.trim().to_string();
+                smtp::check_address(address)?;
+            }
+        }
+        DestinationConfig::Ftp {
+            host,
+            port,
+            username,
+            ..
+        } => {
+            *host = host.trim().to_string();
+            required("FTP host", host)?;
+            required("FTP username", username)?;
+            if *port == 0 {
+                return Err("FTP port is required".to_string());
+            }
+        }
+        DestinationConfig::S3 {
+            bucket,
+            secret_key_secret,
+            prefix,
+        } => {
+            bucket.endpoint = bucket.endpoint.trim().trim_end_matches('/').to_string();
+            if !bucket.endpoint.starts_with("https://") && !bucket.endpoint.starts_with("http://") {
+                return Err("S3 endpoint must start with https:// or http://".to_string());
+            }
+            required("S3 region", &bucket.region)?;
+            required("S3 bucket", &bucket.bucket)?;
+            required("S3 access key id", &bucket.access_key_id)?;
+            required("S3 secret key secret", secret_key_secret)?;
+            *prefix = prefix.trim().trim_start_matches('/').to_string();
+            if !prefix.is_empty() && !prefix.ends_with('/') {
+                prefix.push('/');
+            }
+        }
+        DestinationConfig::Webhook { url, .. } => {
+            *url = url.trim().to_string();
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err("Webhook URL must start with https:// or http://".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn row_to_destination(row: &rusqlite::Row) -> rusqlite::Result<(String, bool, String)> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+}
+
+fn destination(
+    (name, enabled, config): (String, bool, String),
+) -> Result<ExportDestination, String> {
+    Ok(ExportDestination {
+        name,
+        enabled,
+        config: serde_json::from_str(&config).map_err(|e| e.to_string())?,
+    })
+}
+
+pub fn load(conn: &Connection, name: &str) -> Result<ExportDestination, String> {
+    let row = conn
+        .query_row(
+            "SELECT name, enabled, config FROM export_destinations WHERE name = ?1",
+            [name],
+            row_to_destination,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Export destination {} not found", name))?;
+    destination(row)
+}
+
+const DELIVERY_SELECT: &str =
+    "SELECT id, destination, kind, source, format, filename, rows, size_bytes, status,
+            location, error, requested_by, created_at
+     FROM export_deliveries";
+
+fn row_to_delivery(row: &rusqlite::Row) -> rusqlite::Result<ExportDelivery> {
+    Ok(ExportDelivery {
+        id: row.get(0)?,
+        destination: row.get(1)?,
+        kind: row.get(2)?,
+        source: row.get(3)?,
+        format: row.get(4)?,
+        filename: row.get(5)?,
+        rows: row.get(6)?,
+        size_bytes: row.get(7)?,
+        status: row.get(8)?,
+        location: row.get(9)?,
+        error: row.get(10)?,
+        requested_by: row.get(11)?,
+        created_at: row.get(12)?,
+    })
+}
+
+// Render `job`, deliver it to the destination `name` and record the outcome.
+// A failed delivery is recorded too and returned as an error.
+pub fn run(
+    app: &AppHandle,
+    conn: &Connection,
+    name: &str,
+    job: &ExportJob,
+    user_id: &str,
+) -> Result<ExportDelivery, String> {
+    let destination = load(conn, name)?;
+    if !destination.enabled {
+        return Err(format!("Export destination {} is disabled", name));
+    }
+    let file = render(app, conn, job)?;
+    let outcome = deliver(app, conn, &destination, &file, user_id);
+    let (status, location, error) = match &outcome {
+        Ok(location) => ("DELIVERED", Some(location.as_str()), None),
+        Err(e) => ("FAILED", None, Some(e.as_str())),
+    };
+    conn.execute(
+        "INSERT INTO export_deliveries (destination, kind, source, format, filename, rows,
+             size_bytes, status, location, error, requested_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            destination.name,
+            destination.config.kind(),
+            file.source,
+            file.format,
+            file.filename,
+            file.rows.map(|r| r as i64),
+            file.bytes.len() as i64,
+            status,
+            location,
+            error,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+    outcome?;
+    conn.query_row(
+        &format!("{} WHERE id = ?1", DELIVERY_SELECT),
+        [id],
+        row_to_delivery,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_export_destinations(app: AppHandle) -> Result<Vec<ExportDestination>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare("SELECT name, enabled, config FROM export_destinations ORDER BY name")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_destination)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    rows.into_iter().map(destination).collect()
+}
+
+// Add or replace a destination (admin only). Secrets it names are stored
+// separately with set_secret.
+#[tauri::command]
+pub fn set_export_destination(
+    app: AppHandle,
+    destination: ExportDestination,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&destination).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_export_destination", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut destination = destination;
+        validate(&mut destination)?;
+        let config = serde_json::to_string(&destination.config).map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO export_destinations (name, kind, enabled, config)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(name) DO UPDATE SET kind = excluded.kind, enabled = excluded.enabled,
+                 config = excluded.config, updated_at = CURRENT_TIMESTAMP",
+            params![
+                destination.name,
+                destination.config.kind(),
+                destination.enabled,
+                config
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+// Admin only; its delivery history stays
+#[tauri::command]
+pub fn delete_export_destination(
+    app: AppHandle,
+    name: String,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let deleted = conn
+        .execute("DELETE FROM export_destinations WHERE name = ?1", [&name])
+        .map_err(|e| e.to_string())?;
+    match deleted {
+        0 => Err(format!("Export destination {} not found", name)),
+        _ => Ok(()),
+    }
+}
+
+// Export to a destination and return the recorded delivery
+#[tauri::command]
+pub async fn export_to_destination(
+    app: AppHandle,
+    destination: String,
+    job: ExportJob,
+    user_id: String,
+) -> Result<ExportDelivery, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&app)?;
+        run(&app, &conn, &destination, &job, &user_id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Delivery history, newest first, of one destination or all of them
+#[tauri::command]
+pub fn list_export_deliveries(
+    app: AppHandle,
+    destination: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<ExportDelivery>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} WHERE ?1 IS NULL OR destination = ?1 ORDER BY id DESC LIMIT ?2",
+            DELIVERY_SELECT
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![destination, limit.unwrap_or(DEFAULT_HISTORY)],
+            row_to_delivery,
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,161 @@
+// Feature flags for Truckore Pro
+// Risky subsystems (sync, kiosk mode, ANPR) are gated behind named flags that
+// can be set per site. Rows pushed from the synced config ("remote") take
+// precedence over local settings so a feature can be switched off centrally.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// Flag rows with this site apply to every site
+const ALL_SITES: &str = "*";
+const SITE_CONFIG_KEY: &str = "site_id";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub site_id: String,
+    pub enabled: bool,
+    pub source: String,
+    pub updated_at: String,
+}
+
+// A flag as delivered by the synced configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteFlag {
+    pub name: String,
+    pub site_id: Option<String>,
+    pub enabled: bool,
+}
+
+fn current_site(conn: &Connection) -> Result<String, String> {
+    Ok(db::get_config(conn, SITE_CONFIG_KEY)?.unwrap_or_else(|| ALL_SITES.to_string()))
+}
+
+// Resolve a flag for this site: remote before local, site-specific before
+// all-sites. Unknown flags are off.
+pub fn is_enabled(conn: &Connection, flag: &str) -> Result<bool, String> {
+    let site = current_site(conn)?;
+    let enabled: Option<bool> = conn
+        .query_row(
+            "SELECT enabled FROM feature_flags
+             WHERE name = ?1 AND site_id IN (?2, ?3)
+             ORDER BY source = 'remote' DESC, site_id = ?3 ASC
+             LIMIT 1",
+            params![flag, site, ALL_SITES],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(enabled.unwrap_or(false))
+}
+
+// Refuse to go on when a gated feature is off for this site
+pub fn require_enabled(conn: &Connection, flag: &str) -> Result<(), String> {
+    match is_enabled(conn, flag)? {
+        true => Ok(()),
+        false => Err(format!("The {} feature is not enabled on this site", flag)),
+    }
+}
+
+#[tauri::command]
+pub fn is_feature_enabled(app: AppHandle, flag: String) -> Result<bool, String> {
+    let conn = db::open(&app)?;
+    is_enabled(&conn, &flag)
+}
+
+#[tauri::command]
+pub fn list_feature_flags(app: AppHandle) -> Result<Vec<FeatureFlag>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT name, site_id, enabled, source, updated_at FROM feature_flags
+             ORDER BY name, site_id, source",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(FeatureFlag {
+                name: row.get(0)?,
+                site_id: row.get(1)?,
+                enabled: row.get(2)?,
+                source: row.get(3)?,
+                updated_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Set a local flag for one site, or all sites when `site_id` is omitted (admin only)
+#[tauri::command]
+pub fn set_feature_flag(
+    app: AppHandle,
+    flag: String,
+    enabled: bool,
+    site_id: Option<String>,
+    user_id: String,
+) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "set_feature_flag",
+        &user_id,
+        serde_json::json!({ "flag": flag, "enabled": enabled, "site_id": site_id }),
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            conn.execute(
+                "INSERT INTO feature_flags (name, site_id, source, enabled, updated_by)
+                 VALUES (?1, ?2, 'local', ?3, ?4)
+                 ON CONFLICT(name, site_id, source) DO UPDATE SET enabled = excluded.enabled,
+                     updated_by = excluded.updated_by, updated_at = CURRENT_TIMESTAMP",
+                params![
+                    flag,
+                    site_id.as_deref().unwrap_or(ALL_SITES),
+                    enabled,
+                    user_id
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        },
+    )
+}
+
+// Replace all remote flags with the set delivered by the synced config.
+// Called by the sync layer (admin only); an empty list hands control back
+// to local flags.
+#[tauri::command]
+pub fn apply_remote_flags(
+    app: AppHandle,
+    flags: Vec<RemoteFlag>,
+    user_id: String,
+) -> Result<usize, String> {
+    let args = serde_json::json!({ "flags": flags.len() });
+    command_audit::audited(&app, "apply_remote_flags", &user_id, args, || {
+        let mut conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM feature_flags WHERE source = 'remote'", [])
+            .map_err(|e| e.to_string())?;
+        for flag in &flags {
+            tx.execute(
+                "INSERT OR REPLACE INTO feature_flags (name, site_id, source, enabled)
+                 VALUES (?1, ?2, 'remote', ?3)",
+                params![
+                    flag.name,
+                    flag.site_id.as_deref().unwrap_or(ALL_SITES),
+                    flag.enabled
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(flags.len())
+    })
+}
@@ -0,0 +1,407 @@
+// Number formatting for Truckore Pro
+// Indian weighbridge slips group digits in lakhs and crores (12,34,560 kg)
+// and spell the charge out ("Rupees One Thousand Two Hundred Only"), as a
+// cheque does. The site's number format says which grouping and wording to
+// use and what the currency units are called; the PDF slip, the slip layout
+// and the thermal ticket format weights and amounts through it. en-IN is
+// the default. en-US and en-GB group in thousands and count in millions.
+// The format also says how dates are written (dd-mm-yyyy unless set), the
+// unit weights print in, and whether numbers take a decimal comma
+// (1.234.560,50) as in most export markets. It is kept in each company's
+// database, so every company profile has its own; slips, tickets, report
+// and invoice PDFs and CSV exports all go through it.
+
+use crate::command_audit;
+use crate::currency;
+use crate::db;
+use crate::money;
+use crate::roles::{self, Role};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const FORMAT_CONFIG_KEY: &str = "number_format";
+
+const ONES: [&str; 20] = [
+    "Zero",
+    "One",
+    "Two",
+    "Three",
+    "Four",
+    "Five",
+    "Six",
+    "Seven",
+    "Eight",
+    "Nine",
+    "Ten",
+    "Eleven",
+    "Twelve",
+    "Thirteen",
+    "Fourteen",
+    "Fifteen",
+    "Sixteen",
+    "Seventeen",
+    "Eighteen",
+    "Nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "Twenty", "Thirty", "Forty", "Fifty", "Sixty", "Seventy", "Eighty", "Ninety",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "en-IN")]
+    Indian,
+    #[serde(rename = "en-US")]
+    American,
+    #[serde(rename = "en-GB")]
+    British,
+}
+
+impl Locale {
+    pub fn parse(value: &str) -> Result<Locale, String> {
+        match value {
+            "en-IN" => Ok(Locale::Indian),
+            "en-US" => Ok(Locale::American),
+            "en-GB" => Ok(Locale::British),
+            other => Err(format!(
+                "Unsupported locale {} (en-IN, en-US or en-GB)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DateFormat {
+    #[default]
+    #[serde(rename = "dd-mm-yyyy")]
+    DayMonthYear,
+    #[serde(rename = "dd/mm/yyyy")]
+    DayMonthYearSlash,
+    #[serde(rename = "dd.mm.yyyy")]
+    DayMonthYearDot,
+    #[serde(rename = "mm/dd/yyyy")]
+    MonthDayYear,
+    #[serde(rename = "yyyy-mm-dd")]
+    YearMonthDay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WeightUnit {
+    // Whole kilograms
+    #[default]
+    #[serde(rename = "kg")]
+    Kilogram,
+    // Tonnes to three places
+    #[serde(rename = "t")]
+    Tonne,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumberFormat {
+    pub locale: Locale,
+    // Names of the base currency's units, as spelled in words
+    pub currency_major: String,
+    pub currency_minor: String,
+    // Closes an amount in words, e.g. "Only"
+    pub words_suffix: String,
+    // Whether slips and tickets print the amount in words
+    pub amount_in_words: bool,
+    // Point groups and a decimal comma; CSV exports then separate fields
+    // with semicolons, as spreadsheets in those locales expect
+    pub decimal_comma: bool,
+    pub date_format: DateFormat,
+    pub weight_unit: WeightUnit,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            locale: Locale::Indian,
+            currency_major: "Rupees".to_string(),
+            currency_minor: "Paise".to_string(),
+            words_suffix: "Only".to_string(),
+            amount_in_words: true,
+            decimal_comma: false,
+            date_format: DateFormat::default(),
+            weight_unit: WeightUnit::default(),
+        }
+    }
+}
+
+pub fn load(conn: &Connection) -> Result<NumberFormat, String> {
+    match db::get_config(conn, FORMAT_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(NumberFormat::default()),
+    }
+}
+
+// Digits of `n` with the locale's separators: 12,34,567 or 1,234,567
+pub fn group(n: u64, locale: Locale) -> String {
+    let digits = n.to_string();
+    let (head, tail) = digits.split_at(digits.len().saturating_sub(3));
+    let step = match locale {
+        Locale::Indian => 2,
+        Locale::American | Locale::British => 3,
+    };
+    let mut groups: Vec<&str> = Vec::new();
+    let mut end = head.len();
+    while end > 0 {
+        let start = end.saturating_sub(step);
+        groups.push(&head[start..end]);
+        end = start;
+    }
+    groups.reverse();
+    groups.push(tail);
+    groups.join(",")
+}
+
+// With a decimal comma, points group and a comma marks the fraction
+fn separators(text: String, format: &NumberFormat) -> String {
+    match format.decimal_comma {
+        true => text
+            .chars()
+            .map(|c| match c {
+                ',' => '.',
+                '.' => ',',
+                c => c,
+            })
+            .collect(),
+        false => text,
+    }
+}
+
+// `value` rounded to `decimals` places, grouped
+fn grouped(value: f64, decimals: usize, locale: Locale) -> String {
+    let text = format!("{:.*}", decimals, value.abs());
+    let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let sign = if value < 0.0 && text.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        "-"
+    } else {
+        ""
+    };
+    let whole = group(whole.parse().unwrap_or(0), locale);
+    match fraction.is_empty() {
+        true => format!("{}{}", sign, whole),
+        false => format!("{}{}.{}", sign, whole, fraction),
+    }
+}
+
+// `value` rounded to `decimals` places, grouped with the site's separators
+pub fn decimal(value: f64, decimals: usize, format: &NumberFormat) -> String {
+    separators(grouped(value, decimals, format.locale), format)
+}
+
+// A weight in the site's unit, e.g. "12,34,560 kg" or "1.234,560 t"
+pub fn weight(kg: f64, format: &NumberFormat) -> String {
+    match format.weight_unit {
+        WeightUnit::Kilogram => format!("{} kg", decimal(kg, 0, format)),
+        WeightUnit::Tonne => format!("{} t", decimal(kg / 1000.0, 3, format)),
+    }
+}
+
+// Minor units as a grouped amount, e.g. 120050 -> "1,200.50"
+pub fn amount(amount: i64, format: &NumberFormat) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let abs = amount.unsigned_abs();
+    let text = format!(
+        "{}{}.{:02}",
+        sign,
+        group(abs / 100, format.locale),
+        abs % 100
+    );
+    separators(text, format)
+}
+
+// A stored date or timestamp ("2024-03-09", "2024-03-09 14:05:00") in the
+// site's date format, with hours and minutes when it has a time. Text that
+// is not a date comes back as it is.
+pub fn date(text: &str, format: &NumberFormat) -> String {
+    let Some(day) = text.get(..10) else {
+        return text.to_string();
+    };
+    let parts: Vec<&str> = day.split('-').collect();
+    let is_date = matches!(parts.as_slice(), [y, m, d]
+        if y.len() == 4 && m.len() == 2 && d.len() == 2
+            && day.chars().all(|c| c.is_ascii_digit() || c == '-'));
+    if !is_date {
+        return text.to_string();
+    }
+    let (y, m, d) = (parts[0], parts[1], parts[2]);
+    let day = match format.date_format {
+        DateFormat::DayMonthYear => format!("{}-{}-{}", d, m, y),
+        DateFormat::DayMonthYearSlash => format!("{}/{}/{}", d, m, y),
+        DateFormat::DayMonthYearDot => format!("{}.{}.{}", d, m, y),
+        DateFormat::MonthDayYear => format!("{}/{}/{}", m, d, y),
+        DateFormat::YearMonthDay => day.to_string(),
+    };
+    match text.get(11..16).filter(|t| t.as_bytes()[2] == b':') {
+        Some(time) => format!("{} {}", day, time),
+        None => day,
+    }
+}
+
+fn below_thousand(n: u64, locale: Locale) -> String {
+    let mut parts = Vec::new();
+    if n >= 100 {
+        parts.push(format!("{} Hundred", ONES[(n / 100) as usize]));
+    }
+    let rest = n % 100;
+    if rest > 0 {
+        if n >= 100 && locale == Locale::British {
+            parts.push("and".to_string());
+        }
+        parts.push(match rest {
+            0..=19 => ONES[rest as usize].to_string(),
+            _ if rest % 10 == 0 => TENS[(rest / 10) as usize].to_string(),
+            _ => format!(
+                "{} {}",
+                TENS[(rest / 10) as usize],
+                ONES[(rest % 10) as usize]
+            ),
+        });
+    }
+    parts.join(" ")
+}
+
+// `n` in words: crores, lakhs and thousands for en-IN, thousands, millions
+// and billions otherwise
+pub fn words(n: u64, locale: Locale) -> String {
+    if n == 0 {
+        return ONES[0].to_string();
+    }
+    let scales: &[(u64, &str)] = match locale {
+        Locale::Indian => &[
+            (10_000_000, "Crore"),
+            (100_000, "Lakh"),
+            (1_000, "Thousand"),
+        ],
+        Locale::American | Locale::British => &[
+            (1_000_000_000_000, "Trillion"),
+            (1_000_000_000, "Billion"),
+            (1_000_000, "Million"),
+            (1_000, "Thousand"),
+        ],
+    };
+    let mut parts = Vec::new();
+    let mut rest = n;
+    for (scale, name) in scales {
+        if rest >= *scale {
+            // More than a thousand crore is counted in crores
+            parts.push(format!("{} {}", words(rest / scale, locale), name));
+            rest %= scale;
+        }
+    }
+    if rest > 0 {
+        parts.push(below_thousand(rest, locale));
+    }
+    parts.join(" ")
+}
+
+// An amount in minor units in words, e.g. 120050 -> "Rupees One Thousand
+// Two Hundred and Fifty Paise Only"
+pub fn amount_in_words(amount: i64, format: &NumberFormat) -> String {
+    let (major, minor) = (amount.unsigned_abs() / 100, amount.unsigned_abs() % 100);
+    let mut text = format!("{} {}", format.currency_major, words(major, format.locale));
+    if minor > 0 {
+        text.push_str(&format!(
+            " and {} {}",
+            words(minor, format.locale),
+            format.currency_minor
+        ));
+    }
+    if !format.words_suffix.is_empty() {
+        text.push(' ');
+        text.push_str(&format.words_suffix);
+    }
+    match amount < 0 {
+        true => format!("Minus {}", text),
+        false => text.trim().to_string(),
+    }
+}
+
+// The charge of a ticket in words, None when it has none or words are off.
+// A party billed in another currency gets that currency's code, and its
+// minor units as a fraction, as on a cheque.
+pub fn ticket_amount_in_words(
+    conn: &Connection,
+    weighment_id: &str,
+    format: &NumberFormat,
+) -> Result<Option<String>, String> {
+    if !format.amount_in_words {
+        return Ok(None);
+    }
+    if let Some(billing) = currency::load_billing(conn, weighment_id)? {
+        if billing.currency != currency::base_currency(conn)? {
+            let amount = billing.billed_amount_minor;
+            if amount == 0 {
+                return Ok(None);
+            }
+            let abs = amount.unsigned_abs();
+            let mut text = format!("{} {}", billing.currency, words(abs / 100, format.locale));
+            if abs % 100 > 0 {
+                text.push_str(&format!(" and {:02}/100", abs % 100));
+            }
+            text.push(' ');
+            text.push_str(&format.words_suffix);
+            let text = text.trim().to_string();
+            return Ok(Some(match amount < 0 {
+                true => format!("Minus {}", text),
+                false => text,
+            }));
+        }
+    }
+    let amount: i64 = conn
+        .query_row(
+            "SELECT charges_minor FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .unwrap_or(0);
+    Ok((amount != 0).then(|| amount_in_words(amount, format)))
+}
+
+#[tauri::command]
+pub fn get_number_format(app: AppHandle) -> Result<NumberFormat, String> {
+    let conn = db::open(&app)?;
+    load(&conn)
+}
+
+// Admin only
+#[tauri::command]
+pub fn set_number_format(
+    app: AppHandle,
+    format: NumberFormat,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&format).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_number_format", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if format.currency_major.trim().is_empty() || format.currency_minor.trim().is_empty() {
+            return Err("Both currency units need a name".to_string());
+        }
+        let json = serde_json::to_string(&format).map_err(|e| e.to_string())?;
+        db::set_config(&conn, FORMAT_CONFIG_KEY, &json)
+    })
+}
+
+// `amount` in major units (rupees) in words, in `locale` or the site's
+#[tauri::command]
+pub fn format_amount_in_words(
+    app: AppHandle,
+    amount: f64,
+    locale: Option<String>,
+) -> Result<String, String> {
+    let conn = db::open(&app)?;
+    let mut format = load(&conn)?;
+    if let Some(locale) = locale {
+        format.locale = Locale::parse(&locale)?;
+    }
+    Ok(amount_in_words(money::to_minor(amount), &format))
+}
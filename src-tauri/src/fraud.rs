@@ -0,0 +1,304 @@
+// Fraud pattern rules for Truckore Pro
+// Configurable heuristics evaluated when a weighment is completed
+
+use crate::db::{self, DateRange};
+use crate::notifications;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const RULES_CONFIG_KEY: &str = "fraud_rules";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FraudRules {
+    // Flag when a vehicle's tare is exactly the same on this many consecutive tickets
+    pub identical_tare_enabled: bool,
+    pub identical_tare_repeats: i64,
+    // Flag when the same vehicle has another ticket within this many minutes
+    pub rapid_repeat_enabled: bool,
+    pub rapid_repeat_minutes: i64,
+    // Flag tickets edited within this many minutes after being printed
+    pub edit_after_print_enabled: bool,
+    pub edit_after_print_minutes: i64,
+    // Role that receives fraud notifications
+    pub notify_role: String,
+}
+
+impl Default for FraudRules {
+    fn default() -> Self {
+        FraudRules {
+            identical_tare_enabled: true,
+            identical_tare_repeats: 3,
+            rapid_repeat_enabled: true,
+            rapid_repeat_minutes: 15,
+            edit_after_print_enabled: true,
+            edit_after_print_minutes: 60,
+            notify_role: "admin".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FraudFlag {
+    pub id: i64,
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    pub party_name: String,
+    pub rule: String,
+    pub details: String,
+    pub flagged_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RuleCount {
+    pub rule: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FraudReport {
+    pub flags: Vec<FraudFlag>,
+    pub by_rule: Vec<RuleCount>,
+}
+
+pub fn load_rules(conn: &Connection) -> Result<FraudRules, String> {
+    match db::get_config(conn, RULES_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(FraudRules::default()),
+    }
+}
+
+struct Ticket {
+    vehicle_no: String,
+    tare_weight: Option<f64>,
+    first_weight_type: Option<String>,
+    created_at: String,
+    printed_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+fn load_ticket(conn: &Connection, weighment_id: &str) -> Result<Ticket, String> {
+    conn.query_row(
+        "SELECT vehicle_no, tare_weight, first_weight_type, created_at, printed_at, updated_at
+         FROM weighments WHERE id = ?1",
+        [weighment_id],
+        |row| {
+            Ok(Ticket {
+                vehicle_no: row.get(0)?,
+                tare_weight: row.get(1)?,
+                first_weight_type: row.get(2)?,
+                created_at: row.get(3)?,
+                printed_at: row.get(4)?,
+                updated_at: row.get(5)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Weighment {} not found", weighment_id))
+}
+
+fn check_rules(
+    conn: &Connection,
+    rules: &FraudRules,
+    weighment_id: &str,
+    ticket: &Ticket,
+) -> Result<Vec<(&'static str, String)>, String> {
+    let mut hits = Vec::new();
+
+    // Stored-tare (one-time) tickets legitimately repeat the same tare
+    let measured_tare = ticket.first_weight_type.as_deref() != Some("one-time");
+    if let (true, true, Some(tare)) = (
+        rules.identical_tare_enabled,
+        measured_tare,
+        ticket.tare_weight,
+    ) {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT tare_weight FROM weighments
+                 WHERE vehicle_no = ?1 AND id != ?2 AND tare_weight IS NOT NULL
+                   AND created_at <= ?3 AND {}
+                 ORDER BY created_at DESC LIMIT ?4",
+                training::exclude_practice("id")
+            ))
+            .map_err(|e| e.to_string())?;
+        let previous: Vec<f64> = stmt
+            .query_map(
+                params![
+                    ticket.vehicle_no,
+                    weighment_id,
+                    ticket.created_at,
+                    rules.identical_tare_repeats - 1
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+
+        if rules.identical_tare_repeats > 1
+            && previous.len() as i64 == rules.identical_tare_repeats - 1
+            && previous.iter().all(|t| *t == tare)
+        {
+            hits.push((
+                "IDENTICAL_TARE",
+                format!(
+                    "Tare {:.0} kg identical on {} consecutive tickets",
+                    tare, rules.identical_tare_repeats
+                ),
+            ));
+        }
+    }
+
+    if rules.rapid_repeat_enabled {
+        let nearby: i64 = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM weighments
+                     WHERE vehicle_no = ?1 AND id != ?2 AND {}
+                       AND ABS(julianday(created_at) - julianday(?3)) * 1440.0 <= ?4",
+                    training::exclude_practice("id")
+                ),
+                params![
+                    ticket.vehicle_no,
+                    weighment_id,
+                    ticket.created_at,
+                    rules.rapid_repeat_minutes
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if nearby > 0 {
+            hits.push((
+                "RAPID_REPEAT",
+                format!(
+                    "{} other ticket(s) for {} within {} minutes",
+                    nearby, ticket.vehicle_no, rules.rapid_repeat_minutes
+                ),
+            ));
+        }
+    }
+
+    if let (true, Some(printed_at), Some(updated_at)) = (
+        rules.edit_after_print_enabled,
+        ticket.printed_at.as_deref(),
+        ticket.updated_at.as_deref(),
+    ) {
+        let minutes: Option<f64> = conn
+            .query_row(
+                "SELECT (julianday(?1) - julianday(?2)) * 1440.0",
+                [updated_at, printed_at],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if let Some(minutes) = minutes {
+            // Small tolerance: printing itself touches updated_at
+            if minutes > 0.5 && minutes <= rules.edit_after_print_minutes as f64 {
+                hits.push((
+                    "EDIT_AFTER_PRINT",
+                    format!("Edited {:.0} minutes after printing", minutes),
+                ));
+            }
+        }
+    }
+
+    Ok(hits)
+}
+
+// Evaluate fraud rules for a completed weighment, flag hits and notify supervisors.
+// Returns the rules that fired.
+#[tauri::command]
+pub fn evaluate_fraud_rules(app: AppHandle, weighment_id: String) -> Result<Vec<String>, String> {
+    let conn = db::open(&app)?;
+    if training::is_practice(&conn, &weighment_id)? {
+        return Ok(Vec::new());
+    }
+    let rules = load_rules(&conn)?;
+    let ticket = load_ticket(&conn, &weighment_id)?;
+    let hits = check_rules(&conn, &rules, &weighment_id, &ticket)?;
+
+    let mut fired = Vec::new();
+    for (rule, details) in hits {
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO fraud_flags (weighment_id, rule, details) VALUES (?1, ?2, ?3)",
+                params![weighment_id, rule, details],
+            )
+            .map_err(|e| e.to_string())?;
+        if inserted > 0 {
+            notifications::notify(
+                &app,
+                &conn,
+                &rules.notify_role,
+                &format!("Fraud rule {} triggered", rule),
+                &format!("Vehicle {}: {}", ticket.vehicle_no, details),
+                Some(("weighment", &weighment_id)),
+            )?;
+        }
+        fired.push(rule.to_string());
+    }
+
+    Ok(fired)
+}
+
+#[tauri::command]
+pub fn get_fraud_rules(app: AppHandle) -> Result<FraudRules, String> {
+    let conn = db::open(&app)?;
+    load_rules(&conn)
+}
+
+#[tauri::command]
+pub fn set_fraud_rules(app: AppHandle, rules: FraudRules) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    let json = serde_json::to_string(&rules).map_err(|e| e.to_string())?;
+    db::set_config(&conn, RULES_CONFIG_KEY, &json)
+}
+
+// Fraud flags raised for tickets weighed within the range, with per-rule totals
+#[tauri::command]
+pub fn fraud_report(app: AppHandle, range: DateRange) -> Result<FraudReport, String> {
+    let conn = db::open(&app)?;
+
+    let sql = format!(
+        "SELECT f.id, f.weighment_id, w.ticket_no, w.vehicle_no, w.party_name,
+                f.rule, f.details, f.flagged_at
+         FROM fraud_flags f JOIN weighments w ON w.id = f.weighment_id
+         WHERE {} BETWEEN ?1 AND ?2 AND {}
+         ORDER BY f.flagged_at DESC",
+        db::local_date("w.created_at"),
+        training::exclude_practice("w.id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let flags: Vec<FraudFlag> = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok(FraudFlag {
+                id: row.get(0)?,
+                weighment_id: row.get(1)?,
+                ticket_no: row.get(2)?,
+                vehicle_no: row.get(3)?,
+                party_name: row.get(4)?,
+                rule: row.get(5)?,
+                details: row.get(6)?,
+                flagged_at: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut by_rule: Vec<RuleCount> = Vec::new();
+    for flag in &flags {
+        match by_rule.iter_mut().find(|c| c.rule == flag.rule) {
+            Some(c) => c.count += 1,
+            None => by_rule.push(RuleCount {
+                rule: flag.rule.clone(),
+                count: 1,
+            }),
+        }
+    }
+
+    Ok(FraudReport { flags, by_rule })
+}
@@ -0,0 +1,136 @@
+// Headless mode for Truckore Pro
+// For server-closet installs where the weighbridge PC sits next to the
+// indicator and the operators use thin clients over the LAN API. Started
+// with `--headless` or TRUCKORE_HEADLESS=1, the app hides its windows,
+// initialises the database and runs the scale listener and LAN server from
+// the site's headless settings; the sync loop and the other background
+// workers start as they always do. The webview toolkit still needs a
+// display session (a virtual one such as Xvfb will do). An encrypted
+// database is unlocked from TRUCKORE_DB_KEY, or the key remembered on this
+// PC, since there is no one to enter the key.
+
+use crate::crash_reports;
+use crate::db;
+use crate::encryption;
+use crate::lan_server;
+use crate::scale_listener;
+use crate::settings;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+const HEADLESS_FLAG: &str = "--headless";
+const HEADLESS_ENV: &str = "TRUCKORE_HEADLESS";
+const KEY_ENV: &str = "TRUCKORE_DB_KEY";
+const SETTINGS_KEY: &str = "headless_settings";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeadlessSettings {
+    // Address and port the LAN API listens on; beyond loopback it needs
+    // TLS (lan_server.rs)
+    pub lan_host: String,
+    pub lan_port: u16,
+    // Read the indicator from the site's scale settings
+    pub scale_listener: bool,
+}
+
+impl Default for HeadlessSettings {
+    fn default() -> Self {
+        HeadlessSettings {
+            lan_host: lan_server::DEFAULT_HOST.to_string(),
+            lan_port: 8080,
+            scale_listener: true,
+        }
+    }
+}
+
+fn load_settings(conn: &Connection) -> Result<HeadlessSettings, String> {
+    match db::get_config(conn, SETTINGS_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(HeadlessSettings::default()),
+    }
+}
+
+// Command line flag, then environment
+pub fn requested() -> bool {
+    std::env::args().any(|a| a == HEADLESS_FLAG)
+        || std::env::var(HEADLESS_ENV).is_ok_and(|v| v == "1" || v == "true")
+}
+
+// From the environment, else the key remembered on this PC
+fn unlock(app: &AppHandle) -> Result<(), String> {
+    let key = match std::env::var(KEY_ENV) {
+        Ok(key) => {
+            // Kept out of the environment of child processes such as ffmpeg
+            std::env::remove_var(KEY_ENV);
+            key
+        }
+        Err(_) => match settings::remembered_database_key(app)? {
+            Some(key) => key,
+            None => return Ok(()),
+        },
+    };
+    encryption::set_database_key(app.clone(), key)
+}
+
+fn run(app: &AppHandle) -> Result<(), String> {
+    unlock(app)?;
+    crate::init_database(app.clone())?;
+    let settings = {
+        let conn = db::open(app)?;
+        load_settings(&conn)?
+    };
+    // The LAN API is still served without a reader, so clients can see why
+    if settings.scale_listener {
+        if let Err(e) = scale_listener::start_from_settings(app) {
+            crash_reports::report_fatal(app, "headless scale listener", &e);
+        }
+    }
+    tauri::async_runtime::block_on(lan_server::start(
+        app.clone(),
+        Some(settings.lan_host),
+        settings.lan_port,
+    ))
+}
+
+// Called from the Tauri setup hook when headless mode is requested. Windows
+// are hidden rather than closed, since closing the last one exits the app.
+pub fn start(app: AppHandle) {
+    for window in app.windows().values() {
+        let _ = window.hide();
+    }
+    std::thread::spawn(move || {
+        if let Err(e) = run(&app) {
+            crash_reports::report_fatal(&app, "headless start", &e);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_headless_settings(app: AppHandle) -> Result<HeadlessSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+// Applied on the next headless start
+#[tauri::command]
+pub fn set_headless_settings(app: AppHandle, settings: HeadlessSettings) -> Result<(), String> {
+    if settings.lan_port == 0 {
+        return Err("LAN port must be between 1 and 65535".to_string());
+    }
+    if settings
+        .lan_host
+        .trim()
+        .parse::<std::net::IpAddr>()
+        .is_err()
+    {
+        return Err(format!(
+            "Not an IP address to listen on: {}",
+            settings.lan_host
+        ));
+    }
+    let conn = db::open(&app)?;
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    db::set_config(&conn, SETTINGS_KEY, &json)
+}
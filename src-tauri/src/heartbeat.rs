@@ -0,0 +1,258 @@
+// Monitoring heartbeat for Truckore Pro
+// A weighbridge PC that has stopped (a dead indicator cable, a crashed app,
+// a disk that filled up) is usually reported by the customer, days later.
+// When the site opts in, and its license covers monitoring, a small report
+// is posted to the dealer's monitoring endpoint every few minutes: the app
+// version, when the last ticket was made, what the indicator last reported
+// and how many changes are waiting to sync. A site that goes quiet shows up
+// on the dealer's side. The report names the license, never a party, a
+// vehicle or a weight.
+
+use crate::command_audit;
+use crate::communications;
+use crate::db;
+use crate::license;
+use crate::network;
+use crate::roles::{self, Role};
+use crate::scale_listener;
+use crate::settings;
+use crate::shutdown;
+use crate::sync_engine;
+use crate::training;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const SETTINGS_CONFIG_KEY: &str = "heartbeat_settings";
+const FEATURE: &str = "monitoring";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+// How often the loop looks at the settings
+const TICK: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatSettings {
+    pub enabled: bool,
+    // Monitoring endpoint URL, https only
+    pub endpoint: String,
+    pub interval_seconds: u64,
+    // Name of the stored secret sent as a bearer token, if the endpoint wants one
+    pub auth_secret: Option<String>,
+}
+
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        HeartbeatSettings {
+            enabled: false,
+            endpoint: String::new(),
+            interval_seconds: 300,
+            auth_secret: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    // None while the installation runs on its trial
+    pub license_id: Option<String>,
+    pub customer: Option<String>,
+    pub fingerprint: String,
+    pub app_version: String,
+    // UTC, ISO-8601
+    pub sent_at: String,
+    // Creation time of the newest real ticket
+    pub last_ticket_at: Option<String>,
+    // connected, disconnected, stopped or reload_failed as the indicator
+    // listener last reported it; not_running when it has not run
+    pub scale_state: String,
+    pub scale_error: Option<String>,
+    pub sync_state: String,
+    // Local changes the sync server has not acknowledged
+    pub pending_sync: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeartbeatStatus {
+    pub last_attempt_at: Option<String>,
+    pub last_sent_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+// Managed state: how the last heartbeat went
+#[derive(Default)]
+pub struct HeartbeatMonitor(Mutex<HeartbeatStatus>);
+
+fn load_settings(conn: &Connection) -> Result<HeartbeatSettings, String> {
+    match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(HeartbeatSettings::default()),
+    }
+}
+
+fn build(app: &AppHandle, conn: &Connection) -> Result<Heartbeat, String> {
+    let license = license::status(app)?;
+    let (sent_at, last_ticket_at): (String, Option<String>) = conn
+        .query_row(
+            &format!(
+                "SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), MAX(w.created_at)
+                 FROM weighments w WHERE {}",
+                training::exclude_practice("w.id")
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let scale = scale_listener::last_status(app);
+    let sync = sync_engine::status(app);
+    Ok(Heartbeat {
+        license_id: license.license.as_ref().map(|l| l.license_id.clone()),
+        customer: license.license.as_ref().map(|l| l.customer.clone()),
+        fingerprint: license.fingerprint,
+        app_version: app.package_info().version.to_string(),
+        sent_at,
+        last_ticket_at,
+        scale_state: scale
+            .as_ref()
+            .map(|s| s.state.clone())
+            .unwrap_or_else(|| "not_running".to_string()),
+        scale_error: scale.and_then(|s| s.error),
+        sync_state: sync.state,
+        pending_sync: sync.pending,
+    })
+}
+
+// Build a heartbeat and post it to the endpoint
+fn post(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &HeartbeatSettings,
+) -> Result<Heartbeat, String> {
+    communications::require_outbound(app)?;
+    license::covers(app, FEATURE)?;
+    let heartbeat = build(app, conn)?;
+    let agent = network::http_agent(conn, &settings.endpoint, REQUEST_TIMEOUT)?;
+    let mut request = agent
+        .post(&settings.endpoint)
+        .set("Content-Type", "application/json");
+    if let Some(name) = &settings.auth_secret {
+        let token = settings::secret(app, conn, name)?
+            .ok_or_else(|| format!("No secret named {} is stored", name))?;
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let body = serde_json::to_string(&heartbeat).map_err(|e| e.to_string())?;
+    request.send_string(&body).map_err(|e| match e {
+        ureq::Error::Status(code, _) => format!("Monitoring endpoint answered HTTP {}", code),
+        other => other.to_string(),
+    })?;
+    Ok(heartbeat)
+}
+
+// Post one heartbeat and record how it went for get_heartbeat_status
+fn send(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &HeartbeatSettings,
+) -> Result<Heartbeat, String> {
+    let result = post(app, conn, settings);
+    if let Ok(mut status) = app.state::<HeartbeatMonitor>().0.lock() {
+        let now: Option<String> = conn
+            .query_row("SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now')", [], |row| {
+                row.get(0)
+            })
+            .ok();
+        status.last_attempt_at = now.clone();
+        match &result {
+            Ok(_) => {
+                status.last_sent_at = now;
+                status.last_error = None;
+            }
+            Err(e) => status.last_error = Some(e.clone()),
+        }
+    }
+    if let Err(e) = &result {
+        tracing::warn!(error = %e, "Heartbeat failed");
+    }
+    result
+}
+
+// Background heartbeat; started from the app setup hook. Settings are read
+// each tick, so enabling it or changing the interval needs no restart.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut last_sent: Option<Instant> = None;
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            if let Ok(conn) = db::open(&app) {
+                if let Ok(settings) = load_settings(&conn) {
+                    let interval = Duration::from_secs(settings.interval_seconds);
+                    let due = last_sent.map_or(true, |at| at.elapsed() >= interval);
+                    if settings.enabled && !settings.endpoint.is_empty() && due {
+                        last_sent = Some(Instant::now());
+                        let _ = send(&app, &conn, &settings);
+                    }
+                }
+            }
+            std::thread::sleep(TICK);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_heartbeat_settings(app: AppHandle) -> Result<HeartbeatSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+// Admin only
+#[tauri::command]
+pub fn set_heartbeat_settings(
+    app: AppHandle,
+    settings: HeartbeatSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_heartbeat_settings", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut settings = settings;
+        settings.endpoint = settings.endpoint.trim().to_string();
+        if (settings.enabled || !settings.endpoint.is_empty())
+            && !settings.endpoint.starts_with("https://")
+        {
+            return Err("Monitoring endpoint URL must start with https://".to_string());
+        }
+        if settings.interval_seconds < 60 {
+            return Err("Heartbeat interval must be at least 60 seconds".to_string());
+        }
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)
+    })
+}
+
+#[tauri::command]
+pub fn get_heartbeat_status(app: AppHandle) -> Result<HeartbeatStatus, String> {
+    let status = app.state::<HeartbeatMonitor>();
+    let status = status.0.lock().map_err(|e| e.to_string())?;
+    Ok(status.clone())
+}
+
+// What a heartbeat would report, so the site can see exactly what is shared
+#[tauri::command]
+pub fn preview_heartbeat(app: AppHandle) -> Result<Heartbeat, String> {
+    let conn = db::open(&app)?;
+    build(&app, &conn)
+}
+
+// Send a heartbeat now, to test the endpoint (admin only)
+#[tauri::command]
+pub fn send_heartbeat(app: AppHandle, user_id: String) -> Result<Heartbeat, String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let settings = load_settings(&conn)?;
+    if settings.endpoint.is_empty() {
+        return Err("No monitoring endpoint is set".to_string());
+    }
+    send(&app, &conn, &settings)
+}
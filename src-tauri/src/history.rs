@@ -0,0 +1,97 @@
+// Per-ticket change history for Truckore Pro
+// Merges the weighment's own milestones with amendments, void requests and
+// voids into one chronological trail, for settling disputes in the app.
+
+use crate::db;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub at: String,
+    pub event: String,
+    pub user_id: Option<String>,
+    pub field: Option<String>,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub reason: Option<String>,
+}
+
+// Every source yields (at, event, user_id, field, old_value, new_value, reason, seq);
+// seq keeps same-instant events in a sensible order
+const HISTORY_SQL: &str = "
+  SELECT * FROM (
+    SELECT created_at AS at, 'CREATED', NULL,
+           CASE first_weight_type WHEN 'tare' THEN 'tare_weight' ELSE 'gross_weight' END, NULL,
+           CAST(CASE first_weight_type WHEN 'tare' THEN tare_weight ELSE gross_weight END AS TEXT),
+           NULL, 0 AS seq
+    FROM weighments WHERE id = ?1
+    UNION ALL
+    SELECT second_weight_timestamp, 'SECOND_WEIGHT', NULL,
+           CASE first_weight_type WHEN 'tare' THEN 'gross_weight' ELSE 'tare_weight' END, NULL,
+           CAST(CASE first_weight_type WHEN 'tare' THEN gross_weight ELSE tare_weight END AS TEXT),
+           NULL, 1
+    FROM weighments WHERE id = ?1 AND second_weight_timestamp IS NOT NULL
+    UNION ALL
+    SELECT closed_at, 'CLOSED', NULL, 'net_weight', NULL, CAST(net_weight AS TEXT), NULL, 2
+    FROM weighments WHERE id = ?1 AND closed_at IS NOT NULL
+    UNION ALL
+    SELECT printed_at, 'PRINTED', NULL, NULL, NULL, NULL, NULL, 3
+    FROM weighments WHERE id = ?1 AND printed_at IS NOT NULL
+    UNION ALL
+    SELECT requested_at, 'AMENDMENT_REQUESTED', requested_by, field, old_value, new_value,
+           reason, 4
+    FROM weighment_amendments WHERE weighment_id = ?1
+    UNION ALL
+    SELECT decided_at, 'AMENDMENT_' || status, decided_by, field, old_value, new_value,
+           decision_comment, 5
+    FROM weighment_amendments WHERE weighment_id = ?1 AND status != 'PENDING'
+    UNION ALL
+    SELECT requested_at, 'VOID_REQUESTED', requested_by, NULL, NULL, NULL,
+           json_extract(payload, '$.reason'), 6
+    FROM approvals WHERE kind = 'void' AND entity = 'weighment' AND entity_id = ?1
+    UNION ALL
+    SELECT decided_at, 'VOID_REJECTED', decided_by, NULL, NULL, NULL, comment, 7
+    FROM approvals
+    WHERE kind = 'void' AND entity = 'weighment' AND entity_id = ?1 AND status = 'REJECTED'
+    UNION ALL
+    SELECT voided_at, 'VOIDED', voided_by, NULL, NULL, NULL, reason, 8
+    FROM ticket_voids WHERE weighment_id = ?1
+  )
+  ORDER BY julianday(at), seq";
+
+// Every change made to a weighment, oldest first
+#[tauri::command]
+pub fn weighment_history(
+    app: AppHandle,
+    weighment_id: String,
+) -> Result<Vec<HistoryEntry>, String> {
+    let conn = db::open(&app)?;
+    conn.query_row(
+        "SELECT 1 FROM weighments WHERE id = ?1",
+        [&weighment_id],
+        |_| Ok(()),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Weighment {} not found", weighment_id))?;
+
+    let mut stmt = conn.prepare(HISTORY_SQL).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([&weighment_id], |row| {
+            Ok(HistoryEntry {
+                at: row.get(0)?,
+                event: row.get(1)?,
+                user_id: row.get(2)?,
+                field: row.get(3)?,
+                old_value: row.get(4)?,
+                new_value: row.get(5)?,
+                reason: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
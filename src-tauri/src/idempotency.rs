@@ -0,0 +1,66 @@
+// Idempotency keys for Truckore Pro commands
+// A caller sends the same key when it retries (double-clicked Save, IPC
+// retry). The first call's result id is stored under the key; later calls
+// with that key get the stored id back instead of repeating the write.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+// Keys are only remembered long enough to cover retries
+const RETENTION_HOURS: i64 = 24;
+
+pub fn digest(request: &serde_json::Value) -> String {
+    Sha256::digest(request.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Result id stored for `key`, if the command already ran with it. Fails if
+// the key was used for a different command or with different arguments.
+pub fn lookup(
+    conn: &Connection,
+    key: &str,
+    command: &str,
+    request_digest: &str,
+) -> Result<Option<String>, String> {
+    conn.execute(
+        "DELETE FROM idempotency_keys
+         WHERE (julianday('now') - julianday(created_at)) * 24.0 > ?1",
+        [RETENTION_HOURS],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let stored: Option<(String, String, String)> = conn
+        .query_row(
+            "SELECT command, request_digest, result_id FROM idempotency_keys WHERE key = ?1",
+            [key],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match stored {
+        None => Ok(None),
+        Some((c, d, result_id)) if c == command && d == request_digest => Ok(Some(result_id)),
+        Some(_) => Err(format!(
+            "Idempotency key {} was already used for a different request",
+            key
+        )),
+    }
+}
+
+pub fn record(
+    conn: &Connection,
+    key: &str,
+    command: &str,
+    request_digest: &str,
+    result_id: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO idempotency_keys (key, command, request_digest, result_id)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![key, command, request_digest, result_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,175 @@
+// Stock ledger for Truckore Pro
+// Per-material balances posted from completed weighments (inbound adds,
+// outbound removes) plus manual adjustment entries. Voided tickets drop out
+// of every balance without deleting their ledger rows.
+
+use crate::command_audit;
+use crate::db::{self, DateRange};
+use crate::movements;
+use crate::roles::{self, Role};
+use crate::training;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockBalance {
+    pub product_name: String,
+    pub as_of: String,
+    pub balance_kg: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockStatementLine {
+    pub product_name: String,
+    pub opening_kg: f64,
+    pub inbound_kg: f64,
+    pub outbound_kg: f64,
+    pub adjustment_kg: f64,
+    pub closing_kg: f64,
+}
+
+// Ledger rows that count towards balances
+fn live_entries() -> &'static str {
+    "(weighment_id IS NULL OR weighment_id NOT IN (SELECT weighment_id FROM ticket_voids))"
+}
+
+// (Re)post the ledger entry for a completed weighment from its current net and
+// direction. Practice tickets and tickets without a net weight are not posted.
+pub fn post_weighment(conn: &Connection, weighment_id: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM stock_ledger WHERE weighment_id = ?1 AND entry_type = 'WEIGHMENT'",
+        [weighment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    if training::is_practice(conn, weighment_id)? {
+        return Ok(());
+    }
+    conn.execute(
+        &format!(
+            "INSERT INTO stock_ledger (product_name, weighment_id, entry_type, quantity_kg, posted_at)
+             SELECT w.product_name, w.id, 'WEIGHMENT',
+                    CASE {} WHEN 'OUTBOUND' THEN -w.net_weight ELSE w.net_weight END,
+                    COALESCE(w.closed_at, w.updated_at, CURRENT_TIMESTAMP)
+             FROM weighments w
+             WHERE w.id = ?1 AND w.net_weight IS NOT NULL",
+            movements::direction_sql("w")
+        ),
+        [weighment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn balance(conn: &Connection, product_name: &str, as_of: &str) -> Result<f64, String> {
+    conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(quantity_kg), 0) FROM stock_ledger
+             WHERE product_name = ?1 AND {} <= ?2 AND {}",
+            db::local_date("posted_at"),
+            live_entries()
+        ),
+        params![product_name, as_of],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Balance of a material at the end of `as_of` ("YYYY-MM-DD"), today if omitted
+#[tauri::command]
+pub fn get_stock_balance(
+    app: AppHandle,
+    material: String,
+    as_of: Option<String>,
+) -> Result<StockBalance, String> {
+    let conn = db::open(&app)?;
+    let as_of = match as_of {
+        Some(date) => date,
+        None => conn
+            .query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?,
+    };
+    let balance_kg = balance(&conn, &material, &as_of)?;
+    Ok(StockBalance {
+        product_name: material,
+        as_of,
+        balance_kg,
+    })
+}
+
+// Record a manual stock adjustment (admin only); negative quantities reduce stock
+#[tauri::command]
+pub fn adjust_stock(
+    app: AppHandle,
+    material: String,
+    quantity_kg: f64,
+    reason: String,
+    user_id: String,
+) -> Result<i64, String> {
+    let args = serde_json::json!({ "material": material, "quantity_kg": quantity_kg });
+    command_audit::audited(&app, "adjust_stock", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if quantity_kg == 0.0 {
+            return Err("Adjustment quantity must not be zero".to_string());
+        }
+        if reason.trim().is_empty() {
+            return Err("A reason is required for stock adjustments".to_string());
+        }
+        conn.execute(
+            "INSERT INTO stock_ledger (product_name, entry_type, quantity_kg, reason, created_by)
+             VALUES (?1, 'ADJUSTMENT', ?2, ?3, ?4)",
+            params![material, quantity_kg, reason.trim(), user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+// Opening balance, movements and closing balance per material for the range
+#[tauri::command]
+pub fn stock_statement(
+    app: AppHandle,
+    range: DateRange,
+    material: Option<String>,
+) -> Result<Vec<StockStatementLine>, String> {
+    let conn = db::open(&app)?;
+    let sql = format!(
+        "SELECT product_name,
+                SUM(CASE WHEN day < ?1 THEN quantity_kg ELSE 0 END),
+                SUM(CASE WHEN day >= ?1 AND entry_type = 'WEIGHMENT' AND quantity_kg > 0
+                         THEN quantity_kg ELSE 0 END),
+                SUM(CASE WHEN day >= ?1 AND entry_type = 'WEIGHMENT' AND quantity_kg < 0
+                         THEN -quantity_kg ELSE 0 END),
+                SUM(CASE WHEN day >= ?1 AND entry_type = 'ADJUSTMENT' THEN quantity_kg ELSE 0 END)
+         FROM (
+             SELECT product_name, entry_type, quantity_kg, {day} AS day
+             FROM stock_ledger
+             WHERE {day} <= ?2 AND {live} AND (?3 IS NULL OR product_name = ?3)
+         )
+         GROUP BY product_name
+         ORDER BY product_name",
+        day = db::local_date("posted_at"),
+        live = live_entries()
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to, material], |row| {
+            let opening_kg: f64 = row.get(1)?;
+            let inbound_kg: f64 = row.get(2)?;
+            let outbound_kg: f64 = row.get(3)?;
+            let adjustment_kg: f64 = row.get(4)?;
+            Ok(StockStatementLine {
+                product_name: row.get(0)?,
+                opening_kg,
+                inbound_kg,
+                outbound_kg,
+                adjustment_kg,
+                closing_kg: opening_kg + inbound_kg - outbound_kg + adjustment_kg,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,439 @@
+// Consolidated monthly invoicing for Truckore Pro
+// Parties with a standing billing instruction are invoiced once a month: a
+// background job gathers the previous month's billed tickets into one
+// invoice per party, in the party's billing currency. Tickets billed in
+// another currency (after a currency change) are left for a manual statement.
+
+use crate::command_audit;
+use crate::currency::{self, PartyStatementLine};
+use crate::db;
+use crate::formatting::{self, NumberFormat};
+use crate::notifications;
+use crate::pdf::{self, Line, PdfOutput};
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BillingInstruction {
+    pub party_name: String,
+    pub cycle: String,
+    pub active: bool,
+    pub updated_by: Option<String>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsolidatedInvoice {
+    pub id: i64,
+    pub invoice_no: String,
+    pub party_name: String,
+    // "YYYY-MM"
+    pub period: String,
+    pub currency: String,
+    pub ticket_count: i64,
+    pub net_weight_kg: f64,
+    pub total_base_minor: i64,
+    pub total_billed_minor: i64,
+    pub generated_by: Option<String>,
+    pub generated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceDetail {
+    pub invoice: ConsolidatedInvoice,
+    pub lines: Vec<PartyStatementLine>,
+}
+
+const INVOICE_COLUMNS: &str = "id, invoice_no, party_name, period, currency, ticket_count,
+     net_weight_kg, total_base_minor, total_billed_minor, generated_by, generated_at";
+
+fn row_to_invoice(row: &rusqlite::Row) -> rusqlite::Result<ConsolidatedInvoice> {
+    Ok(ConsolidatedInvoice {
+        id: row.get(0)?,
+        invoice_no: row.get(1)?,
+        party_name: row.get(2)?,
+        period: row.get(3)?,
+        currency: row.get(4)?,
+        ticket_count: row.get(5)?,
+        net_weight_kg: row.get(6)?,
+        total_base_minor: row.get(7)?,
+        total_billed_minor: row.get(8)?,
+        generated_by: row.get(9)?,
+        generated_at: row.get(10)?,
+    })
+}
+
+// Billed ticket lines matching `filter` (a condition on w and b)
+fn statement_lines(
+    conn: &Connection,
+    filter: &str,
+    args: &[&dyn ToSql],
+) -> Result<Vec<PartyStatementLine>, String> {
+    let sql = format!(
+        "SELECT w.id, w.ticket_no, {day}, w.product_name, w.net_weight,
+                b.exchange_rate, b.base_amount_minor, b.billed_amount_minor
+         FROM weighments w JOIN weighment_billing b ON b.weighment_id = w.id
+         WHERE {filter}
+         ORDER BY w.created_at",
+        day = db::local_date("w.created_at"),
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(args, |row| {
+            Ok(PartyStatementLine {
+                weighment_id: row.get(0)?,
+                ticket_no: row.get(1)?,
+                date: row.get(2)?,
+                product_name: row.get(3)?,
+                net_weight: row.get(4)?,
+                exchange_rate: row.get(5)?,
+                base_amount_minor: row.get(6)?,
+                billed_amount_minor: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn load_invoice(conn: &Connection, invoice_id: i64) -> Result<ConsolidatedInvoice, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM consolidated_invoices WHERE id = ?1",
+            INVOICE_COLUMNS
+        ),
+        [invoice_id],
+        row_to_invoice,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Invoice {} not found", invoice_id))
+}
+
+fn previous_month(conn: &Connection) -> Result<String, String> {
+    conn.query_row(
+        "SELECT strftime('%Y-%m', 'now', 'localtime', 'start of month', '-1 month')",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Only months that have ended can be invoiced; later tickets would be missed
+fn check_period(conn: &Connection, period: &str) -> Result<(), String> {
+    let b = period.as_bytes();
+    let valid = b.len() == 7
+        && b[4] == b'-'
+        && period[..4].parse::<u16>().is_ok()
+        && matches!(period[5..].parse::<u8>(), Ok(1..=12));
+    if !valid {
+        return Err(format!("Invoice period must be YYYY-MM, got {}", period));
+    }
+    let current: String = conn
+        .query_row("SELECT strftime('%Y-%m', 'now', 'localtime')", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    if period >= current.as_str() {
+        return Err(format!("{} has not ended yet", period));
+    }
+    Ok(())
+}
+
+// Invoice one party for the period. Returns None when it has nothing to bill.
+fn invoice_party(
+    conn: &Connection,
+    party_name: &str,
+    period: &str,
+    generated_by: Option<&str>,
+) -> Result<Option<ConsolidatedInvoice>, String> {
+    let currency = currency::party_currency(conn, party_name)?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let filter = format!(
+        "w.party_name = ?1 AND b.currency = ?2 AND substr({}, 1, 7) = ?3
+         AND w.id NOT IN (SELECT weighment_id FROM consolidated_invoice_lines)
+         AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}",
+        db::local_date("w.created_at"),
+        training::exclude_practice("w.id")
+    );
+    let lines = statement_lines(&tx, &filter, &[&party_name, &currency, &period])?;
+    if lines.is_empty() {
+        return Ok(None);
+    }
+
+    let sequence: i64 = tx
+        .query_row(
+            "SELECT COUNT(*) + 1 FROM consolidated_invoices WHERE period = ?1",
+            [period],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let invoice_no = format!("INV-{}-{:03}", period.replace('-', ""), sequence);
+    tx.execute(
+        "INSERT INTO consolidated_invoices
+             (invoice_no, party_name, period, currency, ticket_count, net_weight_kg,
+              total_base_minor, total_billed_minor, generated_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            invoice_no,
+            party_name,
+            period,
+            currency,
+            lines.len() as i64,
+            lines.iter().filter_map(|l| l.net_weight).sum::<f64>(),
+            lines.iter().map(|l| l.base_amount_minor).sum::<i64>(),
+            lines.iter().map(|l| l.billed_amount_minor).sum::<i64>(),
+            generated_by
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let invoice_id = tx.last_insert_rowid();
+    for line in &lines {
+        tx.execute(
+            "INSERT INTO consolidated_invoice_lines (weighment_id, invoice_id) VALUES (?1, ?2)",
+            params![line.weighment_id, invoice_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    let invoice = load_invoice(&tx, invoice_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(Some(invoice))
+}
+
+// Invoice every party with an active instruction that has no invoice for the
+// period yet. Safe to run repeatedly.
+pub fn generate(
+    conn: &Connection,
+    period: &str,
+    generated_by: Option<&str>,
+) -> Result<Vec<ConsolidatedInvoice>, String> {
+    check_period(conn, period)?;
+    let parties: Vec<String> = conn
+        .prepare(
+            "SELECT party_name FROM billing_instructions
+             WHERE active = 1 AND party_name NOT IN
+                 (SELECT party_name FROM consolidated_invoices WHERE period = ?1)
+             ORDER BY party_name",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map([period], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut invoices = Vec::new();
+    for party_name in parties {
+        if let Some(invoice) = invoice_party(conn, &party_name, period, generated_by)? {
+            invoices.push(invoice);
+        }
+    }
+    Ok(invoices)
+}
+
+fn tick(app: &AppHandle) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let period = previous_month(&conn)?;
+    let invoices = generate(&conn, &period, None)?;
+    if !invoices.is_empty() {
+        notifications::notify(
+            app,
+            &conn,
+            "admin",
+            "Monthly invoices generated",
+            &format!("{} consolidated invoice(s) for {}", invoices.len(), period),
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+// Invoice the previous month in the background once it has ended
+pub fn start_scheduler(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            let _ = tick(&app);
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+fn statement_pdf(detail: &InvoiceDetail, format: &NumberFormat) -> Vec<u8> {
+    let invoice = &detail.invoice;
+    let amount = |minor: i64| format!("{} {}", formatting::amount(minor, format), invoice.currency);
+    let rule = "-".repeat(76);
+    let mut lines = vec![
+        Line::bold(format!("CONSOLIDATED INVOICE {}", invoice.invoice_no)),
+        Line::plain(""),
+        Line::plain(format!("Party:     {}", invoice.party_name)),
+        Line::plain(format!("Period:    {}", invoice.period)),
+        Line::plain(format!("Currency:  {}", invoice.currency)),
+        Line::plain(format!(
+            "Generated: {}",
+            formatting::date(&invoice.generated_at, format)
+        )),
+        Line::plain(""),
+        Line::bold(format!(
+            "{:<14} {:<10} {:<22} {:>12} {:>14}",
+            "Ticket", "Date", "Material", "Net", "Amount"
+        )),
+        Line::plain(rule.clone()),
+    ];
+    for line in &detail.lines {
+        let material: String = line.product_name.chars().take(22).collect();
+        lines.push(Line::plain(format!(
+            "{:<14} {:<10} {:<22} {:>12} {:>14}",
+            line.ticket_no,
+            formatting::date(&line.date, format),
+            material,
+            formatting::weight(line.net_weight.unwrap_or(0.0), format),
+            formatting::amount(line.billed_amount_minor, format)
+        )));
+    }
+    lines.push(Line::plain(rule));
+    lines.push(Line::bold(format!(
+        "{:<14} {:<33} {:>12} {:>14}",
+        format!("{} tickets", invoice.ticket_count),
+        "",
+        formatting::weight(invoice.net_weight_kg, format),
+        formatting::amount(invoice.total_billed_minor, format)
+    )));
+    lines.push(Line::plain(""));
+    lines.push(Line::bold(format!(
+        "Total due: {}",
+        amount(invoice.total_billed_minor)
+    )));
+    pdf::render(&lines)
+}
+
+#[tauri::command]
+pub fn list_billing_instructions(app: AppHandle) -> Result<Vec<BillingInstruction>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT party_name, cycle, active, updated_by, updated_at
+             FROM billing_instructions ORDER BY party_name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(BillingInstruction {
+                party_name: row.get(0)?,
+                cycle: row.get(1)?,
+                active: row.get(2)?,
+                updated_by: row.get(3)?,
+                updated_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Start or stop monthly billing for a party (supervisors only)
+#[tauri::command]
+pub fn set_billing_instruction(
+    app: AppHandle,
+    party_name: String,
+    active: bool,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "party_name": party_name, "active": active });
+    command_audit::audited(&app, "set_billing_instruction", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        conn.execute(
+            "INSERT INTO billing_instructions (party_name, active, updated_by) VALUES (?1, ?2, ?3)
+             ON CONFLICT(party_name) DO UPDATE SET active = excluded.active,
+                 updated_by = excluded.updated_by, updated_at = CURRENT_TIMESTAMP",
+            params![party_name, active, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+// Generate invoices now for `period` ("YYYY-MM", default last month) instead
+// of waiting for the background job (supervisors only)
+#[tauri::command]
+pub fn generate_consolidated_invoices(
+    app: AppHandle,
+    period: Option<String>,
+    user_id: String,
+) -> Result<Vec<ConsolidatedInvoice>, String> {
+    let args = serde_json::json!({ "period": period });
+    command_audit::audited(
+        &app,
+        "generate_consolidated_invoices",
+        &user_id,
+        args,
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            let period = match &period {
+                Some(period) => period.clone(),
+                None => previous_month(&conn)?,
+            };
+            generate(&conn, &period, Some(&user_id))
+        },
+    )
+}
+
+#[tauri::command]
+pub fn list_consolidated_invoices(
+    app: AppHandle,
+    period: Option<String>,
+    party_name: Option<String>,
+) -> Result<Vec<ConsolidatedInvoice>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM consolidated_invoices
+             WHERE (?1 IS NULL OR period = ?1) AND (?2 IS NULL OR party_name = ?2)
+             ORDER BY period DESC, invoice_no",
+            INVOICE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![period, party_name], row_to_invoice)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_consolidated_invoice(app: AppHandle, invoice_id: i64) -> Result<InvoiceDetail, String> {
+    let conn = db::open(&app)?;
+    let invoice = load_invoice(&conn, invoice_id)?;
+    let lines = statement_lines(
+        &conn,
+        "w.id IN (SELECT weighment_id FROM consolidated_invoice_lines WHERE invoice_id = ?1)",
+        &[&invoice_id],
+    )?;
+    Ok(InvoiceDetail { invoice, lines })
+}
+
+// Statement PDF listing every ticket on the invoice, written to `dest_path`
+// or returned base64 encoded for preview
+#[tauri::command]
+pub fn consolidated_invoice_pdf(
+    app: AppHandle,
+    invoice_id: i64,
+    dest_path: Option<String>,
+) -> Result<PdfOutput, String> {
+    let conn = db::open(&app)?;
+    let format = formatting::load(&conn)?;
+    drop(conn);
+    let detail = get_consolidated_invoice(app, invoice_id)?;
+    pdf::deliver(statement_pdf(&detail, &format), dest_path)
+}
@@ -0,0 +1,378 @@
+// Embedded HTTP server for Truckore Pro
+// Serves LAN / mobile API routes from the desktop app on a configurable port.
+// Terminals in the cabin send passwords and tickets over the site network,
+// so beyond loopback it only serves HTTPS (and WSS), with a certificate the
+// site supplies as PEM files; plain HTTP is for this computer alone.
+
+use crate::command_audit;
+use crate::db;
+use crate::lan_terminal;
+use crate::license;
+use crate::mobile_api;
+use crate::roles::{self, Role};
+use crate::slip_verification;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use rusqlite::Connection;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::net::{IpAddr, TcpListener};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::watch;
+
+const TLS_CONFIG_KEY: &str = "lan_server_tls";
+// All interfaces, unless the caller names one
+pub const DEFAULT_HOST: &str = "0.0.0.0";
+// How long requests in flight may take to finish once stopped
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+// Premium feature a license must cover (license.rs)
+const LICENSE_FEATURE: &str = "lan_server";
+
+// Managed state holding the running server, if any
+#[derive(Default)]
+pub struct LanServer(Mutex<Option<RunningServer>>);
+
+pub struct RunningServer {
+    port: u16,
+    // Set to true to stop; connections and live feeds watch it too
+    shutdown: watch::Sender<bool>,
+}
+
+impl LanServer {
+    pub fn stop(&self) -> Result<(), String> {
+        if let Some(running) = self.0.lock().map_err(|e| e.to_string())?.take() {
+            let _ = running.shutdown.send(true);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LanTlsSettings {
+    pub enabled: bool,
+    // PEM certificate chain, server certificate first
+    pub cert_path: String,
+    // PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: String,
+}
+
+// Shared state handed to every route handler
+#[derive(Clone)]
+pub struct ApiState {
+    pub app: AppHandle,
+    // Becomes true when the server stops
+    pub stopped: watch::Receiver<bool>,
+}
+
+// Error returned by route handlers as `{ "error": message }`
+pub struct ApiError(pub StatusCode, pub String);
+
+impl ApiError {
+    pub fn unauthorized(message: &str) -> Self {
+        ApiError(StatusCode::UNAUTHORIZED, message.to_string())
+    }
+
+    pub fn not_found(message: &str) -> Self {
+        ApiError(StatusCode::NOT_FOUND, message.to_string())
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, message.to_string())
+    }
+}
+
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+// Run a database closure off the async runtime threads
+pub async fn with_db<T, F>(state: &ApiState, f: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> Result<T, ApiError> + Send + 'static,
+{
+    let app = state.app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&app)?;
+        f(&conn)
+    })
+    .await
+    .map_err(|e| ApiError::from(e.to_string()))?
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .nest("/mobile/v1", mobile_api::routes())
+        .nest("/lan/v1", lan_terminal::routes())
+        .merge(slip_verification::routes())
+        .with_state(state)
+}
+
+fn load_tls(conn: &Connection) -> Result<LanTlsSettings, String> {
+    match db::get_config(conn, TLS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(LanTlsSettings::default()),
+    }
+}
+
+// Resolves once the server is stopped
+pub async fn until_stopped(stopped: &mut watch::Receiver<bool>) {
+    let _ = stopped.wait_for(|stopped| *stopped).await;
+}
+
+// Server settings for the certificate chain and private key in PEM files
+pub fn server_config(cert_path: &str, key_path: &str) -> Result<Arc<ServerConfig>, String> {
+    let cert_pem =
+        std::fs::read(cert_path).map_err(|e| format!("Failed to read {}: {}", cert_path, e))?;
+    let key_pem =
+        std::fs::read(key_path).map_err(|e| format!("Failed to read {}: {}", key_path, e))?;
+    let certs = CertificateDer::pem_slice_iter(&cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{} is not a PEM certificate: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("{} holds no certificate", cert_path));
+    }
+    let key = PrivateKeyDer::from_pem_slice(&key_pem)
+        .map_err(|e| format!("{} is not a PEM private key: {}", key_path, e))?;
+    let config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Certificate and key do not match: {}", e))?;
+    Ok(Arc::new(config))
+}
+
+// The address to listen on; TLS is required unless it is a loopback one
+fn bind_address(host: &str, tls: bool) -> Result<IpAddr, String> {
+    let ip: IpAddr = host
+        .trim()
+        .parse()
+        .map_err(|_| format!("Not an IP address to listen on: {}", host))?;
+    if !tls && !ip.is_loopback() {
+        return Err(format!(
+            "TLS is required to serve the LAN API on {}; set a certificate in the LAN \
+             server settings, or listen on 127.0.0.1 for this computer only",
+            ip
+        ));
+    }
+    Ok(ip)
+}
+
+type Serving = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>;
+
+// Bind `host`:`port` and serve the API until `stopped`. Returns whether
+// TLS is on.
+async fn serve(
+    app: &AppHandle,
+    host: &str,
+    port: u16,
+    mut stopped: watch::Receiver<bool>,
+) -> Result<bool, String> {
+    let tls = {
+        let conn = db::open(app)?;
+        load_tls(&conn)?
+    };
+    let ip = bind_address(host, tls.enabled)?;
+    let tls_config = match tls.enabled {
+        true => Some(server_config(&tls.cert_path, &tls.key_path)?),
+        false => None,
+    };
+    // Bound here so a port in use is reported to the caller
+    let listener = TcpListener::bind((ip, port)).map_err(|e| e.to_string())?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+    let service = router(ApiState {
+        app: app.clone(),
+        stopped: stopped.clone(),
+    })
+    .into_make_service();
+    let handle = Handle::new();
+    let mut serving: Serving = match tls_config {
+        Some(config) => Box::pin(
+            axum_server::from_tcp_rustls(listener, RustlsConfig::from_config(config))
+                .handle(handle.clone())
+                .serve(service),
+        ),
+        None => Box::pin(
+            axum_server::from_tcp(listener)
+                .handle(handle.clone())
+                .serve(service),
+        ),
+    };
+    tauri::async_runtime::spawn(async move {
+        // The server is polled first so it is listening for the shutdown
+        tokio::select! {
+            biased;
+            result = &mut serving => {
+                if let Err(e) = result {
+                    tracing::warn!(error = %e, "LAN server failed");
+                }
+                return;
+            }
+            _ = until_stopped(&mut stopped) => {}
+        }
+        handle.graceful_shutdown(Some(SHUTDOWN_GRACE));
+        let _ = serving.await;
+    });
+    Ok(tls.enabled)
+}
+
+// Start serving the API on `host` (all interfaces by default) at `port`;
+// also used by headless mode, so the license is checked here rather than
+// by the guard
+pub async fn start(app: AppHandle, host: Option<String>, port: u16) -> Result<(), String> {
+    license::covers(&app, LICENSE_FEATURE)?;
+    let host = host.unwrap_or_else(|| DEFAULT_HOST.to_string());
+    let server = app.state::<LanServer>();
+    // The slot is taken before binding, so two starts cannot both bind
+    let (shutdown, stopped) = watch::channel(false);
+    {
+        let mut slot = server.0.lock().map_err(|e| e.to_string())?;
+        if let Some(running) = slot.as_ref() {
+            return Err(format!(
+                "LAN server already running on port {}",
+                running.port
+            ));
+        }
+        *slot = Some(RunningServer {
+            port,
+            shutdown: shutdown.clone(),
+        });
+    }
+
+    match serve(&app, &host, port, stopped).await {
+        Ok(tls) => {
+            tracing::info!(host = %host, port, tls, "LAN server started");
+            Ok(())
+        }
+        Err(e) => {
+            // Given back, unless a stop took it meanwhile
+            let mut slot = server.0.lock().map_err(|e| e.to_string())?;
+            if slot
+                .as_ref()
+                .is_some_and(|running| running.shutdown.same_channel(&shutdown))
+            {
+                *slot = None;
+            }
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn start_lan_server(
+    app: AppHandle,
+    port: u16,
+    host: Option<String>,
+) -> Result<(), String> {
+    start(app, host, port).await
+}
+
+// Stop the API server if it is running
+#[tauri::command]
+pub fn stop_lan_server(server: State<'_, LanServer>) -> Result<(), String> {
+    server.stop()
+}
+
+// Port the API server is listening on, if running
+#[tauri::command]
+pub fn lan_server_status(server: State<'_, LanServer>) -> Result<Option<u16>, String> {
+    Ok(server
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .as_ref()
+        .map(|r| r.port))
+}
+
+#[tauri::command]
+pub fn get_lan_server_tls(app: AppHandle) -> Result<LanTlsSettings, String> {
+    let conn = db::open(&app)?;
+    load_tls(&conn)
+}
+
+// Serve HTTPS with the certificate and key files (admins). The files are
+// checked now and the setting applies from the next start.
+#[tauri::command]
+pub fn set_lan_server_tls(
+    app: AppHandle,
+    settings: LanTlsSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_lan_server_tls", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if settings.enabled {
+            server_config(&settings.cert_path, &settings.key_path)?;
+        }
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, TLS_CONFIG_KEY, &json)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_http_is_for_loopback_only() {
+        for host in ["127.0.0.1", " 127.0.0.1 ", "::1"] {
+            assert!(bind_address(host, false).unwrap().is_loopback(), "{}", host);
+        }
+        for host in [DEFAULT_HOST, "192.168.1.20", "::"] {
+            assert!(bind_address(host, false).is_err(), "{}", host);
+            assert!(bind_address(host, true).is_ok(), "{}", host);
+        }
+        assert!(bind_address("weighbridge.local", true).is_err());
+    }
+
+    #[test]
+    fn certificates_must_be_pem_and_match_their_key() {
+        let dir = std::env::temp_dir().join(format!("lan-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let site = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let other = rcgen::KeyPair::generate().unwrap();
+        let cert = file("cert.pem", &site.cert.pem());
+        let key = file("key.pem", &site.key_pair.serialize_pem());
+        let other_key = file("other.pem", &other.serialize_pem());
+        let not_pem = file("notes.txt", "not a certificate");
+        let missing = dir.join("missing.pem").to_string_lossy().into_owned();
+
+        assert!(server_config(&cert, &key).is_ok());
+        for (cert_path, key_path, error) in [
+            (&missing, &key, "Failed to read"),
+            (&not_pem, &key, "holds no certificate"),
+            (&cert, &not_pem, "not a PEM private key"),
+            (&cert, &other_key, "do not match"),
+        ] {
+            let refused = server_config(cert_path, key_path).unwrap_err();
+            assert!(refused.contains(error), "{}", refused);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
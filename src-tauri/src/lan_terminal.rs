@@ -0,0 +1,413 @@
+// LAN terminal API for Truckore Pro
+// A second PC in the cabin works against the same database through the LAN
+// server. It signs in like the desktop does (the same sessions and lockout),
+// then runs the registered named queries (query_registry.rs) with its
+// bearer token, so it gets the data layer the frontend uses but never raw
+// SQL. Each request passes the checks an invoke does (authorization.rs):
+// role, license and, for auditors, read-only and logged. /tickets pages
+// the weighments feed (ticket_feed.rs) for ERP consumers pulling
+// incrementally. /weight/live streams the running scale listener's
+// readings over a WebSocket, one JSON text message per reading.
+
+use crate::auth::{self, LoginResult, SessionUser, Sessions};
+use crate::authorization;
+use crate::command_audit::DENIED_PREFIX;
+use crate::db;
+use crate::lan_server::{self, ApiError, ApiState};
+use crate::masking;
+use crate::query_registry::{self, NamedQueryInfo};
+use crate::scale_listener::{self, WeightUpdate};
+use crate::scale_protocol::Reading;
+use crate::ticket_feed::{self, FeedRequest, TicketPage};
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+use tauri::Manager;
+use tokio::sync::{mpsc, watch};
+
+// Readings queued for a slow terminal before the feed waits for it
+const FEED_BUFFER: usize = 64;
+// How often the reading bridge checks whether the terminal has gone
+const FEED_POLL: Duration = Duration::from_secs(1);
+// Larger messages from a terminal close the feed; it expects none
+const MAX_CLIENT_MESSAGE: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RunQuery {
+    #[serde(default)]
+    params: Vec<Value>,
+    min_version: Option<u64>,
+}
+
+// Browsers cannot set headers on a WebSocket, so the feed also takes the
+// token as ?token=
+#[derive(Debug, Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/login", post(login))
+        .route("/logout", post(logout))
+        .route("/queries", get(list_queries))
+        .route("/queries/:name", post(run_query))
+        .route("/tickets", get(tickets))
+        .route("/weight/live", get(live_weight))
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, ApiError> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| ApiError::from(e.to_string()))?
+}
+
+// A backend refusal with the status a terminal can act on
+fn refused(message: String) -> ApiError {
+    let status = if message.starts_with(DENIED_PREFIX) {
+        StatusCode::FORBIDDEN
+    } else if message.starts_with("Unknown query") {
+        StatusCode::NOT_FOUND
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    ApiError(status, message)
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+fn session(state: &ApiState, token: Option<&str>) -> Result<SessionUser, ApiError> {
+    let token = token.ok_or_else(|| ApiError::unauthorized("Missing bearer token"))?;
+    auth::session_user(&state.app, token).map_err(|e| ApiError::unauthorized(&e))
+}
+
+async fn login(
+    State(state): State<ApiState>,
+    Json(credentials): Json<Credentials>,
+) -> Result<Json<LoginResult>, ApiError> {
+    let app = state.app.clone();
+    blocking(move || {
+        let result = auth::login(
+            &app,
+            &app.state::<Sessions>(),
+            &credentials.username,
+            &credentials.password,
+        )
+        .map_err(|e| ApiError::unauthorized(&e))?;
+        tracing::info!(user = %result.user.username, "LAN terminal signed in");
+        Ok(Json(result))
+    })
+    .await
+}
+
+async fn logout(State(state): State<ApiState>, headers: HeaderMap) -> Result<StatusCode, ApiError> {
+    let token = bearer(&headers).ok_or_else(|| ApiError::unauthorized("Missing bearer token"))?;
+    auth::end(&state.app.state::<Sessions>(), token)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_queries(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<NamedQueryInfo>>, ApiError> {
+    let user = session(&state, bearer(&headers))?;
+    let app = state.app.clone();
+    blocking(move || {
+        authorization::check(&app, &user, "list_named_queries", &Value::Null).map_err(refused)?;
+        Ok(Json(query_registry::list_named_queries(app)?))
+    })
+    .await
+}
+
+// Rows of a read, or the outcome of a write recorded under the terminal's user
+async fn run_query(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<RunQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let token = bearer(&headers).map(str::to_string);
+    let user = session(&state, token.as_deref())?;
+    let app = state.app.clone();
+    blocking(move || {
+        tracing::debug!(query = %name, user = %user.username, "LAN named query");
+        let payload = serde_json::json!({
+            "name": name,
+            "params": request.params,
+            "userId": user.id,
+        });
+        authorization::check(&app, &user, "run_named_query", &payload).map_err(refused)?;
+        query_registry::run_named_query(app, token, name, request.params, request.min_version)
+            .map(Json)
+            .map_err(refused)
+    })
+    .await
+}
+
+// A page of ticket changes after ?cursor=, optionally ?since= and ?limit=
+async fn tickets(
+    State(state): State<ApiState>,
+    Query(request): Query<FeedRequest>,
+    headers: HeaderMap,
+) -> Result<Json<TicketPage>, ApiError> {
+    let user = session(&state, bearer(&headers))?;
+    let app = state.app.clone();
+    blocking(move || {
+        tracing::debug!(user = %user.username, cursor = ?request.cursor, "LAN ticket feed");
+        let payload = serde_json::to_value(&request).map_err(|e| e.to_string())?;
+        authorization::check(&app, &user, "weighment_feed", &payload).map_err(refused)?;
+        let conn = db::open(&app)?;
+        let masks = masking::for_role(&conn, &user.role)?;
+        ticket_feed::page(&conn, &request)
+            .map(|page| Json(ticket_feed::masked(page, &masks)))
+            .map_err(refused)
+    })
+    .await
+}
+
+// Pass readings from the listener thread to the socket task until the
+// terminal goes or the listener stops
+fn bridge(port: String, readings: Receiver<Reading>, updates: mpsc::Sender<WeightUpdate>) {
+    std::thread::spawn(move || loop {
+        match readings.recv_timeout(FEED_POLL) {
+            Ok(reading) => {
+                let update = WeightUpdate {
+                    port: port.clone(),
+                    weight_kg: reading.weight_kg,
+                    stable: reading.stable,
+                };
+                if updates.blocking_send(update).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if !updates.is_closed() => {}
+            Err(_) => return,
+        }
+    });
+}
+
+// Send each update as a text message until the terminal closes the
+// socket, the listener stops or the server does. Pings are answered by
+// the socket itself; anything else the terminal sends is ignored.
+async fn stream_weights(
+    mut socket: WebSocket,
+    mut updates: mpsc::Receiver<WeightUpdate>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    let going_away = || {
+        Message::Close(Some(CloseFrame {
+            code: close_code::AWAY,
+            reason: "".into(),
+        }))
+    };
+    loop {
+        let message = tokio::select! {
+            _ = lan_server::until_stopped(&mut stopped) => going_away(),
+            update = updates.recv() => match update.and_then(|u| serde_json::to_string(&u).ok()) {
+                Some(json) => Message::Text(json),
+                // The scale listener stopped
+                None => going_away(),
+            },
+            received = socket.recv() => match received {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        let closing = matches!(message, Message::Close(_));
+        if socket.send(message).await.is_err() || closing {
+            break;
+        }
+    }
+}
+
+// Upgrade to a WebSocket of the live weight
+async fn live_weight(
+    State(state): State<ApiState>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+    upgrade: Option<WebSocketUpgrade>,
+) -> Result<Response, ApiError> {
+    session(&state, bearer(&headers).or(query.token.as_deref()))?;
+    let upgrade = upgrade.ok_or_else(|| ApiError::bad_request("Expected a WebSocket upgrade"))?;
+    let (port, readings) = scale_listener::subscribe(&state.app, None)?.ok_or_else(|| {
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The scale listener is not running".to_string(),
+        )
+    })?;
+
+    let (updates_tx, updates) = mpsc::channel(FEED_BUFFER);
+    bridge(port, readings, updates_tx);
+    let stopped = state.stopped.clone();
+    Ok(upgrade
+        .max_message_size(MAX_CLIENT_MESSAGE)
+        .on_failed_upgrade(|e| tracing::debug!(error = %e, "LAN weight feed upgrade failed"))
+        .on_upgrade(move |socket| stream_weights(socket, updates, stopped)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum_server::tls_rustls::RustlsConfig;
+    use futures_util::{SinkExt, StreamExt};
+    use rustls::pki_types::{CertificateDer, ServerName};
+    use rustls::{ClientConfig, RootCertStore};
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+    struct Feed {
+        port: u16,
+        certificate: CertificateDer<'static>,
+        updates: mpsc::Sender<WeightUpdate>,
+        shutdown: watch::Sender<bool>,
+    }
+
+    // The weight feed behind HTTPS on a loopback port, with a self-signed
+    // certificate for localhost read from PEM files like a site's
+    async fn serve_feed() -> Feed {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!("lan-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+        let config =
+            lan_server::server_config(cert_path.to_str().unwrap(), key_path.to_str().unwrap())
+                .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (updates_tx, updates) = mpsc::channel(FEED_BUFFER);
+        let (shutdown, stopped) = watch::channel(false);
+        let feed = Arc::new(Mutex::new(Some((updates, stopped))));
+        let router = Router::new().route(
+            "/feed",
+            get(move |upgrade: WebSocketUpgrade| {
+                let (updates, stopped) = feed.lock().unwrap().take().unwrap();
+                async move {
+                    upgrade
+                        .max_message_size(MAX_CLIENT_MESSAGE)
+                        .on_upgrade(move |socket| stream_weights(socket, updates, stopped))
+                }
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(
+            axum_server::from_tcp_rustls(listener, RustlsConfig::from_config(config))
+                .serve(router.into_make_service()),
+        );
+        Feed {
+            port,
+            certificate: generated.cert.der().clone(),
+            updates: updates_tx,
+            shutdown,
+        }
+    }
+
+    async fn connect(
+        feed: &Feed,
+    ) -> tokio_tungstenite::WebSocketStream<tokio_rustls::client::TlsStream<TcpStream>> {
+        let mut roots = RootCertStore::empty();
+        roots.add(feed.certificate.clone()).unwrap();
+        let config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        let tcp = TcpStream::connect(("127.0.0.1", feed.port)).await.unwrap();
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+        let (socket, _) = tokio_tungstenite::client_async("wss://localhost/feed", tls)
+            .await
+            .unwrap();
+        socket
+    }
+
+    fn reading(weight_kg: f64) -> WeightUpdate {
+        WeightUpdate {
+            port: "COM3".to_string(),
+            weight_kg,
+            stable: Some(true),
+        }
+    }
+
+    #[tokio::test]
+    async fn readings_arrive_as_text_frames_until_the_server_stops() {
+        let feed = serve_feed().await;
+        let mut socket = connect(&feed).await;
+        // Whatever the terminal sends is ignored
+        socket
+            .send(ClientMessage::Text("hello".to_string()))
+            .await
+            .unwrap();
+
+        for weight in [12_340.0, 12_360.0] {
+            feed.updates.send(reading(weight)).await.unwrap();
+            let ClientMessage::Text(json) = socket.next().await.unwrap().unwrap() else {
+                panic!("expected a text frame");
+            };
+            let update: WeightUpdate = serde_json::from_str(&json).unwrap();
+            assert_eq!((update.port.as_str(), update.weight_kg), ("COM3", weight));
+        }
+
+        feed.shutdown.send(true).unwrap();
+        match socket.next().await.unwrap().unwrap() {
+            ClientMessage::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Away),
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn plain_http_and_oversized_messages_are_refused() {
+        let feed = serve_feed().await;
+        let tcp = TcpStream::connect(("127.0.0.1", feed.port)).await.unwrap();
+        assert!(tokio_tungstenite::client_async("ws://localhost/feed", tcp)
+            .await
+            .is_err());
+
+        let mut socket = connect(&feed).await;
+        let _ = socket
+            .send(ClientMessage::Binary(vec![0; MAX_CLIENT_MESSAGE + 1]))
+            .await;
+        let ended = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match socket.next().await {
+                    Some(Ok(ClientMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        });
+        assert!(ended.await.is_ok(), "the feed stayed open");
+        // With the terminal gone, readings go nowhere and the sender notices
+        assert!(feed.updates.send(reading(1.0)).await.is_err());
+    }
+}
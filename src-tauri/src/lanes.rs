@@ -0,0 +1,327 @@
+// Weighing lanes for Truckore Pro
+// Sites with more than one weighbridge, or one bridge worked from two
+// windows, run a capture flow per lane. Each lane has its own state machine,
+// its own indicator binding (falling back to the site's scale_config) and
+// its own event channel, `lane:<id>:state`, so one window never reacts to
+// another lane's capture.
+
+use crate::cameras::{self, CameraSnapshot};
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::rounding;
+use crate::scale::{self, ScaleConfig};
+use crate::weighing::{self, CapturedWeight};
+use crate::workflow_journal;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+const DEFAULT_CAPTURE_TIMEOUT_SECONDS: f64 = 15.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum LaneState {
+    Idle,
+    Capturing {
+        product_name: String,
+    },
+    // Weight and snapshots held for the lane until the ticket is saved or the
+    // capture discarded
+    Captured {
+        captured: CapturedWeight,
+        #[serde(default)]
+        snapshots: Vec<CameraSnapshot>,
+    },
+}
+
+// A lane capture: the weight plus one snapshot per lane camera. Pass
+// `snapshots` on to create_weighment to link them to the ticket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaneCapture {
+    #[serde(flatten)]
+    pub captured: CapturedWeight,
+    pub snapshots: Vec<CameraSnapshot>,
+}
+
+// Managed state: the state machine of every lane that has been used
+#[derive(Default)]
+pub struct Lanes(Mutex<HashMap<String, LaneState>>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lane {
+    pub lane_id: String,
+    pub name: String,
+    // None uses the site's indicator settings
+    pub scale_config: Option<ScaleConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaneEvent {
+    pub lane_id: String,
+    #[serde(flatten)]
+    pub state: LaneState,
+    pub error: Option<String>,
+}
+
+// Lane ids become part of event names, which allow only a few characters
+fn validate_id(lane_id: &str) -> Result<(), String> {
+    let valid = !lane_id.is_empty()
+        && lane_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Lane id {:?} may only contain letters, digits, '-' and '_'",
+            lane_id
+        ))
+    }
+}
+
+fn load_lane(conn: &Connection, lane_id: &str) -> Result<Lane, String> {
+    conn.query_row(
+        "SELECT lane_id, name, scale_config FROM lanes WHERE lane_id = ?1",
+        [lane_id],
+        row_to_lane,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Lane {} not found", lane_id))
+}
+
+fn row_to_lane(row: &rusqlite::Row) -> rusqlite::Result<Lane> {
+    let config: Option<String> = row.get(2)?;
+    Ok(Lane {
+        lane_id: row.get(0)?,
+        name: row.get(1)?,
+        scale_config: config.and_then(|json| serde_json::from_str(&json).ok()),
+    })
+}
+
+fn scale_for(conn: &Connection, lane: &Lane) -> Result<ScaleConfig, String> {
+    match &lane.scale_config {
+        Some(config) => Ok(config.clone()),
+        None => scale::load_config(conn),
+    }
+}
+
+fn publish(app: &AppHandle, lane_id: &str, state: &LaneState, error: Option<String>) {
+    let event = LaneEvent {
+        lane_id: lane_id.to_string(),
+        state: state.clone(),
+        error,
+    };
+    // Delivery to windows is best effort
+    let _ = app.emit_all(&format!("lane:{}:state", lane_id), &event);
+}
+
+// Move a lane to `next` if it is currently in a state accepted by `from`
+fn transition(
+    app: &AppHandle,
+    lanes: &Lanes,
+    lane_id: &str,
+    from: impl Fn(&LaneState) -> bool,
+    next: LaneState,
+) -> Result<(), String> {
+    let mut states = lanes.0.lock().map_err(|e| e.to_string())?;
+    let current = states.entry(lane_id.to_string()).or_insert(LaneState::Idle);
+    if !from(current) {
+        return Err(format!(
+            "Lane {} is busy ({})",
+            lane_id,
+            match current {
+                LaneState::Idle => "idle",
+                LaneState::Capturing { .. } => "capturing",
+                LaneState::Captured { .. } => "holding a captured weight",
+            }
+        ));
+    }
+    *current = next;
+    publish(app, lane_id, current, None);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_lanes(app: AppHandle) -> Result<Vec<Lane>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare("SELECT lane_id, name, scale_config FROM lanes ORDER BY lane_id")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], row_to_lane).map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Create or update a lane. An indicator can only be bound to one lane.
+#[tauri::command]
+pub fn set_lane(app: AppHandle, lane: Lane, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "lane_id": lane.lane_id });
+    command_audit::audited(&app, "set_lane", &user_id, args, || {
+        validate_id(&lane.lane_id)?;
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if let Some(config) = &lane.scale_config {
+            let taken_by = list_lanes(app.clone())?.into_iter().find(|other| {
+                other.lane_id != lane.lane_id
+                    && other
+                        .scale_config
+                        .as_ref()
+                        .is_some_and(|c| c.endpoint() == config.endpoint())
+            });
+            if let Some(other) = taken_by {
+                return Err(format!(
+                    "Indicator {} is already bound to lane {}",
+                    config.endpoint(),
+                    other.lane_id
+                ));
+            }
+        }
+        let config = lane
+            .scale_config
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO lanes (lane_id, name, scale_config) VALUES (?1, ?2, ?3)
+             ON CONFLICT(lane_id) DO UPDATE SET name = excluded.name,
+                 scale_config = excluded.scale_config",
+            params![lane.lane_id, lane.name, config],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn get_lane_state(lanes: State<'_, Lanes>, lane_id: String) -> Result<LaneState, String> {
+    let states = lanes.0.lock().map_err(|e| e.to_string())?;
+    Ok(states.get(&lane_id).cloned().unwrap_or(LaneState::Idle))
+}
+
+// Capture a stable weight on the lane's own indicator, then snapshot all of
+// the lane's cameras at once. Only an idle lane can start a capture; other
+// lanes are unaffected.
+#[tauri::command]
+pub async fn lane_capture_weight(
+    app: AppHandle,
+    lanes: State<'_, Lanes>,
+    lane_id: String,
+    product_name: String,
+    vehicle_no: Option<String>,
+    timeout_seconds: Option<f64>,
+) -> Result<LaneCapture, String> {
+    let (config, rule, rounding) = {
+        let conn = db::open(&app)?;
+        let lane = load_lane(&conn, &lane_id)?;
+        (
+            scale_for(&conn, &lane)?,
+            weighing::rule_for(&conn, &product_name)?,
+            rounding::load_rules(&conn)?,
+        )
+    };
+    let timeout = Duration::from_secs_f64(
+        timeout_seconds
+            .unwrap_or(DEFAULT_CAPTURE_TIMEOUT_SECONDS)
+            .clamp(1.0, 120.0),
+    );
+    transition(
+        &app,
+        &lanes,
+        &lane_id,
+        |s| matches!(s, LaneState::Idle),
+        LaneState::Capturing {
+            product_name: product_name.clone(),
+        },
+    )?;
+
+    let camera_app = app.clone();
+    let camera_lane = lane_id.clone();
+    let detail = serde_json::json!({ "product_name": product_name });
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let vehicle = vehicle_no.as_deref();
+        let captured = workflow_journal::journalled(
+            &camera_app,
+            "capture",
+            vehicle,
+            Some(&camera_lane),
+            detail,
+            || weighing::capture(&camera_app, &config, &rule, vehicle, timeout),
+        )?;
+        let snapshots = cameras::capture_all(&camera_app, &camera_lane)?;
+        Ok(LaneCapture {
+            captured,
+            snapshots,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r);
+
+    let mut states = lanes.0.lock().map_err(|e| e.to_string())?;
+    match result {
+        Ok(mut capture) => {
+            capture.captured.weight_kg = rounding.weight(capture.captured.weight_kg);
+            let state = LaneState::Captured {
+                captured: capture.captured.clone(),
+                snapshots: capture.snapshots.clone(),
+            };
+            publish(&app, &lane_id, &state, None);
+            states.insert(lane_id, state);
+            Ok(capture)
+        }
+        Err(e) => {
+            publish(&app, &lane_id, &LaneState::Idle, Some(e.clone()));
+            states.insert(lane_id, LaneState::Idle);
+            Err(e)
+        }
+    }
+}
+
+// Hand the lane's captured weight and snapshots to the ticket being saved and
+// free the lane
+#[tauri::command]
+pub fn take_lane_capture(
+    app: AppHandle,
+    lanes: State<'_, Lanes>,
+    lane_id: String,
+) -> Result<LaneCapture, String> {
+    let mut states = lanes.0.lock().map_err(|e| e.to_string())?;
+    match states.remove(&lane_id) {
+        Some(LaneState::Captured {
+            captured,
+            snapshots,
+        }) => {
+            states.insert(lane_id.clone(), LaneState::Idle);
+            publish(&app, &lane_id, &LaneState::Idle, None);
+            Ok(LaneCapture {
+                captured,
+                snapshots,
+            })
+        }
+        other => {
+            if let Some(state) = other {
+                states.insert(lane_id.clone(), state);
+            }
+            Err(format!("Lane {} has no captured weight", lane_id))
+        }
+    }
+}
+
+// Discard a held weight so the lane can capture again
+#[tauri::command]
+pub fn reset_lane(app: AppHandle, lanes: State<'_, Lanes>, lane_id: String) -> Result<(), String> {
+    transition(
+        &app,
+        &lanes,
+        &lane_id,
+        |s| !matches!(s, LaneState::Capturing { .. }),
+        LaneState::Idle,
+    )
+}
@@ -0,0 +1,393 @@
+// Licensing for Truckore Pro
+// The product is sold per installation. A license key is issued offline for
+// one PC's hardware fingerprint: a JSON payload and its Ed25519 signature by
+// the vendor's license key, base64url, joined by a dot. Only the public half
+// is built in, so a key cannot be forged or moved to another PC; the vendor
+// supplies it to release builds as TRUCKORE_LICENSE_PUBLIC_KEY (see build.rs),
+// and debug builds without it cannot activate licenses. Without a
+// key the installation runs a TRIAL_DAYS trial. Its start is written, with
+// an HMAC over the fingerprint, to the database and to two files outside
+// it; the earliest intact record wins, so deleting or editing one does not
+// restart the trial, and a clock set back past the last run ends it. Checks
+// run in the invoke guard (authorization.rs), out of reach of the page:
+// PREMIUM_COMMANDS need a trial or a license covering their feature. The
+// background work those features start (the sync loop, the report
+// scheduler, the ANPR worker, the LAN server, heartbeats, cloud uploads) checks `covers`
+// again each time it runs, so a license that lapses or is replaced stops it.
+// Ticket weighing itself is never locked, and neither is restoring data.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+const TRIAL_DAYS: i64 = 30;
+const DAY_SECS: i64 = 24 * 60 * 60;
+// Clocks drift and time zones change; a clock further behind the last run
+// than this was set back
+const CLOCK_SLACK_SECS: i64 = DAY_SECS;
+// How often the trial's last-seen time is written back
+const LAST_SEEN_STEP_SECS: i64 = 60 * 60;
+const KEY_CONFIG_KEY: &str = "license_key";
+const TRIAL_CONFIG_KEY: &str = "license_trial";
+const KEY_FILE: &str = "license.key";
+const TRIAL_FILE: &str = ".license-trial";
+// Verifies license keys; the signing half is kept by the vendor
+const PUBLIC_KEY: Option<[u8; 32]> = include!(concat!(env!("OUT_DIR"), "/license_public_key.rs"));
+// Keys the trial records' HMAC, with the fingerprint
+const TRIAL_MAC_KEY: &[u8] = b"truckore-pro/license-trial/v1";
+
+// Commands that need a trial or a license, by the feature a license names
+pub const PREMIUM_COMMANDS: &[(&str, &str)] = &[
+    ("activate_script", "scripting"),
+    ("create_party_token", "mobile_api"),
+    ("send_heartbeat", "monitoring"),
+    ("set_anpr_settings", "anpr"),
+    ("set_cloud_backup_settings", "cloud_backup"),
+    ("set_heartbeat_settings", "monitoring"),
+    ("set_report_schedule", "report_schedule"),
+    ("set_sync_settings", "sync"),
+    ("start_lan_server", "lan_server"),
+    ("sync_now", "sync"),
+    ("upload_backup_to_cloud", "cloud_backup"),
+];
+
+// What a license key says, as signed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct License {
+    pub license_id: String,
+    pub customer: String,
+    // Hardware fingerprint of the PC it was issued for
+    pub fingerprint: String,
+    // Premium features it covers; empty covers all of them
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub issued_at: String,
+    // Last valid day, "YYYY-MM-DD"; None for a perpetual license
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LicenseStatus {
+    // licensed, trial or expired
+    pub state: String,
+    // Quoted when asking for a license key
+    pub fingerprint: String,
+    pub license: Option<License>,
+    pub trial_days_left: Option<i64>,
+    // Why premium commands are refused, when they are
+    pub message: Option<String>,
+}
+
+// This PC's identity as the operating system keeps it
+#[cfg(target_os = "linux")]
+fn machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+#[cfg(windows)]
+fn machine_id() -> Option<String> {
+    let output = std::process::Command::new("reg")
+        .args([
+            "query",
+            r"HKLM\SOFTWARE\Microsoft\Cryptography",
+            "/v",
+            "MachineGuid",
+        ])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.contains("MachineGuid"))
+        .and_then(|line| line.split_whitespace().last())
+        .map(str::to_string)
+}
+
+#[cfg(target_os = "macos")]
+fn machine_id() -> Option<String> {
+    let output = std::process::Command::new("ioreg")
+        .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.contains("IOPlatformUUID"))
+        .and_then(|line| line.split('"').nth(3))
+        .map(str::to_string)
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+fn machine_id() -> Option<String> {
+    None
+}
+
+// Sixteen hex digits in groups of four, short enough to read over the phone
+pub fn fingerprint() -> Result<String, String> {
+    let id = machine_id().ok_or("Failed to read this PC's machine id")?;
+    let digest = Sha256::digest(format!("truckore-pro:{}", id.to_lowercase()).as_bytes());
+    let digits: Vec<String> = digest[..8].iter().map(|b| format!("{:02X}", b)).collect();
+    Ok(digits
+        .chunks(2)
+        .map(|c| c.concat())
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+// The license in `key`, when its signature holds and it names this PC
+pub fn verify(key: &str, fingerprint: &str) -> Result<License, String> {
+    let key: String = key.chars().filter(|c| !c.is_whitespace()).collect();
+    let invalid = || "Not a valid license key".to_string();
+    let (payload, signature) = key.split_once('.').ok_or_else(invalid)?;
+    let engine = general_purpose::URL_SAFE_NO_PAD;
+    let payload = engine.decode(payload).map_err(|_| invalid())?;
+    let signature = engine.decode(signature).map_err(|_| invalid())?;
+    let public_key =
+        PUBLIC_KEY.ok_or("This build has no license public key and cannot activate licenses")?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(&payload, &signature)
+        .map_err(|_| "The license key's signature does not check out".to_string())?;
+    let license: License = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+    if license.fingerprint != fingerprint {
+        return Err(format!(
+            "License {} was issued for another PC ({})",
+            license.license_id, license.fingerprint
+        ));
+    }
+    Ok(license)
+}
+
+fn dirs(app: &AppHandle) -> Vec<PathBuf> {
+    let resolver = app.path_resolver();
+    let mut dirs: Vec<PathBuf> = [resolver.app_data_dir(), resolver.app_config_dir()]
+        .into_iter()
+        .flatten()
+        .collect();
+    dirs.dedup();
+    dirs
+}
+
+fn stored_key(app: &AppHandle, conn: &Connection) -> Result<Option<String>, String> {
+    let from_file = dirs(app)
+        .into_iter()
+        .find_map(|dir| fs::read_to_string(dir.join(KEY_FILE)).ok());
+    match from_file {
+        Some(key) => Ok(Some(key)),
+        None => db::get_config(conn, KEY_CONFIG_KEY),
+    }
+}
+
+fn trial_mac(fingerprint: &str, started: i64, last_seen: i64) -> Result<String, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(TRIAL_MAC_KEY).map_err(|e| e.to_string())?;
+    mac.update(format!("{}|{}|{}", fingerprint, started, last_seen).as_bytes());
+    Ok(mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+// "<started>:<last seen>:<mac>", unix seconds; None when absent or altered
+fn parse_trial(record: &str, fingerprint: &str) -> Option<(i64, i64)> {
+    let mut parts = record.trim().splitn(3, ':');
+    let started = parts.next()?.parse().ok()?;
+    let last_seen = parts.next()?.parse().ok()?;
+    let mac = parts.next()?;
+    (trial_mac(fingerprint, started, last_seen).ok()? == mac).then_some((started, last_seen))
+}
+
+struct TrialRecords {
+    // Intact records' (started, last seen)
+    intact: Vec<(i64, i64)>,
+    // Some record exists but failed its check
+    altered: bool,
+}
+
+fn read_trial(app: &AppHandle, conn: &Connection, fingerprint: &str) -> TrialRecords {
+    let mut records: Vec<String> = dirs(app)
+        .into_iter()
+        .filter_map(|dir| fs::read_to_string(dir.join(TRIAL_FILE)).ok())
+        .collect();
+    if let Ok(Some(record)) = db::get_config(conn, TRIAL_CONFIG_KEY) {
+        records.push(record);
+    }
+    let intact: Vec<(i64, i64)> = records
+        .iter()
+        .filter_map(|r| parse_trial(r, fingerprint))
+        .collect();
+    TrialRecords {
+        altered: intact.len() < records.len(),
+        intact,
+    }
+}
+
+// Write the trial record everywhere, best effort per place
+fn write_trial(
+    app: &AppHandle,
+    conn: &Connection,
+    fingerprint: &str,
+    started: i64,
+    last_seen: i64,
+) -> Result<(), String> {
+    let record = format!(
+        "{}:{}:{}",
+        started,
+        last_seen,
+        trial_mac(fingerprint, started, last_seen)?
+    );
+    for dir in dirs(app) {
+        let _ = fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(TRIAL_FILE), &record));
+    }
+    db::set_config(conn, TRIAL_CONFIG_KEY, &record)
+}
+
+fn trial_status(
+    app: &AppHandle,
+    conn: &Connection,
+    fingerprint: String,
+) -> Result<LicenseStatus, String> {
+    let now: i64 = conn
+        .query_row("SELECT CAST(strftime('%s', 'now') AS INTEGER)", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    let records = read_trial(app, conn, &fingerprint);
+    let expired = |message: &str| LicenseStatus {
+        state: "expired".to_string(),
+        fingerprint: fingerprint.clone(),
+        license: None,
+        trial_days_left: Some(0),
+        message: Some(message.to_string()),
+    };
+    let (started, last_seen) = match (
+        records.intact.iter().map(|r| r.0).min(),
+        records.intact.iter().map(|r| r.1).max(),
+    ) {
+        (Some(started), Some(last_seen)) => (started, last_seen),
+        _ if records.altered => return Ok(expired("The trial record has been altered")),
+        _ => {
+            write_trial(app, conn, &fingerprint, now, now)?;
+            (now, now)
+        }
+    };
+    if now + CLOCK_SLACK_SECS < last_seen {
+        return Ok(expired(
+            "The system clock is behind the last time Truckore Pro ran",
+        ));
+    }
+    // Rewritten when it moved on, or to repair a missing or altered record
+    let places = dirs(app).len() + 1;
+    if now >= last_seen + LAST_SEEN_STEP_SECS || records.altered || records.intact.len() < places {
+        write_trial(app, conn, &fingerprint, started, now.max(last_seen))?;
+    }
+    let days_left = TRIAL_DAYS - (now - started) / DAY_SECS;
+    if days_left <= 0 {
+        return Ok(expired("The trial has ended; activate a license key"));
+    }
+    Ok(LicenseStatus {
+        state: "trial".to_string(),
+        fingerprint,
+        license: None,
+        trial_days_left: Some(days_left),
+        message: None,
+    })
+}
+
+pub fn status(app: &AppHandle) -> Result<LicenseStatus, String> {
+    let fingerprint = fingerprint()?;
+    let conn = db::open(app)?;
+    let Some(key) = stored_key(app, &conn)? else {
+        return trial_status(app, &conn, fingerprint);
+    };
+    let license = match verify(&key, &fingerprint) {
+        Ok(license) => license,
+        // A key from another PC or release falls back to the trial
+        Err(_) => return trial_status(app, &conn, fingerprint),
+    };
+    let today: String = conn
+        .query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let lapsed = license
+        .expires_at
+        .as_ref()
+        .is_some_and(|last_day| today.as_str() > last_day.as_str());
+    Ok(LicenseStatus {
+        state: if lapsed { "expired" } else { "licensed" }.to_string(),
+        message: lapsed.then(|| format!("License {} has expired", license.license_id)),
+        fingerprint,
+        license: Some(license),
+        trial_days_left: None,
+    })
+}
+
+// Refuse `command` when no trial or license covers its feature
+pub fn require(app: &AppHandle, command: &str) -> Result<(), String> {
+    let Some((_, feature)) = PREMIUM_COMMANDS.iter().find(|(name, _)| *name == command) else {
+        return Ok(());
+    };
+    covers(app, feature)
+}
+
+// Refuse unless a trial or license covers `feature`
+pub fn covers(app: &AppHandle, feature: &str) -> Result<(), String> {
+    let status = status(app)?;
+    match (status.state.as_str(), &status.license) {
+        ("trial", _) => Ok(()),
+        ("licensed", Some(license))
+            if license.features.is_empty() || license.features.iter().any(|f| f == feature) =>
+        {
+            Ok(())
+        }
+        ("licensed", _) => Err(format!("The license does not include {}", feature)),
+        _ => Err(status
+            .message
+            .unwrap_or_else(|| "A license is required".to_string())),
+    }
+}
+
+#[tauri::command]
+pub fn get_license_status(app: AppHandle) -> Result<LicenseStatus, String> {
+    status(&app)
+}
+
+// Install a license key (admin only). It is kept beside the data folders as
+// well as in the database, so restoring an older backup keeps it.
+#[tauri::command]
+pub fn activate_license(
+    app: AppHandle,
+    key: String,
+    user_id: String,
+) -> Result<LicenseStatus, String> {
+    command_audit::audited(
+        &app,
+        "activate_license",
+        &user_id,
+        serde_json::Value::Null,
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            let key = key.trim().to_string();
+            let license = verify(&key, &fingerprint()?)?;
+            for dir in dirs(&app) {
+                fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+                fs::write(dir.join(KEY_FILE), &key).map_err(|e| e.to_string())?;
+            }
+            db::set_config(&conn, KEY_CONFIG_KEY, &key)?;
+            tracing::info!(license = %license.license_id, "License activated");
+            drop(conn);
+            status(&app)
+        },
+    )
+}
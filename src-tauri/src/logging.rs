@@ -0,0 +1,241 @@
+// Diagnostic logs for Truckore Pro
+// Commands, queries, scale events and errors are traced to logs/truckore.log
+// in the app data folder. The file is rotated at 5 MB and the last five
+// rotations are kept (truckore.log.1 is the newest), so a customer PC holds
+// at most about 30 MB of history. TRUCKORE_LOG (error, warn, info, debug or
+// trace) sets the level, info by default. Support reads the tail with
+// get_recent_logs or takes every file away with export_logs.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::secrets;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tauri::AppHandle;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const LOG_FILE: &str = "truckore.log";
+const LEVEL_ENV: &str = "TRUCKORE_LOG";
+const MAX_BYTES: u64 = 5 * 1024 * 1024;
+const KEEP_ROTATED: usize = 5;
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 5000;
+const LEVELS: &[&str] = &["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    // Module the entry came from, e.g. truckore_pro::scale_listener
+    pub target: String,
+    pub message: String,
+}
+
+struct LogFile {
+    dir: PathBuf,
+    file: Option<File>,
+    size: u64,
+}
+
+impl LogFile {
+    fn path(&self, rotation: usize) -> PathBuf {
+        rotated(&self.dir, rotation)
+    }
+
+    fn open(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            fs::create_dir_all(&self.dir)?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.path(0))?;
+            self.size = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("log file just opened"))
+    }
+
+    // truckore.log.4 -> .5, ..., truckore.log -> .1
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        for rotation in (1..KEEP_ROTATED).rev() {
+            let from = self.path(rotation);
+            if from.exists() {
+                fs::rename(&from, self.path(rotation + 1))?;
+            }
+        }
+        fs::rename(self.path(0), self.path(1))
+    }
+}
+
+fn rotated(dir: &Path, rotation: usize) -> PathBuf {
+    match rotation {
+        0 => dir.join(LOG_FILE),
+        n => dir.join(format!("{}.{}", LOG_FILE, n)),
+    }
+}
+
+// Hands the subscriber a writer per event; each event is one write
+#[derive(Clone)]
+struct RotatingWriter(Arc<Mutex<LogFile>>);
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut log = self
+            .0
+            .lock()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "log file lock poisoned"))?;
+        if log.size > 0 && log.size + buf.len() as u64 > MAX_BYTES {
+            // A failed rotation keeps writing to the current file
+            let _ = log.rotate();
+        }
+        let written = log.open()?.write(buf)?;
+        log.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.lock() {
+            Ok(mut log) => log.file.as_mut().map_or(Ok(()), |f| f.flush()),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingWriter {
+    type Writer = RotatingWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn log_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("logs"))
+        .ok_or_else(|| "Failed to get app data directory".to_string())
+}
+
+// Install the file subscriber; called first in the setup hook. Messages from
+// the `log` crate (Tauri's own) are traced too.
+pub fn init(app: &AppHandle) {
+    let Ok(dir) = log_dir(app) else {
+        return;
+    };
+    let level = std::env::var(LEVEL_ENV)
+        .ok()
+        .and_then(|level| LevelFilter::from_str(&level).ok())
+        .unwrap_or(LevelFilter::INFO);
+    let writer = RotatingWriter(Arc::new(Mutex::new(LogFile {
+        dir,
+        file: None,
+        size: 0,
+    })));
+    let _ = tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_max_level(level)
+        .try_init();
+    tracing::info!(version = %app.package_info().version, "Truckore Pro started");
+}
+
+// Entries of one file in order. Lines that do not start with a timestamp and
+// level continue the message before them.
+fn parse(text: &str) -> Vec<LogEntry> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    for line in text.lines() {
+        // The level is padded, e.g. "2026-01-01T08:00:00.000000Z  INFO target: message"
+        let (timestamp, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim_start();
+        let level = rest.split_whitespace().next().unwrap_or("");
+        if !timestamp.contains('T') || !LEVELS.contains(&level) {
+            if let Some(last) = entries.last_mut() {
+                last.message.push('\n');
+                last.message.push_str(line);
+            }
+            continue;
+        }
+        let body = rest[level.len()..].trim_start();
+        let (target, message) = body.split_once(": ").unwrap_or(("", body));
+        entries.push(LogEntry {
+            timestamp: timestamp.to_string(),
+            level: level.to_string(),
+            target: target.to_string(),
+            message: message.to_string(),
+        });
+    }
+    entries
+}
+
+fn log_files(dir: &Path) -> Vec<PathBuf> {
+    (0..=KEEP_ROTATED)
+        .map(|rotation| rotated(dir, rotation))
+        .filter(|path| path.exists() && !secrets::is_key_file(path))
+        .collect()
+}
+
+// The newest entries at `level` or above, newest first
+#[tauri::command]
+pub fn get_recent_logs(
+    app: AppHandle,
+    level: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<LogEntry>, String> {
+    let min_level = match level.as_deref().map(str::to_ascii_uppercase) {
+        Some(level) => LEVELS
+            .iter()
+            .position(|l| *l == level)
+            .ok_or_else(|| format!("Unknown log level {}", level))?,
+        None => 0,
+    };
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let mut recent = Vec::new();
+    for path in log_files(&log_dir(&app)?) {
+        let text = fs::read(&path).map_err(|e| e.to_string())?;
+        let entries = parse(&String::from_utf8_lossy(&text));
+        for entry in entries.into_iter().rev() {
+            if LEVELS.iter().position(|l| *l == entry.level) >= Some(min_level) {
+                recent.push(entry);
+                if recent.len() == limit {
+                    return Ok(recent);
+                }
+            }
+        }
+    }
+    Ok(recent)
+}
+
+// Every log file in one zip for support (admin only); returns the files added
+#[tauri::command]
+pub fn export_logs(app: AppHandle, zip_path: String, user_id: String) -> Result<usize, String> {
+    let args = serde_json::json!({ "zip_path": zip_path });
+    command_audit::audited(&app, "export_logs", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let files = log_files(&log_dir(&app)?);
+        let file =
+            File::create(&zip_path).map_err(|e| format!("Failed to write {}: {}", zip_path, e))?;
+        let mut zip = ZipWriter::new(file);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for path in &files {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or(LOG_FILE);
+            zip.start_file(name, options).map_err(|e| e.to_string())?;
+            zip.write_all(&fs::read(path).map_err(|e| e.to_string())?)
+                .map_err(|e| e.to_string())?;
+        }
+        zip.finish().map_err(|e| e.to_string())?;
+        Ok(files.len())
+    })
+}
@@ -1,20 +1,158 @@
 // Tauri Backend for Truckore Pro
 // Handles SQLite database operations
 
-use rusqlite::{Connection, types::ValueRef};
-use serde::{Deserialize, Serialize};
+use rusqlite::{OpenFlags, types::ValueRef};
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 use tauri::AppHandle;
 use base64::{Engine as _, engine::general_purpose};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct QueryResult {
-    rows: Vec<serde_json::Value>,
-}
+mod amendments;
+mod analytics;
+mod anpr;
+mod approvals;
+mod archive;
+mod attachments;
+mod audit_log;
+mod auditor;
+mod auth;
+mod authorization;
+mod backup;
+mod backup_schedule;
+mod bandwidth;
+mod barcode;
+mod bulk;
+mod calendar;
+mod cameras;
+mod change_feed;
+mod cloud_backup;
+mod command_audit;
+mod communications;
+mod companies;
+mod compression;
+mod config_sync;
+mod configuration;
+mod crash_reports;
+mod credit;
+mod csv_import;
+mod currency;
+mod cursors;
+mod dashboard;
+mod data_version;
+mod day_close;
+mod db;
+mod deductions;
+mod delta_sync;
+mod disputes;
+mod driver_signatures;
+mod drivers;
+mod encryption;
+mod errors;
+mod export;
+mod export_destinations;
+mod feature_flags;
+mod formatting;
+mod fraud;
+mod headless;
+mod heartbeat;
+mod history;
+mod idempotency;
+mod inventory;
+mod invoicing;
+mod lan_server;
+mod lan_terminal;
+mod lanes;
+mod license;
+mod logging;
+mod maintenance;
+mod masking;
+mod master_data;
+mod messages;
+mod migrations;
+mod mining_portal;
+mod mobile_api;
+mod money;
+mod movements;
+mod network;
+mod notifications;
+mod onvif;
+mod operator_profiles;
+mod overrides;
+mod pdf;
+mod period_lock;
+mod peripherals;
+mod positioning;
+mod preflight;
+mod print_jobs;
+mod profiles;
+mod purchase_orders;
+mod query_control;
+mod query_registry;
+mod recovery;
+mod references;
+mod report_builder;
+mod report_schedule;
+mod reports;
+mod rfid;
+mod roles;
+mod rounding;
+mod runtime_metrics;
+mod scale;
+mod scale_listener;
+mod scale_maintenance;
+mod scale_protocol;
+mod scale_simulator;
+mod schema_drift;
+mod scripting;
+mod search;
+mod secrets;
+mod security;
+mod serial_numbers;
+mod settings;
+mod settings_events;
+mod setup_wizard;
+mod shifts;
+mod shutdown;
+mod signatures;
+mod slip_image;
+mod slip_layout;
+mod slip_verification;
+mod smtp;
+mod snapshots;
+mod sql_values;
+mod stale_tickets;
+mod startup_recovery;
+mod storage;
+mod support_console;
+mod sync_engine;
+mod tariffs;
+mod tasks;
+mod telemetry;
+mod thermal_printer;
+mod ticket_feed;
+mod ticket_parties;
+mod ticket_qr;
+mod training;
+mod undelete;
+mod transporters;
+mod updates;
+mod uploads;
+mod vehicle_classes;
+mod voids;
+mod watermark;
+mod weighing;
+mod weighing_steps;
+mod weight_history;
+mod workflow_journal;
+mod xlsx;
 
 // Helper function to convert serde_json::Value to rusqlite::types::Value
 fn json_to_sql_value(json_val: &serde_json::Value) -> rusqlite::types::Value {
+    // Typed envelopes, see sql_values.rs
+    if let Some(Ok(value)) = sql_values::param(json_val) {
+        return value;
+    }
     match json_val {
         serde_json::Value::Null => rusqlite::types::Value::Null,
         serde_json::Value::Bool(b) => rusqlite::types::Value::Integer(*b as i64),
@@ -40,11 +178,8 @@ fn sql_to_json_value(sql_val: ValueRef) -> serde_json::Value {
         ValueRef::Null => serde_json::Value::Null,
         ValueRef::Integer(i) => serde_json::json!(i),
         ValueRef::Real(f) => serde_json::json!(f),
-        ValueRef::Text(s) => {
-            // Try to parse as JSON first (for arrays/objects stored as text)
-            let text = String::from_utf8_lossy(s);
-            serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text.to_string()))
-        }
+        // As stored; "123" stays a string. See sql_values::json_text.
+        ValueRef::Text(s) => serde_json::Value::String(String::from_utf8_lossy(s).to_string()),
         ValueRef::Blob(b) => {
             // Convert blob to base64 string
             serde_json::Value::String(general_purpose::STANDARD.encode(b))
@@ -61,98 +196,912 @@ fn get_db_path(app: &AppHandle) -> Result<PathBuf, String> {
     
     fs::create_dir_all(&app_data_dir).map_err(|e| e.to_string())?;
     
-    Ok(app_data_dir.join("data").join("truckore_data.db"))
+    // Each company has its own data folder, and each environment profile its
+    // own database file in it
+    let company = companies::active(app);
+    Ok(companies::data_dir(app, &company)?.join(profiles::active(app).db_file()))
 }
 
 // Initialize database with schema
 #[tauri::command]
-fn init_database(app: AppHandle) -> Result<(), String> {
+fn init_database(app: AppHandle) -> Result<(), errors::AppError> {
     let db_path = get_db_path(&app)?;
     
     // Create data directory if it doesn't exist
     if let Some(parent) = db_path.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        fs::create_dir_all(parent)?;
     }
     
-    let conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
-    
-    // Execute schema
-    let schema = include_str!("../../src/services/database/schema.sql");
-    conn.execute_batch(schema).map_err(|e| e.to_string())?;
+    let existed = db_path.exists();
+    // Execute schema; a damaged file is reported so the frontend can offer recovery
+    let conn = encryption::open_file(&app, &db_path, OpenFlags::default())
+        .and_then(|conn| recovery::check_integrity(&conn).map(|_| conn))
+        .and_then(|conn| conn.execute_batch(db::SCHEMA).map(|_| conn))
+        .map_err(recovery::describe_open_error)?;
+    // Data written by an older release is snapshotted before it is migrated
+    let version = migrations::current(&conn)?;
+    if existed && version < migrations::latest() {
+        let detail = format!("Schema {} to {}", version, migrations::latest());
+        snapshots::take(&app, &conn, "migration", Some(detail), "system")?;
+    }
+    migrations::run(&conn)?;
+    // Tables, columns and indexes changed by hand at the site are put right,
+    // or the start stops with what could not be
+    schema_drift::repair(&conn)?;
     
     Ok(())
 }
 
-// Execute a SELECT query
-#[tauri::command]
-fn execute_query(
-    app: AppHandle,
-    query: String,
-    params: Vec<serde_json::Value>,
-) -> Result<Vec<serde_json::Value>, String> {
-    let db_path = get_db_path(&app)?;
-    let conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
+// How a SELECT runs and reads its rows
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct QueryOptions {
+    query_id: Option<String>,
+    timeout_ms: Option<u64>,
+    min_version: Option<u64>,
+    typed: Option<bool>,
+    // Columns whose text is parsed as JSON
+    json_columns: Option<Vec<String>>,
+    // Set for an auditor's session: the query runs read-only and the
+    // records it returns are logged
+    #[serde(skip)]
+    auditor: Option<String>,
+}
+
+// Rows of a query with what SQLite knows about its columns
+#[derive(serde::Serialize)]
+struct QueryResult {
+    columns: Vec<sql_values::ColumnInfo>,
+    rows: Vec<serde_json::Value>,
+}
+
+fn run_query(
+    app: &AppHandle,
+    query: &str,
+    params: &[serde_json::Value],
+    options: &QueryOptions,
+    masks: &masking::Masks,
+) -> Result<QueryResult, errors::AppError> {
+    data_version::wait_for(app, options.min_version)?;
+    let conn = db::open_reader(app, options.auditor.is_some())?;
     
     // Convert JSON params to SQL values
-    let sql_params: Vec<rusqlite::types::Value> = params.iter()
-        .map(|p| json_to_sql_value(p))
-        .collect();
+    let sql_params = sql_values::params(params)?;
     
-    let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+    // Dashboards repeat the same queries; cached statements skip re-parsing.
+    // A masked session's statement is compiled for it alone (masking.rs).
+    let mut cached;
+    let mut fresh;
+    let stmt: &mut rusqlite::Statement = if masks.is_empty() {
+        cached = conn.prepare_cached(query)?;
+        &mut cached
+    } else {
+        fresh = masks.prepare(&conn, || conn.prepare(query))?;
+        &mut fresh
+    };
+    // Writes go through execute_non_query, where prepare_raw_write checks them
+    if !stmt.readonly() {
+        return Err(errors::AppError::new(
+            errors::ErrorCode::PermissionDenied,
+            format!(
+                "{}: execute_query is for read-only queries",
+                command_audit::DENIED_PREFIX
+            ),
+        ));
+    }
     
-    let column_count = stmt.column_count();
-    let column_names: Vec<String> = (0..column_count)
-        .map(|i| stmt.column_name(i).unwrap_or("").to_string())
+    let typed = options.typed.unwrap_or(false);
+    let json_columns = options.json_columns.as_deref().unwrap_or(&[]);
+    let mut columns: Vec<sql_values::ColumnInfo> = stmt
+        .columns()
+        .iter()
+        .map(|c| sql_values::ColumnInfo {
+            name: c.name().to_string(),
+            decl_type: c.decl_type().map(str::to_string),
+            affinity: sql_values::affinity(c.decl_type()).map(str::to_string),
+            storage_classes: Vec::new(),
+            json: json_columns.iter().any(|name| name == c.name()),
+            masked: masks.mode(c.name()).is_some(),
+        })
         .collect();
     
-    let rows = stmt
-        .query_map(rusqlite::params_from_iter(sql_params.iter()), |row| {
+    let timeout = options.timeout_ms.map_or(query_control::DEFAULT_TIMEOUT, Duration::from_millis);
+    let rows = query_control::guarded(app, &conn, options.query_id.as_deref(), timeout, || {
+        stmt.query_map(rusqlite::params_from_iter(sql_params.iter()), |row| {
             let mut map = serde_json::Map::new();
-            for (i, name) in column_names.iter().enumerate() {
-                let value_ref = row.get_ref(i).unwrap();
-                let json_value = sql_to_json_value(value_ref);
-                map.insert(name.clone(), json_value);
+            for (i, column) in columns.iter_mut().enumerate() {
+                let value_ref = row.get_ref(i)?;
+                let class = sql_values::storage_class(value_ref);
+                if !column.storage_classes.iter().any(|c| c == class) {
+                    column.storage_classes.push(class.to_string());
+                }
+                let json_value = if column.json {
+                    sql_values::json_text(value_ref)
+                } else if typed {
+                    sql_values::typed(value_ref, column.decl_type.as_deref())
+                } else {
+                    sql_to_json_value(value_ref)
+                };
+                map.insert(column.name.clone(), json_value);
             }
             Ok(serde_json::Value::Object(map))
-        })
-        .map_err(|e| e.to_string())?;
+        })?
+        .collect::<Result<Vec<_>, _>>()
+    })?;
     
     let mut result = Vec::new();
-    for row in rows {
-        result.push(row.map_err(|e| e.to_string())?);
+    for mut row in rows {
+        masks.apply(&mut row);
+        // Stored images come back as data URLs, as when they were inline
+        attachments::inline_references(app, &conn, &mut row);
+        result.push(row);
+    }
+    if let Some(auditor_id) = &options.auditor {
+        auditor::log_rows(app, auditor_id, "execute_query", &result)?;
     }
     
-    Ok(result)
+    Ok(QueryResult { columns, rows: result })
+}
+
+// Execute a SELECT query; statements that write are refused. It is stopped
+// after `timeout_ms` (60 s by default), or by cancel_query when given a
+// `query_id`. With `min_version` it first waits for that data version, see
+// data_version.rs. With `typed`, BLOBs and
+// DATETIME columns come back as sql_values.rs envelopes. Text comes back as
+// stored, except in `json_columns`, where it is parsed as JSON. Columns the
+// session may not see are masked, see masking.rs.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn execute_query(
+    app: AppHandle,
+    session_token: Option<String>,
+    query: String,
+    params: Vec<serde_json::Value>,
+    query_id: Option<String>,
+    timeout_ms: Option<u64>,
+    min_version: Option<u64>,
+    typed: Option<bool>,
+    json_columns: Option<Vec<String>>,
+) -> Result<Vec<serde_json::Value>, errors::AppError> {
+    let options = QueryOptions {
+        query_id,
+        timeout_ms,
+        min_version,
+        typed,
+        json_columns,
+        auditor: auditor::session_auditor(&app, session_token.as_deref())?,
+    };
+    let masks = masking::for_session(&app, session_token.as_deref())?.for_raw_sql();
+    Ok(run_query(&app, &query, &params, &options, &masks)?.rows)
+}
+
+// A registered read (query_registry.rs): its SQL is the app's own, so
+// partly masked columns are shown in part
+pub(crate) fn read_named(
+    app: &AppHandle,
+    session_token: Option<&str>,
+    query: &str,
+    params: &[serde_json::Value],
+    min_version: Option<u64>,
+) -> Result<Vec<serde_json::Value>, errors::AppError> {
+    let options = QueryOptions {
+        min_version,
+        auditor: auditor::session_auditor(app, session_token)?,
+        ..QueryOptions::default()
+    };
+    let masks = masking::for_session(app, session_token)?;
+    Ok(run_query(app, query, params, &options, &masks)?.rows)
+}
+
+// execute_query that also describes the columns, so the page knows which
+// values are numbers, text or blobs in SQLite
+#[tauri::command]
+fn execute_query_with_columns(
+    app: AppHandle,
+    session_token: Option<String>,
+    query: String,
+    params: Vec<serde_json::Value>,
+    options: Option<QueryOptions>,
+) -> Result<QueryResult, errors::AppError> {
+    let masks = masking::for_session(&app, session_token.as_deref())?.for_raw_sql();
+    let mut options = options.unwrap_or_default();
+    options.auditor = auditor::session_auditor(&app, session_token.as_deref())?;
+    run_query(&app, &query, &params, &options, &masks)
+}
+
+// execute_query with the result compressed for the IPC bridge when it is
+// large and the caller lists an encoding it can decode, e.g. ["gzip"]
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn execute_query_encoded(
+    app: AppHandle,
+    session_token: Option<String>,
+    query: String,
+    params: Vec<serde_json::Value>,
+    accept_encoding: Vec<String>,
+    query_id: Option<String>,
+    timeout_ms: Option<u64>,
+    min_version: Option<u64>,
+    typed: Option<bool>,
+    json_columns: Option<Vec<String>>,
+) -> Result<compression::EncodedPayload, errors::AppError> {
+    let rows = execute_query(
+        app,
+        session_token,
+        query,
+        params,
+        query_id,
+        timeout_ms,
+        min_version,
+        typed,
+        json_columns,
+    )?;
+    Ok(compression::encode(&rows, &accept_encoding)?)
 }
 
-// Execute a non-query (INSERT, UPDATE, DELETE)
+// Outcome of a non-query, read on the connection that ran it
+#[derive(serde::Serialize)]
+struct WriteResult {
+    last_insert_id: i64,
+    rows_affected: usize,
+    // Pass as min_version to reads that must include this write
+    data_version: u64,
+}
+
+// Execute a non-query (INSERT, UPDATE, DELETE). The write and its audit_log
+// entry commit together.
 #[tauri::command]
 fn execute_non_query(
     app: AppHandle,
+    session_token: Option<String>,
     query: String,
     params: Vec<serde_json::Value>,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app)?;
-    let conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
-    
+) -> Result<WriteResult, errors::AppError> {
+    write(
+        &app,
+        session_token.as_deref(),
+        "execute_non_query",
+        query,
+        params,
+    )
+}
+
+// One write and its audit_log entry, recorded under `command` for the
+// session's user, queued to the database writer (db::write)
+fn write(
+    app: &AppHandle,
+    session_token: Option<&str>,
+    command: &'static str,
+    query: String,
+    params: Vec<serde_json::Value>,
+) -> Result<WriteResult, errors::AppError> {
+    let (role, user_id) = authorization::session_writer(app, session_token)?;
     // Convert JSON params to SQL values
-    let sql_params: Vec<rusqlite::types::Value> = params.iter()
-        .map(|p| json_to_sql_value(p))
-        .collect();
-    
-    conn.execute(&query, rusqlite::params_from_iter(sql_params.iter()))
-        .map_err(|e| e.to_string())?;
-    
-    Ok(())
+    let sql_params = sql_values::params(&params)?;
+    let (last_insert_id, rows_affected) = db::write(app, move |tx| {
+        let (mut stmt, table) = authorization::prepare_raw_write(tx, role, &query)?;
+        let rows_affected = stmt.execute(rusqlite::params_from_iter(sql_params.iter()))?;
+        let last_insert_id = tx.last_insert_rowid();
+        audit_log::record(
+            tx,
+            command,
+            table.as_deref(),
+            &query,
+            &serde_json::Value::from(params.clone()),
+            rows_affected,
+            user_id.as_deref(),
+        )?;
+        Ok::<_, errors::AppError>((last_insert_id, rows_affected))
+    })?;
+    Ok(WriteResult {
+        last_insert_id,
+        rows_affected,
+        data_version: data_version::current(app),
+    })
+}
+
+// One statement of an execute_transaction batch
+#[derive(serde::Deserialize)]
+struct BatchStatement {
+    query: String,
+    #[serde(default)]
+    params: Vec<serde_json::Value>,
+}
+
+// Execute several non-queries atomically; any failure rolls back all of them.
+// Returns the affected-row count of each statement.
+#[tauri::command]
+fn execute_transaction(
+    app: AppHandle,
+    session_token: Option<String>,
+    statements: Vec<BatchStatement>,
+) -> Result<Vec<usize>, errors::AppError> {
+    let (role, user_id) = authorization::session_writer(&app, session_token.as_deref())?;
+    db::write(&app, move |tx| {
+        let mut affected = Vec::with_capacity(statements.len());
+        for (index, statement) in statements.iter().enumerate() {
+            let failed = |e: errors::AppError| e.context(format!("Statement {} failed", index + 1));
+            let sql_params = sql_values::params(&statement.params)
+                .map_err(|e| format!("Statement {}: {}", index + 1, e))?;
+            let (mut stmt, table) =
+                authorization::prepare_raw_write(tx, role, &statement.query).map_err(failed)?;
+            let rows = stmt
+                .execute(rusqlite::params_from_iter(sql_params.iter()))
+                .map_err(|e| failed(e.into()))?;
+            audit_log::record(
+                tx,
+                "execute_transaction",
+                table.as_deref(),
+                &statement.query,
+                &serde_json::Value::from(statement.params.clone()),
+                rows,
+                user_id.as_deref(),
+            )?;
+            affected.push(rows);
+        }
+        Ok(affected)
+    })
+}
+
+// Execute one parameterized non-query for each parameter set, preparing it
+// once and running all sets in a single transaction; any failure rolls back
+// all of them. Returns the affected-row count of each set.
+#[tauri::command]
+fn execute_batch(
+    app: AppHandle,
+    session_token: Option<String>,
+    query: String,
+    param_sets: Vec<Vec<serde_json::Value>>,
+) -> Result<Vec<usize>, errors::AppError> {
+    let (role, user_id) = authorization::session_writer(&app, session_token.as_deref())?;
+    db::write(&app, move |tx| {
+        let mut affected = Vec::with_capacity(param_sets.len());
+        let table = {
+            let (mut stmt, table) = authorization::prepare_raw_write(tx, role, &query)?;
+            for (index, params) in param_sets.iter().enumerate() {
+                let sql_params = sql_values::params(params)
+                    .map_err(|e| format!("Parameter set {}: {}", index + 1, e))?;
+                let rows = stmt
+                    .execute(rusqlite::params_from_iter(sql_params.iter()))
+                    .map_err(|e| {
+                        errors::AppError::from(e)
+                            .context(format!("Parameter set {} failed", index + 1))
+                    })?;
+                affected.push(rows);
+            }
+            table
+        };
+        // One entry for the whole batch, over all of its parameter sets
+        audit_log::record(
+            tx,
+            "execute_batch",
+            table.as_deref(),
+            &query,
+            &serde_json::json!(param_sets),
+            affected.iter().sum(),
+            user_id.as_deref(),
+        )?;
+        Ok(affected)
+    })
 }
 
 fn main() {
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![
+        .manage(lan_server::LanServer::default())
+        .manage(auth::Sessions::default())
+        .manage(bandwidth::TransferBudget::default())
+        .manage(bulk::BulkJobs::default())
+        .manage(companies::ActiveCompany::default())
+        .manage(cursors::QueryCursors::default())
+        .manage(dashboard::DashboardCache::default())
+        .manage(data_version::DataVersion::default())
+        .manage(db::DbPool::default())
+        .manage(db::WriteQueue::default())
+        .manage(encryption::DatabaseKey::default())
+        .manage(heartbeat::HeartbeatMonitor::default())
+        .manage(lanes::Lanes::default())
+        .manage(messages::MessageCatalog::default())
+        .manage(peripherals::Peripherals::default())
+        .manage(profiles::ActiveProfile::default())
+        .manage(query_control::RunningQueries::default())
+        .manage(rfid::RfidListener::default())
+        .manage(runtime_metrics::CommandMetrics::default())
+        .manage(scale_listener::ScaleListener::default())
+        .manage(scale_listener::ScaleHealth::default())
+        .manage(weight_history::WeightBuffer::default())
+        .manage(settings_events::SettingsWatcher::default())
+        .manage(shutdown::Operations::default())
+        .manage(sync_engine::SyncEngine::default())
+        .manage(tasks::TaskPool::default())
+        .setup(|app| {
+            crash_reports::install(&app.handle());
+            logging::init(&app.handle());
+            messages::load(&app.handle());
+            profiles::init(&app.handle())?;
+            companies::init(&app.handle())?;
+            if let Err(e) = startup_recovery::recover(&app.handle()) {
+                crash_reports::report_fatal(&app.handle(), "startup recovery", &e);
+            }
+            stale_tickets::start_monitor(app.handle());
+            scale_maintenance::start_reminders(app.handle());
+            backup_schedule::start_scheduler(app.handle());
+            report_schedule::start_scheduler(app.handle());
+            invoicing::start_scheduler(app.handle());
+            telemetry::start_collector(app.handle());
+            heartbeat::start(app.handle());
+            mining_portal::start(app.handle());
+            anpr::start_worker(app.handle());
+            tasks::start_workers(app.handle());
+            storage::start_sampler(app.handle());
+            settings_events::start_watcher(app.handle());
+            scale_listener::watch_settings(app.handle());
+            bandwidth::watch_settings(app.handle());
+            rfid::start_from_settings(&app.handle());
+            sync_engine::start_loop(app.handle());
+            if headless::requested() {
+                headless::start(app.handle());
+            }
+            // A failed check is reported to admins, it must not block startup
+            if let Err(e) = updates::verify_after_update(&app.handle()) {
+                crash_reports::report_fatal(&app.handle(), "post-update verification", &e);
+            }
+            let _ = crash_reports::collect(&app.handle());
+            Ok(())
+        })
+        .on_window_event(shutdown::on_window_event)
+        .invoke_system(
+            runtime_metrics::IPC_SCRIPT.to_string(),
+            runtime_metrics::respond,
+        )
+        .invoke_handler(authorization::guard(tauri::generate_handler![
             init_database,
             execute_query,
-            execute_non_query
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+            execute_query_encoded,
+            execute_query_with_columns,
+            execute_non_query,
+            execute_transaction,
+            execute_batch,
+            backup::backup_database,
+            backup::restore_database,
+            backup::verify_backup,
+            backup::list_backup_verifications,
+            backup_schedule::get_backup_schedule,
+            backup_schedule::set_backup_schedule,
+            cloud_backup::get_cloud_backup_settings,
+            cloud_backup::set_cloud_backup_settings,
+            cloud_backup::upload_backup_to_cloud,
+            cloud_backup::list_cloud_backups,
+            cloud_backup::restore_from_cloud,
+            bandwidth::get_bandwidth_settings,
+            bandwidth::get_bandwidth_status,
+            bandwidth::reserve_bandwidth,
+            bandwidth::set_bandwidth_settings,
+            barcode::decode_barcode,
+            bulk::cancel_bulk_job,
+            bulk::get_bulk_job,
+            bulk::list_bulk_job_items,
+            bulk::start_bulk_job,
+            calendar::delete_holiday,
+            calendar::get_working_calendar,
+            calendar::list_holidays,
+            calendar::list_out_of_hours_activity,
+            calendar::set_holiday,
+            calendar::set_working_calendar,
+            calendar::working_calendar_status,
+            cameras::capture_snapshots,
+            cameras::get_snapshot,
+            cameras::list_lane_cameras,
+            cameras::list_ticket_snapshots,
+            cameras::remove_lane_camera,
+            cameras::set_lane_camera,
+            command_audit::query_security_log,
+            communications::send_communication,
+            communications::retry_communication,
+            communications::list_communications,
+            companies::create_company,
+            companies::list_companies,
+            companies::switch_company,
+            amendments::request_amendment,
+            amendments::approve_amendment,
+            amendments::reject_amendment,
+            amendments::list_amendments,
+            approvals::list_pending_approvals,
+            approvals::approve_request,
+            approvals::reject_request,
+            approvals::list_approvals_for,
+            archive::archive_before,
+            archive::query_archive,
+            archive::restore_from_archive,
+            attachments::collect_attachment_garbage,
+            attachments::deduplicate_attachments,
+            attachments::delete_attachment,
+            attachments::list_attachments,
+            attachments::read_attachment,
+            attachments::save_attachment,
+            audit_log::export_audit_log,
+            audit_log::query_audit_log,
+            auditor::list_auditor_views,
+            auth::change_password,
+            auth::create_user,
+            auth::current_session,
+            auth::logout,
+            auth::verify_login,
+            analytics::scan_anomalies,
+            analytics::list_anomalies,
+            analytics::review_anomaly,
+            analytics::tare_statistics_report,
+            analytics::bridge_utilization_report,
+            analytics::shift_utilization_report,
+            anpr::get_anpr_settings,
+            anpr::set_anpr_settings,
+            anpr::recognize_plate,
+            anpr::list_anpr_requests,
+            anpr::retry_anpr_requests,
+            deductions::list_deduction_rules,
+            deductions::set_deduction_rule,
+            deductions::get_weighment_deductions,
+            delta_sync::ack_sync_changes,
+            delta_sync::get_sync_changes,
+            delta_sync::reset_sync_watermark,
+            disputes::add_dispute_attachment,
+            disputes::dispute_report,
+            disputes::link_dispute_amendment,
+            disputes::list_dispute_attachments,
+            disputes::list_disputes,
+            disputes::raise_dispute,
+            disputes::resolve_dispute,
+            driver_signatures::capture_driver_signature,
+            driver_signatures::get_driver_signature,
+            drivers::list_drivers,
+            encryption::get_database_encryption,
+            encryption::migrate_to_encrypted,
+            encryption::set_database_key,
+            config_sync::list_config_versions,
+            config_sync::publish_configuration,
+            config_sync::pull_configuration,
+            config_sync::rollback_configuration,
+            configuration::export_configuration,
+            configuration::import_configuration,
+            crash_reports::get_crash_upload_opt_in,
+            crash_reports::list_crash_reports,
+            crash_reports::mark_crash_reports_uploaded,
+            crash_reports::pending_crash_uploads,
+            crash_reports::set_crash_upload_opt_in,
+            credit::get_party_credit,
+            credit::list_credit_limits,
+            credit::over_limit_report,
+            credit::set_credit_limit,
+            csv_import::import_csv,
+            currency::add_exchange_rate,
+            currency::get_base_currency,
+            currency::get_party_currency,
+            currency::get_weighment_billing,
+            currency::list_exchange_rates,
+            currency::party_billing_statement,
+            currency::set_base_currency,
+            currency::set_party_currency,
+            dashboard::get_dashboard_stats,
+            data_version::get_data_version,
+            day_close::close_day,
+            day_close::day_close_checklist,
+            day_close::list_day_closings,
+            cursors::close_cursor,
+            cursors::fetch_next,
+            cursors::open_query_cursor,
+            export::export_jsonl,
+            export::export_parquet,
+            export::export_query,
+            export_destinations::delete_export_destination,
+            export_destinations::export_to_destination,
+            export_destinations::list_export_deliveries,
+            export_destinations::list_export_destinations,
+            export_destinations::set_export_destination,
+            feature_flags::is_feature_enabled,
+            feature_flags::list_feature_flags,
+            feature_flags::set_feature_flag,
+            feature_flags::apply_remote_flags,
+            formatting::format_amount_in_words,
+            formatting::get_number_format,
+            formatting::set_number_format,
+            fraud::evaluate_fraud_rules,
+            fraud::get_fraud_rules,
+            fraud::set_fraud_rules,
+            fraud::fraud_report,
+            headless::get_headless_settings,
+            headless::set_headless_settings,
+            heartbeat::get_heartbeat_settings,
+            heartbeat::get_heartbeat_status,
+            heartbeat::preview_heartbeat,
+            heartbeat::send_heartbeat,
+            heartbeat::set_heartbeat_settings,
+            history::weighment_history,
+            lan_server::start_lan_server,
+            lan_server::stop_lan_server,
+            lan_server::lan_server_status,
+            lan_server::get_lan_server_tls,
+            lan_server::set_lan_server_tls,
+            lanes::get_lane_state,
+            lanes::lane_capture_weight,
+            lanes::list_lanes,
+            lanes::reset_lane,
+            lanes::set_lane,
+            lanes::take_lane_capture,
+            license::activate_license,
+            license::get_license_status,
+            logging::export_logs,
+            logging::get_recent_logs,
+            maintenance::check_integrity,
+            maintenance::reindex,
+            maintenance::repair_database,
+            maintenance::vacuum_database,
+            masking::get_masking_policy,
+            masking::set_masking_policy,
+            master_data::get_master_data,
+            messages::get_language,
+            messages::install_language_pack,
+            messages::set_language,
+            migrations::get_schema_version,
+            mining_portal::get_mining_portal_settings,
+            mining_portal::set_mining_portal_settings,
+            mining_portal::preview_mining_submission,
+            mining_portal::list_mining_submissions,
+            mining_portal::retry_mining_submission,
+            mobile_api::create_party_token,
+            mobile_api::list_party_tokens,
+            mobile_api::revoke_party_token,
+            inventory::adjust_stock,
+            inventory::get_stock_balance,
+            inventory::stock_statement,
+            invoicing::consolidated_invoice_pdf,
+            invoicing::generate_consolidated_invoices,
+            invoicing::get_consolidated_invoice,
+            invoicing::list_billing_instructions,
+            invoicing::list_consolidated_invoices,
+            invoicing::set_billing_instruction,
+            movements::list_movement_rules,
+            movements::set_movement_rule,
+            movements::set_ticket_direction,
+            movements::stock_movement_report,
+            network::get_proxy_settings,
+            network::install_ca_certificate,
+            network::list_ca_certificates,
+            network::outbound_route,
+            network::remove_ca_certificate,
+            network::set_proxy_settings,
+            notifications::list_notifications,
+            notifications::mark_notification_read,
+            onvif::discover_onvif_cameras,
+            onvif::get_onvif_device,
+            onvif::goto_lane_camera_preset,
+            operator_profiles::get_operator_profile,
+            operator_profiles::list_operator_profiles,
+            operator_profiles::save_operator_profile,
+            operator_profiles::delete_operator_profile,
+            operator_profiles::set_operator_preferences,
+            overrides::list_overrides,
+            overrides::set_supervisor_pin,
+            period_lock::get_period_lock,
+            period_lock::lock_period,
+            period_lock::unlock_period,
+            period_lock::period_lock_history,
+            peripherals::get_relay_board,
+            peripherals::set_relay_board,
+            peripherals::list_relay_sequences,
+            peripherals::set_relay_sequences,
+            peripherals::set_relay,
+            peripherals::run_relay_sequence,
+            peripherals::get_relay_states,
+            positioning::get_positioning_settings,
+            positioning::set_positioning_settings,
+            preflight::validate_configuration,
+            print_jobs::confirm_print,
+            print_jobs::dismiss_print_job,
+            print_jobs::list_print_jobs,
+            print_jobs::reprint_queue,
+            print_jobs::start_slip_print,
+            profiles::get_active_profile,
+            profiles::list_profiles,
+            profiles::select_profile,
+            profiles::set_profile_settings,
+            purchase_orders::create_purchase_order,
+            purchase_orders::close_purchase_order,
+            purchase_orders::po_fulfillment_report,
+            query_control::cancel_query,
+            query_registry::list_named_queries,
+            query_registry::run_named_query,
+            recovery::check_database_health,
+            recovery::recover_database,
+            references::resolve_reference,
+            report_builder::delete_report_definition,
+            report_builder::export_report_definition,
+            report_builder::list_report_definitions,
+            report_builder::preview_report_definition,
+            report_builder::report_entities,
+            report_builder::run_report_definition,
+            report_builder::save_report_definition,
+            report_schedule::get_report_schedule,
+            report_schedule::send_test_email,
+            report_schedule::set_report_schedule,
+            reports::weighment_slip_pdf,
+            reports::weighment_summary,
+            reports::weighment_summary_pdf,
+            rfid::get_rfid_reader,
+            rfid::set_rfid_reader,
+            rfid::stop_rfid_reader,
+            rfid::submit_rfid_scan,
+            rfid::bind_tag_to_vehicle,
+            rfid::unbind_tag,
+            rfid::lookup_tag,
+            rfid::list_vehicle_tags,
+            rounding::get_rounding_rules,
+            rounding::set_rounding_rules,
+            runtime_metrics::get_runtime_metrics,
+            scale::get_scale_config,
+            scale::set_scale_config,
+            scale::get_scale_source,
+            scale::set_scale_source,
+            scale::list_serial_ports,
+            scale::diagnose_scale,
+            scale::autodetect_protocol,
+            scale_listener::capture_stable_weight,
+            scale_listener::get_stability_settings,
+            scale_listener::set_stability_settings,
+            scale_maintenance::schedule_scale_maintenance,
+            scale_maintenance::record_scale_maintenance,
+            scale_maintenance::cancel_scale_maintenance,
+            scale_maintenance::list_scale_maintenance,
+            scale_maintenance::get_maintenance_reminder_days,
+            scale_maintenance::set_maintenance_reminder_days,
+            scale_maintenance::record_scale_downtime,
+            scale_maintenance::end_scale_downtime,
+            scale_maintenance::list_scale_downtime,
+            scale_listener::start_scale_listener,
+            scale_listener::stop_scale_listener,
+            schema_drift::check_schema_drift,
+            scripting::activate_script,
+            scripting::list_script_errors,
+            scripting::list_script_versions,
+            scripting::list_scripts,
+            scripting::save_script,
+            scripting::test_script,
+            search::search,
+            serial_numbers::allocate_ticket_number,
+            serial_numbers::release_reservation,
+            serial_numbers::reserve_ticket_number,
+            settings::delete_secret,
+            settings::delete_setting,
+            settings::forget_database_key,
+            settings::get_setting,
+            settings::list_secrets,
+            settings::list_settings,
+            settings::remember_database_key,
+            settings::set_secret,
+            settings::set_setting,
+            settings::unlock_remembered_database,
+            setup_wizard::complete_setup,
+            setup_wizard::create_initial_admin,
+            setup_wizard::detect_hardware,
+            setup_wizard::get_company_profile,
+            setup_wizard::get_setup_status,
+            setup_wizard::seed_default_tariffs,
+            setup_wizard::set_company_profile,
+            setup_wizard::set_ticket_numbering,
+            setup_wizard::test_printer,
+            setup_wizard::test_scale_connection,
+            shifts::close_shift,
+            shifts::get_open_shift,
+            shifts::get_shift_reconciliation,
+            shifts::open_shift,
+            shifts::record_payment,
+            shifts::shift_reconciliation_report,
+            shutdown::begin_operation,
+            shutdown::end_operation,
+            shutdown::list_operations,
+            signatures::verify_all,
+            signatures::verify_weighment,
+            slip_image::render_slip_png,
+            slip_layout::save_print_template,
+            slip_layout::render_slip_layout,
+            slip_layout::set_printer_profile,
+            slip_layout::list_printer_profiles,
+            slip_verification::get_slip_verification,
+            snapshots::list_snapshots,
+            snapshots::rollback_to_snapshot,
+            snapshots::take_snapshot,
+            stale_tickets::get_stale_ticket_hours,
+            stale_tickets::list_stale_tickets,
+            stale_tickets::resolve_stale_tickets,
+            stale_tickets::scan_stale_tickets,
+            stale_tickets::set_stale_ticket_hours,
+            startup_recovery::recovery_report,
+            storage::list_storage_samples,
+            storage::storage_forecast,
+            support_console::list_support_queries,
+            support_console::support_query,
+            sync_engine::get_sync_settings,
+            sync_engine::get_sync_status,
+            sync_engine::list_sync_conflicts,
+            sync_engine::pull_changes,
+            sync_engine::push_changes,
+            sync_engine::review_sync_conflict,
+            sync_engine::set_sync_settings,
+            sync_engine::sync_now,
+            tariffs::list_tariffs,
+            tariffs::re_rate_tickets,
+            tariffs::set_tariff,
+            tasks::cancel_task,
+            tasks::get_task_status,
+            tasks::list_tasks,
+            tasks::submit_task,
+            telemetry::get_telemetry_opt_in,
+            telemetry::last_telemetry_day,
+            telemetry::mark_telemetry_uploaded,
+            telemetry::pending_telemetry_batches,
+            telemetry::preview_telemetry,
+            telemetry::set_telemetry_opt_in,
+            thermal_printer::get_thermal_printer,
+            thermal_printer::print_ticket,
+            thermal_printer::set_thermal_printer,
+            training::get_training_mode,
+            training::set_training_mode,
+            training::purge_practice_data,
+            undelete::list_deleted_records,
+            undelete::undelete_with_dependencies,
+            transporters::list_transporters,
+            transporters::set_transporter,
+            transporters::assign_transporter,
+            transporters::transporter_settlement_report,
+            ticket_feed::weighment_feed,
+            ticket_parties::get_ticket_parties,
+            ticket_parties::set_ticket_parties,
+            ticket_parties::party_role_report,
+            ticket_qr::generate_ticket_qr,
+            updates::check_for_updates,
+            updates::get_update_channel,
+            updates::list_update_history,
+            updates::prepare_update,
+            updates::set_update_channel,
+            updates::set_update_manifest,
+            uploads::ack_upload_chunk,
+            uploads::complete_upload,
+            uploads::pending_uploads,
+            uploads::read_upload_chunk,
+            uploads::report_upload_failure,
+            uploads::set_upload_remote_id,
+            uploads::start_upload,
+            vehicle_classes::assign_vehicle_class,
+            vehicle_classes::check_vehicle_weights,
+            vehicle_classes::delete_vehicle_class,
+            vehicle_classes::get_vehicle_class,
+            vehicle_classes::get_vehicle_class_validation,
+            vehicle_classes::list_vehicle_class_violations,
+            vehicle_classes::list_vehicle_classes,
+            vehicle_classes::set_vehicle_class,
+            vehicle_classes::set_vehicle_class_validation,
+            voids::void_ticket,
+            watermark::get_watermark_settings,
+            watermark::set_watermark_settings,
+            watermark::watermark_snapshot,
+            weighing::capture_weight,
+            weighing::complete_weighment,
+            weighing::create_weighment,
+            weighing::list_capture_rules,
+            weighing::quick_weigh,
+            weighing::set_capture_rule,
+            weighing_steps::get_weighment_steps,
+            weighing_steps::list_weighing_sequences,
+            weighing_steps::record_weighing_step,
+            weighing_steps::remove_weighing_sequence,
+            weighing_steps::set_weighing_sequence,
+            weight_history::get_weight_history,
+            workflow_journal::finish_workflow_step,
+            workflow_journal::interrupted_workflow_steps,
+            workflow_journal::record_workflow_intent,
+            workflow_journal::resolve_workflow_step,
+            workflow_journal::workflow_journal,
+            voids::get_void_slip
+        ]))
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(shutdown::on_run_event);
 }
@@ -0,0 +1,202 @@
+// Database maintenance for Truckore Pro
+// Integrity checks, VACUUM, REINDEX and a best-effort repair, run from the
+// settings screen rather than only when startup finds the file corrupt.
+// Each works table by table where SQLite allows it and reports progress to
+// windows as `maintenance_progress` events.
+
+use crate::db;
+use crate::recovery::{self, RecoveryReport};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+
+const PROGRESS_EVENT: &str = "maintenance_progress";
+// Integrity problems reported per check; SQLite stops listing after these
+const MAX_PROBLEMS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceProgress {
+    // "check_integrity", "vacuum_database", "reindex" or "repair_database"
+    pub operation: String,
+    // Table being worked on, or "database" for whole-file steps
+    pub step: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    // "quick" or "full"
+    pub mode: String,
+    pub ok: bool,
+    pub tables_checked: usize,
+    pub problems: Vec<String>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VacuumReport {
+    pub bytes_before: i64,
+    pub bytes_after: i64,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReindexReport {
+    pub tables: usize,
+    pub elapsed_ms: u64,
+}
+
+fn progress(app: &AppHandle, operation: &str, step: &str, done: usize, total: usize) {
+    let event = MaintenanceProgress {
+        operation: operation.to_string(),
+        step: step.to_string(),
+        done,
+        total,
+    };
+    let _ = app.emit_all(PROGRESS_EVENT, &event);
+}
+
+fn file_bytes(conn: &Connection) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn check_rows(conn: &Connection, sql: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rows.into_iter().filter(|r| r != "ok").collect())
+}
+
+// Check each table and its indexes, then the file as a whole (free pages
+// and page overlaps). `full` runs integrity_check, which also compares
+// every index with its table; the default quick_check is much faster.
+fn integrity(app: &AppHandle, full: bool) -> Result<IntegrityReport, String> {
+    let started = Instant::now();
+    let pragma = if full {
+        "integrity_check"
+    } else {
+        "quick_check"
+    };
+    let conn = db::open(app)?;
+    let tables = recovery::table_names(&conn, "main").map_err(|e| e.to_string())?;
+    let mut problems = Vec::new();
+    for (i, table) in tables.iter().enumerate() {
+        progress(app, "check_integrity", table, i, tables.len() + 1);
+        let sql = format!("PRAGMA {}(\"{}\")", pragma, table.replace('"', "\"\""));
+        problems.extend(check_rows(&conn, &sql)?);
+        if problems.len() >= MAX_PROBLEMS {
+            break;
+        }
+    }
+    if problems.len() < MAX_PROBLEMS {
+        progress(
+            app,
+            "check_integrity",
+            "database",
+            tables.len(),
+            tables.len() + 1,
+        );
+        for problem in check_rows(&conn, "PRAGMA quick_check")? {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+    }
+    problems.truncate(MAX_PROBLEMS);
+    progress(
+        app,
+        "check_integrity",
+        "done",
+        tables.len() + 1,
+        tables.len() + 1,
+    );
+    Ok(IntegrityReport {
+        mode: if full { "full" } else { "quick" }.to_string(),
+        ok: problems.is_empty(),
+        tables_checked: tables.len(),
+        problems,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+#[tauri::command]
+pub async fn check_integrity(
+    app: AppHandle,
+    full: Option<bool>,
+) -> Result<IntegrityReport, String> {
+    tauri::async_runtime::spawn_blocking(move || integrity(&app, full.unwrap_or(false)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Rebuild the file without free pages. Needs free disk space up to the
+// database's size, and waits for other connections' writes to finish.
+#[tauri::command]
+pub async fn vacuum_database(app: AppHandle) -> Result<VacuumReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let started = Instant::now();
+        let conn = db::open(&app)?;
+        let bytes_before = file_bytes(&conn)?;
+        progress(&app, "vacuum_database", "database", 0, 1);
+        conn.execute_batch("VACUUM").map_err(|e| e.to_string())?;
+        // Return the freed space to the disk in WAL mode too
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")
+            .map_err(|e| e.to_string())?;
+        progress(&app, "vacuum_database", "done", 1, 1);
+        Ok(VacuumReport {
+            bytes_before,
+            bytes_after: file_bytes(&conn)?,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Rebuild every index, table by table
+#[tauri::command]
+pub async fn reindex(app: AppHandle) -> Result<ReindexReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let started = Instant::now();
+        let conn = db::open(&app)?;
+        let tables = recovery::table_names(&conn, "main").map_err(|e| e.to_string())?;
+        for (i, table) in tables.iter().enumerate() {
+            progress(&app, "reindex", table, i, tables.len());
+            conn.execute_batch(&format!("REINDEX \"{}\"", table.replace('"', "\"\"")))
+                .map_err(|e| format!("Reindexing {} failed: {}", table, e))?;
+        }
+        progress(&app, "reindex", "done", tables.len(), tables.len());
+        Ok(ReindexReport {
+            tables: tables.len(),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Salvage every readable row into a fresh file and switch to it, as the
+// startup recovery does, for damage found by check_integrity
+#[tauri::command]
+pub async fn repair_database(app: AppHandle) -> Result<RecoveryReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let report = recovery::recover(&app, &|done, total, table| {
+            progress(&app, "repair_database", table, done, total)
+        })?;
+        let total = report.tables.len();
+        progress(&app, "repair_database", "done", total, total);
+        Ok(report)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,373 @@
+// Field masking for Truckore Pro
+// Some sites do not want every signed-in user to see everything the screens
+// can read: rates and amounts kept from operators, a party's phone number
+// shown only in part. Masking rules name result columns and the roles they
+// apply to, and the backend blanks or partly hides those values before rows
+// leave it, so editing the page cannot reveal them. They apply to the reads
+// screens and terminals make (execute_query and its variants, named read
+// queries and query cursors), exports, the weighment summary, the ticket
+// feed, the dashboard and the billing, credit and settlement reports, by the
+// caller's session role. A raw write may not read a hidden column either
+// (authorization::prepare_raw_write). Operator is the lowest role, so it is
+// the one rules usually name. Off until enabled.
+//
+// Hidden columns are read as NULL by SQLite itself (an authorizer), so an
+// alias or an expression over them comes back empty too. Partial masking
+// works on the result column by name, which caller-written SQL can change,
+// so for such SQL partly masked columns are hidden instead.
+
+use crate::auth;
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+const POLICY_CONFIG_KEY: &str = "masking_policy";
+// Characters left showing by partial masking
+const PARTIAL_VISIBLE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskMode {
+    // The value comes back as null
+    Hide,
+    // Only the last PARTIAL_VISIBLE characters show, e.g. ******3210
+    Partial,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaskRule {
+    // Result column names, case-insensitive; * matches any run of
+    // characters, e.g. "*_rate"
+    pub columns: Vec<String>,
+    // operator, admin or super_admin
+    pub roles: Vec<String>,
+    pub mode: MaskMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaskingPolicy {
+    pub enabled: bool,
+    pub rules: Vec<MaskRule>,
+}
+
+impl Default for MaskingPolicy {
+    // Disabled, with the usual rules ready to switch on
+    fn default() -> Self {
+        let columns = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        MaskingPolicy {
+            enabled: false,
+            rules: vec![
+                MaskRule {
+                    columns: columns(&["rate", "*_rate", "amount", "*_amount", "*_minor"]),
+                    roles: vec!["operator".to_string()],
+                    mode: MaskMode::Hide,
+                },
+                MaskRule {
+                    columns: columns(&["phone", "*_phone", "mobile"]),
+                    roles: vec!["operator".to_string()],
+                    mode: MaskMode::Partial,
+                },
+            ],
+        }
+    }
+}
+
+// The rules that apply to one session; empty when nothing is masked
+#[derive(Debug, Clone, Default)]
+pub struct Masks(Vec<(String, MaskMode)>);
+
+// `pattern` against `name`, both lower-case, * matching any run
+fn matches(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || name.len() < first.len() + last.len() {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    name.ends_with(last)
+}
+
+fn partial(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let shown = match chars.len() > PARTIAL_VISIBLE {
+        true => PARTIAL_VISIBLE,
+        false => 0,
+    };
+    let hidden = chars.len() - shown;
+    let visible: String = chars[hidden..].iter().collect();
+    format!("{}{}", "*".repeat(hidden), visible)
+}
+
+impl Masks {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // How `column` is masked, None when it is shown as is
+    pub fn mode(&self, column: &str) -> Option<MaskMode> {
+        let column = column.to_lowercase();
+        self.0
+            .iter()
+            .find(|(pattern, _)| matches(pattern, &column))
+            .map(|(_, mode)| *mode)
+    }
+
+    // The masks for SQL the caller wrote: partly masked columns are hidden
+    pub fn for_raw_sql(self) -> Masks {
+        Masks(
+            self.0
+                .into_iter()
+                .map(|(pattern, _)| (pattern, MaskMode::Hide))
+                .collect(),
+        )
+    }
+
+    // Run `prepare` with hidden columns reading as NULL wherever the
+    // statement uses them. The authorizer is consulted while a statement is
+    // compiled, so it is removed again before the connection is reused.
+    pub fn prepare<S, E>(
+        &self,
+        conn: &Connection,
+        prepare: impl FnOnce() -> Result<S, E>,
+    ) -> Result<S, E> {
+        let hidden = self.hidden();
+        if hidden.is_empty() {
+            return prepare();
+        }
+        conn.authorizer(Some(move |context: AuthContext<'_>| match context.action {
+            AuthAction::Read { column_name, .. }
+                if hidden
+                    .iter()
+                    .any(|pattern| matches(pattern, &column_name.to_lowercase())) =>
+            {
+                Authorization::Ignore
+            }
+            _ => Authorization::Allow,
+        }));
+        let statement = prepare();
+        conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+        statement
+    }
+
+    // Mask the values of a result row, an object keyed by column
+    pub fn apply(&self, row: &mut Value) {
+        if self.is_empty() {
+            return;
+        }
+        let Some(row) = row.as_object_mut() else {
+            return;
+        };
+        for (column, value) in row.iter_mut() {
+            if let Some(mode) = self.mode(column) {
+                *value = masked_value(mode, value);
+            }
+        }
+    }
+
+    // `value` as JSON with every field masked by its name, at any depth.
+    // For typed commands that return figures, e.g. dashboard.rs: a masked
+    // amount comes back as null, as it does from execute_query.
+    pub fn masked<T: Serialize>(&self, value: &T) -> Result<Value, String> {
+        let mut value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        if !self.is_empty() {
+            self.apply_nested(&mut value);
+        }
+        Ok(value)
+    }
+
+    fn apply_nested(&self, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (name, field) in fields.iter_mut() {
+                    match self.mode(name) {
+                        Some(mode) => *field = masked_value(mode, field),
+                        None => self.apply_nested(field),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply_nested(item)),
+            _ => {}
+        }
+    }
+
+    // Column patterns whose values are hidden, e.g. for the authorizer
+    fn hidden(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(_, mode)| *mode == MaskMode::Hide)
+            .map(|(pattern, _)| pattern.clone())
+            .collect()
+    }
+
+    // Whether `column` is hidden from the caller
+    pub fn hides(&self, column: &str) -> bool {
+        self.mode(column) == Some(MaskMode::Hide)
+    }
+}
+
+fn masked_value(mode: MaskMode, value: &Value) -> Value {
+    match (mode, value) {
+        (_, Value::Null) => Value::Null,
+        (MaskMode::Partial, Value::String(text)) => Value::String(partial(text)),
+        (MaskMode::Partial, Value::Number(number)) => Value::String(partial(&number.to_string())),
+        _ => Value::Null,
+    }
+}
+
+// A masked value for writers that work on SQLite values, e.g. exports
+pub fn masked_cell(mode: MaskMode, value: ValueRef) -> SqlValue {
+    match (mode, value) {
+        (MaskMode::Partial, ValueRef::Text(text)) => {
+            SqlValue::Text(partial(&String::from_utf8_lossy(text)))
+        }
+        (MaskMode::Partial, ValueRef::Integer(i)) => SqlValue::Text(partial(&i.to_string())),
+        (MaskMode::Partial, ValueRef::Real(f)) => SqlValue::Text(partial(&f.to_string())),
+        _ => SqlValue::Null,
+    }
+}
+
+pub fn load_policy(conn: &Connection) -> Result<MaskingPolicy, String> {
+    match db::get_config(conn, POLICY_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(MaskingPolicy::default()),
+    }
+}
+
+// Rules naming the column `old` name `new` too, once a migration renamed
+// it. Policies never saved are the defaults and need nothing.
+pub fn rename_column(conn: &Connection, old: &str, new: &str) -> Result<(), String> {
+    if db::get_config(conn, POLICY_CONFIG_KEY)?.is_none() {
+        return Ok(());
+    }
+    let mut policy = load_policy(conn)?;
+    for rule in &mut policy.rules {
+        if rule.columns.iter().any(|c| c.eq_ignore_ascii_case(old))
+            && !rule.columns.iter().any(|c| c.eq_ignore_ascii_case(new))
+        {
+            rule.columns.push(new.to_string());
+        }
+    }
+    let json = serde_json::to_string(&policy).map_err(|e| e.to_string())?;
+    db::set_config(conn, POLICY_CONFIG_KEY, &json)
+}
+
+pub fn for_role(conn: &Connection, role: &str) -> Result<Masks, String> {
+    let policy = load_policy(conn)?;
+    if !policy.enabled {
+        return Ok(Masks::default());
+    }
+    Ok(Masks(
+        policy
+            .rules
+            .iter()
+            .filter(|rule| rule.roles.iter().any(|r| r == role))
+            .flat_map(|rule| {
+                rule.columns
+                    .iter()
+                    .map(|column| (column.to_lowercase(), rule.mode))
+            })
+            .collect(),
+    ))
+}
+
+// The role masks are chosen by for the session behind `token`. Without one
+// the lowest role's rules apply, so a read that lost its token is not shown
+// more.
+pub fn session_role(app: &AppHandle, token: Option<&str>) -> Result<String, String> {
+    match token {
+        Some(token) => Ok(auth::session_user(app, token)?.role),
+        None => Ok(Role::Operator.as_str().to_string()),
+    }
+}
+
+// Masks for the session behind `token`
+pub fn for_session(app: &AppHandle, token: Option<&str>) -> Result<Masks, String> {
+    let role = session_role(app, token)?;
+    let conn = db::open(app)?;
+    for_role(&conn, &role)
+}
+
+#[tauri::command]
+pub fn get_masking_policy(app: AppHandle) -> Result<MaskingPolicy, String> {
+    let conn = db::open(&app)?;
+    load_policy(&conn)
+}
+
+// Admin only
+#[tauri::command]
+pub fn set_masking_policy(
+    app: AppHandle,
+    policy: MaskingPolicy,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&policy).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_masking_policy", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        for rule in &policy.rules {
+            if rule.columns.iter().all(|c| c.trim().is_empty()) {
+                return Err("A masking rule needs at least one column".to_string());
+            }
+            for role in &rule.roles {
+                Role::parse(role)?;
+            }
+        }
+        let json = serde_json::to_string(&policy).map_err(|e| e.to_string())?;
+        db::set_config(&conn, POLICY_CONFIG_KEY, &json)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_whole_names() {
+        assert!(matches("rate", "rate"));
+        assert!(!matches("rate", "rates"));
+        assert!(matches("*_rate", "exchange_rate"));
+        assert!(!matches("*_rate", "rate"));
+        assert!(matches("*_minor", "billed_amount_minor"));
+        assert!(matches("a*b*c", "a_b_c"));
+        assert!(!matches("a*b*c", "a_c_b"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn typed_results_are_masked_at_any_depth() {
+        let masks = Masks(vec![
+            ("*_minor".to_string(), MaskMode::Hide),
+            ("phone".to_string(), MaskMode::Partial),
+        ]);
+        let masked = masks
+            .masked(&serde_json::json!({
+                "party": "Acme",
+                "amount_minor": 1250,
+                "top": [{ "name": "Sand", "freight_minor": 900 }],
+                "contact": { "phone": "9876543210" },
+            }))
+            .unwrap();
+        assert_eq!(masked["party"], "Acme");
+        assert!(masked["amount_minor"].is_null());
+        assert!(masked["top"][0]["freight_minor"].is_null());
+        assert_eq!(masked["contact"]["phone"], "******3210");
+        assert!(masks.hides("AMOUNT_MINOR"));
+        assert!(!masks.hides("phone"));
+    }
+}
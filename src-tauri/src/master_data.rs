@@ -0,0 +1,80 @@
+// Master data for form dropdowns
+// Vehicles, parties and products change rarely but are read every time a
+// form opens. A version counter in app_config is bumped by triggers on
+// every change, so callers holding the current version get nothing back.
+
+use crate::db;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const VERSION_CONFIG_KEY: &str = "master_data_version";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MasterEntry {
+    pub id: String,
+    pub name: String,
+    // "master" or "walk-in"
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MasterData {
+    pub vehicles: Vec<MasterEntry>,
+    pub parties: Vec<MasterEntry>,
+    pub products: Vec<MasterEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MasterDataUpdate {
+    pub version: i64,
+    // None when the caller's version is current
+    pub data: Option<MasterData>,
+}
+
+fn load(conn: &Connection, table: &str, name_column: &str) -> Result<Vec<MasterEntry>, String> {
+    let mut stmt = conn
+        .prepare_cached(&format!(
+            "SELECT id, {0}, source FROM {1} ORDER BY {0}",
+            name_column, table
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(MasterEntry {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                source: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Vehicles, parties and products, unless `version` is already current
+#[tauri::command]
+pub fn get_master_data(app: AppHandle, version: Option<i64>) -> Result<MasterDataUpdate, String> {
+    let mut conn = db::open(&app)?;
+    // Version and lists are read from one snapshot so they always match
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let current = db::get_config(&tx, VERSION_CONFIG_KEY)?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if version == Some(current) {
+        return Ok(MasterDataUpdate {
+            version: current,
+            data: None,
+        });
+    }
+    let data = MasterData {
+        vehicles: load(&tx, "vehicles", "vehicle_no")?,
+        parties: load(&tx, "parties", "party_name")?,
+        products: load(&tx, "products", "product_name")?,
+    };
+    Ok(MasterDataUpdate {
+        version: current,
+        data: Some(data),
+    })
+}
@@ -0,0 +1,361 @@
+// Language packs for Truckore Pro
+// The screens are translated in the frontend, but text the backend makes
+// (errors, notifications, slip labels) reached operators in English. A
+// language pack is a JSON file of translations keyed by the English text:
+//   {"language": "ta", "name": "Tamil", "messages": {
+//       "Ticket No": "...",
+//       "Weighment {} not found": "... {} ...",
+//       "Tare {} kg exceeds gross {} kg": "... {2} ... {1} ..."}}
+// `{}` in a key stands for any text, and the translation puts the texts
+// back in order with `{}`, or by position with `{1}`, `{2}`. The pack for
+// the language chosen on this PC (language.json in the app data folder,
+// apart from any company's database, so errors opening one are translated
+// too) is loaded from the languages folder on startup and whenever the
+// choice changes. Errors are translated as they go back to the page, with
+// the English kept beside them for support. Text a pack does not cover
+// stays English, as do the PDF and PNG slips, whose fonts have only Latin
+// letters, and slip watermarks.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tauri::{AppHandle, Manager};
+
+const LANGUAGE_FILE: &str = "language.json";
+const PACKS_DIR: &str = "languages";
+// Built in; needs no pack
+const ENGLISH: &str = "en";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguagePackFile {
+    pub language: String,
+    pub name: String,
+    pub messages: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguagePack {
+    pub language: String,
+    pub name: String,
+    pub messages: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageInfo {
+    pub language: String,
+    // Packs in the languages folder
+    pub available: Vec<LanguagePack>,
+    // Why the chosen language's pack did not load, when it did not
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LanguageSetting {
+    language: Option<String>,
+}
+
+// A key with placeholders: the literal text around each `{}`
+struct Pattern {
+    parts: Vec<String>,
+    translation: String,
+}
+
+#[derive(Default)]
+struct Catalog {
+    language: Option<String>,
+    exact: HashMap<String, String>,
+    patterns: Vec<Pattern>,
+    error: Option<String>,
+}
+
+// Managed state: the loaded pack
+#[derive(Default)]
+pub struct MessageCatalog(RwLock<Catalog>);
+
+fn app_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| "Failed to get app data directory".to_string())
+}
+
+fn packs_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_dir(app)?.join(PACKS_DIR))
+}
+
+fn valid_language(language: &str) -> Result<(), String> {
+    let valid = !language.is_empty()
+        && language.len() <= 16
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    match valid {
+        true => Ok(()),
+        false => Err(format!(
+            "{:?} is not a language code, e.g. ta or hi",
+            language
+        )),
+    }
+}
+
+fn read_pack(path: &Path) -> Result<LanguagePackFile, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let pack: LanguagePackFile =
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+    valid_language(&pack.language)?;
+    Ok(pack)
+}
+
+fn chosen(app: &AppHandle) -> Result<String, String> {
+    let path = app_dir(app)?.join(LANGUAGE_FILE);
+    if !path.exists() {
+        return Ok(ENGLISH.to_string());
+    }
+    let json = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let setting: LanguageSetting = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+    Ok(setting.language.unwrap_or_else(|| ENGLISH.to_string()))
+}
+
+fn catalog(pack: LanguagePackFile) -> Catalog {
+    let mut catalog = Catalog {
+        language: Some(pack.language),
+        ..Catalog::default()
+    };
+    for (key, translation) in pack.messages {
+        if translation.trim().is_empty() {
+            continue;
+        }
+        match key.contains("{}") {
+            true => catalog.patterns.push(Pattern {
+                parts: key.split("{}").map(str::to_string).collect(),
+                translation,
+            }),
+            false => {
+                catalog.exact.insert(key, translation);
+            }
+        }
+    }
+    // Longer literal text first, so the most specific key wins
+    catalog
+        .patterns
+        .sort_by_key(|p| std::cmp::Reverse(p.parts.iter().map(String::len).sum::<usize>()));
+    catalog
+}
+
+// Load the pack for the language chosen on this PC. English, or a pack
+// that cannot be read, leaves the text as it is.
+pub fn load(app: &AppHandle) {
+    let result = chosen(app).and_then(|language| match language.as_str() {
+        ENGLISH => Ok(Catalog::default()),
+        _ => read_pack(&packs_dir(app)?.join(format!("{}.json", language))).map(catalog),
+    });
+    let catalog = result.unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "Language pack not loaded");
+        Catalog {
+            error: Some(e),
+            ..Catalog::default()
+        }
+    });
+    if let Ok(mut loaded) = app.state::<MessageCatalog>().0.write() {
+        *loaded = catalog;
+    }
+}
+
+// The texts `{}` stood for in `text`, when it has the pattern's shape
+fn captures<'t>(parts: &[String], text: &'t str) -> Option<Vec<&'t str>> {
+    let (first, rest) = parts.split_first()?;
+    let (last, middle) = rest.split_last()?;
+    let mut remaining = text.strip_prefix(first.as_str())?;
+    let mut found = Vec::with_capacity(rest.len());
+    for part in middle {
+        // Placeholders never match nothing
+        let at = remaining.get(1..)?.find(part.as_str())? + 1;
+        found.push(&remaining[..at]);
+        remaining = &remaining[at + part.len()..];
+    }
+    let end = remaining.strip_suffix(last.as_str())?;
+    if end.is_empty() {
+        return None;
+    }
+    found.push(end);
+    Some(found)
+}
+
+// Put captured texts into a translation: `{}` in order, `{n}` by position
+fn fill(translation: &str, values: &[&str]) -> String {
+    let mut out = String::with_capacity(translation.len());
+    let mut next = 0;
+    let mut rest = translation;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let Some(close) = after.find('}') else {
+            out.push_str(&rest[open..]);
+            return out;
+        };
+        let inside = &after[..close];
+        let value = match inside {
+            "" => {
+                next += 1;
+                values.get(next - 1)
+            }
+            _ => inside
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|n| values.get(n)),
+        };
+        match value {
+            Some(value) => out.push_str(value),
+            None => out.push_str(&rest[open..open + close + 2]),
+        }
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+impl Catalog {
+    fn lookup(&self, text: &str) -> Option<String> {
+        if let Some(translation) = self.exact.get(text) {
+            return Some(translation.clone());
+        }
+        self.patterns.iter().find_map(|pattern| {
+            captures(&pattern.parts, text).map(|values| fill(&pattern.translation, &values))
+        })
+    }
+
+    fn translate(&self, text: &str) -> Option<String> {
+        self.language.as_ref()?;
+        if let Some(translation) = self.lookup(text) {
+            return Some(translation);
+        }
+        // "Statement 2: UNIQUE constraint failed" and the like: the context
+        // and the message are translated apart
+        let (head, tail) = text.split_once(": ")?;
+        let translated_head = self.lookup(head);
+        let translated_tail = self.translate(tail);
+        if translated_head.is_none() && translated_tail.is_none() {
+            return None;
+        }
+        Some(format!(
+            "{}: {}",
+            translated_head.as_deref().unwrap_or(head),
+            translated_tail.as_deref().unwrap_or(tail)
+        ))
+    }
+}
+
+// `english` in the chosen language, or as it is when the pack lacks it
+pub fn text(app: &AppHandle, english: &str) -> String {
+    translated(app, english).unwrap_or_else(|| english.to_string())
+}
+
+// `english` in the chosen language; None when the pack lacks it
+pub fn translated(app: &AppHandle, english: &str) -> Option<String> {
+    let catalog = app.try_state::<MessageCatalog>()?;
+    let catalog = catalog.0.read().ok()?;
+    catalog.translate(english)
+}
+
+fn info(app: &AppHandle) -> Result<LanguageInfo, String> {
+    let mut available = Vec::new();
+    let dir = packs_dir(app)?;
+    if dir.exists() {
+        for entry in fs::read_dir(&dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match read_pack(&path) {
+                Ok(pack) => available.push(LanguagePack {
+                    language: pack.language,
+                    name: pack.name,
+                    messages: pack.messages.len(),
+                }),
+                Err(e) => tracing::warn!(error = %e, "Unreadable language pack"),
+            }
+        }
+    }
+    available.sort_by(|a, b| a.language.cmp(&b.language));
+    let error = app
+        .state::<MessageCatalog>()
+        .0
+        .read()
+        .map_err(|e| e.to_string())?
+        .error
+        .clone();
+    Ok(LanguageInfo {
+        language: chosen(app)?,
+        available,
+        error,
+    })
+}
+
+#[tauri::command]
+pub fn get_language(app: AppHandle) -> Result<LanguageInfo, String> {
+    info(&app)
+}
+
+// Choose the language of backend text on this PC (admin only); "en" for
+// English. The pack must be installed first.
+#[tauri::command]
+pub fn set_language(
+    app: AppHandle,
+    language: String,
+    user_id: String,
+) -> Result<LanguageInfo, String> {
+    let args = serde_json::json!({ "language": language });
+    command_audit::audited(&app, "set_language", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let language = language.trim().to_string();
+        valid_language(&language)?;
+        if language != ENGLISH {
+            let pack = packs_dir(&app)?.join(format!("{}.json", language));
+            if !pack.exists() {
+                return Err(format!("No language pack for {} is installed", language));
+            }
+            read_pack(&pack)?;
+        }
+        let setting = LanguageSetting {
+            language: Some(language),
+        };
+        let dir = app_dir(&app)?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(&setting).map_err(|e| e.to_string())?;
+        fs::write(dir.join(LANGUAGE_FILE), json).map_err(|e| e.to_string())?;
+        load(&app);
+        info(&app)
+    })
+}
+
+// Copy a language pack file into the languages folder (admin only),
+// replacing the installed pack for its language. It takes effect at once
+// when that language is the one chosen.
+#[tauri::command]
+pub fn install_language_pack(
+    app: AppHandle,
+    path: String,
+    user_id: String,
+) -> Result<LanguageInfo, String> {
+    let args = serde_json::json!({ "path": path });
+    command_audit::audited(&app, "install_language_pack", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let pack = read_pack(Path::new(&path))?;
+        if pack.language == ENGLISH {
+            return Err("English is built in and needs no pack".to_string());
+        }
+        let dir = packs_dir(&app)?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(&pack).map_err(|e| e.to_string())?;
+        fs::write(dir.join(format!("{}.json", pack.language)), json).map_err(|e| e.to_string())?;
+        load(&app);
+        info(&app)
+    })
+}
@@ -0,0 +1,207 @@
+// Versioned schema migrations for Truckore Pro
+// schema.sql only creates what is missing, so changes to existing tables
+// (new columns, rebuilt constraints, data conversions) ship as numbered
+// migrations embedded in the binary. Pending ones are applied in order on
+// startup, each in its own transaction, and recorded in schema_version.
+// Never edit a migration that has shipped; add a new one.
+
+use crate::auditor;
+use crate::db;
+use crate::delta_sync;
+use crate::money;
+use crate::operator_profiles;
+use crate::search;
+use crate::signatures;
+use crate::sync_engine;
+use crate::undelete;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+enum Step {
+    Sql(&'static str),
+    // Conversions that need to inspect the database first
+    Code(fn(&Connection) -> Result<(), String>),
+}
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    step: Step,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        step: Step::Sql(include_str!(
+            "../../src/services/database/migrations/0001_baseline.sql"
+        )),
+    },
+    Migration {
+        version: 2,
+        name: "money_minor_units",
+        step: Step::Code(money::migrate),
+    },
+    Migration {
+        version: 3,
+        name: "lane_camera_ptz",
+        step: Step::Sql(include_str!(
+            "../../src/services/database/migrations/0003_lane_camera_ptz.sql"
+        )),
+    },
+    Migration {
+        version: 4,
+        name: "row_versions",
+        step: Step::Code(delta_sync::migrate),
+    },
+    Migration {
+        version: 5,
+        name: "weighment_signatures",
+        step: Step::Code(signatures::migrate),
+    },
+    Migration {
+        version: 6,
+        name: "sync_change_log",
+        step: Step::Code(sync_engine::migrate),
+    },
+    Migration {
+        version: 7,
+        name: "full_text_search",
+        step: Step::Code(search::migrate),
+    },
+    Migration {
+        version: 8,
+        name: "operator_profile_sync",
+        step: Step::Code(operator_profiles::migrate),
+    },
+    Migration {
+        version: 9,
+        name: "vehicle_tags",
+        step: Step::Sql(include_str!(
+            "../../src/services/database/migrations/0009_vehicle_tags.sql"
+        )),
+    },
+    Migration {
+        version: 10,
+        name: "auditor_role",
+        step: Step::Code(auditor::migrate),
+    },
+    Migration {
+        version: 11,
+        name: "deleted_records",
+        step: Step::Code(undelete::migrate),
+    },
+    Migration {
+        version: 12,
+        name: "sync_origin",
+        step: Step::Sql(include_str!(
+            "../../src/services/database/migrations/0012_sync_origin.sql"
+        )),
+    },
+    Migration {
+        version: 13,
+        name: "weighment_charges_minor",
+        step: Step::Code(money::migrate_charges),
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaStatus {
+    pub current: i64,
+    pub latest: i64,
+    pub pending: Vec<String>,
+    pub applied: Vec<AppliedMigration>,
+}
+
+pub fn latest() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current(conn: &Connection) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Apply pending migrations. Expects schema.sql to have been applied.
+pub fn run(conn: &Connection) -> Result<(), String> {
+    let version = current(conn)?;
+    if version > latest() {
+        return Err(format!(
+            "Database schema version {} is newer than this app supports ({}); install the newer release",
+            version,
+            latest()
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        let result = match migration.step {
+            Step::Sql(sql) => tx.execute_batch(sql).map_err(|e| e.to_string()),
+            Step::Code(apply) => apply(&tx),
+        };
+        result.map_err(|e| {
+            format!(
+                "Migration {} ({}) failed: {}",
+                migration.version, migration.name, e
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?1, ?2)",
+            rusqlite::params![migration.version, migration.name],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Change-log triggers and the search index, which migrations create and a
+// salvaged copy (recovery.rs) starts without; both rebuild from the rows
+pub fn restore_derived(conn: &Connection) -> Result<(), String> {
+    sync_engine::migrate(conn)?;
+    search::migrate(conn)
+}
+
+// Applied and pending migrations, for the about / diagnostics screen
+#[tauri::command]
+pub fn get_schema_version(app: AppHandle) -> Result<SchemaStatus, String> {
+    let conn = db::open(&app)?;
+    let current = current(&conn)?;
+    let mut stmt = conn
+        .prepare("SELECT version, name, applied_at FROM schema_version ORDER BY version")
+        .map_err(|e| e.to_string())?;
+    let applied = stmt
+        .query_map([], |row| {
+            Ok(AppliedMigration {
+                version: row.get(0)?,
+                name: row.get(1)?,
+                applied_at: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(SchemaStatus {
+        current,
+        latest: latest(),
+        pending: MIGRATIONS
+            .iter()
+            .filter(|m| m.version > current)
+            .map(|m| format!("{:04}_{}", m.version, m.name))
+            .collect(),
+        applied,
+    })
+}
@@ -0,0 +1,755 @@
+// Mining portal submissions for Truckore Pro
+// Quarries and crushers must report every load of mineral that leaves the
+// lease to the state's mining portal, and operators were retyping tickets
+// into the portal at the end of the day. The connector sends each completed
+// ticket of a mineral product to the portal on its own: a background worker
+// queues tickets as they close, formats them the way the state's portal
+// wants them (one CSV row under a header, or a JSON object, with the portal's
+// field names mapped to ticket fields in the settings) and posts them. Every
+// ticket keeps its submission status; failures are retried with a growing
+// delay, and a ticket the portal rejects waits for someone to correct it
+// and send it again. Each post is logged in communications. Practice and
+// voided tickets are never sent; voiding a ticket after it was sent has to
+// be reported on the portal itself.
+
+use crate::command_audit;
+use crate::communications::{self, Outbound};
+use crate::db::{self, DateRange};
+use crate::formatting::{self, DateFormat, NumberFormat};
+use crate::network;
+use crate::roles::{self, Role};
+use crate::settings;
+use crate::shutdown;
+use crate::training;
+use crate::voids;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const SETTINGS_CONFIG_KEY: &str = "mining_portal_settings";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const TICK: Duration = Duration::from_secs(30);
+// Tickets posted per tick
+const BATCH: i64 = 20;
+// Retry delays double from a minute up to six hours
+const FIRST_RETRY_SECS: i64 = 60;
+const MAX_RETRY_SECS: i64 = 6 * 60 * 60;
+const DEFAULT_LIMIT: i64 = 200;
+
+// Held while a ticket is being posted, so the worker and a manual retry
+// never send the same ticket twice
+static SUBMITTING: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PortalFormat {
+    // A header and one row, posted as text/csv
+    #[default]
+    #[serde(rename = "CSV")]
+    Csv,
+    // One JSON object per ticket
+    #[serde(rename = "JSON")]
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldSource {
+    TicketNo,
+    BillNo,
+    VehicleNo,
+    PartyName,
+    ProductName,
+    // The portal's code for the ticket's mineral
+    MineralCode,
+    // Whole kilograms
+    GrossKg,
+    TareKg,
+    NetKg,
+    // Tonnes to three places
+    GrossTonnes,
+    TareTonnes,
+    NetTonnes,
+    // Local date and time the ticket closed
+    Date,
+    Time,
+    LeaseId,
+    Remarks,
+    // The field's `value`
+    Constant,
+}
+
+// One field of the portal's format: its name there and where it comes from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortalField {
+    pub name: String,
+    pub source: FieldSource,
+    #[serde(default)]
+    pub value: String,
+}
+
+// A product that is a mineral, with the portal's code for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mineral {
+    pub product_name: String,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MiningPortalSettings {
+    pub enabled: bool,
+    // The state's portal, as shown in the log, e.g. "Tamil Nadu iMMS"
+    pub portal: String,
+    // Submission URL, https only
+    pub endpoint: String,
+    pub format: PortalFormat,
+    // Name of the stored secret sent with each post
+    pub auth_secret: Option<String>,
+    // Header the secret goes in; a bearer token in Authorization when unset
+    pub auth_header: Option<String>,
+    // Mining lease number the loads are reported against
+    pub lease_id: String,
+    pub minerals: Vec<Mineral>,
+    pub fields: Vec<PortalField>,
+    pub date_format: DateFormat,
+    // Key of the portal's JSON answer holding its acknowledgement number
+    pub reference_field: Option<String>,
+    // Tickets that closed before the connector was first switched on are
+    // not sent; UTC, set by the backend
+    pub since: Option<String>,
+}
+
+impl Default for MiningPortalSettings {
+    fn default() -> Self {
+        let field = |name: &str, source| PortalField {
+            name: name.to_string(),
+            source,
+            value: String::new(),
+        };
+        MiningPortalSettings {
+            enabled: false,
+            portal: String::new(),
+            endpoint: String::new(),
+            format: PortalFormat::default(),
+            auth_secret: None,
+            auth_header: None,
+            lease_id: String::new(),
+            minerals: Vec::new(),
+            fields: vec![
+                field("lease_id", FieldSource::LeaseId),
+                field("ticket_no", FieldSource::TicketNo),
+                field("date", FieldSource::Date),
+                field("time", FieldSource::Time),
+                field("vehicle_no", FieldSource::VehicleNo),
+                field("mineral", FieldSource::MineralCode),
+                field("consignee", FieldSource::PartyName),
+                field("gross_tonnes", FieldSource::GrossTonnes),
+                field("tare_tonnes", FieldSource::TareTonnes),
+                field("net_tonnes", FieldSource::NetTonnes),
+            ],
+            date_format: DateFormat::default(),
+            reference_field: None,
+            since: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiningSubmission {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub vehicle_no: Option<String>,
+    pub product_name: Option<String>,
+    pub net_weight: Option<f64>,
+    pub closed_at: Option<String>,
+    // PENDING, SUBMITTED, FAILED (retried) or REJECTED (by the portal;
+    // sent again only by retry_mining_submission)
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    // The portal's acknowledgement number
+    pub portal_reference: Option<String>,
+    pub queued_at: String,
+    pub last_attempt_at: Option<String>,
+    pub next_attempt_at: Option<String>,
+    pub submitted_at: Option<String>,
+}
+
+// What a ticket's post carries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortalPayload {
+    pub content_type: String,
+    pub body: String,
+}
+
+struct Ticket {
+    id: String,
+    ticket_no: String,
+    bill_no: String,
+    vehicle_no: String,
+    party_name: String,
+    product_name: String,
+    gross: f64,
+    tare: f64,
+    net: f64,
+    // Local time, "YYYY-MM-DD HH:MM:SS"
+    closed_at: String,
+    remarks: Option<String>,
+}
+
+enum Cell {
+    Text(String),
+    // Value and decimal places
+    Number(f64, usize),
+}
+
+const SUBMISSION_SELECT: &str =
+    "SELECT s.weighment_id, s.ticket_no, w.vehicle_no, w.product_name, w.net_weight,
+            w.closed_at, s.status, s.attempts, s.last_error, s.portal_reference,
+            s.queued_at, s.last_attempt_at, s.next_attempt_at, s.submitted_at
+     FROM mining_portal_submissions s
+     LEFT JOIN weighments w ON w.id = s.weighment_id";
+
+fn row_to_submission(row: &rusqlite::Row) -> rusqlite::Result<MiningSubmission> {
+    Ok(MiningSubmission {
+        weighment_id: row.get(0)?,
+        ticket_no: row.get(1)?,
+        vehicle_no: row.get(2)?,
+        product_name: row.get(3)?,
+        net_weight: row.get(4)?,
+        closed_at: row.get(5)?,
+        status: row.get(6)?,
+        attempts: row.get(7)?,
+        last_error: row.get(8)?,
+        portal_reference: row.get(9)?,
+        queued_at: row.get(10)?,
+        last_attempt_at: row.get(11)?,
+        next_attempt_at: row.get(12)?,
+        submitted_at: row.get(13)?,
+    })
+}
+
+fn load_settings(conn: &Connection) -> Result<MiningPortalSettings, String> {
+    match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(MiningPortalSettings::default()),
+    }
+}
+
+fn submission(conn: &Connection, weighment_id: &str) -> Result<MiningSubmission, String> {
+    conn.query_row(
+        &format!("{} WHERE s.weighment_id = ?1", SUBMISSION_SELECT),
+        [weighment_id],
+        row_to_submission,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Ticket {} has no mining portal submission", weighment_id))
+}
+
+fn mineral_products(settings: &MiningPortalSettings) -> Result<String, String> {
+    let products: Vec<&str> = settings
+        .minerals
+        .iter()
+        .map(|m| m.product_name.as_str())
+        .collect();
+    serde_json::to_string(&products).map_err(|e| e.to_string())
+}
+
+// Queue the mineral tickets that closed since the connector was switched on
+fn enqueue(conn: &Connection, settings: &MiningPortalSettings) -> Result<usize, String> {
+    let Some(since) = &settings.since else {
+        return Ok(0);
+    };
+    conn.execute(
+        &format!(
+            "INSERT OR IGNORE INTO mining_portal_submissions (weighment_id, ticket_no)
+             SELECT w.id, w.ticket_no FROM weighments w
+             WHERE w.status IN ('CLOSED', 'PRINTED') AND w.net_weight IS NOT NULL
+               AND w.closed_at >= ?1
+               AND w.product_name IN (SELECT value FROM json_each(?2))
+               AND NOT EXISTS (SELECT 1 FROM ticket_voids v WHERE v.weighment_id = w.id)
+               AND {}",
+            training::exclude_practice("w.id")
+        ),
+        params![since, mineral_products(settings)?],
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn ticket(conn: &Connection, weighment_id: &str) -> Result<Ticket, String> {
+    let ticket = conn
+        .query_row(
+            "SELECT id, ticket_no, bill_no, vehicle_no, party_name, product_name,
+                    gross_weight, tare_weight, net_weight,
+                    datetime(closed_at, 'localtime'), remarks, status
+             FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| {
+                Ok((
+                    Ticket {
+                        id: row.get(0)?,
+                        ticket_no: row.get(1)?,
+                        bill_no: row.get(2)?,
+                        vehicle_no: row.get(3)?,
+                        party_name: row.get(4)?,
+                        product_name: row.get(5)?,
+                        gross: row.get::<_, Option<f64>>(6)?.unwrap_or(0.0),
+                        tare: row.get::<_, Option<f64>>(7)?.unwrap_or(0.0),
+                        net: row.get::<_, Option<f64>>(8)?.unwrap_or(0.0),
+                        closed_at: row.get::<_, Option<String>>(9)?.unwrap_or_default(),
+                        remarks: row.get(10)?,
+                    },
+                    row.get::<_, String>(11)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Weighment {} not found", weighment_id))?;
+    match ticket {
+        (_, status) if status == "OPEN" => Err(format!("Weighment {} is still open", weighment_id)),
+        (ticket, _) => Ok(ticket),
+    }
+}
+
+fn cell(settings: &MiningPortalSettings, ticket: &Ticket, field: &PortalField) -> Cell {
+    let date_only = NumberFormat {
+        date_format: settings.date_format,
+        ..NumberFormat::default()
+    };
+    let text = |value: &str| Cell::Text(value.to_string());
+    let tonnes = |kg: f64| Cell::Number(kg / 1000.0, 3);
+    match field.source {
+        FieldSource::TicketNo => text(&ticket.ticket_no),
+        FieldSource::BillNo => text(&ticket.bill_no),
+        FieldSource::VehicleNo => text(&ticket.vehicle_no),
+        FieldSource::PartyName => text(&ticket.party_name),
+        FieldSource::ProductName => text(&ticket.product_name),
+        FieldSource::MineralCode => Cell::Text(
+            settings
+                .minerals
+                .iter()
+                .find(|m| m.product_name == ticket.product_name)
+                .map(|m| m.code.clone())
+                .unwrap_or_default(),
+        ),
+        FieldSource::GrossKg => Cell::Number(ticket.gross, 0),
+        FieldSource::TareKg => Cell::Number(ticket.tare, 0),
+        FieldSource::NetKg => Cell::Number(ticket.net, 0),
+        FieldSource::GrossTonnes => tonnes(ticket.gross),
+        FieldSource::TareTonnes => tonnes(ticket.tare),
+        FieldSource::NetTonnes => tonnes(ticket.net),
+        FieldSource::Date => Cell::Text(formatting::date(
+            ticket.closed_at.get(..10).unwrap_or_default(),
+            &date_only,
+        )),
+        FieldSource::Time => text(ticket.closed_at.get(11..).unwrap_or_default()),
+        FieldSource::LeaseId => text(&settings.lease_id),
+        FieldSource::Remarks => text(ticket.remarks.as_deref().unwrap_or_default()),
+        FieldSource::Constant => text(&field.value),
+    }
+}
+
+fn csv_cell(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+// The ticket in the portal's format
+fn payload(settings: &MiningPortalSettings, ticket: &Ticket) -> Result<PortalPayload, String> {
+    let cells: Vec<(&str, Cell)> = settings
+        .fields
+        .iter()
+        .map(|f| (f.name.as_str(), cell(settings, ticket, f)))
+        .collect();
+    match settings.format {
+        PortalFormat::Csv => {
+            let header: Vec<String> = cells.iter().map(|(name, _)| csv_cell(name)).collect();
+            let row: Vec<String> = cells
+                .iter()
+                .map(|(_, cell)| match cell {
+                    Cell::Text(text) => csv_cell(text),
+                    Cell::Number(value, places) => format!("{:.*}", places, value),
+                })
+                .collect();
+            Ok(PortalPayload {
+                content_type: "text/csv".to_string(),
+                body: format!("{}\r\n{}\r\n", header.join(","), row.join(",")),
+            })
+        }
+        PortalFormat::Json => {
+            let mut object = serde_json::Map::new();
+            for (name, cell) in cells {
+                let value = match cell {
+                    Cell::Text(text) => Value::from(text),
+                    Cell::Number(value, 0) => Value::from(value.round() as i64),
+                    Cell::Number(value, places) => {
+                        let scale = 10f64.powi(places as i32);
+                        Value::from((value * scale).round() / scale)
+                    }
+                };
+                object.insert(name.to_string(), value);
+            }
+            Ok(PortalPayload {
+                content_type: "application/json".to_string(),
+                body: serde_json::to_string(&object).map_err(|e| e.to_string())?,
+            })
+        }
+    }
+}
+
+// How a post went: the portal's reference, or an error and whether the
+// portal refused the ticket itself (so retrying as it is would not help)
+enum Outcome {
+    Submitted(Option<String>),
+    Failed(String),
+    Rejected(String),
+}
+
+fn post(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &MiningPortalSettings,
+    ticket: &Ticket,
+    payload: &PortalPayload,
+) -> Outcome {
+    let mut outcome = Outcome::Failed("The portal was not reached".to_string());
+    let outbound = Outbound {
+        channel: "WEBHOOK",
+        destination: settings.endpoint.clone(),
+        summary: format!(
+            "{} submission of ticket {}",
+            settings.portal, ticket.ticket_no
+        ),
+        weighment_id: Some(ticket.id.clone()),
+        party_name: Some(ticket.party_name.clone()),
+        request: None,
+        created_by: None,
+    };
+    let result = communications::logged(conn, outbound, || {
+        let agent = network::http_agent(conn, &settings.endpoint, REQUEST_TIMEOUT)?;
+        let mut request = agent
+            .post(&settings.endpoint)
+            .set("Content-Type", &payload.content_type);
+        if let Some(name) = &settings.auth_secret {
+            let token = settings::secret(app, conn, name)?
+                .ok_or_else(|| format!("No secret named {} is stored", name))?;
+            request = match &settings.auth_header {
+                Some(header) => request.set(header, &token),
+                None => request.set("Authorization", &format!("Bearer {}", token)),
+            };
+        }
+        match request.send_string(&payload.body) {
+            Ok(response) => {
+                let body = response.into_string().unwrap_or_default();
+                let reference = settings.reference_field.as_ref().and_then(|field| {
+                    let answer: Value = serde_json::from_str(&body).ok()?;
+                    match answer.get(field)? {
+                        Value::String(text) => Some(text.clone()),
+                        Value::Null => None,
+                        other => Some(other.to_string()),
+                    }
+                });
+                outcome = Outcome::Submitted(reference);
+                Ok(())
+            }
+            Err(ureq::Error::Status(code, response)) => {
+                let error = format!(
+                    "Portal answered HTTP {}: {}",
+                    code,
+                    response.into_string().unwrap_or_default()
+                );
+                // Timeouts and throttling are worth retrying; other client
+                // errors are the portal refusing the ticket
+                outcome = match code {
+                    400..=499 if code != 408 && code != 429 => Outcome::Rejected(error.clone()),
+                    _ => Outcome::Failed(error.clone()),
+                };
+                Err(error)
+            }
+            Err(e) => {
+                let error = format!("Portal unreachable: {}", e);
+                outcome = Outcome::Failed(error.clone());
+                Err(error)
+            }
+        }
+    });
+    match (result, outcome) {
+        // Failed before the request was made, e.g. a missing secret
+        (Err(e), Outcome::Failed(_)) => Outcome::Failed(e),
+        (_, outcome) => outcome,
+    }
+}
+
+// Post one queued ticket and record how it went
+fn submit(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &MiningPortalSettings,
+    weighment_id: &str,
+) -> Result<MiningSubmission, String> {
+    let _submitting = SUBMITTING.lock().map_err(|e| e.to_string())?;
+    let current = submission(conn, weighment_id)?;
+    if current.status == "SUBMITTED" {
+        return Ok(current);
+    }
+    let ticket = ticket(conn, weighment_id)?;
+    let payload = payload(settings, &ticket)?;
+    let attempts = current.attempts + 1;
+    match post(app, conn, settings, &ticket, &payload) {
+        Outcome::Submitted(reference) => conn.execute(
+            "UPDATE mining_portal_submissions
+             SET status = 'SUBMITTED', attempts = ?2, last_error = NULL,
+                 portal_reference = ?3, payload = ?4, last_attempt_at = CURRENT_TIMESTAMP,
+                 next_attempt_at = NULL, submitted_at = CURRENT_TIMESTAMP
+             WHERE weighment_id = ?1",
+            params![weighment_id, attempts, reference, payload.body],
+        ),
+        Outcome::Rejected(error) => conn.execute(
+            "UPDATE mining_portal_submissions
+             SET status = 'REJECTED', attempts = ?2, last_error = ?3, payload = ?4,
+                 last_attempt_at = CURRENT_TIMESTAMP, next_attempt_at = NULL
+             WHERE weighment_id = ?1",
+            params![weighment_id, attempts, error, payload.body],
+        ),
+        Outcome::Failed(error) => {
+            let delay = (FIRST_RETRY_SECS << (attempts - 1).min(20)).min(MAX_RETRY_SECS);
+            tracing::warn!(ticket = %ticket.ticket_no, error = %error, "Mining portal submission failed");
+            conn.execute(
+                "UPDATE mining_portal_submissions
+                 SET status = 'FAILED', attempts = ?2, last_error = ?3, payload = ?4,
+                     last_attempt_at = CURRENT_TIMESTAMP,
+                     next_attempt_at = datetime('now', '+' || ?5 || ' seconds')
+                 WHERE weighment_id = ?1",
+                params![weighment_id, attempts, error, payload.body, delay],
+            )
+        }
+    }
+    .map_err(|e| e.to_string())?;
+    submission(conn, weighment_id)
+}
+
+// Queue what has closed and post what is due
+fn run(app: &AppHandle, conn: &Connection, settings: &MiningPortalSettings) -> Result<(), String> {
+    communications::require_outbound(app)?;
+    enqueue(conn, settings)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT s.weighment_id FROM mining_portal_submissions s
+             WHERE s.status IN ('PENDING', 'FAILED')
+               AND COALESCE(s.next_attempt_at, s.queued_at) <= CURRENT_TIMESTAMP
+               AND NOT EXISTS (SELECT 1 FROM ticket_voids v WHERE v.weighment_id = s.weighment_id)
+             ORDER BY s.queued_at LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let due = stmt
+        .query_map([BATCH], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for weighment_id in due {
+        if shutdown::requested() {
+            break;
+        }
+        if let Err(e) = submit(app, conn, settings, &weighment_id) {
+            tracing::warn!(weighment_id = %weighment_id, error = %e, "Mining portal submission skipped");
+        }
+    }
+    Ok(())
+}
+
+// Background worker; started from the app setup hook. Settings are read
+// each tick, so switching the connector on needs no restart.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            if let Ok(conn) = db::open(&app) {
+                if let Ok(settings) = load_settings(&conn) {
+                    if settings.enabled {
+                        let _ = run(&app, &conn, &settings);
+                    }
+                }
+            }
+            std::thread::sleep(TICK);
+        }
+    });
+}
+
+fn validate(settings: &mut MiningPortalSettings) -> Result<(), String> {
+    settings.endpoint = settings.endpoint.trim().to_string();
+    settings.lease_id = settings.lease_id.trim().to_string();
+    if (settings.enabled || !settings.endpoint.is_empty())
+        && !settings.endpoint.starts_with("https://")
+    {
+        return Err("Mining portal URL must start with https://".to_string());
+    }
+    if settings.fields.is_empty() {
+        return Err("The portal format needs at least one field".to_string());
+    }
+    let mut names = HashSet::new();
+    for field in &mut settings.fields {
+        field.name = field.name.trim().to_string();
+        if field.name.is_empty() {
+            return Err("Every portal field needs a name".to_string());
+        }
+        if !names.insert(field.name.clone()) {
+            return Err(format!("Portal field {} is defined twice", field.name));
+        }
+    }
+    let mut products = HashSet::new();
+    for mineral in &settings.minerals {
+        if mineral.code.trim().is_empty() {
+            return Err(format!(
+                "{} needs its portal mineral code",
+                mineral.product_name
+            ));
+        }
+        if !products.insert(mineral.product_name.as_str()) {
+            return Err(format!("{} is mapped twice", mineral.product_name));
+        }
+    }
+    if settings.enabled {
+        if settings.minerals.is_empty() {
+            return Err("Choose the products that are reported to the portal".to_string());
+        }
+        if settings.lease_id.is_empty()
+            && settings
+                .fields
+                .iter()
+                .any(|f| f.source == FieldSource::LeaseId)
+        {
+            return Err("The mining lease number is required".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_mining_portal_settings(app: AppHandle) -> Result<MiningPortalSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+// Admin only. Switching the connector on for the first time starts the
+// queue from that moment; earlier tickets are sent one by one with
+// retry_mining_submission.
+#[tauri::command]
+pub fn set_mining_portal_settings(
+    app: AppHandle,
+    settings: MiningPortalSettings,
+    user_id: String,
+) -> Result<MiningPortalSettings, String> {
+    let args = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_mining_portal_settings", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut settings = settings;
+        validate(&mut settings)?;
+        settings.since = load_settings(&conn)?.since;
+        if settings.enabled && settings.since.is_none() {
+            settings.since = Some(
+                conn.query_row("SELECT CURRENT_TIMESTAMP", [], |row| row.get(0))
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)?;
+        Ok(settings)
+    })
+}
+
+// What would be posted for a ticket, to check the format against the
+// portal's specification
+#[tauri::command]
+pub fn preview_mining_submission(
+    app: AppHandle,
+    weighment_id: String,
+) -> Result<PortalPayload, String> {
+    let conn = db::open(&app)?;
+    let settings = load_settings(&conn)?;
+    payload(&settings, &ticket(&conn, &weighment_id)?)
+}
+
+// Submissions, newest first, by status and the date the ticket closed
+#[tauri::command]
+pub fn list_mining_submissions(
+    app: AppHandle,
+    status: Option<String>,
+    range: Option<DateRange>,
+    limit: Option<i64>,
+) -> Result<Vec<MiningSubmission>, String> {
+    let conn = db::open(&app)?;
+    let sql = format!(
+        "{} WHERE (?1 IS NULL OR s.status = ?1) AND (?2 IS NULL OR {} BETWEEN ?2 AND ?3)
+         ORDER BY s.queued_at DESC, s.rowid DESC LIMIT ?4",
+        SUBMISSION_SELECT,
+        db::local_date("w.closed_at")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                status,
+                range.as_ref().map(|r| &r.from),
+                range.as_ref().map(|r| &r.to),
+                limit.unwrap_or(DEFAULT_LIMIT)
+            ],
+            row_to_submission,
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Send a ticket now: a failed or rejected one again once it is corrected,
+// or one that closed before the connector was switched on
+#[tauri::command]
+pub fn retry_mining_submission(
+    app: AppHandle,
+    weighment_id: String,
+    user_id: String,
+) -> Result<MiningSubmission, String> {
+    let args = serde_json::json!({ "weighment_id": weighment_id });
+    command_audit::audited(&app, "retry_mining_submission", &user_id, args, || {
+        let conn = db::open(&app)?;
+        communications::require_outbound(&app)?;
+        let settings = load_settings(&conn)?;
+        if settings.endpoint.is_empty() {
+            return Err("No mining portal is set up".to_string());
+        }
+        let ticket = ticket(&conn, &weighment_id)?;
+        if !settings
+            .minerals
+            .iter()
+            .any(|m| m.product_name == ticket.product_name)
+        {
+            return Err(format!(
+                "{} is not reported to the mining portal",
+                ticket.product_name
+            ));
+        }
+        if training::is_practice(&conn, &weighment_id)? {
+            return Err("Practice tickets are not sent to the mining portal".to_string());
+        }
+        if voids::is_voided(&conn, &weighment_id)? {
+            return Err(format!("Ticket {} is voided", ticket.ticket_no));
+        }
+        conn.execute(
+            "INSERT INTO mining_portal_submissions (weighment_id, ticket_no) VALUES (?1, ?2)
+             ON CONFLICT(weighment_id) DO UPDATE SET
+                 status = 'PENDING', next_attempt_at = CURRENT_TIMESTAMP
+             WHERE status <> 'SUBMITTED'",
+            params![weighment_id, ticket.ticket_no],
+        )
+        .map_err(|e| e.to_string())?;
+        submit(&app, &conn, &settings, &weighment_id)
+    })
+}
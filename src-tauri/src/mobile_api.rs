@@ -0,0 +1,283 @@
+// Mobile companion API for Truckore Pro
+// Per-party access tokens let customers fetch their own tickets and slips
+
+use crate::command_audit;
+use crate::db;
+use crate::lan_server::{with_db, ApiError, ApiState};
+use crate::roles::{self, Role};
+use crate::training;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::routing::get;
+use axum::{Json, Router};
+use rand::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartyToken {
+    pub id: i64,
+    pub party_name: String,
+    pub label: String,
+    pub created_by: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MobileTicket {
+    pub ticket_no: String,
+    pub bill_no: String,
+    pub vehicle_no: String,
+    pub product_name: String,
+    pub gross_weight: Option<f64>,
+    pub tare_weight: Option<f64>,
+    pub net_weight: Option<f64>,
+    // Paise
+    pub charges_minor: Option<i64>,
+    // Charges in the party's billing currency, once billed
+    pub billed_currency: Option<String>,
+    pub billed_amount_minor: Option<i64>,
+    pub status: String,
+    pub voided: bool,
+    pub created_at: String,
+    pub closed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BridgeStatus {
+    pub open_tickets: i64,
+    pub tickets_today: i64,
+    pub last_weighment_at: Option<String>,
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/tickets/today", get(todays_tickets))
+        .route("/bridge/status", get(bridge_status))
+        .route("/slips/:ticket_no", get(slip))
+}
+
+// Resolve the bearer token to the party it was issued for
+fn authenticate(conn: &Connection, headers: &HeaderMap) -> Result<String, ApiError> {
+    let token = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiError::unauthorized("Missing bearer token"))?;
+
+    let token_hash = hash_token(token.trim());
+    let party: Option<String> = conn
+        .query_row(
+            "SELECT party_name FROM party_access_tokens
+             WHERE token_hash = ?1 AND revoked_at IS NULL",
+            [&token_hash],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let party = party.ok_or_else(|| ApiError::unauthorized("Invalid or revoked token"))?;
+
+    conn.execute(
+        "UPDATE party_access_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE token_hash = ?1",
+        [&token_hash],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(party)
+}
+
+const TICKET_SELECT: &str = "SELECT w.ticket_no, w.bill_no, w.vehicle_no, w.product_name,
+        w.gross_weight, w.tare_weight, w.net_weight, w.charges_minor, w.status,
+        v.weighment_id IS NOT NULL, w.created_at, w.closed_at, b.currency,
+        b.billed_amount_minor
+    FROM weighments w LEFT JOIN ticket_voids v ON v.weighment_id = w.id
+        LEFT JOIN weighment_billing b ON b.weighment_id = w.id";
+
+fn row_to_ticket(row: &rusqlite::Row) -> rusqlite::Result<MobileTicket> {
+    Ok(MobileTicket {
+        ticket_no: row.get(0)?,
+        bill_no: row.get(1)?,
+        vehicle_no: row.get(2)?,
+        product_name: row.get(3)?,
+        gross_weight: row.get(4)?,
+        tare_weight: row.get(5)?,
+        net_weight: row.get(6)?,
+        charges_minor: row.get(7)?,
+        billed_currency: row.get(12)?,
+        billed_amount_minor: row.get(13)?,
+        status: row.get(8)?,
+        voided: row.get(9)?,
+        created_at: row.get(10)?,
+        closed_at: row.get(11)?,
+    })
+}
+
+async fn todays_tickets(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<MobileTicket>>, ApiError> {
+    with_db(&state, move |conn| {
+        let party = authenticate(conn, &headers)?;
+        let sql = format!(
+            "{} WHERE w.party_name = ?1 AND {} = date('now', 'localtime') AND {}
+             ORDER BY w.created_at DESC",
+            TICKET_SELECT,
+            db::local_date("w.created_at"),
+            training::exclude_practice("w.id")
+        );
+        let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+        let tickets = stmt
+            .query_map([party], row_to_ticket)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(Json(tickets))
+    })
+    .await
+}
+
+async fn bridge_status(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<BridgeStatus>, ApiError> {
+    with_db(&state, move |conn| {
+        authenticate(conn, &headers)?;
+        let open_tickets: i64 = conn
+            .query_row("SELECT COUNT(*) FROM open_tickets", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let (tickets_today, last_weighment_at): (i64, Option<String>) = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*), MAX(created_at) FROM weighments
+                     WHERE {} = date('now', 'localtime') AND {}",
+                    db::local_date("created_at"),
+                    training::exclude_practice("id")
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(Json(BridgeStatus {
+            open_tickets,
+            tickets_today,
+            last_weighment_at,
+        }))
+    })
+    .await
+}
+
+// Slip data for one of the party's own tickets
+async fn slip(
+    State(state): State<ApiState>,
+    Path(ticket_no): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<MobileTicket>, ApiError> {
+    with_db(&state, move |conn| {
+        let party = authenticate(conn, &headers)?;
+        let sql = format!(
+            "{} WHERE w.ticket_no = ?1 AND w.party_name = ?2 AND {}",
+            TICKET_SELECT,
+            training::exclude_practice("w.id")
+        );
+        conn.query_row(&sql, params![ticket_no, party], row_to_ticket)
+            .optional()
+            .map_err(|e| e.to_string())?
+            .map(Json)
+            .ok_or_else(|| ApiError::not_found("Ticket not found"))
+    })
+    .await
+}
+
+// Issue a new access token for a party (admin only). The plaintext token is
+// returned once; only its hash is stored.
+#[tauri::command]
+pub fn create_party_token(
+    app: AppHandle,
+    party_name: String,
+    label: String,
+    user_id: String,
+) -> Result<String, String> {
+    command_audit::audited(
+        &app,
+        "create_party_token",
+        &user_id,
+        serde_json::json!({ "party_name": party_name, "label": label }),
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+            conn.execute(
+                "INSERT INTO party_access_tokens (token_hash, party_name, label, created_by)
+             VALUES (?1, ?2, ?3, ?4)",
+                params![hash_token(&token), party_name, label, user_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(token)
+        },
+    )
+}
+
+#[tauri::command]
+pub fn list_party_tokens(
+    app: AppHandle,
+    party_name: Option<String>,
+) -> Result<Vec<PartyToken>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, party_name, label, created_by, created_at, last_used_at, revoked_at
+             FROM party_access_tokens WHERE ?1 IS NULL OR party_name = ?1 ORDER BY id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([party_name], |row| {
+            Ok(PartyToken {
+                id: row.get(0)?,
+                party_name: row.get(1)?,
+                label: row.get(2)?,
+                created_by: row.get(3)?,
+                created_at: row.get(4)?,
+                last_used_at: row.get(5)?,
+                revoked_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn revoke_party_token(app: AppHandle, id: i64, user_id: String) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "revoke_party_token",
+        &user_id,
+        serde_json::json!({ "id": id }),
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            conn.execute(
+                "UPDATE party_access_tokens SET revoked_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND revoked_at IS NULL",
+                [id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        },
+    )
+}
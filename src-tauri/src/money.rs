@@ -0,0 +1,344 @@
+// Money handling for Truckore Pro
+// Amounts are held as integer minor units (paise) so sums and conversions
+// never pick up float error, ticket charges (`weighments.charges_minor`)
+// included; the frontend enters and shows rupees but reads and writes
+// paise. Rupees typed in or held as REAL elsewhere are converted with
+// `to_minor`, or in SQL with `minor_sql`, before any arithmetic.
+
+use crate::masking;
+use crate::report_builder;
+use rusqlite::Connection;
+
+// Drop binary representation noise (2.675 stored as 2.67499...) before rounding
+pub fn snap(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+// Half-up, away from zero for negatives
+pub fn half_up(value: f64) -> f64 {
+    value.signum() * (value.abs() + 0.5).floor()
+}
+
+// Rupees (as entered or stored in a REAL column) to paise
+pub fn to_minor(amount: f64) -> i64 {
+    half_up(snap(amount * 100.0)) as i64
+}
+
+// SQL for a REAL rupee column in paise, rounded like `to_minor`, so SUM
+// adds integers rather than accumulating float error row by row
+pub fn minor_sql(column: &str) -> String {
+    format!("CAST(ROUND(ROUND({} * 100, 6)) AS INTEGER)", column)
+}
+
+// Paise as a decimal string, e.g. -1205 -> "-12.05"
+pub fn format_minor(minor: i64) -> String {
+    let sign = if minor < 0 { "-" } else { "" };
+    let abs = minor.unsigned_abs();
+    format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+}
+
+// Backend-owned REAL money columns replaced by INTEGER minor-unit columns
+const CONVERSIONS: &[(&str, &str, &str)] = &[
+    ("weighment_billing", "base_amount", "base_amount_minor"),
+    ("weighment_billing", "billed_amount", "billed_amount_minor"),
+    ("transporters", "rate", "rate_minor"),
+    ("weighment_transport", "rate", "rate_minor"),
+];
+// Ticket charges, converted by schema migration 13 and in archives
+const CHARGES: (&str, &str, &str) = ("weighments", "charges", "charges_minor");
+
+fn has_column(
+    conn: &Connection,
+    schema: &str,
+    table: &str,
+    column: &str,
+) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1, ?2) WHERE name = ?3",
+        [table, schema, column],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|n| n > 0)
+}
+
+// Drop the triggers on `schema`.`table` while `f` rewrites its rows in
+// another unit, which is not an edit for period locks (period_lock.rs) or
+// row versions (delta_sync.rs) to act on, then recreate them as they were
+fn without_triggers(
+    conn: &Connection,
+    schema: &str,
+    table: &str,
+    f: impl FnOnce() -> rusqlite::Result<()>,
+) -> rusqlite::Result<()> {
+    let triggers: Vec<(String, String)> = conn
+        .prepare(&format!(
+            "SELECT name, sql FROM {}.sqlite_master WHERE type = 'trigger' AND tbl_name = ?1",
+            schema
+        ))?
+        .query_map([table], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    for (name, _) in &triggers {
+        conn.execute_batch(&format!("DROP TRIGGER {}.\"{}\"", schema, name))?;
+    }
+    f()?;
+    for (_, sql) in &triggers {
+        // Stored as written, less IF NOT EXISTS, and created in main
+        // unless qualified
+        let prefix = "CREATE TRIGGER ";
+        let definition = sql
+            .get(..prefix.len())
+            .filter(|start| start.eq_ignore_ascii_case(prefix))
+            .map(|_| &sql[prefix.len()..])
+            .ok_or_else(|| rusqlite::Error::InvalidQuery)?;
+        conn.execute_batch(&format!("CREATE TRIGGER {}.{}", schema, definition))?;
+    }
+    Ok(())
+}
+
+// Replace the REAL rupee column `old` of `schema`.`table` by the paise
+// column `new`, unless that was done already. All or nothing, within the
+// caller's transaction if there is one.
+fn convert(
+    conn: &Connection,
+    schema: &str,
+    table: &str,
+    old: &str,
+    new: &str,
+) -> Result<(), String> {
+    let pending = has_column(conn, schema, table, old).map_err(|e| e.to_string())?
+        && !has_column(conn, schema, table, new).map_err(|e| e.to_string())?;
+    if !pending {
+        return Ok(());
+    }
+    let converted = conn.execute_batch("SAVEPOINT minor_units").and_then(|()| {
+        without_triggers(conn, schema, table, || {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {s}.{t} ADD COLUMN {new} INTEGER NOT NULL DEFAULT 0;
+                 UPDATE {s}.{t} SET {new} = {minor};
+                 ALTER TABLE {s}.{t} DROP COLUMN {old};",
+                s = schema,
+                t = table,
+                new = new,
+                minor = minor_sql(&format!("COALESCE({}, 0)", old)),
+                old = old
+            ))
+        })?;
+        conn.execute_batch("RELEASE minor_units")
+    });
+    if let Err(e) = converted {
+        let _ = conn.execute_batch("ROLLBACK TO minor_units; RELEASE minor_units;");
+        return Err(format!(
+            "Migrating {}.{} to minor units failed: {}",
+            table, old, e
+        ));
+    }
+    Ok(())
+}
+
+// Ticket charges in paise in the database attached as `schema`; archives
+// (archive.rs) are converted when next attached
+pub fn convert_charges(conn: &Connection, schema: &str) -> Result<(), String> {
+    let (table, old, new) = CHARGES;
+    convert(conn, schema, table, old, new)
+}
+
+// The REAL rupee column the paise column `new` of `table` replaced, and
+// SQL reading it as paise, for copying rows out of a database written
+// before the conversion (recovery.rs)
+pub fn unconverted(table: &str, new: &str) -> Option<(&'static str, String)> {
+    CONVERSIONS
+        .iter()
+        .chain(std::iter::once(&CHARGES))
+        .find(|(t, _, n)| *t == table && *n == new)
+        .map(|(_, old, _)| (*old, minor_sql(&format!("COALESCE(\"{}\", 0)", old))))
+}
+
+// Convert databases written before amounts were stored in minor units.
+// Schema migration 2; the caller holds the transaction. Tables converted
+// before migrations were versioned are skipped.
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    for (table, old, new) in CONVERSIONS {
+        convert(conn, "main", table, old, new)?;
+    }
+    Ok(())
+}
+
+// Ticket charges in paise, for the frontend and the backend alike, along
+// with pending amendments, masking rules and saved reports that named the
+// rupee column. Schema migration 13; the caller holds the transaction.
+pub fn migrate_charges(conn: &Connection) -> Result<(), String> {
+    convert_charges(conn, "main")?;
+    // Pending amendments of the charges are applied in paise; decided ones
+    // stay the record of what was changed then
+    without_triggers(conn, "main", "weighment_amendments", || {
+        conn.execute(
+            &format!(
+                "UPDATE weighment_amendments
+                 SET field = 'charges_minor',
+                     old_value = CAST({} AS TEXT), new_value = CAST({} AS TEXT)
+                 WHERE field = 'charges' AND status = 'PENDING'",
+                minor_sql("CAST(old_value AS REAL)"),
+                minor_sql("CAST(new_value AS REAL)")
+            ),
+            [],
+        )
+        .map(|_| ())
+    })
+    .map_err(|e| e.to_string())?;
+    masking::rename_column(conn, "charges", "charges_minor")?;
+    report_builder::migrate_charges(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::migrations;
+    use crate::period_lock;
+    use crate::signatures;
+
+    #[test]
+    fn amounts_round_half_up_to_the_paisa() {
+        for (rupees, paise) in [
+            (2.675, 268),
+            (-2.675, -268),
+            (0.1 + 0.2, 30),
+            (1.005, 101),
+            (12.344, 1234),
+            (0.0, 0),
+        ] {
+            assert_eq!(to_minor(rupees), paise, "{}", rupees);
+        }
+        assert_eq!(format_minor(-1205), "-12.05");
+        assert_eq!(format_minor(5), "0.05");
+        assert_eq!(format_minor(100_000), "1000.00");
+    }
+
+    #[test]
+    fn sql_rounds_like_to_minor() {
+        let conn = Connection::open_in_memory().unwrap();
+        for rupees in [2.675, -2.675, 1.005, 0.1 + 0.2, 99.995, 12.344] {
+            let paise: i64 = conn
+                .query_row(&format!("SELECT {}", minor_sql("?1")), [rupees], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(paise, to_minor(rupees), "{}", rupees);
+        }
+    }
+
+    // A database as it was before migration 13, with one locked ticket of
+    // 12.345 rupees, a pending amendment of it and a report and masking
+    // rule naming the old column
+    fn rupee_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        signatures::register(&conn).unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        migrations::run(&conn).unwrap();
+        conn.execute_batch(
+            "ALTER TABLE weighments ADD COLUMN charges REAL DEFAULT 0;
+             ALTER TABLE weighments DROP COLUMN charges_minor;
+             INSERT INTO weighments (id, bill_no, ticket_no, vehicle_no, party_name,
+                                     product_name, net_weight, charges, status, created_at)
+             VALUES ('w1', 'B1', 'T1', 'KA01AB1234', 'Acme', 'Sand', 1000, 12.345,
+                     'CLOSED', '2026-01-10 10:00:00');
+             INSERT INTO weighment_amendments (weighment_id, field, old_value, new_value,
+                                               reason, requested_by, status)
+             VALUES ('w1', 'charges', '12.345', '20.5', 'Wrong rate', 'u1', 'PENDING');",
+        )
+        .unwrap();
+        let report = r#"{"name":"Charges","entity":"weighments","columns":["ticket_no","charges"],
+            "filters":[{"column":"charges","op":"gt","value":10.5}],
+            "totals":[{"column":"charges","function":"sum"}],"sort":"sum_charges"}"#;
+        conn.execute(
+            "INSERT INTO report_definitions (id, name, definition, created_by)
+             VALUES ('r1', 'Charges', ?1, 'u1')",
+            [report],
+        )
+        .unwrap();
+        db::set_config(
+            &conn,
+            "masking_policy",
+            r#"{"enabled":true,"rules":[{"columns":["charges"],"roles":["operator"],"mode":"hide"}]}"#,
+        )
+        .unwrap();
+        db::set_config(&conn, period_lock::LOCK_CONFIG_KEY, "2026-01-31").unwrap();
+        conn
+    }
+
+    #[test]
+    fn locked_charges_move_to_paise_without_being_edited() {
+        let conn = rupee_database();
+        let version_before: i64 = conn
+            .query_row(
+                "SELECT row_version FROM weighments WHERE id = 'w1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        migrate_charges(&conn).unwrap();
+
+        let (paise, version): (i64, i64) = conn
+            .query_row(
+                "SELECT charges_minor, row_version FROM weighments WHERE id = 'w1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(paise, 1235);
+        assert_eq!(version, version_before);
+        assert!(!has_column(&conn, "main", "weighments", "charges").unwrap());
+
+        // The lock still holds once the triggers are back
+        let edit = conn
+            .execute(
+                "UPDATE weighments SET charges_minor = 1 WHERE id = 'w1'",
+                [],
+            )
+            .unwrap_err();
+        assert!(edit.to_string().contains(period_lock::LOCKED_ERROR));
+
+        let amendment: (String, String, String) = conn
+            .query_row(
+                "SELECT field, old_value, new_value FROM weighment_amendments",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            amendment,
+            ("charges_minor".into(), "1235".into(), "2050".into())
+        );
+
+        let report: report_builder::ReportDefinition = serde_json::from_str(
+            &conn
+                .query_row(
+                    "SELECT definition FROM report_definitions WHERE id = 'r1'",
+                    [],
+                    |row| row.get::<_, String>(0),
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(report.columns, ["ticket_no", "charges_minor"]);
+        assert_eq!(report.filters[0].column, "charges_minor");
+        assert_eq!(report.filters[0].value, serde_json::json!(1050));
+        assert_eq!(report.totals[0].column, "charges_minor");
+        assert_eq!(report.sort.as_deref(), Some("sum_charges_minor"));
+
+        assert!(masking::for_role(&conn, "operator")
+            .unwrap()
+            .hides("charges_minor"));
+    }
+
+    #[test]
+    fn converting_twice_changes_nothing() {
+        let conn = rupee_database();
+        migrate_charges(&conn).unwrap();
+        migrate_charges(&conn).unwrap();
+        let paise: i64 = conn
+            .query_row("SELECT charges_minor FROM weighments", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(paise, 1235);
+    }
+}
@@ -0,0 +1,174 @@
+// Inbound/outbound flow classification for Truckore Pro
+// A ticket's direction comes from a manual override, else the material's
+// movement rule (raw material in, finished goods out), else the weighing
+// order: a truck weighed loaded first came in, one weighed empty first left.
+
+use crate::db::{self, DateRange};
+use crate::inventory;
+use crate::training;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovementRule {
+    pub product_name: String,
+    // "INBOUND" or "OUTBOUND"
+    pub direction: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockMovement {
+    pub date: String,
+    pub product_name: String,
+    pub inbound_kg: f64,
+    pub outbound_kg: f64,
+    pub net_kg: f64,
+    pub inbound_tickets: i64,
+    pub outbound_tickets: i64,
+}
+
+// Direction of weighment `w` for tickets without a stored classification
+pub fn derived_direction_sql(alias: &str) -> String {
+    format!(
+        "COALESCE(
+            (SELECT direction FROM material_movement_rules WHERE product_name = {a}.product_name),
+            CASE {a}.first_weight_type WHEN 'tare' THEN 'OUTBOUND' ELSE 'INBOUND' END)",
+        a = alias
+    )
+}
+
+// Stored or derived direction of weighment `alias`
+pub fn direction_sql(alias: &str) -> String {
+    format!(
+        "COALESCE((SELECT direction FROM weighment_direction WHERE weighment_id = {}.id), {})",
+        alias,
+        derived_direction_sql(alias)
+    )
+}
+
+fn validate(direction: &str) -> Result<(), String> {
+    match direction {
+        "INBOUND" | "OUTBOUND" => Ok(()),
+        other => Err(format!("Unknown direction: {}", other)),
+    }
+}
+
+// Fix the direction of a completed ticket so later rule changes don't move it
+pub fn classify(conn: &Connection, weighment_id: &str) -> Result<String, String> {
+    conn.execute(
+        &format!(
+            "INSERT OR IGNORE INTO weighment_direction (weighment_id, direction, source)
+             SELECT w.id, {}, 'RULE' FROM weighments w WHERE w.id = ?1",
+            derived_direction_sql("w")
+        ),
+        [weighment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.query_row(
+        "SELECT direction FROM weighment_direction WHERE weighment_id = ?1",
+        [weighment_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_movement_rules(app: AppHandle) -> Result<Vec<MovementRule>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT product_name, direction FROM material_movement_rules ORDER BY product_name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(MovementRule {
+                product_name: row.get(0)?,
+                direction: row.get(1)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_movement_rule(app: AppHandle, rule: MovementRule) -> Result<(), String> {
+    validate(&rule.direction)?;
+    let conn = db::open(&app)?;
+    conn.execute(
+        "INSERT INTO material_movement_rules (product_name, direction) VALUES (?1, ?2)
+         ON CONFLICT(product_name) DO UPDATE SET direction = excluded.direction",
+        params![rule.product_name, rule.direction],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Manually set a ticket's direction, overriding the rule
+#[tauri::command]
+pub fn set_ticket_direction(
+    app: AppHandle,
+    weighment_id: String,
+    direction: String,
+) -> Result<(), String> {
+    validate(&direction)?;
+    let conn = db::open(&app)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO weighment_direction (weighment_id, direction, source)
+         VALUES (?1, ?2, 'MANUAL')",
+        params![weighment_id, direction],
+    )
+    .map_err(|e| e.to_string())?;
+    // Keep the stock ledger in line with the corrected direction
+    inventory::post_weighment(&conn, &weighment_id)
+}
+
+// Net inflow/outflow per material per day for closed tickets in the range
+#[tauri::command]
+pub fn stock_movement_report(
+    app: AppHandle,
+    range: DateRange,
+    product_name: Option<String>,
+) -> Result<Vec<StockMovement>, String> {
+    let conn = db::open(&app)?;
+    let sql = format!(
+        "SELECT day, product_name,
+                SUM(CASE dir WHEN 'INBOUND' THEN net_weight ELSE 0 END),
+                SUM(CASE dir WHEN 'OUTBOUND' THEN net_weight ELSE 0 END),
+                SUM(dir = 'INBOUND'), SUM(dir = 'OUTBOUND')
+         FROM (
+             SELECT {day} AS day, w.product_name, w.net_weight, {dir} AS dir
+             FROM weighments w
+             WHERE w.net_weight IS NOT NULL AND {day} BETWEEN ?1 AND ?2
+               AND (?3 IS NULL OR w.product_name = ?3)
+               AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {practice}
+         )
+         GROUP BY day, product_name
+         ORDER BY day, product_name",
+        day = db::local_date("w.created_at"),
+        dir = direction_sql("w"),
+        practice = training::exclude_practice("w.id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to, product_name], |row| {
+            let inbound_kg: f64 = row.get(2)?;
+            let outbound_kg: f64 = row.get(3)?;
+            Ok(StockMovement {
+                date: row.get(0)?,
+                product_name: row.get(1)?,
+                inbound_kg,
+                outbound_kg,
+                net_kg: inbound_kg - outbound_kg,
+                inbound_tickets: row.get(4)?,
+                outbound_tickets: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
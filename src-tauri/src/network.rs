@@ -0,0 +1,390 @@
+// Outbound network settings for Truckore Pro
+// Many plant networks only reach the internet through a corporate proxy,
+// often one that re-signs HTTPS with its own root certificate. The proxy
+// (with optional credentials) and extra trusted CA certificates are set
+// here; the sync layer asks outbound_route for each request it makes (email
+// APIs, e-way bill, webhooks) and gets the proxy to use and a CA bundle file
+// to trust. Requests the backend makes itself go through http_agent.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const PROXY_CONFIG_KEY: &str = "outbound_proxy";
+// Kept apart, so the settings JSON never holds it
+const PROXY_PASSWORD_CONFIG_KEY: &str = "outbound_proxy_password";
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+const PROXY_SCHEMES: &[&str] = &["http://", "https://", "socks5://"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxySettings {
+    pub enabled: bool,
+    // e.g. http://proxy.plant.local:3128
+    pub url: String,
+    pub username: Option<String>,
+    // Never sent back to the UI; None keeps the stored password
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    // Hosts reached directly: "erp.plant.local", ".plant.local" for a whole
+    // domain, or "*" to bypass the proxy entirely
+    pub no_proxy: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CaCertificate {
+    // SHA-256 of the DER certificate, hex
+    pub fingerprint: String,
+    pub label: String,
+    pub installed_by: String,
+    pub installed_at: String,
+}
+
+// How to reach one URL
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutboundRoute {
+    // Proxy URL with credentials, None to connect directly
+    pub proxy_url: Option<String>,
+    // PEM bundle of installed certificates, trusted alongside the system
+    // store; None when none are installed
+    pub ca_bundle_path: Option<String>,
+}
+
+fn load_proxy(conn: &Connection) -> Result<ProxySettings, String> {
+    let mut settings: ProxySettings = match db::get_config(conn, PROXY_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+        None => ProxySettings::default(),
+    };
+    settings.password = db::get_config(conn, PROXY_PASSWORD_CONFIG_KEY)?;
+    Ok(settings)
+}
+
+// Host part of a URL, without credentials or port
+fn host_of(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match authority.strip_prefix('[') {
+        // [IPv6]:port
+        Some(v6) => v6.split(']').next()?,
+        None => authority.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+fn bypasses_proxy(settings: &ProxySettings, host: &str) -> bool {
+    if host == "localhost" || host == "::1" || host.starts_with("127.") {
+        return true;
+    }
+    settings.no_proxy.iter().any(|entry| {
+        let entry = entry.trim().to_ascii_lowercase();
+        if entry == "*" {
+            return true;
+        }
+        match entry.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(&entry),
+            None => !entry.is_empty() && host == entry,
+        }
+    })
+}
+
+// Percent-encode a credential for the userinfo part of a URL
+fn encode_userinfo(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn proxy_url_with_credentials(settings: &ProxySettings) -> String {
+    let Some(user) = settings.username.as_deref().filter(|u| !u.is_empty()) else {
+        return settings.url.clone();
+    };
+    let (scheme, rest) = settings
+        .url
+        .split_once("://")
+        .unwrap_or(("http", &settings.url));
+    let credentials = match settings.password.as_deref() {
+        Some(password) => format!("{}:{}", encode_userinfo(user), encode_userinfo(password)),
+        None => encode_userinfo(user),
+    };
+    format!("{}://{}@{}", scheme, credentials, rest)
+}
+
+// Decode one PEM certificate, returning its DER bytes
+fn decode_pem(pem: &str) -> Result<Vec<u8>, String> {
+    let pem = pem.trim();
+    let body = pem
+        .strip_prefix(PEM_BEGIN)
+        .and_then(|rest| rest.strip_suffix(PEM_END))
+        .ok_or("Expected one PEM certificate (-----BEGIN CERTIFICATE-----)")?;
+    let base64: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    let der = general_purpose::STANDARD
+        .decode(base64)
+        .map_err(|e| format!("Certificate is not valid PEM: {}", e))?;
+    // Every X.509 certificate is a DER SEQUENCE
+    if der.first() != Some(&0x30) {
+        return Err("PEM block is not an X.509 certificate".to_string());
+    }
+    Ok(der)
+}
+
+fn bundle_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("ca-bundle.pem"))
+        .ok_or_else(|| "App data directory is unavailable".to_string())
+}
+
+fn installed_pems(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT pem FROM ca_certificates ORDER BY installed_at, fingerprint")
+        .map_err(|e| e.to_string())?;
+    let pems = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(pems)
+}
+
+// Rewrite the CA bundle from the installed certificates; removes it when
+// none are left
+fn write_bundle(app: &AppHandle, conn: &Connection) -> Result<(), String> {
+    let pems = installed_pems(conn)?;
+    let path = bundle_path(app)?;
+    if pems.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+        return Ok(());
+    }
+    // Written aside and renamed, so a client never reads half a bundle
+    let staging = path.with_extension("pem.tmp");
+    std::fs::write(&staging, pems.join("\n") + "\n").map_err(|e| e.to_string())?;
+    std::fs::rename(&staging, &path).map_err(|e| e.to_string())
+}
+
+// Proxy URL (with credentials) to reach `url` through, if any
+fn proxy_for(conn: &Connection, url: &str) -> Result<Option<String>, String> {
+    let settings = load_proxy(conn)?;
+    let host = host_of(url).ok_or_else(|| format!("{} has no host", url))?;
+    Ok((settings.enabled && !bypasses_proxy(&settings, &host))
+        .then(|| proxy_url_with_credentials(&settings)))
+}
+
+// TLS client settings trusting the installed certificates alongside the
+// public roots
+pub fn tls_config(conn: &Connection) -> Result<rustls::ClientConfig, String> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for pem in installed_pems(conn)? {
+        roots
+            .add(decode_pem(&pem)?.into())
+            .map_err(|e| format!("Installed CA certificate is unusable: {}", e))?;
+    }
+    let tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| e.to_string())?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    Ok(tls)
+}
+
+// HTTP client for a request the backend makes itself, routed like
+// outbound_route: through the proxy unless the host bypasses it
+pub fn http_agent(conn: &Connection, url: &str, timeout: Duration) -> Result<ureq::Agent, String> {
+    let mut agent = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .tls_config(Arc::new(tls_config(conn)?));
+    if let Some(proxy) = proxy_for(conn, url)? {
+        agent = agent.proxy(ureq::Proxy::new(proxy).map_err(|e| e.to_string())?);
+    }
+    Ok(agent.build())
+}
+
+// Whether the first hop towards `url` (the proxy, or the host itself)
+// accepts connections
+pub fn probe(conn: &Connection, url: &str, timeout: Duration) -> Result<(), String> {
+    let hop = proxy_for(conn, url)?.unwrap_or_else(|| url.to_string());
+    let (scheme, rest) = hop.split_once("://").unwrap_or(("http", hop.as_str()));
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    let address = match authority.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => authority.to_string(),
+        _ => match scheme {
+            "https" => format!("{}:443", authority),
+            "socks5" => format!("{}:1080", authority),
+            _ => format!("{}:80", authority),
+        },
+    };
+    let address = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("Failed to resolve {}", address))?;
+    TcpStream::connect_timeout(&address, timeout)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to connect to {}: {}", address, e))
+}
+
+#[tauri::command]
+pub fn get_proxy_settings(app: AppHandle) -> Result<ProxySettings, String> {
+    let conn = db::open(&app)?;
+    load_proxy(&conn)
+}
+
+// Set the outbound proxy (admin only)
+#[tauri::command]
+pub fn set_proxy_settings(
+    app: AppHandle,
+    settings: ProxySettings,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "enabled": settings.enabled, "url": settings.url });
+    command_audit::audited(&app, "set_proxy_settings", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut settings = settings;
+        settings.url = settings.url.trim().to_string();
+        if settings.enabled || !settings.url.is_empty() {
+            if !PROXY_SCHEMES.iter().any(|s| settings.url.starts_with(s)) {
+                return Err("Proxy URL must start with http://, https:// or socks5://".to_string());
+            }
+            if host_of(&settings.url).is_none() {
+                return Err(format!("Proxy URL {} has no host", settings.url));
+            }
+        }
+        if let Some(password) = &settings.password {
+            db::set_config(&conn, PROXY_PASSWORD_CONFIG_KEY, password)?;
+        }
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, PROXY_CONFIG_KEY, &json)
+    })
+}
+
+#[tauri::command]
+pub fn list_ca_certificates(app: AppHandle) -> Result<Vec<CaCertificate>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT fingerprint, label, installed_by, installed_at
+             FROM ca_certificates ORDER BY installed_at, fingerprint",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(CaCertificate {
+                fingerprint: row.get(0)?,
+                label: row.get(1)?,
+                installed_by: row.get(2)?,
+                installed_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Trust a PEM certificate for outbound HTTPS (admin only). Installing the
+// same certificate again only updates its label.
+#[tauri::command]
+pub fn install_ca_certificate(
+    app: AppHandle,
+    pem: String,
+    label: String,
+    user_id: String,
+) -> Result<String, String> {
+    let args = serde_json::json!({ "label": label });
+    command_audit::audited(&app, "install_ca_certificate", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let der = decode_pem(&pem)?;
+        let fingerprint: String = Sha256::digest(&der)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        // Stored re-encoded, so the bundle holds clean 64-column PEM
+        let encoded = general_purpose::STANDARD.encode(&der);
+        let lines: Vec<&str> = encoded
+            .as_bytes()
+            .chunks(64)
+            .map(|line| std::str::from_utf8(line).unwrap_or_default())
+            .collect();
+        let clean = format!("{}\n{}\n{}", PEM_BEGIN, lines.join("\n"), PEM_END);
+        conn.execute(
+            "INSERT INTO ca_certificates (fingerprint, label, pem, installed_by)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(fingerprint) DO UPDATE SET label = excluded.label",
+            params![fingerprint, label, clean, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        write_bundle(&app, &conn)?;
+        Ok(fingerprint)
+    })
+}
+
+#[tauri::command]
+pub fn remove_ca_certificate(
+    app: AppHandle,
+    fingerprint: String,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "fingerprint": fingerprint });
+    command_audit::audited(&app, "remove_ca_certificate", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        conn.execute(
+            "DELETE FROM ca_certificates WHERE fingerprint = ?1",
+            params![fingerprint],
+        )
+        .map_err(|e| e.to_string())?;
+        write_bundle(&app, &conn)
+    })
+}
+
+// Called by the sync layer before each outbound request
+#[tauri::command]
+pub fn outbound_route(app: AppHandle, url: String) -> Result<OutboundRoute, String> {
+    let conn = db::open(&app)?;
+    let proxy_url = proxy_for(&conn, &url)?;
+
+    let installed: i64 = conn
+        .query_row("SELECT COUNT(*) FROM ca_certificates", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let ca_bundle_path = match installed {
+        0 => None,
+        _ => {
+            let path = bundle_path(&app)?;
+            // Restored from a backup without the file, or deleted by hand
+            if !path.exists() {
+                write_bundle(&app, &conn)?;
+            }
+            Some(path.to_string_lossy().into_owned())
+        }
+    };
+    Ok(OutboundRoute {
+        proxy_url,
+        ca_bundle_path,
+    })
+}
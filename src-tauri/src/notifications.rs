@@ -0,0 +1,116 @@
+// Notification center for Truckore Pro
+// Persists backend alerts per audience role and pushes them to open windows.
+// Stored in English and put in the language chosen on this PC (messages.rs)
+// as they go out, so changing the language covers older ones too.
+
+use crate::db;
+use crate::messages;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: i64,
+    pub audience: String,
+    pub title: String,
+    pub message: String,
+    pub entity: Option<String>,
+    pub entity_id: Option<String>,
+    pub created_at: String,
+    pub read_at: Option<String>,
+}
+
+// Store a notification for `audience` (a user role) and emit a `notification` event
+pub fn notify(
+    app: &AppHandle,
+    conn: &Connection,
+    audience: &str,
+    title: &str,
+    message: &str,
+    entity: Option<(&str, &str)>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO notifications (audience, title, message, entity, entity_id)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            audience,
+            title,
+            message,
+            entity.map(|e| e.0),
+            entity.map(|e| e.1)
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let notification = conn
+        .query_row(
+            "SELECT id, audience, title, message, entity, entity_id, created_at, read_at
+             FROM notifications WHERE id = ?1",
+            [conn.last_insert_rowid()],
+            row_to_notification,
+        )
+        .map_err(|e| e.to_string())?;
+
+    // Delivery to windows is best effort; the stored row is the source of truth
+    let _ = app.emit_all("notification", &localized(app, notification));
+    Ok(())
+}
+
+fn row_to_notification(row: &rusqlite::Row) -> rusqlite::Result<Notification> {
+    Ok(Notification {
+        id: row.get(0)?,
+        audience: row.get(1)?,
+        title: row.get(2)?,
+        message: row.get(3)?,
+        entity: row.get(4)?,
+        entity_id: row.get(5)?,
+        created_at: row.get(6)?,
+        read_at: row.get(7)?,
+    })
+}
+
+fn localized(app: &AppHandle, notification: Notification) -> Notification {
+    Notification {
+        title: messages::text(app, &notification.title),
+        message: messages::text(app, &notification.message),
+        ..notification
+    }
+}
+
+// List notifications addressed to a role, newest first
+#[tauri::command]
+pub fn list_notifications(
+    app: AppHandle,
+    audience: String,
+    unread_only: bool,
+) -> Result<Vec<Notification>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, audience, title, message, entity, entity_id, created_at, read_at
+             FROM notifications
+             WHERE audience = ?1 AND (?2 = 0 OR read_at IS NULL)
+             ORDER BY id DESC LIMIT 200",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![audience, unread_only], row_to_notification)
+        .map_err(|e| e.to_string())?;
+
+    rows.map(|row| row.map(|notification| localized(&app, notification)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Mark a notification as read
+#[tauri::command]
+pub fn mark_notification_read(app: AppHandle, id: i64) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    conn.execute(
+        "UPDATE notifications SET read_at = CURRENT_TIMESTAMP WHERE id = ?1 AND read_at IS NULL",
+        [id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,349 @@
+// ONVIF support for Truckore Pro lane cameras
+// Discovery finds IP cameras on the local network (WS-Discovery multicast
+// probe), and device queries list their media profiles, snapshot URIs and
+// PTZ presets for the settings screen. A lane camera with a PTZ preset is
+// moved to it before each snapshot.
+// Requests use a WS-Security UsernameToken with a plain-text password, which
+// ONVIF cameras accept over the local network.
+
+use crate::cameras::{self, LaneCamera};
+use crate::db;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const DISCOVERY_ADDRESS: &str = "239.255.255.250:3702";
+const DEFAULT_DISCOVERY_SECONDS: f64 = 3.0;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredCamera {
+    // Device service URL(s) reported by the camera
+    pub xaddrs: Vec<String>,
+    pub name: Option<String>,
+    pub hardware: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PtzPreset {
+    pub token: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaProfile {
+    pub token: String,
+    pub name: String,
+    pub snapshot_uri: Option<String>,
+    pub presets: Vec<PtzPreset>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnvifDevice {
+    pub media_url: Option<String>,
+    pub ptz_url: Option<String>,
+    pub profiles: Vec<MediaProfile>,
+}
+
+// Elements named `name` in any namespace, as (start tag attributes, content).
+// Enough for the flat ONVIF responses read here.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<(&'a str, &'a str)> {
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open + 1..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        let tag_name = tag.split_whitespace().next().unwrap_or("");
+        let local = tag_name.rsplit(':').next().unwrap_or(tag_name);
+        if local.trim_end_matches('/') != name || tag.starts_with('/') {
+            continue;
+        }
+        let attributes = &tag[tag_name.len()..];
+        let body = &rest[tag_end + 1..];
+        if tag.ends_with('/') {
+            found.push((attributes, ""));
+            continue;
+        }
+        let close = format!("</{}>", tag_name);
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        found.push((attributes, &body[..end]));
+        rest = &body[end + close.len()..];
+    }
+    found
+}
+
+fn text(xml: &str, name: &str) -> Option<String> {
+    elements(xml, name)
+        .first()
+        .map(|(_, inner)| inner.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let start = attributes.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = attributes[start..].find('"')?;
+    Some(attributes[start..start + len].to_string())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn envelope(auth: Option<(&str, &str)>, body: &str) -> String {
+    let header = match auth {
+        Some((user, password)) => format!(
+            "<s:Header><wsse:Security xmlns:wsse=\"http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd\">\
+             <wsse:UsernameToken><wsse:Username>{}</wsse:Username>\
+             <wsse:Password Type=\"http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-username-token-profile-1.0#PasswordText\">{}</wsse:Password>\
+             </wsse:UsernameToken></wsse:Security></s:Header>",
+            escape(user),
+            escape(password)
+        ),
+        None => String::new(),
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <s:Envelope xmlns:s=\"http://www.w3.org/2003/05/soap-envelope\" \
+         xmlns:tds=\"http://www.onvif.org/ver10/device/wsdl\" \
+         xmlns:trt=\"http://www.onvif.org/ver10/media/wsdl\" \
+         xmlns:tptz=\"http://www.onvif.org/ver20/ptz/wsdl\" \
+         xmlns:tt=\"http://www.onvif.org/ver10/schema\">{}<s:Body>{}</s:Body></s:Envelope>",
+        header, body
+    )
+}
+
+fn soap(url: &str, auth: Option<(&str, &str)>, body: &str) -> Result<String, String> {
+    let (status, _, response) = cameras::http_request(
+        "POST",
+        url,
+        None,
+        &[("Content-Type", "application/soap+xml; charset=utf-8")],
+        envelope(auth, body).as_bytes(),
+        REQUEST_TIMEOUT,
+    )?;
+    let response = String::from_utf8_lossy(&response).to_string();
+    if status != 200 {
+        let reason = text(&response, "Text").unwrap_or_else(|| format!("HTTP {}", status));
+        return Err(format!("ONVIF request failed: {}", reason));
+    }
+    Ok(response)
+}
+
+fn probe(seconds: f64) -> Result<Vec<DiscoveredCamera>, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(250)))
+        .map_err(|e| e.to_string())?;
+    let message = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <e:Envelope xmlns:e=\"http://www.w3.org/2003/05/soap-envelope\" \
+         xmlns:w=\"http://schemas.xmlsoap.org/ws/2004/08/addressing\" \
+         xmlns:d=\"http://schemas.xmlsoap.org/ws/2005/04/discovery\" \
+         xmlns:dn=\"http://www.onvif.org/ver10/network/wsdl\">\
+         <e:Header><w:MessageID>uuid:{}</w:MessageID>\
+         <w:To e:mustUnderstand=\"true\">urn:schemas-xmlsoap-org:ws:2005:04:discovery</w:To>\
+         <w:Action e:mustUnderstand=\"true\">http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</w:Action>\
+         </e:Header><e:Body><d:Probe><d:Types>dn:NetworkVideoTransmitter</d:Types></d:Probe></e:Body></e:Envelope>",
+        uuid::Uuid::new_v4()
+    );
+    socket
+        .send_to(message.as_bytes(), DISCOVERY_ADDRESS)
+        .map_err(|e| e.to_string())?;
+
+    let mut cameras = Vec::new();
+    let mut seen = HashSet::new();
+    let mut buf = vec![0u8; 65535];
+    let deadline = Instant::now() + Duration::from_secs_f64(seconds);
+    while Instant::now() < deadline {
+        let Ok((n, _)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let reply = String::from_utf8_lossy(&buf[..n]).to_string();
+        let xaddrs: Vec<String> = text(&reply, "XAddrs")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        // Cameras often answer once per network interface
+        let Some(first) = xaddrs.first() else {
+            continue;
+        };
+        if !seen.insert(first.clone()) {
+            continue;
+        }
+        let scopes = text(&reply, "Scopes").unwrap_or_default();
+        let scope = |kind: &str| {
+            scopes.split_whitespace().find_map(|s| {
+                s.strip_prefix(&format!("onvif://www.onvif.org/{}/", kind))
+                    .map(|v| v.replace("%20", " "))
+            })
+        };
+        cameras.push(DiscoveredCamera {
+            name: scope("name"),
+            hardware: scope("hardware"),
+            xaddrs,
+        });
+    }
+    Ok(cameras)
+}
+
+fn device(device_url: &str, auth: Option<(&str, &str)>) -> Result<OnvifDevice, String> {
+    let capabilities = soap(
+        device_url,
+        auth,
+        "<tds:GetCapabilities><tds:Category>All</tds:Category></tds:GetCapabilities>",
+    )?;
+    let service = |name: &str| {
+        elements(&capabilities, name)
+            .first()
+            .and_then(|(_, inner)| text(inner, "XAddr"))
+    };
+    let (media_url, ptz_url) = (service("Media"), service("PTZ"));
+
+    let mut profiles = Vec::new();
+    if let Some(media) = media_url.as_deref() {
+        let response = soap(media, auth, "<trt:GetProfiles/>")?;
+        for (attributes, inner) in elements(&response, "Profiles") {
+            let Some(token) = attribute(attributes, "token") else {
+                continue;
+            };
+            let snapshot_uri = soap(
+                media,
+                auth,
+                &format!(
+                    "<trt:GetSnapshotUri><trt:ProfileToken>{}</trt:ProfileToken></trt:GetSnapshotUri>",
+                    escape(&token)
+                ),
+            )
+            .ok()
+            .and_then(|r| text(&r, "Uri"))
+            .map(|uri| uri.replace("&amp;", "&"));
+            let presets = match ptz_url.as_deref() {
+                Some(ptz) => presets(ptz, auth, &token).unwrap_or_default(),
+                None => Vec::new(),
+            };
+            profiles.push(MediaProfile {
+                name: text(inner, "Name").unwrap_or_else(|| token.clone()),
+                token,
+                snapshot_uri,
+                presets,
+            });
+        }
+    }
+    Ok(OnvifDevice {
+        media_url,
+        ptz_url,
+        profiles,
+    })
+}
+
+fn presets(
+    ptz_url: &str,
+    auth: Option<(&str, &str)>,
+    profile: &str,
+) -> Result<Vec<PtzPreset>, String> {
+    let response = soap(
+        ptz_url,
+        auth,
+        &format!(
+            "<tptz:GetPresets><tptz:ProfileToken>{}</tptz:ProfileToken></tptz:GetPresets>",
+            escape(profile)
+        ),
+    )?;
+    Ok(elements(&response, "Preset")
+        .into_iter()
+        .filter_map(|(attributes, inner)| {
+            let token = attribute(attributes, "token")?;
+            Some(PtzPreset {
+                name: text(inner, "Name").unwrap_or_else(|| token.clone()),
+                token,
+            })
+        })
+        .collect())
+}
+
+// Move a camera to its configured preset. Returns false when none is set.
+pub fn goto_preset(camera: &LaneCamera) -> Result<bool, String> {
+    let (Some(url), Some(profile), Some(preset)) = (
+        camera.ptz_url.as_deref(),
+        camera.ptz_profile.as_deref(),
+        camera.ptz_preset.as_deref(),
+    ) else {
+        return Ok(false);
+    };
+    let auth = camera
+        .username
+        .as_deref()
+        .map(|u| (u, camera.password.as_deref().unwrap_or("")));
+    soap(
+        url,
+        auth,
+        &format!(
+            "<tptz:GotoPreset><tptz:ProfileToken>{}</tptz:ProfileToken>\
+             <tptz:PresetToken>{}</tptz:PresetToken></tptz:GotoPreset>",
+            escape(profile),
+            escape(preset)
+        ),
+    )?;
+    Ok(true)
+}
+
+// Probe the local network for ONVIF cameras
+#[tauri::command]
+pub async fn discover_onvif_cameras(
+    timeout_seconds: Option<f64>,
+) -> Result<Vec<DiscoveredCamera>, String> {
+    let seconds = timeout_seconds
+        .unwrap_or(DEFAULT_DISCOVERY_SECONDS)
+        .clamp(0.5, 15.0);
+    tauri::async_runtime::spawn_blocking(move || probe(seconds))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Media profiles, snapshot URIs and PTZ presets of a discovered camera
+#[tauri::command]
+pub async fn get_onvif_device(
+    device_url: String,
+    username: Option<String>,
+    password: Option<String>,
+) -> Result<OnvifDevice, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let auth = username
+            .as_deref()
+            .map(|u| (u, password.as_deref().unwrap_or("")));
+        device(&device_url, auth)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Move a lane camera to its preset now, e.g. to check framing from settings
+#[tauri::command]
+pub async fn goto_lane_camera_preset(
+    app: AppHandle,
+    lane_id: String,
+    role: String,
+) -> Result<bool, String> {
+    let camera = {
+        let conn = db::open(&app)?;
+        cameras::load_cameras(&conn, &lane_id)?
+            .into_iter()
+            .find(|c| c.role == role)
+            .ok_or_else(|| format!("Lane {} has no {} camera", lane_id, role))?
+    };
+    tauri::async_runtime::spawn_blocking(move || goto_preset(&camera))
+        .await
+        .map_err(|e| e.to_string())?
+}
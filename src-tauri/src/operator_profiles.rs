@@ -0,0 +1,283 @@
+// Operator profiles for Truckore Pro
+// What an operator may do and how their screens start out used to live in
+// each PC's browser storage, so it was lost on a new machine and any
+// operator could weigh on any lane. A profile now holds the lanes the
+// operator may weigh on, the payment modes they may take, their default
+// material and the UI's own preferences, in operator_profiles, which is
+// synced like the masters so it follows the operator between PCs and sites.
+// The limits are enforced here, not by the UI: lane commands are checked by
+// the command guard (authorization.rs) and payments by record_payment. An
+// empty list means no limit, and a user without a profile is not limited.
+
+use crate::command_audit;
+use crate::command_audit::DENIED_PREFIX;
+use crate::db;
+use crate::delta_sync;
+use crate::roles::{self, Role};
+use crate::shifts;
+use crate::sync_engine;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+// Commands that act on the lane in their `laneId` argument
+pub const LANE_COMMANDS: &[&str] = &[
+    "capture_snapshots",
+    "lane_capture_weight",
+    "reset_lane",
+    "take_lane_capture",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperatorProfile {
+    // users.id of the operator
+    pub operator_id: String,
+    pub allowed_lanes: Vec<String>,
+    pub default_material: Option<String>,
+    pub allowed_payment_methods: Vec<String>,
+    pub preferences: Value,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperatorProfileInput {
+    pub operator_id: String,
+    #[serde(default)]
+    pub allowed_lanes: Vec<String>,
+    pub default_material: Option<String>,
+    #[serde(default)]
+    pub allowed_payment_methods: Vec<String>,
+}
+
+// Row versions and change logging for operator_profiles, which was added
+// after migrations 4 and 6. Schema migration 8; the caller holds the
+// transaction.
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    delta_sync::track_versions(conn, "operator_profiles")?;
+    sync_engine::track_changes(conn, "operator_profiles")
+}
+
+const PROFILE_COLUMNS: &str = "id, allowed_lanes, default_material, allowed_payment_methods,
+     preferences, updated_by, updated_at";
+
+fn row_to_profile(row: &rusqlite::Row) -> rusqlite::Result<OperatorProfile> {
+    let allowed_lanes: String = row.get(1)?;
+    let allowed_payment_methods: String = row.get(3)?;
+    let preferences: String = row.get(4)?;
+    Ok(OperatorProfile {
+        operator_id: row.get(0)?,
+        allowed_lanes: serde_json::from_str(&allowed_lanes).unwrap_or_default(),
+        default_material: row.get(2)?,
+        allowed_payment_methods: serde_json::from_str(&allowed_payment_methods).unwrap_or_default(),
+        preferences: serde_json::from_str(&preferences)
+            .unwrap_or_else(|_| Value::Object(Default::default())),
+        updated_by: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+fn find(conn: &Connection, operator_id: &str) -> Result<Option<OperatorProfile>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM operator_profiles WHERE id = ?1",
+            PROFILE_COLUMNS
+        ),
+        [operator_id],
+        row_to_profile,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// The operator's profile, or an unlimited one when they have none
+pub fn load(conn: &Connection, operator_id: &str) -> Result<OperatorProfile, String> {
+    Ok(find(conn, operator_id)?.unwrap_or_else(|| OperatorProfile {
+        operator_id: operator_id.to_string(),
+        allowed_lanes: Vec::new(),
+        default_material: None,
+        allowed_payment_methods: Vec::new(),
+        preferences: Value::Object(Default::default()),
+        updated_by: None,
+        updated_at: None,
+    }))
+}
+
+pub fn require_lane(conn: &Connection, user_id: &str, lane_id: &str) -> Result<(), String> {
+    let profile = load(conn, user_id)?;
+    if !profile.allowed_lanes.is_empty() && !profile.allowed_lanes.iter().any(|l| l == lane_id) {
+        return Err(format!(
+            "{}: lane {} is not assigned to this operator",
+            DENIED_PREFIX, lane_id
+        ));
+    }
+    Ok(())
+}
+
+pub fn require_payment_method(
+    conn: &Connection,
+    user_id: &str,
+    method: &str,
+) -> Result<(), String> {
+    let profile = load(conn, user_id)?;
+    if !profile.allowed_payment_methods.is_empty()
+        && !profile.allowed_payment_methods.iter().any(|m| m == method)
+    {
+        return Err(format!(
+            "{}: this operator may not take {} payments",
+            DENIED_PREFIX, method
+        ));
+    }
+    Ok(())
+}
+
+// Trimmed, without blanks or repeats, in the order given
+fn clean_list(values: &[String], upper: bool) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for value in values {
+        let value = match upper {
+            true => value.trim().to_ascii_uppercase(),
+            false => value.trim().to_string(),
+        };
+        if !value.is_empty() && !cleaned.contains(&value) {
+            cleaned.push(value);
+        }
+    }
+    cleaned
+}
+
+fn save(conn: &Connection, input: &OperatorProfileInput, user_id: &str) -> Result<(), String> {
+    // Also refuses unknown and deactivated users
+    roles::user_role(conn, &input.operator_id)?;
+    let lanes = clean_list(&input.allowed_lanes, false);
+    let methods = clean_list(&input.allowed_payment_methods, true);
+    if let Some(method) = methods
+        .iter()
+        .find(|m| !shifts::PAYMENT_METHODS.contains(&m.as_str()))
+    {
+        return Err(format!("Unknown payment method: {}", method));
+    }
+    let material = input
+        .default_material
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty());
+    if let Some(material) = material {
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM products WHERE product_name = ?1)",
+                [material],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            return Err(format!("Unknown material: {}", material));
+        }
+    }
+    conn.execute(
+        "INSERT INTO operator_profiles
+             (id, allowed_lanes, default_material, allowed_payment_methods, updated_by)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(id) DO UPDATE SET
+             allowed_lanes = excluded.allowed_lanes,
+             default_material = excluded.default_material,
+             allowed_payment_methods = excluded.allowed_payment_methods,
+             updated_by = excluded.updated_by,
+             updated_at = CURRENT_TIMESTAMP",
+        params![
+            input.operator_id,
+            serde_json::to_string(&lanes).map_err(|e| e.to_string())?,
+            material,
+            serde_json::to_string(&methods).map_err(|e| e.to_string())?,
+            user_id,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_operator_profile(
+    app: AppHandle,
+    operator_id: String,
+) -> Result<OperatorProfile, String> {
+    let conn = db::open(&app)?;
+    load(&conn, &operator_id)
+}
+
+#[tauri::command]
+pub fn list_operator_profiles(app: AppHandle) -> Result<Vec<OperatorProfile>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM operator_profiles ORDER BY id",
+            PROFILE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_profile)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Set an operator's lanes, payment modes and default material (supervisors
+// only); their preferences are kept
+#[tauri::command]
+pub fn save_operator_profile(
+    app: AppHandle,
+    profile: OperatorProfileInput,
+    user_id: String,
+) -> Result<OperatorProfile, String> {
+    let args = serde_json::to_value(&profile).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "save_operator_profile", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        save(&conn, &profile, &user_id)?;
+        load(&conn, &profile.operator_id)
+    })
+}
+
+// Lift every limit on an operator (supervisors only)
+#[tauri::command]
+pub fn delete_operator_profile(
+    app: AppHandle,
+    operator_id: String,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "operator_id": operator_id });
+    command_audit::audited(&app, "delete_operator_profile", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        conn.execute(
+            "DELETE FROM operator_profiles WHERE id = ?1",
+            [&operator_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+// Replace the signed-in user's own UI preferences, a JSON object
+#[tauri::command]
+pub fn set_operator_preferences(
+    app: AppHandle,
+    preferences: Value,
+    user_id: String,
+) -> Result<OperatorProfile, String> {
+    if !preferences.is_object() {
+        return Err("Preferences must be a JSON object".to_string());
+    }
+    let conn = db::open(&app)?;
+    conn.execute(
+        "INSERT INTO operator_profiles (id, preferences, updated_by) VALUES (?1, ?2, ?1)
+         ON CONFLICT(id) DO UPDATE SET
+             preferences = excluded.preferences,
+             updated_by = excluded.updated_by,
+             updated_at = CURRENT_TIMESTAMP",
+        params![user_id, preferences.to_string()],
+    )
+    .map_err(|e| e.to_string())?;
+    load(&conn, &user_id)
+}
@@ -0,0 +1,247 @@
+// Supervisor overrides for Truckore Pro
+// A blocked action can go ahead when a supervisor authorizes it on the spot,
+// either with their override PIN or by logging in a second time. Every
+// attempt, granted or refused, is recorded with who asked, who authorized
+// and why.
+
+use crate::auth;
+use crate::db::{self, DateRange};
+use crate::roles::{self, Role};
+use crate::security;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// Completing or editing a ticket dated within a locked period
+pub const LOCKED_PERIOD: &str = "LOCKED_PERIOD";
+// Completing a ticket that takes its party over a BLOCK credit limit
+pub const CREDIT_LIMIT: &str = "CREDIT_LIMIT";
+// Weighing outside the working calendar when it is enforced (calendar.rs)
+pub const OUT_OF_HOURS: &str = "OUT_OF_HOURS";
+// Weights that do not fit the vehicle's class when it is enforced
+// (vehicle_classes.rs)
+pub const VEHICLE_CLASS: &str = "VEHICLE_CLASS";
+
+// Refused attempts per supervisor before overrides are blocked for a while
+const MAX_FAILED_ATTEMPTS: i64 = 5;
+const LOCKOUT_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorOverride {
+    // Operator whose action was blocked
+    pub requested_by: String,
+    // Supervisor's username
+    pub supervisor: String,
+    // Either the supervisor's override PIN or their login password
+    pub pin: Option<String>,
+    pub password: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OverrideRecord {
+    pub id: i64,
+    pub action: String,
+    pub entity: Option<String>,
+    pub entity_id: Option<String>,
+    pub requested_by: String,
+    pub authorized_by: Option<String>,
+    pub method: String,
+    pub reason: String,
+    pub granted: bool,
+    pub refusal: Option<String>,
+    pub created_at: String,
+    pub used_at: Option<String>,
+}
+
+fn check_credentials(conn: &Connection, grant: &SupervisorOverride) -> Result<String, String> {
+    let (supervisor_id, password_hash): (String, String) = conn
+        .query_row(
+            "SELECT id, password_hash FROM users WHERE username = ?1 AND is_active = 1",
+            [&grant.supervisor],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Unknown supervisor: {}", grant.supervisor))?;
+
+    let recent_failures: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM supervisor_overrides
+             WHERE authorized_by = ?1 AND granted = 0
+               AND julianday('now') - julianday(created_at) <= ?2 / 1440.0",
+            params![supervisor_id, LOCKOUT_MINUTES],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if recent_failures >= MAX_FAILED_ATTEMPTS {
+        return Err(format!(
+            "Too many refused overrides for {}; try again in {} minutes",
+            grant.supervisor, LOCKOUT_MINUTES
+        ));
+    }
+
+    let verified = match (&grant.pin, &grant.password) {
+        (Some(pin), _) => {
+            let pin_hash: Option<String> = conn
+                .query_row(
+                    "SELECT pin_hash FROM supervisor_pins WHERE user_id = ?1",
+                    [&supervisor_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let pin_hash =
+                pin_hash.ok_or_else(|| format!("{} has no override PIN set", grant.supervisor))?;
+            auth::verify_secret(pin, &pin_hash)
+        }
+        (None, Some(password)) => auth::verify_secret(password, &password_hash),
+        (None, None) => return Err("A supervisor PIN or password is required".to_string()),
+    };
+    if !verified {
+        return Err(format!("Invalid credentials for {}", grant.supervisor));
+    }
+    Ok(supervisor_id)
+}
+
+// Authorize `action` on an entity. Records the attempt either way and returns
+// the override id when granted. Supervisors cannot authorize their own actions.
+pub fn authorize(
+    conn: &Connection,
+    action: &str,
+    entity: Option<(&str, &str)>,
+    grant: &SupervisorOverride,
+) -> Result<i64, String> {
+    let method = if grant.pin.is_some() {
+        "PIN"
+    } else {
+        "PASSWORD"
+    };
+    let reason = grant.reason.trim();
+    let supervisor_id: Option<String> = conn
+        .query_row(
+            "SELECT id FROM users WHERE username = ?1",
+            [&grant.supervisor],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    let outcome = if reason.is_empty() {
+        Err("A reason is required for supervisor overrides".to_string())
+    } else {
+        check_credentials(conn, grant).and_then(|id| {
+            roles::require_role(conn, &id, Role::Admin)?;
+            if id == grant.requested_by {
+                return Err("Supervisors cannot override their own actions".to_string());
+            }
+            Ok(id)
+        })
+    };
+
+    conn.execute(
+        "INSERT INTO supervisor_overrides
+             (action, entity, entity_id, requested_by, authorized_by, method, reason, granted, refusal)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            action,
+            entity.map(|e| e.0),
+            entity.map(|e| e.1),
+            grant.requested_by,
+            outcome.as_ref().ok().or(supervisor_id.as_ref()),
+            method,
+            reason,
+            outcome.is_ok(),
+            outcome.as_ref().err()
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+
+    let authorized_by = outcome?;
+    security::log_event(
+        conn,
+        Some(&authorized_by),
+        "SUPERVISOR_OVERRIDE",
+        &serde_json::json!({
+            "override_id": id,
+            "action": action,
+            "requested_by": grant.requested_by,
+            "reason": reason,
+        })
+        .to_string(),
+    )?;
+    Ok(id)
+}
+
+// Mark a granted override as spent once the blocked action has gone ahead
+pub fn mark_used(conn: &Connection, override_id: i64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE supervisor_overrides SET used_at = CURRENT_TIMESTAMP WHERE id = ?1",
+        [override_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Set the caller's own override PIN (supervisors only)
+#[tauri::command]
+pub fn set_supervisor_pin(app: AppHandle, user_id: String, pin: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    if !(4..=8).contains(&pin.len()) || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err("Override PIN must be 4 to 8 digits".to_string());
+    }
+    let pin_hash = auth::hash_secret(&pin)?;
+    conn.execute(
+        "INSERT INTO supervisor_pins (user_id, pin_hash) VALUES (?1, ?2)
+         ON CONFLICT(user_id) DO UPDATE SET pin_hash = excluded.pin_hash,
+             updated_at = CURRENT_TIMESTAMP",
+        params![user_id, pin_hash],
+    )
+    .map_err(|e| e.to_string())?;
+    security::log_event(&conn, Some(&user_id), "SUPERVISOR_PIN_SET", "{}")
+}
+
+// Override attempts in the range, newest first (supervisors only)
+#[tauri::command]
+pub fn list_overrides(
+    app: AppHandle,
+    range: DateRange,
+    action: Option<String>,
+    user_id: String,
+) -> Result<Vec<OverrideRecord>, String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT id, action, entity, entity_id, requested_by, authorized_by, method, reason,
+                    granted, refusal, created_at, used_at
+             FROM supervisor_overrides
+             WHERE {} BETWEEN ?1 AND ?2 AND (?3 IS NULL OR action = ?3)
+             ORDER BY id DESC",
+            db::local_date("created_at")
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to, action], |row| {
+            Ok(OverrideRecord {
+                id: row.get(0)?,
+                action: row.get(1)?,
+                entity: row.get(2)?,
+                entity_id: row.get(3)?,
+                requested_by: row.get(4)?,
+                authorized_by: row.get(5)?,
+                method: row.get(6)?,
+                reason: row.get(7)?,
+                granted: row.get(8)?,
+                refusal: row.get(9)?,
+                created_at: row.get(10)?,
+                used_at: row.get(11)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,315 @@
+// Minimal PDF writer for Truckore Pro statements
+// Text-only A4 pages in the standard Courier fonts, so columns line up by
+// padding strings. Long documents are split into pages automatically, each
+// with a "Page n of m" footer. A line may carry a QR code, drawn against the
+// right margin from that line down, or a signature, drawn under its text at
+// the left margin; the caller leaves blank lines for it.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 40.0;
+const FONT_SIZE: f64 = 9.0;
+const LEADING: f64 = 12.0;
+const LINES_PER_PAGE: usize = ((PAGE_HEIGHT - 2.0 * MARGIN - LEADING) / LEADING) as usize;
+// Side of one QR module
+const QR_MODULE: f64 = 2.5;
+// Side of one signature dot
+pub const SIGNATURE_DOT: f64 = 0.75;
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub text: String,
+    pub bold: bool,
+    // Rows of QR modules, dark true (ticket_qr.rs)
+    pub qr: Option<Vec<Vec<bool>>>,
+    // Rows of signature dots, dark true (driver_signatures.rs)
+    pub signature: Option<Vec<Vec<bool>>>,
+}
+
+impl Line {
+    pub fn plain(text: impl Into<String>) -> Line {
+        Line {
+            text: text.into(),
+            bold: false,
+            qr: None,
+            signature: None,
+        }
+    }
+
+    pub fn bold(text: impl Into<String>) -> Line {
+        Line {
+            text: text.into(),
+            bold: true,
+            qr: None,
+            signature: None,
+        }
+    }
+
+    pub fn with_qr(mut self, modules: Vec<Vec<bool>>) -> Line {
+        self.qr = Some(modules);
+        self
+    }
+
+    pub fn with_signature(mut self, dots: Vec<Vec<bool>>) -> Line {
+        self.signature = Some(dots);
+        self
+    }
+}
+
+// Where a generated PDF went: written to `path`, or returned inline
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfOutput {
+    pub path: Option<String>,
+    pub base64: Option<String>,
+}
+
+// PDF string literal; characters outside Latin-1 become '?'
+fn escape(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() + 2);
+    out.push(b'(');
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push(b'\\');
+                out.push(c as u8);
+            }
+            c if (c as u32) < 0x20 => out.push(b' '),
+            c if (c as u32) <= 0xFF => out.push(c as u32 as u8),
+            _ => out.push(b'?'),
+        }
+    }
+    out.push(b')');
+    out
+}
+
+// Dark modules as filled squares, the code's top edge at `top`
+fn qr_content(content: &mut Vec<u8>, modules: &[Vec<bool>], top: f64) {
+    let width = modules.first().map_or(0, |row| row.len());
+    let left = PAGE_WIDTH - MARGIN - width as f64 * QR_MODULE;
+    for (row, cells) in modules.iter().enumerate() {
+        let y = top - (row + 1) as f64 * QR_MODULE;
+        for (column, dark) in cells.iter().enumerate() {
+            if *dark {
+                let x = left + column as f64 * QR_MODULE;
+                content
+                    .extend(format!("{:.2} {:.2} {} {} re ", x, y, QR_MODULE, QR_MODULE).bytes());
+            }
+        }
+    }
+    content.extend(b"f\n");
+}
+
+// Dark dots as one rectangle per horizontal run, the top edge at `top`
+fn signature_content(content: &mut Vec<u8>, dots: &[Vec<bool>], top: f64) {
+    for (row, cells) in dots.iter().enumerate() {
+        let y = top - (row + 1) as f64 * SIGNATURE_DOT;
+        let mut column = 0;
+        while column < cells.len() {
+            if !cells[column] {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < cells.len() && cells[column] {
+                column += 1;
+            }
+            let x = MARGIN + start as f64 * SIGNATURE_DOT;
+            let width = (column - start) as f64 * SIGNATURE_DOT;
+            content.extend(format!("{:.2} {:.2} {:.2} {} re ", x, y, width, SIGNATURE_DOT).bytes());
+        }
+    }
+    content.extend(b"f\n");
+}
+
+fn page_content(lines: &[Line], number: usize, total: usize) -> Vec<u8> {
+    let mut content = Vec::new();
+    let mut y = PAGE_HEIGHT - MARGIN - FONT_SIZE;
+    for line in lines {
+        let font = if line.bold { "F2" } else { "F1" };
+        content.extend(format!("BT /{} {} Tf {} {:.1} Td ", font, FONT_SIZE, MARGIN, y).bytes());
+        content.extend(escape(&line.text));
+        content.extend(b" Tj ET\n");
+        if let Some(modules) = &line.qr {
+            qr_content(&mut content, modules, y + FONT_SIZE);
+        }
+        if let Some(dots) = &line.signature {
+            signature_content(&mut content, dots, y - (LEADING - FONT_SIZE));
+        }
+        y -= LEADING;
+    }
+    let footer = format!("Page {} of {}", number, total);
+    // Courier glyphs are 0.6 em wide
+    let x = PAGE_WIDTH - MARGIN - footer.len() as f64 * FONT_SIZE * 0.6;
+    content.extend(format!("BT /F1 {} Tf {:.1} {} Td ", FONT_SIZE, x, MARGIN / 2.0).bytes());
+    content.extend(escape(&footer));
+    content.extend(b" Tj ET\n");
+    content
+}
+
+// Lay the lines out on as many A4 pages as they need
+pub fn render(lines: &[Line]) -> Vec<u8> {
+    let pages: Vec<&[Line]> = if lines.is_empty() {
+        vec![&[]]
+    } else {
+        lines.chunks(LINES_PER_PAGE).collect()
+    };
+
+    // Objects: 1 catalog, 2 page tree, 3-4 fonts, then a page and its
+    // content stream per page
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", 5 + i * 2))
+        .collect();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    objects.push(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+    );
+    for font in ["Courier", "Courier-Bold"] {
+        objects.push(
+            format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                font
+            )
+            .into_bytes(),
+        );
+    }
+    for (i, page) in pages.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                6 + i * 2
+            )
+            .into_bytes(),
+        );
+        let content = page_content(page, i + 1, pages.len());
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"endstream");
+        objects.push(stream);
+    }
+
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend(format!("{} 0 obj\n", i + 1).bytes());
+        out.extend(object);
+        out.extend(b"\nendobj\n");
+    }
+    let xref = out.len();
+    out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+    for offset in offsets {
+        out.extend(format!("{:010} 00000 n \n", offset).bytes());
+    }
+    out.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .bytes(),
+    );
+    out
+}
+
+// Write the PDF to `dest_path`, or return it base64 encoded for preview
+pub fn deliver(bytes: Vec<u8>, dest_path: Option<String>) -> Result<PdfOutput, String> {
+    match dest_path {
+        Some(path) => {
+            fs::write(&path, bytes).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            Ok(PdfOutput {
+                path: Some(path),
+                base64: None,
+            })
+        }
+        None => Ok(PdfOutput {
+            path: None,
+            base64: Some(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(pdf: &[u8]) -> String {
+        String::from_utf8_lossy(pdf).into_owned()
+    }
+
+    #[test]
+    fn strings_are_escaped_for_latin1() {
+        assert_eq!(escape(r"a(b)\c"), br"(a\(b\)\\c)".to_vec());
+        assert_eq!(escape("₹ 50\tx"), b"(? 50 x)".to_vec());
+        assert_eq!(escape("é"), vec![b'(', 0xE9, b')']);
+    }
+
+    #[test]
+    fn the_xref_table_points_at_every_object() {
+        let pdf = render(&[Line::plain("Gross 1200"), Line::bold("Net 800")]);
+        let body = text(&pdf);
+        assert!(body.starts_with("%PDF-1.4\n"));
+        assert!(body.ends_with("%%EOF\n"));
+        assert!(body.contains("/Count 1 >>"));
+        assert!(body.contains("BT /F2 9 Tf 40 "));
+        assert!(body.contains("(Page 1 of 1) Tj"));
+
+        let startxref = body.rfind("startxref\n").unwrap() + "startxref\n".len();
+        let xref: usize = body[startxref..].lines().next().unwrap().parse().unwrap();
+        assert!(body[xref..].starts_with("xref\n0 7\n"));
+        let offsets = body[xref..].lines().skip(3).take(6);
+        for (i, entry) in offsets.enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(body[offset..].starts_with(&format!("{} 0 obj\n", i + 1)));
+        }
+    }
+
+    #[test]
+    fn long_documents_run_onto_numbered_pages() {
+        let lines: Vec<Line> = (0..LINES_PER_PAGE * 2 + 1)
+            .map(|i| Line::plain(format!("line {}", i)))
+            .collect();
+        let body = text(&render(&lines));
+        assert!(body.contains("/Kids [5 0 R 7 0 R 9 0 R] /Count 3"));
+        assert!(body.contains("(Page 3 of 3) Tj"));
+        assert_eq!(text(&render(&[])).matches("/Type /Page ").count(), 1);
+    }
+
+    #[test]
+    fn qr_and_signature_marks_are_filled_rectangles() {
+        let line = Line::plain("Scan")
+            .with_qr(vec![vec![true, false], vec![false, true]])
+            .with_signature(vec![vec![true, true, false, true]]);
+        let content = text(&page_content(&[line], 1, 1));
+        assert_eq!(
+            content
+                .matches(&format!("{} {} re", QR_MODULE, QR_MODULE))
+                .count(),
+            2
+        );
+        // One run of two dots and one single dot
+        assert!(content.contains("40.00 "));
+        assert!(content.contains(" 1.50 0.75 re "));
+        assert!(content.contains(" 0.75 0.75 re "));
+        assert_eq!(content.matches("f\n").count(), 2);
+    }
+
+    #[test]
+    fn inline_delivery_is_base64() {
+        let output = deliver(b"%PDF".to_vec(), None).unwrap();
+        assert_eq!(output.path, None);
+        assert_eq!(output.base64.as_deref(), Some("JVBERg=="));
+    }
+}
@@ -0,0 +1,318 @@
+// Period locking for Truckore Pro
+// Weighments dated on or before the lock date cannot be inserted, edited or deleted.
+// Enforcement lives in SQLite triggers (see schema.sql) so every write path is covered.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::security;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+pub const LOCK_CONFIG_KEY: &str = "period_lock_date";
+// How the lock triggers' refusals start
+pub const LOCKED_ERROR: &str = "PERIOD_LOCKED:";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeriodLockChange {
+    pub user_id: Option<String>,
+    pub action: String,
+    pub details: Option<String>,
+    pub timestamp: String,
+}
+
+// Current lock date, if any
+pub fn current_lock(conn: &Connection) -> Result<Option<String>, String> {
+    db::get_config(conn, LOCK_CONFIG_KEY)
+}
+
+// Whether a weighment is dated on or before the lock date
+pub fn is_weighment_locked(conn: &Connection, weighment_id: &str) -> Result<bool, String> {
+    let Some(lock) = current_lock(conn)? else {
+        return Ok(false);
+    };
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*) > 0 FROM weighments WHERE id = ?1 AND {} <= ?2",
+            db::local_date("created_at")
+        ),
+        params![weighment_id, lock],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+pub fn validate_date(conn: &Connection, date: &str) -> Result<(), String> {
+    let normalized: Option<String> = conn
+        .query_row("SELECT date(?1)", [date], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if normalized.as_deref() != Some(date) {
+        return Err(format!("Invalid date (expected YYYY-MM-DD): {}", date));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_period_lock(app: AppHandle) -> Result<Option<String>, String> {
+    let conn = db::open(&app)?;
+    current_lock(&conn)
+}
+
+// Lock all weighments dated on or before `up_to_date`, at most today.
+// Moving the lock backwards is an unlock and requires a supervisor.
+#[tauri::command]
+pub fn lock_period(app: AppHandle, up_to_date: String, user_id: String) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "lock_period",
+        &user_id,
+        serde_json::json!({ "up_to_date": up_to_date }),
+        || {
+            let conn = db::open(&app)?;
+            validate_date(&conn, &up_to_date)?;
+            roles::user_role(&conn, &user_id)?;
+            lock_up_to(&conn, &up_to_date, &user_id)
+        },
+    )
+}
+
+// Move the lock forward to `up_to_date` and record who did it. A lock past
+// today would refuse the coming days' tickets until a supervisor undid it.
+pub fn lock_up_to(conn: &Connection, up_to_date: &str, user_id: &str) -> Result<(), String> {
+    let today: String = conn
+        .query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if up_to_date > today.as_str() {
+        return Err(format!(
+            "Cannot lock {}: periods can be locked up to today ({}) at most",
+            up_to_date, today
+        ));
+    }
+    let previous = current_lock(conn)?;
+    if let Some(prev) = &previous {
+        if up_to_date < prev.as_str() {
+            return Err(format!(
+                "Period is already locked up to {}; use unlock_period to move the lock back",
+                prev
+            ));
+        }
+    }
+
+    db::set_config(conn, LOCK_CONFIG_KEY, up_to_date)?;
+    let details = serde_json::json!({ "previous": previous, "locked_up_to": up_to_date });
+    security::log_event(conn, Some(user_id), "PERIOD_LOCKED", &details.to_string())
+}
+
+// Move the lock back to `to_date`, or remove it entirely. The lock only
+// ever moves back here; lock_period moves it forward.
+pub fn unlock_to(
+    conn: &Connection,
+    to_date: Option<&str>,
+    user_id: &str,
+    reason: &str,
+) -> Result<(), String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is required to unlock a period".to_string());
+    }
+    let previous = current_lock(conn)?;
+    match to_date {
+        Some(date) => {
+            validate_date(conn, date)?;
+            let today: String = conn
+                .query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            if date > today.as_str() {
+                return Err(format!(
+                    "Cannot unlock to {}: it is later than today ({})",
+                    date, today
+                ));
+            }
+            match &previous {
+                None => return Err("No period is locked; use lock_period to lock one".to_string()),
+                Some(prev) if date > prev.as_str() => {
+                    return Err(format!(
+                        "Cannot unlock to {}: the period is only locked up to {}",
+                        date, prev
+                    ))
+                }
+                Some(_) => {}
+            }
+            db::set_config(conn, LOCK_CONFIG_KEY, date)?;
+        }
+        None => {
+            conn.execute("DELETE FROM app_config WHERE key = ?1", [LOCK_CONFIG_KEY])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let details = serde_json::json!({
+        "previous": previous,
+        "locked_up_to": to_date,
+        "reason": reason,
+    });
+    security::log_event(conn, Some(user_id), "PERIOD_UNLOCKED", &details.to_string())
+}
+
+// Move the lock back to `to_date`, or remove it entirely (supervisor only)
+#[tauri::command]
+pub fn unlock_period(
+    app: AppHandle,
+    to_date: Option<String>,
+    user_id: String,
+    reason: String,
+) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "unlock_period",
+        &user_id,
+        serde_json::json!({ "to_date": to_date, "reason": reason }),
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            unlock_to(&conn, to_date.as_deref(), &user_id, &reason)
+        },
+    )
+}
+
+// Audit trail of lock and unlock actions, newest first
+#[tauri::command]
+pub fn period_lock_history(app: AppHandle) -> Result<Vec<PeriodLockChange>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT user_id, action, details, timestamp FROM security_logs
+             WHERE action IN ('PERIOD_LOCKED', 'PERIOD_UNLOCKED')
+             ORDER BY timestamp DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![], |row| {
+            Ok(PeriodLockChange {
+                user_id: row.get(0)?,
+                action: row.get(1)?,
+                details: row.get(2)?,
+                timestamp: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{migrations, signatures};
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        signatures::register(&conn).unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        migrations::run(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO users (id, username, password_hash, role)
+             VALUES ('u1', 'admin', 'x', 'admin')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn unlocking_only_moves_the_lock_back() {
+        let conn = conn();
+        assert!(unlock_to(&conn, Some("2026-01-15"), "u1", "Audit").is_err());
+
+        lock_up_to(&conn, "2026-01-31", "u1").unwrap();
+        for later in ["2026-02-01", "2999-01-01"] {
+            assert!(
+                unlock_to(&conn, Some(later), "u1", "Audit").is_err(),
+                "{}",
+                later
+            );
+        }
+        assert!(unlock_to(&conn, Some("2026-01-15"), "u1", " ").is_err());
+        assert_eq!(current_lock(&conn).unwrap().as_deref(), Some("2026-01-31"));
+
+        unlock_to(&conn, Some("2026-01-15"), "u1", "Audit").unwrap();
+        assert_eq!(current_lock(&conn).unwrap().as_deref(), Some("2026-01-15"));
+        unlock_to(&conn, None, "u1", "Year end").unwrap();
+        assert_eq!(current_lock(&conn).unwrap(), None);
+    }
+
+    // A closed ticket weighed at midday on `date`
+    fn weigh(conn: &Connection, id: &str, date: &str) -> rusqlite::Result<usize> {
+        conn.execute(
+            "INSERT INTO weighments (id, bill_no, ticket_no, vehicle_no, party_name,
+                                     product_name, net_weight, status, created_at)
+             VALUES (?1, ?1, ?1, 'KA01AB1234', 'Acme', 'Sand', 1000, 'CLOSED', ?2)",
+            params![id, format!("{} 12:00:00", date)],
+        )
+    }
+
+    fn refused(result: rusqlite::Result<usize>) -> bool {
+        result.is_err_and(|e| e.to_string().contains(LOCKED_ERROR))
+    }
+
+    #[test]
+    fn locked_tickets_cannot_be_written_without_an_override() {
+        let conn = conn();
+        weigh(&conn, "old", "2026-01-10").unwrap();
+        weigh(&conn, "new", "2026-02-10").unwrap();
+        lock_up_to(&conn, "2026-01-31", "u1").unwrap();
+        assert!(is_weighment_locked(&conn, "old").unwrap());
+        assert!(!is_weighment_locked(&conn, "new").unwrap());
+
+        let edit = "UPDATE weighments SET remarks = 'edited' WHERE id = ?1";
+        assert!(refused(weigh(&conn, "late", "2026-01-31")));
+        assert!(refused(conn.execute(edit, ["old"])));
+        assert!(refused(
+            conn.execute("DELETE FROM weighments WHERE id = 'old'", [])
+        ));
+        // Nor can an open ticket be backdated into the locked period
+        assert!(refused(conn.execute(
+            "UPDATE weighments SET created_at = '2026-01-05 12:00:00' WHERE id = 'new'",
+            [],
+        )));
+        assert!(refused(conn.execute(
+            "INSERT INTO ticket_voids (weighment_id, reason, voided_by)
+             VALUES ('old', 'Duplicate', 'u1')",
+            [],
+        )));
+        conn.execute(edit, ["new"]).unwrap();
+
+        // A refused or spent override does not open the lock
+        let grant = "INSERT INTO supervisor_overrides (action, entity, entity_id, requested_by,
+                                                      method, reason, granted, used_at)
+                     VALUES ('LOCKED_PERIOD', 'weighment', 'old', 'u1', 'PIN', 'Typo', ?1, ?2)";
+        conn.execute(grant, params![0, None::<String>]).unwrap();
+        conn.execute(grant, params![1, "2026-02-01 09:00:00"])
+            .unwrap();
+        assert!(refused(conn.execute(edit, ["old"])));
+        conn.execute(grant, params![1, None::<String>]).unwrap();
+        assert_eq!(conn.execute(edit, ["old"]).unwrap(), 1);
+    }
+
+    #[test]
+    fn practice_and_archived_tickets_may_leave_a_locked_period() {
+        let conn = conn();
+        for id in ["practice", "archived", "kept"] {
+            weigh(&conn, id, "2026-01-10").unwrap();
+        }
+        lock_up_to(&conn, "2026-01-31", "u1").unwrap();
+        conn.execute_batch(
+            "INSERT INTO practice_tickets (weighment_id) VALUES ('practice');
+             INSERT INTO archive_moves (weighment_id) VALUES ('archived');",
+        )
+        .unwrap();
+
+        let delete = "DELETE FROM weighments WHERE id = ?1";
+        assert_eq!(conn.execute(delete, ["practice"]).unwrap(), 1);
+        assert_eq!(conn.execute(delete, ["archived"]).unwrap(), 1);
+        assert!(refused(conn.execute(delete, ["kept"])));
+        // A ticket coming back from the archive is let in too
+        weigh(&conn, "archived", "2026-01-10").unwrap();
+    }
+}
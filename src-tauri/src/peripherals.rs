@@ -0,0 +1,391 @@
+// Barrier and traffic light control for Truckore Pro
+// Entry barriers and red/green lights hang off a USB relay board, which
+// shows up as a serial port. set_relay switches one channel; sequences
+// switch several in turn, with waits between the steps, when the site's
+// scale settles (`weight_stable`, above an optional minimum weight), when a
+// ticket has printed (`ticket_printed`), or on demand (`manual`). So
+// "green on stable weight" is a weight_stable sequence turning the red
+// channel off and the green one on, and "open barrier after print" is a
+// ticket_printed sequence pulsing the barrier channel.
+// Boards are driven with one of the common serial protocols:
+//   lcus      A0 <channel> <state> <checksum>   (LCUS / CH340 boards)
+//   kmtronic  FF <channel> <state>
+//   numato    "relay on|off <index>\r"          (Numato Lab)
+// Channels are numbered from 1 whatever the board counts from.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+const BOARD_CONFIG_KEY: &str = "relay_board";
+const SEQUENCES_CONFIG_KEY: &str = "relay_sequences";
+const PROTOCOLS: &[&str] = &["lcus", "kmtronic", "numato"];
+const TRIGGERS: &[&str] = &["weight_stable", "ticket_printed", "manual"];
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+// Longest wait a sequence step may ask for
+const MAX_STEP_DELAY_MS: u64 = 60_000;
+
+fn default_baud_rate() -> u32 {
+    9600
+}
+
+fn default_channels() -> u8 {
+    4
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayBoard {
+    #[serde(default)]
+    pub enabled: bool,
+    pub port: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    // lcus, kmtronic or numato
+    pub protocol: String,
+    #[serde(default = "default_channels")]
+    pub channels: u8,
+    // What each channel drives, e.g. "barrier" or "green", for the UI
+    #[serde(default)]
+    pub labels: BTreeMap<u8, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayStep {
+    // Waited before switching
+    #[serde(default)]
+    pub delay_ms: u64,
+    pub channel: u8,
+    pub on: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelaySequence {
+    pub name: String,
+    // weight_stable, ticket_printed or manual
+    pub trigger: String,
+    // weight_stable only: lighter stable weights (an empty deck) are ignored
+    pub min_weight_kg: Option<f64>,
+    pub steps: Vec<RelayStep>,
+}
+
+// The open board and the state each channel was last switched to
+#[derive(Default)]
+pub struct Peripherals {
+    port: Mutex<Option<(String, Box<dyn serialport::SerialPort>)>>,
+    states: Mutex<BTreeMap<u8, bool>>,
+    // Sequences running now; a trigger does not start one twice
+    running: Mutex<HashSet<String>>,
+}
+
+fn load_board(conn: &rusqlite::Connection) -> Result<Option<RelayBoard>, String> {
+    db::get_config(conn, BOARD_CONFIG_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+fn load_sequences(conn: &rusqlite::Connection) -> Result<Vec<RelaySequence>, String> {
+    match db::get_config(conn, SEQUENCES_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Bytes that switch `channel` on the board
+fn command(protocol: &str, channel: u8, on: bool) -> Result<Vec<u8>, String> {
+    let state = u8::from(on);
+    match protocol {
+        "lcus" => Ok(vec![
+            0xA0,
+            channel,
+            state,
+            0xA0u8.wrapping_add(channel).wrapping_add(state),
+        ]),
+        "kmtronic" => Ok(vec![0xFF, channel, state]),
+        "numato" => {
+            // Numato counts from 0, in base 32 past 9
+            let index = char::from_digit(u32::from(channel) - 1, 32)
+                .ok_or_else(|| format!("Channel {} is out of range", channel))?
+                .to_ascii_uppercase();
+            Ok(format!("relay {} {}\r", if on { "on" } else { "off" }, index).into_bytes())
+        }
+        other => Err(format!("Unknown relay protocol: {}", other)),
+    }
+}
+
+fn validate_board(board: &RelayBoard) -> Result<(), String> {
+    if board.port.trim().is_empty() {
+        return Err("Relay board port is required".to_string());
+    }
+    if !PROTOCOLS.contains(&board.protocol.as_str()) {
+        return Err(format!("Unknown relay protocol: {}", board.protocol));
+    }
+    if board.channels == 0 || (board.protocol == "numato" && board.channels > 32) {
+        return Err(format!(
+            "A {} board cannot have {} channels",
+            board.protocol, board.channels
+        ));
+    }
+    if let Some(channel) = board
+        .labels
+        .keys()
+        .find(|c| **c == 0 || **c > board.channels)
+    {
+        return Err(format!("Channel {} is not on the board", channel));
+    }
+    Ok(())
+}
+
+fn check_channel(board: &RelayBoard, channel: u8) -> Result<(), String> {
+    if channel == 0 || channel > board.channels {
+        return Err(format!(
+            "Channel {} is not on the board (1 to {})",
+            channel, board.channels
+        ));
+    }
+    Ok(())
+}
+
+// Switch one channel, opening the board's port when it is not open yet.
+// A failed write closes the port so the next switch opens it again.
+fn switch(app: &AppHandle, board: &RelayBoard, channel: u8, on: bool) -> Result<(), String> {
+    check_channel(board, channel)?;
+    let bytes = command(&board.protocol, channel, on)?;
+    let peripherals = app.state::<Peripherals>();
+    let mut port = peripherals.port.lock().map_err(|e| e.to_string())?;
+    if port.as_ref().map(|(name, _)| name.as_str()) != Some(board.port.as_str()) {
+        *port = None;
+        let opened = serialport::new(&board.port, board.baud_rate)
+            .timeout(WRITE_TIMEOUT)
+            .open()
+            .map_err(|e| format!("Failed to open {}: {}", board.port, e))?;
+        *port = Some((board.port.clone(), opened));
+    }
+    let written = match port.as_mut() {
+        Some((_, open)) => open.write_all(&bytes).and_then(|_| open.flush()),
+        None => return Err("Relay board port unavailable".to_string()),
+    };
+    if let Err(e) = written {
+        *port = None;
+        return Err(format!("Failed to switch relay {}: {}", channel, e));
+    }
+    drop(port);
+    peripherals
+        .states
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(channel, on);
+    Ok(())
+}
+
+fn run_sequence(
+    app: &AppHandle,
+    board: &RelayBoard,
+    sequence: &RelaySequence,
+) -> Result<(), String> {
+    {
+        let peripherals = app.state::<Peripherals>();
+        let mut running = peripherals.running.lock().map_err(|e| e.to_string())?;
+        if !running.insert(sequence.name.clone()) {
+            return Err(format!("Sequence {} is already running", sequence.name));
+        }
+    }
+    let mut result = Ok(());
+    for step in &sequence.steps {
+        if shutdown::requested() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(step.delay_ms));
+        result = switch(app, board, step.channel, step.on);
+        if result.is_err() {
+            break;
+        }
+    }
+    if let Ok(mut running) = app.state::<Peripherals>().running.lock() {
+        running.remove(&sequence.name);
+    }
+    result
+}
+
+// Run the sequences for a trigger in the background
+fn fire(app: &AppHandle, trigger: &'static str, weight_kg: Option<f64>) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let Ok(conn) = db::open(&app) else {
+            return;
+        };
+        let board = match load_board(&conn) {
+            Ok(Some(board)) if board.enabled => board,
+            _ => return,
+        };
+        let sequences = load_sequences(&conn).unwrap_or_default();
+        drop(conn);
+        for sequence in sequences.iter().filter(|s| s.trigger == trigger) {
+            let light = matches!(
+                (sequence.min_weight_kg, weight_kg),
+                (Some(min), Some(weight)) if weight < min
+            );
+            if light {
+                continue;
+            }
+            if let Err(e) = run_sequence(&app, &board, sequence) {
+                tracing::warn!(sequence = %sequence.name, error = %e, "Relay sequence failed");
+            }
+        }
+    });
+}
+
+// Called by the scale listener when the weight settles
+pub fn weight_stable(app: &AppHandle, weight_kg: f64) {
+    fire(app, "weight_stable", Some(weight_kg));
+}
+
+// Called once a ticket has been sent to the printer
+pub fn ticket_printed(app: &AppHandle) {
+    fire(app, "ticket_printed", None);
+}
+
+fn enabled_board(app: &AppHandle) -> Result<RelayBoard, String> {
+    let conn = db::open(app)?;
+    match load_board(&conn)? {
+        Some(board) if board.enabled => Ok(board),
+        _ => Err("No relay board is enabled".to_string()),
+    }
+}
+
+#[tauri::command]
+pub fn get_relay_board(app: AppHandle) -> Result<Option<RelayBoard>, String> {
+    let conn = db::open(&app)?;
+    load_board(&conn)
+}
+
+// Configure the relay board (admins); the port is reopened on the next switch
+#[tauri::command]
+pub fn set_relay_board(
+    app: AppHandle,
+    peripherals: State<'_, Peripherals>,
+    board: RelayBoard,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&board).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_relay_board", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        validate_board(&board)?;
+        let json = serde_json::to_string(&board).map_err(|e| e.to_string())?;
+        db::set_config(&conn, BOARD_CONFIG_KEY, &json)?;
+        *peripherals.port.lock().map_err(|e| e.to_string())? = None;
+        peripherals
+            .states
+            .lock()
+            .map_err(|e| e.to_string())?
+            .clear();
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn list_relay_sequences(app: AppHandle) -> Result<Vec<RelaySequence>, String> {
+    let conn = db::open(&app)?;
+    load_sequences(&conn)
+}
+
+// Replace the relay sequences (admins)
+#[tauri::command]
+pub fn set_relay_sequences(
+    app: AppHandle,
+    sequences: Vec<RelaySequence>,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&sequences).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_relay_sequences", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let board = load_board(&conn)?;
+        let mut names = HashSet::new();
+        for sequence in &sequences {
+            if sequence.name.trim().is_empty() || !names.insert(sequence.name.as_str()) {
+                return Err(format!(
+                    "Sequence names must be unique and not blank: {:?}",
+                    sequence.name
+                ));
+            }
+            if !TRIGGERS.contains(&sequence.trigger.as_str()) {
+                return Err(format!("Unknown sequence trigger: {}", sequence.trigger));
+            }
+            for step in &sequence.steps {
+                if step.delay_ms > MAX_STEP_DELAY_MS {
+                    return Err(format!(
+                        "{}: steps may wait at most {} ms",
+                        sequence.name, MAX_STEP_DELAY_MS
+                    ));
+                }
+                if let Some(board) = board.as_ref() {
+                    check_channel(board, step.channel)?;
+                }
+            }
+        }
+        let json = serde_json::to_string(&sequences).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SEQUENCES_CONFIG_KEY, &json)
+    })
+}
+
+// Switch one relay channel on or off
+#[tauri::command]
+pub async fn set_relay(
+    app: AppHandle,
+    channel: u8,
+    state: bool,
+    user_id: String,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let args = serde_json::json!({ "channel": channel, "state": state });
+        command_audit::audited(&app, "set_relay", &user_id, args, || {
+            let board = enabled_board(&app)?;
+            switch(&app, &board, channel, state)
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Run a sequence now, whatever its trigger; returns once it has finished
+#[tauri::command]
+pub async fn run_relay_sequence(
+    app: AppHandle,
+    name: String,
+    user_id: String,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let args = serde_json::json!({ "name": name });
+        command_audit::audited(&app, "run_relay_sequence", &user_id, args, || {
+            let board = enabled_board(&app)?;
+            let conn = db::open(&app)?;
+            let sequence = load_sequences(&conn)?
+                .into_iter()
+                .find(|s| s.name == name)
+                .ok_or_else(|| format!("Unknown relay sequence {}", name))?;
+            drop(conn);
+            run_sequence(&app, &board, &sequence)
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Each channel's state as last switched by the app since start; boards do
+// not report their own
+#[tauri::command]
+pub fn get_relay_states(peripherals: State<'_, Peripherals>) -> Result<BTreeMap<u8, bool>, String> {
+    Ok(peripherals
+        .states
+        .lock()
+        .map_err(|e| e.to_string())?
+        .clone())
+}
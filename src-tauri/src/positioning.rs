@@ -0,0 +1,155 @@
+// Partial-positioning check for Truckore Pro
+// A truck with an axle off the deck, or one that rolled part way off after
+// driving on, still settles to a steady weight, and a light gross or tare
+// has been a recurring source of disputed and fraudulent tickets. Before a
+// captured weight is accepted it is checked against the vehicle's known tare
+// and against the loading curve: the weight must not settle well below a
+// level the deck already held since it was last empty. A failed check
+// refuses the capture with a PARTIAL_POSITIONING error. Indicators here
+// report only the total weight, so per-load-cell balance is not checked.
+
+use crate::db;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+pub const PARTIAL_POSITIONING: &str = "PARTIAL_POSITIONING";
+
+// How far back the loading curve is read from the weight history
+pub const CURVE_SECONDS: u64 = 120;
+
+const SETTINGS_KEY: &str = "positioning_check";
+// Consecutive readings within PLATEAU_BAND_KG that count as a held level
+const PLATEAU_READINGS: usize = 5;
+const PLATEAU_BAND_KG: f64 = 20.0;
+// Recent tare weighings a vehicle's known tare is taken from
+const TARE_HISTORY: i64 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PositioningSettings {
+    pub enabled: bool,
+    // A weight under this share of the vehicle's known tare is refused
+    pub min_tare_fraction: f64,
+    // A weight this share below the highest level held is refused
+    pub max_drop_fraction: f64,
+    // Readings under this weight mean the deck is empty
+    pub empty_deck_kg: f64,
+}
+
+impl Default for PositioningSettings {
+    fn default() -> Self {
+        PositioningSettings {
+            enabled: true,
+            min_tare_fraction: 0.9,
+            max_drop_fraction: 0.05,
+            empty_deck_kg: 200.0,
+        }
+    }
+}
+
+pub fn load_settings(conn: &Connection) -> Result<PositioningSettings, String> {
+    match db::get_config(conn, SETTINGS_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(PositioningSettings::default()),
+    }
+}
+
+// The vehicle's valid stored tare, otherwise the lightest of its recent tares
+fn known_tare(conn: &Connection, vehicle_no: &str) -> Result<Option<f64>, String> {
+    let stored: Option<f64> = conn
+        .query_row(
+            "SELECT tare_weight FROM stored_tares
+             WHERE vehicle_no = ?1 AND expires_at > CURRENT_TIMESTAMP",
+            [vehicle_no],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if stored.is_some() {
+        return Ok(stored);
+    }
+    conn.query_row(
+        "SELECT MIN(tare_weight) FROM (
+             SELECT tare_weight FROM weighments
+             WHERE vehicle_no = ?1 AND tare_weight > 0
+             ORDER BY created_at DESC LIMIT ?2
+         )",
+        rusqlite::params![vehicle_no, TARE_HISTORY],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Highest level the deck held since it was last empty
+fn highest_plateau(curve: &[f64], empty_deck_kg: f64) -> Option<f64> {
+    let start = curve
+        .iter()
+        .rposition(|w| *w < empty_deck_kg)
+        .map_or(0, |i| i + 1);
+    curve[start..]
+        .windows(PLATEAU_READINGS)
+        .filter_map(|window| {
+            let low = window.iter().copied().fold(f64::INFINITY, f64::min);
+            let high = window.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            (high - low <= PLATEAU_BAND_KG).then_some(low)
+        })
+        .reduce(f64::max)
+}
+
+// Refuse a captured weight that looks like a vehicle not fully on the deck.
+// `curve` is the readings leading up to the capture, oldest first.
+pub fn check(
+    conn: &Connection,
+    vehicle_no: Option<&str>,
+    weight_kg: f64,
+    curve: &[f64],
+) -> Result<(), String> {
+    let settings = load_settings(conn)?;
+    if !settings.enabled {
+        return Ok(());
+    }
+    if let Some(vehicle_no) = vehicle_no {
+        if let Some(tare) = known_tare(conn, vehicle_no)? {
+            if weight_kg < tare * settings.min_tare_fraction {
+                return Err(format!(
+                    "{}: {} weighs {:.0} kg, under its known tare of {:.0} kg; \
+                     check that every axle is on the platform",
+                    PARTIAL_POSITIONING, vehicle_no, weight_kg, tare
+                ));
+            }
+        }
+    }
+    if let Some(peak) = highest_plateau(curve, settings.empty_deck_kg) {
+        if weight_kg < peak * (1.0 - settings.max_drop_fraction) {
+            return Err(format!(
+                "{}: the weight settled at {:.0} kg after the deck held {:.0} kg; \
+                 the vehicle may have rolled partly off the platform",
+                PARTIAL_POSITIONING, weight_kg, peak
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_positioning_settings(app: AppHandle) -> Result<PositioningSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+#[tauri::command]
+pub fn set_positioning_settings(
+    app: AppHandle,
+    settings: PositioningSettings,
+) -> Result<(), String> {
+    let fractions = [settings.min_tare_fraction, settings.max_drop_fraction];
+    if fractions.iter().any(|f| !(0.0..=1.0).contains(f)) || settings.empty_deck_kg < 0.0 {
+        return Err(
+            "Fractions must be between 0 and 1 and the empty-deck weight non-negative".to_string(),
+        );
+    }
+    let conn = db::open(&app)?;
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    db::set_config(&conn, SETTINGS_KEY, &json)
+}
@@ -0,0 +1,195 @@
+// Preflight check for Truckore Pro
+// Run before the first weighment of the day: every configured integration is
+// tried for real (indicator port, ticket printer, lane cameras, backup
+// folder) so a dead printer or a moved COM port is found before the first
+// truck is on the bridge, not while it waits.
+
+use crate::backup_schedule;
+use crate::cameras;
+use crate::db;
+use crate::scale::{self, ScaleSource, Transport};
+use crate::thermal_printer::{self, PrinterTransport};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreflightItem {
+    // "scale", "printer", "camera", "backup" or "email"
+    pub item: String,
+    // Port, address or path that was checked
+    pub target: Option<String>,
+    // PASS, FAIL or SKIPPED (not configured)
+    pub status: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreflightReport {
+    // No item failed
+    pub passed: bool,
+    pub items: Vec<PreflightItem>,
+}
+
+fn item(name: &str, target: Option<String>, outcome: Result<String, String>) -> PreflightItem {
+    let (status, detail) = match outcome {
+        Ok(detail) => ("PASS", detail),
+        Err(detail) => ("FAIL", detail),
+    };
+    PreflightItem {
+        item: name.to_string(),
+        target,
+        status: status.to_string(),
+        detail,
+    }
+}
+
+fn skipped(name: &str, detail: &str) -> PreflightItem {
+    PreflightItem {
+        item: name.to_string(),
+        target: None,
+        status: "SKIPPED".to_string(),
+        detail: detail.to_string(),
+    }
+}
+
+fn serial_port_present(port: &str) -> Result<String, String> {
+    let ports = serialport::available_ports().map_err(|e| e.to_string())?;
+    match ports.iter().any(|p| p.port_name == port) {
+        true => Ok("Port present".to_string()),
+        false => Err(format!("{} is not connected to this computer", port)),
+    }
+}
+
+fn tcp_reachable(endpoint: &str) -> Result<String, String> {
+    let address = endpoint
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", endpoint, e))?
+        .next()
+        .ok_or_else(|| format!("Failed to resolve {}", endpoint))?;
+    TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .map(|_| "Accepting connections".to_string())
+        .map_err(|e| format!("Failed to connect to {}: {}", endpoint, e))
+}
+
+fn check_scale(conn: &Connection) -> PreflightItem {
+    if db::get_config(conn, "scale_config")
+        .ok()
+        .flatten()
+        .is_none()
+    {
+        return skipped("scale", "No indicator configured");
+    }
+    let config = match scale::load_config(conn) {
+        Ok(config) => config,
+        Err(e) => return item("scale", None, Err(e)),
+    };
+    // Passing with simulated weights would let a site weigh trucks on them
+    if config.source == ScaleSource::Simulator {
+        let outcome =
+            Err("The scale source is the simulator: readings are not real weights".to_string());
+        return item("scale", Some(config.endpoint()), outcome);
+    }
+    let outcome = match config.transport {
+        Transport::Serial => serial_port_present(&config.port),
+        Transport::Tcp => tcp_reachable(&config.endpoint()),
+    };
+    item("scale", Some(config.endpoint()), outcome)
+}
+
+fn check_printer(conn: &Connection) -> PreflightItem {
+    if db::get_config(conn, "thermal_printer")
+        .ok()
+        .flatten()
+        .is_none()
+    {
+        return skipped("printer", "No ticket printer configured");
+    }
+    let settings = match thermal_printer::load_settings(conn) {
+        Ok(settings) => settings,
+        Err(e) => return item("printer", None, Err(e)),
+    };
+    match settings.transport {
+        PrinterTransport::Serial => item(
+            "printer",
+            Some(settings.port.clone()),
+            serial_port_present(&settings.port),
+        ),
+        PrinterTransport::Tcp => {
+            let endpoint = format!("{}:{}", settings.host, settings.tcp_port);
+            item("printer", Some(endpoint.clone()), tcp_reachable(&endpoint))
+        }
+        // Opened for writing without sending anything
+        PrinterTransport::Usb => item(
+            "printer",
+            Some(settings.device.clone()),
+            OpenOptions::new()
+                .write(true)
+                .open(&settings.device)
+                .map(|_| "Device ready".to_string())
+                .map_err(|e| format!("Failed to open {}: {}", settings.device, e)),
+        ),
+    }
+}
+
+fn check_cameras(conn: &Connection) -> Result<Vec<PreflightItem>, String> {
+    let mut items = Vec::new();
+    for lane in cameras::camera_lanes(conn)? {
+        for camera in cameras::load_cameras(conn, &lane)? {
+            let outcome = cameras::fetch(&camera)
+                .map(|(_, body)| format!("Snapshot received ({} bytes)", body.len()));
+            items.push(item(
+                "camera",
+                Some(format!("{} {}", camera.lane_id, camera.role)),
+                outcome,
+            ));
+        }
+    }
+    Ok(items)
+}
+
+fn check_backup(app: &AppHandle, conn: &Connection) -> PreflightItem {
+    let dir = match backup_schedule::load_schedule(conn)
+        .and_then(|schedule| backup_schedule::backup_dir(app, &schedule))
+    {
+        Ok(dir) => dir,
+        Err(e) => return item("backup", None, Err(e)),
+    };
+    let target = Some(dir.to_string_lossy().into_owned());
+    item("backup", target, writable(&dir))
+}
+
+// Create the folder if needed and write and remove a probe file in it
+fn writable(dir: &Path) -> Result<String, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create the folder: {}", e))?;
+    let probe = dir.join(".preflight");
+    std::fs::write(&probe, b"ok").map_err(|e| format!("Folder is not writable: {}", e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok("Writable".to_string())
+}
+
+// Check every configured integration. Items that are not set up are
+// SKIPPED and do not fail the check.
+#[tauri::command]
+pub async fn validate_configuration(app: AppHandle) -> Result<PreflightReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&app)?;
+        let mut items = vec![check_scale(&conn), check_printer(&conn)];
+        items.extend(check_cameras(&conn)?);
+        items.push(check_backup(&app, &conn));
+        // The backend sends no email of its own yet
+        items.push(skipped("email", "No email integration configured"));
+        Ok(PreflightReport {
+            passed: items.iter().all(|i| i.status != "FAIL"),
+            items,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,255 @@
+// Print confirmation for Truckore Pro
+// Sending a slip to a printer is not the same as the driver walking away with
+// it: paper runs out, the cover is open, the roll jams. Printing is now two
+// steps. Each print is a job, recorded when it is rendered and sent; the
+// ticket counts as printed (status PRINTED, printed_at) only once the job is
+// confirmed, by the printer itself where its protocol can report its state
+// (ESC/POS real-time status) or else by the operator. Jobs the printer faulted
+// on, and jobs nobody confirmed, make up the reprint queue.
+
+use crate::db;
+use crate::period_lock;
+use crate::peripherals;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// THERMAL from print_ticket, SLIP for pages the frontend prints itself
+pub const CHANNELS: &[&str] = &["THERMAL", "SLIP"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrintJob {
+    pub id: String,
+    pub weighment_id: Option<String>,
+    pub ticket_no: String,
+    pub channel: String,
+    pub printer: Option<String>,
+    // SENT (awaiting confirmation), CONFIRMED, FAILED or DISMISSED
+    pub status: String,
+    // PRINTER or OPERATOR, once confirmed
+    pub confirmed_by: Option<String>,
+    pub error: Option<String>,
+    pub requested_by: Option<String>,
+    pub operator: Option<String>,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+}
+
+const JOB_SELECT: &str =
+    "SELECT id, weighment_id, ticket_no, channel, printer, status, confirmed_by,
+            error, requested_by, operator, created_at, finished_at
+     FROM print_jobs";
+
+fn row_to_job(row: &rusqlite::Row) -> rusqlite::Result<PrintJob> {
+    Ok(PrintJob {
+        id: row.get(0)?,
+        weighment_id: row.get(1)?,
+        ticket_no: row.get(2)?,
+        channel: row.get(3)?,
+        printer: row.get(4)?,
+        status: row.get(5)?,
+        confirmed_by: row.get(6)?,
+        error: row.get(7)?,
+        requested_by: row.get(8)?,
+        operator: row.get(9)?,
+        created_at: row.get(10)?,
+        finished_at: row.get(11)?,
+    })
+}
+
+pub fn get(conn: &Connection, id: &str) -> Result<PrintJob, String> {
+    conn.query_row(&format!("{} WHERE id = ?1", JOB_SELECT), [id], row_to_job)
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Print job {} not found", id))
+}
+
+// Record a print as sent and awaiting confirmation
+pub fn sent(
+    conn: &Connection,
+    ticket_no: &str,
+    channel: &str,
+    printer: Option<&str>,
+    requested_by: Option<&str>,
+) -> Result<String, String> {
+    if !CHANNELS.contains(&channel) {
+        return Err(format!("Unknown print channel {}", channel));
+    }
+    let weighment_id: Option<String> = conn
+        .query_row(
+            "SELECT id FROM weighments WHERE ticket_no = ?1 ORDER BY created_at DESC LIMIT 1",
+            [ticket_no],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO print_jobs (id, weighment_id, ticket_no, channel, printer, requested_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![id, weighment_id, ticket_no, channel, printer, requested_by],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+// The job came out: the ticket is printed. A ticket dated in a locked period
+// keeps its status; the job still records the print.
+pub fn confirm(
+    app: &AppHandle,
+    conn: &Connection,
+    id: &str,
+    confirmed_by: &str,
+    operator: Option<&str>,
+) -> Result<PrintJob, String> {
+    let changed = conn
+        .execute(
+            "UPDATE print_jobs SET status = 'CONFIRMED', confirmed_by = ?2, operator = ?3,
+                    error = NULL, finished_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND status IN ('SENT', 'FAILED')",
+            params![id, confirmed_by, operator],
+        )
+        .map_err(|e| e.to_string())?;
+    let job = get(conn, id)?;
+    if changed == 0 {
+        return Err(format!("Print job {} is already {}", id, job.status));
+    }
+    if let Some(weighment_id) = &job.weighment_id {
+        if !period_lock::is_weighment_locked(conn, weighment_id)? {
+            conn.execute(
+                "UPDATE weighments SET status = 'PRINTED',
+                        printed_at = COALESCE(printed_at, CURRENT_TIMESTAMP),
+                        updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?1 AND status IN ('CLOSED', 'PRINTED')",
+                [weighment_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    peripherals::ticket_printed(app);
+    Ok(job)
+}
+
+// The job did not come out, or came out unusable; it waits in the reprint queue
+pub fn fail(
+    conn: &Connection,
+    id: &str,
+    error: &str,
+    operator: Option<&str>,
+) -> Result<PrintJob, String> {
+    let changed = conn
+        .execute(
+            "UPDATE print_jobs SET status = 'FAILED', error = ?2, operator = ?3,
+                    finished_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND status IN ('SENT', 'FAILED')",
+            params![id, error, operator],
+        )
+        .map_err(|e| e.to_string())?;
+    let job = get(conn, id)?;
+    match changed {
+        0 => Err(format!("Print job {} is already {}", id, job.status)),
+        _ => Ok(job),
+    }
+}
+
+// Record a print the frontend performs itself (a PDF or laser slip); it is
+// confirmed with confirm_print once the operator has the page
+#[tauri::command]
+pub fn start_slip_print(
+    app: AppHandle,
+    ticket_no: String,
+    printer: Option<String>,
+    user_id: Option<String>,
+) -> Result<PrintJob, String> {
+    let conn = db::open(&app)?;
+    let id = sent(
+        &conn,
+        &ticket_no,
+        "SLIP",
+        printer.as_deref(),
+        user_id.as_deref(),
+    )?;
+    get(&conn, &id)
+}
+
+// The operator's word on a job: `printed` confirms it, otherwise it failed
+// and `error` says how
+#[tauri::command]
+pub fn confirm_print(
+    app: AppHandle,
+    job_id: String,
+    printed: bool,
+    error: Option<String>,
+    user_id: String,
+) -> Result<PrintJob, String> {
+    let conn = db::open(&app)?;
+    match printed {
+        true => confirm(&app, &conn, &job_id, "OPERATOR", Some(&user_id)),
+        false => fail(
+            &conn,
+            &job_id,
+            error
+                .as_deref()
+                .filter(|e| !e.trim().is_empty())
+                .unwrap_or("Reported not printed by the operator"),
+            Some(&user_id),
+        ),
+    }
+}
+
+// Take a job off the reprint queue without printing it, e.g. when the driver
+// left with a handwritten copy
+#[tauri::command]
+pub fn dismiss_print_job(app: AppHandle, job_id: String, user_id: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    let changed = conn
+        .execute(
+            "UPDATE print_jobs SET status = 'DISMISSED', operator = ?2,
+                    finished_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND status IN ('SENT', 'FAILED')",
+            params![job_id, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+    match changed {
+        0 => Err(format!("Print job {} is not awaiting a reprint", job_id)),
+        _ => Ok(()),
+    }
+}
+
+// Tickets whose latest print was not confirmed, oldest first. A ticket
+// printed again since drops off.
+#[tauri::command]
+pub fn reprint_queue(app: AppHandle) -> Result<Vec<PrintJob>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} j WHERE j.status IN ('SENT', 'FAILED')
+               AND NOT EXISTS (SELECT 1 FROM print_jobs later
+                               WHERE later.ticket_no = j.ticket_no
+                                 AND later.status IN ('CONFIRMED', 'DISMISSED')
+                                 AND later.rowid > j.rowid)
+             ORDER BY j.created_at, j.rowid",
+            JOB_SELECT
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], row_to_job).map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Every print of a ticket, oldest first
+#[tauri::command]
+pub fn list_print_jobs(app: AppHandle, ticket_no: String) -> Result<Vec<PrintJob>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} WHERE ticket_no = ?1 ORDER BY created_at, rowid",
+            JOB_SELECT
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([&ticket_no], row_to_job)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
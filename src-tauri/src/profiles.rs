@@ -0,0 +1,208 @@
+// Environment profiles for Truckore Pro
+// Production, training and demo each get their own database file, printer
+// target and outbound-integration switch, so staff can practise without
+// touching real data or contacting real customers.
+//
+// The profile is chosen at startup from `--profile <name>`, the
+// TRUCKORE_PROFILE variable, or the last selection saved in profiles.json.
+
+use crate::auth::{self, Sessions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+const SETTINGS_FILE: &str = "profiles.json";
+const PROFILE_ENV: &str = "TRUCKORE_PROFILE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    Production,
+    Training,
+    Demo,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 3] = [Profile::Production, Profile::Training, Profile::Demo];
+
+    pub fn parse(value: &str) -> Result<Profile, String> {
+        match value {
+            "production" => Ok(Profile::Production),
+            "training" => Ok(Profile::Training),
+            "demo" => Ok(Profile::Demo),
+            other => Err(format!("Unknown profile: {}", other)),
+        }
+    }
+
+    pub fn db_file(&self) -> &'static str {
+        match self {
+            Profile::Production => "truckore_data.db",
+            Profile::Training => "truckore_training.db",
+            Profile::Demo => "truckore_demo.db",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileSettings {
+    // Printer slips go to; None uses the system default
+    pub printer: Option<String>,
+    // Email, SMS, webhooks and cloud sync
+    pub outbound_integrations: bool,
+}
+
+impl ProfileSettings {
+    fn default_for(profile: Profile) -> Self {
+        ProfileSettings {
+            printer: None,
+            outbound_integrations: profile == Profile::Production,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SettingsFile {
+    selected: Option<Profile>,
+    profiles: HashMap<Profile, ProfileSettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileInfo {
+    pub profile: Profile,
+    pub db_file: String,
+    pub settings: ProfileSettings,
+}
+
+// Active profile, managed by Tauri
+pub struct ActiveProfile(Mutex<Profile>);
+
+impl Default for ActiveProfile {
+    fn default() -> Self {
+        ActiveProfile(Mutex::new(Profile::Production))
+    }
+}
+
+fn settings_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(SETTINGS_FILE))
+        .ok_or_else(|| "Failed to get app data directory".to_string())
+}
+
+fn load_settings(app: &AppHandle) -> Result<SettingsFile, String> {
+    let path = settings_path(app)?;
+    if !path.exists() {
+        return Ok(SettingsFile::default());
+    }
+    let json = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+fn save_settings(app: &AppHandle, settings: &SettingsFile) -> Result<(), String> {
+    let path = settings_path(app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| e.to_string())
+}
+
+// Command line flag, then environment, then the saved selection
+fn startup_profile(app: &AppHandle) -> Result<Profile, String> {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--profile") {
+        let name = args.get(i + 1).ok_or("--profile needs a value")?;
+        return Profile::parse(name);
+    }
+    if let Ok(name) = std::env::var(PROFILE_ENV) {
+        return Profile::parse(&name);
+    }
+    Ok(load_settings(app)?.selected.unwrap_or(Profile::Production))
+}
+
+// Pick the startup profile; called from the Tauri setup hook
+pub fn init(app: &AppHandle) -> Result<(), String> {
+    let profile = startup_profile(app)?;
+    *app.state::<ActiveProfile>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())? = profile;
+    Ok(())
+}
+
+pub fn active(app: &AppHandle) -> Profile {
+    app.state::<ActiveProfile>()
+        .0
+        .lock()
+        .map(|p| *p)
+        .unwrap_or(Profile::Production)
+}
+
+pub fn current(app: &AppHandle) -> Result<ProfileInfo, String> {
+    let profile = active(app);
+    let settings = load_settings(app)?
+        .profiles
+        .remove(&profile)
+        .unwrap_or_else(|| ProfileSettings::default_for(profile));
+    Ok(ProfileInfo {
+        profile,
+        db_file: profile.db_file().to_string(),
+        settings,
+    })
+}
+
+#[tauri::command]
+pub fn get_active_profile(app: AppHandle) -> Result<ProfileInfo, String> {
+    current(&app)
+}
+
+#[tauri::command]
+pub fn list_profiles(app: AppHandle) -> Result<Vec<ProfileInfo>, String> {
+    let mut settings = load_settings(&app)?;
+    Ok(Profile::ALL
+        .iter()
+        .map(|&profile| ProfileInfo {
+            profile,
+            db_file: profile.db_file().to_string(),
+            settings: settings
+                .profiles
+                .remove(&profile)
+                .unwrap_or_else(|| ProfileSettings::default_for(profile)),
+        })
+        .collect())
+}
+
+// Switch profile and remember it for the next start. Everyone is signed
+// out; the frontend re-runs init_database afterwards, as every command opens
+// the profile's database, and shows the sign-in screen.
+#[tauri::command]
+pub fn select_profile(
+    app: AppHandle,
+    sessions: State<'_, Sessions>,
+    profile: Profile,
+) -> Result<ProfileInfo, String> {
+    let mut settings = load_settings(&app)?;
+    settings.selected = Some(profile);
+    save_settings(&app, &settings)?;
+    *app.state::<ActiveProfile>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())? = profile;
+    auth::end_all(&sessions)?;
+    current(&app)
+}
+
+#[tauri::command]
+pub fn set_profile_settings(
+    app: AppHandle,
+    profile: Profile,
+    settings: ProfileSettings,
+) -> Result<(), String> {
+    let mut file = load_settings(&app)?;
+    file.profiles.insert(profile, settings);
+    save_settings(&app, &file)
+}
@@ -0,0 +1,258 @@
+// Purchase order / contract tracking for Truckore Pro
+// Completed weighments consume quantity against a party's PO. When a PO runs
+// out the ticket is either refused (BLOCK) or let through with a warning to
+// supervisors (WARN). Voided tickets stop counting against the PO.
+
+use crate::db;
+use crate::roles::{self, Role};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurchaseOrder {
+    pub id: i64,
+    pub po_number: String,
+    pub party_name: String,
+    // None covers every material for the party
+    pub product_name: Option<String>,
+    pub quantity_kg: f64,
+    pub valid_from: Option<String>,
+    pub valid_to: Option<String>,
+    // "WARN" or "BLOCK" once the quantity is used up
+    pub enforcement: String,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewPurchaseOrder {
+    pub po_number: String,
+    pub party_name: String,
+    pub product_name: Option<String>,
+    pub quantity_kg: f64,
+    pub valid_from: Option<String>,
+    pub valid_to: Option<String>,
+    pub enforcement: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoConsumption {
+    pub po_number: String,
+    pub consumed_kg: f64,
+    pub remaining_kg: f64,
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoFulfillment {
+    pub order: PurchaseOrder,
+    pub tickets: i64,
+    pub consumed_kg: f64,
+    pub remaining_kg: f64,
+    pub fulfilled_pct: f64,
+}
+
+const PO_COLUMNS: &str = "id, po_number, party_name, product_name, quantity_kg, valid_from,
+    valid_to, enforcement, status";
+
+fn row_to_po(row: &rusqlite::Row) -> rusqlite::Result<PurchaseOrder> {
+    Ok(PurchaseOrder {
+        id: row.get(0)?,
+        po_number: row.get(1)?,
+        party_name: row.get(2)?,
+        product_name: row.get(3)?,
+        quantity_kg: row.get(4)?,
+        valid_from: row.get(5)?,
+        valid_to: row.get(6)?,
+        enforcement: row.get(7)?,
+        status: row.get(8)?,
+    })
+}
+
+// Quantity consumed by tickets that still stand (not voided)
+fn consumed(conn: &Connection, po_id: i64) -> Result<(i64, f64), String> {
+    conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(quantity_kg), 0) FROM po_consumptions
+         WHERE po_id = ?1 AND weighment_id NOT IN (SELECT weighment_id FROM ticket_voids)",
+        [po_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// The PO a ticket consumes: the named one, or the oldest open, in-date PO for
+// the party and material
+fn find_po(
+    conn: &Connection,
+    party_name: &str,
+    product_name: &str,
+    po_number: Option<&str>,
+) -> Result<Option<PurchaseOrder>, String> {
+    if let Some(number) = po_number {
+        let po = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM purchase_orders WHERE po_number = ?1",
+                    PO_COLUMNS
+                ),
+                [number],
+                row_to_po,
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Purchase order {} not found", number))?;
+        if po.party_name != party_name {
+            return Err(format!(
+                "Purchase order {} belongs to {}, not {}",
+                number, po.party_name, party_name
+            ));
+        }
+        if po.status != "OPEN" {
+            return Err(format!("Purchase order {} is closed", number));
+        }
+        return Ok(Some(po));
+    }
+
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM purchase_orders
+             WHERE status = 'OPEN' AND party_name = ?1
+               AND (product_name IS NULL OR product_name = ?2)
+               AND (valid_from IS NULL OR valid_from <= date('now', 'localtime'))
+               AND (valid_to IS NULL OR valid_to >= date('now', 'localtime'))
+             ORDER BY product_name IS NULL, COALESCE(valid_from, created_at), id
+             LIMIT 1",
+            PO_COLUMNS
+        ),
+        params![party_name, product_name],
+        row_to_po,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Record a completed ticket against its PO. Fails for BLOCK POs that don't
+// have enough balance left; returns None when the party has no PO.
+pub fn consume(
+    conn: &Connection,
+    weighment_id: &str,
+    party_name: &str,
+    product_name: &str,
+    po_number: Option<&str>,
+    quantity_kg: f64,
+) -> Result<Option<PoConsumption>, String> {
+    let Some(po) = find_po(conn, party_name, product_name, po_number)? else {
+        return Ok(None);
+    };
+    let (_, used) = consumed(conn, po.id)?;
+    let remaining = po.quantity_kg - used - quantity_kg;
+
+    let mut warning = None;
+    if remaining < 0.0 {
+        let message = format!(
+            "Purchase order {} exhausted: {:.0} kg left, ticket needs {:.0} kg",
+            po.po_number,
+            (po.quantity_kg - used).max(0.0),
+            quantity_kg
+        );
+        if po.enforcement == "BLOCK" {
+            return Err(message);
+        }
+        warning = Some(message);
+    }
+
+    conn.execute(
+        "INSERT OR REPLACE INTO po_consumptions (weighment_id, po_id, quantity_kg)
+         VALUES (?1, ?2, ?3)",
+        params![weighment_id, po.id, quantity_kg],
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(Some(PoConsumption {
+        po_number: po.po_number,
+        consumed_kg: used + quantity_kg,
+        remaining_kg: remaining,
+        warning,
+    }))
+}
+
+// Create a purchase order (admin only). Returns its id.
+#[tauri::command]
+pub fn create_purchase_order(
+    app: AppHandle,
+    order: NewPurchaseOrder,
+    user_id: String,
+) -> Result<i64, String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    if order.quantity_kg <= 0.0 {
+        return Err("PO quantity must be positive".to_string());
+    }
+    conn.execute(
+        "INSERT INTO purchase_orders (po_number, party_name, product_name, quantity_kg,
+             valid_from, valid_to, enforcement, created_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            order.po_number,
+            order.party_name,
+            order.product_name,
+            order.quantity_kg,
+            order.valid_from,
+            order.valid_to,
+            order.enforcement,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Close a PO so no further tickets consume against it (admin only)
+#[tauri::command]
+pub fn close_purchase_order(app: AppHandle, id: i64, user_id: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    conn.execute(
+        "UPDATE purchase_orders SET status = 'CLOSED' WHERE id = ?1",
+        [id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Quantity, consumption and remaining balance per PO
+#[tauri::command]
+pub fn po_fulfillment_report(
+    app: AppHandle,
+    party_name: Option<String>,
+    include_closed: bool,
+) -> Result<Vec<PoFulfillment>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM purchase_orders
+             WHERE (?1 IS NULL OR party_name = ?1) AND (?2 = 1 OR status = 'OPEN')
+             ORDER BY party_name, po_number",
+            PO_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let orders = stmt
+        .query_map(params![party_name, include_closed], row_to_po)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    orders
+        .into_iter()
+        .map(|order| {
+            let (tickets, consumed_kg) = consumed(&conn, order.id)?;
+            Ok(PoFulfillment {
+                tickets,
+                consumed_kg,
+                remaining_kg: order.quantity_kg - consumed_kg,
+                fulfilled_pct: consumed_kg / order.quantity_kg * 100.0,
+                order,
+            })
+        })
+        .collect()
+}
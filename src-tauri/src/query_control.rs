@@ -0,0 +1,94 @@
+// Query timeouts and cancellation for Truckore Pro
+// A runaway report query used to hold its connection until the app was
+// killed. Queries run under a progress handler that stops them once their
+// time is up, or as soon as cancel_query is called with the id the caller
+// gave the query.
+
+use crate::errors::{AppError, ErrorCode};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// SQLite VM steps between checks; small enough to react within milliseconds
+const CHECK_EVERY: i32 = 1000;
+
+// Queries slower than this are logged as warnings
+const SLOW_QUERY_MS: f64 = 1000.0;
+
+// Managed state: cancel flags of queries running under an id
+#[derive(Default)]
+pub struct RunningQueries(Mutex<HashMap<String, Arc<AtomicBool>>>);
+
+// Run `query` on `conn`, interrupting it after `timeout` or when cancelled
+pub fn guarded<T>(
+    app: &AppHandle,
+    conn: &Connection,
+    query_id: Option<&str>,
+    timeout: Duration,
+    query: impl FnOnce() -> rusqlite::Result<T>,
+) -> Result<T, AppError> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    if let Some(id) = query_id {
+        let running = app.state::<RunningQueries>();
+        let mut running = running.0.lock().map_err(|e| e.to_string())?;
+        if running.contains_key(id) {
+            return Err(format!("A query with id {} is already running", id).into());
+        }
+        running.insert(id.to_string(), cancel.clone());
+    }
+
+    let started = Instant::now();
+    let deadline = started + timeout;
+    let flag = cancel.clone();
+    conn.progress_handler(
+        CHECK_EVERY,
+        Some(move || flag.load(Ordering::Relaxed) || Instant::now() >= deadline),
+    );
+    let outcome = query();
+    // The connection goes back to the pool, so the handler must not outlive
+    // this query
+    conn.progress_handler(0, None::<fn() -> bool>);
+    let ms = started.elapsed().as_secs_f64() * 1000.0;
+    match ms > SLOW_QUERY_MS {
+        true => tracing::warn!(query_id, ms, "Slow query"),
+        false => tracing::debug!(query_id, ms, "Query finished"),
+    }
+    if let Some(id) = query_id {
+        if let Ok(mut running) = app.state::<RunningQueries>().0.lock() {
+            running.remove(id);
+        }
+    }
+
+    outcome.map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.code == rusqlite::ErrorCode::OperationInterrupted =>
+        {
+            if cancel.load(Ordering::Relaxed) {
+                AppError::new(ErrorCode::Cancelled, "Query cancelled")
+            } else {
+                let message = format!("Query stopped after {:.0} s", timeout.as_secs_f64());
+                AppError::new(ErrorCode::Timeout, message)
+            }
+        }
+        other => AppError::from(other),
+    })
+}
+
+// Stop a query started with this id. Returns false when it is not running,
+// e.g. because it has already finished.
+#[tauri::command]
+pub fn cancel_query(queries: State<'_, RunningQueries>, query_id: String) -> Result<bool, String> {
+    let running = queries.0.lock().map_err(|e| e.to_string())?;
+    Ok(match running.get(&query_id) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    })
+}
@@ -0,0 +1,292 @@
+// Named queries for Truckore Pro
+// The webview sent SQL strings over IPC, so whatever ran in the page could
+// run any statement. The queries the app needs now live here, keyed by name
+// (`weighments.insert`, `vehicles.search`, ...), and run_named_query takes a
+// name and its positional parameters. Writes are recorded in audit_log like
+// execute_non_query. Setting the `raw_sql_disabled` feature flag turns the
+// raw SQL commands off in release builds; development builds keep them.
+
+use crate::auditor;
+use crate::command_audit::DENIED_PREFIX;
+use crate::db;
+use crate::feature_flags;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+pub const RAW_SQL_FLAG: &str = "raw_sql_disabled";
+
+// Commands that take SQL text from the caller
+pub const RAW_SQL_COMMANDS: &[&str] = &[
+    "execute_batch",
+    "execute_non_query",
+    "execute_query",
+    "execute_query_encoded",
+    "execute_query_with_columns",
+    "execute_transaction",
+    "open_query_cursor",
+];
+
+// Commands that take an export entity name or SQL text; only SQL text
+// counts as raw SQL
+pub const RAW_SQL_EXPORTS: &[&str] = &["export_jsonl", "export_query"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryKind {
+    Read,
+    Write,
+}
+
+struct NamedQuery {
+    name: &'static str,
+    kind: QueryKind,
+    sql: &'static str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NamedQueryInfo {
+    pub name: String,
+    pub kind: QueryKind,
+    // Positional parameters, ?1 to ?N
+    pub params: usize,
+}
+
+// Columns of a weighment under the names the frontend's Bill type uses
+macro_rules! bill_columns {
+    () => {
+        "id, bill_no AS billNo, ticket_no AS ticketNo,
+         vehicle_no AS vehicleNo, party_name AS partyName,
+         product_name AS productName, gross_weight AS grossWeight,
+         tare_weight AS tareWeight, net_weight AS netWeight,
+         charges_minor AS chargesMinor, front_camera_image AS frontImage,
+         back_camera_image AS rearImage, status,
+         created_at AS createdAt, updated_at AS updatedAt,
+         first_weight_type AS firstWeightType,
+         first_vehicle_status AS firstVehicleStatus,
+         second_vehicle_status AS secondVehicleStatus,
+         second_weight_timestamp AS secondWeightTimestamp,
+         closed_at AS closedAt, printed_at AS printedAt, remarks"
+    };
+}
+
+const QUERIES: &[NamedQuery] = &[
+    NamedQuery {
+        name: "weighments.list",
+        kind: QueryKind::Read,
+        sql: concat!(
+            "SELECT ",
+            bill_columns!(),
+            " FROM weighments ORDER BY created_at DESC"
+        ),
+    },
+    NamedQuery {
+        name: "weighments.get",
+        kind: QueryKind::Read,
+        sql: concat!("SELECT ", bill_columns!(), " FROM weighments WHERE id = ?1"),
+    },
+    // Text anywhere in the vehicle, party, product or bill number
+    NamedQuery {
+        name: "weighments.search",
+        kind: QueryKind::Read,
+        sql: concat!(
+            "SELECT ",
+            bill_columns!(),
+            " FROM weighments
+             WHERE vehicle_no LIKE '%' || ?1 || '%' OR party_name LIKE '%' || ?1 || '%'
+                OR product_name LIKE '%' || ?1 || '%' OR bill_no LIKE '%' || ?1 || '%'
+             ORDER BY created_at DESC"
+        ),
+    },
+    NamedQuery {
+        name: "weighments.by_date_range",
+        kind: QueryKind::Read,
+        sql: concat!(
+            "SELECT ",
+            bill_columns!(),
+            " FROM weighments WHERE created_at BETWEEN ?1 AND ?2
+             ORDER BY created_at DESC"
+        ),
+    },
+    NamedQuery {
+        name: "weighments.insert",
+        kind: QueryKind::Write,
+        sql: "INSERT INTO weighments (
+                  id, bill_no, ticket_no, vehicle_no, party_name, product_name,
+                  gross_weight, tare_weight, net_weight, charges_minor,
+                  front_camera_image, back_camera_image, status,
+                  first_weight_type, first_vehicle_status, second_vehicle_status,
+                  second_weight_timestamp, created_at, updated_at, closed_at, remarks
+              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14,
+                        ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+    },
+    // id, status, timestamp; CLOSED and PRINTED also stamp closed_at or printed_at
+    NamedQuery {
+        name: "weighments.set_status",
+        kind: QueryKind::Write,
+        sql: "UPDATE weighments
+              SET status = ?2, updated_at = ?3,
+                  closed_at = CASE WHEN ?2 = 'CLOSED' THEN ?3 ELSE closed_at END,
+                  printed_at = CASE WHEN ?2 = 'PRINTED' THEN ?3 ELSE printed_at END
+              WHERE id = ?1",
+    },
+    NamedQuery {
+        name: "open_tickets.list",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM open_tickets ORDER BY created_at DESC",
+    },
+    NamedQuery {
+        name: "open_tickets.get",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM open_tickets WHERE id = ?1",
+    },
+    NamedQuery {
+        name: "open_tickets.insert",
+        kind: QueryKind::Write,
+        sql: "INSERT INTO open_tickets (
+                  id, ticket_no, vehicle_no, party_name, product_name,
+                  first_weight, first_weight_time, camera_image
+              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    },
+    NamedQuery {
+        name: "open_tickets.delete",
+        kind: QueryKind::Write,
+        sql: "DELETE FROM open_tickets WHERE id = ?1",
+    },
+    NamedQuery {
+        name: "stored_tares.list",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM stored_tares ORDER BY created_at DESC",
+    },
+    NamedQuery {
+        name: "stored_tares.get",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM stored_tares WHERE vehicle_no = ?1",
+    },
+    // id, vehicle, tare, expiry; replaces the vehicle's stored tare
+    NamedQuery {
+        name: "stored_tares.upsert",
+        kind: QueryKind::Write,
+        sql: "INSERT INTO stored_tares (id, vehicle_no, tare_weight, expires_at)
+              VALUES (?1, ?2, ?3, ?4)
+              ON CONFLICT(vehicle_no) DO UPDATE SET
+                  tare_weight = excluded.tare_weight,
+                  expires_at = excluded.expires_at,
+                  created_at = CURRENT_TIMESTAMP",
+    },
+    NamedQuery {
+        name: "vehicles.list",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM vehicles ORDER BY vehicle_no",
+    },
+    NamedQuery {
+        name: "vehicles.get",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM vehicles WHERE vehicle_no = ?1",
+    },
+    // Vehicle numbers starting with the typed text, for autocomplete
+    NamedQuery {
+        name: "vehicles.search",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM vehicles WHERE vehicle_no LIKE ?1 || '%'
+              ORDER BY vehicle_no LIMIT 20",
+    },
+    NamedQuery {
+        name: "parties.list",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM parties ORDER BY party_name",
+    },
+    NamedQuery {
+        name: "parties.get",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM parties WHERE party_name = ?1",
+    },
+    NamedQuery {
+        name: "products.list",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM products ORDER BY product_name",
+    },
+    NamedQuery {
+        name: "products.get",
+        kind: QueryKind::Read,
+        sql: "SELECT * FROM products WHERE product_name = ?1",
+    },
+];
+
+fn find(name: &str) -> Result<&'static NamedQuery, String> {
+    QUERIES
+        .iter()
+        .find(|q| q.name == name)
+        .ok_or_else(|| format!("Unknown query {}", name))
+}
+
+// Refuse a raw SQL command when the site has switched them off
+pub fn require_raw_sql(app: &AppHandle) -> Result<(), String> {
+    if cfg!(debug_assertions) {
+        return Ok(());
+    }
+    let conn = db::open(app)?;
+    if feature_flags::is_enabled(&conn, RAW_SQL_FLAG)? {
+        return Err(format!(
+            "{}: raw SQL is disabled on this site; use run_named_query",
+            crate::command_audit::DENIED_PREFIX
+        ));
+    }
+    Ok(())
+}
+
+// The registered queries, for the frontend to check against
+#[tauri::command]
+pub fn list_named_queries(app: AppHandle) -> Result<Vec<NamedQueryInfo>, String> {
+    let conn = db::open(&app)?;
+    QUERIES
+        .iter()
+        .map(|query| {
+            let stmt = conn.prepare_cached(query.sql).map_err(|e| e.to_string())?;
+            Ok(NamedQueryInfo {
+                name: query.name.to_string(),
+                kind: query.kind,
+                params: stmt.parameter_count(),
+            })
+        })
+        .collect()
+}
+
+// Run a registered query. Reads return their rows like execute_query and
+// take its min_version, writes the outcome of execute_non_query. Auditors
+// may only run reads.
+#[tauri::command]
+pub fn run_named_query(
+    app: AppHandle,
+    session_token: Option<String>,
+    name: String,
+    params: Vec<Value>,
+    min_version: Option<u64>,
+) -> Result<Value, String> {
+    let query = find(&name)?;
+    match query.kind {
+        QueryKind::Read => {
+            let rows = crate::read_named(
+                &app,
+                session_token.as_deref(),
+                query.sql,
+                &params,
+                min_version,
+            )?;
+            Ok(Value::from(rows))
+        }
+        QueryKind::Write => {
+            if auditor::session_auditor(&app, session_token.as_deref())?.is_some() {
+                return Err(format!("{}: auditor sessions are read-only", DENIED_PREFIX));
+            }
+            let outcome = crate::write(
+                &app,
+                session_token.as_deref(),
+                "run_named_query",
+                query.sql.to_string(),
+                params,
+            )?;
+            serde_json::to_value(outcome).map_err(|e| e.to_string())
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// Corruption recovery for Truckore Pro
+// When the database can't be opened, salvage every readable row into a fresh
+// database, keep the damaged file for support and carry on with the salvage.
+
+use crate::db;
+use crate::encryption;
+use crate::migrations;
+use crate::money;
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+// Prefix on init_database errors that should start the recovery wizard
+pub const CORRUPT_PREFIX: &str = "DATABASE_CORRUPT";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseHealth {
+    pub ok: bool,
+    pub corrupt: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableSalvage {
+    pub table: String,
+    // Row count in the damaged file, when it could still be counted
+    pub original_rows: Option<i64>,
+    pub salvaged_rows: i64,
+    // Rows known to exist that could not be read
+    pub lost_rows: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    pub corrupt_copy: String,
+    pub report_path: String,
+    pub tables: Vec<TableSalvage>,
+}
+
+fn is_corruption(err: &rusqlite::Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(ErrorCode::DatabaseCorrupt) | Some(ErrorCode::NotADatabase)
+    )
+}
+
+// Fail with SQLITE_CORRUPT when a quick check finds damage
+pub fn check_integrity(conn: &Connection) -> rusqlite::Result<()> {
+    let result: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if result == "ok" {
+        Ok(())
+    } else {
+        Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+            Some(result),
+        ))
+    }
+}
+
+pub fn describe_open_error(err: rusqlite::Error) -> String {
+    if is_corruption(&err) {
+        format!("{}: {}", CORRUPT_PREFIX, err)
+    } else {
+        err.to_string()
+    }
+}
+
+pub fn table_names(conn: &Connection, schema: &str) -> rusqlite::Result<Vec<String>> {
+    let sql = format!(
+        "SELECT name FROM {}.sqlite_master
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        schema
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let names = stmt.query_map([], |row| row.get(0))?.collect();
+    names
+}
+
+fn column_names(conn: &Connection, schema: &str, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {}.table_info(\"{}\")", schema, table))?;
+    let names = stmt.query_map([], |row| row.get(1))?.collect();
+    names
+}
+
+// Copy one table from the attached damaged database. A bulk copy is tried
+// first; if a damaged page stops it, rows are probed one rowid at a time so
+// everything before and after the damage is kept.
+fn salvage_table(conn: &Connection, table: &str) -> TableSalvage {
+    let mut salvage = TableSalvage {
+        table: table.to_string(),
+        original_rows: None,
+        salvaged_rows: 0,
+        lost_rows: None,
+        error: None,
+    };
+
+    // Only columns present in both schemas, so older files still salvage,
+    // and amounts an older file held in rupees converted to paise
+    let (columns, sources): (Vec<String>, Vec<String>) = match (
+        column_names(conn, "main", table),
+        column_names(conn, "old", table),
+    ) {
+        (Ok(new), Ok(old)) => new
+            .into_iter()
+            .filter_map(|c| {
+                let quoted = format!("\"{}\"", c);
+                if old.contains(&c) {
+                    return Some((quoted.clone(), quoted));
+                }
+                money::unconverted(table, &c)
+                    .filter(|(rupees, _)| old.iter().any(|o| o == rupees))
+                    .map(|(_, paise)| (quoted, paise))
+            })
+            .unzip(),
+        (_, Err(e)) | (Err(e), _) => {
+            salvage.error = Some(e.to_string());
+            return salvage;
+        }
+    };
+    let (columns, sources) = (columns.join(", "), sources.join(", "));
+    if columns.is_empty() {
+        salvage.error = Some("Table missing from damaged database".to_string());
+        return salvage;
+    }
+
+    let quoted = format!("\"{}\"", table);
+    salvage.original_rows = conn
+        .query_row(&format!("SELECT COUNT(*) FROM old.{}", quoted), [], |row| {
+            row.get(0)
+        })
+        .ok();
+
+    let bulk = format!(
+        "INSERT OR REPLACE INTO main.{t} ({c}) SELECT {s} FROM old.{t}",
+        t = quoted,
+        c = columns,
+        s = sources
+    );
+    let copied = conn
+        .execute_batch("SAVEPOINT bulk")
+        .and_then(|_| conn.execute(&bulk, []));
+    match copied {
+        Ok(rows) => {
+            let _ = conn.execute_batch("RELEASE bulk");
+            salvage.salvaged_rows = rows as i64;
+        }
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK TO bulk; RELEASE bulk");
+            salvage.error = Some(e.to_string());
+
+            let max_rowid: i64 = conn
+                .query_row(
+                    &format!("SELECT MAX(rowid) FROM old.{}", quoted),
+                    [],
+                    |row| row.get::<_, Option<i64>>(0),
+                )
+                .ok()
+                .flatten()
+                .unwrap_or(0);
+            let probe = format!(
+                "INSERT OR REPLACE INTO main.{t} ({c}) SELECT {s} FROM old.{t} WHERE rowid = ?1",
+                t = quoted,
+                c = columns,
+                s = sources
+            );
+            for rowid in 1..=max_rowid {
+                if let Ok(rows) = conn.execute(&probe, [rowid]) {
+                    salvage.salvaged_rows += rows as i64;
+                }
+            }
+        }
+    }
+
+    salvage.lost_rows = salvage
+        .original_rows
+        .map(|original| (original - salvage.salvaged_rows).max(0));
+    salvage
+}
+
+// The copy is keyed like the live database; ATTACH reuses that key.
+// `progress` is told (tables done, tables in all, table) before each table.
+fn salvage_into(
+    app: &AppHandle,
+    damaged: &Path,
+    target: &Path,
+    progress: &dyn Fn(usize, usize, &str),
+) -> Result<Vec<TableSalvage>, String> {
+    let conn =
+        encryption::open_file(app, target, OpenFlags::default()).map_err(|e| e.to_string())?;
+    conn.execute_batch(db::SCHEMA).map_err(|e| e.to_string())?;
+
+    // Audit and period-lock triggers would reject re-inserting history, so
+    // they are dropped for the copy and recreated by re-applying the schema
+    let triggers: Vec<String> = {
+        let mut stmt = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'trigger'")
+            .map_err(|e| e.to_string())?;
+        let names = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        names
+    };
+    for trigger in &triggers {
+        conn.execute_batch(&format!("DROP TRIGGER \"{}\"", trigger))
+            .map_err(|e| e.to_string())?;
+    }
+    conn.execute_batch("PRAGMA foreign_keys = OFF")
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "ATTACH DATABASE ?1 AS old",
+        [damaged.to_string_lossy().as_ref()],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let tables = table_names(&conn, "main").map_err(|e| e.to_string())?;
+    let old_tables = table_names(&conn, "old").unwrap_or_default();
+    let report = tables
+        .iter()
+        .enumerate()
+        .map(|(i, table)| {
+            progress(i, tables.len(), table);
+            if old_tables.is_empty() || old_tables.contains(table) {
+                salvage_table(&conn, table)
+            } else {
+                TableSalvage {
+                    table: table.clone(),
+                    original_rows: None,
+                    salvaged_rows: 0,
+                    lost_rows: None,
+                    error: Some("Table missing from damaged database".to_string()),
+                }
+            }
+        })
+        .collect();
+
+    conn.execute_batch("DETACH DATABASE old")
+        .map_err(|e| e.to_string())?;
+    conn.execute_batch(db::SCHEMA).map_err(|e| e.to_string())?;
+    migrations::restore_derived(&conn)?;
+    Ok(report)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Open the database and run a quick integrity check
+#[tauri::command]
+pub fn check_database_health(app: AppHandle) -> Result<DatabaseHealth, String> {
+    let db_path = crate::get_db_path(&app)?;
+    let checked = encryption::open_file(&app, &db_path, OpenFlags::default())
+        .and_then(|conn| check_integrity(&conn));
+    Ok(match checked {
+        Ok(()) => DatabaseHealth {
+            ok: true,
+            corrupt: false,
+            detail: "ok".to_string(),
+        },
+        Err(e) => DatabaseHealth {
+            ok: false,
+            corrupt: is_corruption(&e),
+            detail: e.to_string(),
+        },
+    })
+}
+
+// Salvage readable rows into a new database and switch to it. The damaged
+// file (and any WAL/SHM sidecars) is kept alongside with a JSON report of
+// what could not be recovered, for support to investigate.
+pub fn recover(
+    app: &AppHandle,
+    progress: &dyn Fn(usize, usize, &str),
+) -> Result<RecoveryReport, String> {
+    let db_path = crate::get_db_path(app)?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+
+    let recovered = with_suffix(&db_path, &format!(".recovered-{}", stamp));
+    let tables = salvage_into(app, &db_path, &recovered, progress).map_err(|e| {
+        let _ = fs::remove_file(&recovered);
+        format!("Salvage failed: {}", e)
+    })?;
+
+    // Pooled connections would keep the damaged file open
+    app.state::<db::DbPool>().close_all();
+    let corrupt_copy = with_suffix(&db_path, &format!(".corrupt-{}", stamp));
+    fs::rename(&db_path, &corrupt_copy).map_err(|e| e.to_string())?;
+    for sidecar in ["-wal", "-shm"] {
+        let file = with_suffix(&db_path, sidecar);
+        if file.exists() {
+            let _ = fs::rename(&file, with_suffix(&corrupt_copy, sidecar));
+        }
+    }
+    fs::rename(&recovered, &db_path).map_err(|e| e.to_string())?;
+
+    let report_path = with_suffix(&corrupt_copy, ".report.json");
+    let report = RecoveryReport {
+        corrupt_copy: corrupt_copy.to_string_lossy().to_string(),
+        report_path: report_path.to_string_lossy().to_string(),
+        tables,
+    };
+    let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+    fs::write(&report_path, json).map_err(|e| e.to_string())?;
+
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn recover_database(app: AppHandle) -> Result<RecoveryReport, String> {
+    recover(&app, &|_, _, _| {})
+}
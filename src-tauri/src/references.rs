@@ -0,0 +1,257 @@
+// Reference resolver for Truckore Pro
+// The global search box takes whatever the operator types or scans: a
+// ticket or bill number, the gate QR code off a ticket (ticket_qr.rs), a
+// ticket number reservation token, an RFID tag, a purchase order number or
+// a vehicle number. Each kind is tried as an exact match, and every record
+// that matches is returned with the kind that matched, so a scan opens the
+// ticket directly. Text that matches nothing exactly falls back to the
+// full-text search (search.rs). E-way bill numbers are not recorded in this
+// database, so they are found only where they were typed into remarks.
+
+use crate::db;
+use crate::rfid;
+use crate::search;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const SEARCH_LIMIT: i64 = 20;
+// Open tickets listed for a matched vehicle
+const OPEN_TICKETS_PER_VEHICLE: i64 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceMatch {
+    // ticket_no, bill_no, gate_pass, token, rfid_tag, po_number,
+    // vehicle_no or search
+    pub kind: String,
+    // weighment, reservation, vehicle, party or purchase_order
+    pub entity: String,
+    pub id: String,
+    pub title: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResolvedReference {
+    pub text: String,
+    // Exact matches first; search results only when there are none
+    pub matches: Vec<ReferenceMatch>,
+}
+
+// Ticket number in a gate QR payload: "/verify/<ticket_no>?code=...", alone
+// or at the end of the verification URL
+fn gate_pass_ticket(text: &str) -> Option<String> {
+    let (_, rest) = text.split_once("/verify/")?;
+    let ticket_no = rest.split(['?', '&', '#']).next()?.trim();
+    (!ticket_no.is_empty()).then(|| ticket_no.to_string())
+}
+
+fn plate(text: &str) -> String {
+    text.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.'))
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// Ids from `sql`, which takes the value as ?1
+fn ids(conn: &Connection, sql: &str, value: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([value], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+struct Resolver<'a> {
+    conn: &'a Connection,
+    matches: Vec<ReferenceMatch>,
+}
+
+impl Resolver<'_> {
+    // Add a matched record with its search title, once
+    fn add(&mut self, kind: &str, entity: &str, id: String) -> Result<(), String> {
+        if self
+            .matches
+            .iter()
+            .any(|m| m.entity == entity && m.id == id)
+        {
+            return Ok(());
+        }
+        if let Some((title, detail)) = search::describe_record(self.conn, entity, &id)? {
+            self.matches.push(ReferenceMatch {
+                kind: kind.to_string(),
+                entity: entity.to_string(),
+                id,
+                title,
+                detail,
+            });
+        }
+        Ok(())
+    }
+
+    fn weighments(&mut self, kind: &str, column: &str, value: &str) -> Result<(), String> {
+        let sql = format!(
+            "SELECT CAST(id AS TEXT) FROM weighments WHERE {} = ?1 COLLATE NOCASE
+             ORDER BY created_at DESC",
+            column
+        );
+        for id in ids(self.conn, &sql, value)? {
+            self.add(kind, "weighment", id)?;
+        }
+        Ok(())
+    }
+
+    fn token(&mut self, text: &str) -> Result<(), String> {
+        let reservation = self
+            .conn
+            .query_row(
+                "SELECT serial, status, weighment_id, expires_at
+                 FROM ticket_number_reservations WHERE token = ?1",
+                [text],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        match reservation {
+            Some((_, _, Some(weighment_id), _)) => self.add("token", "weighment", weighment_id),
+            Some((serial, status, None, expires_at)) => {
+                self.matches.push(ReferenceMatch {
+                    kind: "token".to_string(),
+                    entity: "reservation".to_string(),
+                    title: format!("Ticket number {}", serial),
+                    detail: Some(format!("{} · expires {}", status, expires_at)),
+                    id: serial,
+                });
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // The vehicle, and its tickets still waiting for a second weighing
+    fn vehicle(&mut self, kind: &str, vehicle_id: String) -> Result<(), String> {
+        let open = ids(
+            self.conn,
+            &format!(
+                "SELECT CAST(w.id AS TEXT) FROM weighments w JOIN vehicles v
+                     ON v.vehicle_no = w.vehicle_no
+                 WHERE v.id = ?1 AND w.status = 'OPEN'
+                 ORDER BY w.created_at DESC LIMIT {}",
+                OPEN_TICKETS_PER_VEHICLE
+            ),
+            &vehicle_id,
+        )?;
+        self.add(kind, "vehicle", vehicle_id)?;
+        for id in open {
+            self.add(kind, "weighment", id)?;
+        }
+        Ok(())
+    }
+
+    fn purchase_order(&mut self, text: &str) -> Result<(), String> {
+        let order = self
+            .conn
+            .query_row(
+                "SELECT CAST(id AS TEXT), po_number, party_name, product_name, quantity_kg
+                 FROM purchase_orders WHERE po_number = ?1 COLLATE NOCASE",
+                [text],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, f64>(4)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some((id, po_number, party, product, quantity_kg)) = order {
+            let mut detail = party;
+            if let Some(product) = product {
+                detail.push_str(&format!(" · {}", product));
+            }
+            detail.push_str(&format!(" · {:.0} kg", quantity_kg));
+            self.matches.push(ReferenceMatch {
+                kind: "po_number".to_string(),
+                entity: "purchase_order".to_string(),
+                id,
+                title: format!("PO {}", po_number),
+                detail: Some(detail),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub fn resolve(conn: &Connection, text: &str) -> Result<Vec<ReferenceMatch>, String> {
+    let text = text.trim();
+    let mut resolver = Resolver {
+        conn,
+        matches: Vec::new(),
+    };
+    if text.is_empty() {
+        return Ok(resolver.matches);
+    }
+    if let Some(ticket_no) = gate_pass_ticket(text) {
+        resolver.weighments("gate_pass", "ticket_no", &ticket_no)?;
+        return Ok(resolver.matches);
+    }
+    resolver.weighments("ticket_no", "ticket_no", text)?;
+    resolver.weighments("bill_no", "bill_no", text)?;
+    resolver.token(text)?;
+    let tag = rfid::normalize_tag(text);
+    if !tag.is_empty() {
+        let bound: Option<String> = conn
+            .query_row(
+                "SELECT vehicle_id FROM vehicle_tags WHERE tag = ?1",
+                [&tag],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some(vehicle_id) = bound {
+            resolver.vehicle("rfid_tag", vehicle_id)?;
+        }
+    }
+    resolver.purchase_order(text)?;
+    let vehicles = ids(
+        conn,
+        "SELECT id FROM vehicles
+         WHERE upper(replace(replace(replace(vehicle_no, ' ', ''), '-', ''), '.', '')) = ?1",
+        &plate(text),
+    )?;
+    for vehicle_id in vehicles {
+        resolver.vehicle("vehicle_no", vehicle_id)?;
+    }
+    Ok(resolver.matches)
+}
+
+// What a scanned or typed string refers to, for the global search box
+#[tauri::command]
+pub fn resolve_reference(app: AppHandle, text: String) -> Result<ResolvedReference, String> {
+    let conn = db::open(&app)?;
+    let mut matches = resolve(&conn, &text)?;
+    if matches.is_empty() {
+        matches = search::search(app.clone(), None, text.clone(), Some(SEARCH_LIMIT))?
+            .into_iter()
+            .map(|result| ReferenceMatch {
+                kind: "search".to_string(),
+                entity: result.entity,
+                id: result.id,
+                title: result.title,
+                detail: result.detail,
+            })
+            .collect();
+    }
+    Ok(ResolvedReference { text, matches })
+}
@@ -0,0 +1,740 @@
+// Report builder for Truckore Pro
+// Admins compose reports from an export entity: the columns to show,
+// filters, groupings and totals. Definitions are saved in
+// report_definitions and turned into a single SELECT here, whose rows feed
+// the on-screen grid and the CSV/XLSX exporter alike. Column names are
+// checked against the entity's export columns and filter values are written
+// as SQL literals, so a definition cannot reach outside its entity.
+// Weighment reports can take in the archives too: each archive file is
+// attached and its tickets unioned with the live ones before filtering and
+// grouping, so totals over several years survive archival.
+// Masked columns read as NULL for the user running a saved report, totals
+// over them included (masking.rs).
+
+use crate::archive;
+use crate::command_audit;
+use crate::db::{self, DateRange};
+use crate::encryption;
+use crate::export::{self, EntityExport};
+use crate::masking::{self, Masks};
+use crate::money;
+use crate::roles::{self, Role};
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+
+pub const FILTER_OPS: &[&str] = &[
+    "eq",
+    "ne",
+    "lt",
+    "le",
+    "gt",
+    "ge",
+    "contains",
+    "starts_with",
+    "in",
+    "is_null",
+    "not_null",
+];
+pub const TOTAL_FUNCTIONS: &[&str] = &["count", "sum", "avg", "min", "max"];
+
+// SQLite attaches at most ten databases
+const MAX_ARCHIVES: usize = 9;
+
+// Rows returned to the grid by default, and at most
+const DEFAULT_LIMIT: usize = 1000;
+const MAX_LIMIT: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportFilter {
+    pub column: String,
+    pub op: String,
+    // A list for `in`, ignored by is_null and not_null
+    #[serde(default)]
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportTotal {
+    // "*" counts rows
+    pub column: String,
+    pub function: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportDefinition {
+    pub name: String,
+    pub entity: String,
+    // Shown when the report is not grouped; empty means every column
+    pub columns: Vec<String>,
+    pub filters: Vec<ReportFilter>,
+    // Grouped reports show the group columns followed by the totals
+    pub group_by: Vec<String>,
+    pub totals: Vec<ReportTotal>,
+    // A column, or a total's name such as sum_net_weight
+    pub sort: Option<String>,
+    pub descending: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedReport {
+    pub id: String,
+    pub definition: ReportDefinition,
+    pub created_by: String,
+    pub updated_by: Option<String>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportTotalValue {
+    pub name: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    // More rows matched than the limit allowed
+    pub truncated: bool,
+    // Totals over every matching row, grouped or not
+    pub totals: Vec<ReportTotalValue>,
+}
+
+// Payload of `report-archive-progress`, sent as each archive is attached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveProgress {
+    pub archive: String,
+    // 1-based, out of `total`
+    pub index: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportEntity {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub date_column: String,
+}
+
+fn entity(name: &str) -> Result<&'static EntityExport, String> {
+    export::ENTITIES
+        .iter()
+        .find(|e| e.name == name)
+        .ok_or_else(|| format!("Unknown report entity {}", name))
+}
+
+fn column<'e>(entity: &'e EntityExport, name: &str) -> Result<&'e str, String> {
+    entity
+        .columns
+        .iter()
+        .find(|c| **c == name)
+        .copied()
+        .ok_or_else(|| format!("{} has no column {}", entity.name, name))
+}
+
+fn literal(value: &Value) -> Result<String, String> {
+    match value {
+        Value::Null => Ok("NULL".to_string()),
+        Value::Bool(b) => Ok(if *b { "1" } else { "0" }.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(format!("'{}'", s.replace('\'', "''"))),
+        other => Err(format!(
+            "Filter value must be text or a number, got {}",
+            other
+        )),
+    }
+}
+
+fn condition(entity: &EntityExport, filter: &ReportFilter) -> Result<String, String> {
+    let col = column(entity, &filter.column)?;
+    let compare = |op: &str| -> Result<String, String> {
+        Ok(format!("{} {} {}", col, op, literal(&filter.value)?))
+    };
+    match filter.op.as_str() {
+        "eq" => compare("="),
+        "ne" => compare("IS NOT"),
+        "lt" => compare("<"),
+        "le" => compare("<="),
+        "gt" => compare(">"),
+        "ge" => compare(">="),
+        "contains" => Ok(format!("instr({}, {}) > 0", col, literal(&filter.value)?)),
+        "starts_with" => {
+            let value = literal(&filter.value)?;
+            Ok(format!("substr({}, 1, length({})) = {}", col, value, value))
+        }
+        "in" => {
+            let values = filter
+                .value
+                .as_array()
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| format!("Filter on {} needs a list of values", col))?;
+            let values = values.iter().map(literal).collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("{} IN ({})", col, values.join(", ")))
+        }
+        "is_null" => Ok(format!("{} IS NULL", col)),
+        "not_null" => Ok(format!("{} IS NOT NULL", col)),
+        other => Err(format!("Unknown filter operator {}", other)),
+    }
+}
+
+fn total(entity: &EntityExport, total: &ReportTotal) -> Result<(String, String), String> {
+    if !TOTAL_FUNCTIONS.contains(&total.function.as_str()) {
+        return Err(format!("Unknown total function {}", total.function));
+    }
+    if total.column == "*" {
+        return match total.function.as_str() {
+            "count" => Ok(("COUNT(*)".to_string(), "count".to_string())),
+            _ => Err(format!("{} needs a column", total.function)),
+        };
+    }
+    let col = column(entity, &total.column)?;
+    // Amounts are paise (money.rs), so their sums stay whole numbers
+    let expr = format!("{}({})", total.function.to_ascii_uppercase(), col);
+    Ok((expr, format!("{}_{}", total.function, col)))
+}
+
+fn where_clause(
+    entity: &EntityExport,
+    definition: &ReportDefinition,
+    ranged: bool,
+) -> Result<String, String> {
+    let mut conditions = definition
+        .filters
+        .iter()
+        .map(|f| condition(entity, f))
+        .collect::<Result<Vec<_>, _>>()?;
+    if ranged {
+        conditions.push(format!(
+            "{} BETWEEN :from AND :to",
+            db::local_date(entity.date_column)
+        ));
+    }
+    if entity.excludes_practice {
+        conditions.push(training::exclude_practice("id"));
+    }
+    Ok(match conditions.is_empty() {
+        true => String::new(),
+        false => format!(" WHERE {}", conditions.join(" AND ")),
+    })
+}
+
+// The entity's table, or its live rows unioned with those of the attached
+// `archives`; archived practice tickets are left out here, live ones by the
+// WHERE clause
+fn source(entity: &EntityExport, archives: &[String]) -> String {
+    if archives.is_empty() {
+        return entity.table.to_string();
+    }
+    let columns = entity.columns.join(", ");
+    let mut parts = vec![format!("SELECT {} FROM main.{}", columns, entity.table)];
+    for schema in archives {
+        parts.push(format!(
+            "SELECT {c} FROM {s}.{t} WHERE id NOT IN (SELECT weighment_id FROM {s}.practice_tickets)",
+            c = columns,
+            s = schema,
+            t = entity.table
+        ));
+    }
+    format!("({}) AS {}", parts.join(" UNION ALL "), entity.table)
+}
+
+// The report's SELECT over the live table and the attached `archives`; the
+// range, when given, is bound as :from and :to
+pub fn report_sql(
+    definition: &ReportDefinition,
+    ranged: bool,
+    archives: &[String],
+) -> Result<String, String> {
+    let entity = entity(&definition.entity)?;
+    let filter = where_clause(entity, definition, ranged)?;
+    let totals = definition
+        .totals
+        .iter()
+        .map(|t| total(entity, t))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (select, names, group) = if definition.group_by.is_empty() {
+        let columns = match definition.columns.is_empty() {
+            true => entity.columns.to_vec(),
+            false => definition
+                .columns
+                .iter()
+                .map(|c| column(entity, c))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        let names = columns.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        (columns.join(", "), names, String::new())
+    } else {
+        let groups = definition
+            .group_by
+            .iter()
+            .map(|c| column(entity, c))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut select = groups.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+        let mut names = select.clone();
+        for (expr, name) in &totals {
+            select.push(format!("{} AS {}", expr, name));
+            names.push(name.clone());
+        }
+        (
+            select.join(", "),
+            names,
+            format!(" GROUP BY {}", groups.join(", ")),
+        )
+    };
+
+    let order = match &definition.sort {
+        Some(sort) => {
+            if !names.contains(sort) {
+                return Err(format!("Cannot sort by {}, it is not in the report", sort));
+            }
+            sort.clone()
+        }
+        None if group.is_empty() => entity.date_column.to_string(),
+        None => definition.group_by.join(", "),
+    };
+    Ok(format!(
+        "SELECT {} FROM {}{}{} ORDER BY {}{}",
+        select,
+        source(entity, archives),
+        filter,
+        group,
+        order,
+        if definition.descending { " DESC" } else { "" }
+    ))
+}
+
+// Totals over all matching rows
+fn grand_totals(
+    conn: &Connection,
+    definition: &ReportDefinition,
+    range: Option<&DateRange>,
+    archives: &[String],
+    masks: &Masks,
+) -> Result<Vec<ReportTotalValue>, String> {
+    if definition.totals.is_empty() {
+        return Ok(Vec::new());
+    }
+    let entity = entity(&definition.entity)?;
+    let totals = definition
+        .totals
+        .iter()
+        .map(|t| total(entity, t))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sql = format!(
+        "SELECT {} FROM {}{}",
+        totals
+            .iter()
+            .map(|(expr, _)| expr.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        source(entity, archives),
+        where_clause(entity, definition, range.is_some())?
+    );
+    let mut stmt = export::prepare_export(conn, &sql, range, masks)?;
+    let mut rows = stmt.raw_query();
+    let row = rows
+        .next()
+        .map_err(|e| e.to_string())?
+        .ok_or("Totals query returned no row")?;
+    totals
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, name))| {
+            let value = crate::sql_to_json_value(row.get_ref(i).map_err(|e| e.to_string())?);
+            Ok(ReportTotalValue { name, value })
+        })
+        .collect()
+}
+
+fn run(
+    conn: &Connection,
+    definition: &ReportDefinition,
+    range: Option<&DateRange>,
+    limit: Option<usize>,
+    archives: &[String],
+    masks: &Masks,
+) -> Result<ReportResult, String> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let sql = report_sql(definition, range.is_some(), archives)?;
+    let mut stmt = export::prepare_export(conn, &sql, range, masks)?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.raw_query();
+    let mut out = Vec::new();
+    let mut truncated = false;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        if out.len() == limit {
+            truncated = true;
+            break;
+        }
+        let mut values = Vec::with_capacity(columns.len());
+        for i in 0..columns.len() {
+            values.push(crate::sql_to_json_value(
+                row.get_ref(i).map_err(|e| e.to_string())?,
+            ));
+        }
+        out.push(values);
+    }
+    drop(rows);
+    Ok(ReportResult {
+        columns,
+        rows: out,
+        truncated,
+        totals: grand_totals(conn, definition, range, archives, masks)?,
+    })
+}
+
+// Run `f` with every archive attached when `include` is set, passing the
+// attached schema names. Pooled connections are reused, so they are always
+// detached again.
+fn with_archives<T>(
+    app: &AppHandle,
+    conn: &Connection,
+    definition: &ReportDefinition,
+    include: bool,
+    f: impl FnOnce(&[String]) -> Result<T, String>,
+) -> Result<T, String> {
+    if !include {
+        return f(&[]);
+    }
+    if definition.entity != "weighments" {
+        return Err(format!("{} is not archived", definition.entity));
+    }
+    let files = archive::archive_files(app)?;
+    if files.len() > MAX_ARCHIVES {
+        return Err(format!(
+            "{} archives found; at most {} can be reported on together",
+            files.len(),
+            MAX_ARCHIVES
+        ));
+    }
+    let mut attached = Vec::new();
+    let mut result = Ok(());
+    for (i, file) in files.iter().enumerate() {
+        let schema = format!("report_archive_{}", i + 1);
+        if let Err(e) = encryption::attach(app, conn, file, &schema) {
+            result = Err(format!("Failed to open archive {}: {}", file.display(), e));
+            break;
+        }
+        attached.push(schema.clone());
+        if let Err(e) = money::convert_charges(conn, &schema) {
+            result = Err(format!(
+                "Failed to update archive {}: {}",
+                file.display(),
+                e
+            ));
+            break;
+        }
+        let _ = app.emit_all(
+            "report-archive-progress",
+            ArchiveProgress {
+                archive: file.to_string_lossy().into_owned(),
+                index: i + 1,
+                total: files.len(),
+            },
+        );
+    }
+    let result = result.and_then(|_| f(&attached));
+    for schema in &attached {
+        let _ = conn.execute_batch(&format!("DETACH DATABASE {}", schema));
+    }
+    result
+}
+
+fn validate(conn: &Connection, definition: &ReportDefinition) -> Result<(), String> {
+    if definition.name.trim().is_empty() {
+        return Err("A report needs a name".to_string());
+    }
+    for filter in &definition.filters {
+        if !FILTER_OPS.contains(&filter.op.as_str()) {
+            return Err(format!("Unknown filter operator {}", filter.op));
+        }
+    }
+    // Preparing catches anything the checks above let through
+    conn.prepare(&report_sql(definition, true, &[])?)
+        .map(|_| ())
+        .map_err(|e| format!("Report {} cannot run: {}", definition.name, e))
+}
+
+// A saved definition's reference to the rupee `charges` column, moved to
+// paise: the column and totals over it are renamed and filter values
+// converted
+fn charges_to_minor(definition: &mut ReportDefinition) -> bool {
+    const OLD: &str = "charges";
+    const NEW: &str = "charges_minor";
+    let mut changed = false;
+    let mut rename = |name: &mut String| {
+        if name == OLD {
+            *name = NEW.to_string();
+            changed = true;
+        }
+    };
+    definition.columns.iter_mut().for_each(&mut rename);
+    definition.group_by.iter_mut().for_each(&mut rename);
+    definition
+        .totals
+        .iter_mut()
+        .for_each(|t| rename(&mut t.column));
+    let mut converted = false;
+    for filter in definition.filters.iter_mut().filter(|f| f.column == OLD) {
+        filter.column = NEW.to_string();
+        let to_minor = |value: &mut Value| {
+            if let Some(rupees) = value.as_f64() {
+                *value = Value::from(money::to_minor(rupees));
+            }
+        };
+        match &mut filter.value {
+            Value::Array(values) => values.iter_mut().for_each(to_minor),
+            value => to_minor(value),
+        }
+        converted = true;
+    }
+    if let Some(sort) = &mut definition.sort {
+        if let Some(function) = sort.strip_suffix(&format!("_{}", OLD)) {
+            if TOTAL_FUNCTIONS.contains(&function) {
+                *sort = format!("{}_{}", function, NEW);
+                converted = true;
+            }
+        }
+        if sort == OLD {
+            *sort = NEW.to_string();
+            converted = true;
+        }
+    }
+    changed || converted
+}
+
+// Move saved reports over to weighments.charges_minor. Part of schema
+// migration 13 (money.rs).
+pub fn migrate_charges(conn: &Connection) -> Result<(), String> {
+    let saved: Vec<(String, String)> = conn
+        .prepare("SELECT id, definition FROM report_definitions")
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    for (id, json) in saved {
+        // One that no longer reads is left for its owner to fix
+        let Ok(mut definition) = serde_json::from_str::<ReportDefinition>(&json) else {
+            continue;
+        };
+        if definition.entity != "weighments" || !charges_to_minor(&mut definition) {
+            continue;
+        }
+        let json = serde_json::to_string(&definition).map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE report_definitions SET definition = ?2 WHERE id = ?1",
+            params![id, json],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn load(conn: &Connection, id: &str) -> Result<ReportDefinition, String> {
+    let json: String = conn
+        .query_row(
+            "SELECT definition FROM report_definitions WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Report {} not found", id))?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+// Entities and columns a report can be built from
+#[tauri::command]
+pub fn report_entities() -> Vec<ReportEntity> {
+    export::ENTITIES
+        .iter()
+        .map(|e| ReportEntity {
+            name: e.name.to_string(),
+            columns: e.columns.iter().map(|c| c.to_string()).collect(),
+            date_column: e.date_column.to_string(),
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn list_report_definitions(app: AppHandle) -> Result<Vec<SavedReport>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, definition, created_by, updated_by, updated_at
+             FROM report_definitions ORDER BY name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.map(|row| {
+        let (id, json, created_by, updated_by, updated_at) = row.map_err(|e| e.to_string())?;
+        Ok(SavedReport {
+            id,
+            definition: serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            created_by,
+            updated_by,
+            updated_at,
+        })
+    })
+    .collect()
+}
+
+// Create a report, or replace report `id` (admin only). Returns its id.
+#[tauri::command]
+pub fn save_report_definition(
+    app: AppHandle,
+    id: Option<String>,
+    definition: ReportDefinition,
+    user_id: String,
+) -> Result<String, String> {
+    let args = serde_json::json!({ "id": id, "definition": definition });
+    command_audit::audited(&app, "save_report_definition", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut definition = definition;
+        definition.name = definition.name.trim().to_string();
+        validate(&conn, &definition)?;
+        let json = serde_json::to_string(&definition).map_err(|e| e.to_string())?;
+        match id {
+            Some(id) => {
+                let changed = conn
+                    .execute(
+                        "UPDATE report_definitions
+                         SET name = ?2, definition = ?3, updated_by = ?4,
+                             updated_at = CURRENT_TIMESTAMP
+                         WHERE id = ?1",
+                        params![id, definition.name, json, user_id],
+                    )
+                    .map_err(|e| e.to_string())?;
+                match changed {
+                    0 => Err(format!("Report {} not found", id)),
+                    _ => Ok(id),
+                }
+            }
+            None => {
+                let id = uuid::Uuid::new_v4().to_string();
+                conn.execute(
+                    "INSERT INTO report_definitions (id, name, definition, created_by)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![id, definition.name, json, user_id],
+                )
+                .map_err(|e| e.to_string())?;
+                Ok(id)
+            }
+        }
+    })
+}
+
+#[tauri::command]
+pub fn delete_report_definition(app: AppHandle, id: String, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "id": id });
+    command_audit::audited(&app, "delete_report_definition", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        conn.execute("DELETE FROM report_definitions WHERE id = ?1", [&id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+}
+
+// Try a definition before saving it (admin only)
+#[tauri::command]
+pub fn preview_report_definition(
+    app: AppHandle,
+    definition: ReportDefinition,
+    range: Option<DateRange>,
+    limit: Option<usize>,
+    include_archives: Option<bool>,
+    user_id: String,
+) -> Result<ReportResult, String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let include = include_archives.unwrap_or(false);
+    with_archives(&app, &conn, &definition, include, |archives| {
+        run(
+            &conn,
+            &definition,
+            range.as_ref(),
+            limit,
+            archives,
+            &Masks::default(),
+        )
+    })
+}
+
+// A saved report's rows for the grid, `limit` at a time (1000 by default).
+// `include_archives` adds the archived weighments.
+#[tauri::command]
+pub fn run_report_definition(
+    app: AppHandle,
+    session_token: Option<String>,
+    id: String,
+    range: Option<DateRange>,
+    limit: Option<usize>,
+    include_archives: Option<bool>,
+) -> Result<ReportResult, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?.for_raw_sql();
+    let conn = db::open(&app)?;
+    let definition = load(&conn, &id)?;
+    let include = include_archives.unwrap_or(false);
+    with_archives(&app, &conn, &definition, include, |archives| {
+        run(&conn, &definition, range.as_ref(), limit, archives, &masks)
+    })
+}
+
+// Every row of a saved report to a .csv or .xlsx file, like export_query.
+// Returns the number of rows written.
+#[tauri::command]
+pub fn export_report_definition(
+    app: AppHandle,
+    session_token: Option<String>,
+    id: String,
+    path: String,
+    range: Option<DateRange>,
+    include_archives: Option<bool>,
+) -> Result<usize, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?.for_raw_sql();
+    let conn = db::open(&app)?;
+    write_report(
+        &app,
+        &conn,
+        &id,
+        &path,
+        range.as_ref(),
+        include_archives.unwrap_or(false),
+        &masks,
+    )
+}
+
+// Every row of the saved report `id` to a .csv or .xlsx file
+pub fn write_report(
+    app: &AppHandle,
+    conn: &Connection,
+    id: &str,
+    path: &str,
+    range: Option<&DateRange>,
+    include_archives: bool,
+    masks: &Masks,
+) -> Result<usize, String> {
+    let definition = load(conn, id)?;
+    with_archives(app, conn, &definition, include_archives, |archives| {
+        let sql = report_sql(&definition, range.is_some(), archives)?;
+        export::write_query(conn, &sql, path, range, masks, &mut |_| Ok(()))
+    })
+}
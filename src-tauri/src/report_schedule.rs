@@ -0,0 +1,372 @@
+// Scheduled report mail for Truckore Pro
+// A background thread mails the daily register once a day at the configured
+// local time: the summary PDF and the weighments CSV for today or
+// yesterday, built by the same code as the report and export commands. The
+// SMTP password is kept as the `smtp_password` secret. A run missed while
+// the app was closed happens as soon as it starts later that same day.
+// Each mail is logged in the communication log (communications.rs).
+
+use crate::backup_schedule;
+use crate::command_audit;
+use crate::communications::{self, Outbound, Request};
+use crate::db::{self, DateRange};
+use crate::export;
+use crate::formatting;
+use crate::license;
+use crate::masking::Masks;
+use crate::reports;
+use crate::roles::{self, Role};
+use crate::settings;
+use crate::shutdown;
+use crate::smtp::{self, Attachment, Message, SmtpSettings};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const SCHEDULE_CONFIG_KEY: &str = "report_schedule";
+const PASSWORD_SECRET: &str = "smtp_password";
+const LAST_RUN_CONFIG_KEY: &str = "report_last_run";
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Premium feature a license must cover (license.rs)
+const LICENSE_FEATURE: &str = "report_schedule";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportSchedule {
+    pub enabled: bool,
+    // Local time of day, "HH:MM"
+    pub time: String,
+    // Days to run on, 0 = Sunday .. 6 = Saturday; empty means every day
+    pub weekdays: Vec<u8>,
+    pub recipients: Vec<String>,
+    pub pdf: bool,
+    pub csv: bool,
+    // "today" for an end-of-day run, "yesterday" for a morning one
+    pub period: String,
+    pub smtp: SmtpSettings,
+}
+
+impl Default for ReportSchedule {
+    fn default() -> Self {
+        ReportSchedule {
+            enabled: false,
+            time: "20:00".to_string(),
+            weekdays: Vec::new(),
+            recipients: Vec::new(),
+            pdf: true,
+            csv: true,
+            period: "today".to_string(),
+            smtp: SmtpSettings::default(),
+        }
+    }
+}
+
+// Outcome of the latest scheduled run, also the payload of the
+// `report-sent` and `report-failed` events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledReport {
+    // Local date of the run, "YYYY-MM-DD"
+    pub date: String,
+    // Day the register covers
+    pub report_date: Option<String>,
+    pub recipients: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportScheduleStatus {
+    pub schedule: ReportSchedule,
+    pub password_stored: bool,
+    pub last_run: Option<ScheduledReport>,
+}
+
+pub fn load_schedule(conn: &Connection) -> Result<ReportSchedule, String> {
+    match db::get_config(conn, SCHEDULE_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(ReportSchedule::default()),
+    }
+}
+
+// The SMTP settings with the stored password unsealed
+pub fn smtp_settings(
+    app: &AppHandle,
+    conn: &Connection,
+    schedule: &ReportSchedule,
+) -> Result<SmtpSettings, String> {
+    let mut settings = schedule.smtp.clone();
+    settings.password = settings::secret(app, conn, PASSWORD_SECRET)?;
+    Ok(settings)
+}
+
+fn last_run(conn: &Connection) -> Result<Option<ScheduledReport>, String> {
+    db::get_config(conn, LAST_RUN_CONFIG_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+fn validate(schedule: &ReportSchedule) -> Result<(), String> {
+    if !backup_schedule::valid_time(&schedule.time) {
+        return Err(format!("Report time must be HH:MM, got {}", schedule.time));
+    }
+    if let Some(day) = schedule.weekdays.iter().find(|d| **d > 6) {
+        return Err(format!(
+            "Unknown weekday {} (0 = Sunday .. 6 = Saturday)",
+            day
+        ));
+    }
+    if !matches!(schedule.period.as_str(), "today" | "yesterday") {
+        return Err(format!(
+            "Report period must be today or yesterday, got {}",
+            schedule.period
+        ));
+    }
+    for address in &schedule.recipients {
+        smtp::check_address(address)?;
+    }
+    if schedule.enabled {
+        if schedule.recipients.is_empty() {
+            return Err("The report needs at least one recipient".to_string());
+        }
+        if !schedule.pdf && !schedule.csv {
+            return Err("Choose the PDF summary, the CSV register or both".to_string());
+        }
+        smtp::validate(&schedule.smtp)?;
+    }
+    Ok(())
+}
+
+// Today's local date when a report is due now, None otherwise
+fn due(conn: &Connection, schedule: &ReportSchedule) -> Result<Option<String>, String> {
+    if !schedule.enabled {
+        return Ok(None);
+    }
+    let last = last_run(conn)?;
+    backup_schedule::due_today(
+        conn,
+        &schedule.time,
+        &schedule.weekdays,
+        last.as_ref().map(|run| run.date.as_str()),
+    )
+}
+
+// The register as a CSV, through a temporary file since the exporter writes
+// to a path
+fn register_csv(conn: &Connection, range: &DateRange) -> Result<Vec<u8>, String> {
+    let path = std::env::temp_dir().join(format!(
+        "truckore-register-{}.csv",
+        uuid::Uuid::new_v4().simple()
+    ));
+    let path_text = path.to_string_lossy().to_string();
+    let written = export::write_query(
+        conn,
+        "weighments",
+        &path_text,
+        Some(range),
+        &Masks::default(),
+        &mut |_| Ok(()),
+    )
+    .and_then(|_| fs::read(&path).map_err(|e| e.to_string()));
+    let _ = fs::remove_file(&path);
+    written
+}
+
+fn run_report(
+    app: &AppHandle,
+    schedule: &ReportSchedule,
+    date: &str,
+) -> Result<ScheduledReport, String> {
+    let conn = db::open(app)?;
+    let report_date: String = conn
+        .query_row(
+            "SELECT CASE ?2 WHEN 'yesterday' THEN date(?1, '-1 day') ELSE ?1 END",
+            [date, &schedule.period],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    mail_register(app, &conn, schedule, &report_date)?;
+    Ok(ScheduledReport {
+        date: date.to_string(),
+        report_date: Some(report_date),
+        recipients: schedule.recipients.clone(),
+        error: None,
+    })
+}
+
+// Mail the register for `report_date` to the schedule's recipients through
+// its server, with the attachments it asks for, and log it
+pub fn mail_register(
+    app: &AppHandle,
+    conn: &Connection,
+    schedule: &ReportSchedule,
+    report_date: &str,
+) -> Result<(), String> {
+    let outbound = Outbound {
+        channel: "EMAIL",
+        destination: schedule.recipients.join(", "),
+        summary: format!("Weighment register for {}", report_date),
+        weighment_id: None,
+        party_name: None,
+        request: Some(Request::Register {
+            report_date: report_date.to_string(),
+        }),
+        created_by: None,
+    };
+    communications::logged(conn, outbound, || {
+        send_register(app, conn, schedule, report_date)
+    })
+}
+
+// The register mail itself; mail_register logs it
+pub fn send_register(
+    app: &AppHandle,
+    conn: &Connection,
+    schedule: &ReportSchedule,
+    report_date: &str,
+) -> Result<(), String> {
+    let range = DateRange {
+        from: report_date.to_string(),
+        to: report_date.to_string(),
+    };
+    let summary = reports::summary(conn, range.clone())?;
+    let format = formatting::load(conn)?;
+    let mut attachments = Vec::new();
+    if schedule.pdf {
+        attachments.push(Attachment {
+            filename: format!("summary-{}.pdf", report_date),
+            content_type: "application/pdf".to_string(),
+            data: reports::summary_pdf(&summary, &format),
+        });
+    }
+    if schedule.csv {
+        attachments.push(Attachment {
+            filename: format!("register-{}.csv", report_date),
+            content_type: "text/csv; charset=utf-8".to_string(),
+            data: register_csv(conn, &range)?,
+        });
+    }
+    let message = Message {
+        to: schedule.recipients.clone(),
+        subject: format!("Weighment register for {}", report_date),
+        body: format!(
+            "Weighment register for {}\r\n\r\nTickets: {}\r\nNet weight: {}\r\n",
+            formatting::date(report_date, &format),
+            summary.total.tickets,
+            formatting::weight(summary.total.net_weight_kg, &format)
+        ),
+        attachments,
+    };
+    smtp::send(conn, &smtp_settings(app, conn, schedule)?, &message)
+}
+
+// Send the report when due, record the outcome and tell the windows
+fn tick(app: &AppHandle) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let schedule = load_schedule(&conn)?;
+    let Some(date) = due(&conn, &schedule)? else {
+        return Ok(());
+    };
+    drop(conn);
+
+    // A lapsed license is recorded as the day's failure
+    let run =
+        license::covers(app, LICENSE_FEATURE).and_then(|()| run_report(app, &schedule, &date));
+    let (event, outcome) = match run {
+        Ok(run) => ("report-sent", run),
+        Err(error) => (
+            "report-failed",
+            ScheduledReport {
+                date,
+                report_date: None,
+                recipients: schedule.recipients.clone(),
+                error: Some(error),
+            },
+        ),
+    };
+    // Recorded on failure too, so a broken mail server is not retried every tick
+    let conn = db::open(app)?;
+    let json = serde_json::to_string(&outcome).map_err(|e| e.to_string())?;
+    db::set_config(&conn, LAST_RUN_CONFIG_KEY, &json)?;
+    let _ = app.emit_all(event, &outcome);
+    Ok(())
+}
+
+// Check the schedule in the background for the life of the app
+pub fn start_scheduler(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            let _ = tick(&app);
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_report_schedule(app: AppHandle) -> Result<ReportScheduleStatus, String> {
+    let conn = db::open(&app)?;
+    Ok(ReportScheduleStatus {
+        schedule: load_schedule(&conn)?,
+        password_stored: settings::has_secret(&conn, PASSWORD_SECRET)?,
+        last_run: last_run(&conn)?,
+    })
+}
+
+// Supervisors only. An empty password clears the stored one.
+#[tauri::command]
+pub fn set_report_schedule(
+    app: AppHandle,
+    schedule: ReportSchedule,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&schedule).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_report_schedule", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut schedule = schedule;
+        schedule.smtp.host = schedule.smtp.host.trim().to_string();
+        schedule.smtp.username = schedule.smtp.username.trim().to_string();
+        validate(&schedule)?;
+        match schedule.smtp.password.as_deref() {
+            Some("") => settings::delete_secret_value(&conn, PASSWORD_SECRET)?,
+            Some(password) => settings::set_secret_value(&app, &conn, PASSWORD_SECRET, password)?,
+            None => {}
+        }
+        let json = serde_json::to_string(&schedule).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SCHEDULE_CONFIG_KEY, &json)
+    })
+}
+
+// Check the stored SMTP settings with a short message to `to` (supervisors only)
+#[tauri::command]
+pub async fn send_test_email(app: AppHandle, to: String, user_id: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let args = serde_json::json!({ "to": to });
+        command_audit::audited(&app, "send_test_email", &user_id, args, || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            let schedule = load_schedule(&conn)?;
+            let message = Message {
+                to: vec![to.trim().to_string()],
+                subject: "Truckore Pro test message".to_string(),
+                body: "The report schedule can send mail through this server.\r\n".to_string(),
+                attachments: Vec::new(),
+            };
+            let outbound = Outbound {
+                channel: "EMAIL",
+                destination: to.trim().to_string(),
+                summary: message.subject.clone(),
+                weighment_id: None,
+                party_name: None,
+                request: None,
+                created_by: Some(user_id.clone()),
+            };
+            communications::logged(&conn, outbound, || {
+                smtp::send(&conn, &smtp_settings(&app, &conn, &schedule)?, &message)
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,302 @@
+// PDF reports for Truckore Pro
+// Single weighment slips and date-range summaries with totals per customer
+// and per material, and the weighings taken outside the working calendar
+// (calendar.rs), rendered with the built-in PDF writer so the layout does
+// not depend on the webview's print dialog. Summaries follow the caller's
+// masking rules (masking.rs): masked charges leave the amounts out, and
+// masked names and vehicle numbers are masked in every table.
+
+use crate::auditor;
+use crate::calendar::{self, OutOfHoursActivity};
+use crate::db::{self, DateRange};
+use crate::driver_signatures;
+use crate::formatting::{self, NumberFormat};
+use crate::masking::{self, Masks};
+use crate::pdf::{self, Line, PdfOutput};
+use crate::scripting;
+use crate::slip_layout;
+use crate::ticket_qr;
+use crate::training;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// Largest signature on a PDF slip, in dots
+const SLIP_SIGNATURE_MAX: (usize, usize) = (240, 80);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummaryTotal {
+    pub name: String,
+    pub tickets: i64,
+    pub net_weight_kg: f64,
+    pub amount_minor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WeighmentSummary {
+    pub range: DateRange,
+    pub by_customer: Vec<SummaryTotal>,
+    pub by_material: Vec<SummaryTotal>,
+    pub total: SummaryTotal,
+    pub out_of_hours: Vec<OutOfHoursActivity>,
+    // Amounts are zero and printed as "-" when charges are masked
+    #[serde(default)]
+    pub amounts_masked: bool,
+}
+
+// Closed, non-void, non-practice tickets in the range, grouped by `group`
+pub(crate) fn totals(
+    conn: &Connection,
+    group: &str,
+    range: &DateRange,
+) -> Result<Vec<SummaryTotal>, String> {
+    let sql = format!(
+        "SELECT {group}, COUNT(*), COALESCE(SUM(w.net_weight), 0), COALESCE(SUM(w.charges_minor), 0)
+         FROM weighments w
+         WHERE w.net_weight IS NOT NULL AND {} BETWEEN ?1 AND ?2
+           AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}
+         GROUP BY {group}
+         ORDER BY SUM(w.net_weight) DESC",
+        db::local_date("w.created_at"),
+        training::exclude_practice("w.id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok(SummaryTotal {
+                name: row.get(0)?,
+                tickets: row.get(1)?,
+                net_weight_kg: row.get(2)?,
+                amount_minor: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+pub(crate) fn summary(conn: &Connection, range: DateRange) -> Result<WeighmentSummary, String> {
+    let by_customer = totals(conn, "w.party_name", &range)?;
+    let by_material = totals(conn, "w.product_name", &range)?;
+    let total = SummaryTotal {
+        name: "Total".to_string(),
+        tickets: by_customer.iter().map(|t| t.tickets).sum(),
+        net_weight_kg: by_customer.iter().map(|t| t.net_weight_kg).sum(),
+        amount_minor: by_customer.iter().map(|t| t.amount_minor).sum(),
+    };
+    let out_of_hours = calendar::activity(conn, &range)?;
+    Ok(WeighmentSummary {
+        range,
+        by_customer,
+        by_material,
+        total,
+        out_of_hours,
+        amounts_masked: false,
+    })
+}
+
+// `text` as `column` is masked for the caller
+fn mask_text(masks: &Masks, column: &str, text: &mut String) {
+    if let Some(mode) = masks.mode(column) {
+        *text = match masking::masked_cell(mode, ValueRef::Text(text.as_bytes())) {
+            SqlValue::Text(masked) => masked,
+            _ => "*****".to_string(),
+        };
+    }
+}
+
+fn mask_summary(summary: &mut WeighmentSummary, masks: &Masks) {
+    if masks.is_empty() {
+        return;
+    }
+    if masks.mode("charges_minor").is_some() || masks.mode("amount").is_some() {
+        summary.amounts_masked = true;
+        for total in summary
+            .by_customer
+            .iter_mut()
+            .chain(summary.by_material.iter_mut())
+            .chain(std::iter::once(&mut summary.total))
+        {
+            total.amount_minor = 0;
+        }
+    }
+    for total in summary.by_customer.iter_mut() {
+        mask_text(masks, "party_name", &mut total.name);
+    }
+    for total in summary.by_material.iter_mut() {
+        mask_text(masks, "product_name", &mut total.name);
+    }
+    for activity in summary.out_of_hours.iter_mut() {
+        mask_text(masks, "ticket_no", &mut activity.ticket_no);
+        mask_text(masks, "vehicle_no", &mut activity.vehicle_no);
+    }
+}
+
+// An amount for the summary PDF, "-" when amounts are masked
+fn summary_amount(amount_minor: i64, masked: bool, format: &NumberFormat) -> String {
+    match masked {
+        true => "-".to_string(),
+        false => formatting::amount(amount_minor, format),
+    }
+}
+
+// `qr` is drawn beside the fields, from the title down; `signature` closes
+// the slip
+fn slip_pdf(
+    values: &slip_layout::SlipValues,
+    qr: Option<Vec<Vec<bool>>>,
+    signature: Option<Vec<Vec<bool>>>,
+) -> Vec<u8> {
+    let mut title = Line::bold("WEIGHMENT SLIP");
+    if let Some(modules) = qr {
+        title = title.with_qr(modules);
+    }
+    let mut lines = vec![title];
+    if values.practice {
+        lines.push(Line::bold("PRACTICE - NOT A VALID TICKET"));
+    }
+    if values.audit_view {
+        lines.push(Line::bold("AUDIT VIEW"));
+    }
+    lines.push(Line::plain(""));
+    for (_, label, value) in &values.fields {
+        lines.push(Line::plain(format!("{:<12} {}", label, value)));
+    }
+    if !values.steps.is_empty() {
+        lines.push(Line::plain(""));
+        lines.push(Line::bold("Weighings"));
+        for (label, weight) in &values.steps {
+            lines.push(Line::plain(format!("{:<24} {}", label, weight)));
+        }
+    }
+    if let Some(dots) = signature {
+        let height = dots.len() as f64 * pdf::SIGNATURE_DOT;
+        lines.push(Line::plain(""));
+        lines.push(Line::bold("Driver signature").with_signature(dots));
+        // Room for the signature below the heading
+        lines.extend((0..(height / 12.0).ceil() as usize).map(|_| Line::plain("")));
+    }
+    pdf::render(&lines)
+}
+
+fn total_table(
+    lines: &mut Vec<Line>,
+    heading: &str,
+    rows: &[SummaryTotal],
+    amounts_masked: bool,
+    format: &NumberFormat,
+) {
+    let rule = "-".repeat(76);
+    lines.push(Line::bold(format!(
+        "{:<34} {:>8} {:>16} {:>16}",
+        heading, "Tickets", "Net", "Amount"
+    )));
+    lines.push(Line::plain(rule));
+    for row in rows {
+        let name: String = row.name.chars().take(34).collect();
+        lines.push(Line::plain(format!(
+            "{:<34} {:>8} {:>16} {:>16}",
+            name,
+            row.tickets,
+            formatting::weight(row.net_weight_kg, format),
+            summary_amount(row.amount_minor, amounts_masked, format)
+        )));
+    }
+    lines.push(Line::plain(""));
+}
+
+pub(crate) fn summary_pdf(summary: &WeighmentSummary, format: &NumberFormat) -> Vec<u8> {
+    let mut lines = vec![
+        Line::bold("WEIGHMENT SUMMARY"),
+        Line::plain(format!(
+            "Period: {} to {}",
+            formatting::date(&summary.range.from, format),
+            formatting::date(&summary.range.to, format)
+        )),
+        Line::plain(""),
+    ];
+    let masked = summary.amounts_masked;
+    total_table(&mut lines, "Customer", &summary.by_customer, masked, format);
+    total_table(&mut lines, "Material", &summary.by_material, masked, format);
+    lines.push(Line::bold(format!(
+        "{:<34} {:>8} {:>16} {:>16}",
+        summary.total.name,
+        summary.total.tickets,
+        formatting::weight(summary.total.net_weight_kg, format),
+        summary_amount(summary.total.amount_minor, masked, format)
+    )));
+    if !summary.out_of_hours.is_empty() {
+        lines.push(Line::plain(""));
+        lines.push(Line::bold("Out-of-hours activity"));
+        lines.push(Line::plain("-".repeat(76)));
+        for activity in &summary.out_of_hours {
+            let at: String = formatting::date(&activity.occurred_at, format)
+                .chars()
+                .take(16)
+                .collect();
+            let ticket: String = activity.ticket_no.chars().take(12).collect();
+            let vehicle: String = activity.vehicle_no.chars().take(12).collect();
+            let weighing = match activity.event.as_str() {
+                calendar::FIRST_WEIGHT => "First",
+                _ => "Second",
+            };
+            lines.push(Line::plain(format!(
+                "{:<16} {:<12} {:<12} {:<7} {:<14} {}",
+                at, ticket, vehicle, weighing, activity.reason, activity.outcome
+            )));
+        }
+    }
+    pdf::render(&lines)
+}
+
+// Slip for one ticket with its gate QR code, written to `dest_path` or returned base64 encoded
+#[tauri::command]
+pub fn weighment_slip_pdf(
+    app: AppHandle,
+    ticket_id: String,
+    dest_path: Option<String>,
+    session_token: Option<String>,
+) -> Result<PdfOutput, String> {
+    let conn = db::open(&app)?;
+    let mut values = slip_layout::load_values(&conn, &ticket_id)?;
+    values.audit_view = auditor::session_auditor(&app, session_token.as_deref())?.is_some();
+    scripting::before_print(&conn, &ticket_id, &mut values.fields)?;
+    let qr = match ticket_qr::payload(&conn, "id", &ticket_id)? {
+        Some((_, payload)) => Some(ticket_qr::modules(&ticket_qr::encode(&payload)?)),
+        None => None,
+    };
+    let signature =
+        driver_signatures::for_print(&app, &conn, "id", &ticket_id, SLIP_SIGNATURE_MAX)?;
+    pdf::deliver(slip_pdf(&values, qr, signature), dest_path)
+}
+
+#[tauri::command]
+pub fn weighment_summary(
+    app: AppHandle,
+    range: DateRange,
+    session_token: Option<String>,
+) -> Result<WeighmentSummary, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?;
+    let conn = db::open(&app)?;
+    let mut summary = summary(&conn, range)?;
+    mask_summary(&mut summary, &masks);
+    Ok(summary)
+}
+
+// Totals per customer and material, written to `dest_path` or returned
+// base64 encoded
+#[tauri::command]
+pub fn weighment_summary_pdf(
+    app: AppHandle,
+    range: DateRange,
+    dest_path: Option<String>,
+    session_token: Option<String>,
+) -> Result<PdfOutput, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?;
+    let conn = db::open(&app)?;
+    let mut summary = summary(&conn, range)?;
+    mask_summary(&mut summary, &masks);
+    pdf::deliver(summary_pdf(&summary, &formatting::load(&conn)?), dest_path)
+}
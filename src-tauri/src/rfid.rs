@@ -0,0 +1,433 @@
+// RFID and proximity card readers for Truckore Pro
+// Regular trucks carry a tag, so the operator need not type the vehicle
+// number. A serial reader (a Wiegand-to-serial converter, or an EM4100
+// module such as the RDM6300) is read by a background listener started
+// from the reader settings; a USB reader that types like a keyboard sends
+// what it typed with submit_rfid_scan instead. Either way each tag read is
+// looked up in vehicle_tags (migration 0009) and emitted as an
+// `rfid-scanned` event carrying the tag and, when it is bound, the vehicle.
+// A reader repeats a tag for as long as it stays in the field, so repeats
+// within a few seconds are dropped.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const READER_CONFIG_KEY: &str = "rfid_reader";
+const FORMATS: &[&str] = &["line", "em4100"];
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+// The same tag read again within this long is the card still in the field
+const REPEAT_WINDOW: Duration = Duration::from_secs(3);
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+// Longest frame kept; longer input is noise
+const MAX_FRAME: usize = 64;
+
+fn default_baud_rate() -> u32 {
+    9600
+}
+
+fn default_format() -> String {
+    "line".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RfidReader {
+    #[serde(default)]
+    pub enabled: bool,
+    pub port: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    // "line": one tag per line, or between STX and ETX, as sent.
+    // "em4100": ten hex digits and an XOR checksum between STX and ETX.
+    #[serde(default = "default_format")]
+    pub format: String,
+    // Lane the reader sits at, passed on with each scan
+    pub lane_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RfidScan {
+    pub tag: String,
+    // "serial" or "keyboard"
+    pub source: String,
+    pub lane_id: Option<String>,
+    // None when the tag is not bound to a vehicle
+    pub vehicle_id: Option<String>,
+    pub vehicle_no: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VehicleTag {
+    pub tag: String,
+    pub vehicle_id: String,
+    pub vehicle_no: Option<String>,
+    pub bound_by: Option<String>,
+    pub bound_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RfidStatus {
+    port: String,
+    state: String,
+    error: Option<String>,
+}
+
+struct Listener {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+// Managed state: the running serial listener and the last tag seen
+#[derive(Default)]
+pub struct RfidListener {
+    listener: Mutex<Option<Listener>>,
+    last: Mutex<Option<(String, Instant)>>,
+}
+
+impl RfidListener {
+    fn stop(&self) {
+        let listener = self.listener.lock().ok().and_then(|mut l| l.take());
+        if let Some(listener) = listener {
+            listener.stop.store(true, Ordering::SeqCst);
+            let _ = listener.thread.join();
+        }
+    }
+
+    // Whether `tag` is a new read rather than a repeat
+    fn fresh(&self, tag: &str) -> bool {
+        let Ok(mut last) = self.last.lock() else {
+            return true;
+        };
+        let now = Instant::now();
+        let repeat = matches!(&*last, Some((seen, at)) if seen == tag && now - *at < REPEAT_WINDOW);
+        *last = Some((tag.to_string(), now));
+        !repeat
+    }
+}
+
+fn load_reader(conn: &Connection) -> Result<Option<RfidReader>, String> {
+    db::get_config(conn, READER_CONFIG_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+// Tags are compared without spaces or separators, in upper case
+pub(crate) fn normalize_tag(tag: &str) -> String {
+    tag.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+// The tag in one frame from the reader, None when it is not a valid read
+fn parse_frame(format: &str, frame: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(frame);
+    let tag = normalize_tag(&text);
+    match format {
+        "em4100" => {
+            if tag.len() != 12 || !tag.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            let bytes: Vec<u8> = (0..6)
+                .map(|i| u8::from_str_radix(&tag[i * 2..i * 2 + 2], 16).unwrap_or(0))
+                .collect();
+            let checksum = bytes[..5].iter().fold(0, |acc, b| acc ^ b);
+            (checksum == bytes[5]).then(|| tag[..10].to_string())
+        }
+        _ => (!tag.is_empty()).then_some(tag),
+    }
+}
+
+fn lookup(conn: &Connection, tag: &str) -> Result<Option<(String, Option<String>)>, String> {
+    conn.query_row(
+        "SELECT t.vehicle_id, v.vehicle_no FROM vehicle_tags t
+         LEFT JOIN vehicles v ON v.id = t.vehicle_id
+         WHERE t.tag = ?1",
+        [tag],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Look the tag up and tell the windows; None for a repeated read
+fn scanned(
+    app: &AppHandle,
+    tag: &str,
+    source: &str,
+    lane_id: Option<String>,
+) -> Result<Option<RfidScan>, String> {
+    if !app.state::<RfidListener>().fresh(tag) {
+        return Ok(None);
+    }
+    let conn = db::open(app)?;
+    let vehicle = lookup(&conn, tag)?;
+    let scan = RfidScan {
+        tag: tag.to_string(),
+        source: source.to_string(),
+        lane_id,
+        vehicle_id: vehicle.as_ref().map(|(id, _)| id.clone()),
+        vehicle_no: vehicle.and_then(|(_, no)| no),
+    };
+    tracing::info!(tag, vehicle = ?scan.vehicle_no, "RFID tag read");
+    let _ = app.emit_all("rfid-scanned", &scan);
+    Ok(Some(scan))
+}
+
+fn status(app: &AppHandle, port: &str, state: &str, error: Option<String>) {
+    if let Some(e) = &error {
+        tracing::warn!(port, state, error = %e, "RFID reader status");
+    }
+    let status = RfidStatus {
+        port: port.to_string(),
+        state: state.to_string(),
+        error,
+    };
+    let _ = app.emit_all("rfid-status", &status);
+}
+
+fn listen(app: AppHandle, reader: RfidReader, stop: Arc<AtomicBool>) {
+    let stopped = || stop.load(Ordering::SeqCst) || shutdown::requested();
+    let mut backoff = RECONNECT_MIN;
+    while !stopped() {
+        let mut port = match serialport::new(&reader.port, reader.baud_rate)
+            .timeout(READ_TIMEOUT)
+            .open()
+        {
+            Ok(port) => port,
+            Err(e) => {
+                status(&app, &reader.port, "disconnected", Some(e.to_string()));
+                let retry_at = Instant::now() + backoff;
+                while Instant::now() < retry_at && !stopped() {
+                    std::thread::sleep(READ_TIMEOUT);
+                }
+                backoff = (backoff * 2).min(RECONNECT_MAX);
+                continue;
+            }
+        };
+        backoff = RECONNECT_MIN;
+        status(&app, &reader.port, "connected", None);
+        let mut frame = Vec::new();
+        let mut buf = [0u8; 64];
+        while !stopped() {
+            let read = match port.read(&mut buf) {
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => 0,
+                Err(e) => {
+                    status(&app, &reader.port, "disconnected", Some(e.to_string()));
+                    break;
+                }
+            };
+            for &byte in &buf[..read] {
+                match byte {
+                    STX => frame.clear(),
+                    ETX | b'\r' | b'\n' => {
+                        if let Some(tag) = parse_frame(&reader.format, &frame) {
+                            if let Err(e) = scanned(&app, &tag, "serial", reader.lane_id.clone()) {
+                                tracing::warn!(error = %e, "RFID lookup failed");
+                            }
+                        }
+                        frame.clear();
+                    }
+                    _ if frame.len() < MAX_FRAME => frame.push(byte),
+                    _ => frame.clear(),
+                }
+            }
+        }
+    }
+    status(&app, &reader.port, "stopped", None);
+}
+
+fn start(app: &AppHandle, reader: Option<RfidReader>) {
+    let state = app.state::<RfidListener>();
+    state.stop();
+    let Some(reader) = reader.filter(|r| r.enabled) else {
+        return;
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = {
+        let app = app.clone();
+        let stop = stop.clone();
+        std::thread::spawn(move || listen(app, reader, stop))
+    };
+    if let Ok(mut listener) = state.listener.lock() {
+        *listener = Some(Listener { stop, thread });
+    };
+}
+
+// Start the reader from its settings, if it is enabled; called from setup
+pub fn start_from_settings(app: &AppHandle) {
+    let reader = db::open(app).and_then(|conn| load_reader(&conn));
+    match reader {
+        Ok(reader) => start(app, reader),
+        Err(e) => tracing::warn!(error = %e, "RFID reader settings unavailable"),
+    }
+}
+
+#[tauri::command]
+pub fn get_rfid_reader(app: AppHandle) -> Result<Option<RfidReader>, String> {
+    let conn = db::open(&app)?;
+    load_reader(&conn)
+}
+
+// Configure the serial reader (admins); the listener restarts with it
+#[tauri::command]
+pub fn set_rfid_reader(app: AppHandle, reader: RfidReader, user_id: String) -> Result<(), String> {
+    let args = serde_json::to_value(&reader).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_rfid_reader", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if reader.port.trim().is_empty() {
+            return Err("RFID reader port is required".to_string());
+        }
+        if !FORMATS.contains(&reader.format.as_str()) {
+            return Err(format!("Unknown RFID frame format: {}", reader.format));
+        }
+        let json = serde_json::to_string(&reader).map_err(|e| e.to_string())?;
+        db::set_config(&conn, READER_CONFIG_KEY, &json)?;
+        start(&app, Some(reader.clone()));
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn stop_rfid_reader(listener: State<'_, RfidListener>) -> Result<(), String> {
+    listener.stop();
+    Ok(())
+}
+
+// A tag typed by a keyboard-wedge reader; emitted like a serial read.
+// None when it repeats the last read.
+#[tauri::command]
+pub fn submit_rfid_scan(
+    app: AppHandle,
+    tag: String,
+    lane_id: Option<String>,
+) -> Result<Option<RfidScan>, String> {
+    let tag = normalize_tag(&tag);
+    if tag.is_empty() {
+        return Err("Empty RFID tag".to_string());
+    }
+    scanned(&app, &tag, "keyboard", lane_id)
+}
+
+// Bind a tag to a vehicle, replacing any earlier binding of the tag
+// (supervisors only)
+#[tauri::command]
+pub fn bind_tag_to_vehicle(
+    app: AppHandle,
+    tag: String,
+    vehicle_id: String,
+    user_id: String,
+) -> Result<VehicleTag, String> {
+    let args = serde_json::json!({ "tag": tag, "vehicle_id": vehicle_id });
+    command_audit::audited(&app, "bind_tag_to_vehicle", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let tag = normalize_tag(&tag);
+        if tag.is_empty() {
+            return Err("Empty RFID tag".to_string());
+        }
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM vehicles WHERE id = ?1)",
+                [&vehicle_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            return Err(format!("Unknown vehicle {}", vehicle_id));
+        }
+        conn.execute(
+            "INSERT INTO vehicle_tags (tag, vehicle_id, bound_by) VALUES (?1, ?2, ?3)
+             ON CONFLICT(tag) DO UPDATE SET
+                 vehicle_id = excluded.vehicle_id,
+                 bound_by = excluded.bound_by,
+                 bound_at = CURRENT_TIMESTAMP",
+            params![tag, vehicle_id, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        find_tag(&conn, &tag)?.ok_or_else(|| format!("Unknown tag {}", tag))
+    })
+}
+
+#[tauri::command]
+pub fn unbind_tag(app: AppHandle, tag: String, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "tag": tag });
+    command_audit::audited(&app, "unbind_tag", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let removed = conn
+            .execute(
+                "DELETE FROM vehicle_tags WHERE tag = ?1",
+                [normalize_tag(&tag)],
+            )
+            .map_err(|e| e.to_string())?;
+        match removed {
+            0 => Err(format!("Unknown tag {}", tag)),
+            _ => Ok(()),
+        }
+    })
+}
+
+const TAG_QUERY: &str = "SELECT t.tag, t.vehicle_id, v.vehicle_no, t.bound_by, t.bound_at
+     FROM vehicle_tags t LEFT JOIN vehicles v ON v.id = t.vehicle_id";
+
+fn row_to_tag(row: &rusqlite::Row) -> rusqlite::Result<VehicleTag> {
+    Ok(VehicleTag {
+        tag: row.get(0)?,
+        vehicle_id: row.get(1)?,
+        vehicle_no: row.get(2)?,
+        bound_by: row.get(3)?,
+        bound_at: row.get(4)?,
+    })
+}
+
+fn find_tag(conn: &Connection, tag: &str) -> Result<Option<VehicleTag>, String> {
+    conn.query_row(
+        &format!("{} WHERE t.tag = ?1", TAG_QUERY),
+        [tag],
+        row_to_tag,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// The binding of a tag, if any
+#[tauri::command]
+pub fn lookup_tag(app: AppHandle, tag: String) -> Result<Option<VehicleTag>, String> {
+    let conn = db::open(&app)?;
+    find_tag(&conn, &normalize_tag(&tag))
+}
+
+// Tags bound to a vehicle, or every binding
+#[tauri::command]
+pub fn list_vehicle_tags(
+    app: AppHandle,
+    vehicle_id: Option<String>,
+) -> Result<Vec<VehicleTag>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} WHERE ?1 IS NULL OR t.vehicle_id = ?1 ORDER BY v.vehicle_no, t.tag",
+            TAG_QUERY
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([vehicle_id], row_to_tag)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,64 @@
+// User roles for backend permission checks
+// Mirrors the users.role column: operator < admin (supervisor) < super_admin
+
+use rusqlite::{Connection, OptionalExtension};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    // Read-only inspection, see auditor.rs; ranks below operators
+    Auditor,
+    Operator,
+    Admin,
+    SuperAdmin,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Result<Role, String> {
+        match value {
+            "auditor" => Ok(Role::Auditor),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            "super_admin" => Ok(Role::SuperAdmin),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Auditor => "auditor",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+            Role::SuperAdmin => "super_admin",
+        }
+    }
+}
+
+// Look up the role of an active user
+pub fn user_role(conn: &Connection, user_id: &str) -> Result<Role, String> {
+    let role: Option<String> = conn
+        .query_row(
+            "SELECT role FROM users WHERE id = ?1 AND is_active = 1",
+            [user_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    match role {
+        Some(role) => Role::parse(&role),
+        None => Err(format!("Unknown or inactive user: {}", user_id)),
+    }
+}
+
+// Fail unless the user holds at least `min` role. Supervisor actions use Role::Admin.
+pub fn require_role(conn: &Connection, user_id: &str, min: Role) -> Result<(), String> {
+    let role = user_role(conn, user_id)?;
+    if role < min {
+        return Err(format!(
+            "{}: requires {} role",
+            crate::command_audit::DENIED_PREFIX,
+            min.as_str()
+        ));
+    }
+    Ok(())
+}
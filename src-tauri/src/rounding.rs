@@ -0,0 +1,115 @@
+// Rounding rules for Truckore Pro
+// Weights and amounts are rounded here, once, by the capture and charge
+// engines so every slip, bill and report agrees with the stored figure.
+
+use crate::command_audit;
+use crate::db;
+use crate::money::{half_up, snap};
+use crate::roles::{self, Role};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const RULES_CONFIG_KEY: &str = "rounding_rules";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightMode {
+    Nearest,
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountMode {
+    // Half-up to the paisa
+    Paisa,
+    // Half-up to the rupee
+    NearestRupee,
+    // Paise dropped
+    TruncatePaisa,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoundingRules {
+    // Weights are multiples of this many kg (1, 5, 10, 20, ...)
+    pub weight_step_kg: f64,
+    pub weight_mode: WeightMode,
+    pub amount_mode: AmountMode,
+}
+
+impl Default for RoundingRules {
+    fn default() -> Self {
+        RoundingRules {
+            weight_step_kg: 1.0,
+            weight_mode: WeightMode::Nearest,
+            amount_mode: AmountMode::Paisa,
+        }
+    }
+}
+
+impl RoundingRules {
+    pub fn weight(&self, kg: f64) -> f64 {
+        let steps = snap(kg / self.weight_step_kg);
+        let steps = match self.weight_mode {
+            WeightMode::Nearest => half_up(steps),
+            WeightMode::Down => steps.floor(),
+            WeightMode::Up => steps.ceil(),
+        };
+        steps * self.weight_step_kg
+    }
+
+    // Round an amount given in (possibly fractional) paise to whole paise
+    pub fn amount(&self, minor: f64) -> i64 {
+        let minor = snap(minor);
+        let rounded = match self.amount_mode {
+            AmountMode::Paisa => half_up(minor),
+            AmountMode::NearestRupee => half_up(minor / 100.0) * 100.0,
+            AmountMode::TruncatePaisa => (minor / 100.0).trunc() * 100.0,
+        };
+        rounded as i64
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let step = self.weight_step_kg;
+        if step < 1.0 || step.fract() != 0.0 {
+            return Err(format!(
+                "Weight step must be a whole number of kg, got {}",
+                step
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn load_rules(conn: &Connection) -> Result<RoundingRules, String> {
+    match db::get_config(conn, RULES_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(RoundingRules::default()),
+    }
+}
+
+#[tauri::command]
+pub fn get_rounding_rules(app: AppHandle) -> Result<RoundingRules, String> {
+    let conn = db::open(&app)?;
+    load_rules(&conn)
+}
+
+// Replace the site's rounding rules (admin only); affects tickets completed afterwards
+#[tauri::command]
+pub fn set_rounding_rules(
+    app: AppHandle,
+    rules: RoundingRules,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&rules).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_rounding_rules", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        rules.validate()?;
+        let json = serde_json::to_string(&rules).map_err(|e| e.to_string())?;
+        db::set_config(&conn, RULES_CONFIG_KEY, &json)
+    })
+}
@@ -0,0 +1,241 @@
+// Per-command execution metrics for Truckore Pro
+// Counts, failures and a latency histogram for every command since the app
+// started, so support can see which operations are slow or failing at a
+// site. Tauri pairs a reply with its invoke only by callback id: the IPC
+// script below is Tauri's own with the id copied into the arguments, the
+// guard (authorization.rs) starts the clock under it, and the responder
+// stops it when the reply goes back to the page.
+
+use crate::errors;
+use crate::messages;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::api::ipc::{format_callback, format_callback_result, CallbackFn};
+use tauri::{InvokeMessage, InvokeResponse, Manager, State, Window, Wry};
+
+// Tauri's default __TAURI_POST_MESSAGE__, plus __invokeCallback
+pub const IPC_SCRIPT: &str = "Object.defineProperty(window, '__TAURI_POST_MESSAGE__', {
+  value: (message) => {
+    message.__invokeCallback = message.callback;
+    window.ipc.postMessage(JSON.stringify(message, (_k, val) => {
+      if (val instanceof Map) {
+        let o = {};
+        val.forEach((v, k) => o[k] = v);
+        return o;
+      }
+      return val;
+    }));
+  }
+})";
+
+// Upper bounds of the latency buckets; slower calls land in a last,
+// unbounded one
+const BUCKETS_MS: &[u64] = &[1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+// Invokes that never answered are forgotten past this many
+const MAX_IN_FLIGHT: usize = 1024;
+
+#[derive(Default)]
+struct CommandStats {
+    calls: u64,
+    errors: u64,
+    total_ms: f64,
+    max_ms: f64,
+    // One count per BUCKETS_MS entry, then the overflow
+    buckets: Vec<u64>,
+}
+
+struct InFlight {
+    command: String,
+    started: Instant,
+}
+
+// Managed state: figures per command, and the invokes still running by
+// window and callback id
+pub struct CommandMetrics {
+    since: Mutex<Instant>,
+    stats: Mutex<HashMap<String, CommandStats>>,
+    in_flight: Mutex<HashMap<(String, usize), InFlight>>,
+}
+
+impl Default for CommandMetrics {
+    fn default() -> Self {
+        CommandMetrics {
+            since: Mutex::new(Instant::now()),
+            stats: Mutex::default(),
+            in_flight: Mutex::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    // None for the bucket above the last bound
+    pub le_ms: Option<u64>,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandReport {
+    pub command: String,
+    pub calls: u64,
+    // Rejections, including refused authorization
+    pub errors: u64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    // Upper bound of the bucket the percentile falls in
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub histogram: Vec<LatencyBucket>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RuntimeMetrics {
+    // Seconds since counting started (app start or the last reset)
+    pub seconds: u64,
+    // Slowest overall first
+    pub commands: Vec<CommandReport>,
+}
+
+// Start timing an invoke; called by the guard before anything else
+pub fn begin(message: &InvokeMessage<Wry>) {
+    let Some(callback) = message.payload()["__invokeCallback"].as_u64() else {
+        return;
+    };
+    let window = message.window_ref();
+    let metrics = window.state::<CommandMetrics>();
+    let Ok(mut in_flight) = metrics.in_flight.lock() else {
+        return;
+    };
+    if in_flight.len() >= MAX_IN_FLIGHT {
+        in_flight.clear();
+    }
+    in_flight.insert(
+        (window.label().to_string(), callback as usize),
+        InFlight {
+            command: message.command().to_string(),
+            started: Instant::now(),
+        },
+    );
+}
+
+// Returns the command the reply answers, when its invoke was seen
+fn record(
+    metrics: &CommandMetrics,
+    window: &str,
+    callback: CallbackFn,
+    ok: bool,
+) -> Option<String> {
+    let call = metrics
+        .in_flight
+        .lock()
+        .ok()
+        .and_then(|mut in_flight| in_flight.remove(&(window.to_string(), callback.0)))?;
+    let ms = call.started.elapsed().as_secs_f64() * 1000.0;
+    match ok {
+        true => tracing::debug!(command = %call.command, ms, "Command finished"),
+        false => tracing::warn!(command = %call.command, ms, "Command failed"),
+    }
+    let Ok(mut stats) = metrics.stats.lock() else {
+        return Some(call.command);
+    };
+    let entry = stats.entry(call.command.clone()).or_default();
+    if entry.buckets.is_empty() {
+        entry.buckets = vec![0; BUCKETS_MS.len() + 1];
+    }
+    entry.calls += 1;
+    if !ok {
+        entry.errors += 1;
+    }
+    entry.total_ms += ms;
+    entry.max_ms = entry.max_ms.max(ms);
+    let bucket = BUCKETS_MS
+        .iter()
+        .position(|bound| ms <= *bound as f64)
+        .unwrap_or(BUCKETS_MS.len());
+    entry.buckets[bucket] += 1;
+    Some(call.command)
+}
+
+// Answer the page as Tauri's default responder does, recording the outcome.
+// Rejections go back as structured errors, see errors.rs, in the language
+// chosen on this PC (messages.rs).
+pub fn respond(
+    window: Window<Wry>,
+    response: InvokeResponse,
+    success_callback: CallbackFn,
+    error_callback: CallbackFn,
+) {
+    let ok = matches!(response, InvokeResponse::Ok(_));
+    let command = window
+        .try_state::<CommandMetrics>()
+        .and_then(|metrics| record(&metrics, window.label(), success_callback, ok));
+    let app = window.app_handle();
+    let result = response.into_result().map_err(|error| {
+        errors::to_response(error, command.as_deref(), |message| {
+            messages::translated(&app, message)
+        })
+    });
+    let script = match format_callback_result(result, success_callback, error_callback) {
+        Ok(script) => script,
+        Err(e) => format_callback(error_callback, &e.to_string())
+            .expect("unable to serialize response string to json"),
+    };
+    let _ = window.eval(&script);
+}
+
+// Bound of the bucket holding the given share of calls
+fn percentile(buckets: &[u64], calls: u64, share: f64) -> Option<u64> {
+    let rank = (calls as f64 * share).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (i, count) in buckets.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return BUCKETS_MS.get(i).copied();
+        }
+    }
+    None
+}
+
+// Figures for every command run since counting started; `reset` starts
+// afresh after reading them
+#[tauri::command]
+pub fn get_runtime_metrics(
+    metrics: State<'_, CommandMetrics>,
+    reset: Option<bool>,
+) -> Result<RuntimeMetrics, String> {
+    let mut stats = metrics.stats.lock().map_err(|e| e.to_string())?;
+    let mut commands: Vec<CommandReport> = stats
+        .iter()
+        .map(|(command, s)| CommandReport {
+            command: command.clone(),
+            calls: s.calls,
+            errors: s.errors,
+            mean_ms: s.total_ms / s.calls.max(1) as f64,
+            max_ms: s.max_ms,
+            p50_ms: percentile(&s.buckets, s.calls, 0.5),
+            p95_ms: percentile(&s.buckets, s.calls, 0.95),
+            histogram: s
+                .buckets
+                .iter()
+                .enumerate()
+                .map(|(i, count)| LatencyBucket {
+                    le_ms: BUCKETS_MS.get(i).copied(),
+                    count: *count,
+                })
+                .collect(),
+        })
+        .collect();
+    commands.sort_by(|a, b| {
+        let total = |r: &CommandReport| r.mean_ms * r.calls as f64;
+        total(b).total_cmp(&total(a))
+    });
+    let mut since = metrics.since.lock().map_err(|e| e.to_string())?;
+    let seconds = since.elapsed().as_secs();
+    if reset.unwrap_or(false) {
+        stats.clear();
+        *since = Instant::now();
+    }
+    Ok(RuntimeMetrics { seconds, commands })
+}
@@ -0,0 +1,463 @@
+// Weighbridge indicator connection for Truckore Pro
+// The indicator's connection settings and output protocol are stored in
+// app_config.scale_config and used by every scale command. Indicators are
+// reached over a serial port or, for Ethernet models, a TCP socket; both
+// carry the same protocols. The scale source can instead be the simulator
+// (scale_simulator.rs), which needs no hardware.
+
+use crate::command_audit;
+use crate::db;
+use crate::drivers::{self, ScaleDriver};
+use crate::roles::{self, Role};
+use crate::scale_protocol::Reading;
+use crate::scale_simulator::{self, Simulator};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+pub const CONFIG_KEY: &str = "scale_config";
+const DEFAULT_DIAGNOSE_SECONDS: f64 = 3.0;
+const DEFAULT_BAUD_CANDIDATES: &[u32] = &[9600, 4800, 2400, 19200, 1200];
+// Listening time per serial setting during auto-detection
+const AUTODETECT_LISTEN: Duration = Duration::from_millis(1500);
+// Valid frames needed before a match counts as fully confident
+const AUTODETECT_FULL_CONFIDENCE_FRAMES: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    Serial,
+    Tcp,
+}
+
+// Where readings come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleSource {
+    #[default]
+    Indicator,
+    // Generated truck cycles for demos and testing
+    Simulator,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScaleConfig {
+    pub source: ScaleSource,
+    pub transport: Transport,
+    // Serial settings
+    pub port: String,
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    // "none", "odd" or "even"
+    pub parity: String,
+    pub stop_bits: u8,
+    // TCP settings
+    pub host: String,
+    pub tcp_port: u16,
+    // Scale driver id, see drivers.rs
+    pub protocol: String,
+}
+
+impl ScaleConfig {
+    // What the indicator is reached through: "COM3" or "10.0.0.5:4001"
+    pub fn endpoint(&self) -> String {
+        if self.source == ScaleSource::Simulator {
+            return "simulator".to_string();
+        }
+        match self.transport {
+            Transport::Serial => self.port.clone(),
+            Transport::Tcp => format!("{}:{}", self.host, self.tcp_port),
+        }
+    }
+}
+
+impl Default for ScaleConfig {
+    fn default() -> Self {
+        ScaleConfig {
+            source: ScaleSource::Indicator,
+            transport: Transport::Serial,
+            port: String::new(),
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: "none".to_string(),
+            stop_bits: 1,
+            host: String::new(),
+            tcp_port: 4001,
+            protocol: "ascii".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScaleDiagnostics {
+    pub port: String,
+    pub protocol: String,
+    // Model/ID reported by the indicator, where the protocol supports it
+    pub model: Option<String>,
+    pub duration_ms: u64,
+    pub bytes_received: u64,
+    pub frames: u64,
+    pub valid_frames: u64,
+    pub parse_errors: u64,
+    // Frames dropped as noise (too long, usually a baud/parity mismatch)
+    pub framing_errors: u64,
+    pub frames_per_second: f64,
+    // Share of frames that parsed, 0.0 - 1.0
+    pub signal_quality: f64,
+    pub last_error: Option<String>,
+    pub last_reading: Option<Reading>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolMatch {
+    pub config: ScaleConfig,
+    // 0.0 - 1.0: share of frames that parsed, reduced when few frames arrived
+    pub confidence: f64,
+    pub valid_frames: u64,
+    pub sample: Option<Reading>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutodetectResult {
+    pub best: Option<ProtocolMatch>,
+    // Every protocol/serial setting combination that produced a reading, best first
+    pub candidates: Vec<ProtocolMatch>,
+}
+
+pub fn load_config(conn: &Connection) -> Result<ScaleConfig, String> {
+    match db::get_config(conn, CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Err("No indicator configured".to_string()),
+    }
+}
+
+// An open indicator connection: serial, TCP or the simulator
+pub trait IndicatorLink: Read + Write + Send {}
+
+impl<T: Read + Write + Send> IndicatorLink for T {}
+
+pub fn open_port(config: &ScaleConfig) -> Result<Box<dyn IndicatorLink>, String> {
+    if config.source == ScaleSource::Simulator {
+        return Ok(Box::new(Simulator::new(&config.protocol)?));
+    }
+    match config.transport {
+        Transport::Serial => open_serial(config),
+        Transport::Tcp => open_tcp(config),
+    }
+}
+
+fn open_tcp(config: &ScaleConfig) -> Result<Box<dyn IndicatorLink>, String> {
+    let endpoint = config.endpoint();
+    let address = endpoint
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", endpoint, e))?
+        .next()
+        .ok_or_else(|| format!("Failed to resolve {}", endpoint))?;
+    let stream = TcpStream::connect_timeout(&address, Duration::from_secs(3))
+        .map_err(|e| format!("Failed to connect to {}: {}", endpoint, e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .map_err(|e| e.to_string())?;
+    stream.set_nodelay(true).map_err(|e| e.to_string())?;
+    Ok(Box::new(stream))
+}
+
+fn open_serial(config: &ScaleConfig) -> Result<Box<dyn IndicatorLink>, String> {
+    let data_bits = match config.data_bits {
+        7 => serialport::DataBits::Seven,
+        8 => serialport::DataBits::Eight,
+        other => return Err(format!("Unsupported data bits: {}", other)),
+    };
+    let parity = match config.parity.as_str() {
+        "none" => serialport::Parity::None,
+        "odd" => serialport::Parity::Odd,
+        "even" => serialport::Parity::Even,
+        other => return Err(format!("Unsupported parity: {}", other)),
+    };
+    let stop_bits = match config.stop_bits {
+        1 => serialport::StopBits::One,
+        2 => serialport::StopBits::Two,
+        other => return Err(format!("Unsupported stop bits: {}", other)),
+    };
+    serialport::new(&config.port, config.baud_rate)
+        .data_bits(data_bits)
+        .parity(parity)
+        .stop_bits(stop_bits)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .map(|port| Box::new(port) as Box<dyn IndicatorLink>)
+        .map_err(|e| format!("Failed to open {}: {}", config.port, e))
+}
+
+// Read from the port until `deadline`, handing each byte to `on_byte`
+pub fn read_until(
+    port: &mut dyn IndicatorLink,
+    deadline: Instant,
+    mut on_byte: impl FnMut(u8),
+) -> Result<u64, String> {
+    let mut buf = [0u8; 256];
+    let mut total = 0u64;
+    while Instant::now() < deadline {
+        match port.read(&mut buf) {
+            // Only a socket reports end of stream; serial reads time out instead
+            Ok(0) => return Err("The indicator closed the connection".to_string()),
+            Ok(n) => {
+                total += n as u64;
+                buf[..n].iter().for_each(|b| on_byte(*b));
+            }
+            // Sockets report a read timeout as WouldBlock on Unix
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(total)
+}
+
+pub fn diagnose(config: &ScaleConfig, seconds: f64) -> Result<ScaleDiagnostics, String> {
+    let driver = drivers::scale(&config.protocol)?;
+    let mut port = open_port(config)?;
+    if let Some(command) = driver.identify_command() {
+        port.write_all(command).map_err(|e| e.to_string())?;
+    }
+
+    let mut framer = driver.framer();
+    let mut model = None;
+    let (mut frames, mut valid, mut errors) = (0u64, 0u64, 0u64);
+    let (mut last_error, mut last_reading) = (None, None);
+
+    let started = Instant::now();
+    let bytes = read_until(
+        port.as_mut(),
+        started + Duration::from_secs_f64(seconds),
+        |byte| {
+            let Some(frame) = framer.push(byte) else {
+                return;
+            };
+            if let Some(id) = driver.identify(&frame) {
+                model = Some(id);
+                return;
+            }
+            frames += 1;
+            match driver.parse(&frame) {
+                Ok(reading) => {
+                    valid += 1;
+                    last_reading = Some(reading);
+                }
+                Err(e) => {
+                    errors += 1;
+                    last_error = Some(e);
+                }
+            }
+        },
+    )?;
+    let elapsed = started.elapsed();
+
+    let framing_errors = framer.overruns;
+    let total = frames + framing_errors;
+    Ok(ScaleDiagnostics {
+        port: config.endpoint(),
+        protocol: config.protocol.clone(),
+        model,
+        duration_ms: elapsed.as_millis() as u64,
+        bytes_received: bytes,
+        frames,
+        valid_frames: valid,
+        parse_errors: errors,
+        framing_errors,
+        frames_per_second: valid as f64 / elapsed.as_secs_f64().max(0.001),
+        signal_quality: if total == 0 {
+            0.0
+        } else {
+            valid as f64 / total as f64
+        },
+        last_error,
+        last_reading,
+    })
+}
+
+// Score a driver against a captured byte stream
+fn score(driver: &dyn ScaleDriver, bytes: &[u8]) -> (f64, u64, Option<Reading>) {
+    let mut framer = driver.framer();
+    let (mut frames, mut valid, mut sample) = (0u64, 0u64, None);
+    for frame in bytes.iter().filter_map(|b| framer.push(*b)) {
+        frames += 1;
+        if let Ok(reading) = driver.parse(&frame) {
+            valid += 1;
+            sample = Some(reading);
+        }
+    }
+    let total = frames + framer.overruns;
+    if valid == 0 {
+        return (0.0, 0, None);
+    }
+    let quality = valid as f64 / total as f64;
+    let volume = (valid as f64 / AUTODETECT_FULL_CONFIDENCE_FRAMES).min(1.0);
+    (quality * volume, valid, sample)
+}
+
+fn autodetect(port: &str, bauds: &[u32]) -> Result<AutodetectResult, String> {
+    let mut candidates = Vec::new();
+    let mut last_error = None;
+    // 8N1 covers most indicators; 7E1 is common on older Toledo-compatibles
+    for &baud_rate in bauds {
+        for (data_bits, parity) in [(8, "none"), (7, "even")] {
+            let settings = ScaleConfig {
+                port: port.to_string(),
+                baud_rate,
+                data_bits,
+                parity: parity.to_string(),
+                ..ScaleConfig::default()
+            };
+            let mut serial = match open_port(&settings) {
+                Ok(serial) => serial,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            let mut bytes = Vec::new();
+            read_until(serial.as_mut(), Instant::now() + AUTODETECT_LISTEN, |b| {
+                bytes.push(b)
+            })?;
+
+            for driver in drivers::scale_drivers() {
+                let (confidence, valid_frames, sample) = score(*driver, &bytes);
+                if valid_frames > 0 {
+                    candidates.push(ProtocolMatch {
+                        config: ScaleConfig {
+                            protocol: driver.id().to_string(),
+                            ..settings.clone()
+                        },
+                        confidence,
+                        valid_frames,
+                        sample,
+                    });
+                }
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        if let Some(e) = last_error {
+            return Err(e);
+        }
+    }
+    // Lenient protocols accept almost any numeric stream, so on equal
+    // confidence the stricter framed protocols win
+    let strict = |m: &ProtocolMatch| {
+        drivers::scale(&m.config.protocol).is_ok_and(|driver| !driver.lenient())
+    };
+    candidates.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then(strict(b).cmp(&strict(a)))
+    });
+    Ok(AutodetectResult {
+        best: candidates.first().cloned(),
+        candidates,
+    })
+}
+
+#[tauri::command]
+pub fn get_scale_config(app: AppHandle) -> Result<Option<ScaleConfig>, String> {
+    let conn = db::open(&app)?;
+    match db::get_config(&conn, CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub fn set_scale_config(app: AppHandle, config: ScaleConfig) -> Result<(), String> {
+    drivers::scale(&config.protocol)?;
+    let conn = db::open(&app)?;
+    let json = serde_json::to_string(&config).map_err(|e| e.to_string())?;
+    db::set_config(&conn, CONFIG_KEY, &json)
+}
+
+#[tauri::command]
+pub fn get_scale_source(app: AppHandle) -> Result<ScaleSource, String> {
+    let conn = db::open(&app)?;
+    match db::get_config(&conn, CONFIG_KEY)? {
+        Some(json) => Ok(serde_json::from_str::<ScaleConfig>(&json)
+            .map_err(|e| e.to_string())?
+            .source),
+        None => Ok(ScaleSource::Indicator),
+    }
+}
+
+// Switch between the indicator and the simulator, keeping the connection
+// settings for switching back. A site without an indicator configured gets
+// the default settings, so the simulator runs on a fresh install. The
+// listener follows the change like any other settings change.
+#[tauri::command]
+pub fn set_scale_source(
+    app: AppHandle,
+    source: ScaleSource,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "source": source });
+    command_audit::audited(&app, "set_scale_source", &user_id, args, || {
+        let conn = db::open(&app)?;
+        // Simulated weights would pass for real ones on a ticket
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut config = match db::get_config(&conn, CONFIG_KEY)? {
+            Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            None => ScaleConfig::default(),
+        };
+        if source == ScaleSource::Simulator && !scale_simulator::supports(&config.protocol) {
+            return Err(format!(
+                "The simulator cannot send protocol {}; switch the indicator to ascii, st_gs or toledo first",
+                config.protocol
+            ));
+        }
+        config.source = source;
+        let json = serde_json::to_string(&config).map_err(|e| e.to_string())?;
+        db::set_config(&conn, CONFIG_KEY, &json)
+    })
+}
+
+#[tauri::command]
+pub fn list_serial_ports() -> Result<Vec<String>, String> {
+    serialport::available_ports()
+        .map(|ports| ports.into_iter().map(|p| p.port_name).collect())
+        .map_err(|e| e.to_string())
+}
+
+// Listen to the configured indicator for a few seconds and report what came
+// back: model (where supported), frame rate, error counts and signal quality.
+// Backs the settings screen's "Test connection" button.
+#[tauri::command]
+pub async fn diagnose_scale(
+    app: AppHandle,
+    seconds: Option<f64>,
+) -> Result<ScaleDiagnostics, String> {
+    let conn = db::open(&app)?;
+    let config = load_config(&conn)?;
+    let seconds = seconds.unwrap_or(DEFAULT_DIAGNOSE_SECONDS).clamp(0.5, 30.0);
+    let diagnostics = tauri::async_runtime::spawn_blocking(move || diagnose(&config, seconds))
+        .await
+        .map_err(|e| e.to_string())??;
+    // Kept so usage telemetry can report which indicators are in the field
+    if let Some(model) = &diagnostics.model {
+        db::set_config(&conn, "indicator_model", model)?;
+    }
+    Ok(diagnostics)
+}
+
+// Listen on `port` at each candidate baud rate and try every known protocol
+// against the incoming stream; returns the best match with a confidence score
+#[tauri::command]
+pub async fn autodetect_protocol(
+    port: String,
+    baud_candidates: Option<Vec<u32>>,
+) -> Result<AutodetectResult, String> {
+    let bauds = baud_candidates.unwrap_or_else(|| DEFAULT_BAUD_CANDIDATES.to_vec());
+    tauri::async_runtime::spawn_blocking(move || autodetect(&port, &bauds))
+        .await
+        .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,537 @@
+// Streaming weight from the indicator for Truckore Pro
+// The listener keeps the indicator port open in a background thread, parses
+// its continuous output and emits a `weight-update` event per reading, so
+// the live weight display needs no polling. Connection changes are emitted
+// as `scale-status`; a lost connection is retried with backoff until the
+// listener is stopped. Serial and TCP indicators produce the same events.
+// Only one process can hold a serial port (and many TCP indicators accept a
+// single client), so while the listener runs, captures on the same
+// indicator read from it instead of connecting again.
+// A stability detector watches the stream and emits `weight-stable` once
+// the last N readings stay within a tolerance, so a truck still rolling on
+// the deck never looks settled.
+// The readings of the last few minutes are kept for the loading curve
+// (weight_history.rs).
+// A listener started from the site's settings follows them: when the
+// indicator or stability settings change it reconnects with the new ones.
+
+use crate::db;
+use crate::drivers::{self, ScaleDriver};
+use crate::peripherals;
+use crate::positioning;
+use crate::rounding;
+use crate::scale::{self, IndicatorLink, ScaleConfig};
+use crate::scale_protocol::{Framer, Reading};
+use crate::scripting;
+use crate::settings_events;
+use crate::shutdown;
+use crate::weight_history;
+use crate::workflow_journal;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const READ_WINDOW: Duration = Duration::from_millis(200);
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+const STABILITY_KEY: &str = "stability_detector";
+const DEFAULT_STABLE_TIMEOUT_SECONDS: f64 = 30.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StabilitySettings {
+    // Consecutive readings that must agree
+    pub window: usize,
+    // Largest spread between them, in kg
+    pub tolerance_kg: f64,
+}
+
+impl Default for StabilitySettings {
+    fn default() -> Self {
+        StabilitySettings {
+            window: 10,
+            tolerance_kg: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StableWeight {
+    pub port: String,
+    pub weight_kg: f64,
+    pub readings: usize,
+    pub spread_kg: f64,
+}
+
+pub fn load_stability(conn: &rusqlite::Connection) -> Result<StabilitySettings, String> {
+    match db::get_config(conn, STABILITY_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(StabilitySettings::default()),
+    }
+}
+
+// Sliding window over the reading stream. Reports each steady stretch once.
+pub struct StabilityDetector {
+    settings: StabilitySettings,
+    weights: VecDeque<f64>,
+    reported: bool,
+}
+
+impl StabilityDetector {
+    pub fn new(settings: StabilitySettings) -> Self {
+        StabilityDetector {
+            weights: VecDeque::with_capacity(settings.window),
+            settings,
+            reported: false,
+        }
+    }
+
+    // Returns (weight, spread) when this reading completes a stable window
+    pub fn push(&mut self, reading: &Reading) -> Option<(f64, f64)> {
+        // An indicator that flags motion overrides the window
+        if reading.stable == Some(false) {
+            self.weights.clear();
+            self.reported = false;
+            return None;
+        }
+        if self.weights.len() == self.settings.window.max(1) {
+            self.weights.pop_front();
+        }
+        self.weights.push_back(reading.weight_kg);
+        if self.weights.len() < self.settings.window.max(1) {
+            return None;
+        }
+        let (min, max) = self
+            .weights
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(lo, hi), w| (lo.min(*w), hi.max(*w)));
+        let spread = max - min;
+        if spread > self.settings.tolerance_kg {
+            self.reported = false;
+            return None;
+        }
+        if self.reported {
+            return None;
+        }
+        self.reported = true;
+        Some((reading.weight_kg, spread))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightUpdate {
+    pub port: String,
+    pub weight_kg: f64,
+    pub stable: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScaleStatus {
+    pub port: String,
+    // "connected", "disconnected", "stopped", or "reload_failed" when new
+    // settings could not be applied
+    pub state: String,
+    pub error: Option<String>,
+}
+
+type Subscribers = Arc<Mutex<Vec<Sender<Reading>>>>;
+
+struct Listener {
+    endpoint: String,
+    // What it was started with, when that was the site's settings
+    settings: Option<(ScaleConfig, StabilitySettings)>,
+    stop: Arc<AtomicBool>,
+    subscribers: Subscribers,
+    thread: JoinHandle<()>,
+}
+
+// Managed state: the running listener, if any
+#[derive(Default)]
+pub struct ScaleListener(Mutex<Option<Listener>>);
+
+// Managed state: the last status the listener reported, for the heartbeat
+#[derive(Default)]
+pub struct ScaleHealth(Mutex<Option<ScaleStatus>>);
+
+impl ScaleListener {
+    // Stop the listener and wait for its thread to release the port
+    pub fn stop(&self) {
+        let listener = self.0.lock().ok().and_then(|mut l| l.take());
+        if let Some(listener) = listener {
+            listener.stop.store(true, Ordering::SeqCst);
+            let _ = listener.thread.join();
+        }
+    }
+}
+
+fn status(app: &AppHandle, port: &str, state: &str, error: Option<String>) {
+    match &error {
+        Some(e) => tracing::warn!(port, state, error = %e, "Scale status"),
+        None => tracing::info!(port, state, "Scale status"),
+    }
+    let status = ScaleStatus {
+        port: port.to_string(),
+        state: state.to_string(),
+        error,
+    };
+    if let Ok(mut last) = app.state::<ScaleHealth>().0.lock() {
+        *last = Some(status.clone());
+    }
+    // Delivery to windows is best effort
+    let _ = app.emit_all("scale-status", &status);
+}
+
+// What the listener last reported; None when it has not run
+pub fn last_status(app: &AppHandle) -> Option<ScaleStatus> {
+    app.state::<ScaleHealth>()
+        .0
+        .lock()
+        .ok()
+        .and_then(|last| last.clone())
+}
+
+fn listen(
+    app: AppHandle,
+    config: ScaleConfig,
+    driver: &'static dyn ScaleDriver,
+    stability: StabilitySettings,
+    stop: Arc<AtomicBool>,
+    subscribers: Subscribers,
+) {
+    let stopped = || stop.load(Ordering::SeqCst) || shutdown::requested();
+    let endpoint = config.endpoint();
+    let window = stability.window;
+    let mut detector = StabilityDetector::new(stability);
+    let mut backoff = RECONNECT_MIN;
+    while !stopped() {
+        let mut port = match scale::open_port(&config) {
+            Ok(port) => port,
+            Err(e) => {
+                status(&app, &endpoint, "disconnected", Some(e));
+                // Sleep in short steps so stopping is not held up by the backoff
+                let retry_at = Instant::now() + backoff;
+                while Instant::now() < retry_at && !stopped() {
+                    std::thread::sleep(READ_WINDOW);
+                }
+                backoff = (backoff * 2).min(RECONNECT_MAX);
+                continue;
+            }
+        };
+        backoff = RECONNECT_MIN;
+        status(&app, &endpoint, "connected", None);
+        let mut framer = driver.framer();
+        while !stopped() {
+            let mut readings = Vec::new();
+            let read = scale::read_until(port.as_mut(), Instant::now() + READ_WINDOW, |byte| {
+                if let Some(reading) = framer
+                    .push(byte)
+                    .and_then(|frame| driver.parse(&frame).ok())
+                {
+                    readings.push(reading);
+                }
+            });
+            for reading in readings {
+                let update = WeightUpdate {
+                    port: endpoint.clone(),
+                    weight_kg: reading.weight_kg,
+                    stable: reading.stable,
+                };
+                weight_history::record(&app, &update);
+                let _ = app.emit_all("weight-update", &update);
+                if let Some((weight_kg, spread_kg)) = detector.push(&reading) {
+                    let stable = StableWeight {
+                        port: endpoint.clone(),
+                        weight_kg,
+                        readings: window,
+                        spread_kg,
+                    };
+                    tracing::info!(port = %endpoint, weight_kg, spread_kg, "Stable weight");
+                    let _ = app.emit_all("weight-stable", &stable);
+                    peripherals::weight_stable(&app, weight_kg);
+                }
+                if let Ok(mut subs) = subscribers.lock() {
+                    subs.retain(|tx| tx.send(reading).is_ok());
+                }
+            }
+            if let Err(e) = read {
+                status(&app, &endpoint, "disconnected", Some(e));
+                break;
+            }
+        }
+    }
+    status(&app, &endpoint, "stopped", None);
+}
+
+// Readings for a capture: shared from the listener when it holds the port,
+// otherwise read from the port directly
+pub enum ReadingFeed {
+    Shared(Receiver<Reading>),
+    Direct {
+        port: Box<dyn IndicatorLink>,
+        framer: Framer,
+        driver: &'static dyn ScaleDriver,
+    },
+}
+
+// Readings from the running listener, if any (and if it reads `endpoint`
+// when given), with the endpoint it reads
+pub fn subscribe(
+    app: &AppHandle,
+    endpoint: Option<&str>,
+) -> Result<Option<(String, Receiver<Reading>)>, String> {
+    let Some(state) = app.try_state::<ScaleListener>() else {
+        return Ok(None);
+    };
+    let listener = state.0.lock().map_err(|e| e.to_string())?;
+    let Some(listener) = listener
+        .as_ref()
+        .filter(|l| endpoint.map_or(true, |e| l.endpoint == e))
+    else {
+        return Ok(None);
+    };
+    let (tx, rx) = mpsc::channel();
+    listener
+        .subscribers
+        .lock()
+        .map_err(|e| e.to_string())?
+        .push(tx);
+    Ok(Some((listener.endpoint.clone(), rx)))
+}
+
+impl ReadingFeed {
+    pub fn open(app: &AppHandle, config: &ScaleConfig) -> Result<ReadingFeed, String> {
+        if let Some((_, rx)) = subscribe(app, Some(&config.endpoint()))? {
+            return Ok(ReadingFeed::Shared(rx));
+        }
+        let driver = drivers::scale(&config.protocol)?;
+        Ok(ReadingFeed::Direct {
+            port: scale::open_port(config)?,
+            framer: driver.framer(),
+            driver,
+        })
+    }
+
+    // Readings that arrive within `window`
+    pub fn read(&mut self, window: Duration) -> Result<Vec<Reading>, String> {
+        let deadline = Instant::now() + window;
+        let mut readings = Vec::new();
+        match self {
+            ReadingFeed::Shared(rx) => loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                match rx.recv_timeout(left) {
+                    Ok(reading) => readings.push(reading),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err("Scale listener stopped during capture".to_string())
+                    }
+                }
+            },
+            ReadingFeed::Direct {
+                port,
+                framer,
+                driver,
+            } => {
+                scale::read_until(port.as_mut(), deadline, |byte| {
+                    if let Some(reading) = framer
+                        .push(byte)
+                        .and_then(|frame| driver.parse(&frame).ok())
+                    {
+                        readings.push(reading);
+                    }
+                })?;
+            }
+        }
+        Ok(readings)
+    }
+}
+
+fn start(
+    app: &AppHandle,
+    listener: &ScaleListener,
+    config: Option<ScaleConfig>,
+) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let stability = load_stability(&conn)?;
+    let (config, settings) = match config {
+        Some(config) => (config, None),
+        None => {
+            let config = scale::load_config(&conn)?;
+            (config.clone(), Some((config, stability.clone())))
+        }
+    };
+    let driver = drivers::scale(&config.protocol)?;
+    listener.stop();
+    weight_history::clear(app);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let subscribers: Subscribers = Arc::default();
+    let endpoint = config.endpoint();
+    let thread = {
+        let (app, stop, subscribers) = (app.clone(), stop.clone(), subscribers.clone());
+        std::thread::spawn(move || listen(app, config, driver, stability, stop, subscribers))
+    };
+    *listener.0.lock().map_err(|e| e.to_string())? = Some(Listener {
+        endpoint,
+        settings,
+        stop,
+        subscribers,
+        thread,
+    });
+    Ok(())
+}
+
+// Restart a listener that follows the site's settings if they now differ.
+// A change that cannot be applied leaves it running as it was.
+fn reload(app: &AppHandle) {
+    let listener = app.state::<ScaleListener>();
+    let running = match listener.0.lock() {
+        Ok(running) => running.as_ref().and_then(|l| l.settings.clone()),
+        Err(_) => return,
+    };
+    let Some(running) = running else {
+        return;
+    };
+    let current =
+        db::open(app).and_then(|conn| Ok((scale::load_config(&conn)?, load_stability(&conn)?)));
+    let result = match current {
+        Ok(current) if current == running => Ok(()),
+        Ok(_) => start(app, &listener, None),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        status(app, &running.0.endpoint(), "reload_failed", Some(e));
+    }
+}
+
+// Follow indicator and stability settings changes for the life of the app
+pub fn watch_settings(app: AppHandle) {
+    let handle = app.clone();
+    app.listen_global(settings_events::EVENT, move |event| {
+        let relevant = settings_events::parse(&event)
+            .is_some_and(|c| c.contains(scale::CONFIG_KEY) || c.contains(STABILITY_KEY));
+        if relevant {
+            reload(&handle);
+        }
+    });
+}
+
+// Follow the site's indicator settings; used by headless mode
+pub fn start_from_settings(app: &AppHandle) -> Result<(), String> {
+    start(app, &app.state::<ScaleListener>(), None)
+}
+
+// Start streaming from `config`, or the site's indicator settings when omitted.
+// A running listener is replaced.
+#[tauri::command]
+pub fn start_scale_listener(
+    app: AppHandle,
+    listener: State<'_, ScaleListener>,
+    config: Option<ScaleConfig>,
+) -> Result<(), String> {
+    start(&app, &listener, config)
+}
+
+#[tauri::command]
+pub fn stop_scale_listener(listener: State<'_, ScaleListener>) -> Result<(), String> {
+    listener.stop();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_stability_settings(app: AppHandle) -> Result<StabilitySettings, String> {
+    let conn = db::open(&app)?;
+    load_stability(&conn)
+}
+
+// A listener running on the site's settings picks the change up live
+#[tauri::command]
+pub fn set_stability_settings(app: AppHandle, settings: StabilitySettings) -> Result<(), String> {
+    if settings.window < 2 || settings.tolerance_kg < 0.0 {
+        return Err(
+            "Stability needs a window of at least 2 readings and a non-negative tolerance"
+                .to_string(),
+        );
+    }
+    let conn = db::open(&app)?;
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    db::set_config(&conn, STABILITY_KEY, &json)
+}
+
+// Wait until the indicator settles by the stability detector's rule and
+// return that weight, or fail after the timeout. The weight must pass the
+// partial-positioning check, against `vehicle_no`'s tare when given.
+#[tauri::command]
+pub async fn capture_stable_weight(
+    app: AppHandle,
+    vehicle_no: Option<String>,
+    timeout_seconds: Option<f64>,
+) -> Result<StableWeight, String> {
+    let (config, stability, rounding) = {
+        let conn = db::open(&app)?;
+        (
+            scale::load_config(&conn)?,
+            load_stability(&conn)?,
+            rounding::load_rules(&conn)?,
+        )
+    };
+    let timeout = Duration::from_secs_f64(
+        timeout_seconds
+            .unwrap_or(DEFAULT_STABLE_TIMEOUT_SECONDS)
+            .clamp(1.0, 120.0),
+    );
+    let mut stable = tauri::async_runtime::spawn_blocking({
+        let app = app.clone();
+        move || {
+            let vehicle = vehicle_no.as_deref();
+            let detail = serde_json::json!({ "source": "capture_stable_weight" });
+            workflow_journal::journalled(&app, "capture", vehicle, None, detail, || {
+                let mut feed = ReadingFeed::open(&app, &config)?;
+                let window = stability.window;
+                let mut detector = StabilityDetector::new(stability);
+                let deadline = Instant::now() + timeout;
+                let mut curve = Vec::new();
+                while Instant::now() < deadline && !shutdown::requested() {
+                    for reading in feed.read(READ_WINDOW)? {
+                        curve.push(reading.weight_kg);
+                        if let Some((weight_kg, spread_kg)) = detector.push(&reading) {
+                            let history = weight_history::recent(
+                                &app,
+                                &config.endpoint(),
+                                positioning::CURVE_SECONDS,
+                            );
+                            let curve = if history.is_empty() { curve } else { history };
+                            let conn = db::open(&app)?;
+                            positioning::check(&conn, vehicle, weight_kg, &curve)?;
+                            return Ok(StableWeight {
+                                port: config.endpoint(),
+                                weight_kg,
+                                readings: window,
+                                spread_kg,
+                            });
+                        }
+                    }
+                }
+                Err(format!(
+                    "Weight did not settle within {:.0} s",
+                    timeout.as_secs_f64()
+                ))
+            })
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    stable.weight_kg = rounding.weight(stable.weight_kg);
+    let conn = db::open(&app)?;
+    scripting::call(
+        &conn,
+        scripting::WEIGHT_CAPTURED,
+        None,
+        &serde_json::json!({ "product_name": null, "weight_kg": stable.weight_kg }),
+    )?;
+    Ok(stable)
+}
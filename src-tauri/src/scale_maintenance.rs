@@ -0,0 +1,566 @@
+// Weighbridge maintenance log for Truckore Pro
+// Calibration, stamping, repairs and other upkeep of the bridge are
+// scheduled with a due date and recorded when done, with who did the work.
+// A background check notifies supervisors when scheduled work falls due.
+// Downtime windows (the bridge out of use, with the reason) are logged
+// separately, optionally against the maintenance that caused them; the
+// utilization reports (analytics.rs) leave them out of the span they judge.
+// Times are entered in local time and stored in UTC like the rest.
+
+use crate::db::{self, DateRange};
+use crate::notifications;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const REMINDER_DAYS_CONFIG_KEY: &str = "scale_maintenance_reminder_days";
+const DEFAULT_REMINDER_DAYS: i64 = 3;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const KINDS: &[&str] = &[
+    "CALIBRATION",
+    "STAMPING",
+    "INSPECTION",
+    "REPAIR",
+    "CLEANING",
+    "OTHER",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScaleMaintenance {
+    pub id: i64,
+    pub kind: String,
+    pub description: String,
+    // SCHEDULED, DONE or CANCELLED
+    pub status: String,
+    // Local date the work is due, for scheduled work
+    pub due_date: Option<String>,
+    pub performed_at: Option<String>,
+    // Technician or service company
+    pub performed_by: Option<String>,
+    pub notes: Option<String>,
+    pub reminded_at: Option<String>,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRecord {
+    // Scheduled entry this completes; None logs unscheduled work
+    pub id: Option<i64>,
+    pub kind: Option<String>,
+    pub description: Option<String>,
+    // Local time; now when omitted
+    pub performed_at: Option<String>,
+    pub performed_by: Option<String>,
+    pub notes: Option<String>,
+    // Schedules the next one of the same kind
+    pub next_due_date: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceFilter {
+    pub status: Option<String>,
+    pub kind: Option<String>,
+    // Local dates of the due date or, for work done, when it was done
+    pub range: Option<DateRange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScaleDowntime {
+    pub id: i64,
+    pub reason: String,
+    pub started_at: String,
+    // None while the bridge is still down
+    pub ended_at: Option<String>,
+    pub maintenance_id: Option<i64>,
+    pub recorded_by: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewDowntime {
+    pub reason: String,
+    // Local times; started now when omitted, still down when no end is given
+    pub started_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub maintenance_id: Option<i64>,
+}
+
+fn valid_kind(kind: &str) -> Result<String, String> {
+    let kind = kind.trim().to_ascii_uppercase();
+    match KINDS.contains(&kind.as_str()) {
+        true => Ok(kind),
+        false => Err(format!(
+            "Unknown maintenance kind {} ({})",
+            kind,
+            KINDS.join(", ")
+        )),
+    }
+}
+
+fn valid_date(conn: &Connection, date: &str) -> Result<String, String> {
+    let valid: bool = conn
+        .query_row("SELECT date(?1) IS ?1", [date], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    match valid {
+        true => Ok(date.to_string()),
+        false => Err(format!("Dates must be YYYY-MM-DD, got {}", date)),
+    }
+}
+
+// A local time as stored (UTC), or now
+fn stored_time(conn: &Connection, local: Option<&str>) -> Result<String, String> {
+    let Some(local) = local else {
+        return conn
+            .query_row("SELECT datetime('now')", [], |row| row.get(0))
+            .map_err(|e| e.to_string());
+    };
+    let stored: Option<String> = conn
+        .query_row("SELECT datetime(?1, 'utc')", [local.trim()], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    stored.ok_or_else(|| format!("Times must be YYYY-MM-DD HH:MM, got {}", local))
+}
+
+pub fn reminder_days(conn: &Connection) -> Result<i64, String> {
+    Ok(db::get_config(conn, REMINDER_DAYS_CONFIG_KEY)?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_REMINDER_DAYS))
+}
+
+const MAINTENANCE_COLUMNS: &str = "id, kind, description, status, due_date, performed_at,
+     performed_by, notes, reminded_at, created_by, created_at";
+
+fn row_to_maintenance(row: &rusqlite::Row) -> rusqlite::Result<ScaleMaintenance> {
+    Ok(ScaleMaintenance {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        description: row.get(2)?,
+        status: row.get(3)?,
+        due_date: row.get(4)?,
+        performed_at: row.get(5)?,
+        performed_by: row.get(6)?,
+        notes: row.get(7)?,
+        reminded_at: row.get(8)?,
+        created_by: row.get(9)?,
+        created_at: row.get(10)?,
+    })
+}
+
+fn find_maintenance(conn: &Connection, id: i64) -> Result<ScaleMaintenance, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM scale_maintenance WHERE id = ?1",
+            MAINTENANCE_COLUMNS
+        ),
+        [id],
+        row_to_maintenance,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Unknown maintenance entry {}", id))
+}
+
+fn schedule(
+    conn: &Connection,
+    kind: &str,
+    description: &str,
+    due_date: &str,
+    user_id: &str,
+) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO scale_maintenance (kind, description, due_date, created_by)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![kind, description, due_date, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Notify supervisors of scheduled work due within the reminder days, once
+// per entry. Returns the entries reminded about.
+pub fn remind(app: &AppHandle, conn: &Connection) -> Result<Vec<i64>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM scale_maintenance
+             WHERE status = 'SCHEDULED' AND reminded_at IS NULL
+               AND due_date <= date('now', 'localtime', '+' || ?1 || ' days')
+             ORDER BY due_date, id",
+            MAINTENANCE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let due = stmt
+        .query_map([reminder_days(conn)?], row_to_maintenance)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    for entry in &due {
+        let due_date = entry.due_date.as_deref().unwrap_or_default();
+        notifications::notify(
+            app,
+            conn,
+            "admin",
+            "Weighbridge maintenance due",
+            &format!(
+                "{} ({}) is due on {}",
+                entry.description,
+                entry.kind.to_lowercase(),
+                due_date
+            ),
+            Some(("scale_maintenance", &entry.id.to_string())),
+        )?;
+        conn.execute(
+            "UPDATE scale_maintenance SET reminded_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            [entry.id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(due.iter().map(|entry| entry.id).collect())
+}
+
+// Run `remind` in the background for the life of the app
+pub fn start_reminders(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            if let Ok(conn) = db::open(&app) {
+                let _ = remind(&app, &conn);
+            }
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+// Downtime between two julianday instants in seconds, clipped to them and
+// merged where windows overlap; the bridge counts as down until now while a
+// window is open
+pub fn downtime_windows(
+    conn: &Connection,
+    start: f64,
+    end: f64,
+) -> Result<Vec<(f64, f64)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT julianday(started_at) * 86400.0,
+                    julianday(COALESCE(ended_at, CURRENT_TIMESTAMP)) * 86400.0
+             FROM scale_downtime
+             WHERE julianday(started_at) * 86400.0 < ?2
+               AND julianday(COALESCE(ended_at, CURRENT_TIMESTAMP)) * 86400.0 > ?1
+             ORDER BY started_at",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![start, end], |row| {
+            Ok((row.get::<_, f64>(0)?, row.get::<_, f64>(1)?))
+        })
+        .map_err(|e| e.to_string())?;
+    let mut windows: Vec<(f64, f64)> = Vec::new();
+    for row in rows {
+        let (from, to) = row.map_err(|e| e.to_string())?;
+        let (from, to) = (from.max(start), to.min(end));
+        match windows.last_mut() {
+            Some(last) if from <= last.1 => last.1 = last.1.max(to),
+            _ => windows.push((from, to)),
+        }
+    }
+    Ok(windows)
+}
+
+// Seconds of `windows` falling between `from` and `to`
+pub fn overlap(windows: &[(f64, f64)], from: f64, to: f64) -> f64 {
+    windows
+        .iter()
+        .map(|(start, end)| (end.min(to) - start.max(from)).max(0.0))
+        .sum()
+}
+
+// Put work on the schedule (supervisors only)
+#[tauri::command]
+pub fn schedule_scale_maintenance(
+    app: AppHandle,
+    kind: String,
+    description: String,
+    due_date: String,
+    user_id: String,
+) -> Result<ScaleMaintenance, String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let kind = valid_kind(&kind)?;
+    let due_date = valid_date(&conn, due_date.trim())?;
+    if description.trim().is_empty() {
+        return Err("A description is required".to_string());
+    }
+    let id = schedule(&conn, &kind, description.trim(), &due_date, &user_id)?;
+    find_maintenance(&conn, id)
+}
+
+// Record work done, completing a scheduled entry or logging unscheduled
+// work, and schedule the next one when `next_due_date` is given
+#[tauri::command]
+pub fn record_scale_maintenance(
+    app: AppHandle,
+    record: MaintenanceRecord,
+    user_id: String,
+) -> Result<ScaleMaintenance, String> {
+    let mut conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let performed_at = stored_time(&conn, record.performed_at.as_deref())?;
+    let next_due_date = record
+        .next_due_date
+        .as_deref()
+        .map(|date| valid_date(&conn, date.trim()))
+        .transpose()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let (id, kind, description) = match record.id {
+        Some(id) => {
+            let entry = find_maintenance(&tx, id)?;
+            if entry.status != "SCHEDULED" {
+                return Err(format!("Maintenance entry {} is {}", id, entry.status));
+            }
+            let description = record
+                .description
+                .filter(|d| !d.trim().is_empty())
+                .unwrap_or(entry.description);
+            (id, entry.kind, description)
+        }
+        None => {
+            let kind = valid_kind(record.kind.as_deref().unwrap_or_default())?;
+            let description = record
+                .description
+                .filter(|d| !d.trim().is_empty())
+                .ok_or("A description is required")?;
+            tx.execute(
+                "INSERT INTO scale_maintenance (kind, description, created_by)
+                 VALUES (?1, ?2, ?3)",
+                params![kind, description.trim(), user_id],
+            )
+            .map_err(|e| e.to_string())?;
+            (tx.last_insert_rowid(), kind, description)
+        }
+    };
+    tx.execute(
+        "UPDATE scale_maintenance
+         SET status = 'DONE', description = ?2, performed_at = ?3, performed_by = ?4,
+             notes = ?5
+         WHERE id = ?1",
+        params![
+            id,
+            description.trim(),
+            performed_at,
+            record.performed_by,
+            record.notes
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    if let Some(due_date) = next_due_date {
+        schedule(&tx, &kind, description.trim(), &due_date, &user_id)?;
+    }
+    let entry = find_maintenance(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(entry)
+}
+
+#[tauri::command]
+pub fn cancel_scale_maintenance(app: AppHandle, id: i64, user_id: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    match conn
+        .execute(
+            "UPDATE scale_maintenance SET status = 'CANCELLED'
+             WHERE id = ?1 AND status = 'SCHEDULED'",
+            [id],
+        )
+        .map_err(|e| e.to_string())?
+    {
+        0 => Err(format!("No scheduled maintenance entry {}", id)),
+        _ => Ok(()),
+    }
+}
+
+// Scheduled work by due date, then work done, newest first
+#[tauri::command]
+pub fn list_scale_maintenance(
+    app: AppHandle,
+    filter: Option<MaintenanceFilter>,
+) -> Result<Vec<ScaleMaintenance>, String> {
+    let filter = filter.unwrap_or_default();
+    let conn = db::open(&app)?;
+    let (from, to) = filter.range.map(|r| (r.from, r.to)).unzip();
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM scale_maintenance
+             WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR kind = ?2)
+               AND (?3 IS NULL OR COALESCE({}, due_date) BETWEEN ?3 AND ?4)
+             ORDER BY status <> 'SCHEDULED', due_date, performed_at DESC, id DESC",
+            MAINTENANCE_COLUMNS,
+            db::local_date("performed_at")
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                filter.status.map(|s| s.to_ascii_uppercase()),
+                filter.kind.map(|k| k.to_ascii_uppercase()),
+                from,
+                to
+            ],
+            row_to_maintenance,
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_maintenance_reminder_days(app: AppHandle) -> Result<i64, String> {
+    let conn = db::open(&app)?;
+    reminder_days(&conn)
+}
+
+#[tauri::command]
+pub fn set_maintenance_reminder_days(
+    app: AppHandle,
+    days: i64,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    if !(0..=90).contains(&days) {
+        return Err("Reminders must come 0 to 90 days ahead".to_string());
+    }
+    db::set_config(&conn, REMINDER_DAYS_CONFIG_KEY, &days.to_string())
+}
+
+const DOWNTIME_COLUMNS: &str =
+    "id, reason, started_at, ended_at, maintenance_id, recorded_by, created_at";
+
+fn row_to_downtime(row: &rusqlite::Row) -> rusqlite::Result<ScaleDowntime> {
+    Ok(ScaleDowntime {
+        id: row.get(0)?,
+        reason: row.get(1)?,
+        started_at: row.get(2)?,
+        ended_at: row.get(3)?,
+        maintenance_id: row.get(4)?,
+        recorded_by: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+fn find_downtime(conn: &Connection, id: i64) -> Result<ScaleDowntime, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM scale_downtime WHERE id = ?1",
+            DOWNTIME_COLUMNS
+        ),
+        [id],
+        row_to_downtime,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Unknown downtime {}", id))
+}
+
+// Log the bridge out of use: a past window with its end, or one starting
+// now (or at `started_at`) that end_scale_downtime closes
+#[tauri::command]
+pub fn record_scale_downtime(
+    app: AppHandle,
+    downtime: NewDowntime,
+    user_id: String,
+) -> Result<ScaleDowntime, String> {
+    let conn = db::open(&app)?;
+    if downtime.reason.trim().is_empty() {
+        return Err("A downtime reason is required".to_string());
+    }
+    let started_at = stored_time(&conn, downtime.started_at.as_deref())?;
+    let ended_at = downtime
+        .ended_at
+        .as_deref()
+        .map(|end| stored_time(&conn, Some(end)))
+        .transpose()?;
+    if ended_at.as_ref().is_some_and(|end| *end < started_at) {
+        return Err("Downtime cannot end before it starts".to_string());
+    }
+    if ended_at.is_none() {
+        let open: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM scale_downtime WHERE ended_at IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some(id) = open {
+            return Err(format!("The bridge is already down (downtime {})", id));
+        }
+    }
+    if let Some(id) = downtime.maintenance_id {
+        find_maintenance(&conn, id)?;
+    }
+    conn.execute(
+        "INSERT INTO scale_downtime (reason, started_at, ended_at, maintenance_id, recorded_by)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            downtime.reason.trim(),
+            started_at,
+            ended_at,
+            downtime.maintenance_id,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    find_downtime(&conn, conn.last_insert_rowid())
+}
+
+// Bring the bridge back into use, now or at `ended_at` (local time)
+#[tauri::command]
+pub fn end_scale_downtime(
+    app: AppHandle,
+    id: i64,
+    ended_at: Option<String>,
+    user_id: String,
+) -> Result<ScaleDowntime, String> {
+    let conn = db::open(&app)?;
+    let downtime = find_downtime(&conn, id)?;
+    if downtime.ended_at.is_some() {
+        return Err(format!("Downtime {} has already ended", id));
+    }
+    let ended_at = stored_time(&conn, ended_at.as_deref())?;
+    if ended_at < downtime.started_at {
+        return Err("Downtime cannot end before it starts".to_string());
+    }
+    conn.execute(
+        "UPDATE scale_downtime SET ended_at = ?2 WHERE id = ?1",
+        params![id, ended_at],
+    )
+    .map_err(|e| e.to_string())?;
+    tracing::info!(downtime = id, user = %user_id, "Weighbridge back in use");
+    find_downtime(&conn, id)
+}
+
+// Downtime overlapping the range of local dates, oldest first
+#[tauri::command]
+pub fn list_scale_downtime(app: AppHandle, range: DateRange) -> Result<Vec<ScaleDowntime>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM scale_downtime
+             WHERE {} <= ?2 AND (ended_at IS NULL OR {} >= ?1)
+             ORDER BY started_at, id",
+            DOWNTIME_COLUMNS,
+            db::local_date("started_at"),
+            db::local_date("ended_at")
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], row_to_downtime)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,234 @@
+// Indicator output protocols for Truckore Pro
+// Each protocol is a scale driver: it splits the serial byte stream into
+// frames and turns a frame into a weight reading. Weights are always reported
+// in kilograms. Drivers are listed in the registry in drivers.rs.
+
+use crate::drivers::ScaleDriver;
+use serde::{Deserialize, Serialize};
+
+const STX: u8 = 0x02;
+const CR: u8 = b'\r';
+const LF: u8 = b'\n';
+// Longest frame any supported indicator sends; anything longer is noise
+const MAX_FRAME: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Reading {
+    pub weight_kg: f64,
+    // None when the protocol carries no motion/stability flag
+    pub stable: Option<bool>,
+}
+
+// Plain continuous ASCII: one weight per line, e.g. "  12340 kg"
+pub struct Ascii;
+
+impl ScaleDriver for Ascii {
+    fn id(&self) -> &'static str {
+        "ascii"
+    }
+
+    fn name(&self) -> &'static str {
+        "Continuous ASCII"
+    }
+
+    fn framer(&self) -> Framer {
+        Framer::new(None)
+    }
+
+    fn parse(&self, frame: &[u8]) -> Result<Reading, String> {
+        parse_ascii(frame)
+    }
+
+    // Almost any numeric stream parses as plain ASCII
+    fn lenient(&self) -> bool {
+        true
+    }
+}
+
+// Header-prefixed CSV lines, e.g. "ST,GS,+0012340kg" (A&D and compatibles)
+pub struct StGs;
+
+impl ScaleDriver for StGs {
+    fn id(&self) -> &'static str {
+        "st_gs"
+    }
+
+    fn name(&self) -> &'static str {
+        "ST/GS (A&D and compatibles)"
+    }
+
+    fn framer(&self) -> Framer {
+        Framer::new(None)
+    }
+
+    fn parse(&self, frame: &[u8]) -> Result<Reading, String> {
+        parse_st_gs(frame)
+    }
+
+    fn identify_command(&self) -> Option<&'static [u8]> {
+        Some(b"?ID\r\n")
+    }
+
+    fn identify(&self, frame: &[u8]) -> Option<String> {
+        let text = String::from_utf8_lossy(frame);
+        text.trim()
+            .strip_prefix("ID,")
+            .map(|id| id.trim().to_string())
+    }
+}
+
+// Mettler Toledo continuous output: STX, 3 status bytes, weight, tare, CR
+pub struct Toledo;
+
+impl ScaleDriver for Toledo {
+    fn id(&self) -> &'static str {
+        "toledo"
+    }
+
+    fn name(&self) -> &'static str {
+        "Mettler Toledo continuous"
+    }
+
+    fn framer(&self) -> Framer {
+        Framer::new(Some(STX))
+    }
+
+    fn parse(&self, frame: &[u8]) -> Result<Reading, String> {
+        parse_toledo(frame)
+    }
+}
+
+// Splits a byte stream into frames. Frames end at CR or LF; protocols with a
+// start byte discard everything until it arrives.
+pub struct Framer {
+    start: Option<u8>,
+    buffer: Vec<u8>,
+    in_frame: bool,
+    // Frames dropped for exceeding MAX_FRAME (line noise, wrong baud rate)
+    pub overruns: u64,
+}
+
+impl Framer {
+    // `start`: byte that opens every frame, for protocols that have one
+    pub fn new(start: Option<u8>) -> Framer {
+        Framer {
+            start,
+            buffer: Vec::new(),
+            in_frame: false,
+            overruns: 0,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        if let Some(start) = self.start {
+            if byte == start {
+                self.buffer.clear();
+                self.in_frame = true;
+                return None;
+            }
+            if !self.in_frame {
+                return None;
+            }
+        }
+        if byte == CR || byte == LF {
+            self.in_frame = false;
+            let frame = std::mem::take(&mut self.buffer);
+            return if frame.iter().all(|b| b.is_ascii_whitespace()) {
+                None
+            } else {
+                Some(frame)
+            };
+        }
+        if self.buffer.len() >= MAX_FRAME {
+            self.buffer.clear();
+            self.in_frame = false;
+            self.overruns += 1;
+            return None;
+        }
+        self.buffer.push(byte);
+        None
+    }
+}
+
+// First signed decimal number in the text, scaled by a trailing unit
+fn parse_number_with_unit(text: &str) -> Result<f64, String> {
+    let start = text
+        .find(|c: char| c.is_ascii_digit() || c == '-' || c == '+')
+        .ok_or_else(|| format!("No weight in frame: {:?}", text))?;
+    let rest = &text[start..];
+    let end = rest
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| !(c.is_ascii_digit() || *c == '.' || *c == ' '))
+        .map(|(i, _)| i)
+        .unwrap_or(rest.len());
+    let number: String = rest[..end].chars().filter(|c| *c != ' ').collect();
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid weight: {:?}", number))?;
+
+    let unit = rest[end..].trim().to_ascii_lowercase();
+    match unit.as_str() {
+        "" | "kg" => Ok(value),
+        "t" => Ok(value * 1000.0),
+        other => Err(format!("Unsupported unit: {}", other)),
+    }
+}
+
+fn parse_ascii(frame: &[u8]) -> Result<Reading, String> {
+    let text = std::str::from_utf8(frame).map_err(|_| "Non-ASCII frame".to_string())?;
+    Ok(Reading {
+        weight_kg: parse_number_with_unit(text)?,
+        stable: None,
+    })
+}
+
+fn parse_st_gs(frame: &[u8]) -> Result<Reading, String> {
+    let text = std::str::from_utf8(frame).map_err(|_| "Non-ASCII frame".to_string())?;
+    let mut parts = text.trim().splitn(3, ',');
+    let (header, _kind, weight) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(h), Some(k), Some(w)) => (h, k, w),
+        _ => return Err(format!("Not an ST/US frame: {:?}", text)),
+    };
+    let stable = match header {
+        "ST" => true,
+        "US" => false,
+        "OL" => return Err("Indicator overload".to_string()),
+        other => return Err(format!("Unknown header: {}", other)),
+    };
+    Ok(Reading {
+        weight_kg: parse_number_with_unit(weight)?,
+        stable: Some(stable),
+    })
+}
+
+fn parse_toledo(frame: &[u8]) -> Result<Reading, String> {
+    // Status A, B, C then six displayed-weight digits (tare digits follow)
+    if frame.len() < 9 {
+        return Err("Short Toledo frame".to_string());
+    }
+    let (swa, swb) = (frame[0], frame[1]);
+    let digits = std::str::from_utf8(&frame[3..9]).map_err(|_| "Non-ASCII frame".to_string())?;
+    let raw: f64 = digits
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid weight digits: {:?}", digits))?;
+
+    // SWA bits 0-2: decimal point position, from x100 down to 0.00000X
+    let scale = match swa & 0x07 {
+        0 => 100.0,
+        1 => 10.0,
+        2 => 1.0,
+        n => 1.0 / 10f64.powi(n as i32 - 2),
+    };
+    if swb & 0x04 != 0 {
+        return Err("Indicator over/under range".to_string());
+    }
+    let sign = if swb & 0x02 != 0 { -1.0 } else { 1.0 };
+    // SWB bit 4 set means kilograms, otherwise pounds
+    let unit = if swb & 0x10 != 0 { 1.0 } else { 0.453_592_37 };
+    Ok(Reading {
+        weight_kg: sign * raw * scale * unit,
+        stable: Some(swb & 0x08 == 0),
+    })
+}
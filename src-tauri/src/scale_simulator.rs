@@ -0,0 +1,223 @@
+// Weighbridge simulator for Truckore Pro
+// Demos, training and development need a weighbridge, and most laptops are
+// not wired to one. With the scale source set to "simulator", open_port
+// (scale.rs) hands back this link in place of a serial port or socket. It
+// writes frames in the configured protocol at an indicator's pace, so the
+// listener, stability detection, weight events and captures run exactly as
+// they do against real hardware. Trucks come and go in a loop: the deck sits
+// empty, a truck of random load drives on axle by axle, the reading rings
+// down and holds with load-cell noise, then the truck drives off.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+// Indicators send about ten readings a second
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+// Displayed weights step by this, in kg
+const RESOLUTION_KG: f64 = 10.0;
+// Scatter of the load cells under a standing truck, in kg
+const NOISE_KG: f64 = 3.0;
+// Bounce while a truck moves on the deck, in kg
+const MOTION_NOISE_KG: f64 = 40.0;
+const LOAD_KG: (f64, f64) = (6_000.0, 42_000.0);
+const EMPTY_SECS: (f64, f64) = (8.0, 15.0);
+const HOLD_SECS: (f64, f64) = (20.0, 40.0);
+const DRIVE_ON_SECS: f64 = 6.0;
+const SETTLE_SECS: f64 = 3.0;
+const DRIVE_OFF_SECS: f64 = 4.0;
+const STX: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Empty,
+    DriveOn,
+    Settle,
+    Hold,
+    DriveOff,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Ascii,
+    StGs,
+    Toledo,
+}
+
+pub struct Simulator {
+    format: Format,
+    rng: StdRng,
+    phase: Phase,
+    phase_started: Instant,
+    phase_secs: f64,
+    load_kg: f64,
+    axles: u32,
+    next_frame: Instant,
+    // Bytes of the current frame not yet read
+    pending: Vec<u8>,
+}
+
+// Whether the simulator can speak the scale driver `protocol`
+pub fn supports(protocol: &str) -> bool {
+    format(protocol).is_some()
+}
+
+fn format(protocol: &str) -> Option<Format> {
+    match protocol {
+        "ascii" => Some(Format::Ascii),
+        "st_gs" => Some(Format::StGs),
+        "toledo" => Some(Format::Toledo),
+        _ => None,
+    }
+}
+
+// 0 to 1 with zero slope at both ends, so ramps start and stop gently
+fn smoothstep(t: f64) -> f64 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl Simulator {
+    pub fn new(protocol: &str) -> Result<Simulator, String> {
+        let format = format(protocol).ok_or_else(|| {
+            format!(
+                "The simulator cannot send protocol {} (ascii, st_gs or toledo)",
+                protocol
+            )
+        })?;
+        let mut rng = StdRng::from_entropy();
+        let now = Instant::now();
+        Ok(Simulator {
+            format,
+            phase: Phase::Empty,
+            phase_started: now,
+            phase_secs: rng.gen_range(EMPTY_SECS.0..EMPTY_SECS.1),
+            load_kg: 0.0,
+            axles: 2,
+            next_frame: now,
+            pending: Vec::new(),
+            rng,
+        })
+    }
+
+    // Roughly normal, with standard deviation `kg`
+    fn noise(&mut self, kg: f64) -> f64 {
+        let sum: f64 = (0..3).map(|_| self.rng.gen::<f64>()).sum();
+        (sum - 1.5) * 2.0 * kg
+    }
+
+    fn enter(&mut self, phase: Phase, now: Instant) {
+        self.phase_secs = match phase {
+            Phase::Empty => self.rng.gen_range(EMPTY_SECS.0..EMPTY_SECS.1),
+            Phase::DriveOn => DRIVE_ON_SECS,
+            Phase::Settle => SETTLE_SECS,
+            Phase::Hold => self.rng.gen_range(HOLD_SECS.0..HOLD_SECS.1),
+            Phase::DriveOff => DRIVE_OFF_SECS,
+        };
+        if phase == Phase::DriveOn {
+            let load = self.rng.gen_range(LOAD_KG.0..LOAD_KG.1);
+            self.load_kg = (load / RESOLUTION_KG).round() * RESOLUTION_KG;
+            // Heavier trucks run on more axles: 2 to 6
+            self.axles = (2 + (self.load_kg / 10_000.0) as u32).min(6);
+        }
+        self.phase = phase;
+        self.phase_started = now;
+    }
+
+    // Weight on the deck now, and whether the indicator would call it stable
+    fn sample(&mut self, now: Instant) -> (f64, bool) {
+        let mut elapsed = now.duration_since(self.phase_started).as_secs_f64();
+        if elapsed >= self.phase_secs {
+            let next = match self.phase {
+                Phase::Empty => Phase::DriveOn,
+                Phase::DriveOn => Phase::Settle,
+                Phase::Settle => Phase::Hold,
+                Phase::Hold => Phase::DriveOff,
+                Phase::DriveOff => Phase::Empty,
+            };
+            self.enter(next, now);
+            elapsed = 0.0;
+        }
+        let t = elapsed / self.phase_secs;
+        match self.phase {
+            // Zero tracking holds an empty deck at exactly zero
+            Phase::Empty => (0.0, true),
+            Phase::DriveOn => {
+                // Each axle adds its share as it rolls onto the deck
+                let axle = t * self.axles as f64;
+                let share = (axle.floor() + smoothstep(axle.fract())) / self.axles as f64;
+                let weight = self.load_kg * share + self.noise(MOTION_NOISE_KG);
+                (weight, false)
+            }
+            Phase::Settle => {
+                let amplitude = (self.load_kg * 0.02).min(300.0) * (-4.0 * t).exp();
+                let ring = amplitude * (elapsed * 1.5 * std::f64::consts::TAU).sin();
+                (self.load_kg + ring + self.noise(NOISE_KG), false)
+            }
+            Phase::Hold => (self.load_kg + self.noise(NOISE_KG), true),
+            Phase::DriveOff => {
+                let weight = self.load_kg * (1.0 - smoothstep(t)) + self.noise(MOTION_NOISE_KG);
+                (weight.max(0.0), false)
+            }
+        }
+    }
+
+    fn frame(&self, weight_kg: f64, stable: bool) -> Vec<u8> {
+        let shown = ((weight_kg / RESOLUTION_KG).round() * RESOLUTION_KG) as i64;
+        match self.format {
+            Format::Ascii => format!("{:>8} kg\r\n", shown).into_bytes(),
+            Format::StGs => {
+                let header = if stable { "ST" } else { "US" };
+                format!("{},GS,{:+08}kg\r\n", header, shown).into_bytes()
+            }
+            Format::Toledo => {
+                // SWA: weight digits x1, increment x1; SWB: kilograms, plus
+                // the sign and motion bits
+                let mut swb = 0x30;
+                if shown < 0 {
+                    swb |= 0x02;
+                }
+                if !stable {
+                    swb |= 0x08;
+                }
+                let mut frame = vec![STX, 0x2A, swb, 0x20];
+                frame.extend(format!("{:06}{:06}\r", shown.abs(), 0).into_bytes());
+                frame
+            }
+        }
+    }
+}
+
+impl Read for Simulator {
+    // Blocks until the next frame is due, at most one frame interval, as a
+    // serial port blocks until its read timeout
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let now = Instant::now();
+            if self.next_frame > now {
+                std::thread::sleep(self.next_frame - now);
+            }
+            let now = Instant::now();
+            // A reader that paused does not get a burst of stale frames
+            self.next_frame = (self.next_frame + FRAME_INTERVAL).max(now);
+            let (weight_kg, stable) = self.sample(now);
+            self.pending = self.frame(weight_kg, stable);
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+// Commands sent to the indicator (identify requests) are accepted and ignored
+impl Write for Simulator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
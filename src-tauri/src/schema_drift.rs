@@ -0,0 +1,338 @@
+// Schema drift detection for Truckore Pro
+// Support staff at customer sites open the database in a SQLite browser and
+// "fix" it: an index dropped because inserts felt slow, a column renamed, a
+// table recreated without the columns migrations added. Queries then fail at
+// runtime, far from the cause. On startup the live schema is compared with
+// the one this release expects (schema.sql plus every migration, built in
+// memory): tables, columns and indexes. What can be put right without
+// touching data is repaired (missing tables and indexes are created, missing
+// columns added when SQLite allows it); anything else stops the start with a
+// report of what differs. Extra tables, columns and indexes are noted only.
+
+use crate::db;
+use crate::migrations;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftIssue {
+    // "table", "column" or "index"
+    pub kind: String,
+    pub table: String,
+    pub name: String,
+    // REPAIRED, REPAIRABLE (found by a check), BLOCKING or NOTICE
+    pub status: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftReport {
+    // Some difference cannot be repaired; the app does not start
+    pub blocked: bool,
+    pub issues: Vec<DriftIssue>,
+}
+
+struct Column {
+    name: String,
+    decl_type: String,
+    not_null: bool,
+    default: Option<String>,
+    pk: i64,
+}
+
+// The schema a fresh database of this release has
+fn expected() -> Result<Connection, String> {
+    let conn = Connection::open_in_memory().map_err(|e| e.to_string())?;
+    conn.execute_batch(db::SCHEMA).map_err(|e| e.to_string())?;
+    migrations::run(&conn)?;
+    Ok(conn)
+}
+
+// (name, table, sql) of the tables or indexes SQLite did not make itself
+fn objects(conn: &Connection, kind: &str) -> Result<Vec<(String, String, String)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name, tbl_name, sql FROM sqlite_master
+             WHERE type = ?1 AND name NOT LIKE 'sqlite_%' AND sql IS NOT NULL
+             ORDER BY name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([kind], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn object_sql(conn: &Connection, kind: &str, name: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT sql FROM sqlite_master WHERE type = ?1 AND name = ?2",
+        [kind, name],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<Column>, String> {
+    let mut stmt = conn
+        .prepare(r#"SELECT name, type, "notnull", dflt_value, pk FROM pragma_table_info(?1)"#)
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([table], |row| {
+            Ok(Column {
+                name: row.get(0)?,
+                decl_type: row.get(1)?,
+                not_null: row.get(2)?,
+                default: row.get(3)?,
+                pk: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Definition for ALTER TABLE ADD COLUMN, with the column's foreign key.
+// A CHECK on the column is not carried over.
+fn column_definition(conn: &Connection, table: &str, column: &Column) -> Result<String, String> {
+    let mut definition = format!("\"{}\" {}", column.name, column.decl_type);
+    if column.not_null {
+        definition.push_str(" NOT NULL");
+    }
+    if let Some(default) = &column.default {
+        definition.push_str(&format!(" DEFAULT {}", default));
+    }
+    let reference: Option<(String, Option<String>, String)> = conn
+        .query_row(
+            r#"SELECT "table", "to", on_delete FROM pragma_foreign_key_list(?1) WHERE "from" = ?2"#,
+            [table, &column.name],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some((parent, to, on_delete)) = reference {
+        definition.push_str(&format!(" REFERENCES \"{}\"", parent));
+        if let Some(to) = to {
+            definition.push_str(&format!("(\"{}\")", to));
+        }
+        if on_delete != "NO ACTION" {
+            definition.push_str(&format!(" ON DELETE {}", on_delete));
+        }
+    }
+    Ok(definition)
+}
+
+fn normalize(sql: &str) -> String {
+    sql.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+// Run one repair on its own savepoint, so a failed one leaves nothing behind
+fn attempt(conn: &Connection, sql: &str) -> Result<(), String> {
+    conn.execute_batch("SAVEPOINT drift_repair")
+        .map_err(|e| e.to_string())?;
+    match conn.execute_batch(sql) {
+        Ok(()) => conn
+            .execute_batch("RELEASE drift_repair")
+            .map_err(|e| e.to_string()),
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO drift_repair; RELEASE drift_repair")
+                .map_err(|e| e.to_string())?;
+            Err(e.to_string())
+        }
+    }
+}
+
+struct Comparison<'a> {
+    live: &'a Connection,
+    issues: Vec<DriftIssue>,
+}
+
+impl Comparison<'_> {
+    fn note(&mut self, kind: &str, table: &str, name: &str, status: &str, detail: String) {
+        self.issues.push(DriftIssue {
+            kind: kind.to_string(),
+            table: table.to_string(),
+            name: name.to_string(),
+            status: status.to_string(),
+            detail,
+        });
+    }
+
+    // Try `sql`; the issue is repairable when it runs, blocking when not
+    fn repair(&mut self, kind: &str, table: &str, name: &str, detail: &str, sql: &str) -> bool {
+        match attempt(self.live, sql) {
+            Ok(()) => {
+                self.note(kind, table, name, "REPAIRABLE", detail.to_string());
+                true
+            }
+            Err(e) => {
+                let detail = format!("{}; cannot be repaired automatically: {}", detail, e);
+                self.note(kind, table, name, "BLOCKING", detail);
+                false
+            }
+        }
+    }
+
+    fn compare_columns(&mut self, expected: &Connection, table: &str) -> Result<(), String> {
+        let live_columns = columns(self.live, table)?;
+        let wanted = columns(expected, table)?;
+        for column in &wanted {
+            let Some(found) = live_columns.iter().find(|c| c.name == column.name) else {
+                let sql = format!(
+                    "ALTER TABLE \"{}\" ADD COLUMN {}",
+                    table,
+                    column_definition(expected, table, column)?
+                );
+                self.repair("column", table, &column.name, "Column is missing", &sql);
+                continue;
+            };
+            if !found.decl_type.eq_ignore_ascii_case(&column.decl_type) || found.pk != column.pk {
+                let detail = format!(
+                    "Column is {}{} but this release expects {}{}",
+                    found.decl_type,
+                    if found.pk > 0 { " PRIMARY KEY" } else { "" },
+                    column.decl_type,
+                    if column.pk > 0 { " PRIMARY KEY" } else { "" },
+                );
+                self.note("column", table, &column.name, "BLOCKING", detail);
+            } else if found.not_null != column.not_null || found.default != column.default {
+                let detail = format!(
+                    "Column is{} NULL with default {} but this release expects{} NULL with default {}",
+                    if found.not_null { " NOT" } else { "" },
+                    found.default.as_deref().unwrap_or("none"),
+                    if column.not_null { " NOT" } else { "" },
+                    column.default.as_deref().unwrap_or("none"),
+                );
+                self.note("column", table, &column.name, "NOTICE", detail);
+            }
+        }
+        for extra in live_columns
+            .iter()
+            .filter(|c| !wanted.iter().any(|w| w.name == c.name))
+        {
+            let detail = "Column is not part of this release's schema".to_string();
+            self.note("column", table, &extra.name, "NOTICE", detail);
+        }
+        Ok(())
+    }
+}
+
+// Compare the live schema with this release's, trying each repair inside
+// one transaction that is committed only when `apply` is set and nothing
+// blocks
+fn compare(live: &Connection, apply: bool) -> Result<DriftReport, String> {
+    let expected = expected()?;
+    let tx = live.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut comparison = Comparison {
+        live,
+        issues: Vec::new(),
+    };
+
+    let tables = objects(&expected, "table")?;
+    // Full-text tables keep their rows in shadow tables SQLite manages
+    let virtual_tables: Vec<&str> = tables
+        .iter()
+        .filter(|(_, _, sql)| normalize(sql).starts_with("create virtual table"))
+        .map(|(name, _, _)| name.as_str())
+        .collect();
+    let shadow = |name: &str| {
+        virtual_tables
+            .iter()
+            .any(|v| name.starts_with(&format!("{}_", v)))
+    };
+    let mut present = HashSet::new();
+    for (name, _, sql) in tables.iter().filter(|(name, _, _)| !shadow(name)) {
+        if object_sql(live, "table", name)?.is_some() {
+            if !virtual_tables.contains(&name.as_str()) {
+                comparison.compare_columns(&expected, name)?;
+            }
+            present.insert(name.clone());
+        } else if virtual_tables.contains(&name.as_str()) {
+            let detail =
+                "Search index is missing; restore a backup or rebuild it with database recovery";
+            comparison.note("table", name, name, "BLOCKING", detail.to_string());
+        } else if comparison.repair("table", name, name, "Table is missing", sql) {
+            present.insert(name.clone());
+        }
+    }
+    for (name, _, _) in objects(live, "table")? {
+        if !shadow(&name) && !tables.iter().any(|(t, _, _)| *t == name) {
+            let detail = "Table is not part of this release's schema".to_string();
+            comparison.note("table", &name, &name, "NOTICE", detail);
+        }
+    }
+
+    let indexes = objects(&expected, "index")?;
+    for (name, table, sql) in indexes.iter().filter(|(_, t, _)| present.contains(t)) {
+        match object_sql(live, "index", name)? {
+            None => {
+                comparison.repair("index", table, name, "Index is missing", sql);
+            }
+            Some(found) if normalize(&found) != normalize(sql) => {
+                let repair = format!("DROP INDEX \"{}\"; {}", name, sql);
+                let detail = format!("Index is defined as {}", found);
+                comparison.repair("index", table, name, &detail, &repair);
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, table, _) in objects(live, "index")? {
+        if !indexes.iter().any(|(i, _, _)| *i == name) {
+            let detail = "Index is not part of this release's schema".to_string();
+            comparison.note("index", &table, &name, "NOTICE", detail);
+        }
+    }
+
+    let mut issues = comparison.issues;
+    let blocked = issues.iter().any(|i| i.status == "BLOCKING");
+    if apply && !blocked {
+        tx.commit().map_err(|e| e.to_string())?;
+        for issue in issues.iter_mut().filter(|i| i.status == "REPAIRABLE") {
+            issue.status = "REPAIRED".to_string();
+            tracing::warn!(
+                kind = %issue.kind,
+                table = %issue.table,
+                name = %issue.name,
+                detail = %issue.detail,
+                "Schema drift repaired"
+            );
+        }
+    }
+    Ok(DriftReport { blocked, issues })
+}
+
+// Startup: repair what differs, or refuse to start with what cannot be
+// repaired. Expects migrations to have run.
+pub fn repair(conn: &Connection) -> Result<DriftReport, String> {
+    let report = compare(conn, true)?;
+    if report.blocked {
+        let blocking: Vec<String> = report
+            .issues
+            .iter()
+            .filter(|i| i.status == "BLOCKING")
+            .map(|i| match i.kind.as_str() {
+                "table" => format!("table {}: {}", i.table, i.detail),
+                _ => format!("{} {}.{}: {}", i.kind, i.table, i.name, i.detail),
+            })
+            .collect();
+        return Err(format!(
+            "The database schema was changed outside the app and cannot be repaired automatically ({})",
+            blocking.join("; ")
+        ));
+    }
+    Ok(report)
+}
+
+// How the live schema differs from this release's, without changing it
+// (admin only)
+#[tauri::command]
+pub fn check_schema_drift(app: AppHandle) -> Result<DriftReport, String> {
+    let conn = db::open(&app)?;
+    compare(&conn, false)
+}
@@ -0,0 +1,415 @@
+// Site scripting hooks for Truckore Pro
+// Sites can add business rules we do not ship as Rhai scripts. Each hook is a
+// script defining a function of the same name:
+//   on_ticket_create(ticket)    - before a ticket is saved; may return a map
+//                                 of derived fields
+//   on_weight_captured(weight)  - after a stable weight is captured
+//   on_before_print(fields)     - before a slip is laid out or printed; may
+//                                 return a map of replacement field values
+// `throw "reason"` refuses the action with that message. Any other failure is
+// recorded in script_errors and the action goes ahead as if the hook were off.
+// Scripts run sandboxed: no file or module access and a bounded operation count.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::AppHandle;
+
+pub const TICKET_CREATE: &str = "on_ticket_create";
+pub const WEIGHT_CAPTURED: &str = "on_weight_captured";
+pub const BEFORE_PRINT: &str = "on_before_print";
+const HOOKS: &[&str] = &[TICKET_CREATE, WEIGHT_CAPTURED, BEFORE_PRINT];
+
+// Enough for a few thousand statements; runaway loops stop here
+const MAX_OPERATIONS: u64 = 200_000;
+const DEFAULT_ERROR_LIMIT: u32 = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScriptVersion {
+    pub id: i64,
+    pub hook: String,
+    pub version: i64,
+    pub source: String,
+    pub notes: Option<String>,
+    pub created_by: String,
+    pub created_at: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HookStatus {
+    pub hook: String,
+    pub active_version: Option<i64>,
+    pub activated_by: Option<String>,
+    pub activated_at: Option<String>,
+    pub latest_version: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScriptError {
+    pub id: i64,
+    pub hook: String,
+    pub version: i64,
+    // Ticket number or id the hook ran for
+    pub subject: Option<String>,
+    pub message: String,
+    pub created_at: String,
+}
+
+// Result of a dry run from the script editor
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScriptTest {
+    pub result: Option<Value>,
+    // Set when the script threw to refuse the action
+    pub rejected: Option<String>,
+}
+
+enum Failure {
+    // The script threw on purpose
+    Rejected(String),
+    Fault(String),
+}
+
+fn check_hook(hook: &str) -> Result<(), String> {
+    if HOOKS.contains(&hook) {
+        Ok(())
+    } else {
+        Err(format!("Unknown script hook: {}", hook))
+    }
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(1_000);
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine
+}
+
+fn compile(engine: &Engine, hook: &str, source: &str) -> Result<AST, String> {
+    let ast = engine
+        .compile(source)
+        .map_err(|e| format!("Script does not compile: {}", e))?;
+    if !ast
+        .iter_functions()
+        .any(|f| f.name == hook && f.params.len() == 1)
+    {
+        return Err(format!("Script must define fn {}(input)", hook));
+    }
+    Ok(ast)
+}
+
+// A `throw` inside the hook, possibly wrapped by the function call
+fn rejection(error: &EvalAltResult) -> Option<String> {
+    match error {
+        EvalAltResult::ErrorRuntime(value, _) => Some(value.to_string()),
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => rejection(inner),
+        _ => None,
+    }
+}
+
+fn run(hook: &str, source: &str, input: &Value) -> Result<Option<Value>, Failure> {
+    let engine = engine();
+    let ast = compile(&engine, hook, source).map_err(Failure::Fault)?;
+    let input = rhai::serde::to_dynamic(input).map_err(|e| Failure::Fault(e.to_string()))?;
+    let result: Dynamic = engine
+        .call_fn(&mut Scope::new(), &ast, hook, (input,))
+        .map_err(|e| match rejection(&e) {
+            Some(reason) => Failure::Rejected(reason),
+            None => Failure::Fault(e.to_string()),
+        })?;
+    if result.is_unit() {
+        return Ok(None);
+    }
+    rhai::serde::from_dynamic(&result)
+        .map(Some)
+        .map_err(|e| Failure::Fault(format!("Unusable return value: {}", e)))
+}
+
+fn active(conn: &Connection, hook: &str) -> Result<Option<(i64, String)>, String> {
+    conn.query_row(
+        "SELECT v.id, v.source FROM active_scripts a
+         JOIN script_versions v ON v.id = a.version_id
+         WHERE a.hook = ?1",
+        [hook],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Run a hook's active script, if any. Returns what the script returned; a
+// throw becomes the error, a faulty script is recorded and yields None.
+pub fn call(
+    conn: &Connection,
+    hook: &str,
+    subject: Option<&str>,
+    input: &Value,
+) -> Result<Option<Value>, String> {
+    let Some((version_id, source)) = active(conn, hook)? else {
+        return Ok(None);
+    };
+    match run(hook, &source, input) {
+        Ok(result) => Ok(result),
+        Err(Failure::Rejected(reason)) => Err(reason),
+        Err(Failure::Fault(message)) => {
+            conn.execute(
+                "INSERT INTO script_errors (version_id, hook, subject, message)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![version_id, hook, subject, message],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(None)
+        }
+    }
+}
+
+// Apply a returned map onto `fields`, keeping only keys the hook was given.
+// Anything else the script returns is ignored.
+pub fn merge(fields: &mut Map<String, Value>, result: Option<Value>) {
+    if let Some(Value::Object(changes)) = result {
+        for (key, value) in changes {
+            if let Some(field) = fields.get_mut(&key) {
+                *field = value;
+            }
+        }
+    }
+}
+
+// Run on_before_print over a ticket's slip fields (key -> text)
+pub fn before_print(
+    conn: &Connection,
+    subject: &str,
+    fields: &mut [(&'static str, &'static str, String)],
+) -> Result<(), String> {
+    let mut input: Map<String, Value> = fields
+        .iter()
+        .map(|(key, _, value)| (key.to_string(), Value::String(value.clone())))
+        .collect();
+    let result = call(
+        conn,
+        BEFORE_PRINT,
+        Some(subject),
+        &Value::Object(input.clone()),
+    )?;
+    merge(&mut input, result);
+    for (key, _, value) in fields.iter_mut() {
+        match input.get(*key) {
+            Some(Value::String(text)) => *value = text.clone(),
+            Some(Value::Null) | None => {}
+            Some(other) => *value = other.to_string(),
+        }
+    }
+    Ok(())
+}
+
+fn row_to_version(row: &rusqlite::Row) -> rusqlite::Result<ScriptVersion> {
+    Ok(ScriptVersion {
+        id: row.get(0)?,
+        hook: row.get(1)?,
+        version: row.get(2)?,
+        source: row.get(3)?,
+        notes: row.get(4)?,
+        created_by: row.get(5)?,
+        created_at: row.get(6)?,
+        active: row.get(7)?,
+    })
+}
+
+const VERSION_COLUMNS: &str = "v.id, v.hook, v.version, v.source, v.notes, v.created_by,
+    v.created_at, v.id IN (SELECT version_id FROM active_scripts)";
+
+#[tauri::command]
+pub fn list_scripts(app: AppHandle) -> Result<Vec<HookStatus>, String> {
+    let conn = db::open(&app)?;
+    HOOKS
+        .iter()
+        .map(|hook| {
+            conn.query_row(
+                "SELECT (SELECT v.version FROM active_scripts a
+                         JOIN script_versions v ON v.id = a.version_id WHERE a.hook = ?1),
+                        (SELECT activated_by FROM active_scripts WHERE hook = ?1),
+                        (SELECT activated_at FROM active_scripts WHERE hook = ?1),
+                        (SELECT MAX(version) FROM script_versions WHERE hook = ?1)",
+                [hook],
+                |row| {
+                    Ok(HookStatus {
+                        hook: hook.to_string(),
+                        active_version: row.get(0)?,
+                        activated_by: row.get(1)?,
+                        activated_at: row.get(2)?,
+                        latest_version: row.get(3)?,
+                    })
+                },
+            )
+            .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+// Every saved revision of a hook's script, newest first
+#[tauri::command]
+pub fn list_script_versions(app: AppHandle, hook: String) -> Result<Vec<ScriptVersion>, String> {
+    check_hook(&hook)?;
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM script_versions v WHERE v.hook = ?1 ORDER BY v.version DESC",
+            VERSION_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([&hook], row_to_version)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn set_active(conn: &Connection, hook: &str, version_id: i64, user_id: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO active_scripts (hook, version_id, activated_by) VALUES (?1, ?2, ?3)
+         ON CONFLICT(hook) DO UPDATE SET version_id = excluded.version_id,
+             activated_by = excluded.activated_by, activated_at = CURRENT_TIMESTAMP",
+        params![hook, version_id, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Save a new revision of a hook's script (supervisors only). Scripts that do
+// not compile are refused. With `activate` the revision goes live at once.
+#[tauri::command]
+pub fn save_script(
+    app: AppHandle,
+    hook: String,
+    source: String,
+    notes: Option<String>,
+    activate: bool,
+    user_id: String,
+) -> Result<ScriptVersion, String> {
+    let args = serde_json::json!({ "hook": hook, "notes": notes, "activate": activate });
+    command_audit::audited(&app, "save_script", &user_id, args, || {
+        check_hook(&hook)?;
+        let mut conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        compile(&engine(), &hook, &source)?;
+
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO script_versions (hook, version, source, notes, created_by)
+             VALUES (?1, (SELECT COALESCE(MAX(version), 0) + 1 FROM script_versions
+                          WHERE hook = ?1), ?2, ?3, ?4)",
+            params![hook, source, notes, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+        let id = tx.last_insert_rowid();
+        if activate {
+            set_active(&tx, &hook, id, &user_id)?;
+        }
+        let saved = tx
+            .query_row(
+                &format!(
+                    "SELECT {} FROM script_versions v WHERE v.id = ?1",
+                    VERSION_COLUMNS
+                ),
+                [id],
+                row_to_version,
+            )
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(saved)
+    })
+}
+
+// Run a saved revision of a hook, or turn the hook off with `version`
+// omitted (supervisors only)
+#[tauri::command]
+pub fn activate_script(
+    app: AppHandle,
+    hook: String,
+    version: Option<i64>,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "hook": hook, "version": version });
+    command_audit::audited(&app, "activate_script", &user_id, args, || {
+        check_hook(&hook)?;
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let Some(version) = version else {
+            conn.execute("DELETE FROM active_scripts WHERE hook = ?1", [&hook])
+                .map_err(|e| e.to_string())?;
+            return Ok(());
+        };
+        let id: i64 = conn
+            .query_row(
+                "SELECT id FROM script_versions WHERE hook = ?1 AND version = ?2",
+                params![hook, version],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("{} has no version {}", hook, version))?;
+        set_active(&conn, &hook, id, &user_id)
+    })
+}
+
+// Dry-run a script against a sample input without saving it
+#[tauri::command]
+pub fn test_script(hook: String, source: String, input: Value) -> Result<ScriptTest, String> {
+    check_hook(&hook)?;
+    match run(&hook, &source, &input) {
+        Ok(result) => Ok(ScriptTest {
+            result,
+            rejected: None,
+        }),
+        Err(Failure::Rejected(reason)) => Ok(ScriptTest {
+            result: None,
+            rejected: Some(reason),
+        }),
+        Err(Failure::Fault(message)) => Err(message),
+    }
+}
+
+#[tauri::command]
+pub fn list_script_errors(
+    app: AppHandle,
+    hook: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<ScriptError>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT e.id, e.hook, v.version, e.subject, e.message, e.created_at
+             FROM script_errors e JOIN script_versions v ON v.id = e.version_id
+             WHERE ?1 IS NULL OR e.hook = ?1
+             ORDER BY e.id DESC LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![hook, limit.unwrap_or(DEFAULT_ERROR_LIMIT)], |row| {
+            Ok(ScriptError {
+                id: row.get(0)?,
+                hook: row.get(1)?,
+                version: row.get(2)?,
+                subject: row.get(3)?,
+                message: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,248 @@
+// Full-text search for Truckore Pro
+// The global search box ran LIKE '%...%' over whole tables, which scans
+// every row and misses a vehicle number typed with different spacing. A
+// trigram FTS5 index (search_index) now holds the searchable text of each
+// weighment, vehicle and party, so any three or more characters match
+// inside a word, ranked by bm25. Vehicle numbers are also indexed with
+// spaces, dashes and dots removed, so "TN 38 AB 1234" is found as
+// "tn38ab". Triggers created by migration 7 keep the index current;
+// search_documents maps index rows to the records, since those are keyed by
+// text ids.
+
+use crate::db;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 200;
+// Trigram matching needs at least this many characters
+const MIN_TERM_CHARS: usize = 3;
+
+// A searchable record type. `{r}` in the expressions stands for the row
+// being indexed.
+struct Indexed {
+    entity: &'static str,
+    table: &'static str,
+    // Columns whose change re-indexes the row
+    columns: &'static str,
+    body: &'static str,
+    plate: &'static str,
+    title: &'static str,
+    detail: &'static str,
+}
+
+const INDEXED: &[Indexed] = &[
+    Indexed {
+        entity: "weighment",
+        table: "weighments",
+        columns: "bill_no, ticket_no, vehicle_no, party_name, product_name, remarks",
+        body: "{r}bill_no || ' ' || {r}ticket_no || ' ' || {r}vehicle_no || ' ' || \
+               {r}party_name || ' ' || {r}product_name || ' ' || COALESCE({r}remarks, '')",
+        plate: "upper(replace(replace(replace({r}vehicle_no, ' ', ''), '-', ''), '.', ''))",
+        title: "bill_no || ' · ' || vehicle_no",
+        detail: "party_name || ' · ' || product_name || ' · ' || created_at",
+    },
+    Indexed {
+        entity: "vehicle",
+        table: "vehicles",
+        columns: "vehicle_no",
+        body: "{r}vehicle_no",
+        plate: "upper(replace(replace(replace({r}vehicle_no, ' ', ''), '-', ''), '.', ''))",
+        title: "vehicle_no",
+        detail: "NULL",
+    },
+    Indexed {
+        entity: "party",
+        table: "parties",
+        columns: "party_name",
+        body: "{r}party_name",
+        plate: "NULL",
+        title: "party_name",
+        detail: "NULL",
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub entity: String,
+    pub id: String,
+    pub title: String,
+    pub detail: Option<String>,
+    // bm25 score; lower is a better match
+    pub rank: f64,
+}
+
+// Index tables, the triggers that maintain them, and the existing rows
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS search_documents (
+             doc INTEGER PRIMARY KEY AUTOINCREMENT,
+             entity TEXT NOT NULL,
+             id TEXT NOT NULL,
+             UNIQUE(entity, id)
+         );
+         CREATE VIRTUAL TABLE IF NOT EXISTS search_index
+             USING fts5(body, plate, tokenize = 'trigram');",
+    )
+    .map_err(|e| e.to_string())?;
+    for indexed in INDEXED {
+        let expr = |template: &str, row: &str| template.replace("{r}", row);
+        let add = |row: &str| {
+            format!(
+                "INSERT INTO search_documents (entity, id) VALUES ('{e}', {row}id)
+                 ON CONFLICT(entity, id) DO NOTHING;
+                 INSERT INTO search_index (rowid, body, plate)
+                 SELECT doc, {body}, {plate} FROM search_documents
+                 WHERE entity = '{e}' AND id = {row}id;",
+                e = indexed.entity,
+                row = row,
+                body = expr(indexed.body, row),
+                plate = expr(indexed.plate, row),
+            )
+        };
+        let remove = format!(
+            "DELETE FROM search_index WHERE rowid =
+                 (SELECT doc FROM search_documents WHERE entity = '{e}' AND id = OLD.id);
+             DELETE FROM search_documents WHERE entity = '{e}' AND id = OLD.id;",
+            e = indexed.entity
+        );
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER {t}_search_insert AFTER INSERT ON {t}
+             BEGIN {add_new} END;
+             CREATE TRIGGER {t}_search_update AFTER UPDATE OF {columns} ON {t}
+             BEGIN {remove} {add_new} END;
+             CREATE TRIGGER {t}_search_delete AFTER DELETE ON {t}
+             BEGIN {remove} END;
+             INSERT INTO search_documents (entity, id)
+             SELECT '{e}', id FROM {t} WHERE true
+             ON CONFLICT(entity, id) DO NOTHING;
+             INSERT INTO search_index (rowid, body, plate)
+             SELECT d.doc, {body}, {plate}
+             FROM {t} JOIN search_documents d ON d.entity = '{e}' AND d.id = {t}.id;",
+            t = indexed.table,
+            e = indexed.entity,
+            columns = indexed.columns,
+            add_new = add("NEW."),
+            remove = remove,
+            body = expr(indexed.body, &format!("{}.", indexed.table)),
+            plate = expr(indexed.plate, &format!("{}.", indexed.table)),
+        ))
+        .map_err(|e| format!("Search index for {} failed: {}", indexed.table, e))?;
+    }
+    Ok(())
+}
+
+fn phrase(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+// FTS5 query for the typed text: every word, or the text as a vehicle
+// number. None when nothing is long enough to match.
+fn match_expression(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .filter(|w| w.chars().count() >= MIN_TERM_CHARS)
+        .map(phrase)
+        .collect();
+    let plate: String = text
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.'))
+        .collect::<String>()
+        .to_uppercase();
+    let mut alternatives = Vec::new();
+    if !words.is_empty() {
+        alternatives.push(format!("({})", words.join(" AND ")));
+    }
+    if plate.chars().count() >= MIN_TERM_CHARS {
+        alternatives.push(phrase(&plate));
+    }
+    (!alternatives.is_empty()).then(|| alternatives.join(" OR "))
+}
+
+fn describe(
+    conn: &Connection,
+    indexed: &Indexed,
+    id: &str,
+) -> Result<Option<(String, Option<String>)>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {}, {} FROM {} WHERE id = ?1",
+            indexed.title, indexed.detail, indexed.table
+        ),
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Title and detail of a record as search results show them, by entity
+// ("weighment", "vehicle" or "party") and id
+pub(crate) fn describe_record(
+    conn: &Connection,
+    entity: &str,
+    id: &str,
+) -> Result<Option<(String, Option<String>)>, String> {
+    match INDEXED.iter().find(|i| i.entity == entity) {
+        Some(indexed) => describe(conn, indexed, id),
+        None => Ok(None),
+    }
+}
+
+// Ranked matches for the global search box, from one entity ("weighment",
+// "vehicle" or "party") or all of them
+#[tauri::command]
+pub fn search(
+    app: AppHandle,
+    entity: Option<String>,
+    text: String,
+    limit: Option<i64>,
+) -> Result<Vec<SearchResult>, String> {
+    if let Some(entity) = entity.as_deref() {
+        if !INDEXED.iter().any(|i| i.entity == entity) {
+            return Err(format!("Unknown search entity {}", entity));
+        }
+    }
+    let Some(expression) = match_expression(&text) else {
+        return Ok(Vec::new());
+    };
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT d.entity, d.id, bm25(search_index) AS rank
+             FROM search_index JOIN search_documents d ON d.doc = search_index.rowid
+             WHERE search_index MATCH ?1 AND (?2 IS NULL OR d.entity = ?2)
+             ORDER BY rank LIMIT ?3",
+        )
+        .map_err(|e| e.to_string())?;
+    let hits = stmt
+        .query_map(params![expression, entity, limit], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::new();
+    for (entity, id, rank) in hits {
+        let Some(indexed) = INDEXED.iter().find(|i| i.entity == entity) else {
+            continue;
+        };
+        if let Some((title, detail)) = describe(&conn, indexed, &id)? {
+            results.push(SearchResult {
+                entity,
+                id,
+                title,
+                detail,
+                rank,
+            });
+        }
+    }
+    Ok(results)
+}
@@ -0,0 +1,247 @@
+// Stored credentials for Truckore Pro
+// Passwords the backend must replay to other systems (the SMTP login) are
+// sealed with ChaCha20-Poly1305 under a key kept in the OS credential store
+// (Windows Credential Manager, the macOS keychain or the Secret Service),
+// so a copied database, backup or support bundle does not give them away.
+// Losing the key only means re-entering the passwords. Where no credential
+// store is reachable (a Linux PC without a keyring daemon) the key is kept
+// in a file beside the data folders instead, limited to this user (mode
+// 0600, or on Windows a protected DACL for the owner and SYSTEM) and never
+// added to a support bundle. A key file left from before is moved into the
+// store once it can be.
+
+use base64::{engine::general_purpose, Engine as _};
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+const KEY_FILE: &str = "secrets.key";
+// Credential store entry holding the key, base64
+const KEYRING_SERVICE: &str = "truckore-pro";
+const KEYRING_USER: &str = "secrets-key";
+const SEALED_PREFIX: &str = "sealed:v1:";
+
+fn key_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(KEY_FILE))
+        .ok_or_else(|| "Failed to get app data directory".to_string())
+}
+
+// Whether `path` is the key file; bundles for support skip it
+pub fn is_key_file(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()) == Some(KEY_FILE)
+}
+
+#[cfg(unix)]
+fn restrict(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())
+}
+
+// Replace the inherited ACL with a protected one: full access for the
+// file's owner and SYSTEM, nobody else
+#[cfg(windows)]
+fn restrict(path: &Path) -> Result<(), String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use windows_sys::Win32::Security::{
+        SetFileSecurityW, DACL_SECURITY_INFORMATION, PROTECTED_DACL_SECURITY_INFORMATION,
+        PSECURITY_DESCRIPTOR,
+    };
+    use windows_sys::Win32::System::Memory::LocalFree;
+    let sddl: Vec<u16> = "D:P(A;;FA;;;OW)(A;;FA;;;SY)"
+        .encode_utf16()
+        .chain(Some(0))
+        .collect();
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut descriptor: PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+    let converted = unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            sddl.as_ptr(),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            std::ptr::null_mut(),
+        )
+    };
+    if converted == 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    let applied = unsafe {
+        SetFileSecurityW(
+            wide.as_ptr(),
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            descriptor,
+        )
+    };
+    let error = std::io::Error::last_os_error();
+    unsafe { LocalFree(descriptor as isize) };
+    if applied == 0 {
+        return Err(error.to_string());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn restrict(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+fn new_key() -> Vec<u8> {
+    let mut bytes = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+// The key file, created on first use and readable by this user only. Only
+// a missing file is created, never one that failed to read, and never over
+// one another thread created first. The file is restricted while still
+// empty, so the key is never readable by others; keys made before the ACL
+// change are restricted when next read.
+fn file_key(path: &Path) -> Result<Vec<u8>, String> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            if let Err(e) = restrict(path) {
+                tracing::warn!(error = %e, "Could not restrict {}", path.display());
+            }
+            return Ok(bytes);
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e)),
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            return fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+        }
+        Err(e) => return Err(format!("Cannot create {}: {}", path.display(), e)),
+    };
+    restrict(path)?;
+    let bytes = new_key();
+    file.write_all(&bytes)
+        .and_then(|()| file.sync_all())
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(bytes)
+}
+
+// The key from the credential store, moving a key file into it or creating
+// the key on first use. None when the store cannot be reached.
+fn stored_key(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    let entry = match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER) {
+        Ok(entry) => entry,
+        Err(e) => {
+            tracing::warn!(error = %e, "Credential store unavailable");
+            return Ok(None);
+        }
+    };
+    let stored = match entry.get_password() {
+        Ok(encoded) => encoded,
+        Err(keyring::Error::NoEntry) => {
+            let bytes = match path.exists() {
+                true => file_key(path)?,
+                false => new_key(),
+            };
+            match entry.set_password(&general_purpose::STANDARD.encode(&bytes)) {
+                Ok(()) => {}
+                Err(keyring::Error::NoStorageAccess(_) | keyring::Error::PlatformFailure(_)) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(format!("Cannot store the secrets key: {}", e)),
+            }
+            if path.exists() {
+                if let Err(e) = fs::remove_file(path) {
+                    tracing::warn!(error = %e, "Could not remove {}", path.display());
+                }
+            }
+            return Ok(Some(bytes));
+        }
+        Err(keyring::Error::NoStorageAccess(e) | keyring::Error::PlatformFailure(e)) => {
+            tracing::warn!(error = %e, "Credential store unavailable");
+            return Ok(None);
+        }
+        Err(e) => return Err(format!("Cannot read the secrets key: {}", e)),
+    };
+    general_purpose::STANDARD
+        .decode(stored)
+        .map(Some)
+        .map_err(|_| "The stored secrets key is damaged".to_string())
+}
+
+// The site's key, from the credential store or else the key file. One
+// caller at a time, so two first uses cannot each create a key.
+fn key(app: &AppHandle) -> Result<LessSafeKey, String> {
+    static FIRST_USE: Mutex<()> = Mutex::new(());
+    let _first_use = FIRST_USE.lock().map_err(|e| e.to_string())?;
+    let path = key_path(app)?;
+    let bytes = match stored_key(&path)? {
+        Some(bytes) => bytes,
+        None => file_key(&path)?,
+    };
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+        .map_err(|_| "The secrets key is not a usable key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+pub fn seal(app: &AppHandle, secret: &str) -> Result<String, String> {
+    let key = key(app)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let mut data = secret.as_bytes().to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| "Failed to seal the secret".to_string())?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(data);
+    Ok(format!(
+        "{}{}",
+        SEALED_PREFIX,
+        general_purpose::STANDARD.encode(sealed)
+    ))
+}
+
+pub fn open(app: &AppHandle, sealed: &str) -> Result<String, String> {
+    let unreadable = || "A stored password cannot be read on this PC; enter it again".to_string();
+    let encoded = sealed.strip_prefix(SEALED_PREFIX).ok_or_else(unreadable)?;
+    let bytes = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| unreadable())?;
+    if bytes.len() < NONCE_LEN {
+        return Err(unreadable());
+    }
+    let (nonce, data) = bytes.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| unreadable())?;
+    let mut data = data.to_vec();
+    let plain = key(app)?
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| unreadable())?;
+    String::from_utf8(plain.to_vec()).map_err(|_| unreadable())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_key_file_is_created_once_and_never_replaced() {
+        let dir = std::env::temp_dir().join(format!("secrets-{}", uuid::Uuid::new_v4()));
+        let path = dir.join(KEY_FILE);
+        let created = file_key(&path).unwrap();
+        assert_eq!(created.len(), 32);
+        assert_eq!(file_key(&path).unwrap(), created);
+
+        // A key file that cannot be read is an error, not a new key
+        let unreadable = dir.join("unreadable");
+        fs::create_dir_all(unreadable.join(KEY_FILE)).unwrap();
+        assert!(file_key(&unreadable.join(KEY_FILE)).is_err());
+        assert_eq!(fs::read(&path).unwrap(), created);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
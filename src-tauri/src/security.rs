@@ -0,0 +1,19 @@
+// Security event logging into the security_logs table shared with the frontend
+
+use rusqlite::{params, Connection};
+
+// Record a security-relevant action
+pub fn log_event(
+    conn: &Connection,
+    user_id: Option<&str>,
+    action: &str,
+    details: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO security_logs (id, user_id, action, details, timestamp)
+         VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)",
+        params![uuid::Uuid::new_v4().to_string(), user_id, action, details],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,372 @@
+// Ticket serial numbers for Truckore Pro
+// Backend counterpart of the frontend serial number service, for commands
+// that create tickets without the UI. Both read and advance the same
+// app_config.serial_number_config, so numbers stay in one sequence.
+// Kiosk and booking flows can reserve a number to show the driver before
+// capture; a reservation that is released or expires goes back in the pool.
+// Several windows or lanes may take numbers at once, so every allocation
+// goes through the database writer (db::write), one at a time, and a ticket
+// number already on a ticket is refused when the ticket is saved.
+
+use crate::db;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::AppHandle;
+
+const CONFIG_KEY: &str = "serial_number_config";
+const DEFAULT_RESERVATION_MINUTES: i64 = 30;
+const MAX_RESERVATION_MINUTES: i64 = 24 * 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketReservation {
+    // Pass to create_weighment as `reservation` with the ticket number
+    pub token: String,
+    pub ticket_no: String,
+    pub purpose: Option<String>,
+    pub reserved_at: String,
+    pub expires_at: String,
+}
+
+// Take back the lowest released or expired number of the current period
+// (`stem` is the serial up to the counter), unless a ticket already has it
+fn reclaim(conn: &Connection, stem: &str) -> Result<Option<String>, String> {
+    conn.execute(
+        "UPDATE ticket_number_reservations SET status = 'RELEASED', closed_at = CURRENT_TIMESTAMP
+         WHERE status = 'RESERVED' AND expires_at <= CURRENT_TIMESTAMP",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    let serial: Option<String> = conn
+        .query_row(
+            "SELECT serial FROM ticket_number_reservations
+             WHERE status = 'RELEASED' AND substr(serial, 1, length(?1)) = ?1
+               AND serial NOT IN (SELECT ticket_no FROM weighments)
+             ORDER BY serial LIMIT 1",
+            [stem],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(serial) = &serial {
+        // The ticket itself now guards against the number being reissued
+        conn.execute(
+            "DELETE FROM ticket_number_reservations WHERE serial = ?1",
+            [serial],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(serial)
+}
+
+// Format the next serial number and advance the counter, resetting it first
+// when the configured yearly/monthly period has rolled over. Numbers released
+// in the current period are handed out again before the counter moves;
+// those left over from an earlier period stay unused.
+pub fn next(conn: &Connection) -> Result<String, String> {
+    let mut config: Value = match db::get_config(conn, CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+        None => return Err("Serial number format is not configured".to_string()),
+    };
+    let text = |key: &str, default: &str| -> String {
+        config[key].as_str().unwrap_or(default).to_string()
+    };
+    let prefix = text("prefix", "WB");
+    let separator = text("separator", "-");
+    let year_format = text("yearFormat", "YYYY");
+    let reset_frequency = text("resetFrequency", "never");
+    let last_reset = config["lastResetDate"].as_str().map(|s| s.to_string());
+    let counter_start = config["counterStart"].as_i64().unwrap_or(1);
+    let padding = config["counterPadding"].as_u64().unwrap_or(3) as usize;
+    let mut counter = config["currentCounter"].as_i64().unwrap_or(counter_start);
+
+    let (year, month, reset_year, reset_month, now_iso): (
+        String,
+        String,
+        Option<String>,
+        Option<String>,
+        String,
+    ) = conn
+        .query_row(
+            "SELECT strftime('%Y', 'now', 'localtime'), strftime('%m', 'now', 'localtime'),
+                    strftime('%Y', ?1, 'localtime'), strftime('%m', ?1, 'localtime'),
+                    strftime('%Y-%m-%dT%H:%M:%fZ', 'now')",
+            [&last_reset],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+    let rolled_over = match (reset_frequency.as_str(), reset_year, reset_month) {
+        ("yearly", Some(y), _) => year > y,
+        ("monthly", Some(y), Some(m)) => (year.as_str(), month.as_str()) > (y.as_str(), m.as_str()),
+        _ => false,
+    };
+    if rolled_over {
+        counter = counter_start;
+        config["lastResetDate"] = json!(now_iso);
+    }
+
+    let mut serial = prefix;
+    if config["includeYear"].as_bool().unwrap_or(false) {
+        let year = if year_format == "YY" {
+            &year[2..]
+        } else {
+            &year[..]
+        };
+        serial.push_str(&separator);
+        serial.push_str(year);
+    }
+    if config["includeMonth"].as_bool().unwrap_or(false) {
+        serial.push_str(&separator);
+        serial.push_str(&month);
+    }
+    serial.push_str(&separator);
+    if !rolled_over {
+        if let Some(reclaimed) = reclaim(conn, &serial)? {
+            return Ok(reclaimed);
+        }
+    }
+    serial.push_str(&format!("{:0width$}", counter, width = padding));
+
+    config["currentCounter"] = json!(counter + 1);
+    db::set_config(conn, CONFIG_KEY, &config.to_string())?;
+    Ok(serial)
+}
+
+// Mark the ticket's number as used, after the ticket row is written in the
+// same transaction. A number on another ticket is refused. A number reserved
+// by another flow needs that reservation's token; expired reservations can
+// be taken by anyone.
+pub fn claim(
+    conn: &Connection,
+    ticket_no: &str,
+    token: Option<&str>,
+    weighment_id: &str,
+) -> Result<(), String> {
+    let reservation: Option<(String, Option<String>, bool)> = conn
+        .query_row(
+            "SELECT status, token, expires_at <= CURRENT_TIMESTAMP
+             FROM ticket_number_reservations WHERE serial = ?1",
+            [ticket_no],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let taken: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM weighments WHERE ticket_no = ?1 AND id != ?2)",
+            params![ticket_no, weighment_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if taken {
+        return Err(format!("Ticket number {} is already used", ticket_no));
+    }
+    let Some((status, reserved_token, expired)) = reservation else {
+        return match token {
+            Some(_) => Err(format!("No reservation for ticket number {}", ticket_no)),
+            None => Ok(()),
+        };
+    };
+    if token.is_some() && token != reserved_token.as_deref() {
+        return Err(format!(
+            "Reservation does not match ticket number {}",
+            ticket_no
+        ));
+    }
+    match status.as_str() {
+        "USED" => return Err(format!("Ticket number {} is already used", ticket_no)),
+        "RESERVED" if token.is_none() && !expired => {
+            return Err(format!("Ticket number {} is reserved", ticket_no))
+        }
+        _ => {}
+    }
+    conn.execute(
+        "UPDATE ticket_number_reservations
+         SET status = 'USED', weighment_id = ?2, closed_at = CURRENT_TIMESTAMP
+         WHERE serial = ?1",
+        params![ticket_no, weighment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Reserve the next ticket number for `ttl_minutes` (default 30)
+#[tauri::command]
+pub fn reserve_ticket_number(
+    app: AppHandle,
+    purpose: Option<String>,
+    ttl_minutes: Option<i64>,
+    user_id: Option<String>,
+) -> Result<TicketReservation, String> {
+    let ttl = ttl_minutes
+        .unwrap_or(DEFAULT_RESERVATION_MINUTES)
+        .clamp(1, MAX_RESERVATION_MINUTES);
+    db::write(&app, move |tx| {
+        let serial = next(tx)?;
+        let token = uuid::Uuid::new_v4().to_string();
+        tx.execute(
+            "INSERT INTO ticket_number_reservations
+                 (serial, token, status, purpose, reserved_by, expires_at)
+             VALUES (?1, ?2, 'RESERVED', ?3, ?4, datetime('now', ?5))",
+            params![serial, token, purpose, user_id, format!("+{} minutes", ttl)],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.query_row(
+            "SELECT token, serial, purpose, reserved_at, expires_at
+             FROM ticket_number_reservations WHERE serial = ?1",
+            [&serial],
+            |row| {
+                Ok(TicketReservation {
+                    token: row.get(0)?,
+                    ticket_no: row.get(1)?,
+                    purpose: row.get(2)?,
+                    reserved_at: row.get(3)?,
+                    expires_at: row.get(4)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+// Take the next ticket number for a ticket about to be saved. Screens use
+// this rather than advancing serial_number_config themselves, so two windows
+// can never read the same counter.
+#[tauri::command]
+pub fn allocate_ticket_number(app: AppHandle) -> Result<String, String> {
+    db::write(&app, |tx| next(tx))
+}
+
+// Give an unused reservation back; its number goes to the next ticket
+#[tauri::command]
+pub fn release_reservation(app: AppHandle, token: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    let released = conn
+        .execute(
+            "UPDATE ticket_number_reservations
+             SET status = 'RELEASED', closed_at = CURRENT_TIMESTAMP
+             WHERE token = ?1 AND status = 'RESERVED'",
+            [&token],
+        )
+        .map_err(|e| e.to_string())?;
+    if released == 0 {
+        return Err("Reservation not found, already used or released".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Transaction;
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::Duration;
+
+    const THREADS: usize = 8;
+    const TICKETS_PER_THREAD: usize = 25;
+
+    fn temp_db() -> PathBuf {
+        let path = std::env::temp_dir().join(format!("serials-{}.db", uuid::Uuid::new_v4()));
+        let conn = Connection::open(&path).unwrap();
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))
+            .unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        path
+    }
+
+    fn remove_db(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    // What allocate_ticket_number and then saving the ticket do, each on
+    // its own connection as separate windows and PCs would. Every fourth
+    // number is reserved and released first, so reclaimed numbers race too.
+    fn weigh(path: &Path, lane: usize) -> Vec<String> {
+        let mut conn = Connection::open(path).unwrap();
+        conn.busy_timeout(Duration::from_secs(5)).unwrap();
+        let mut saved = Vec::new();
+        for i in 0..TICKETS_PER_THREAD {
+            if i % 4 == 0 {
+                let serial: String = db::write_on(&mut conn, &mut |tx: &Transaction| {
+                    let serial = next(tx)?;
+                    tx.execute(
+                        "INSERT INTO ticket_number_reservations (serial, token, status, expires_at)
+                         VALUES (?1, ?2, 'RESERVED', datetime('now', '+5 minutes'))",
+                        params![serial, uuid::Uuid::new_v4().to_string()],
+                    )
+                    .map_err(|e| e.to_string())?;
+                    Ok::<_, String>(serial)
+                })
+                .unwrap();
+                conn.execute(
+                    "UPDATE ticket_number_reservations SET status = 'RELEASED' WHERE serial = ?1",
+                    [&serial],
+                )
+                .unwrap();
+            }
+            let id = format!("lane{}-{}", lane, i);
+            let ticket_no = db::write_on(&mut conn, &mut |tx: &Transaction| {
+                let ticket_no = next(tx)?;
+                tx.execute(
+                    "INSERT INTO weighments
+                         (id, bill_no, ticket_no, vehicle_no, party_name, product_name, status)
+                     VALUES (?1, ?1, ?2, 'KA01AB1234', 'Party', 'Sand', 'OPEN')",
+                    params![id, ticket_no],
+                )
+                .map_err(|e| e.to_string())?;
+                claim(tx, &ticket_no, None, &id)?;
+                Ok::<_, String>(ticket_no)
+            })
+            .unwrap();
+            saved.push(ticket_no);
+        }
+        saved
+    }
+
+    #[test]
+    fn concurrent_allocations_never_repeat_a_ticket_number() {
+        let path = temp_db();
+        let lanes: Vec<_> = (0..THREADS)
+            .map(|lane| {
+                let path = path.clone();
+                thread::spawn(move || weigh(&path, lane))
+            })
+            .collect();
+        let saved: Vec<String> = lanes
+            .into_iter()
+            .flat_map(|lane| lane.join().unwrap())
+            .collect();
+
+        let unique: HashSet<&String> = saved.iter().collect();
+        assert_eq!(saved.len(), THREADS * TICKETS_PER_THREAD);
+        assert_eq!(
+            unique.len(),
+            saved.len(),
+            "a ticket number was issued twice"
+        );
+        let conn = Connection::open(&path).unwrap();
+        let (tickets, numbers): (i64, i64) = conn
+            .query_row(
+                "SELECT COUNT(*), COUNT(DISTINCT ticket_no) FROM weighments",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(tickets, numbers);
+        drop(conn);
+        remove_db(&path);
+    }
+}
@@ -0,0 +1,271 @@
+// Typed settings and stored secrets for Truckore Pro
+// Preferences the frontend kept in localStorage move to app_config under
+// `setting:<key>`, each stored with its type, so they survive a reinstall,
+// travel with backups and are announced as settings://changed by the
+// settings watcher like every other app_config key. A key keeps the type it
+// was first written with. Secrets (the SMTP password, API keys) are sealed
+// with the site's secrets key and written under `secret:<name>`; only their
+// names go back to the UI. The database key cannot be kept inside the
+// database it opens, so a remembered key is sealed into a file beside it.
+
+use crate::command_audit;
+use crate::db;
+use crate::encryption;
+use crate::roles::{self, Role};
+use crate::secrets;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+const SETTING_PREFIX: &str = "setting:";
+const SECRET_PREFIX: &str = "secret:";
+const DATABASE_KEY_SUFFIX: &str = ".key";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum SettingValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Json(Value),
+}
+
+impl SettingValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            SettingValue::Bool(_) => "bool",
+            SettingValue::Int(_) => "int",
+            SettingValue::Float(_) => "float",
+            SettingValue::Text(_) => "text",
+            SettingValue::Json(_) => "json",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Setting {
+    pub key: String,
+    #[serde(flatten)]
+    pub value: SettingValue,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretInfo {
+    pub name: String,
+    pub updated_at: Option<String>,
+}
+
+// Letters, digits, dots, dashes and underscores, e.g. `ui.theme`
+fn check_name(kind: &str, name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 128
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    match valid {
+        true => Ok(()),
+        false => Err(format!("{} name {:?} is not valid", kind, name)),
+    }
+}
+
+pub fn get(conn: &Connection, key: &str) -> Result<Option<SettingValue>, String> {
+    db::get_config(conn, &format!("{}{}", SETTING_PREFIX, key))?
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+pub fn set(conn: &Connection, key: &str, value: &SettingValue) -> Result<(), String> {
+    check_name("Setting", key)?;
+    if let Some(current) = get(conn, key)? {
+        if current.type_name() != value.type_name() {
+            return Err(format!(
+                "Setting {} holds a {} value, not {}",
+                key,
+                current.type_name(),
+                value.type_name()
+            ));
+        }
+    }
+    let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    db::set_config(conn, &format!("{}{}", SETTING_PREFIX, key), &json)
+}
+
+// A stored secret, unsealed for the backend's own use
+pub fn secret(app: &AppHandle, conn: &Connection, name: &str) -> Result<Option<String>, String> {
+    db::get_config(conn, &format!("{}{}", SECRET_PREFIX, name))?
+        .map(|sealed| secrets::open(app, &sealed))
+        .transpose()
+}
+
+pub fn set_secret_value(
+    app: &AppHandle,
+    conn: &Connection,
+    name: &str,
+    value: &str,
+) -> Result<(), String> {
+    check_name("Secret", name)?;
+    let sealed = secrets::seal(app, value)?;
+    db::set_config(conn, &format!("{}{}", SECRET_PREFIX, name), &sealed)
+}
+
+pub fn has_secret(conn: &Connection, name: &str) -> Result<bool, String> {
+    Ok(db::get_config(conn, &format!("{}{}", SECRET_PREFIX, name))?.is_some())
+}
+
+pub fn delete_secret_value(conn: &Connection, name: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM app_config WHERE key = ?1",
+        [format!("{}{}", SECRET_PREFIX, name)],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn with_prefix<T>(
+    conn: &Connection,
+    prefix: &str,
+    f: impl Fn(String, String, Option<String>) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT key, value, updated_at FROM app_config
+             WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([prefix], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.map(|row| {
+        let (key, value, updated_at) = row.map_err(|e| e.to_string())?;
+        f(key[prefix.len()..].to_string(), value, updated_at)
+    })
+    .collect()
+}
+
+fn database_key_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let mut name = crate::get_db_path(app)?.into_os_string();
+    name.push(DATABASE_KEY_SUFFIX);
+    Ok(PathBuf::from(name))
+}
+
+// The key remembered on this PC for the current database, if any
+pub fn remembered_database_key(app: &AppHandle) -> Result<Option<String>, String> {
+    match fs::read_to_string(database_key_path(app)?) {
+        Ok(sealed) => secrets::open(app, sealed.trim()).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub fn get_setting(app: AppHandle, key: String) -> Result<Option<SettingValue>, String> {
+    let conn = db::open(&app)?;
+    get(&conn, &key)
+}
+
+#[tauri::command]
+pub fn list_settings(app: AppHandle) -> Result<Vec<Setting>, String> {
+    let conn = db::open(&app)?;
+    with_prefix(&conn, SETTING_PREFIX, |key, json, updated_at| {
+        Ok(Setting {
+            key,
+            value: serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            updated_at,
+        })
+    })
+}
+
+#[tauri::command]
+pub fn set_setting(app: AppHandle, key: String, value: SettingValue) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    set(&conn, &key, &value)
+}
+
+#[tauri::command]
+pub fn delete_setting(app: AppHandle, key: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    conn.execute(
+        "DELETE FROM app_config WHERE key = ?1",
+        [format!("{}{}", SETTING_PREFIX, key)],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Names of the stored secrets; their values never leave the backend
+#[tauri::command]
+pub fn list_secrets(app: AppHandle) -> Result<Vec<SecretInfo>, String> {
+    let conn = db::open(&app)?;
+    with_prefix(&conn, SECRET_PREFIX, |name, _, updated_at| {
+        Ok(SecretInfo { name, updated_at })
+    })
+}
+
+// Store or replace a secret (admin only)
+#[tauri::command]
+pub fn set_secret(
+    app: AppHandle,
+    name: String,
+    value: String,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "name": name });
+    command_audit::audited(&app, "set_secret", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        set_secret_value(&app, &conn, &name, &value)
+    })
+}
+
+#[tauri::command]
+pub fn delete_secret(app: AppHandle, name: String, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "name": name });
+    command_audit::audited(&app, "delete_secret", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        delete_secret_value(&conn, &name)
+    })
+}
+
+// Keep this run's database key on this PC so the app (or headless mode) can
+// open the database unattended (admin only)
+#[tauri::command]
+pub fn remember_database_key(app: AppHandle, user_id: String) -> Result<(), String> {
+    command_audit::audited(&app, "remember_database_key", &user_id, Value::Null, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let key = encryption::current_key(&app).ok_or("The database is not encrypted")?;
+        fs::write(database_key_path(&app)?, secrets::seal(&app, &key)?).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn forget_database_key(app: AppHandle, user_id: String) -> Result<(), String> {
+    command_audit::audited(&app, "forget_database_key", &user_id, Value::Null, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        match fs::remove_file(database_key_path(&app)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    })
+}
+
+// Open the database with the key remembered on this PC; false when none is
+#[tauri::command]
+pub fn unlock_remembered_database(app: AppHandle) -> Result<bool, String> {
+    match remembered_database_key(&app)? {
+        Some(key) => encryption::set_database_key(app, key).map(|_| true),
+        None => Ok(false),
+    }
+}
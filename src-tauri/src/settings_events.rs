@@ -0,0 +1,116 @@
+// Settings change events for Truckore Pro
+// Settings live in app_config, and running subsystems used to read them once
+// at start, so changing the indicator's baud rate or the printer address
+// mid-shift meant restarting the app. Commits that touch app_config are
+// reported by the change feed; once writes have been quiet for a moment the
+// watcher compares app_config with what it saw last and announces the
+// changed keys as `settings://changed`, to the windows (the sync layer
+// re-reads its settings) and to backend listeners registered with
+// `listen_global`, which reconfigure live. Printers and cameras read their
+// settings on every use and need no listener.
+
+use crate::db;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Event, Manager};
+
+pub const EVENT: &str = "settings://changed";
+// An import writes many keys; subsystems reconfigure once after the last
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsChanged {
+    // Keys added, changed or removed; values are not sent
+    pub keys: Vec<String>,
+}
+
+impl SettingsChanged {
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| k == key)
+    }
+}
+
+// Managed state: wakes the watcher thread
+#[derive(Default)]
+pub struct SettingsWatcher(Mutex<Option<Sender<()>>>);
+
+// Called by the change feed when a commit touched app_config
+pub fn notify(app: &AppHandle) {
+    if let Some(watcher) = app.try_state::<SettingsWatcher>() {
+        if let Some(tx) = watcher.0.lock().ok().as_ref().and_then(|tx| tx.as_ref()) {
+            let _ = tx.send(());
+        }
+    }
+}
+
+// The settings a listener was told about; None for an unreadable payload
+pub fn parse(event: &Event) -> Option<SettingsChanged> {
+    serde_json::from_str(event.payload()?).ok()
+}
+
+fn snapshot(app: &AppHandle) -> Result<HashMap<String, String>, String> {
+    let conn = db::open(app)?;
+    let mut stmt = conn
+        .prepare("SELECT key, value FROM app_config")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<HashMap<String, String>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rows)
+}
+
+fn changed_keys(before: &HashMap<String, String>, after: &HashMap<String, String>) -> Vec<String> {
+    let mut keys: Vec<String> = after
+        .iter()
+        .filter(|(key, value)| before.get(*key) != Some(value))
+        .map(|(key, _)| key.clone())
+        .chain(
+            before
+                .keys()
+                .filter(|key| !after.contains_key(*key))
+                .cloned(),
+        )
+        .collect();
+    keys.sort();
+    keys
+}
+
+// Watch for settings changes in the background for the life of the app
+pub fn start_watcher(app: AppHandle) {
+    let (tx, rx) = mpsc::channel();
+    if let Ok(mut watcher) = app.state::<SettingsWatcher>().0.lock() {
+        *watcher = Some(tx);
+    }
+    std::thread::spawn(move || {
+        // Best effort: the database may not be initialised yet
+        let mut seen = snapshot(&app).unwrap_or_default();
+        while rx.recv().is_ok() {
+            // Wait for the burst to end, and for its commit to land
+            loop {
+                match rx.recv_timeout(QUIET_PERIOD) {
+                    Ok(()) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            let Ok(now) = snapshot(&app) else {
+                continue;
+            };
+            let keys = changed_keys(&seen, &now);
+            seen = now;
+            if keys.is_empty() {
+                continue;
+            }
+            let changed = SettingsChanged { keys };
+            let _ = app.emit_all(EVENT, &changed);
+            if let Ok(json) = serde_json::to_string(&changed) {
+                app.trigger_global(EVENT, Some(json));
+            }
+        }
+    });
+}
@@ -0,0 +1,333 @@
+// First-run setup wizard backend for Truckore Pro
+// A new install used to need its settings edited blind. The wizard finds
+// serial ports and USB printers, tests the indicator and printer before
+// anything is saved, creates the first administrator and records the
+// company profile, ticket numbering and a default tariff. Only the first
+// administrator is created without signing in, and only before setup is
+// completed; the later steps run as that administrator.
+
+use crate::auth;
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use crate::scale::{self, ScaleConfig, ScaleDiagnostics};
+use crate::security;
+use crate::thermal_printer::{self, ThermalPrinter};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serialport::SerialPortType;
+use tauri::AppHandle;
+
+const COMPANY_CONFIG_KEY: &str = "company_profile";
+const NUMBERING_CONFIG_KEY: &str = "serial_number_config";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerialPortCandidate {
+    pub name: String,
+    // "usb", "pci", "bluetooth" or "unknown"
+    pub kind: String,
+    // USB adapter manufacturer and product, where the system reports them
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HardwareScan {
+    pub serial_ports: Vec<SerialPortCandidate>,
+    // USB printer device files (Linux); network printers are entered by address
+    pub usb_printers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompanyProfile {
+    pub name: String,
+    pub address: String,
+    pub gstin: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+}
+
+// Ticket numbering as the frontend serial number service stores it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TicketNumbering {
+    pub prefix: String,
+    pub separator: String,
+    pub include_year: bool,
+    pub include_month: bool,
+    // "YYYY" or "YY"
+    pub year_format: String,
+    pub counter_start: i64,
+    pub counter_padding: u32,
+    // "never", "yearly" or "monthly"
+    pub reset_frequency: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetupStatus {
+    pub completed: bool,
+    pub admin_created: bool,
+    pub company_profile: bool,
+    pub scale_configured: bool,
+    pub printer_configured: bool,
+    pub numbering_configured: bool,
+    pub tariffs: i64,
+}
+
+fn setup_completed(conn: &Connection) -> Result<bool, String> {
+    Ok(db::get_config(conn, "setup_completed")?.as_deref() == Some("true"))
+}
+
+fn count(conn: &Connection, sql: &str) -> Result<i64, String> {
+    conn.query_row(sql, [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+fn admin_exists(conn: &Connection) -> Result<bool, String> {
+    Ok(count(
+        conn,
+        "SELECT COUNT(*) FROM users
+         WHERE role IN ('admin', 'super_admin') AND is_active = 1",
+    )? > 0)
+}
+
+// Scan for serial ports (indicators, serial printers) and USB printers
+#[tauri::command]
+pub fn detect_hardware() -> Result<HardwareScan, String> {
+    let serial_ports = serialport::available_ports()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|port| {
+            let (kind, description) = match port.port_type {
+                SerialPortType::UsbPort(usb) => {
+                    let description = [usb.manufacturer, usb.product]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    ("usb", (!description.is_empty()).then_some(description))
+                }
+                SerialPortType::PciPort => ("pci", None),
+                SerialPortType::BluetoothPort => ("bluetooth", None),
+                SerialPortType::Unknown => ("unknown", None),
+            };
+            SerialPortCandidate {
+                name: port.port_name,
+                kind: kind.to_string(),
+                description,
+            }
+        })
+        .collect();
+
+    let mut usb_printers: Vec<String> = std::fs::read_dir("/dev/usb")
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("lp"))
+                })
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    usb_printers.sort();
+
+    Ok(HardwareScan {
+        serial_ports,
+        usb_printers,
+    })
+}
+
+// Listen to an indicator with settings that are not saved yet
+#[tauri::command]
+pub async fn test_scale_connection(
+    config: ScaleConfig,
+    seconds: Option<f64>,
+) -> Result<ScaleDiagnostics, String> {
+    let seconds = seconds.unwrap_or(3.0).clamp(0.5, 30.0);
+    tauri::async_runtime::spawn_blocking(move || scale::diagnose(&config, seconds))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Print a test page with printer settings that are not saved yet
+#[tauri::command]
+pub async fn test_printer(settings: ThermalPrinter) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || thermal_printer::print_test_page(&settings))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Create the first administrator (super admin). Refused once any
+// administrator exists or setup is complete. Returns the new user's id.
+#[tauri::command]
+pub fn create_initial_admin(
+    app: AppHandle,
+    username: String,
+    password: String,
+    email: Option<String>,
+) -> Result<String, String> {
+    let conn = db::open(&app)?;
+    if setup_completed(&conn)? || admin_exists(&conn)? {
+        return Err("An administrator already exists; sign in to add users".to_string());
+    }
+    let username = username.trim();
+    if username.is_empty() {
+        return Err("Username is required".to_string());
+    }
+    let hash = auth::hash_password(&password)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO users (id, username, email, password_hash, role)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, username, email, hash, Role::SuperAdmin.as_str()],
+    )
+    .map_err(|e| e.to_string())?;
+    security::log_event(
+        &conn,
+        Some(&id),
+        "SETUP_ADMIN_CREATED",
+        &format!(
+            "First administrator {} created by the setup wizard",
+            username
+        ),
+    )?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn get_company_profile(app: AppHandle) -> Result<Option<CompanyProfile>, String> {
+    let conn = db::open(&app)?;
+    db::get_config(&conn, COMPANY_CONFIG_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+#[tauri::command]
+pub fn set_company_profile(
+    app: AppHandle,
+    profile: CompanyProfile,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&profile).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_company_profile", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if profile.name.trim().is_empty() {
+            return Err("Company name is required".to_string());
+        }
+        let json = serde_json::to_string(&profile).map_err(|e| e.to_string())?;
+        db::set_config(&conn, COMPANY_CONFIG_KEY, &json)
+    })
+}
+
+// Set the ticket number format. The counter starts at `counter_start` while
+// no tickets exist; after that it keeps running.
+#[tauri::command]
+pub fn set_ticket_numbering(
+    app: AppHandle,
+    numbering: TicketNumbering,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&numbering).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_ticket_numbering", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if !matches!(numbering.year_format.as_str(), "YYYY" | "YY") {
+            return Err(format!("Unknown year format {}", numbering.year_format));
+        }
+        if !matches!(
+            numbering.reset_frequency.as_str(),
+            "never" | "yearly" | "monthly"
+        ) {
+            return Err(format!(
+                "Unknown reset frequency {}",
+                numbering.reset_frequency
+            ));
+        }
+        if numbering.counter_start < 0 || numbering.counter_padding > 10 {
+            return Err("Counter must start at 0 or more with at most 10 digits".to_string());
+        }
+
+        let mut config = serde_json::to_value(&numbering).map_err(|e| e.to_string())?;
+        let current = db::get_config(&conn, NUMBERING_CONFIG_KEY)?
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok());
+        let counter = match count(&conn, "SELECT COUNT(*) FROM weighments")? {
+            0 => Some(serde_json::json!(numbering.counter_start)),
+            _ => current
+                .as_ref()
+                .and_then(|c| c.get("currentCounter").cloned()),
+        };
+        config["currentCounter"] = counter.unwrap_or(serde_json::json!(numbering.counter_start));
+        if let Some(reset) = current.as_ref().and_then(|c| c.get("lastResetDate")) {
+            config["lastResetDate"] = reset.clone();
+        }
+        db::set_config(&conn, NUMBERING_CONFIG_KEY, &config.to_string())
+    })
+}
+
+// Create the site default tariff (every party and material) unless tariffs
+// exist already. Returns whether one was created.
+#[tauri::command]
+pub fn seed_default_tariffs(
+    app: AppHandle,
+    flat_minor: i64,
+    per_tonne_minor: i64,
+    user_id: String,
+) -> Result<bool, String> {
+    let args = serde_json::json!({ "flat_minor": flat_minor, "per_tonne_minor": per_tonne_minor });
+    command_audit::audited(&app, "seed_default_tariffs", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if flat_minor < 0 || per_tonne_minor < 0 {
+            return Err("Tariff amounts cannot be negative".to_string());
+        }
+        if count(&conn, "SELECT COUNT(*) FROM tariffs")? > 0 {
+            return Ok(false);
+        }
+        conn.execute(
+            "INSERT INTO tariffs (party_name, product_name, flat_minor, per_tonne_minor, active)
+             VALUES (NULL, NULL, ?1, ?2, 1)",
+            params![flat_minor, per_tonne_minor],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(true)
+    })
+}
+
+// What the wizard still has to do
+#[tauri::command]
+pub fn get_setup_status(app: AppHandle) -> Result<SetupStatus, String> {
+    let conn = db::open(&app)?;
+    Ok(SetupStatus {
+        completed: setup_completed(&conn)?,
+        admin_created: admin_exists(&conn)?,
+        company_profile: db::get_config(&conn, COMPANY_CONFIG_KEY)?.is_some(),
+        scale_configured: db::get_config(&conn, "scale_config")?.is_some(),
+        printer_configured: db::get_config(&conn, "thermal_printer")?.is_some(),
+        numbering_configured: db::get_config(&conn, NUMBERING_CONFIG_KEY)?.is_some(),
+        tariffs: count(&conn, "SELECT COUNT(*) FROM tariffs WHERE active = 1")?,
+    })
+}
+
+// Finish the wizard; needs an administrator and a company profile
+#[tauri::command]
+pub fn complete_setup(app: AppHandle, user_id: String) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "complete_setup",
+        &user_id,
+        serde_json::json!({}),
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            if db::get_config(&conn, COMPANY_CONFIG_KEY)?.is_none() {
+                return Err("Set the company profile first".to_string());
+            }
+            db::set_config(&conn, "setup_completed", "true")
+        },
+    )
+}
@@ -0,0 +1,499 @@
+// Operator shifts and cash reconciliation for Truckore Pro
+// Payments are recorded against the operator's open shift. Closing a shift
+// counts the drawer by denomination and records the variance against the
+// expected cash (opening float plus cash payments), with a reason when
+// they differ. Amounts are in paise.
+
+use crate::db::{self, DateRange};
+use crate::money;
+use crate::operator_profiles;
+use crate::roles::{self, Role};
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// Indian notes and coins, in paise
+const DENOMINATIONS_MINOR: &[i64] = &[200000, 50000, 20000, 10000, 5000, 2000, 1000, 500, 200, 100];
+
+pub const PAYMENT_METHODS: &[&str] = &["CASH", "UPI", "CARD", "CREDIT"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Shift {
+    pub id: i64,
+    pub operator_id: String,
+    pub opening_float_minor: i64,
+    pub status: String,
+    pub opened_at: String,
+    pub closed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DenominationCount {
+    pub denomination_minor: i64,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShiftReconciliation {
+    pub shift_id: i64,
+    pub operator_id: String,
+    pub opened_at: String,
+    pub closed_at: Option<String>,
+    pub opening_float_minor: i64,
+    pub cash_payments_minor: i64,
+    pub other_payments_minor: i64,
+    pub expected_minor: i64,
+    pub counted_minor: i64,
+    pub variance_minor: i64,
+    pub reason: Option<String>,
+    pub reconciled_by: String,
+    pub counts: Vec<DenominationCount>,
+}
+
+fn load_shift(conn: &Connection, shift_id: i64) -> Result<Shift, String> {
+    conn.query_row(
+        "SELECT id, operator_id, opening_float_minor, status, opened_at, closed_at
+         FROM shifts WHERE id = ?1",
+        [shift_id],
+        row_to_shift,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Shift {} not found", shift_id))
+}
+
+fn row_to_shift(row: &rusqlite::Row) -> rusqlite::Result<Shift> {
+    Ok(Shift {
+        id: row.get(0)?,
+        operator_id: row.get(1)?,
+        opening_float_minor: row.get(2)?,
+        status: row.get(3)?,
+        opened_at: row.get(4)?,
+        closed_at: row.get(5)?,
+    })
+}
+
+fn open_shift_for(conn: &Connection, operator_id: &str) -> Result<Option<Shift>, String> {
+    conn.query_row(
+        "SELECT id, operator_id, opening_float_minor, status, opened_at, closed_at
+         FROM shifts WHERE operator_id = ?1 AND status = 'OPEN'",
+        [operator_id],
+        row_to_shift,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Cash and non-cash payments for a shift, excluding practice tickets
+fn payment_totals(conn: &Connection, shift_id: i64) -> Result<(i64, i64), String> {
+    conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(CASE method WHEN 'CASH' THEN amount_minor END), 0),
+                    COALESCE(SUM(CASE WHEN method != 'CASH' THEN amount_minor END), 0)
+             FROM shift_payments
+             WHERE shift_id = ?1 AND (weighment_id IS NULL OR {})",
+            training::exclude_practice("weighment_id")
+        ),
+        [shift_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn load_counts(conn: &Connection, shift_id: i64) -> Result<Vec<DenominationCount>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT denomination_minor, count FROM shift_cash_counts
+             WHERE shift_id = ?1 ORDER BY denomination_minor DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([shift_id], |row| {
+            Ok(DenominationCount {
+                denomination_minor: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn load_reconciliation(conn: &Connection, shift_id: i64) -> Result<ShiftReconciliation, String> {
+    let mut reconciliation = conn
+        .query_row(
+            "SELECT s.id, s.operator_id, s.opened_at, s.closed_at, s.opening_float_minor,
+                    r.cash_payments_minor, r.other_payments_minor, r.expected_minor,
+                    r.counted_minor, r.variance_minor, r.reason, r.reconciled_by
+             FROM shift_reconciliations r JOIN shifts s ON s.id = r.shift_id
+             WHERE r.shift_id = ?1",
+            [shift_id],
+            |row| {
+                Ok(ShiftReconciliation {
+                    shift_id: row.get(0)?,
+                    operator_id: row.get(1)?,
+                    opened_at: row.get(2)?,
+                    closed_at: row.get(3)?,
+                    opening_float_minor: row.get(4)?,
+                    cash_payments_minor: row.get(5)?,
+                    other_payments_minor: row.get(6)?,
+                    expected_minor: row.get(7)?,
+                    counted_minor: row.get(8)?,
+                    variance_minor: row.get(9)?,
+                    reason: row.get(10)?,
+                    reconciled_by: row.get(11)?,
+                    counts: Vec::new(),
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Shift {} has not been reconciled", shift_id))?;
+    reconciliation.counts = load_counts(conn, shift_id)?;
+    Ok(reconciliation)
+}
+
+// Open a shift for the operator with the cash float handed over. Returns its id.
+#[tauri::command]
+pub fn open_shift(
+    app: AppHandle,
+    user_id: String,
+    opening_float_minor: i64,
+) -> Result<i64, String> {
+    let conn = db::open(&app)?;
+    roles::user_role(&conn, &user_id)?;
+    if opening_float_minor < 0 {
+        return Err("Opening float cannot be negative".to_string());
+    }
+    // Through the writer, so two windows opening a shift for the operator
+    // cannot both find none open
+    db::write(&app, move |tx| start(tx, &user_id, opening_float_minor))
+}
+
+fn start(tx: &Connection, user_id: &str, opening_float_minor: i64) -> Result<i64, String> {
+    if let Some(shift) = open_shift_for(tx, user_id)? {
+        return Err(format!("Shift {} is still open", shift.id));
+    }
+    tx.execute(
+        "INSERT INTO shifts (operator_id, opening_float_minor) VALUES (?1, ?2)",
+        params![user_id, opening_float_minor],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(tx.last_insert_rowid())
+}
+
+#[tauri::command]
+pub fn get_open_shift(app: AppHandle, user_id: String) -> Result<Option<Shift>, String> {
+    let conn = db::open(&app)?;
+    open_shift_for(&conn, &user_id)
+}
+
+// Record a payment taken by the operator in their open shift. Returns its id.
+#[tauri::command]
+pub fn record_payment(
+    app: AppHandle,
+    user_id: String,
+    weighment_id: Option<String>,
+    method: String,
+    amount_minor: i64,
+) -> Result<i64, String> {
+    let conn = db::open(&app)?;
+    if !PAYMENT_METHODS.contains(&method.as_str()) {
+        return Err(format!("Unknown payment method: {}", method));
+    }
+    operator_profiles::require_payment_method(&conn, &user_id, &method)?;
+    if amount_minor <= 0 {
+        return Err("Payment amount must be positive".to_string());
+    }
+    // Through the writer, so the payment cannot land in a shift closed
+    // after it was found open
+    db::write(&app, move |tx| {
+        take_payment(tx, &user_id, weighment_id.as_deref(), &method, amount_minor)
+    })
+}
+
+fn take_payment(
+    tx: &Connection,
+    user_id: &str,
+    weighment_id: Option<&str>,
+    method: &str,
+    amount_minor: i64,
+) -> Result<i64, String> {
+    let shift = open_shift_for(tx, user_id)?
+        .ok_or_else(|| "Open a shift before recording payments".to_string())?;
+    tx.execute(
+        "INSERT INTO shift_payments (shift_id, weighment_id, method, amount_minor, recorded_by)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![shift.id, weighment_id, method, amount_minor, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(tx.last_insert_rowid())
+}
+
+// Close a shift with the counted cash. Any variance needs a reason. The
+// shift's own operator or a supervisor may close it.
+#[tauri::command]
+pub fn close_shift(
+    app: AppHandle,
+    shift_id: i64,
+    counts: Vec<DenominationCount>,
+    reason: Option<String>,
+    user_id: String,
+) -> Result<ShiftReconciliation, String> {
+    let mut conn = db::open(&app)?;
+    close(&mut conn, shift_id, &counts, reason, &user_id)
+}
+
+fn close(
+    conn: &mut Connection,
+    shift_id: i64,
+    counts: &[DenominationCount],
+    reason: Option<String>,
+    user_id: &str,
+) -> Result<ShiftReconciliation, String> {
+    // Immediate from the start: the shift is read open, totalled and closed
+    // with no other window closing it or taking a payment in between
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|e| e.to_string())?;
+    let shift = load_shift(&tx, shift_id)?;
+    if shift.operator_id != user_id {
+        roles::require_role(&tx, user_id, Role::Admin)?;
+    }
+    if shift.status != "OPEN" {
+        return Err(format!("Shift {} is already closed", shift_id));
+    }
+
+    let mut counted_minor = 0;
+    for c in counts {
+        if !DENOMINATIONS_MINOR.contains(&c.denomination_minor) {
+            return Err(format!(
+                "Unknown denomination: {}",
+                money::format_minor(c.denomination_minor)
+            ));
+        }
+        if c.count < 0 {
+            return Err("Denomination counts cannot be negative".to_string());
+        }
+        counted_minor += c.denomination_minor * c.count;
+    }
+
+    let (cash_payments_minor, other_payments_minor) = payment_totals(&tx, shift_id)?;
+    let expected_minor = shift.opening_float_minor + cash_payments_minor;
+    let variance_minor = counted_minor - expected_minor;
+    let reason = reason
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty());
+    if variance_minor != 0 && reason.is_none() {
+        return Err(format!(
+            "Cash differs from expected by {}; a reason is required",
+            money::format_minor(variance_minor)
+        ));
+    }
+
+    for c in counts.iter().filter(|c| c.count > 0) {
+        tx.execute(
+            "INSERT INTO shift_cash_counts (shift_id, denomination_minor, count)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT(shift_id, denomination_minor) DO UPDATE SET count = count + excluded.count",
+            params![shift_id, c.denomination_minor, c.count],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "INSERT INTO shift_reconciliations (shift_id, cash_payments_minor, other_payments_minor,
+             expected_minor, counted_minor, variance_minor, reason, reconciled_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            shift_id,
+            cash_payments_minor,
+            other_payments_minor,
+            expected_minor,
+            counted_minor,
+            variance_minor,
+            reason,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE shifts SET status = 'CLOSED', closed_at = CURRENT_TIMESTAMP WHERE id = ?1",
+        [shift_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    load_reconciliation(conn, shift_id)
+}
+
+#[tauri::command]
+pub fn get_shift_reconciliation(
+    app: AppHandle,
+    shift_id: i64,
+) -> Result<ShiftReconciliation, String> {
+    let conn = db::open(&app)?;
+    load_reconciliation(&conn, shift_id)
+}
+
+// Reconciliations of shifts closed within the range, optionally for one operator
+#[tauri::command]
+pub fn shift_reconciliation_report(
+    app: AppHandle,
+    range: DateRange,
+    operator_id: Option<String>,
+) -> Result<Vec<ShiftReconciliation>, String> {
+    let conn = db::open(&app)?;
+    let ids: Vec<i64> = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT s.id FROM shifts s JOIN shift_reconciliations r ON r.shift_id = s.id
+                 WHERE {} BETWEEN ?1 AND ?2 AND (?3 IS NULL OR s.operator_id = ?3)
+                 ORDER BY s.closed_at",
+                db::local_date("s.closed_at")
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![range.from, range.to, operator_id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    ids.into_iter()
+        .map(|id| load_reconciliation(&conn, id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Transaction;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    const WINDOWS: usize = 6;
+    const PAYMENTS_PER_WINDOW: usize = 40;
+
+    fn temp_db() -> PathBuf {
+        let path = std::env::temp_dir().join(format!("shifts-{}.db", uuid::Uuid::new_v4()));
+        let conn = Connection::open(&path).unwrap();
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))
+            .unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        conn.execute(
+            "INSERT INTO users (id, username, password_hash, role)
+             VALUES ('op1', 'operator', 'x', 'operator')",
+            [],
+        )
+        .unwrap();
+        path
+    }
+
+    fn remove_db(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn connect(path: &Path) -> Connection {
+        let conn = Connection::open(path).unwrap();
+        conn.busy_timeout(Duration::from_secs(5)).unwrap();
+        conn
+    }
+
+    #[test]
+    fn windows_racing_to_open_a_shift_open_one() {
+        let path = temp_db();
+        let start_line = Arc::new(Barrier::new(WINDOWS));
+        let windows: Vec<_> = (0..WINDOWS)
+            .map(|_| {
+                let (path, start_line) = (path.clone(), Arc::clone(&start_line));
+                thread::spawn(move || {
+                    let mut conn = connect(&path);
+                    start_line.wait();
+                    db::write_on(&mut conn, &mut |tx: &Transaction| start(tx, "op1", 50_000))
+                })
+            })
+            .collect();
+        let opened: Vec<i64> = windows
+            .into_iter()
+            .filter_map(|window| window.join().unwrap().ok())
+            .collect();
+        assert_eq!(opened.len(), 1);
+
+        let conn = connect(&path);
+        let shifts: i64 = conn
+            .query_row("SELECT COUNT(*) FROM shifts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(shifts, 1);
+        drop(conn);
+        remove_db(&path);
+    }
+
+    // Windows take cash while another closes the shift: every payment is
+    // either in the reconciliation or refused, never left in a closed shift
+    #[test]
+    fn payments_racing_a_close_are_counted_or_refused() {
+        let path = temp_db();
+        let shift_id = db::write_on(&mut connect(&path), &mut |tx: &Transaction| {
+            start(tx, "op1", 50_000)
+        })
+        .unwrap();
+
+        let start_line = Arc::new(Barrier::new(WINDOWS + 1));
+        let windows: Vec<_> = (0..WINDOWS)
+            .map(|_| {
+                let (path, start_line) = (path.clone(), Arc::clone(&start_line));
+                thread::spawn(move || {
+                    let mut conn = connect(&path);
+                    start_line.wait();
+                    let mut taken = 0;
+                    for i in 0..PAYMENTS_PER_WINDOW {
+                        let method = if i % 3 == 0 { "UPI" } else { "CASH" };
+                        match db::write_on(&mut conn, &mut |tx: &Transaction| {
+                            take_payment(tx, "op1", None, method, 100)
+                        }) {
+                            Ok(_) if method == "CASH" => taken += 100,
+                            Ok(_) => {}
+                            Err(e) => assert!(e.starts_with("Open a shift"), "{}", e),
+                        }
+                    }
+                    taken
+                })
+            })
+            .collect();
+        let closer = {
+            let (path, start_line) = (path.clone(), Arc::clone(&start_line));
+            thread::spawn(move || {
+                let mut conn = connect(&path);
+                start_line.wait();
+                thread::sleep(Duration::from_millis(5));
+                let counts = [DenominationCount {
+                    denomination_minor: 50_000,
+                    count: 1,
+                }];
+                close(
+                    &mut conn,
+                    shift_id,
+                    &counts,
+                    Some("Mid-shift count".into()),
+                    "op1",
+                )
+            })
+        };
+        let cash_taken: i64 = windows.into_iter().map(|w| w.join().unwrap()).sum();
+        let reconciliation = closer.join().unwrap().unwrap();
+
+        assert_eq!(reconciliation.cash_payments_minor, cash_taken);
+        assert_eq!(reconciliation.expected_minor, 50_000 + cash_taken);
+        assert_eq!(reconciliation.variance_minor, -cash_taken);
+        let conn = connect(&path);
+        assert_eq!(payment_totals(&conn, shift_id).unwrap().0, cash_taken);
+        assert!(open_shift_for(&conn, "op1").unwrap().is_none());
+        assert!(close(&mut connect(&path), shift_id, &[], None, "op1").is_err());
+        drop(conn);
+        remove_db(&path);
+    }
+}
@@ -0,0 +1,177 @@
+// Graceful shutdown for Truckore Pro
+// Ticket writes, print jobs, backups, bulk jobs and background tasks register as in-flight
+// operations. Closing the last window or exiting while any are running is
+// held back (`shutdown_blocked` event) and the app exits once they finish.
+// Each operation is also journalled in operation_journal for its lifetime,
+// so one that was killed mid-way is still visible on the next start.
+
+use crate::bulk::BulkJobs;
+use crate::db;
+use crate::lan_server::LanServer;
+use crate::scale_listener::ScaleListener;
+use crate::tasks::TaskPool;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::{AppHandle, GlobalWindowEvent, Manager, RunEvent, State, WindowEvent};
+
+// Set once exit has been requested; background loops and scale readers stop
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: String,
+    // ticket_write, print, backup, restore, bulk_job, task, import, sync or
+    // encrypt
+    pub kind: String,
+    pub entity: Option<String>,
+}
+
+// Managed state: operations currently in flight
+#[derive(Default)]
+pub struct Operations(Mutex<HashMap<String, Operation>>);
+
+// Ends its operation when dropped, whichever way the operation finished
+pub struct OperationGuard {
+    app: AppHandle,
+    id: String,
+}
+
+pub fn requested() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+// Register an in-flight operation. Refused once shutdown has started.
+pub fn begin(app: &AppHandle, kind: &str, entity: Option<&str>) -> Result<OperationGuard, String> {
+    if requested() {
+        return Err("The application is shutting down".to_string());
+    }
+    let operation = Operation {
+        id: uuid::Uuid::new_v4().to_string(),
+        kind: kind.to_string(),
+        entity: entity.map(|e| e.to_string()),
+    };
+    // Journalling is best effort: the database may not be initialised yet
+    let _ = db::open(app).and_then(|conn| {
+        conn.execute(
+            "INSERT INTO operation_journal (id, kind, entity) VALUES (?1, ?2, ?3)",
+            rusqlite::params![operation.id, operation.kind, operation.entity],
+        )
+        .map_err(|e| e.to_string())
+    });
+    let id = operation.id.clone();
+    if let Ok(mut active) = app.state::<Operations>().0.lock() {
+        active.insert(id.clone(), operation);
+    }
+    Ok(OperationGuard {
+        app: app.clone(),
+        id,
+    })
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        let _ = db::open(&self.app).and_then(|conn| {
+            conn.execute("DELETE FROM operation_journal WHERE id = ?1", [&self.id])
+                .map_err(|e| e.to_string())
+        });
+        let idle = match self.app.state::<Operations>().0.lock() {
+            Ok(mut active) => {
+                active.remove(&self.id);
+                active.is_empty()
+            }
+            Err(_) => false,
+        };
+        // The last operation out completes an exit that was held back
+        if idle && requested() {
+            self.app.exit(0);
+        }
+    }
+}
+
+pub fn in_flight(app: &AppHandle) -> Vec<Operation> {
+    match app.state::<Operations>().0.lock() {
+        Ok(active) => active.values().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Start shutting down. Returns true when exit must wait for operations.
+fn request_exit(app: &AppHandle) -> bool {
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+    // Running bulk jobs stop after their current ticket, tasks at their next
+    // checkpoint
+    app.state::<BulkJobs>().cancel_all();
+    app.state::<TaskPool>().cancel_all();
+    let pending = in_flight(app);
+    if pending.is_empty() {
+        return false;
+    }
+    let _ = app.emit_all("shutdown_blocked", &pending);
+    true
+}
+
+// Final steps once nothing is in flight: stop the LAN server and checkpoint
+// the database so no write is left only in the WAL
+fn finish(app: &AppHandle) {
+    let _ = app.state::<LanServer>().stop();
+    app.state::<ScaleListener>().stop();
+    if let Ok(conn) = db::open(app) {
+        let _ = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);");
+        let _ = conn.execute(
+            "INSERT INTO app_config (key, value, updated_at)
+             VALUES ('clean_shutdown_at', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value,
+                 updated_at = CURRENT_TIMESTAMP",
+            [],
+        );
+    }
+    app.state::<db::DbPool>().close_all();
+}
+
+// Closing the last window is held while operations are in flight, so a
+// print job owned by that window can still complete
+pub fn on_window_event(event: GlobalWindowEvent) {
+    if let WindowEvent::CloseRequested { api, .. } = event.event() {
+        let app = event.window().app_handle();
+        if app.windows().len() == 1 && request_exit(&app) {
+            api.prevent_close();
+        }
+    }
+}
+
+pub fn on_run_event(app: &AppHandle, event: RunEvent) {
+    match event {
+        RunEvent::ExitRequested { api, .. } if request_exit(app) => api.prevent_exit(),
+        RunEvent::Exit => finish(app),
+        _ => {}
+    }
+}
+
+// For operations the frontend owns (printing, backups). The id is passed
+// back to end_operation.
+#[tauri::command]
+pub fn begin_operation(
+    app: AppHandle,
+    kind: String,
+    entity: Option<String>,
+) -> Result<String, String> {
+    let guard = begin(&app, &kind, entity.as_deref())?;
+    let id = guard.id.clone();
+    // Ended explicitly through end_operation instead of on drop
+    std::mem::forget(guard);
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn end_operation(app: AppHandle, id: String) -> Result<(), String> {
+    drop(OperationGuard { app, id });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_operations(operations: State<'_, Operations>) -> Result<Vec<Operation>, String> {
+    let active = operations.0.lock().map_err(|e| e.to_string())?;
+    Ok(active.values().cloned().collect())
+}
@@ -0,0 +1,311 @@
+// Tamper-evident weighments for Truckore Pro
+// Legal-for-trade rules ask that stored weighments cannot be silently
+// edited. Each ticket's identity, weights and times are signed with
+// HMAC-SHA256 under a site key when it is inserted, by a trigger, so tickets
+// saved by the frontend are covered too. Only the app's own weight changes
+// (completion, approved amendments, bulk reassignment) sign again; an edit
+// made any other way, or a deleted ticket, shows up in verify_all.
+// Tickets pulled from head office (sync_engine.rs) keep the signature of the
+// site that weighed them, which this site's key cannot check; they are
+// reported as REMOTE until the app here changes them and signs again.
+
+use crate::db;
+use crate::security;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use tauri::AppHandle;
+
+const KEY_CONFIG_KEY: &str = "weighment_signing_key";
+// Mismatches listed in a verify_all report
+const REPORT_LIMIT: usize = 1000;
+// Field of a synced weighments row carrying its signature and signing site
+pub const SYNC_FIELD: &str = "_signature";
+
+// SQL for the signed fields of a weighments row, as one JSON array
+fn canonical(row: &str) -> String {
+    [
+        "id",
+        "bill_no",
+        "ticket_no",
+        "vehicle_no",
+        "party_name",
+        "product_name",
+        "gross_weight",
+        "tare_weight",
+        "net_weight",
+        "created_at",
+        "second_weight_timestamp",
+    ]
+    .iter()
+    .map(|column| format!("{}.{}", row, column))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+// SQL computing the signature of a weighments row
+fn signature_sql(row: &str) -> String {
+    format!(
+        "hmac_sha256((SELECT value FROM app_config WHERE key = '{}'), json_array({}))",
+        KEY_CONFIG_KEY,
+        canonical(row)
+    )
+}
+
+// hmac_sha256(key, message) as lower-case hex; NULL without a key. Every
+// connection needs it, since inserting a weighment fires the signing trigger.
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "hmac_sha256",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let Some(key) = ctx.get::<Option<String>>(0)? else {
+                return Ok(None);
+            };
+            let message = ctx.get::<String>(1)?;
+            let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+                .map_err(|e| rusqlite::Error::UserFunctionError(e.into()))?;
+            mac.update(message.as_bytes());
+            let digest = mac.finalize().into_bytes();
+            Ok(Some(
+                digest
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>(),
+            ))
+        },
+    )
+}
+
+// Create the site key and the signing trigger, and sign the tickets stored
+// so far as they are. Schema migration 5; the caller holds the transaction.
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    if db::get_config(conn, KEY_CONFIG_KEY)?.is_none() {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        db::set_config(conn, KEY_CONFIG_KEY, &hex)?;
+    }
+    conn.execute_batch(&format!(
+        "CREATE TRIGGER weighments_sign_insert
+         AFTER INSERT ON weighments
+         BEGIN
+             INSERT OR REPLACE INTO weighment_signatures (weighment_id, signature, signed_at)
+             VALUES (NEW.id, {new}, CURRENT_TIMESTAMP);
+         END;
+         INSERT OR REPLACE INTO weighment_signatures (weighment_id, signature)
+         SELECT w.id, {w} FROM weighments w;",
+        new = signature_sql("NEW"),
+        w = signature_sql("w")
+    ))
+    .map_err(|e| e.to_string())
+}
+
+// Sign a weighment again after the app itself changed signed fields
+pub fn sign(conn: &Connection, weighment_id: &str) -> Result<(), String> {
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO weighment_signatures (weighment_id, signature, signed_at)
+             SELECT w.id, {}, CURRENT_TIMESTAMP FROM weighments w WHERE w.id = ?1",
+            signature_sql("w")
+        ),
+        params![weighment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// The signature to send along with a pushed weighment, naming `site` when
+// it was signed here
+pub fn exported(
+    conn: &Connection,
+    weighment_id: &str,
+    site: &str,
+) -> Result<Option<Value>, String> {
+    conn.query_row(
+        "SELECT signature, COALESCE(origin_site, ?2) FROM weighment_signatures
+         WHERE weighment_id = ?1",
+        params![weighment_id, site],
+        |row| {
+            Ok(serde_json::json!({
+                "signature": row.get::<_, Option<String>>(0)?,
+                "origin_site": row.get::<_, String>(1)?,
+            }))
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Keep the origin site's signature for a pulled weighment in place of the
+// one the insert trigger made here. `signed` is the row's SYNC_FIELD; a row
+// that came without one is still marked as signed elsewhere.
+pub fn keep_remote(
+    conn: &Connection,
+    weighment_id: &str,
+    signed: Option<&Value>,
+    site: &str,
+) -> Result<(), String> {
+    let signature = signed.and_then(|s| s["signature"].as_str());
+    let origin = signed
+        .and_then(|s| s["origin_site"].as_str())
+        .unwrap_or("unknown");
+    // A ticket weighed here and echoed back by the server is ours to check
+    let origin = (origin != site).then_some(origin);
+    conn.execute(
+        "INSERT OR REPLACE INTO weighment_signatures
+             (weighment_id, signature, signed_at, origin_site)
+         VALUES (?1, ?2, CURRENT_TIMESTAMP, ?3)",
+        params![weighment_id, signature, origin],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignatureCheck {
+    pub weighment_id: String,
+    // VALID, TAMPERED, UNSIGNED, DELETED or REMOTE (signed at another site)
+    pub status: String,
+    pub signed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerificationReport {
+    pub checked: i64,
+    pub valid: i64,
+    pub tampered: i64,
+    pub unsigned: i64,
+    pub deleted: i64,
+    pub remote: i64,
+    // Everything not VALID or REMOTE, at most 1000
+    pub problems: Vec<SignatureCheck>,
+}
+
+// Stored signature against the one the row has now
+fn status(stored: Option<&str>, expected: Option<&str>, origin: Option<&str>) -> &'static str {
+    match (stored, expected) {
+        _ if origin.is_some() => "REMOTE",
+        // Inserted while the site key was missing
+        (None, _) => "UNSIGNED",
+        (Some(stored), Some(expected)) if stored == expected => "VALID",
+        _ => "TAMPERED",
+    }
+}
+
+// Check one weighment against its signature
+#[tauri::command]
+pub fn verify_weighment(app: AppHandle, weighment_id: String) -> Result<SignatureCheck, String> {
+    let conn = db::open(&app)?;
+    let signed: Option<(Option<String>, Option<String>, Option<String>)> = conn
+        .query_row(
+            "SELECT signature, signed_at, origin_site FROM weighment_signatures
+             WHERE weighment_id = ?1",
+            params![weighment_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let expected: Option<Option<String>> = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM weighments w WHERE w.id = ?1",
+                signature_sql("w")
+            ),
+            params![weighment_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    let (status, signed_at) = match (expected, signed) {
+        (None, None) => return Err(format!("Weighment {} not found", weighment_id)),
+        (None, Some((_, signed_at, _))) => ("DELETED", signed_at),
+        (Some(_), None) => ("UNSIGNED", None),
+        (Some(expected), Some((stored, signed_at, origin))) => (
+            status(stored.as_deref(), expected.as_deref(), origin.as_deref()),
+            signed_at,
+        ),
+    };
+    Ok(SignatureCheck {
+        weighment_id,
+        status: status.to_string(),
+        signed_at,
+    })
+}
+
+// Check every weighment, and every signature whose ticket is gone. Tampered
+// or deleted tickets are written to the security log.
+#[tauri::command]
+pub fn verify_all(app: AppHandle, user_id: Option<String>) -> Result<VerificationReport, String> {
+    let conn = db::open(&app)?;
+    let mut report = VerificationReport {
+        checked: 0,
+        valid: 0,
+        tampered: 0,
+        unsigned: 0,
+        deleted: 0,
+        remote: 0,
+        problems: Vec::new(),
+    };
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT w.id, 1, s.weighment_id IS NOT NULL, s.signature, s.signed_at, {},
+                    s.origin_site
+             FROM weighments w LEFT JOIN weighment_signatures s ON s.weighment_id = w.id
+             UNION ALL
+             SELECT s.weighment_id, 0, 1, s.signature, s.signed_at, NULL, NULL
+             FROM weighment_signatures s
+             WHERE NOT EXISTS (SELECT 1 FROM weighments w WHERE w.id = s.weighment_id)",
+            signature_sql("w")
+        ))
+        .map_err(|e| e.to_string())?;
+    let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let weighment_id: String = row.get(0).map_err(|e| e.to_string())?;
+        let present: bool = row.get(1).map_err(|e| e.to_string())?;
+        let has_signature: bool = row.get(2).map_err(|e| e.to_string())?;
+        let stored: Option<String> = row.get(3).map_err(|e| e.to_string())?;
+        let signed_at: Option<String> = row.get(4).map_err(|e| e.to_string())?;
+        let expected: Option<String> = row.get(5).map_err(|e| e.to_string())?;
+        let origin: Option<String> = row.get(6).map_err(|e| e.to_string())?;
+        let status = match (present, has_signature) {
+            (false, _) => "DELETED",
+            (true, false) => "UNSIGNED",
+            (true, true) => status(stored.as_deref(), expected.as_deref(), origin.as_deref()),
+        };
+        report.checked += 1;
+        match status {
+            "VALID" => report.valid += 1,
+            "TAMPERED" => report.tampered += 1,
+            "UNSIGNED" => report.unsigned += 1,
+            "REMOTE" => report.remote += 1,
+            _ => report.deleted += 1,
+        }
+        if !matches!(status, "VALID" | "REMOTE") && report.problems.len() < REPORT_LIMIT {
+            report.problems.push(SignatureCheck {
+                weighment_id,
+                status: status.to_string(),
+                signed_at,
+            });
+        }
+    }
+
+    if report.tampered > 0 || report.deleted > 0 {
+        security::log_event(
+            &conn,
+            user_id.as_deref(),
+            "WEIGHMENT_TAMPERING_DETECTED",
+            &format!(
+                "{} tampered and {} deleted weighments found",
+                report.tampered, report.deleted
+            ),
+        )?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,157 @@
+// Slip images for Truckore Pro
+// Some printers only print reliably through the OS driver's image path, and
+// a slip shared over WhatsApp or mail is easier to open as a picture than a
+// PDF. render_slip_png rasterises the same layout as render_slip_layout at
+// the requested resolution, with the built-in bitmap font the snapshot
+// watermark uses (text comes out in capitals) and the camera images scaled
+// into their boxes.
+
+use crate::attachments;
+use crate::auditor;
+use crate::db;
+use crate::pdf::{self, PdfOutput};
+use crate::slip_layout::{self, LayoutItem, PaperSize, SlipLayout, SlipValues};
+use crate::watermark::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgb, RgbImage};
+use rusqlite::Connection;
+use std::io::Cursor;
+use tauri::AppHandle;
+
+const DEFAULT_DPI: u32 = 203;
+const MAX_DPI: u32 = 600;
+const INK: Rgb<u8> = Rgb([0, 0, 0]);
+const WATERMARK_INK: Rgb<u8> = Rgb([210, 210, 210]);
+
+// Camera image column, as a data URL, an attachment reference or a path
+fn camera_image(app: &AppHandle, conn: &Connection, value: &str) -> Result<RgbImage, String> {
+    let bytes = if value.starts_with(attachments::REF_PREFIX) {
+        let data_url = attachments::load(app, conn, value)?;
+        attachments::parse_data_url(&data_url)
+            .ok_or("Stored camera image is unreadable")?
+            .bytes
+    } else if value.starts_with("data:") {
+        attachments::parse_data_url(value)
+            .ok_or("Camera image is not a base64 data URL")?
+            .bytes
+    } else {
+        std::fs::read(value).map_err(|e| format!("Failed to read {}: {}", value, e))?
+    };
+    image::load_from_memory(&bytes)
+        .map(|img| img.to_rgb8())
+        .map_err(|e| e.to_string())
+}
+
+fn draw_item(
+    app: &AppHandle,
+    conn: &Connection,
+    img: &mut RgbImage,
+    item: &LayoutItem,
+    values: &SlipValues,
+    px_per_pt: f64,
+) -> Result<(), String> {
+    let px = |pt: f64| (pt * px_per_pt).max(0.0).round() as u32;
+    match item {
+        LayoutItem::Text {
+            field,
+            text,
+            x,
+            y,
+            font_size,
+            bold,
+            align,
+        } => {
+            let font_px = font_size * px_per_pt;
+            let scale = ((font_px / 10.0).round() as u32).max(1);
+            let width = text.chars().count() as u32 * (GLYPH_WIDTH + 1) * scale;
+            let left = match align.as_str() {
+                "center" => px(*x).saturating_sub(width / 2),
+                "right" => px(*x).saturating_sub(width),
+                _ => px(*x),
+            };
+            // Text boxes are positioned by their top edge, the watermark by its centre
+            let top = match field.as_str() {
+                "watermark" => px(*y).saturating_sub(GLYPH_HEIGHT * scale / 2),
+                _ => px(*y) + (font_px as u32).saturating_sub(GLYPH_HEIGHT * scale) / 2,
+            };
+            let colour = match field.as_str() {
+                "watermark" => WATERMARK_INK,
+                _ => INK,
+            };
+            watermark::draw_text(img, text, left, top, scale, colour);
+            if *bold {
+                watermark::draw_text(img, text, left + (scale + 1) / 2, top, scale, colour);
+            }
+        }
+        LayoutItem::Image {
+            field,
+            x,
+            y,
+            width,
+            height,
+            ..
+        } => {
+            let source = match field.as_str() {
+                "frontImage" => values.front_image.as_deref(),
+                _ => values.rear_image.as_deref(),
+            };
+            let Some(source) = source else {
+                return Ok(());
+            };
+            let (w, h) = (px(*width).max(1), px(*height).max(1));
+            let photo = imageops::resize(
+                &camera_image(app, conn, source)?,
+                w,
+                h,
+                FilterType::Triangle,
+            );
+            imageops::overlay(img, &photo, px(*x) as i64, px(*y) as i64);
+        }
+    }
+    Ok(())
+}
+
+pub fn render(
+    app: &AppHandle,
+    conn: &Connection,
+    layout: &SlipLayout,
+    values: &SlipValues,
+    dpi: u32,
+) -> Result<Vec<u8>, String> {
+    let px_per_pt = dpi as f64 / 72.0;
+    let mut img = RgbImage::from_pixel(
+        (layout.width_pt * px_per_pt).ceil() as u32,
+        (layout.height_pt * px_per_pt).ceil() as u32,
+        Rgb([255, 255, 255]),
+    );
+    for item in &layout.items {
+        draw_item(app, conn, &mut img, item, values, px_per_pt)?;
+    }
+    let mut out = Vec::new();
+    img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+// Slip as a PNG at `dpi` (203, a thermal head's, when not given), laid out
+// for the configured printer or `paper_size`; written to `dest_path` or
+// returned base64 encoded
+#[tauri::command]
+pub fn render_slip_png(
+    app: AppHandle,
+    ticket_id: String,
+    dpi: Option<u32>,
+    paper_size: Option<PaperSize>,
+    dest_path: Option<String>,
+    session_token: Option<String>,
+) -> Result<PdfOutput, String> {
+    let dpi = dpi.unwrap_or(DEFAULT_DPI);
+    if !(72..=MAX_DPI).contains(&dpi) {
+        return Err(format!("DPI must be between 72 and {}", MAX_DPI));
+    }
+    let audit_view = auditor::session_auditor(&app, session_token.as_deref())?.is_some();
+    let conn = db::open(&app)?;
+    let (layout, values) =
+        slip_layout::slip(&app, &conn, &ticket_id, None, paper_size, audit_view)?;
+    pdf::deliver(render(&app, &conn, &layout, &values, dpi)?, dest_path)
+}
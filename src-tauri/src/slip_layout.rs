@@ -0,0 +1,625 @@
+// Slip layout engine for Truckore Pro
+// Maps the print template (designed in the frontend) onto a physical paper size:
+// A4 laser and A5 pre-printed pages keep the template geometry scaled to the
+// sheet, 4-inch continuous paper reflows fields into a single column.
+
+use crate::auditor;
+use crate::currency;
+use crate::db;
+use crate::formatting::{self, NumberFormat};
+use crate::profiles;
+use crate::scripting;
+use crate::weighing_steps;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const TEMPLATE_CONFIG_KEY: &str = "print_template";
+const POINTS_PER_MM: f64 = 72.0 / 25.4;
+// 4-inch continuous roll, with a small margin either side
+const CONTINUOUS_WIDTH_PT: f64 = 4.0 * 72.0;
+const CONTINUOUS_MARGIN_PT: f64 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    A4,
+    A5,
+    Continuous,
+}
+
+impl PaperSize {
+    pub fn parse(value: &str) -> Result<PaperSize, String> {
+        match value {
+            "a4" => Ok(PaperSize::A4),
+            "a5" => Ok(PaperSize::A5),
+            "continuous" => Ok(PaperSize::Continuous),
+            other => Err(format!("Unknown paper size: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaperSize::A4 => "a4",
+            PaperSize::A5 => "a5",
+            PaperSize::Continuous => "continuous",
+        }
+    }
+
+    // Portrait sheet dimensions in points
+    fn sheet(&self) -> Option<(f64, f64)> {
+        match self {
+            PaperSize::A4 => Some((595.0, 842.0)),
+            PaperSize::A5 => Some((420.0, 595.0)),
+            PaperSize::Continuous => None,
+        }
+    }
+}
+
+// Mirrors FieldPosition / ImagePosition / PrintTemplate in src/types/printTemplate.ts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldPosition {
+    pub x: f64,
+    pub y: f64,
+    pub font_size: f64,
+    #[serde(default)]
+    pub font_weight: Option<String>,
+    #[serde(default)]
+    pub align: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImagePosition {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateFields {
+    pub ticket_no: FieldPosition,
+    pub vehicle_no: FieldPosition,
+    pub customer_name: FieldPosition,
+    pub material: FieldPosition,
+    pub vehicle_status: FieldPosition,
+    pub first_weight: FieldPosition,
+    pub second_weight: FieldPosition,
+    pub net_weight: FieldPosition,
+    pub date_time: FieldPosition,
+    pub amount: FieldPosition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrintTemplate {
+    pub page_width: f64,
+    pub page_height: f64,
+    pub fields: TemplateFields,
+    pub front_image: ImagePosition,
+    pub rear_image: ImagePosition,
+}
+
+impl Default for PrintTemplate {
+    // Same geometry as DEFAULT_TEMPLATE in the frontend
+    fn default() -> Self {
+        let field = |x: f64, y: f64, font_size: f64, bold: bool| FieldPosition {
+            x,
+            y,
+            font_size,
+            font_weight: bold.then(|| "bold".to_string()),
+            align: Some("left".to_string()),
+        };
+        PrintTemplate {
+            page_width: 842.0,
+            page_height: 595.0,
+            fields: TemplateFields {
+                ticket_no: field(50.0, 50.0, 14.0, true),
+                vehicle_no: field(50.0, 80.0, 14.0, true),
+                customer_name: field(50.0, 110.0, 12.0, false),
+                material: field(50.0, 140.0, 12.0, false),
+                vehicle_status: field(50.0, 170.0, 12.0, true),
+                first_weight: field(50.0, 230.0, 16.0, true),
+                second_weight: field(250.0, 230.0, 16.0, true),
+                net_weight: field(450.0, 230.0, 18.0, true),
+                date_time: field(50.0, 450.0, 12.0, false),
+                amount: field(50.0, 480.0, 14.0, true),
+            },
+            front_image: ImagePosition {
+                x: 500.0,
+                y: 250.0,
+                width: 150.0,
+                height: 120.0,
+            },
+            rear_image: ImagePosition {
+                x: 670.0,
+                y: 250.0,
+                width: 150.0,
+                height: 120.0,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrinterProfile {
+    pub name: String,
+    pub paper_size: PaperSize,
+    // Calibration shift for pre-printed stationery, in millimetres
+    pub offset_x_mm: f64,
+    pub offset_y_mm: f64,
+}
+
+// One positioned element on the slip, in points from the top-left corner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum LayoutItem {
+    Text {
+        field: String,
+        text: String,
+        x: f64,
+        y: f64,
+        font_size: f64,
+        bold: bool,
+        align: String,
+    },
+    Image {
+        field: String,
+        // Weighment column holding the image (data URL or path)
+        source: String,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlipLayout {
+    pub paper_size: PaperSize,
+    pub width_pt: f64,
+    pub height_pt: f64,
+    pub items: Vec<LayoutItem>,
+    // Laid out for an auditor's session, and watermarked AUDIT VIEW
+    #[serde(default)]
+    pub audit_view: bool,
+}
+
+// Ticket values in template field order, with the label used on plain paper
+pub struct SlipValues {
+    pub fields: Vec<(&'static str, &'static str, String)>,
+    pub front_image: Option<String>,
+    pub rear_image: Option<String>,
+    // Practice tickets get a PRACTICE watermark
+    pub practice: bool,
+    // Slips reprinted in an auditor's session get an AUDIT VIEW watermark
+    pub audit_view: bool,
+    // (label, weight) per step of a ticket weighed in a sequence
+    pub steps: Vec<(String, String)>,
+}
+
+fn format_weight(weight: Option<f64>, format: &NumberFormat) -> String {
+    weight
+        .map(|w| formatting::weight(w, format))
+        .unwrap_or_default()
+}
+
+pub fn load_values(conn: &Connection, ticket_id: &str) -> Result<SlipValues, String> {
+    let format = formatting::load(conn)?;
+    conn.query_row(
+        "SELECT ticket_no, vehicle_no, party_name, product_name,
+                COALESCE(second_vehicle_status, first_vehicle_status, ''),
+                first_weight_type, gross_weight, tare_weight, net_weight,
+                datetime(COALESCE(closed_at, second_weight_timestamp, created_at), 'localtime'),
+                charges_minor,
+                front_camera_image, back_camera_image,
+                id IN (SELECT weighment_id FROM practice_tickets)
+         FROM weighments WHERE id = ?1",
+        [ticket_id],
+        |row| {
+            let first_type: Option<String> = row.get(5)?;
+            let gross: Option<f64> = row.get(6)?;
+            let tare: Option<f64> = row.get(7)?;
+            // First weighing is the tare only when the truck came in empty
+            let (first, second) = match first_type.as_deref() {
+                Some("tare") => (tare, gross),
+                _ => (gross, tare),
+            };
+            Ok(SlipValues {
+                fields: vec![
+                    ("ticketNo", "Ticket No", row.get(0)?),
+                    ("vehicleNo", "Vehicle No", row.get(1)?),
+                    ("customerName", "Customer", row.get(2)?),
+                    ("material", "Material", row.get(3)?),
+                    ("vehicleStatus", "Status", row.get(4)?),
+                    ("firstWeight", "First Wt", format_weight(first, &format)),
+                    ("secondWeight", "Second Wt", format_weight(second, &format)),
+                    ("netWeight", "Net Wt", format_weight(row.get(8)?, &format)),
+                    (
+                        "dateTime",
+                        "Date/Time",
+                        formatting::date(
+                            &row.get::<_, Option<String>>(9)?.unwrap_or_default(),
+                            &format,
+                        ),
+                    ),
+                    (
+                        "amount",
+                        "Amount",
+                        formatting::amount(row.get(10)?, &format),
+                    ),
+                ],
+                front_image: row.get(11)?,
+                rear_image: row.get(12)?,
+                practice: row.get(13)?,
+                audit_view: false,
+                steps: Vec::new(),
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Ticket {} not found", ticket_id))
+    .and_then(|mut values| {
+        values.steps = weighing_steps::slip_lines(conn, ticket_id)?
+            .into_iter()
+            .map(|(label, weight)| (label, format_weight(Some(weight), &format)))
+            .collect();
+        // Parties billed in a foreign currency see the converted amount
+        if let Some(billing) = currency::load_billing(conn, ticket_id)? {
+            if billing.currency != currency::base_currency(conn)? {
+                if let Some(amount) = values.fields.iter_mut().find(|f| f.0 == "amount") {
+                    amount.2 = format!(
+                        "{} {}",
+                        billing.currency,
+                        formatting::amount(billing.billed_amount_minor, &format)
+                    );
+                }
+            }
+        }
+        if let Some(words) = formatting::ticket_amount_in_words(conn, ticket_id, &format)? {
+            values.fields.push(("amountInWords", "In Words", words));
+        }
+        Ok(values)
+    })
+}
+
+fn template_position<'t>(template: &'t PrintTemplate, field: &str) -> &'t FieldPosition {
+    let f = &template.fields;
+    match field {
+        "ticketNo" => &f.ticket_no,
+        "vehicleNo" => &f.vehicle_no,
+        "customerName" => &f.customer_name,
+        "material" => &f.material,
+        "vehicleStatus" => &f.vehicle_status,
+        "firstWeight" => &f.first_weight,
+        "secondWeight" => &f.second_weight,
+        "netWeight" => &f.net_weight,
+        "dateTime" => &f.date_time,
+        _ => &f.amount,
+    }
+}
+
+// Fixed-page layout: keep template geometry, scaled uniformly onto the sheet
+// (rotated to landscape when the template is landscape) plus printer offset
+fn page_layout(
+    template: &PrintTemplate,
+    values: &SlipValues,
+    paper: PaperSize,
+    offset: (f64, f64),
+) -> SlipLayout {
+    let (mut w, mut h) = paper.sheet().expect("fixed page size");
+    if template.page_width > template.page_height {
+        std::mem::swap(&mut w, &mut h);
+    }
+    let scale = (w / template.page_width).min(h / template.page_height);
+    let (dx, dy) = (offset.0 * POINTS_PER_MM, offset.1 * POINTS_PER_MM);
+
+    let mut items = Vec::new();
+    for (field, _, text) in &values.fields {
+        let pos = template_position(template, field);
+        // The amount in words goes under the amount, a size smaller
+        let (y, font_size) = match *field {
+            "amountInWords" => (pos.y + pos.font_size * 1.4, pos.font_size * 0.75),
+            _ => (pos.y, pos.font_size),
+        };
+        items.push(LayoutItem::Text {
+            field: field.to_string(),
+            text: text.clone(),
+            x: pos.x * scale + dx,
+            y: y * scale + dy,
+            font_size: font_size * scale,
+            bold: pos.font_weight.as_deref() == Some("bold"),
+            align: pos.align.clone().unwrap_or_else(|| "left".to_string()),
+        });
+    }
+    // Steps are listed under the first weight, a size smaller
+    let first = &template.fields.first_weight;
+    let step_size = first.font_size * 0.75;
+    for (i, (label, weight)) in values.steps.iter().enumerate() {
+        items.push(LayoutItem::Text {
+            field: format!("step{}", i + 1),
+            text: format!("{}: {}", label, weight),
+            x: first.x * scale + dx,
+            y: (first.y + (i + 1) as f64 * step_size * 1.4) * scale + dy,
+            font_size: step_size * scale,
+            bold: false,
+            align: "left".to_string(),
+        });
+    }
+    for (field, source, pos, present) in [
+        (
+            "frontImage",
+            "front_camera_image",
+            &template.front_image,
+            values.front_image.is_some(),
+        ),
+        (
+            "rearImage",
+            "back_camera_image",
+            &template.rear_image,
+            values.rear_image.is_some(),
+        ),
+    ] {
+        if present {
+            items.push(LayoutItem::Image {
+                field: field.to_string(),
+                source: source.to_string(),
+                x: pos.x * scale + dx,
+                y: pos.y * scale + dy,
+                width: pos.width * scale,
+                height: pos.height * scale,
+            });
+        }
+    }
+
+    SlipLayout {
+        paper_size: paper,
+        width_pt: w,
+        height_pt: h,
+        items,
+        audit_view: false,
+    }
+}
+
+// Continuous-roll layout: fields in template reading order (top-to-bottom,
+// left-to-right), one labelled line each, images stacked at full width
+fn continuous_layout(template: &PrintTemplate, values: &SlipValues) -> SlipLayout {
+    let mut ordered: Vec<&(&str, &str, String)> = values.fields.iter().collect();
+    ordered.sort_by(|a, b| {
+        let (pa, pb) = (
+            template_position(template, a.0),
+            template_position(template, b.0),
+        );
+        pa.y.total_cmp(&pb.y).then(pa.x.total_cmp(&pb.x))
+    });
+
+    let usable = CONTINUOUS_WIDTH_PT - 2.0 * CONTINUOUS_MARGIN_PT;
+    let mut y = CONTINUOUS_MARGIN_PT;
+    let mut items = Vec::new();
+    for (field, label, text) in ordered {
+        let pos = template_position(template, field);
+        // Roll paper is narrow: cap font sizes so a label and value fit one line
+        let font_size = pos.font_size.min(12.0);
+        y += font_size * 1.4;
+        items.push(LayoutItem::Text {
+            field: field.to_string(),
+            text: format!("{}: {}", label, text),
+            x: CONTINUOUS_MARGIN_PT,
+            y,
+            font_size,
+            bold: pos.font_weight.as_deref() == Some("bold"),
+            align: "left".to_string(),
+        });
+    }
+    for (i, (label, weight)) in values.steps.iter().enumerate() {
+        y += 12.0 * 1.4;
+        items.push(LayoutItem::Text {
+            field: format!("step{}", i + 1),
+            text: format!("{}: {}", label, weight),
+            x: CONTINUOUS_MARGIN_PT,
+            y,
+            font_size: 10.0,
+            bold: false,
+            align: "left".to_string(),
+        });
+    }
+
+    for (field, source, pos, present) in [
+        (
+            "frontImage",
+            "front_camera_image",
+            &template.front_image,
+            values.front_image.is_some(),
+        ),
+        (
+            "rearImage",
+            "back_camera_image",
+            &template.rear_image,
+            values.rear_image.is_some(),
+        ),
+    ] {
+        if present {
+            let height = usable * pos.height / pos.width.max(1.0);
+            y += 8.0;
+            items.push(LayoutItem::Image {
+                field: field.to_string(),
+                source: source.to_string(),
+                x: CONTINUOUS_MARGIN_PT,
+                y,
+                width: usable,
+                height,
+            });
+            y += height;
+        }
+    }
+
+    SlipLayout {
+        paper_size: PaperSize::Continuous,
+        width_pt: CONTINUOUS_WIDTH_PT,
+        height_pt: y + CONTINUOUS_MARGIN_PT,
+        items,
+        audit_view: false,
+    }
+}
+
+// Large watermark centred on the slip, drawn first so the
+// renderers put it underneath the ticket data
+fn watermark(layout: &mut SlipLayout, text: &str) {
+    let font_size = (layout.width_pt / text.len() as f64).min(72.0);
+    layout.items.insert(
+        0,
+        LayoutItem::Text {
+            field: "watermark".to_string(),
+            text: text.to_string(),
+            x: layout.width_pt / 2.0,
+            y: layout.height_pt / 2.0,
+            font_size,
+            bold: true,
+            align: "center".to_string(),
+        },
+    );
+}
+
+pub fn layout(
+    template: &PrintTemplate,
+    values: &SlipValues,
+    paper: PaperSize,
+    offset: (f64, f64),
+) -> SlipLayout {
+    let mut layout = match paper {
+        PaperSize::Continuous => continuous_layout(template, values),
+        _ => page_layout(template, values, paper, offset),
+    };
+    match (values.practice, values.audit_view) {
+        (true, true) => watermark(&mut layout, "PRACTICE - AUDIT VIEW"),
+        (true, false) => watermark(&mut layout, "PRACTICE"),
+        (false, true) => watermark(&mut layout, "AUDIT VIEW"),
+        (false, false) => {}
+    }
+    layout.audit_view = values.audit_view;
+    layout
+}
+
+// Saved template (synced from the frontend designer) or the built-in default
+pub fn load_template(conn: &Connection) -> Result<PrintTemplate, String> {
+    match db::get_config(conn, TEMPLATE_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(PrintTemplate::default()),
+    }
+}
+
+pub fn load_profile(conn: &Connection, name: &str) -> Result<PrinterProfile, String> {
+    conn.query_row(
+        "SELECT name, paper_size, offset_x_mm, offset_y_mm FROM printer_profiles WHERE name = ?1",
+        [name],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get(2)?,
+                row.get(3)?,
+            ))
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Printer profile {} not found", name))
+    .and_then(|(name, paper, offset_x_mm, offset_y_mm)| {
+        Ok(PrinterProfile {
+            name,
+            paper_size: PaperSize::parse(&paper)?,
+            offset_x_mm,
+            offset_y_mm,
+        })
+    })
+}
+
+// Store the print template designed in the frontend so backend renderers use it
+#[tauri::command]
+pub fn save_print_template(app: AppHandle, template: PrintTemplate) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    let json = serde_json::to_string(&template).map_err(|e| e.to_string())?;
+    db::set_config(&conn, TEMPLATE_CONFIG_KEY, &json)
+}
+
+// A ticket's slip laid out for a configured printer, or an explicit paper
+// size, with the values it was laid out from
+pub fn slip(
+    app: &AppHandle,
+    conn: &Connection,
+    ticket_id: &str,
+    printer: Option<String>,
+    paper_size: Option<PaperSize>,
+    audit_view: bool,
+) -> Result<(SlipLayout, SlipValues), String> {
+    let template = load_template(conn)?;
+    let mut values = load_values(conn, ticket_id)?;
+    values.audit_view = audit_view;
+    scripting::before_print(conn, ticket_id, &mut values.fields)?;
+    // Fall back to the environment profile's printer target
+    let printer = printer.or(profiles::current(app)?.settings.printer);
+
+    let (paper, offset) = match (&printer, paper_size) {
+        (_, Some(paper)) => (paper, (0.0, 0.0)),
+        (Some(name), None) => {
+            let profile = load_profile(conn, name)?;
+            (
+                profile.paper_size,
+                (profile.offset_x_mm, profile.offset_y_mm),
+            )
+        }
+        (None, None) => (PaperSize::A5, (0.0, 0.0)),
+    };
+    Ok((layout(&template, &values, paper, offset), values))
+}
+
+// Lay out a ticket's slip for a configured printer, or an explicit paper size
+#[tauri::command]
+pub fn render_slip_layout(
+    app: AppHandle,
+    ticket_id: String,
+    printer: Option<String>,
+    paper_size: Option<PaperSize>,
+    session_token: Option<String>,
+) -> Result<SlipLayout, String> {
+    let audit_view = auditor::session_auditor(&app, session_token.as_deref())?.is_some();
+    let conn = db::open(&app)?;
+    slip(&app, &conn, &ticket_id, printer, paper_size, audit_view).map(|(layout, _)| layout)
+}
+
+#[tauri::command]
+pub fn set_printer_profile(app: AppHandle, profile: PrinterProfile) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    conn.execute(
+        "INSERT INTO printer_profiles (name, paper_size, offset_x_mm, offset_y_mm)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(name) DO UPDATE SET paper_size = excluded.paper_size,
+             offset_x_mm = excluded.offset_x_mm, offset_y_mm = excluded.offset_y_mm",
+        params![
+            profile.name,
+            profile.paper_size.as_str(),
+            profile.offset_x_mm,
+            profile.offset_y_mm
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_printer_profiles(app: AppHandle) -> Result<Vec<PrinterProfile>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare("SELECT name FROM printer_profiles ORDER BY name")
+        .map_err(|e| e.to_string())?;
+    let names: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    names.iter().map(|name| load_profile(&conn, name)).collect()
+}
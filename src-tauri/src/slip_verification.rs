@@ -0,0 +1,176 @@
+// Public slip verification for Truckore Pro
+// Slips carry a short HMAC code over the stored weights; scanning the slip QR
+// hits /verify/{ticket_no}?code=... and gets the stored record back.
+
+use crate::db;
+use crate::lan_server::{with_db, ApiError, ApiState};
+use axum::extract::{Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tauri::AppHandle;
+
+const SECRET_CONFIG_KEY: &str = "slip_verification_secret";
+const BASE_URL_CONFIG_KEY: &str = "slip_verification_base_url";
+// Hex characters of the HMAC printed on slips
+const CODE_LENGTH: usize = 16;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifiedSlip {
+    pub ticket_no: String,
+    pub bill_no: String,
+    pub vehicle_no: String,
+    pub product_name: String,
+    pub gross_weight: Option<f64>,
+    pub tare_weight: Option<f64>,
+    pub net_weight: Option<f64>,
+    pub first_weighed_at: String,
+    pub second_weighed_at: Option<String>,
+    pub status: String,
+    pub voided: bool,
+    // Training-mode ticket, not a real weighment
+    pub practice: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SlipVerification {
+    pub code: String,
+    pub path: String,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyQuery {
+    pub code: String,
+}
+
+// Site secret used to sign slips, generated on first use
+fn secret(conn: &Connection) -> Result<Vec<u8>, String> {
+    if let Some(hex) = db::get_config(conn, SECRET_CONFIG_KEY)? {
+        return Ok(hex.into_bytes());
+    }
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    db::set_config(conn, SECRET_CONFIG_KEY, &hex)?;
+    Ok(hex.into_bytes())
+}
+
+struct SlipRecord {
+    slip: VerifiedSlip,
+    canonical: String,
+}
+
+fn load_record(conn: &Connection, column: &str, value: &str) -> Result<Option<SlipRecord>, String> {
+    let sql = format!(
+        "SELECT w.id, w.ticket_no, w.bill_no, w.vehicle_no, w.product_name,
+                w.gross_weight, w.tare_weight, w.net_weight, w.created_at,
+                w.second_weight_timestamp, w.status, v.weighment_id IS NOT NULL,
+                p.weighment_id IS NOT NULL
+         FROM weighments w LEFT JOIN ticket_voids v ON v.weighment_id = w.id
+              LEFT JOIN practice_tickets p ON p.weighment_id = w.id
+         WHERE w.{} = ?1",
+        column
+    );
+    conn.query_row(&sql, [value], |row| {
+        let id: String = row.get(0)?;
+        let slip = VerifiedSlip {
+            ticket_no: row.get(1)?,
+            bill_no: row.get(2)?,
+            vehicle_no: row.get(3)?,
+            product_name: row.get(4)?,
+            gross_weight: row.get(5)?,
+            tare_weight: row.get(6)?,
+            net_weight: row.get(7)?,
+            first_weighed_at: row.get(8)?,
+            second_weighed_at: row.get(9)?,
+            status: row.get(10)?,
+            voided: row.get(11)?,
+            practice: row.get(12)?,
+        };
+        let canonical = format!(
+            "{}|{}|{}|{:?}|{:?}|{:?}|{}",
+            id,
+            slip.ticket_no,
+            slip.vehicle_no,
+            slip.gross_weight,
+            slip.tare_weight,
+            slip.net_weight,
+            slip.first_weighed_at
+        );
+        Ok(SlipRecord { slip, canonical })
+    })
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+fn code_for(conn: &Connection, record: &SlipRecord) -> Result<String, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&secret(conn)?).map_err(|e| e.to_string())?;
+    mac.update(record.canonical.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(hex[..CODE_LENGTH].to_string())
+}
+
+// Compare without short-circuiting so response timing doesn't leak the code
+fn codes_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new().route("/verify/:ticket_no", get(verify))
+}
+
+async fn verify(
+    State(state): State<ApiState>,
+    Path(ticket_no): Path<String>,
+    Query(query): Query<VerifyQuery>,
+) -> Result<Json<VerifiedSlip>, ApiError> {
+    with_db(&state, move |conn| {
+        // Unknown ticket and wrong code look the same to the caller
+        let not_found = || ApiError::not_found("No matching slip");
+        let record = load_record(conn, "ticket_no", &ticket_no)?.ok_or_else(not_found)?;
+        if !codes_match(&code_for(conn, &record)?, query.code.trim()) {
+            return Err(not_found());
+        }
+        Ok(Json(record.slip))
+    })
+    .await
+}
+
+// A ticket's stored record and the verification printed on its slip, by
+// weighment "id" or "ticket_no"
+pub fn for_ticket(
+    conn: &Connection,
+    column: &str,
+    value: &str,
+) -> Result<Option<(VerifiedSlip, SlipVerification)>, String> {
+    let Some(record) = load_record(conn, column, value)? else {
+        return Ok(None);
+    };
+    let code = code_for(conn, &record)?;
+    let path = format!("/verify/{}?code={}", record.slip.ticket_no, code);
+    let url = db::get_config(conn, BASE_URL_CONFIG_KEY)?
+        .map(|base| format!("{}{}", base.trim_end_matches('/'), path));
+    Ok(Some((record.slip, SlipVerification { code, path, url })))
+}
+
+// Verification code and QR path to print on a ticket's slip
+#[tauri::command]
+pub fn get_slip_verification(
+    app: AppHandle,
+    ticket_id: String,
+) -> Result<SlipVerification, String> {
+    let conn = db::open(&app)?;
+    for_ticket(&conn, "id", &ticket_id)?
+        .map(|(_, verification)| verification)
+        .ok_or_else(|| format!("Ticket {} not found", ticket_id))
+}
@@ -0,0 +1,489 @@
+// Outgoing mail for Truckore Pro
+// A small SMTP client for the reports the backend sends on its own: implicit
+// TLS (usually port 465), STARTTLS (587) or, for a relay on the site LAN,
+// plain SMTP. TLS trusts the same certificates as the other outbound
+// connections. Credentials are only sent over TLS.
+
+use crate::network;
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+pub const SECURITY_MODES: &[&str] = &["tls", "starttls", "none"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    // tls, starttls or none
+    pub security: String,
+    // Empty for a relay that takes mail without logging in
+    pub username: String,
+    // Never sent back to the UI; None keeps the stored password
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    pub from: String,
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        SmtpSettings {
+            host: String::new(),
+            port: 587,
+            security: "starttls".to_string(),
+            username: String::new(),
+            password: None,
+            from: String::new(),
+        }
+    }
+}
+
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+pub struct Message {
+    pub to: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+}
+
+trait Stream: Read + Write {}
+impl<S: Read + Write> Stream for S {}
+
+// A bare address, without anything that could end a header line
+pub fn check_address(address: &str) -> Result<(), String> {
+    let valid = address.split_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty() && domain.contains('.') && !domain.starts_with('.')
+    }) && !address
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || "<>,;\"".contains(c));
+    match valid {
+        true => Ok(()),
+        false => Err(format!("{} is not an email address", address)),
+    }
+}
+
+pub fn validate(settings: &SmtpSettings) -> Result<(), String> {
+    if settings.host.trim().is_empty() {
+        return Err("SMTP host is required".to_string());
+    }
+    if settings.port == 0 {
+        return Err("SMTP port must be between 1 and 65535".to_string());
+    }
+    if !SECURITY_MODES.contains(&settings.security.as_str()) {
+        return Err(format!(
+            "SMTP security must be tls, starttls or none, got {}",
+            settings.security
+        ));
+    }
+    if settings.security == "none" && !settings.username.is_empty() {
+        return Err("SMTP credentials are only sent over TLS or STARTTLS".to_string());
+    }
+    check_address(&settings.from)
+}
+
+// One reply, joining the lines of a multi-line one
+fn reply<S: Read>(stream: &mut BufReader<S>) -> Result<(u16, String), String> {
+    let mut text = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = stream
+            .read_line(&mut line)
+            .map_err(|e| format!("SMTP server did not answer: {}", e))?;
+        if read == 0 {
+            return Err("SMTP server closed the connection".to_string());
+        }
+        let line = line.trim_end();
+        let code = line
+            .get(..3)
+            .and_then(|c| c.parse::<u16>().ok())
+            .ok_or_else(|| format!("Unexpected SMTP reply: {}", line))?;
+        text.push(line.get(4..).unwrap_or("").to_string());
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok((code, text.join("\n")));
+        }
+    }
+}
+
+fn expect<S: Read>(stream: &mut BufReader<S>, codes: &[u16], step: &str) -> Result<String, String> {
+    let (code, text) = reply(stream)?;
+    match codes.contains(&code) {
+        true => Ok(text),
+        false => Err(format!("SMTP {} refused: {} {}", step, code, text)),
+    }
+}
+
+fn command<S: Read + Write>(
+    stream: &mut BufReader<S>,
+    line: &str,
+    codes: &[u16],
+    step: &str,
+) -> Result<String, String> {
+    let writer = stream.get_mut();
+    writer
+        .write_all(format!("{}\r\n", line).as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("SMTP connection lost: {}", e))?;
+    expect(stream, codes, step)
+}
+
+fn with_tls(
+    conn: &Connection,
+    host: &str,
+    tcp: TcpStream,
+) -> Result<BufReader<Box<dyn Stream>>, String> {
+    let name = rustls::pki_types::ServerName::try_from(host.to_string())
+        .map_err(|_| format!("{} is not a valid SMTP host name", host))?;
+    let tls = rustls::ClientConnection::new(Arc::new(network::tls_config(conn)?), name)
+        .map_err(|e| e.to_string())?;
+    let stream: Box<dyn Stream> = Box::new(rustls::StreamOwned::new(tls, tcp));
+    Ok(BufReader::new(stream))
+}
+
+// Connected, greeted and secured as the settings ask
+fn open_session(
+    conn: &Connection,
+    settings: &SmtpSettings,
+) -> Result<BufReader<Box<dyn Stream>>, String> {
+    let host = settings.host.trim();
+    let address = format!("{}:{}", host, settings.port);
+    let socket = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("Failed to resolve {}", address))?;
+    let tcp = TcpStream::connect_timeout(&socket, TIMEOUT)
+        .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+    tcp.set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| tcp.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+    let ehlo = "EHLO truckore";
+
+    let mut session = match settings.security.as_str() {
+        "tls" => {
+            let mut session = with_tls(conn, host, tcp)?;
+            expect(&mut session, &[220], "greeting")?;
+            session
+        }
+        "starttls" => {
+            let mut plain = BufReader::new(tcp);
+            expect(&mut plain, &[220], "greeting")?;
+            let features = command(&mut plain, ehlo, &[250], "EHLO")?;
+            if !features
+                .lines()
+                .any(|l| l.trim().eq_ignore_ascii_case("STARTTLS"))
+            {
+                return Err(format!("{} does not offer STARTTLS", host));
+            }
+            command(&mut plain, "STARTTLS", &[220], "STARTTLS")?;
+            with_tls(conn, host, plain.into_inner())?
+        }
+        _ => {
+            let stream: Box<dyn Stream> = Box::new(tcp);
+            let mut session = BufReader::new(stream);
+            expect(&mut session, &[220], "greeting")?;
+            session
+        }
+    };
+    command(&mut session, ehlo, &[250], "EHLO")?;
+    Ok(session)
+}
+
+// RFC 2047 encoded-word for a subject outside ASCII
+fn header_text(text: &str) -> String {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    match text.is_ascii() {
+        true => text,
+        false => format!("=?UTF-8?B?{}?=", general_purpose::STANDARD.encode(text)),
+    }
+}
+
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = general_purpose::STANDARD.encode(data);
+    encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+// The RFC 5322 Date header, from SQLite's clock like the rest of the app
+fn date_header(conn: &Connection) -> Result<String, String> {
+    const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (weekday, day, month, rest): (usize, String, usize, String) = conn
+        .query_row(
+            "SELECT CAST(strftime('%w', 'now') AS INTEGER), strftime('%d', 'now'),
+                    CAST(strftime('%m', 'now') AS INTEGER), strftime('%Y %H:%M:%S', 'now')",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "{}, {} {} {} +0000",
+        DAYS[weekday % 7],
+        day,
+        MONTHS[(month + 11) % 12],
+        rest
+    ))
+}
+
+fn render(conn: &Connection, from: &str, message: &Message) -> Result<String, String> {
+    let boundary = format!("truckore-{}", uuid::Uuid::new_v4().simple());
+    let domain = from.rsplit('@').next().unwrap_or("truckore");
+    let mut mail = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\n\
+         MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n",
+        from,
+        message.to.join(", "),
+        header_text(&message.subject),
+        date_header(conn)?,
+        uuid::Uuid::new_v4().simple(),
+        domain,
+        boundary
+    );
+    mail.push_str(&format!(
+        "--{}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        boundary,
+        base64_lines(message.body.as_bytes())
+    ));
+    for attachment in &message.attachments {
+        let filename: String = attachment
+            .filename
+            .chars()
+            .filter(|c| !c.is_control() && *c != '"')
+            .collect();
+        mail.push_str(&format!(
+            "--{}\r\nContent-Type: {}; name=\"{}\"\r\n\
+             Content-Disposition: attachment; filename=\"{}\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+            boundary,
+            attachment.content_type,
+            filename,
+            filename,
+            base64_lines(&attachment.data)
+        ));
+    }
+    mail.push_str(&format!("--{}--\r\n", boundary));
+    Ok(mail)
+}
+
+// The DATA payload up to its final dot; lines starting with a dot are
+// doubled so none ends the message early
+fn dot_stuffed(mail: &str) -> String {
+    let mut data = String::with_capacity(mail.len() + 8);
+    for line in mail.split_inclusive("\r\n") {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+    }
+    data.push('.');
+    data
+}
+
+pub fn send(conn: &Connection, settings: &SmtpSettings, message: &Message) -> Result<(), String> {
+    validate(settings)?;
+    if message.to.is_empty() {
+        return Err("The message has no recipients".to_string());
+    }
+    for address in &message.to {
+        check_address(address)?;
+    }
+    let mail = render(conn, &settings.from, message)?;
+
+    let mut session = open_session(conn, settings)?;
+    if !settings.username.is_empty() {
+        let password = settings
+            .password
+            .as_deref()
+            .ok_or("No SMTP password is stored")?;
+        let login = format!("\0{}\0{}", settings.username, password);
+        command(
+            &mut session,
+            &format!("AUTH PLAIN {}", general_purpose::STANDARD.encode(login)),
+            &[235],
+            "login",
+        )?;
+    }
+    command(
+        &mut session,
+        &format!("MAIL FROM:<{}>", settings.from),
+        &[250],
+        "MAIL FROM",
+    )?;
+    for address in &message.to {
+        command(
+            &mut session,
+            &format!("RCPT TO:<{}>", address),
+            &[250, 251],
+            "recipient",
+        )?;
+    }
+    command(&mut session, "DATA", &[354], "DATA")?;
+    command(&mut session, &dot_stuffed(&mail), &[250], "message")?;
+    let _ = command(&mut session, "QUIT", &[221], "QUIT");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn addresses_cannot_smuggle_header_lines() {
+        assert!(check_address("weighbridge@example.in").is_ok());
+        for address in [
+            "weighbridge",
+            "@example.in",
+            "a@example",
+            "a@.in",
+            "a b@example.in",
+            "a@example.in\r\nBcc: x@y.z",
+            "<a@example.in>",
+        ] {
+            assert!(check_address(address).is_err(), "{:?}", address);
+        }
+    }
+
+    #[test]
+    fn credentials_need_tls() {
+        let settings = SmtpSettings {
+            host: "relay.lan".to_string(),
+            port: 25,
+            security: "none".to_string(),
+            username: "reports".to_string(),
+            from: "reports@example.in".to_string(),
+            ..SmtpSettings::default()
+        };
+        assert!(validate(&settings).is_err());
+        assert!(validate(&SmtpSettings {
+            username: String::new(),
+            ..settings.clone()
+        })
+        .is_ok());
+        assert!(validate(&SmtpSettings {
+            security: "ssl".to_string(),
+            ..settings
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn leading_dots_are_doubled() {
+        assert_eq!(
+            dot_stuffed("a\r\n.\r\n..b\r\nc.\r\n"),
+            "a\r\n..\r\n...b\r\nc.\r\n."
+        );
+    }
+
+    #[test]
+    fn replies_and_headers_are_parsed_and_encoded() {
+        let mut input = BufReader::new(&b"250-relay.lan\r\n250-STARTTLS\r\n250 SIZE 1000\r\n"[..]);
+        assert_eq!(
+            reply(&mut input).unwrap(),
+            (250, "relay.lan\nSTARTTLS\nSIZE 1000".to_string())
+        );
+        assert!(reply(&mut input).is_err());
+
+        assert_eq!(header_text("Daily\r\nBcc: x"), "DailyBcc: x");
+        assert_eq!(header_text("₹ report"), "=?UTF-8?B?4oK5IHJlcG9ydA==?=");
+        let lines = base64_lines(&[0u8; 100]);
+        assert!(lines.split("\r\n").all(|line| line.len() <= 76));
+        assert_eq!(lines.split("\r\n").count(), 2);
+    }
+
+    // A plain SMTP relay that accepts one message and hands back its DATA lines
+    fn relay(listener: TcpListener) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut writer = socket.try_clone().unwrap();
+            let mut reader = BufReader::new(socket);
+            let mut data = Vec::new();
+            let mut in_data = false;
+            writer.write_all(b"220 relay.lan\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches("\r\n").to_string();
+                let answer: &[u8] = if in_data {
+                    if line != "." {
+                        data.push(line);
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-relay.lan\r\n250 8BITMIME\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(answer).unwrap();
+            }
+            data
+        })
+    }
+
+    #[test]
+    fn a_message_is_sent_as_mime_and_ends_with_a_lone_dot() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = relay(listener);
+        let settings = SmtpSettings {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: "none".to_string(),
+            from: "reports@example.in".to_string(),
+            ..SmtpSettings::default()
+        };
+        let message = Message {
+            to: vec!["owner@example.in".to_string()],
+            subject: "Daily report".to_string(),
+            body: ".\r\nnot the end".to_string(),
+            attachments: vec![Attachment {
+                filename: "day\".pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                data: b"%PDF".to_vec(),
+            }],
+        };
+        let conn = Connection::open_in_memory().unwrap();
+        send(&conn, &settings, &message).unwrap();
+        let data = server.join().unwrap();
+
+        assert!(data.contains(&"From: reports@example.in".to_string()));
+        assert!(data.contains(&"To: owner@example.in".to_string()));
+        assert!(data
+            .iter()
+            .any(|l| l.starts_with("Message-ID: <") && l.ends_with("@example.in>")));
+        assert!(data.contains(&"Content-Disposition: attachment; filename=\"day.pdf\"".to_string()));
+        assert!(data.contains(&general_purpose::STANDARD.encode(&message.body)));
+        assert!(data.contains(&"JVBERg==".to_string()));
+        assert!(
+            data.last().unwrap().starts_with("--truckore-") && data.last().unwrap().ends_with("--")
+        );
+    }
+}
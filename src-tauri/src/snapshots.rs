@@ -0,0 +1,206 @@
+// Pre-operation snapshots for Truckore Pro
+// Migrations, imports, re-rating and restores rewrite many rows at once, and
+// undoing a botched one used to mean finding last night's backup and losing
+// the day since. Each of them now first copies the live database with the
+// online backup API (backup.rs) into snapshots/<database>/<tag>.db in the
+// data directory, with a <tag>.json beside it saying what was about to run.
+// The description lives in a file rather than a table so that rolling back,
+// which replaces every table, keeps the list intact. The newest KEEP
+// snapshots of each database are kept.
+
+use crate::backup::{self, RestoreReport};
+use crate::command_audit;
+use crate::db;
+use crate::migrations;
+use crate::roles::{self, Role};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+const KEEP: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    // "<operation>-<YYYYMMDD-HHMMSS>", unique per database
+    pub tag: String,
+    // migration, import, re_rate, restore, rollback or manual
+    pub operation: String,
+    pub detail: Option<String>,
+    pub schema_version: i64,
+    pub size_bytes: u64,
+    pub created_by: String,
+    // UTC, as SQLite writes CURRENT_TIMESTAMP
+    pub created_at: String,
+    #[serde(skip_deserializing)]
+    pub path: String,
+}
+
+fn dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let db_path = crate::get_db_path(app)?;
+    let database = db_path
+        .file_stem()
+        .ok_or("Failed to resolve the database name")?
+        .to_string_lossy()
+        .to_string();
+    Ok(db_path
+        .parent()
+        .ok_or("Failed to resolve the data directory")?
+        .join("snapshots")
+        .join(database))
+}
+
+fn valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Snapshots of the live database, newest first
+pub fn list(app: &AppHandle) -> Result<Vec<Snapshot>, String> {
+    let dir = dir(app)?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let database = path.with_extension("db");
+        // A description whose copy is gone, or is unreadable, is not listed
+        let Some(mut snapshot) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Snapshot>(&json).ok())
+        else {
+            continue;
+        };
+        if !database.exists() {
+            continue;
+        }
+        snapshot.path = database.to_string_lossy().to_string();
+        snapshots.push(snapshot);
+    }
+    snapshots.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.tag.cmp(&a.tag)));
+    Ok(snapshots)
+}
+
+// Drop all but the newest KEEP snapshots
+pub fn prune(app: &AppHandle) -> Result<(), String> {
+    for snapshot in list(app)?.into_iter().skip(KEEP) {
+        let path = PathBuf::from(&snapshot.path);
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+        let _ = fs::remove_file(path.with_extension("json"));
+    }
+    Ok(())
+}
+
+// Copy the live database before `operation`, without pruning
+pub fn take_unpruned(
+    app: &AppHandle,
+    conn: &Connection,
+    operation: &str,
+    detail: Option<String>,
+    user_id: &str,
+) -> Result<Snapshot, String> {
+    let dir = dir(app)?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let (stamp, created_at): (String, String) = conn
+        .query_row(
+            "SELECT strftime('%Y%m%d-%H%M%S', 'now'), datetime('now')",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut tag = format!("{}-{}", operation, stamp);
+    let mut n = 1;
+    while dir.join(format!("{}.db", tag)).exists() {
+        n += 1;
+        tag = format!("{}-{}-{}", operation, stamp, n);
+    }
+    let path = dir.join(format!("{}.db", tag));
+    backup::online_copy(app, conn, &path).map_err(|e| format!("Snapshot failed: {}", e))?;
+    let snapshot = Snapshot {
+        tag,
+        operation: operation.to_string(),
+        detail,
+        schema_version: migrations::current(conn).unwrap_or(0),
+        size_bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+        created_by: user_id.to_string(),
+        created_at,
+        path: path.to_string_lossy().to_string(),
+    };
+    let json = serde_json::to_string_pretty(&snapshot).map_err(|e| e.to_string())?;
+    fs::write(path.with_extension("json"), json).map_err(|e| e.to_string())?;
+    tracing::info!(tag = %snapshot.tag, operation, "Database snapshot taken");
+    Ok(snapshot)
+}
+
+// Copy the live database before `operation`; the operation should not run
+// when this fails
+pub fn take(
+    app: &AppHandle,
+    conn: &Connection,
+    operation: &str,
+    detail: Option<String>,
+    user_id: &str,
+) -> Result<Snapshot, String> {
+    let snapshot = take_unpruned(app, conn, operation, detail, user_id)?;
+    prune(app)?;
+    Ok(snapshot)
+}
+
+#[tauri::command]
+pub fn list_snapshots(app: AppHandle) -> Result<Vec<Snapshot>, String> {
+    list(&app)
+}
+
+// Snapshot on demand, e.g. before a manual clean-up (admin only)
+#[tauri::command]
+pub fn take_snapshot(
+    app: AppHandle,
+    detail: Option<String>,
+    user_id: String,
+) -> Result<Snapshot, String> {
+    let args = serde_json::json!({ "detail": detail });
+    command_audit::audited(&app, "take_snapshot", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        take(&app, &conn, "manual", detail.clone(), &user_id)
+    })
+}
+
+// Put the database back as it was when `tag` was taken (admin only). Like a
+// restore, the current database is snapshotted first, so a rollback can be
+// rolled back too.
+#[tauri::command]
+pub fn rollback_to_snapshot(
+    app: AppHandle,
+    tag: String,
+    user_id: String,
+) -> Result<RestoreReport, String> {
+    let args = serde_json::json!({ "tag": tag });
+    command_audit::audited(&app, "rollback_to_snapshot", &user_id, args, || {
+        {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+        }
+        if !valid_tag(&tag) {
+            return Err(format!("Invalid snapshot tag: {}", tag));
+        }
+        let path = dir(&app)?.join(format!("{}.db", tag));
+        if !path.exists() {
+            return Err(format!("Snapshot {} not found", tag));
+        }
+        backup::restore_from(
+            &app,
+            &path,
+            "rollback",
+            Some(format!("Rolled back to {}", tag)),
+            &user_id,
+        )
+    })
+}
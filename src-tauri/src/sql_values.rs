@@ -0,0 +1,278 @@
+// Typed SQL values for Truckore Pro
+// JSON has no bytes and no timestamps, so a signature image written through
+// execute_non_query used to land as base64 text and a time with an offset
+// was stored as typed. A parameter can now be an envelope:
+//   {"type": "blob", "base64": "..."}       stored as a BLOB
+//   {"type": "datetime", "value": "..."}    ISO 8601, or Unix milliseconds,
+//                                           stored in UTC the way
+//                                           CURRENT_TIMESTAMP writes it
+//   {"type": "text", "value": "..."}        stored as text, never guessed at
+//   {"type": "json", "value": ...}          stored as its JSON text
+// Queries run with `typed` return BLOBs and DATETIME columns as the same
+// envelopes (times in UTC with a Z). Text always comes back exactly as
+// stored; it is only read as JSON in the columns a query names in
+// `json_columns`, and execute_query_with_columns reports each column's
+// declared type, affinity and the storage classes it held.
+
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn number(text: &str, digits: usize) -> Option<i64> {
+    (text.len() == digits && text.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| text.parse().ok())
+        .flatten()
+}
+
+// Milliseconds since the Unix epoch of "YYYY-MM-DD[( |T)HH:MM[:SS[.fff]]]"
+// with an optional "Z" or "±HH:MM" offset; no offset means UTC
+fn parse_datetime(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let date = text.get(..10)?;
+    let (year, month, day) = (
+        number(date.get(..4)?, 4)?,
+        number(date.get(5..7)?, 2)?,
+        number(date.get(8..10)?, 2)?,
+    );
+    let bytes = date.as_bytes();
+    if bytes[4] != b'-' || bytes[7] != b'-' || !(1..=12).contains(&month) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    // Rejects 31 April and 29 February outside leap years
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    let mut rest = &text[10..];
+    let mut millis = 0;
+    if let Some(time) = rest.strip_prefix(|c| c == 'T' || c == ' ') {
+        let hour = number(time.get(..2)?, 2)?;
+        let minute = number(time.get(3..5)?, 2)?;
+        if time.as_bytes()[2] != b':' || hour > 23 || minute > 59 {
+            return None;
+        }
+        rest = &time[5..];
+        let mut second = 0;
+        if let Some(secs) = rest.strip_prefix(':') {
+            second = number(secs.get(..2)?, 2)?;
+            if second > 59 {
+                return None;
+            }
+            rest = &secs[2..];
+            if let Some(fraction) = rest.strip_prefix('.') {
+                let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+                if digits == 0 {
+                    return None;
+                }
+                let ms: String = format!("{:0<3}", &fraction[..digits.min(3)]);
+                millis = ms.parse::<i64>().ok()?;
+                rest = &fraction[digits..];
+            }
+        }
+        millis += ((hour * 60 + minute) * 60 + second) * 1000;
+    }
+    let offset_minutes = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let (sign, offset) = match (rest.strip_prefix('+'), rest.strip_prefix('-')) {
+                (Some(offset), _) => (1, offset),
+                (_, Some(offset)) => (-1, offset),
+                _ => return None,
+            };
+            let offset = offset.replace(':', "");
+            let hours = number(offset.get(..2)?, 2)?;
+            let minutes = number(offset.get(2..)?, 2)?;
+            sign * (hours * 60 + minutes)
+        }
+    };
+    Some(days * SECS_PER_DAY * 1000 + millis - offset_minutes * 60_000)
+}
+
+// "YYYY-MM-DD HH:MM:SS", with ".fff" when there are milliseconds
+fn format_datetime(millis: i64, separator: char) -> String {
+    let secs = millis.div_euclid(1000);
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+    let time = secs.rem_euclid(SECS_PER_DAY);
+    let mut text = format!(
+        "{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        separator,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    );
+    if millis.rem_euclid(1000) != 0 {
+        text.push_str(&format!(".{:03}", millis.rem_euclid(1000)));
+    }
+    text
+}
+
+// The SQL value of a typed envelope; None when `json` is not one
+pub fn param(json: &Value) -> Option<Result<SqlValue, String>> {
+    let Value::Object(fields) = json else {
+        return None;
+    };
+    let kind = fields.get("type")?.as_str()?;
+    let payload = match kind {
+        "blob" => fields.get("base64"),
+        "datetime" | "text" | "json" => fields.get("value"),
+        _ => return None,
+    };
+    if fields.len() != 2 {
+        return None;
+    }
+    let payload = payload?;
+    Some(match kind {
+        "blob" => payload
+            .as_str()
+            .ok_or_else(|| "blob base64 must be a string".to_string())
+            .and_then(|text| {
+                general_purpose::STANDARD
+                    .decode(text)
+                    .map_err(|e| format!("blob is not valid base64: {}", e))
+            })
+            .map(SqlValue::Blob),
+        "datetime" => match payload {
+            Value::String(text) => parse_datetime(text)
+                .ok_or_else(|| format!("{:?} is not an ISO 8601 date or time", text)),
+            Value::Number(n) => n
+                .as_i64()
+                .ok_or_else(|| "datetime milliseconds must be an integer".to_string()),
+            _ => Err("datetime must be a string or Unix milliseconds".to_string()),
+        }
+        .map(|millis| SqlValue::Text(format_datetime(millis, ' '))),
+        "text" => match payload {
+            Value::String(text) => Ok(SqlValue::Text(text.clone())),
+            _ => Err("text value must be a string".to_string()),
+        },
+        _ => Ok(SqlValue::Text(payload.to_string())),
+    })
+}
+
+// SQL values of a command's parameters; a malformed envelope is an error
+// rather than text
+pub fn params(values: &[Value]) -> Result<Vec<SqlValue>, String> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| match param(value) {
+            Some(typed) => typed.map_err(|e| format!("Parameter {}: {}", index + 1, e)),
+            None => Ok(crate::json_to_sql_value(value)),
+        })
+        .collect()
+}
+
+fn is_datetime_column(decl_type: Option<&str>) -> bool {
+    decl_type.is_some_and(|t| {
+        let t = t.to_ascii_uppercase();
+        t.contains("DATE") || t.contains("TIME")
+    })
+}
+
+// A column value for a `typed` query
+pub fn typed(value: ValueRef, decl_type: Option<&str>) -> Value {
+    match value {
+        ValueRef::Blob(bytes) => json!({
+            "type": "blob",
+            "base64": general_purpose::STANDARD.encode(bytes),
+        }),
+        ValueRef::Text(bytes) => {
+            let text = String::from_utf8_lossy(bytes).to_string();
+            match is_datetime_column(decl_type) {
+                true => {
+                    // Date-only and unparseable values come back as stored
+                    let value = match parse_datetime(&text) {
+                        Some(millis) if text.len() > 10 => {
+                            format!("{}Z", format_datetime(millis, 'T'))
+                        }
+                        _ => text,
+                    };
+                    json!({ "type": "datetime", "value": value })
+                }
+                false => Value::String(text),
+            }
+        }
+        other => crate::sql_to_json_value(other),
+    }
+}
+
+// Text of a `json_columns` column, parsed; text that is not JSON is kept
+pub fn json_text(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Text(bytes) => {
+            let text = String::from_utf8_lossy(bytes);
+            serde_json::from_str(&text).unwrap_or_else(|_| Value::String(text.to_string()))
+        }
+        other => crate::sql_to_json_value(other),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    // As written in CREATE TABLE; None for expressions
+    pub decl_type: Option<String>,
+    // INTEGER, TEXT, BLOB, REAL or NUMERIC, from decl_type by SQLite's rules
+    pub affinity: Option<String>,
+    // Storage classes seen in the returned rows: null, integer, real, text, blob
+    pub storage_classes: Vec<String>,
+    // Whether the column's text was read as JSON
+    pub json: bool,
+    // Whether the session sees the column's values masked (masking.rs)
+    pub masked: bool,
+}
+
+// Column affinity of a declared type (SQLite datatype3, section 3.1)
+pub fn affinity(decl_type: Option<&str>) -> Option<&'static str> {
+    let t = decl_type?.to_ascii_uppercase();
+    Some(if t.contains("INT") {
+        "INTEGER"
+    } else if t.contains("CHAR") || t.contains("CLOB") || t.contains("TEXT") {
+        "TEXT"
+    } else if t.contains("BLOB") || t.is_empty() {
+        "BLOB"
+    } else if t.contains("REAL") || t.contains("FLOA") || t.contains("DOUB") {
+        "REAL"
+    } else {
+        "NUMERIC"
+    })
+}
+
+pub fn storage_class(value: ValueRef) -> &'static str {
+    match value {
+        ValueRef::Null => "null",
+        ValueRef::Integer(_) => "integer",
+        ValueRef::Real(_) => "real",
+        ValueRef::Text(_) => "text",
+        ValueRef::Blob(_) => "blob",
+    }
+}
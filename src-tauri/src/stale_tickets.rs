@@ -0,0 +1,253 @@
+// Stale open-ticket handling for Truckore Pro
+// Weigh-in tickets left open longer than the configured number of hours are
+// flagged by a background scan and supervisors are notified. Supervisors
+// then close or void them in bulk, with a reason, so they stop inflating
+// pending-ticket counts.
+
+use crate::command_audit;
+use crate::db;
+use crate::notifications;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::training;
+use crate::voids;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const HOURS_CONFIG_KEY: &str = "stale_ticket_hours";
+const DEFAULT_STALE_HOURS: f64 = 12.0;
+const SCAN_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StaleTicket {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    pub party_name: String,
+    pub created_at: String,
+    pub hours_open: f64,
+    pub flagged_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkResolution {
+    pub resolved: Vec<String>,
+    // Tickets left as they were, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+pub fn stale_hours(conn: &Connection) -> Result<f64, String> {
+    Ok(db::get_config(conn, HOURS_CONFIG_KEY)?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_STALE_HOURS))
+}
+
+// Flag newly stale tickets and notify supervisors once per scan. Returns the
+// ids flagged by this scan.
+pub fn scan(app: &AppHandle, conn: &Connection) -> Result<Vec<String>, String> {
+    let hours = stale_hours(conn)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT w.id FROM weighments w
+             WHERE w.status = 'OPEN'
+               AND (julianday('now') - julianday(w.created_at)) * 24.0 > ?1
+               AND w.id NOT IN (SELECT weighment_id FROM stale_ticket_flags)
+               AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}",
+            training::exclude_practice("w.id")
+        ))
+        .map_err(|e| e.to_string())?;
+    let ids: Vec<String> = stmt
+        .query_map([hours], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    for id in &ids {
+        conn.execute(
+            "INSERT OR IGNORE INTO stale_ticket_flags (weighment_id) VALUES (?1)",
+            [id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    if !ids.is_empty() {
+        notifications::notify(
+            app,
+            conn,
+            "admin",
+            "Stale open tickets",
+            &format!(
+                "{} ticket(s) have been open for more than {} hours",
+                ids.len(),
+                hours
+            ),
+            None,
+        )?;
+    }
+    Ok(ids)
+}
+
+// Run `scan` in the background for the life of the app
+pub fn start_monitor(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            if let Ok(conn) = db::open(&app) {
+                let _ = scan(&app, &conn);
+            }
+            std::thread::sleep(SCAN_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_stale_ticket_hours(app: AppHandle) -> Result<f64, String> {
+    let conn = db::open(&app)?;
+    stale_hours(&conn)
+}
+
+#[tauri::command]
+pub fn set_stale_ticket_hours(app: AppHandle, hours: f64, user_id: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    if hours.is_nan() || hours < 1.0 {
+        return Err("Stale ticket threshold must be at least 1 hour".to_string());
+    }
+    db::set_config(&conn, HOURS_CONFIG_KEY, &hours.to_string())
+}
+
+// Scan now instead of waiting for the background run
+#[tauri::command]
+pub fn scan_stale_tickets(app: AppHandle) -> Result<Vec<String>, String> {
+    let conn = db::open(&app)?;
+    scan(&app, &conn)
+}
+
+// Flagged tickets that are still open and unresolved, oldest first
+#[tauri::command]
+pub fn list_stale_tickets(app: AppHandle) -> Result<Vec<StaleTicket>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT w.id, w.ticket_no, w.vehicle_no, w.party_name, w.created_at,
+                    (julianday('now') - julianday(w.created_at)) * 24.0, f.flagged_at
+             FROM stale_ticket_flags f JOIN weighments w ON w.id = f.weighment_id
+             WHERE f.resolved_at IS NULL AND w.status = 'OPEN'
+               AND w.id NOT IN (SELECT weighment_id FROM ticket_voids)
+             ORDER BY w.created_at",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(StaleTicket {
+                weighment_id: row.get(0)?,
+                ticket_no: row.get(1)?,
+                vehicle_no: row.get(2)?,
+                party_name: row.get(3)?,
+                created_at: row.get(4)?,
+                hours_open: row.get(5)?,
+                flagged_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Close or void one open ticket and mark its stale flag resolved
+pub fn resolve_one(
+    conn: &mut Connection,
+    weighment_id: &str,
+    action: &str,
+    reason: &str,
+    user_id: &str,
+) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let status: Option<String> = tx
+        .query_row(
+            "SELECT status FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match status.as_deref() {
+        Some("OPEN") => {}
+        Some(_) => return Err("Ticket is no longer open".to_string()),
+        None => return Err("Ticket not found".to_string()),
+    }
+    if voids::is_voided(&tx, weighment_id)? {
+        return Err("Ticket is already voided".to_string());
+    }
+    match action {
+        // Abandoned weigh-in: closed without a second weight
+        "CLOSE" => {
+            tx.execute(
+                "UPDATE weighments SET status = 'CLOSED', closed_at = CURRENT_TIMESTAMP,
+                        updated_at = CURRENT_TIMESTAMP,
+                        remarks = TRIM(COALESCE(remarks, '') || ' [Closed as stale: ' || ?2 || ']')
+                 WHERE id = ?1",
+                params![weighment_id, reason],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        _ => voids::record_void(&tx, weighment_id, reason, user_id, None)?,
+    }
+    tx.execute(
+        "INSERT INTO stale_ticket_flags (weighment_id) VALUES (?1)
+         ON CONFLICT(weighment_id) DO NOTHING",
+        [weighment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE stale_ticket_flags SET resolved_at = CURRENT_TIMESTAMP, resolution = ?2,
+                resolved_by = ?3, reason = ?4
+         WHERE weighment_id = ?1",
+        params![weighment_id, action, user_id, reason],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// Close or void many stale tickets at once (supervisors only). `action` is
+// "CLOSE" or "VOID"; each ticket is handled on its own so one failure (for
+// example a locked period) doesn't hold up the rest.
+#[tauri::command]
+pub fn resolve_stale_tickets(
+    app: AppHandle,
+    weighment_ids: Vec<String>,
+    action: String,
+    reason: String,
+    user_id: String,
+) -> Result<BulkResolution, String> {
+    let args = serde_json::json!({
+        "weighment_ids": weighment_ids,
+        "action": action,
+        "reason": reason,
+    });
+    command_audit::audited(&app, "resolve_stale_tickets", &user_id, args, || {
+        let mut conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if action != "CLOSE" && action != "VOID" {
+            return Err(format!("Unknown action: {}", action));
+        }
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err("A reason is required".to_string());
+        }
+
+        let mut result = BulkResolution {
+            resolved: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for id in &weighment_ids {
+            match resolve_one(&mut conn, id, &action, reason, &user_id) {
+                Ok(()) => result.resolved.push(id.clone()),
+                Err(e) => result.skipped.push((id.clone(), e)),
+            }
+        }
+        Ok(result)
+    })
+}
@@ -0,0 +1,249 @@
+// Startup recovery for Truckore Pro
+// Operations still listed in operation_journal at startup were cut short by
+// a crash, power cut or killed process. Each is resolved by kind, always the
+// same way for the same state, and the outcome is logged, announced to
+// admins and kept for the recovery report.
+
+use crate::currency;
+use crate::db;
+use crate::encryption;
+use crate::notifications;
+use crate::workflow_journal;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveredOperation {
+    pub kind: String,
+    pub entity: Option<String>,
+    // ROLLED_BACK, COMPLETED, VERIFIED, REPRINT or FAILED
+    pub action: String,
+    pub detail: String,
+    pub started_at: String,
+    pub recovered_at: String,
+}
+
+struct Interrupted {
+    id: String,
+    kind: String,
+    entity: Option<String>,
+    started_at: String,
+}
+
+// A killed write left either the whole ticket change or none of it; finish
+// the post-close step that may not have run
+fn recover_ticket(conn: &Connection, entity: Option<&str>) -> Result<(String, String), String> {
+    let Some(id) = entity else {
+        return Ok((
+            "ROLLED_BACK".to_string(),
+            "New ticket was not saved".to_string(),
+        ));
+    };
+    let ticket: Option<(String, String)> = conn
+        .query_row(
+            "SELECT ticket_no, status FROM weighments WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((ticket_no, status)) = ticket else {
+        return Ok((
+            "ROLLED_BACK".to_string(),
+            "Ticket was not saved".to_string(),
+        ));
+    };
+    if status != "OPEN" && currency::load_billing(conn, id)?.is_none() {
+        currency::bill_weighment(conn, id)?;
+        return Ok((
+            "COMPLETED".to_string(),
+            format!("Ticket {} billed after interrupted close", ticket_no),
+        ));
+    }
+    Ok((
+        "VERIFIED".to_string(),
+        format!("Ticket {} is consistent ({})", ticket_no, status),
+    ))
+}
+
+fn recover_one(
+    app: &AppHandle,
+    conn: &Connection,
+    op: &Interrupted,
+) -> Result<(String, String), String> {
+    match op.kind.as_str() {
+        "ticket_write" => recover_ticket(conn, op.entity.as_deref()),
+        "print" => {
+            notifications::notify(
+                app,
+                conn,
+                "operator",
+                "Slip may not have printed",
+                "Printing was interrupted; reprint the slip if it did not come out",
+                op.entity.as_deref().map(|id| ("weighment", id)),
+            )?;
+            Ok((
+                "REPRINT".to_string(),
+                "Operator asked to reprint".to_string(),
+            ))
+        }
+        // Bulk jobs commit per ticket: processed tickets stay done, the rest
+        // are left for a new job
+        "bulk_job" => Ok((
+            "ROLLED_BACK".to_string(),
+            "Job stopped; tickets not yet processed were left unchanged".to_string(),
+        )),
+        // The task's own operation is journalled and recovered separately;
+        // a partial export file may be left behind
+        "task" => Ok((
+            "ROLLED_BACK".to_string(),
+            "Background task stopped; submit it again".to_string(),
+        )),
+        // Imports, syncs and backups run in one transaction or write a file
+        // that is only trusted after verification
+        "import" | "sync" => Ok((
+            "ROLLED_BACK".to_string(),
+            "Data left as it was before the import".to_string(),
+        )),
+        // Tickets are copied and removed in one transaction
+        "archive" => Ok((
+            "ROLLED_BACK".to_string(),
+            "Archival did not finish; tickets are where they were".to_string(),
+        )),
+        "backup" => Ok((
+            "ROLLED_BACK".to_string(),
+            "Backup incomplete; run it again before updating".to_string(),
+        )),
+        // The online backup API copies into the live file in one transaction
+        "restore" => Ok((
+            "ROLLED_BACK".to_string(),
+            "Restore did not finish; the database is as it was before".to_string(),
+        )),
+        // The encrypted copy is only swapped in once complete
+        "encrypt" => Ok((
+            "ROLLED_BACK".to_string(),
+            "Encryption did not finish; the database is still plaintext".to_string(),
+        )),
+        other => Ok((
+            "VERIFIED".to_string(),
+            format!("No recovery step for {} operations", other),
+        )),
+    }
+}
+
+// Resolve interrupted operations. Called from the setup hook before anything
+// else touches the database.
+pub fn recover(app: &AppHandle) -> Result<Vec<RecoveredOperation>, String> {
+    let db_path = crate::get_db_path(app)?;
+    if !db_path.exists() {
+        return Ok(Vec::new());
+    }
+    let conn =
+        encryption::open_file(app, &db_path, OpenFlags::default()).map_err(|e| e.to_string())?;
+    conn.execute_batch(db::SCHEMA).map_err(|e| e.to_string())?;
+
+    // Nothing runs before startup, so any job still RUNNING was interrupted
+    conn.execute(
+        "UPDATE bulk_jobs SET status = 'CANCELLED', finished_at = CURRENT_TIMESTAMP,
+                summary = 'Interrupted by shutdown'
+         WHERE status = 'RUNNING'",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE background_tasks SET status = 'CANCELLED', finished_at = CURRENT_TIMESTAMP,
+                error = 'Interrupted by shutdown'
+         WHERE status IN ('QUEUED', 'RUNNING')",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    workflow_journal::mark_interrupted(app, &conn)?;
+
+    let mut stmt = conn
+        .prepare("SELECT id, kind, entity, started_at FROM operation_journal ORDER BY started_at")
+        .map_err(|e| e.to_string())?;
+    let interrupted = stmt
+        .query_map([], |row| {
+            Ok(Interrupted {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                entity: row.get(2)?,
+                started_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut recovered = Vec::new();
+    for op in interrupted {
+        let (action, detail) =
+            recover_one(app, &conn, &op).unwrap_or_else(|e| ("FAILED".to_string(), e));
+        conn.execute(
+            "INSERT INTO recovery_log (kind, entity, action, detail, started_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![op.kind, op.entity, action, detail, op.started_at],
+        )
+        .map_err(|e| e.to_string())?;
+        let entry_id = conn.last_insert_rowid();
+        conn.execute("DELETE FROM operation_journal WHERE id = ?1", [&op.id])
+            .map_err(|e| e.to_string())?;
+        recovered.push(load_entry(&conn, entry_id)?);
+    }
+
+    if !recovered.is_empty() {
+        notifications::notify(
+            app,
+            &conn,
+            "admin",
+            "Recovered interrupted operations",
+            &format!(
+                "{} operation(s) were interrupted at the last shutdown; see the recovery report",
+                recovered.len()
+            ),
+            None,
+        )?;
+        let _ = app.emit_all("startup_recovery", &recovered);
+    }
+    Ok(recovered)
+}
+
+fn load_entry(conn: &Connection, id: i64) -> Result<RecoveredOperation, String> {
+    conn.query_row(
+        "SELECT kind, entity, action, detail, started_at, recovered_at
+         FROM recovery_log WHERE id = ?1",
+        [id],
+        row_to_entry,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn row_to_entry(row: &rusqlite::Row) -> rusqlite::Result<RecoveredOperation> {
+    Ok(RecoveredOperation {
+        kind: row.get(0)?,
+        entity: row.get(1)?,
+        action: row.get(2)?,
+        detail: row.get(3)?,
+        started_at: row.get(4)?,
+        recovered_at: row.get(5)?,
+    })
+}
+
+// Past recoveries, newest first
+#[tauri::command]
+pub fn recovery_report(app: AppHandle) -> Result<Vec<RecoveredOperation>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT kind, entity, action, detail, started_at, recovered_at
+             FROM recovery_log ORDER BY id DESC LIMIT 200",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_entry)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
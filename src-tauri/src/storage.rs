@@ -0,0 +1,296 @@
+// Database growth forecasting for Truckore Pro
+// Once a day the database file, the camera images stored in it and the free
+// space on its disk are sampled. A linear fit over recent samples projects
+// when the disk fills up; when that is close, admins are told what to
+// archive or prune through the notification center.
+
+use crate::db;
+use crate::notifications;
+use crate::shutdown;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// Samples used for the growth trend
+const TREND_DAYS: i64 = 90;
+// Warn when the disk is projected to fill within this many days
+const WARN_DAYS: f64 = 180.0;
+const ALERT_CONFIG_KEY: &str = "storage_alert_at";
+const ALERT_REPEAT_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageSample {
+    pub sampled_on: String,
+    pub db_bytes: i64,
+    // Camera images held inline in ticket rows
+    pub attachment_bytes: i64,
+    pub free_bytes: Option<i64>,
+    pub total_bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageForecast {
+    pub latest: Option<StorageSample>,
+    pub db_growth_bytes_per_day: f64,
+    pub attachment_growth_bytes_per_day: f64,
+    // None when the database is not growing or free space is unknown
+    pub days_until_full: Option<f64>,
+    pub recommendations: Vec<String>,
+}
+
+// (free, total) bytes on the filesystem holding `path`
+#[cfg(unix)]
+fn disk_space(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let block = stat.f_frsize as u64;
+    Some((stat.f_bavail as u64 * block, stat.f_blocks as u64 * block))
+}
+
+#[cfg(windows)]
+fn disk_space(path: &Path) -> Option<(u64, u64)> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let (mut free, mut total) = (0u64, 0u64);
+    let ok =
+        unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut free, &mut total, std::ptr::null_mut()) };
+    if ok == 0 {
+        return None;
+    }
+    Some((free, total))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn disk_space(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+fn take_sample(app: &AppHandle, conn: &Connection) -> Result<StorageSample, String> {
+    let db_path = crate::get_db_path(app)?;
+    let mut db_bytes = 0;
+    for suffix in ["", "-wal"] {
+        let file = format!("{}{}", db_path.to_string_lossy(), suffix);
+        db_bytes += fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+    }
+    let attachment_bytes: i64 = conn
+        .query_row(
+            "SELECT COALESCE(SUM(LENGTH(front_camera_image)), 0)
+                  + COALESCE(SUM(LENGTH(back_camera_image)), 0)
+                  + (SELECT COALESCE(SUM(LENGTH(camera_image)), 0) FROM open_tickets)
+                  + (SELECT COALESCE(SUM(size_bytes), 0) FROM attachments)
+             FROM weighments",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let space = db_path.parent().and_then(disk_space);
+    let sampled_on: String = conn
+        .query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    Ok(StorageSample {
+        sampled_on,
+        db_bytes: db_bytes as i64,
+        attachment_bytes,
+        free_bytes: space.map(|s| s.0 as i64),
+        total_bytes: space.map(|s| s.1 as i64),
+    })
+}
+
+fn record(conn: &Connection, sample: &StorageSample) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO storage_samples (sampled_on, db_bytes, attachment_bytes, free_bytes, total_bytes)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(sampled_on) DO UPDATE SET db_bytes = excluded.db_bytes,
+             attachment_bytes = excluded.attachment_bytes, free_bytes = excluded.free_bytes,
+             total_bytes = excluded.total_bytes",
+        params![
+            sample.sampled_on,
+            sample.db_bytes,
+            sample.attachment_bytes,
+            sample.free_bytes,
+            sample.total_bytes
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn load_samples(conn: &Connection, days: i64) -> Result<Vec<StorageSample>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT sampled_on, db_bytes, attachment_bytes, free_bytes, total_bytes
+             FROM storage_samples
+             WHERE sampled_on >= date('now', 'localtime', '-' || ?1 || ' days')
+             ORDER BY sampled_on",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([days], |row| {
+            Ok(StorageSample {
+                sampled_on: row.get(0)?,
+                db_bytes: row.get(1)?,
+                attachment_bytes: row.get(2)?,
+                free_bytes: row.get(3)?,
+                total_bytes: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Least-squares slope of (day index, value); 0 with fewer than two points
+fn slope(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let (num, den) = points.iter().fold((0.0, 0.0), |(num, den), (x, y)| {
+        (
+            num + (x - mean_x) * (y - mean_y),
+            den + (x - mean_x) * (x - mean_x),
+        )
+    });
+    if den == 0.0 {
+        0.0
+    } else {
+        num / den
+    }
+}
+
+fn forecast(conn: &Connection) -> Result<StorageForecast, String> {
+    let samples = load_samples(conn, TREND_DAYS)?;
+    // Day offsets come from SQLite so gaps in sampling are accounted for
+    let mut days = Vec::with_capacity(samples.len());
+    for sample in &samples {
+        let day: f64 = conn
+            .query_row("SELECT julianday(?1)", [&sample.sampled_on], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        days.push(day);
+    }
+    let series = |value: fn(&StorageSample) -> i64| -> Vec<(f64, f64)> {
+        days.iter()
+            .zip(&samples)
+            .map(|(d, s)| (*d, value(s) as f64))
+            .collect()
+    };
+    let db_growth = slope(&series(|s| s.db_bytes));
+    let attachment_growth = slope(&series(|s| s.attachment_bytes));
+    let latest = samples.last().cloned();
+
+    let days_until_full = match latest.as_ref().and_then(|s| s.free_bytes) {
+        Some(free) if db_growth > 0.0 => Some(free as f64 / db_growth),
+        _ => None,
+    };
+
+    let mut recommendations = Vec::new();
+    if let Some(days) = days_until_full.filter(|d| *d < WARN_DAYS) {
+        recommendations.push(format!(
+            "Disk projected to fill in {:.0} days; archive or export tickets older than the retention period",
+            days
+        ));
+    }
+    if let Some(sample) = &latest {
+        if sample.db_bytes > 0 && sample.attachment_bytes * 2 > sample.db_bytes {
+            recommendations.push(
+                "Camera images are over half of the database; archive images of printed tickets"
+                    .to_string(),
+            );
+        }
+        if let (Some(free), Some(total)) = (sample.free_bytes, sample.total_bytes) {
+            if total > 0 && free * 10 < total {
+                recommendations.push(
+                    "Less than 10% of the disk is free; move backups to another drive".to_string(),
+                );
+            }
+        }
+    }
+
+    Ok(StorageForecast {
+        latest,
+        db_growth_bytes_per_day: db_growth,
+        attachment_growth_bytes_per_day: attachment_growth,
+        days_until_full,
+        recommendations,
+    })
+}
+
+// Sample today's usage and notify admins of recommendations, at most weekly
+pub fn check(app: &AppHandle, conn: &Connection) -> Result<StorageForecast, String> {
+    record(conn, &take_sample(app, conn)?)?;
+    let forecast = forecast(conn)?;
+    if forecast.recommendations.is_empty() {
+        return Ok(forecast);
+    }
+    let due: bool = conn
+        .query_row(
+            "SELECT ?1 IS NULL OR julianday('now') - julianday(?1) >= ?2",
+            params![db::get_config(conn, ALERT_CONFIG_KEY)?, ALERT_REPEAT_DAYS],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if due {
+        notifications::notify(
+            app,
+            conn,
+            "admin",
+            "Storage housekeeping recommended",
+            &forecast.recommendations.join("\n"),
+            None,
+        )?;
+        conn.execute(
+            "INSERT INTO app_config (key, value, updated_at)
+             VALUES (?1, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value,
+                 updated_at = CURRENT_TIMESTAMP",
+            [ALERT_CONFIG_KEY],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(forecast)
+}
+
+// Background sampler; started from the app setup hook
+pub fn start_sampler(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            if let Ok(conn) = db::open(&app) {
+                let _ = check(&app, &conn);
+            }
+            std::thread::sleep(SAMPLE_INTERVAL);
+        }
+    });
+}
+
+// Take a fresh sample and return the projection
+#[tauri::command]
+pub fn storage_forecast(app: AppHandle) -> Result<StorageForecast, String> {
+    let conn = db::open(&app)?;
+    record(&conn, &take_sample(&app, &conn)?)?;
+    forecast(&conn)
+}
+
+#[tauri::command]
+pub fn list_storage_samples(
+    app: AppHandle,
+    days: Option<i64>,
+) -> Result<Vec<StorageSample>, String> {
+    let conn = db::open(&app)?;
+    load_samples(&conn, days.unwrap_or(TREND_DAYS).clamp(1, 3650))
+}
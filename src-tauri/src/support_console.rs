@@ -0,0 +1,189 @@
+// Read-only SQL console for remote support
+// Admins can run a single SELECT-style statement against a read-only
+// connection. Results are capped, long queries are interrupted, and every
+// query is logged in full with who ran it and what came back.
+
+use crate::command_audit;
+use crate::db;
+use crate::encryption;
+use crate::roles::{self, Role};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const DEFAULT_ROW_LIMIT: usize = 200;
+const MAX_ROW_LIMIT: usize = 5000;
+const DEFAULT_TIMEOUT_SECONDS: f64 = 5.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SupportQueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    // More rows were available than the limit allowed
+    pub truncated: bool,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SupportQueryLog {
+    pub id: i64,
+    pub user_id: String,
+    pub query: String,
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+    pub duration_ms: i64,
+    pub ran_at: String,
+}
+
+// Attached databases would let a query read other files on the machine
+fn check_statement(query: &str) -> Result<(), String> {
+    let attaches = query
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .any(|word| word.eq_ignore_ascii_case("attach"));
+    if attaches {
+        return Err("ATTACH is not allowed in support queries".to_string());
+    }
+    Ok(())
+}
+
+fn run_query(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+    timeout: Duration,
+) -> Result<SupportQueryResult, String> {
+    let started = Instant::now();
+    // prepare() also refuses more than one statement
+    let mut stmt = conn.prepare(query).map_err(|e| e.to_string())?;
+    if !stmt.readonly() {
+        return Err("Only read-only statements can be run".to_string());
+    }
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+
+    // Watchdog interrupts the statement when it runs past the timeout
+    let interrupt = conn.get_interrupt_handle();
+    let (done, finished) = mpsc::channel::<()>();
+    let watchdog = std::thread::spawn(move || {
+        if finished.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout) {
+            interrupt.interrupt();
+        }
+    });
+
+    let outcome = (|| {
+        let mut rows = Vec::new();
+        let mut truncated = false;
+        let mut cursor = stmt.query([])?;
+        while let Some(row) = cursor.next()? {
+            if rows.len() == limit {
+                truncated = true;
+                break;
+            }
+            let mut values = Vec::with_capacity(columns.len());
+            for i in 0..columns.len() {
+                values.push(crate::sql_to_json_value(row.get_ref(i)?));
+            }
+            rows.push(values);
+        }
+        Ok::<_, rusqlite::Error>((rows, truncated))
+    })();
+    let _ = done.send(());
+    let _ = watchdog.join();
+
+    let (rows, truncated) = outcome.map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.code == rusqlite::ErrorCode::OperationInterrupted =>
+        {
+            format!("Query stopped after {:.0} s", timeout.as_secs_f64())
+        }
+        other => other.to_string(),
+    })?;
+    Ok(SupportQueryResult {
+        columns,
+        rows,
+        truncated,
+        duration_ms: started.elapsed().as_millis() as i64,
+    })
+}
+
+// Run one read-only statement for support (admins only)
+#[tauri::command]
+pub fn support_query(
+    app: AppHandle,
+    query: String,
+    limit: Option<usize>,
+    timeout_seconds: Option<f64>,
+    user_id: String,
+) -> Result<SupportQueryResult, String> {
+    let args = serde_json::json!({ "query": query });
+    command_audit::audited(&app, "support_query", &user_id, args, || {
+        let log = db::open(&app)?;
+        roles::require_role(&log, &user_id, Role::Admin)?;
+
+        let limit = limit.unwrap_or(DEFAULT_ROW_LIMIT).clamp(1, MAX_ROW_LIMIT);
+        let timeout = Duration::from_secs_f64(
+            timeout_seconds
+                .unwrap_or(DEFAULT_TIMEOUT_SECONDS)
+                .clamp(0.5, 60.0),
+        );
+        let started = Instant::now();
+        let result = check_statement(&query).and_then(|_| {
+            let conn = encryption::open_file(
+                &app,
+                &crate::get_db_path(&app)?,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+            .map_err(|e| e.to_string())?;
+            conn.execute_batch("PRAGMA query_only = ON;")
+                .map_err(|e| e.to_string())?;
+            run_query(&conn, &query, limit, timeout)
+        });
+
+        log.execute(
+            "INSERT INTO support_query_log (user_id, query, row_count, error, duration_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                user_id,
+                query,
+                result.as_ref().ok().map(|r| r.rows.len() as i64),
+                result.as_ref().err(),
+                started.elapsed().as_millis() as i64
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        result
+    })
+}
+
+// Support query history, newest first (admins only)
+#[tauri::command]
+pub fn list_support_queries(
+    app: AppHandle,
+    user_id: String,
+) -> Result<Vec<SupportQueryLog>, String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, user_id, query, row_count, error, duration_ms, ran_at
+             FROM support_query_log ORDER BY id DESC LIMIT 200",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(SupportQueryLog {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                query: row.get(2)?,
+                row_count: row.get(3)?,
+                error: row.get(4)?,
+                duration_ms: row.get(5)?,
+                ran_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
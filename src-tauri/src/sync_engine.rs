@@ -0,0 +1,1227 @@
+// Head-office sync for Truckore Pro
+// Sites keep weighing while the link is down and consolidate at head office
+// when it is back. Every write to a tracked table moves its row_version
+// (delta_sync.rs) and records when the row last changed in sync_change_log.
+// push_changes sends the server what it has not acknowledged and
+// pull_changes applies what other sites wrote, both as JSON over HTTPS. When
+// this site and the server both changed a row since the last sync the later
+// write wins, and the pair is kept in sync_conflicts for review. A pulled
+// weighment in this site's locked period (period_lock.rs) is not applied
+// but kept there too, so the rest of the batch still lands. Weighments
+// travel with the signature of the site that weighed them (signatures.rs). A
+// background loop pushes then pulls on an interval while the server is
+// reachable and reports on `sync-status`. Gated by the `sync` feature flag.
+// Practice tickets (training.rs) stay on this PC and are not counted as
+// waiting. Attachments the pushed rows refer to are uploaded in chunks
+// (uploads.rs) after the rows. Crash reports and usage telemetry the site
+// opted in to share go along with each pass, telemetry without the site's
+// token; an upload that fails stays queued for the next.
+
+use crate::attachments;
+use crate::bandwidth;
+use crate::command_audit;
+use crate::crash_reports;
+use crate::db;
+use crate::delta_sync::{self, TRACKED_TABLES};
+use crate::feature_flags;
+use crate::license;
+use crate::network;
+use crate::period_lock;
+use crate::roles::{self, Role};
+use crate::settings_events;
+use crate::shutdown;
+use crate::signatures;
+use crate::telemetry;
+use crate::training;
+use crate::uploads;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+const SETTINGS_CONFIG_KEY: &str = "sync_settings";
+const TOKEN_CONFIG_KEY: &str = "sync_api_token";
+const FEATURE_FLAG: &str = "sync";
+// Premium feature a license must cover (license.rs)
+const LICENSE_FEATURE: &str = "sync";
+const STATUS_EVENT: &str = "sync-status";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+// How soon to look for the server again after the link went down
+const OFFLINE_RETRY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+    pub enabled: bool,
+    // Base URL of the head-office sync API, https only
+    pub endpoint: String,
+    pub interval_seconds: u64,
+    // Changes per request either way
+    pub batch_size: i64,
+    // Kept under its own key and never sent back to the frontend
+    #[serde(skip_serializing)]
+    pub api_token: Option<String>,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        SyncSettings {
+            enabled: false,
+            endpoint: String::new(),
+            interval_seconds: 300,
+            batch_size: 500,
+            api_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncStatus {
+    // disabled, offline, syncing, idle or error
+    pub state: String,
+    pub last_sync_at: Option<String>,
+    pub last_error: Option<String>,
+    // Local changes the server has not acknowledged
+    pub pending: i64,
+    // Conflicts nobody has reviewed yet
+    pub conflicts: i64,
+}
+
+impl Default for SyncStatus {
+    fn default() -> Self {
+        SyncStatus {
+            state: "disabled".to_string(),
+            last_sync_at: None,
+            last_error: None,
+            pending: 0,
+            conflicts: 0,
+        }
+    }
+}
+
+// One row change as exchanged with the server, either way
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteChange {
+    pub table: String,
+    pub row_key: String,
+    // None for a deletion
+    pub row: Option<Value>,
+    // UTC, ISO-8601
+    pub changed_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PullResponse {
+    // Pass back on the next pull
+    cursor: i64,
+    changes: Vec<RemoteChange>,
+    #[serde(default)]
+    has_more: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PushOutcome {
+    pub sent: usize,
+    // Watermark the server has acknowledged
+    pub version: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PullOutcome {
+    pub applied: usize,
+    // Local writes kept over the server's, already in `conflicts`
+    pub skipped: usize,
+    pub conflicts: usize,
+    pub cursor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub id: i64,
+    pub table_name: String,
+    pub row_key: String,
+    pub winner: String,
+    pub local_changed_at: String,
+    pub remote_changed_at: String,
+    pub local_row: Option<Value>,
+    pub remote_row: Option<Value>,
+    // Set when the row was kept for another reason, e.g. period_locked
+    pub reason: Option<String>,
+    pub detected_at: String,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<String>,
+}
+
+// Managed state: the loop's wake-up channel, the last status, and a lock so
+// the loop and the commands never sync at the same time
+#[derive(Default)]
+pub struct SyncEngine {
+    wake: Mutex<Option<Sender<()>>>,
+    status: Mutex<SyncStatus>,
+    running: Mutex<()>,
+}
+
+// Log every tracked write with its time, and give existing rows the time
+// they were last touched. Schema migration 6; the caller holds the
+// transaction.
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    for table in TRACKED_TABLES {
+        track_changes(conn, table)?;
+    }
+    Ok(())
+}
+
+// Change-log triggers for one tracked table, and a log entry for each row
+// already there
+pub fn track_changes(conn: &Connection, table: &str) -> Result<(), String> {
+    let touched = match table {
+        "weighments" => "COALESCE(updated_at, created_at)",
+        "operator_profiles" => "updated_at",
+        _ => "created_at",
+    };
+    // Upserts rather than INSERT OR REPLACE, which an outer upsert (as
+    // pull_changes writes) would override
+    conn.execute_batch(&format!(
+        "CREATE TRIGGER IF NOT EXISTS {t}_change_log_write
+         AFTER UPDATE OF row_version ON {t}
+         WHEN NEW.row_version <> OLD.row_version
+         BEGIN
+             INSERT INTO sync_change_log (table_name, row_key, op, changed_at)
+             VALUES ('{t}', CAST(NEW.id AS TEXT), 'upsert',
+                     strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+             ON CONFLICT(table_name, row_key) DO UPDATE SET
+                 op = excluded.op, changed_at = excluded.changed_at, origin = 'local';
+         END;
+         CREATE TRIGGER IF NOT EXISTS {t}_change_log_delete
+         AFTER DELETE ON {t}
+         BEGIN
+             INSERT INTO sync_change_log (table_name, row_key, op, changed_at)
+             VALUES ('{t}', CAST(OLD.id AS TEXT), 'delete',
+                     strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+             ON CONFLICT(table_name, row_key) DO UPDATE SET
+                 op = excluded.op, changed_at = excluded.changed_at, origin = 'local';
+         END;
+         INSERT OR IGNORE INTO sync_change_log (table_name, row_key, op, changed_at)
+         SELECT '{t}', CAST(id AS TEXT), 'upsert',
+                strftime('%Y-%m-%dT%H:%M:%fZ', COALESCE({touched}, 'now'))
+         FROM {t};",
+        t = table,
+        touched = touched
+    ))
+    .map_err(|e| format!("Change log for {} failed: {}", table, e))
+}
+
+fn load_settings(conn: &Connection) -> Result<SyncSettings, String> {
+    let mut settings: SyncSettings = match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+        None => SyncSettings::default(),
+    };
+    settings.api_token = db::get_config(conn, TOKEN_CONFIG_KEY)?;
+    Ok(settings)
+}
+
+// Watermark target for changes the server holds, and for its pull cursor
+fn push_target(settings: &SyncSettings) -> String {
+    settings.endpoint.clone()
+}
+
+fn pull_target(settings: &SyncSettings) -> String {
+    format!("pull:{}", settings.endpoint)
+}
+
+fn site_id(conn: &Connection) -> Result<String, String> {
+    db::get_config(conn, "site_id")?
+        .filter(|site| !site.is_empty())
+        .ok_or_else(|| "Set this site's id before syncing".to_string())
+}
+
+// Settings for a sync that is about to run: the flag is on, a trial or
+// license covers sync, sync is enabled and the server is configured
+fn ready(app: &AppHandle, conn: &Connection) -> Result<SyncSettings, String> {
+    feature_flags::require_enabled(conn, FEATURE_FLAG)?;
+    license::covers(app, LICENSE_FEATURE)?;
+    let settings = load_settings(conn)?;
+    if !settings.enabled {
+        return Err("Sync is switched off in the sync settings".to_string());
+    }
+    if settings.endpoint.is_empty() {
+        return Err("No sync server is configured".to_string());
+    }
+    Ok(settings)
+}
+
+fn post<T: DeserializeOwned>(
+    conn: &Connection,
+    settings: &SyncSettings,
+    path: &str,
+    body: &str,
+) -> Result<T, String> {
+    send(conn, settings, path, body, settings.api_token.as_deref())
+}
+
+fn send<T: DeserializeOwned>(
+    conn: &Connection,
+    settings: &SyncSettings,
+    path: &str,
+    body: &str,
+    token: Option<&str>,
+) -> Result<T, String> {
+    let url = format!("{}/{}", settings.endpoint.trim_end_matches('/'), path);
+    let agent = network::http_agent(conn, &url, REQUEST_TIMEOUT)?;
+    let mut request = agent.post(&url).set("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let response = request.send_string(body).map_err(|e| match e {
+        ureq::Error::Status(code, response) => format!(
+            "Sync server answered HTTP {}: {}",
+            code,
+            response.into_string().unwrap_or_default()
+        ),
+        other => format!("Sync server unreachable: {}", other),
+    })?;
+    response
+        .into_json()
+        .map_err(|e| format!("Sync server sent an invalid response: {}", e))
+}
+
+fn changed_at(conn: &Connection, table: &str, row_key: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT changed_at FROM sync_change_log WHERE table_name = ?1 AND row_key = ?2",
+        params![table, row_key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+fn now(conn: &Connection) -> Result<String, String> {
+    conn.query_row("SELECT strftime('%Y-%m-%dT%H:%M:%fZ', 'now')", [], |row| {
+        row.get(0)
+    })
+    .map_err(|e| e.to_string())
+}
+
+// Key of a row as the tombstones and the change log spell it
+fn row_key(row: &Value) -> Option<String> {
+    match &row["id"] {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+// Attachments a row refers to, which the server needs as files
+fn attachment_references(row: &Value) -> impl Iterator<Item = &str> {
+    row.as_object()
+        .into_iter()
+        .flat_map(|object| object.values())
+        .filter_map(Value::as_str)
+        .filter(|text| text.starts_with(attachments::REF_PREFIX))
+}
+
+// Local changes after `version` that the server has not been sent
+fn pending_after(conn: &Connection, version: i64) -> Result<i64, String> {
+    let mut total: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sync_tombstones WHERE row_version > ?1",
+            params![version],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    for table in TRACKED_TABLES {
+        let practice = match *table {
+            "weighments" => format!(" AND {}", training::exclude_practice("id")),
+            _ => String::new(),
+        };
+        total += conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM {} WHERE row_version > ?1{}",
+                    table, practice
+                ),
+                params![version],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(total)
+}
+
+// Send every unacknowledged change, a batch at a time, holding to the
+// bandwidth limits
+fn push(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &SyncSettings,
+) -> Result<PushOutcome, String> {
+    let site = site_id(conn)?;
+    let target = push_target(settings);
+    let mut outcome = PushOutcome {
+        sent: 0,
+        version: delta_sync::watermark(conn, &target)?,
+    };
+    loop {
+        let changes =
+            delta_sync::collect(conn, &target, TRACKED_TABLES, settings.batch_size.max(1))?;
+        if changes.rows.is_empty() && changes.deleted.is_empty() {
+            return Ok(outcome);
+        }
+        let mut batch = Vec::new();
+        for changed in &changes.rows {
+            let key = row_key(&changed.row)
+                .ok_or_else(|| format!("A {} row has no id", changed.table))?;
+            // A missing file must not hold the rows back
+            for reference in attachment_references(&changed.row) {
+                if let Err(e) = uploads::queue_attachment(app, conn, reference) {
+                    tracing::warn!(reference, error = %e, "Attachment not queued for upload");
+                }
+            }
+            let mut row = changed.row.clone();
+            if changed.table == "weighments" {
+                if let (Some(object), Some(signed)) = (
+                    row.as_object_mut(),
+                    signatures::exported(conn, &key, &site)?,
+                ) {
+                    object.insert(signatures::SYNC_FIELD.to_string(), signed);
+                }
+            }
+            batch.push(RemoteChange {
+                changed_at: changed_at(conn, &changed.table, &key)?.unwrap_or(now(conn)?),
+                table: changed.table.clone(),
+                row_key: key,
+                row: Some(row),
+            });
+        }
+        for deleted in &changes.deleted {
+            batch.push(RemoteChange {
+                changed_at: changed_at(conn, &deleted.table, &deleted.row_key)?
+                    .unwrap_or(now(conn)?),
+                table: deleted.table.clone(),
+                row_key: deleted.row_key.clone(),
+                row: None,
+            });
+        }
+        let body = serde_json::json!({
+            "site_id": site,
+            "from_version": changes.from_version,
+            "to_version": changes.to_version,
+            "changes": batch,
+        })
+        .to_string();
+        // A busy link is waited for; a paused one ends the push
+        while let Err(e) = bandwidth::reserve(app, conn, body.len() as u64) {
+            let wait = bandwidth::wait(app)?;
+            if wait.is_zero() {
+                return Err(e);
+            }
+            std::thread::sleep(wait);
+        }
+        let _: Value = post(conn, settings, "push", &body)?;
+        outcome.sent += batch.len();
+        outcome.version = delta_sync::ack(conn, &target, changes.to_version)?;
+        if !changes.has_more {
+            return Ok(outcome);
+        }
+    }
+}
+
+fn local_row(conn: &Connection, table: &str, row_key: &str) -> Result<Option<Value>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT * FROM {} WHERE CAST(id AS TEXT) = ?1",
+            table
+        ))
+        .map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    stmt.query_row(params![row_key], |row| {
+        let mut map = serde_json::Map::new();
+        for (i, name) in columns.iter().enumerate() {
+            map.insert(name.clone(), crate::sql_to_json_value(row.get_ref(i)?));
+        }
+        Ok(Value::Object(map))
+    })
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Version of a row's last local write, live or deleted
+fn local_version(conn: &Connection, table: &str, row_key: &str) -> Result<Option<i64>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT COALESCE(
+                 (SELECT row_version FROM {} WHERE CAST(id AS TEXT) = ?1),
+                 (SELECT row_version FROM sync_tombstones
+                  WHERE table_name = ?2 AND row_key = ?1))",
+            table
+        ),
+        params![row_key, table],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| e.to_string())?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(columns)
+}
+
+// Write the server's version of a row. row_version is left to the
+// triggers, which also stamp the change log; the remote time replaces the
+// local one afterwards. A weighment keeps its origin site's signature.
+fn apply_change(conn: &Connection, change: &RemoteChange, site: &str) -> Result<(), String> {
+    match &change.row {
+        None => {
+            conn.execute(
+                &format!("DELETE FROM {} WHERE CAST(id AS TEXT) = ?1", change.table),
+                params![change.row_key],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Some(row) => {
+            let object = row
+                .as_object()
+                .ok_or_else(|| format!("{} {} is not an object", change.table, change.row_key))?;
+            let columns: Vec<String> = table_columns(conn, &change.table)?
+                .into_iter()
+                .filter(|c| c != "row_version" && object.contains_key(c))
+                .collect();
+            if !columns.iter().any(|c| c == "id") {
+                return Err(format!("{} {} has no id", change.table, change.row_key));
+            }
+            let placeholders: Vec<String> =
+                (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+            let updates: Vec<String> = columns
+                .iter()
+                .filter(|c| *c != "id")
+                .map(|c| format!("{c} = excluded.{c}"))
+                .collect();
+            let sql = format!(
+                "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT(id) DO {}",
+                change.table,
+                columns.join(", "),
+                placeholders.join(", "),
+                match updates.is_empty() {
+                    true => "NOTHING".to_string(),
+                    false => format!("UPDATE SET {}", updates.join(", ")),
+                }
+            );
+            let values: Vec<rusqlite::types::Value> = columns
+                .iter()
+                .map(|c| crate::json_to_sql_value(&object[c]))
+                .collect();
+            conn.execute(&sql, rusqlite::params_from_iter(values))
+                .map_err(|e| {
+                    format!("Applying {} {} failed: {}", change.table, change.row_key, e)
+                })?;
+            if change.table == "weighments" {
+                signatures::keep_remote(
+                    conn,
+                    &change.row_key,
+                    object.get(signatures::SYNC_FIELD),
+                    site,
+                )?;
+            }
+        }
+    }
+    conn.execute(
+        "UPDATE sync_change_log SET changed_at = strftime('%Y-%m-%dT%H:%M:%fZ', ?3),
+                origin = 'remote'
+         WHERE table_name = ?1 AND row_key = ?2",
+        params![change.table, change.row_key, change.changed_at],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Keep both sides of a row that was not simply applied, with `reason`
+// when it is not that both changed
+fn record_conflict(
+    conn: &Connection,
+    change: &RemoteChange,
+    winner: &str,
+    local: Option<&Value>,
+    reason: Option<&str>,
+) -> Result<(), String> {
+    let local_at = changed_at(conn, &change.table, &change.row_key)?.unwrap_or_default();
+    conn.execute(
+        "INSERT INTO sync_conflicts (table_name, row_key, winner, local_changed_at,
+                                     remote_changed_at, local_row, remote_row, reason)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            change.table,
+            change.row_key,
+            winner,
+            local_at,
+            change.changed_at,
+            local.map(|row| row.to_string()),
+            change.row.as_ref().map(|row| row.to_string()),
+            reason,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Apply one pulled batch and move the cursor, all or nothing. A row with a
+// local write the server has not seen goes to whichever write is later. A
+// row the period lock refuses stays as it is here and is recorded as a
+// conflict, rather than holding the cursor back for good.
+fn apply_batch(
+    conn: &mut Connection,
+    settings: &SyncSettings,
+    response: &PullResponse,
+    outcome: &mut PullOutcome,
+) -> Result<(), String> {
+    let site = site_id(conn)?;
+    let mut tx = conn.transaction().map_err(|e| e.to_string())?;
+    let pushed = delta_sync::watermark(&tx, &push_target(settings))?;
+    let caught_up = pending_after(&tx, pushed)? == 0;
+    for change in &response.changes {
+        if !TRACKED_TABLES.contains(&change.table.as_str()) {
+            return Err(format!("{} is not synced", change.table));
+        }
+        let local = local_row(&tx, &change.table, &change.row_key)?;
+        let unpushed = local_version(&tx, &change.table, &change.row_key)?
+            .is_some_and(|version| version > pushed);
+        if unpushed {
+            let local_at = changed_at(&tx, &change.table, &change.row_key)?.unwrap_or_default();
+            let local_wins: bool = tx
+                .query_row(
+                    "SELECT COALESCE(julianday(?1) > julianday(?2), 0)",
+                    params![local_at, change.changed_at],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            if local_wins {
+                record_conflict(&tx, change, "local", local.as_ref(), None)?;
+                outcome.conflicts += 1;
+                outcome.skipped += 1;
+                continue;
+            }
+        }
+        let savepoint = tx.savepoint().map_err(|e| e.to_string())?;
+        match apply_change(&savepoint, change, &site) {
+            Ok(()) => savepoint.commit().map_err(|e| e.to_string())?,
+            Err(e) if e.contains(period_lock::LOCKED_ERROR) => {
+                // Dropping the savepoint rolls the row back
+                drop(savepoint);
+                record_conflict(&tx, change, "local", local.as_ref(), Some("period_locked"))?;
+                outcome.conflicts += 1;
+                outcome.skipped += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        if unpushed {
+            record_conflict(&tx, change, "remote", local.as_ref(), None)?;
+            outcome.conflicts += 1;
+        }
+        outcome.applied += 1;
+    }
+    // What came from the server is not pushed back to it, unless local
+    // writes are still waiting in front of it
+    if caught_up {
+        let clock: i64 = tx
+            .query_row("SELECT version FROM sync_clock WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        delta_sync::ack(&tx, &push_target(settings), clock)?;
+    }
+    outcome.cursor = delta_sync::ack(&tx, &pull_target(settings), response.cursor)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn pull(conn: &mut Connection, settings: &SyncSettings) -> Result<PullOutcome, String> {
+    let site = site_id(conn)?;
+    let target = pull_target(settings);
+    let mut outcome = PullOutcome {
+        cursor: delta_sync::watermark(conn, &target)?,
+        ..Default::default()
+    };
+    loop {
+        let body = serde_json::json!({
+            "site_id": site,
+            "cursor": outcome.cursor,
+            "limit": settings.batch_size.max(1),
+        })
+        .to_string();
+        let response: PullResponse = post(conn, settings, "pull", &body)?;
+        apply_batch(conn, settings, &response, &mut outcome)?;
+        if !response.has_more || response.changes.is_empty() {
+            return Ok(outcome);
+        }
+    }
+}
+
+pub fn status(app: &AppHandle) -> SyncStatus {
+    app.state::<SyncEngine>()
+        .status
+        .lock()
+        .map(|status| status.clone())
+        .unwrap_or_default()
+}
+
+// Store and announce the status, with fresh counts when the database is
+// readable
+fn publish(app: &AppHandle, mut status: SyncStatus) {
+    if let Ok(conn) = db::open(app) {
+        if let Ok(settings) = load_settings(&conn) {
+            if let Ok(pushed) = delta_sync::watermark(&conn, &push_target(&settings)) {
+                status.pending = pending_after(&conn, pushed).unwrap_or(status.pending);
+            }
+        }
+        if let Ok(open) = conn.query_row(
+            "SELECT COUNT(*) FROM sync_conflicts WHERE reviewed_at IS NULL",
+            [],
+            |row| row.get(0),
+        ) {
+            status.conflicts = open;
+        }
+    }
+    if let Ok(mut current) = app.state::<SyncEngine>().status.lock() {
+        *current = status.clone();
+    }
+    let _ = app.emit_all(STATUS_EVENT, &status);
+}
+
+fn set_state(app: &AppHandle, state: &str, last_error: Option<String>) {
+    let mut next = status(app);
+    next.state = state.to_string();
+    if state == "idle" {
+        next.last_sync_at = db::open(app).and_then(|conn| now(&conn)).ok();
+    }
+    if state != "syncing" {
+        next.last_error = last_error;
+    }
+    publish(app, next);
+}
+
+// Push then pull, one sync at a time
+fn sync_once(app: &AppHandle) -> Result<(PushOutcome, PullOutcome), String> {
+    let engine = app.state::<SyncEngine>();
+    let _running = engine.running.lock().map_err(|e| e.to_string())?;
+    let mut conn = db::open(app)?;
+    let settings = ready(app, &conn)?;
+    let pushed = push(app, &conn, &settings)?;
+    let pulled = pull(&mut conn, &settings)?;
+    if let Err(e) = send_uploads(app, &conn, &settings) {
+        tracing::warn!(error = %e, "Upload stopped");
+    }
+    if let Err(e) = send_crash_reports(app, &conn, &settings) {
+        tracing::warn!(error = %e, "Crash report upload failed");
+    }
+    if let Err(e) = send_telemetry(app, &conn, &settings) {
+        tracing::warn!(error = %e, "Telemetry upload failed");
+    }
+    Ok((pushed, pulled))
+}
+
+// Pending uploads, a chunk at a time. The server answers each chunk with
+// the SHA-256 it received; a send that fails leaves the rest of the upload
+// for the next pass.
+fn send_uploads(app: &AppHandle, conn: &Connection, settings: &SyncSettings) -> Result<(), String> {
+    for session in uploads::pending(conn)? {
+        let remote_id = match session.remote_id.clone() {
+            Some(id) => id,
+            None => {
+                let body = serde_json::json!({
+                    "source": session.source,
+                    "size_bytes": session.size_bytes,
+                    "sha256": session.sha256,
+                    "chunk_size": session.chunk_size,
+                    "chunk_count": session.chunk_count,
+                })
+                .to_string();
+                let created: Value = post(conn, settings, "uploads", &body)?;
+                let id = created["upload_id"]
+                    .as_str()
+                    .ok_or("Sync server sent no upload id")?
+                    .to_string();
+                uploads::set_remote_id(conn, &session.id, &id)?;
+                id
+            }
+        };
+        for index in &session.pending_chunks {
+            let chunk = uploads::read_chunk(app, conn, &session.id, *index)?;
+            let body = serde_json::to_string(&chunk).map_err(|e| e.to_string())?;
+            let path = format!("uploads/{}/chunks", remote_id);
+            let received: Value = match post(conn, settings, &path, &body) {
+                Ok(received) => received,
+                Err(e) => {
+                    uploads::record_failure(conn, &session.id, &e)?;
+                    return Err(e);
+                }
+            };
+            let sha256 = received["sha256"].as_str().unwrap_or_default();
+            uploads::ack_chunk(conn, &session.id, *index, sha256)?;
+        }
+        let body = serde_json::json!({ "sha256": session.sha256 }).to_string();
+        let _: Value = post(
+            conn,
+            settings,
+            &format!("uploads/{}/complete", remote_id),
+            &body,
+        )?;
+        uploads::complete(conn, &session.id)?;
+    }
+    Ok(())
+}
+
+// Queued crash reports, all in one request
+fn send_crash_reports(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &SyncSettings,
+) -> Result<(), String> {
+    let reports = crash_reports::pending(conn)?;
+    if reports.is_empty() {
+        return Ok(());
+    }
+    let body = serde_json::json!({ "site_id": site_id(conn)?, "reports": reports }).to_string();
+    bandwidth::reserve(app, conn, body.len() as u64)?;
+    let _: Value = post(conn, settings, "crash-reports", &body)?;
+    let ids: Vec<i64> = reports.iter().map(|r| r.id).collect();
+    crash_reports::mark_uploaded(conn, &ids)
+}
+
+// Queued telemetry batches. They carry an install id rather than the site
+// id, so they are sent without the site's token.
+fn send_telemetry(
+    app: &AppHandle,
+    conn: &Connection,
+    settings: &SyncSettings,
+) -> Result<(), String> {
+    let entries = telemetry::pending(conn)?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let batches: Vec<_> = entries.iter().map(|e| &e.batch).collect();
+    let body = serde_json::json!({ "batches": batches }).to_string();
+    bandwidth::reserve(app, conn, body.len() as u64)?;
+    let _: Value = send(conn, settings, "telemetry", &body, None)?;
+    let ids: Vec<i64> = entries.iter().map(|e| e.id).collect();
+    telemetry::mark_uploaded(conn, &ids)
+}
+
+// One pass of the loop; returns how long to wait before the next
+fn cycle(app: &AppHandle) -> Duration {
+    let settings = match db::open(app).and_then(|conn| {
+        let enabled = feature_flags::is_enabled(&conn, FEATURE_FLAG)?;
+        Ok((enabled, load_settings(&conn)?, conn))
+    }) {
+        Ok((true, settings, conn)) if settings.enabled && !settings.endpoint.is_empty() => {
+            (settings, conn)
+        }
+        Ok((_, settings, _)) => {
+            set_state(app, "disabled", None);
+            return Duration::from_secs(settings.interval_seconds.max(1));
+        }
+        // The database may not be initialised yet
+        Err(_) => return OFFLINE_RETRY,
+    };
+    let (settings, conn) = settings;
+    let interval = Duration::from_secs(settings.interval_seconds.max(1));
+    // Switched on while licensed; a lapsed license stops it here
+    if let Err(e) = license::covers(app, LICENSE_FEATURE) {
+        set_state(app, "disabled", Some(e));
+        return interval;
+    }
+    if let Err(e) = network::probe(&conn, &settings.endpoint, PROBE_TIMEOUT) {
+        set_state(app, "offline", Some(e));
+        return OFFLINE_RETRY.min(interval);
+    }
+    drop(conn);
+    set_state(app, "syncing", None);
+    match sync_once(app) {
+        Ok(_) => set_state(app, "idle", None),
+        Err(e) => set_state(app, "error", Some(e)),
+    }
+    interval
+}
+
+// Sync in the background for the life of the app. Changed sync settings
+// and sync_now start a pass straight away.
+pub fn start_loop(app: AppHandle) {
+    let (tx, rx) = mpsc::channel();
+    if let Ok(mut wake) = app.state::<SyncEngine>().wake.lock() {
+        *wake = Some(tx.clone());
+    }
+    app.listen_global(settings_events::EVENT, move |event| {
+        if settings_events::parse(&event)
+            .is_some_and(|c| c.contains(SETTINGS_CONFIG_KEY) || c.contains(TOKEN_CONFIG_KEY))
+        {
+            let _ = tx.send(());
+        }
+    });
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            let wait = cycle(&app);
+            match rx.recv_timeout(wait) {
+                Ok(()) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_sync_settings(app: AppHandle) -> Result<SyncSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+// Supervisors only. A token of None keeps the stored one.
+#[tauri::command]
+pub fn set_sync_settings(
+    app: AppHandle,
+    settings: SyncSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "enabled": settings.enabled, "endpoint": settings.endpoint });
+    command_audit::audited(&app, "set_sync_settings", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let mut settings = settings;
+        settings.endpoint = settings.endpoint.trim().trim_end_matches('/').to_string();
+        if (settings.enabled || !settings.endpoint.is_empty())
+            && !settings.endpoint.starts_with("https://")
+        {
+            return Err("Sync server URL must start with https://".to_string());
+        }
+        if settings.interval_seconds < 30 {
+            return Err("Sync interval must be at least 30 seconds".to_string());
+        }
+        if !(1..=5000).contains(&settings.batch_size) {
+            return Err("Batch size must be between 1 and 5000".to_string());
+        }
+        if let Some(token) = &settings.api_token {
+            db::set_config(&conn, TOKEN_CONFIG_KEY, token)?;
+        }
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)
+    })
+}
+
+#[tauri::command]
+pub fn get_sync_status(engine: State<'_, SyncEngine>) -> Result<SyncStatus, String> {
+    let status = engine.status.lock().map_err(|e| e.to_string())?;
+    Ok(status.clone())
+}
+
+// Start a background pass now instead of at the next interval
+#[tauri::command]
+pub fn sync_now(engine: State<'_, SyncEngine>) -> Result<(), String> {
+    let wake = engine.wake.lock().map_err(|e| e.to_string())?;
+    let tx = wake.as_ref().ok_or("Sync has not started")?;
+    tx.send(()).map_err(|e| e.to_string())
+}
+
+// Send local changes now, waiting for the result
+#[tauri::command]
+pub async fn push_changes(app: AppHandle) -> Result<PushOutcome, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let engine = app.state::<SyncEngine>();
+        let outcome = {
+            let _running = engine.running.lock().map_err(|e| e.to_string())?;
+            let conn = db::open(&app)?;
+            let settings = ready(&app, &conn)?;
+            push(&app, &conn, &settings)
+        };
+        publish(&app, status(&app));
+        outcome
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Apply the server's changes now, waiting for the result
+#[tauri::command]
+pub async fn pull_changes(app: AppHandle) -> Result<PullOutcome, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let engine = app.state::<SyncEngine>();
+        let outcome = {
+            let _running = engine.running.lock().map_err(|e| e.to_string())?;
+            let mut conn = db::open(&app)?;
+            let settings = ready(&app, &conn)?;
+            pull(&mut conn, &settings)
+        };
+        publish(&app, status(&app));
+        outcome
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Conflicts newest first; reviewed ones only when asked for
+#[tauri::command]
+pub fn list_sync_conflicts(
+    app: AppHandle,
+    include_reviewed: Option<bool>,
+    limit: Option<i64>,
+) -> Result<Vec<SyncConflict>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, table_name, row_key, winner, local_changed_at, remote_changed_at,
+                    local_row, remote_row, detected_at, reviewed_by, reviewed_at, reason
+             FROM sync_conflicts
+             WHERE ?1 OR reviewed_at IS NULL
+             ORDER BY id DESC LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let json = |text: Option<String>| text.and_then(|t| serde_json::from_str(&t).ok());
+    let conflicts = stmt
+        .query_map(
+            params![include_reviewed.unwrap_or(false), limit.unwrap_or(200)],
+            |row| {
+                Ok(SyncConflict {
+                    id: row.get(0)?,
+                    table_name: row.get(1)?,
+                    row_key: row.get(2)?,
+                    winner: row.get(3)?,
+                    local_changed_at: row.get(4)?,
+                    remote_changed_at: row.get(5)?,
+                    local_row: json(row.get(6)?),
+                    remote_row: json(row.get(7)?),
+                    detected_at: row.get(8)?,
+                    reviewed_by: row.get(9)?,
+                    reviewed_at: row.get(10)?,
+                    reason: row.get(11)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(conflicts)
+}
+
+// Mark a conflict as looked at (supervisors)
+#[tauri::command]
+pub fn review_sync_conflict(app: AppHandle, id: i64, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "id": id });
+    command_audit::audited(&app, "review_sync_conflict", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let updated = conn
+            .execute(
+                "UPDATE sync_conflicts SET reviewed_by = ?2, reviewed_at = CURRENT_TIMESTAMP
+                 WHERE id = ?1 AND reviewed_at IS NULL",
+                params![id, user_id],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err(format!("Conflict {} is not open", id));
+        }
+        Ok(())
+    })?;
+    publish(&app, status(&app));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        signatures::register(&conn).unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        migrations::run(&conn).unwrap();
+        db::set_config(&conn, "site_id", "site-a").unwrap();
+        conn
+    }
+
+    fn weighment(id: &str, created_at: &str) -> RemoteChange {
+        RemoteChange {
+            table: "weighments".to_string(),
+            row_key: id.to_string(),
+            row: Some(serde_json::json!({
+                "id": id,
+                "bill_no": id,
+                "ticket_no": id,
+                "vehicle_no": "KA01AB1234",
+                "party_name": "Party",
+                "product_name": "Sand",
+                "gross_weight": 30000.0,
+                "tare_weight": 10000.0,
+                "net_weight": 20000.0,
+                "status": "CLOSED",
+                "created_at": created_at,
+                signatures::SYNC_FIELD: { "signature": "feed", "origin_site": "site-b" },
+            })),
+            changed_at: "2026-02-10T08:00:00.000Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn locked_rows_are_kept_as_conflicts_and_the_cursor_moves_on() {
+        let mut conn = conn();
+        db::set_config(&conn, period_lock::LOCK_CONFIG_KEY, "2026-01-31").unwrap();
+        let settings = SyncSettings {
+            endpoint: "https://hq.example".to_string(),
+            ..Default::default()
+        };
+        let response = PullResponse {
+            cursor: 42,
+            changes: vec![
+                weighment("locked", "2026-01-15 10:00:00"),
+                weighment("open", "2026-02-10 10:00:00"),
+            ],
+            has_more: false,
+        };
+        let mut outcome = PullOutcome::default();
+        apply_batch(&mut conn, &settings, &response, &mut outcome).unwrap();
+
+        assert_eq!(
+            (outcome.applied, outcome.skipped, outcome.conflicts),
+            (1, 1, 1)
+        );
+        assert_eq!(outcome.cursor, 42);
+        assert_eq!(
+            delta_sync::watermark(&conn, &pull_target(&settings)).unwrap(),
+            42
+        );
+        let ids: Vec<String> = conn
+            .prepare("SELECT id FROM weighments")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ids, ["open"]);
+        let (row_key, winner, reason): (String, String, Option<String>) = conn
+            .query_row(
+                "SELECT row_key, winner, reason FROM sync_conflicts",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (row_key.as_str(), winner.as_str(), reason.as_deref()),
+            ("locked", "local", Some("period_locked"))
+        );
+    }
+
+    #[test]
+    fn pulled_weighments_keep_their_origin_signature() {
+        let mut conn = conn();
+        let settings = SyncSettings {
+            endpoint: "https://hq.example".to_string(),
+            ..Default::default()
+        };
+        let response = PullResponse {
+            cursor: 1,
+            changes: vec![weighment("w1", "2026-02-10 10:00:00")],
+            has_more: false,
+        };
+        apply_batch(&mut conn, &settings, &response, &mut PullOutcome::default()).unwrap();
+        let (signature, origin): (Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT signature, origin_site FROM weighment_signatures
+                 WHERE weighment_id = 'w1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(signature.as_deref(), Some("feed"));
+        assert_eq!(origin.as_deref(), Some("site-b"));
+        // Pushed on, it still names the site that signed it
+        let exported = signatures::exported(&conn, "w1", "site-a")
+            .unwrap()
+            .unwrap();
+        assert_eq!(exported["origin_site"], "site-b");
+    }
+
+    #[test]
+    fn unpushed_local_edits_go_to_the_later_write() {
+        let mut conn = conn();
+        let settings = SyncSettings {
+            endpoint: "https://hq.example".to_string(),
+            ..Default::default()
+        };
+        // Both edited here after the last push, one before the server's
+        // write and one after it
+        for (id, changed_at) in [
+            ("later", "2026-02-10T09:00:00.000Z"),
+            ("earlier", "2026-02-10T07:00:00.000Z"),
+        ] {
+            conn.execute(
+                "INSERT INTO weighments (id, bill_no, ticket_no, vehicle_no, party_name,
+                                         product_name, net_weight, status, created_at)
+                 VALUES (?1, ?1, ?1, 'KA01AB1234', 'Party', 'Sand', 1000, 'CLOSED',
+                         '2026-02-10 10:00:00')",
+                [id],
+            )
+            .unwrap();
+            conn.execute(
+                "UPDATE sync_change_log SET changed_at = ?2
+                 WHERE table_name = 'weighments' AND row_key = ?1",
+                [id, changed_at],
+            )
+            .unwrap();
+        }
+        let response = PullResponse {
+            cursor: 7,
+            changes: vec![
+                weighment("later", "2026-02-10 10:00:00"),
+                weighment("earlier", "2026-02-10 10:00:00"),
+            ],
+            has_more: false,
+        };
+        let mut outcome = PullOutcome::default();
+        apply_batch(&mut conn, &settings, &response, &mut outcome).unwrap();
+        assert_eq!(
+            (outcome.applied, outcome.skipped, outcome.conflicts),
+            (1, 1, 2)
+        );
+
+        let net = |id: &str| -> f64 {
+            conn.query_row(
+                "SELECT net_weight FROM weighments WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(net("later"), 1000.0);
+        assert_eq!(net("earlier"), 20000.0);
+        let winners: Vec<(String, String, String)> = conn
+            .prepare("SELECT row_key, winner, local_row FROM sync_conflicts ORDER BY row_key")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(winners[0].0, "earlier");
+        assert_eq!(winners[0].1, "remote");
+        // The overwritten local row is kept for review
+        assert!(winners[0].2.contains("1000"));
+        assert_eq!(
+            (winners[1].0.as_str(), winners[1].1.as_str()),
+            ("later", "local")
+        );
+
+        // Local writes are still waiting, so the push watermark stays put
+        assert_eq!(
+            delta_sync::watermark(&conn, &push_target(&settings)).unwrap(),
+            0
+        );
+        assert_eq!(outcome.cursor, 7);
+    }
+}
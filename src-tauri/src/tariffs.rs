@@ -0,0 +1,296 @@
+// Weighing tariffs for Truckore Pro
+// A tariff is a flat fee per ticket plus a rate per tonne of net weight, in
+// paise, for a party, a material, both, or as the site default. The most
+// specific active tariff prices a ticket when it is completed; tickets with
+// no matching tariff keep the charge entered by the operator.
+
+use crate::command_audit;
+use crate::currency;
+use crate::db::{self, DateRange};
+use crate::idempotency;
+use crate::roles::{self, Role};
+use crate::rounding;
+use crate::snapshots;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tariff {
+    pub id: Option<i64>,
+    pub party_name: Option<String>,
+    pub product_name: Option<String>,
+    pub flat_minor: i64,
+    pub per_tonne_minor: i64,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChargeChange {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub party_name: String,
+    pub product_name: String,
+    pub net_weight: f64,
+    pub before_minor: i64,
+    pub after_minor: i64,
+    pub tariff_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReRateResult {
+    pub applied: bool,
+    pub changes: Vec<ChargeChange>,
+    pub total_before_minor: i64,
+    pub total_after_minor: i64,
+    // Pass back with dry_run = false to apply exactly this preview
+    pub confirmation: String,
+}
+
+const TARIFF_COLUMNS: &str = "id, party_name, product_name, flat_minor, per_tonne_minor, active";
+
+fn row_to_tariff(row: &rusqlite::Row) -> rusqlite::Result<Tariff> {
+    Ok(Tariff {
+        id: row.get(0)?,
+        party_name: row.get(1)?,
+        product_name: row.get(2)?,
+        flat_minor: row.get(3)?,
+        per_tonne_minor: row.get(4)?,
+        active: row.get(5)?,
+    })
+}
+
+pub fn matching_tariff(
+    conn: &Connection,
+    party_name: &str,
+    product_name: &str,
+) -> Result<Option<Tariff>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM tariffs
+             WHERE active = 1
+               AND (party_name IS NULL OR party_name = ?1)
+               AND (product_name IS NULL OR product_name = ?2)
+             ORDER BY party_name IS NOT NULL AND product_name IS NOT NULL DESC,
+                      product_name IS NOT NULL DESC, party_name IS NOT NULL DESC, id DESC
+             LIMIT 1",
+            TARIFF_COLUMNS
+        ),
+        params![party_name, product_name],
+        row_to_tariff,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Charge in paise for a ticket of `net_kg` under `tariff`
+pub fn charge_for(conn: &Connection, tariff: &Tariff, net_kg: f64) -> Result<i64, String> {
+    let rounding = rounding::load_rules(conn)?;
+    Ok(rounding.amount(tariff.flat_minor as f64 + tariff.per_tonne_minor as f64 * net_kg / 1000.0))
+}
+
+// Price a completed ticket from its tariff, if one matches. Returns the charge.
+pub fn apply(conn: &Connection, weighment_id: &str) -> Result<Option<i64>, String> {
+    let (party_name, product_name, net): (String, String, Option<f64>) = conn
+        .query_row(
+            "SELECT party_name, product_name, net_weight FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let (Some(net), Some(tariff)) = (net, matching_tariff(conn, &party_name, &product_name)?)
+    else {
+        return Ok(None);
+    };
+    let charge = charge_for(conn, &tariff, net)?;
+    set_charge(conn, weighment_id, charge)?;
+    Ok(Some(charge))
+}
+
+fn set_charge(conn: &Connection, weighment_id: &str, charge_minor: i64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE weighments SET charges_minor = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+        params![weighment_id, charge_minor],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_tariffs(app: AppHandle) -> Result<Vec<Tariff>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM tariffs ORDER BY party_name, product_name, id",
+            TARIFF_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_tariff)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Create or update a tariff (admin only). Returns its id.
+#[tauri::command]
+pub fn set_tariff(app: AppHandle, tariff: Tariff, user_id: String) -> Result<i64, String> {
+    let args = serde_json::to_value(&tariff).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_tariff", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if tariff.flat_minor < 0 || tariff.per_tonne_minor < 0 {
+            return Err("Tariff amounts cannot be negative".to_string());
+        }
+        match tariff.id {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE tariffs SET party_name = ?2, product_name = ?3, flat_minor = ?4,
+                            per_tonne_minor = ?5, active = ?6, updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ?1",
+                    params![
+                        id,
+                        tariff.party_name,
+                        tariff.product_name,
+                        tariff.flat_minor,
+                        tariff.per_tonne_minor,
+                        tariff.active
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+                Ok(id)
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO tariffs (party_name, product_name, flat_minor, per_tonne_minor, active)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        tariff.party_name,
+                        tariff.product_name,
+                        tariff.flat_minor,
+                        tariff.per_tonne_minor,
+                        tariff.active
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+                Ok(conn.last_insert_rowid())
+            }
+        }
+    })
+}
+
+// Closed, unprinted tickets in the range whose tariff now gives a different charge
+fn pending_changes(conn: &Connection, range: &DateRange) -> Result<Vec<ChargeChange>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT w.id, w.ticket_no, w.party_name, w.product_name, w.net_weight, w.charges_minor
+             FROM weighments w
+             WHERE w.status = 'CLOSED' AND w.net_weight IS NOT NULL
+               AND {} BETWEEN ?1 AND ?2
+               AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}
+             ORDER BY w.created_at",
+            db::local_date("w.created_at"),
+            training::exclude_practice("w.id")
+        ))
+        .map_err(|e| e.to_string())?;
+    let tickets: Vec<(String, String, String, String, f64, i64)> = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut changes = Vec::new();
+    for (weighment_id, ticket_no, party_name, product_name, net_weight, before_minor) in tickets {
+        let Some(tariff) = matching_tariff(conn, &party_name, &product_name)? else {
+            continue;
+        };
+        let after_minor = charge_for(conn, &tariff, net_weight)?;
+        if before_minor != after_minor {
+            changes.push(ChargeChange {
+                weighment_id,
+                ticket_no,
+                party_name,
+                product_name,
+                net_weight,
+                before_minor,
+                after_minor,
+                tariff_id: tariff.id.unwrap_or_default(),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+// Recompute charges of unbilled (closed, not yet printed) tickets from the
+// current tariffs. A dry run returns the before/after diff and a confirmation
+// code; calling again with dry_run = false and that code applies it, and
+// fails if tickets or tariffs changed in between (admin only).
+#[tauri::command]
+pub fn re_rate_tickets(
+    app: AppHandle,
+    range: DateRange,
+    dry_run: bool,
+    confirmation: Option<String>,
+    user_id: String,
+) -> Result<ReRateResult, String> {
+    let args = serde_json::json!({ "range": range, "dry_run": dry_run });
+    command_audit::audited(&app, "re_rate_tickets", &user_id, args, || {
+        let mut conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        if !dry_run {
+            let detail = Some(format!("Tickets {} to {}", range.from, range.to));
+            snapshots::take(&app, &conn, "re_rate", detail, &user_id)?;
+        }
+        let tx = conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(|e| e.to_string())?;
+
+        let changes = pending_changes(&tx, &range)?;
+        let digest =
+            idempotency::digest(&serde_json::to_value(&changes).map_err(|e| e.to_string())?);
+        if !dry_run {
+            if confirmation.as_deref() != Some(digest.as_str()) {
+                return Err(
+                    "Tickets or tariffs changed since the preview; run a dry run again".to_string(),
+                );
+            }
+            for change in &changes {
+                set_charge(&tx, &change.weighment_id, change.after_minor)?;
+                tx.execute(
+                    "INSERT INTO charge_revisions
+                         (weighment_id, before_minor, after_minor, tariff_id, revised_by)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        change.weighment_id,
+                        change.before_minor,
+                        change.after_minor,
+                        change.tariff_id,
+                        user_id
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+                currency::bill_weighment(&tx, &change.weighment_id)?;
+            }
+            tx.commit().map_err(|e| e.to_string())?;
+        }
+
+        Ok(ReRateResult {
+            applied: !dry_run,
+            total_before_minor: changes.iter().map(|c| c.before_minor).sum(),
+            total_after_minor: changes.iter().map(|c| c.after_minor).sum(),
+            changes,
+            confirmation: digest,
+        })
+    })
+}
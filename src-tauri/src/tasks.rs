@@ -0,0 +1,464 @@
+// Background task pool for Truckore Pro
+// Exports, imports, re-rating and backups can be submitted as tasks and run
+// on a small pool of worker threads instead of blocking the command that
+// started them. Each task has an id, a status row in background_tasks and
+// progress pushed to windows as `task_progress` events. Cancelling a queued
+// task drops it; a running task stops at its next checkpoint.
+
+use crate::backup;
+use crate::configuration;
+use crate::csv_import::{self, CsvImportRequest};
+use crate::db::{self, DateRange};
+use crate::export;
+use crate::masking;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use crate::tariffs;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const WORKERS: usize = 2;
+
+// Progress is written and emitted at most this often
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+const CANCELLED: &str = "Cancelled";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TaskRequest {
+    // CSV or XLSX, picked by the extension of `path`
+    Export {
+        query_or_entity: String,
+        path: String,
+        range: Option<DateRange>,
+    },
+    ExportParquet {
+        dir: String,
+        range: Option<DateRange>,
+    },
+    ImportConfiguration {
+        file: String,
+    },
+    ImportCsv {
+        request: CsvImportRequest,
+    },
+    ReRate {
+        range: DateRange,
+        confirmation: String,
+    },
+    Backup {
+        dest_path: String,
+    },
+}
+
+impl TaskRequest {
+    fn kind(&self) -> &'static str {
+        match self {
+            TaskRequest::Export { .. } => "export",
+            TaskRequest::ExportParquet { .. } => "export_parquet",
+            TaskRequest::ImportConfiguration { .. } => "import_configuration",
+            TaskRequest::ImportCsv { .. } => "import_csv",
+            TaskRequest::ReRate { .. } => "re_rate",
+            TaskRequest::Backup { .. } => "backup",
+        }
+    }
+}
+
+// Managed state: the queue and the cancel flags of running tasks
+#[derive(Default)]
+pub struct TaskPool {
+    queue: Mutex<VecDeque<String>>,
+    wake: Condvar,
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl TaskPool {
+    // Ask every running task to stop at its next checkpoint
+    pub fn cancel_all(&self) {
+        if let Ok(running) = self.running.lock() {
+            for cancel in running.values() {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        self.wake.notify_all();
+    }
+
+    // Next queued task id, waiting up to a second for one
+    fn next(&self) -> Option<String> {
+        let queue = self.queue.lock().ok()?;
+        let (mut queue, _) = self
+            .wake
+            .wait_timeout_while(queue, Duration::from_secs(1), |q| {
+                q.is_empty() && !shutdown::requested()
+            })
+            .ok()?;
+        queue.pop_front()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub id: String,
+    pub kind: String,
+    pub request: serde_json::Value,
+    // QUEUED, RUNNING, COMPLETED, FAILED or CANCELLED
+    pub status: String,
+    pub requested_by: String,
+    pub done: i64,
+    pub total: Option<i64>,
+    pub message: Option<String>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub cancel_requested: bool,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+const TASK_COLUMNS: &str = "id, kind, request, status, requested_by, done, total, message,
+     result, error, cancel_requested, created_at, started_at, finished_at";
+
+fn row_to_task(row: &rusqlite::Row) -> rusqlite::Result<TaskStatus> {
+    let json = |text: Option<String>| text.and_then(|t| serde_json::from_str(&t).ok());
+    Ok(TaskStatus {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        request: json(row.get(2)?).unwrap_or(serde_json::Value::Null),
+        status: row.get(3)?,
+        requested_by: row.get(4)?,
+        done: row.get(5)?,
+        total: row.get(6)?,
+        message: row.get(7)?,
+        result: json(row.get(8)?),
+        error: row.get(9)?,
+        cancel_requested: row.get(10)?,
+        created_at: row.get(11)?,
+        started_at: row.get(12)?,
+        finished_at: row.get(13)?,
+    })
+}
+
+fn load_task(conn: &Connection, task_id: &str) -> Result<TaskStatus, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM background_tasks WHERE id = ?1",
+            TASK_COLUMNS
+        ),
+        params![task_id],
+        row_to_task,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Task {} not found", task_id))
+}
+
+fn emit(app: &AppHandle, conn: &Connection, task_id: &str) {
+    if let Ok(task) = load_task(conn, task_id) {
+        let _ = app.emit_all("task_progress", task);
+    }
+}
+
+// Handed to a running task for progress reports and cancellation checks
+struct TaskContext<'a> {
+    app: &'a AppHandle,
+    conn: &'a Connection,
+    id: &'a str,
+    user_id: &'a str,
+    cancel: &'a AtomicBool,
+    last_report: Cell<Instant>,
+}
+
+impl TaskContext<'_> {
+    // Err once cancelled, so a task can stop with `?`
+    fn checkpoint(&self) -> Result<(), String> {
+        if self.cancel.load(Ordering::Relaxed) {
+            return Err(CANCELLED.to_string());
+        }
+        Ok(())
+    }
+
+    fn progress(&self, done: usize, total: Option<usize>, message: &str) -> Result<(), String> {
+        self.checkpoint()?;
+        if self.last_report.get().elapsed() < REPORT_INTERVAL {
+            return Ok(());
+        }
+        self.last_report.set(Instant::now());
+        self.conn
+            .execute(
+                "UPDATE background_tasks SET done = ?2, total = ?3, message = ?4 WHERE id = ?1",
+                params![self.id, done as i64, total.map(|t| t as i64), message],
+            )
+            .map_err(|e| e.to_string())?;
+        emit(self.app, self.conn, self.id);
+        Ok(())
+    }
+}
+
+fn to_json<T: Serialize>(value: T) -> Result<serde_json::Value, String> {
+    serde_json::to_value(value).map_err(|e| e.to_string())
+}
+
+fn execute(ctx: &TaskContext, request: TaskRequest) -> Result<serde_json::Value, String> {
+    let app = ctx.app.clone();
+    let user_id = ctx.user_id.to_string();
+    match request {
+        TaskRequest::Export {
+            query_or_entity,
+            path,
+            range,
+        } => {
+            let role = roles::user_role(ctx.conn, ctx.user_id)?;
+            let masks = masking::for_role(ctx.conn, role.as_str())?;
+            let written = export::write_query(
+                ctx.conn,
+                &query_or_entity,
+                &path,
+                range.as_ref(),
+                &export::export_masks(masks, &query_or_entity),
+                &mut |rows| match rows % 500 {
+                    0 => ctx.progress(rows, None, &format!("{} rows written", rows)),
+                    _ => ctx.checkpoint(),
+                },
+            );
+            if written.is_err() && ctx.cancel.load(Ordering::Relaxed) {
+                let _ = std::fs::remove_file(&path);
+            }
+            to_json(serde_json::json!({ "path": path, "rows": written? }))
+        }
+        // A cancelled import is rolled back as a whole
+        TaskRequest::ImportCsv { request } => to_json(csv_import::run_import(
+            &app,
+            &request,
+            &user_id,
+            &mut |p| {
+                ctx.progress(
+                    p.rows_read,
+                    None,
+                    &format!("{} rows imported, {} failed", p.imported, p.failed),
+                )
+            },
+        )?),
+        // The steps below run to completion once started
+        TaskRequest::ExportParquet { dir, range } => {
+            to_json(export::export_parquet(app, dir, range)?)
+        }
+        TaskRequest::ImportConfiguration { file } => {
+            to_json(configuration::import_configuration(app, file, user_id)?)
+        }
+        TaskRequest::ReRate {
+            range,
+            confirmation,
+        } => to_json(tariffs::re_rate_tickets(
+            app,
+            range,
+            false,
+            Some(confirmation),
+            user_id,
+        )?),
+        TaskRequest::Backup { dest_path } => {
+            to_json(backup::backup_database(app, dest_path, user_id)?)
+        }
+    }
+}
+
+fn run(app: &AppHandle, task_id: &str) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let (request, user_id): (String, String) = conn
+        .query_row(
+            "SELECT request, requested_by FROM background_tasks
+             WHERE id = ?1 AND status = 'QUEUED'",
+            params![task_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        // Cancelled while queued
+        .ok_or_else(|| CANCELLED.to_string())?;
+    let request: TaskRequest = serde_json::from_str(&request).map_err(|e| e.to_string())?;
+
+    let pool = app.state::<TaskPool>();
+    let cancel = Arc::new(AtomicBool::new(false));
+    pool.running
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(task_id.to_string(), cancel.clone());
+    let operation = shutdown::begin(app, "task", Some(task_id));
+    conn.execute(
+        "UPDATE background_tasks SET status = 'RUNNING', started_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![task_id],
+    )
+    .map_err(|e| e.to_string())?;
+    emit(app, &conn, task_id);
+
+    let ctx = TaskContext {
+        app,
+        conn: &conn,
+        id: task_id,
+        user_id: &user_id,
+        cancel: &cancel,
+        last_report: Cell::new(Instant::now()),
+    };
+    let outcome = match &operation {
+        Ok(_) => ctx.checkpoint().and_then(|_| execute(&ctx, request)),
+        Err(e) => Err(e.clone()),
+    };
+    if let Ok(mut running) = pool.running.lock() {
+        running.remove(task_id);
+    }
+
+    // Stopped by the user or by shutdown
+    let cancelled = outcome.is_err() && (cancel.load(Ordering::Relaxed) || shutdown::requested());
+    let (status, result, error) = match outcome {
+        Ok(value) => ("COMPLETED", Some(value.to_string()), None),
+        Err(_) if cancelled => ("CANCELLED", None, None),
+        Err(e) => ("FAILED", None, Some(e)),
+    };
+    conn.execute(
+        "UPDATE background_tasks SET status = ?2, result = ?3, error = ?4,
+                finished_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![task_id, status, result, error],
+    )
+    .map_err(|e| e.to_string())?;
+    emit(app, &conn, task_id);
+    drop(operation);
+    Ok(())
+}
+
+// Start the worker threads; called once from the setup hook
+pub fn start_workers(app: AppHandle) {
+    for _ in 0..WORKERS {
+        let app = app.clone();
+        std::thread::spawn(move || {
+            while !shutdown::requested() {
+                let Some(task_id) = app.state::<TaskPool>().next() else {
+                    continue;
+                };
+                let _ = run(&app, &task_id);
+            }
+        });
+    }
+}
+
+// Queue a long operation. Returns the task id at once; follow progress via
+// `task_progress` events or get_task_status. The operation checks the
+// user's role itself when it runs.
+#[tauri::command]
+pub fn submit_task(
+    app: AppHandle,
+    pool: State<'_, TaskPool>,
+    request: TaskRequest,
+    user_id: String,
+) -> Result<String, String> {
+    if shutdown::requested() {
+        return Err("The application is shutting down".to_string());
+    }
+    let conn = db::open(&app)?;
+    let task_id = uuid::Uuid::new_v4().to_string();
+    let json = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO background_tasks (id, kind, request, requested_by) VALUES (?1, ?2, ?3, ?4)",
+        params![task_id, request.kind(), json, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    pool.queue
+        .lock()
+        .map_err(|e| e.to_string())?
+        .push_back(task_id.clone());
+    pool.wake.notify_one();
+    emit(&app, &conn, &task_id);
+    Ok(task_id)
+}
+
+#[tauri::command]
+pub fn get_task_status(app: AppHandle, task_id: String) -> Result<TaskStatus, String> {
+    let conn = db::open(&app)?;
+    load_task(&conn, &task_id)
+}
+
+// Newest first
+#[tauri::command]
+pub fn list_tasks(
+    app: AppHandle,
+    status: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<TaskStatus>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM background_tasks
+             WHERE ?1 IS NULL OR status = ?1
+             ORDER BY created_at DESC, rowid DESC LIMIT ?2",
+            TASK_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![status, limit.unwrap_or(100)], row_to_task)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// The requester or a supervisor may cancel. A queued task is dropped at once;
+// a running one stops at its next checkpoint.
+#[tauri::command]
+pub fn cancel_task(
+    app: AppHandle,
+    pool: State<'_, TaskPool>,
+    task_id: String,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    let task = load_task(&conn, &task_id)?;
+    if task.requested_by != user_id {
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+    }
+    match task.status.as_str() {
+        "QUEUED" => {
+            pool.queue
+                .lock()
+                .map_err(|e| e.to_string())?
+                .retain(|id| *id != task_id);
+            conn.execute(
+                "UPDATE background_tasks SET status = 'CANCELLED', cancel_requested = 1,
+                        finished_at = CURRENT_TIMESTAMP
+                 WHERE id = ?1 AND status = 'QUEUED'",
+                params![task_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        "RUNNING" => {
+            if let Some(cancel) = pool
+                .running
+                .lock()
+                .map_err(|e| e.to_string())?
+                .get(&task_id)
+            {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            conn.execute(
+                "UPDATE background_tasks SET cancel_requested = 1 WHERE id = ?1",
+                params![task_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        other => {
+            return Err(format!(
+                "Task {} is already {}",
+                task_id,
+                other.to_lowercase()
+            ))
+        }
+    }
+    emit(&app, &conn, &task_id);
+    Ok(())
+}
@@ -0,0 +1,291 @@
+// Usage telemetry for Truckore Pro
+// Off unless the site opts in. Once a day the previous days are summarised
+// into anonymous batches: ticket counts, how often each privileged command
+// ran and which indicator and printer setups are in use. Batches carry a
+// random install id, never the site id, party names or weights, and wait in
+// telemetry_batches for the sync loop (sync_engine.rs) to upload them,
+// without the site's credentials.
+
+use crate::db;
+use crate::shutdown;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const OPT_IN_CONFIG_KEY: &str = "telemetry_opt_in";
+const INSTALL_ID_CONFIG_KEY: &str = "telemetry_install_id";
+const COLLECT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// Days summarised in one pass, so a long-offline site catches up gradually
+const MAX_DAYS_PER_PASS: usize = 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HardwareSummary {
+    pub indicator_protocol: Option<String>,
+    // As last reported by the indicator during diagnostics
+    pub indicator_model: Option<String>,
+    pub printer_paper_sizes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryBatch {
+    pub install_id: String,
+    pub app_version: String,
+    pub day: String,
+    pub tickets: i64,
+    pub voided_tickets: i64,
+    // Privileged command name -> invocations that day
+    pub feature_usage: BTreeMap<String, i64>,
+    pub hardware: HardwareSummary,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TelemetryEntry {
+    pub id: i64,
+    pub batch: TelemetryBatch,
+    pub status: String,
+    pub created_at: String,
+}
+
+fn opted_in(conn: &Connection) -> Result<bool, String> {
+    Ok(db::get_config(conn, OPT_IN_CONFIG_KEY)?.as_deref() == Some("true"))
+}
+
+fn hardware(conn: &Connection) -> Result<HardwareSummary, String> {
+    let indicator_protocol = db::get_config(conn, "scale_config")?
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .and_then(|config| config["protocol"].as_str().map(|p| p.to_string()));
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT paper_size FROM printer_profiles ORDER BY paper_size")
+        .map_err(|e| e.to_string())?;
+    let printer_paper_sizes = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(HardwareSummary {
+        indicator_protocol,
+        indicator_model: db::get_config(conn, "indicator_model")?,
+        printer_paper_sizes,
+    })
+}
+
+fn build_batch(
+    conn: &Connection,
+    install_id: &str,
+    app_version: &str,
+    day: &str,
+) -> Result<TelemetryBatch, String> {
+    let (tickets, voided_tickets): (i64, i64) = conn
+        .query_row(
+            &format!(
+                "SELECT COUNT(*),
+                        COALESCE(SUM(w.id IN (SELECT weighment_id FROM ticket_voids)), 0)
+                 FROM weighments w WHERE {} = ?1 AND {}",
+                db::local_date("w.created_at"),
+                training::exclude_practice("w.id")
+            ),
+            [day],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT command, COUNT(*) FROM command_audit_log
+             WHERE {} = ?1 AND decision = 'ALLOW' GROUP BY command",
+            db::local_date("invoked_at")
+        ))
+        .map_err(|e| e.to_string())?;
+    let feature_usage = stmt
+        .query_map([day], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<BTreeMap<String, i64>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(TelemetryBatch {
+        install_id: install_id.to_string(),
+        app_version: app_version.to_string(),
+        day: day.to_string(),
+        tickets,
+        voided_tickets,
+        feature_usage,
+        hardware: hardware(conn)?,
+    })
+}
+
+// Summarise each finished day since the last batch. Does nothing unless opted in.
+pub fn collect(app: &AppHandle, conn: &Connection) -> Result<usize, String> {
+    if !opted_in(conn)? {
+        return Ok(0);
+    }
+    let Some(install_id) = db::get_config(conn, INSTALL_ID_CONFIG_KEY)? else {
+        return Ok(0);
+    };
+    let app_version = app.package_info().version.to_string();
+
+    // The first batch covers yesterday; later ones resume after the last day sent
+    let last_day: Option<String> = conn
+        .query_row("SELECT MAX(day) FROM telemetry_batches", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    let mut day: String = conn
+        .query_row(
+            "SELECT COALESCE(date(?1, '+1 day'), date('now', 'localtime', '-1 day'))",
+            [&last_day],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut created = 0;
+    for _ in 0..MAX_DAYS_PER_PASS {
+        let finished: bool = conn
+            .query_row("SELECT ?1 < date('now', 'localtime')", [&day], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        if !finished {
+            break;
+        }
+        let batch = build_batch(conn, &install_id, &app_version, &day)?;
+        let payload = serde_json::to_string(&batch).map_err(|e| e.to_string())?;
+        created += conn
+            .execute(
+                "INSERT OR IGNORE INTO telemetry_batches (day, payload) VALUES (?1, ?2)",
+                params![day, payload],
+            )
+            .map_err(|e| e.to_string())?;
+        day = conn
+            .query_row("SELECT date(?1, '+1 day')", [&day], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(created)
+}
+
+// Background collector; started from the app setup hook
+pub fn start_collector(app: AppHandle) {
+    std::thread::spawn(move || {
+        while !shutdown::requested() {
+            // Best effort: the database may not be initialised yet
+            if let Ok(conn) = db::open(&app) {
+                let _ = collect(&app, &conn);
+            }
+            std::thread::sleep(COLLECT_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_telemetry_opt_in(app: AppHandle) -> Result<bool, String> {
+    let conn = db::open(&app)?;
+    opted_in(&conn)
+}
+
+// Opting out discards batches that have not been uploaded yet
+#[tauri::command]
+pub fn set_telemetry_opt_in(app: AppHandle, enabled: bool) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    if enabled {
+        let existing = db::get_config(&conn, INSTALL_ID_CONFIG_KEY)?;
+        if existing.is_none() {
+            let id = uuid::Uuid::new_v4().to_string();
+            db::set_config(&conn, INSTALL_ID_CONFIG_KEY, &id)?;
+        }
+    } else {
+        conn.execute(
+            "DELETE FROM telemetry_batches WHERE status = 'PENDING_UPLOAD'",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    db::set_config(
+        &conn,
+        OPT_IN_CONFIG_KEY,
+        if enabled { "true" } else { "false" },
+    )
+}
+
+// What a batch for `day` would contain, so the site can see exactly what is shared
+#[tauri::command]
+pub fn preview_telemetry(app: AppHandle, day: String) -> Result<TelemetryBatch, String> {
+    let conn = db::open(&app)?;
+    let install_id = db::get_config(&conn, INSTALL_ID_CONFIG_KEY)?.unwrap_or_default();
+    build_batch(
+        &conn,
+        &install_id,
+        &app.package_info().version.to_string(),
+        &day,
+    )
+}
+
+// Batches waiting for upload, oldest day first
+pub fn pending(conn: &Connection) -> Result<Vec<TelemetryEntry>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, payload, status, created_at FROM telemetry_batches
+             WHERE status = 'PENDING_UPLOAD' ORDER BY day",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            let payload: String = row.get(1)?;
+            Ok(TelemetryEntry {
+                id: row.get(0)?,
+                batch: serde_json::from_str(&payload).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        1,
+                        rusqlite::types::Type::Text,
+                        Box::new(e),
+                    )
+                })?,
+                status: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+pub fn mark_uploaded(conn: &Connection, ids: &[i64]) -> Result<(), String> {
+    for id in ids {
+        conn.execute(
+            "UPDATE telemetry_batches SET status = 'UPLOADED', uploaded_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND status = 'PENDING_UPLOAD'",
+            [id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Batches waiting for the sync layer
+#[tauri::command]
+pub fn pending_telemetry_batches(app: AppHandle) -> Result<Vec<TelemetryEntry>, String> {
+    let conn = db::open(&app)?;
+    pending(&conn)
+}
+
+// Called by the sync layer once batches have been delivered
+#[tauri::command]
+pub fn mark_telemetry_uploaded(app: AppHandle, ids: Vec<i64>) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    mark_uploaded(&conn, &ids)
+}
+
+// Day of the most recent batch, for the settings screen
+#[tauri::command]
+pub fn last_telemetry_day(app: AppHandle) -> Result<Option<String>, String> {
+    let conn = db::open(&app)?;
+    conn.query_row(
+        "SELECT day FROM telemetry_batches ORDER BY day DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
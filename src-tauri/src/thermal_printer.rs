@@ -0,0 +1,608 @@
+// Thermal ticket printing for Truckore Pro
+// Renders a ticket with the configured printer driver (ESC/POS for 58/80 mm
+// receipt printers) and sends it over a serial port, a raw network socket
+// (port 9100) or a USB printer device file. Text is plain ASCII so it prints
+// on any code page. Tickets on record get their gate QR code (ticket_qr.rs)
+// and the driver's signature (driver_signatures.rs) printed above the footer.
+// Weights are grouped and the charge spelled out as the site's number format
+// says (formatting.rs). Each print is a job (print_jobs.rs); the printer's
+// real-time status confirms it where the transport can read it back.
+// Labels print in the language chosen on this PC (messages.rs) when the
+// pack spells them in plain ASCII.
+
+use crate::command_audit;
+use crate::db;
+use crate::driver_signatures;
+use crate::drivers::{self, PrinterDriver};
+use crate::formatting::{self, NumberFormat};
+use crate::messages;
+use crate::print_jobs::{self, PrintJob};
+use crate::roles::{self, Role};
+use crate::scripting;
+use crate::shutdown;
+use crate::ticket_qr;
+use crate::workflow_journal;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const CONFIG_KEY: &str = "thermal_printer";
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+const DLE: u8 = 0x10;
+const EOT: u8 = 0x04;
+// Time the printer is given to print and cut before it is asked how it went
+const PRINT_SETTLE: Duration = Duration::from_millis(1500);
+// Printer dots per QR module: about 25 mm wide at 203 dpi
+const QR_DOTS: u32 = 4;
+// Ticket labels a language pack may translate
+const LABELS: [&str; 11] = [
+    "WEIGHMENT TICKET",
+    "Ticket No",
+    "Date",
+    "Vehicle",
+    "Party",
+    "Material",
+    "Gross",
+    "Tare",
+    "Net",
+    "Amount",
+    "Driver signature",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrinterTransport {
+    Serial,
+    Tcp,
+    Usb,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalPrinter {
+    // Printer driver id, see drivers.rs
+    pub driver: String,
+    pub transport: PrinterTransport,
+    // Serial port name, e.g. "COM3" or "/dev/ttyUSB0"
+    pub port: String,
+    pub baud_rate: u32,
+    pub host: String,
+    pub tcp_port: u16,
+    // USB printer device, e.g. "/dev/usb/lp0"
+    pub device: String,
+    // 48 for 80 mm paper, 32 for 58 mm
+    pub chars_per_line: usize,
+    pub header_lines: Vec<String>,
+    pub footer_lines: Vec<String>,
+    pub cut: bool,
+    pub qr_code: bool,
+}
+
+impl Default for ThermalPrinter {
+    fn default() -> Self {
+        ThermalPrinter {
+            driver: "escpos".to_string(),
+            transport: PrinterTransport::Serial,
+            port: String::new(),
+            baud_rate: 9600,
+            host: String::new(),
+            tcp_port: 9100,
+            device: String::new(),
+            chars_per_line: 48,
+            header_lines: Vec::new(),
+            footer_lines: vec!["Thank you".to_string()],
+            cut: true,
+            qr_code: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TicketPayload {
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    pub party_name: Option<String>,
+    pub product_name: Option<String>,
+    pub date_time: String,
+    pub gross_weight: Option<f64>,
+    pub tare_weight: Option<f64>,
+    pub net_weight: Option<f64>,
+    // Amount as shown to the customer, already formatted
+    pub amount: Option<String>,
+    pub remarks: Option<String>,
+    // Gate QR payload, looked up by print_ticket
+    #[serde(default, skip_deserializing)]
+    pub qr: Option<String>,
+    // Driver's signature dots, looked up by print_ticket
+    #[serde(skip)]
+    pub signature: Option<Vec<Vec<bool>>>,
+    // How weights and the date print, from the site's number format
+    #[serde(skip)]
+    pub format: NumberFormat,
+    // The charge in words, looked up by print_ticket
+    #[serde(default, skip_deserializing)]
+    pub amount_in_words: Option<String>,
+    // Translated labels by their English, looked up by print_ticket
+    #[serde(skip)]
+    pub labels: HashMap<String, String>,
+}
+
+impl TicketPayload {
+    fn label<'a>(&'a self, english: &'a str) -> &'a str {
+        self.labels.get(english).map_or(english, String::as_str)
+    }
+}
+
+pub fn load_settings(conn: &Connection) -> Result<ThermalPrinter, String> {
+    match db::get_config(conn, CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(ThermalPrinter::default()),
+    }
+}
+
+fn validate(settings: &ThermalPrinter) -> Result<(), String> {
+    drivers::printer(&settings.driver)?;
+    if !(24..=64).contains(&settings.chars_per_line) {
+        return Err("Characters per line must be between 24 and 64".to_string());
+    }
+    let missing = match settings.transport {
+        PrinterTransport::Serial => settings.port.trim().is_empty(),
+        PrinterTransport::Tcp => settings.host.trim().is_empty(),
+        PrinterTransport::Usb => settings.device.trim().is_empty(),
+    };
+    if missing {
+        return Err("Printer connection is not configured".to_string());
+    }
+    Ok(())
+}
+
+pub fn is_configured(settings: &ThermalPrinter) -> bool {
+    validate(settings).is_ok()
+}
+
+// Printable ASCII only; anything else becomes '?'
+fn ascii(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c == ' ' || c.is_ascii_graphic() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect()
+}
+
+fn ascii_only(text: &str) -> bool {
+    text.chars().all(|c| c == ' ' || c.is_ascii_graphic())
+}
+
+fn text_line(out: &mut Vec<u8>, text: &str) {
+    out.extend(ascii(text).bytes());
+    out.push(b'\n');
+}
+
+// Label on the left, value on the right, truncated to the line width
+fn row(out: &mut Vec<u8>, width: usize, label: &str, value: &str) {
+    let label: String = label.chars().take(width).collect();
+    let room = width.saturating_sub(label.len() + 1);
+    let value: String = value.chars().take(room).collect();
+    let line = format!("{}{:>pad$}", label, value, pad = width - label.len());
+    text_line(out, &line);
+}
+
+// Words across as many lines as they need
+fn wrapped(out: &mut Vec<u8>, width: usize, text: &str) {
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            text_line(out, &line);
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        text_line(out, &line);
+    }
+}
+
+fn weight(value: Option<f64>, format: &NumberFormat) -> String {
+    value
+        .map(|kg| formatting::weight(kg, format))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+// Epson ESC/POS and the many receipt printers that copy it
+pub struct EscPos;
+
+impl PrinterDriver for EscPos {
+    fn id(&self) -> &'static str {
+        "escpos"
+    }
+
+    fn name(&self) -> &'static str {
+        "ESC/POS receipt printer"
+    }
+
+    fn render(&self, ticket: &TicketPayload, settings: &ThermalPrinter) -> Vec<u8> {
+        render(ticket, settings)
+    }
+
+    // DLE EOT 2: real-time offline cause
+    fn status_command(&self) -> Option<&'static [u8]> {
+        Some(&[DLE, EOT, 2])
+    }
+
+    fn status_fault(&self, reply: u8) -> Option<String> {
+        // Bits 1 and 4 are always set and bits 0 and 7 clear
+        if reply & 0x93 != 0x12 {
+            return Some(format!("Unexpected printer status {:#04x}", reply));
+        }
+        [
+            (0x04, "Printer cover is open"),
+            (0x08, "Paper is being fed by the button"),
+            (0x20, "Printing stopped: out of paper"),
+            (0x40, "Printer error (jam or cutter)"),
+        ]
+        .iter()
+        .find(|(bit, _)| reply & bit != 0)
+        .map(|(_, fault)| fault.to_string())
+    }
+}
+
+// ESC/POS byte stream for one ticket
+fn render(ticket: &TicketPayload, settings: &ThermalPrinter) -> Vec<u8> {
+    let width = settings.chars_per_line;
+    let separator = "-".repeat(width);
+    let mut out = Vec::new();
+
+    // Initialise, then centred header in double size
+    out.extend([ESC, b'@', ESC, b'a', 1]);
+    for (i, line) in settings.header_lines.iter().enumerate() {
+        if i == 0 {
+            out.extend([GS, b'!', 0x11]);
+            text_line(&mut out, line);
+            out.extend([GS, b'!', 0x00]);
+        } else {
+            text_line(&mut out, line);
+        }
+    }
+    out.extend([ESC, b'E', 1]);
+    text_line(&mut out, ticket.label("WEIGHMENT TICKET"));
+    out.extend([ESC, b'E', 0, ESC, b'a', 0]);
+    text_line(&mut out, &separator);
+
+    row(
+        &mut out,
+        width,
+        ticket.label("Ticket No"),
+        &ticket.ticket_no,
+    );
+    row(
+        &mut out,
+        width,
+        ticket.label("Date"),
+        &formatting::date(&ticket.date_time, &ticket.format),
+    );
+    row(&mut out, width, ticket.label("Vehicle"), &ticket.vehicle_no);
+    if let Some(party) = &ticket.party_name {
+        row(&mut out, width, ticket.label("Party"), party);
+    }
+    if let Some(product) = &ticket.product_name {
+        row(&mut out, width, ticket.label("Material"), product);
+    }
+    text_line(&mut out, &separator);
+
+    row(
+        &mut out,
+        width,
+        ticket.label("Gross"),
+        &weight(ticket.gross_weight, &ticket.format),
+    );
+    row(
+        &mut out,
+        width,
+        ticket.label("Tare"),
+        &weight(ticket.tare_weight, &ticket.format),
+    );
+    out.extend([ESC, b'E', 1]);
+    row(
+        &mut out,
+        width,
+        ticket.label("Net"),
+        &weight(ticket.net_weight, &ticket.format),
+    );
+    if let Some(amount) = &ticket.amount {
+        row(&mut out, width, ticket.label("Amount"), amount);
+    }
+    out.extend([ESC, b'E', 0]);
+    if let (Some(_), Some(words)) = (&ticket.amount, &ticket.amount_in_words) {
+        wrapped(&mut out, width, words);
+    }
+    text_line(&mut out, &separator);
+
+    if let Some(remarks) = ticket.remarks.as_deref().filter(|r| !r.trim().is_empty()) {
+        text_line(&mut out, remarks);
+    }
+    out.extend([ESC, b'a', 1]);
+    // A payload too long to encode prints without its code
+    if let Some(matrix) = ticket.qr.as_deref().and_then(|p| ticket_qr::encode(p).ok()) {
+        out.extend(ticket_qr::escpos_raster(
+            &ticket_qr::modules(&matrix),
+            QR_DOTS,
+        ));
+        out.push(b'\n');
+    }
+    if let Some(dots) = &ticket.signature {
+        text_line(&mut out, ticket.label("Driver signature"));
+        out.extend(ticket_qr::escpos_raster(dots, 1));
+        out.push(b'\n');
+    }
+    for line in &settings.footer_lines {
+        text_line(&mut out, line);
+    }
+    out.extend([ESC, b'a', 0]);
+
+    // Feed past the tear bar, then partial cut
+    out.extend([ESC, b'd', 4]);
+    if settings.cut {
+        out.extend([GS, b'V', 66, 0]);
+    }
+    out
+}
+
+// ESC/POS report page: the header, a bold title and label/value rows
+fn render_summary(title: &str, rows: &[(String, String)], settings: &ThermalPrinter) -> Vec<u8> {
+    let width = settings.chars_per_line;
+    let mut out = Vec::new();
+    out.extend([ESC, b'@', ESC, b'a', 1]);
+    for line in &settings.header_lines {
+        text_line(&mut out, line);
+    }
+    out.extend([ESC, b'E', 1]);
+    text_line(&mut out, title);
+    out.extend([ESC, b'E', 0, ESC, b'a', 0]);
+    text_line(&mut out, &"-".repeat(width));
+    for (label, value) in rows {
+        row(&mut out, width, label, value);
+    }
+    out.extend([ESC, b'd', 4]);
+    if settings.cut {
+        out.extend([GS, b'V', 66, 0]);
+    }
+    out
+}
+
+// Print a short report, such as the day's register totals, on the receipt printer
+pub fn print_summary(
+    settings: &ThermalPrinter,
+    title: &str,
+    rows: &[(String, String)],
+) -> Result<(), String> {
+    validate(settings)?;
+    send(settings, &render_summary(title, rows, settings))
+}
+
+fn send(settings: &ThermalPrinter, bytes: &[u8]) -> Result<(), String> {
+    match settings.transport {
+        PrinterTransport::Serial => {
+            let mut port = serialport::new(&settings.port, settings.baud_rate)
+                .timeout(Duration::from_secs(5))
+                .open()
+                .map_err(|e| format!("Failed to open {}: {}", settings.port, e))?;
+            port.write_all(bytes).map_err(|e| e.to_string())?;
+            port.flush().map_err(|e| e.to_string())
+        }
+        PrinterTransport::Tcp => {
+            let endpoint = format!("{}:{}", settings.host, settings.tcp_port);
+            let address = endpoint
+                .to_socket_addrs()
+                .map_err(|e| format!("Failed to resolve {}: {}", endpoint, e))?
+                .next()
+                .ok_or_else(|| format!("Failed to resolve {}", endpoint))?;
+            let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(3))
+                .map_err(|e| format!("Failed to connect to {}: {}", endpoint, e))?;
+            stream
+                .set_write_timeout(Some(Duration::from_secs(5)))
+                .map_err(|e| e.to_string())?;
+            stream.write_all(bytes).map_err(|e| e.to_string())?;
+            stream.flush().map_err(|e| e.to_string())
+        }
+        PrinterTransport::Usb => {
+            let mut device = OpenOptions::new()
+                .write(true)
+                .open(&settings.device)
+                .map_err(|e| format!("Failed to open {}: {}", settings.device, e))?;
+            device.write_all(bytes).map_err(|e| e.to_string())?;
+            device.flush().map_err(|e| e.to_string())
+        }
+    }
+}
+
+// Ask the printer how the last ticket went, over a fresh connection. None
+// when the transport or driver cannot tell (USB device files are write-only)
+// or the printer did not answer; the operator confirms those prints.
+fn printer_status(
+    settings: &ThermalPrinter,
+    driver: &dyn PrinterDriver,
+) -> Option<Result<(), String>> {
+    let command = driver.status_command()?;
+    std::thread::sleep(PRINT_SETTLE);
+    let mut reply = [0u8; 1];
+    let answered = match settings.transport {
+        PrinterTransport::Serial => {
+            let mut port = serialport::new(&settings.port, settings.baud_rate)
+                .timeout(Duration::from_secs(2))
+                .open()
+                .ok()?;
+            port.write_all(command).ok()?;
+            port.read_exact(&mut reply).is_ok()
+        }
+        PrinterTransport::Tcp => {
+            let endpoint = format!("{}:{}", settings.host, settings.tcp_port);
+            let address = endpoint.to_socket_addrs().ok()?.next()?;
+            let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(3)).ok()?;
+            stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+            stream.write_all(command).ok()?;
+            stream.read_exact(&mut reply).is_ok()
+        }
+        PrinterTransport::Usb => false,
+    };
+    answered.then(|| match driver.status_fault(reply[0]) {
+        Some(fault) => Err(fault),
+        None => Ok(()),
+    })
+}
+
+// Site rules may refuse the print or replace payload values
+fn apply_print_script(conn: &Connection, ticket: TicketPayload) -> Result<TicketPayload, String> {
+    let input = serde_json::to_value(&ticket).map_err(|e| e.to_string())?;
+    let result = scripting::call(
+        conn,
+        scripting::BEFORE_PRINT,
+        Some(&ticket.ticket_no),
+        &input,
+    )?;
+    let serde_json::Value::Object(mut fields) = input else {
+        return Ok(ticket);
+    };
+    scripting::merge(&mut fields, result);
+    // Values of the wrong type leave the ticket as it was
+    Ok(serde_json::from_value(serde_json::Value::Object(fields)).unwrap_or(ticket))
+}
+
+// Sends the ticket and records the print job. It counts as printed once the
+// printer reports it came out, or the operator confirms it (confirm_print);
+// the job says which is still awaited.
+#[tauri::command]
+pub fn print_ticket(
+    app: AppHandle,
+    ticket: TicketPayload,
+    user_id: Option<String>,
+) -> Result<PrintJob, String> {
+    let (settings, ticket) = {
+        let conn = db::open(&app)?;
+        let settings = load_settings(&conn)?;
+        let mut ticket = apply_print_script(&conn, ticket)?;
+        let format = formatting::load(&conn)?;
+        let weighment_id: Option<String> = conn
+            .query_row(
+                "SELECT id FROM weighments WHERE ticket_no = ?1",
+                [&ticket.ticket_no],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some(id) = weighment_id {
+            ticket.amount_in_words = formatting::ticket_amount_in_words(&conn, &id, &format)?;
+        }
+        ticket.format = format;
+        // The printer has ASCII only; other scripts keep the English label
+        ticket.labels = LABELS
+            .iter()
+            .filter_map(|english| {
+                let translated = messages::translated(&app, english)?;
+                ascii_only(&translated).then(|| (english.to_string(), translated))
+            })
+            .collect();
+        if settings.qr_code {
+            ticket.qr = ticket_qr::payload(&conn, "ticket_no", &ticket.ticket_no)?.map(|(_, p)| p);
+        }
+        // An unreadable signature file does not stop the ticket printing
+        ticket.signature = driver_signatures::for_print(
+            &app,
+            &conn,
+            "ticket_no",
+            &ticket.ticket_no,
+            driver_signatures::PRINT_MAX,
+        )
+        .ok()
+        .flatten();
+        (settings, ticket)
+    };
+    validate(&settings)?;
+    let _operation = shutdown::begin(&app, "print", Some(&ticket.ticket_no))?;
+    let driver = drivers::printer(&settings.driver)?;
+    let detail = serde_json::json!({ "printer": settings.driver });
+    let ticket_no = Some(ticket.ticket_no.as_str());
+    let job_id = {
+        let conn = db::open(&app)?;
+        print_jobs::sent(
+            &conn,
+            &ticket.ticket_no,
+            "THERMAL",
+            Some(&settings.driver),
+            user_id.as_deref(),
+        )?
+    };
+    let sent = workflow_journal::journalled(&app, "print", ticket_no, None, detail, || {
+        send(&settings, &driver.render(&ticket, &settings))
+    });
+    let conn = db::open(&app)?;
+    if let Err(e) = sent {
+        print_jobs::fail(&conn, &job_id, &e, None)?;
+        return Err(e);
+    }
+    match printer_status(&settings, driver) {
+        Some(Ok(())) => print_jobs::confirm(&app, &conn, &job_id, "PRINTER", None),
+        Some(Err(fault)) => print_jobs::fail(&conn, &job_id, &fault, None),
+        None => print_jobs::get(&conn, &job_id),
+    }
+}
+
+// Print a sample ticket with settings that need not be saved yet
+pub fn print_test_page(settings: &ThermalPrinter) -> Result<(), String> {
+    validate(settings)?;
+    let driver = drivers::printer(&settings.driver)?;
+    let sample = TicketPayload {
+        ticket_no: "TEST".to_string(),
+        vehicle_no: "TEST PRINT".to_string(),
+        party_name: None,
+        product_name: None,
+        date_time: "-".to_string(),
+        gross_weight: Some(0.0),
+        tare_weight: Some(0.0),
+        net_weight: Some(0.0),
+        amount: None,
+        remarks: Some("Printer test page".to_string()),
+        qr: None,
+        signature: None,
+        format: NumberFormat::default(),
+        amount_in_words: None,
+        labels: HashMap::new(),
+    };
+    send(settings, &driver.render(&sample, settings))
+}
+
+#[tauri::command]
+pub fn get_thermal_printer(app: AppHandle) -> Result<ThermalPrinter, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+// Supervisors only
+#[tauri::command]
+pub fn set_thermal_printer(
+    app: AppHandle,
+    settings: ThermalPrinter,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    command_audit::audited(&app, "set_thermal_printer", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        validate(&settings)?;
+        let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        db::set_config(&conn, CONFIG_KEY, &json)
+    })
+}
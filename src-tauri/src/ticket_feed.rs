@@ -0,0 +1,195 @@
+// Ticket feed for Truckore Pro
+// ERP systems pull weighments to post them, and offset paging (LIMIT and
+// OFFSET over created_at) skipped or repeated tickets whenever one was
+// written between two pages. The feed pages on row_version instead: every
+// write gives the row the next value of one local clock (delta_sync.rs), so
+// ordering by it is total and stable, a page ends at a version no later
+// write can fall behind, and a ticket edited after it was pulled comes
+// round again with its new version. Deleted tickets come in the same order
+// from their tombstones. The cursor is opaque to the consumer; `since`
+// narrows a first pull to tickets written from that time, and polling with
+// the last next_cursor returns only what changed since. Tickets are masked
+// by the reader's role (masking.rs), and practice tickets (training.rs) are
+// left out.
+
+use crate::db;
+use crate::masking::{self, Masks};
+use crate::training;
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::AppHandle;
+
+const CURSOR_PREFIX: &str = "weighments:";
+const DEFAULT_LIMIT: usize = 500;
+const MAX_LIMIT: usize = 5000;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeedRequest {
+    // next_cursor of the previous page; omitted for the first
+    pub cursor: Option<String>,
+    // ISO 8601 date or time (UTC): only tickets written from then on
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletedTicket {
+    pub id: String,
+    pub row_version: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketPage {
+    // Every weighments column, by row_version
+    pub tickets: Vec<Value>,
+    pub deleted: Vec<DeletedTicket>,
+    // Pass back for the next page, or later to poll for changes
+    pub next_cursor: String,
+    // More changes are waiting now
+    pub has_more: bool,
+}
+
+fn encode_cursor(version: i64) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(format!("{}{}", CURSOR_PREFIX, version))
+}
+
+fn decode_cursor(cursor: &str) -> Result<i64, String> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor.trim())
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|text| text.strip_prefix(CURSOR_PREFIX)?.parse().ok())
+        .filter(|version: &i64| *version >= 0)
+        .ok_or_else(|| "Invalid cursor".to_string())
+}
+
+// `since` as SQLite writes CURRENT_TIMESTAMP
+fn normalize_since(conn: &Connection, since: &str) -> Result<String, String> {
+    let normalized: Option<String> = conn
+        .query_row("SELECT datetime(?1)", [since.trim()], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    normalized.ok_or_else(|| format!("Invalid since (expected ISO 8601): {}", since))
+}
+
+// One page of the feed after `request.cursor`
+pub fn page(conn: &Connection, request: &FeedRequest) -> Result<TicketPage, String> {
+    let after = match request.cursor.as_deref() {
+        Some(cursor) => decode_cursor(cursor)?,
+        None => 0,
+    };
+    let limit = request.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let since = request
+        .since
+        .as_deref()
+        .map(|since| normalize_since(conn, since))
+        .transpose()?;
+
+    // Versions and ids of the next changes, one more than the page holds
+    let mut values: Vec<rusqlite::types::Value> = vec![after.into()];
+    let mut ticket_filter = String::new();
+    let mut tombstone_filter = String::new();
+    if let Some(since) = since {
+        values.push(since.into());
+        ticket_filter = "AND COALESCE(updated_at, created_at) >= ?2".to_string();
+        tombstone_filter = "AND deleted_at >= ?2".to_string();
+    }
+    values.push((limit as i64 + 1).into());
+    let sql = format!(
+        "SELECT row_version, CAST(id AS TEXT), 0 FROM weighments
+         WHERE row_version > ?1 AND {} {}
+         UNION ALL
+         SELECT row_version, row_key, 1 FROM sync_tombstones
+         WHERE table_name = 'weighments' AND row_version > ?1 {}
+         ORDER BY 1 LIMIT ?{}",
+        training::exclude_practice("id"),
+        ticket_filter,
+        tombstone_filter,
+        values.len()
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut changes = stmt
+        .query_map(params_from_iter(values), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, bool>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let has_more = changes.len() > limit;
+    changes.truncate(limit);
+
+    let next = changes.last().map_or(after, |(version, _, _)| *version);
+    let ids: Vec<&str> = changes
+        .iter()
+        .filter(|(_, _, deleted)| !deleted)
+        .map(|(_, id, _)| id.as_str())
+        .collect();
+    let deleted = changes
+        .iter()
+        .filter(|(_, _, deleted)| *deleted)
+        .map(|(version, id, _)| DeletedTicket {
+            id: id.clone(),
+            row_version: *version,
+        })
+        .collect();
+    Ok(TicketPage {
+        tickets: tickets(conn, &ids)?,
+        deleted,
+        next_cursor: encode_cursor(next),
+        has_more,
+    })
+}
+
+// Full rows of `ids`, by row_version
+fn tickets(conn: &Connection, ids: &[&str]) -> Result<Vec<Value>, String> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT * FROM weighments
+             WHERE CAST(id AS TEXT) IN (SELECT value FROM json_each(?1))
+             ORDER BY row_version",
+        )
+        .map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let ids = serde_json::to_string(ids).map_err(|e| e.to_string())?;
+    let mut rows = stmt.query([ids]).map_err(|e| e.to_string())?;
+    let mut tickets = Vec::new();
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let mut ticket = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            let value = row.get_ref(i).map_err(|e| e.to_string())?;
+            ticket.insert(column.clone(), crate::sql_to_json_value(value));
+        }
+        tickets.push(Value::Object(ticket));
+    }
+    Ok(tickets)
+}
+
+// The feed for ERP connectors running on this PC; LAN consumers use
+// GET /lan/v1/tickets with the same parameters
+#[tauri::command]
+pub fn weighment_feed(
+    app: AppHandle,
+    request: Option<FeedRequest>,
+    session_token: Option<String>,
+) -> Result<TicketPage, String> {
+    let masks = masking::for_session(&app, session_token.as_deref())?;
+    let conn = db::open(&app)?;
+    Ok(masked(page(&conn, &request.unwrap_or_default())?, &masks))
+}
+
+// `page` with its tickets masked
+pub fn masked(mut page: TicketPage, masks: &Masks) -> TicketPage {
+    for ticket in page.tickets.iter_mut() {
+        masks.apply(ticket);
+    }
+    page
+}
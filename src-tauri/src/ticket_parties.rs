@@ -0,0 +1,181 @@
+// Consignor, consignee and transporter per ticket
+// The ticket's party_name stays the billed party; the goods can come from
+// and go to other parties, and the haulier is booked in weighment_transport.
+
+use crate::db::{self, DateRange};
+use crate::period_lock;
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartyRole {
+    Billed,
+    Consignor,
+    Consignee,
+    Transporter,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketParties {
+    pub weighment_id: String,
+    pub billed_party: String,
+    pub consignor: Option<String>,
+    pub consignee: Option<String>,
+    pub transporter: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartyRoleTotal {
+    pub party_name: String,
+    pub tickets: i64,
+    pub net_weight_kg: f64,
+}
+
+fn set_role(
+    conn: &Connection,
+    weighment_id: &str,
+    role: &str,
+    party_name: Option<&str>,
+) -> Result<(), String> {
+    match party_name.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(name) => conn.execute(
+            "INSERT INTO weighment_parties (weighment_id, role, party_name) VALUES (?1, ?2, ?3)
+             ON CONFLICT(weighment_id, role) DO UPDATE
+             SET party_name = excluded.party_name, assigned_at = CURRENT_TIMESTAMP",
+            params![weighment_id, role, name],
+        ),
+        None => conn.execute(
+            "DELETE FROM weighment_parties WHERE weighment_id = ?1 AND role = ?2",
+            params![weighment_id, role],
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Record consignor and consignee; a missing or blank name clears the role
+pub fn link(
+    conn: &Connection,
+    weighment_id: &str,
+    consignor: Option<&str>,
+    consignee: Option<&str>,
+) -> Result<(), String> {
+    set_role(conn, weighment_id, "consignor", consignor)?;
+    set_role(conn, weighment_id, "consignee", consignee)
+}
+
+#[tauri::command]
+pub fn get_ticket_parties(app: AppHandle, weighment_id: String) -> Result<TicketParties, String> {
+    let conn = db::open(&app)?;
+    conn.query_row(
+        "SELECT w.party_name,
+                (SELECT party_name FROM weighment_parties
+                 WHERE weighment_id = w.id AND role = 'consignor'),
+                (SELECT party_name FROM weighment_parties
+                 WHERE weighment_id = w.id AND role = 'consignee'),
+                t.name
+         FROM weighments w
+         LEFT JOIN weighment_transport wt ON wt.weighment_id = w.id
+         LEFT JOIN transporters t ON t.id = wt.transporter_id
+         WHERE w.id = ?1",
+        [&weighment_id],
+        |row| {
+            Ok(TicketParties {
+                weighment_id: weighment_id.clone(),
+                billed_party: row.get(0)?,
+                consignor: row.get(1)?,
+                consignee: row.get(2)?,
+                transporter: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Weighment {} not found", weighment_id))
+}
+
+// Set consignor and consignee on an existing ticket. Use assign_transporter
+// for the transporter and amendments for the billed party.
+#[tauri::command]
+pub fn set_ticket_parties(
+    app: AppHandle,
+    weighment_id: String,
+    consignor: Option<String>,
+    consignee: Option<String>,
+) -> Result<TicketParties, String> {
+    {
+        let conn = db::open(&app)?;
+        if period_lock::is_weighment_locked(&conn, &weighment_id)? {
+            return Err(format!(
+                "Weighment {} is in a locked period and cannot be changed",
+                weighment_id
+            ));
+        }
+        // Checks the ticket exists before writing rows for it
+        get_ticket_parties(app.clone(), weighment_id.clone())?;
+        link(
+            &conn,
+            &weighment_id,
+            consignor.as_deref(),
+            consignee.as_deref(),
+        )?;
+    }
+    get_ticket_parties(app, weighment_id)
+}
+
+// Tickets and net weight per party acting in `role`, for closed tickets in
+// the range. Tickets without a party in that role are left out.
+#[tauri::command]
+pub fn party_role_report(
+    app: AppHandle,
+    range: DateRange,
+    role: PartyRole,
+) -> Result<Vec<PartyRoleTotal>, String> {
+    let conn = db::open(&app)?;
+    let (name, join) = match role {
+        PartyRole::Billed => ("w.party_name", String::new()),
+        PartyRole::Consignor | PartyRole::Consignee => (
+            "wp.party_name",
+            format!(
+                "JOIN weighment_parties wp ON wp.weighment_id = w.id AND wp.role = '{}'",
+                if matches!(role, PartyRole::Consignor) {
+                    "consignor"
+                } else {
+                    "consignee"
+                }
+            ),
+        ),
+        PartyRole::Transporter => (
+            "t.name",
+            "JOIN weighment_transport wt ON wt.weighment_id = w.id
+             JOIN transporters t ON t.id = wt.transporter_id"
+                .to_string(),
+        ),
+    };
+    let sql = format!(
+        "SELECT {name}, COUNT(*), COALESCE(SUM(w.net_weight), 0)
+         FROM weighments w {join}
+         WHERE w.net_weight IS NOT NULL AND {} BETWEEN ?1 AND ?2
+           AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}
+         GROUP BY {name}
+         ORDER BY SUM(w.net_weight) DESC",
+        db::local_date("w.created_at"),
+        training::exclude_practice("w.id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok(PartyRoleTotal {
+                party_name: row.get(0)?,
+                tickets: row.get(1)?,
+                net_weight_kg: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
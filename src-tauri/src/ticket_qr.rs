@@ -0,0 +1,138 @@
+// Ticket QR codes for Truckore Pro
+// Printed tickets carry a QR code so the outbound gate can check a truck's
+// ticket by scanning it. The code holds the slip verification link
+// (slip_verification.rs) with the stored weights appended as gross=, tare=
+// and net=: a gate app reads the ticket number and weights straight from
+// it, and the HMAC code in the link ties them to the record. Codes are
+// encoded with rxing, which already reads barcodes (barcode.rs), and drawn
+// by the ESC/POS and PDF printers from the same module matrix.
+
+use crate::db;
+use crate::slip_verification;
+use base64::{engine::general_purpose, Engine as _};
+use image::{ImageFormat, Luma};
+use rusqlite::Connection;
+use rxing::common::BitMatrix;
+use rxing::qrcode::QRCodeWriter;
+use rxing::{BarcodeFormat, EncodeHints, Writer};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tauri::AppHandle;
+
+// Pixels per module in the PNG
+const PNG_MODULE_PX: u32 = 8;
+const GS: u8 = 0x1D;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketQr {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    // Text encoded in the code
+    pub payload: String,
+    pub png_base64: String,
+    pub size_px: u32,
+}
+
+// Ticket number and what its QR code says, by weighment "id" or
+// "ticket_no"; None for a ticket that is not on record
+pub fn payload(
+    conn: &Connection,
+    column: &str,
+    value: &str,
+) -> Result<Option<(String, String)>, String> {
+    let Some((slip, verification)) = slip_verification::for_ticket(conn, column, value)? else {
+        return Ok(None);
+    };
+    let mut payload = verification.url.unwrap_or(verification.path);
+    for (name, weight) in [
+        ("gross", slip.gross_weight),
+        ("tare", slip.tare_weight),
+        ("net", slip.net_weight),
+    ] {
+        if let Some(kg) = weight {
+            payload.push_str(&format!("&{}={}", name, kg));
+        }
+    }
+    Ok(Some((slip.ticket_no, payload)))
+}
+
+// One bit per module, dark set, with the standard four-module quiet zone
+pub fn encode(payload: &str) -> Result<BitMatrix, String> {
+    let hints = EncodeHints {
+        // Medium error correction survives a smudged or creased ticket
+        ErrorCorrection: Some("M".to_string()),
+        ..Default::default()
+    };
+    QRCodeWriter
+        .encode_with_hints(payload, &BarcodeFormat::QR_CODE, 0, 0, &hints)
+        .map_err(|e| format!("Failed to encode QR code: {}", e))
+}
+
+// Rows of modules, dark true, for the PDF writer
+pub fn modules(matrix: &BitMatrix) -> Vec<Vec<bool>> {
+    (0..matrix.getHeight())
+        .map(|y| (0..matrix.getWidth()).map(|x| matrix.get(x, y)).collect())
+        .collect()
+}
+
+pub fn png(matrix: &BitMatrix, module_px: u32) -> Result<Vec<u8>, String> {
+    let img = image::GrayImage::from_fn(
+        matrix.getWidth() * module_px,
+        matrix.getHeight() * module_px,
+        |x, y| match matrix.get(x / module_px, y / module_px) {
+            true => Luma([0]),
+            false => Luma([255]),
+        },
+    );
+    let mut out = Vec::new();
+    img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+// ESC/POS raster image (GS v 0) of rows of modules, `dots` per module;
+// raster prints on receipt printers without the native QR command too
+pub fn escpos_raster(modules: &[Vec<bool>], dots: u32) -> Vec<u8> {
+    let columns = modules.first().map_or(0, |row| row.len()) as u32;
+    let (width, height) = (columns * dots, modules.len() as u32 * dots);
+    let bytes_per_row = (width + 7) / 8;
+    let mut out = vec![
+        GS,
+        b'v',
+        b'0',
+        0,
+        (bytes_per_row & 0xFF) as u8,
+        (bytes_per_row >> 8) as u8,
+        (height & 0xFF) as u8,
+        (height >> 8) as u8,
+    ];
+    for y in 0..height {
+        for byte in 0..bytes_per_row {
+            let mut bits = 0u8;
+            for bit in 0..8 {
+                let x = byte * 8 + bit;
+                if x < width && modules[(y / dots) as usize][(x / dots) as usize] {
+                    bits |= 0x80 >> bit;
+                }
+            }
+            out.push(bits);
+        }
+    }
+    out
+}
+
+// The QR code printed on a ticket, as a PNG
+#[tauri::command]
+pub fn generate_ticket_qr(app: AppHandle, weighment_id: String) -> Result<TicketQr, String> {
+    let conn = db::open(&app)?;
+    let (ticket_no, payload) = payload(&conn, "id", &weighment_id)?
+        .ok_or_else(|| format!("Ticket {} not found", weighment_id))?;
+    let matrix = encode(&payload)?;
+    Ok(TicketQr {
+        weighment_id,
+        ticket_no,
+        png_base64: general_purpose::STANDARD.encode(png(&matrix, PNG_MODULE_PX)?),
+        size_px: matrix.getWidth() * PNG_MODULE_PX,
+        payload,
+    })
+}
@@ -0,0 +1,119 @@
+// Training mode for Truckore Pro
+// While app_config.training_mode is on, a schema trigger tags every new
+// weighment as a PRACTICE ticket. Practice tickets are left out of reports,
+// exports and sync, carry a watermark on their slips, and can be purged.
+
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const TRAINING_CONFIG_KEY: &str = "training_mode";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeResult {
+    pub tickets: usize,
+}
+
+// SQL condition keeping practice tickets out of reports, exports and sync
+pub fn exclude_practice(id_column: &str) -> String {
+    format!(
+        "{} NOT IN (SELECT weighment_id FROM practice_tickets)",
+        id_column
+    )
+}
+
+pub fn is_practice(conn: &Connection, weighment_id: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM practice_tickets WHERE weighment_id = ?1)",
+        [weighment_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_training_mode(app: AppHandle) -> Result<bool, String> {
+    let conn = db::open(&app)?;
+    Ok(db::get_config(&conn, TRAINING_CONFIG_KEY)?.as_deref() == Some("true"))
+}
+
+// Switch training mode on or off (admin only)
+#[tauri::command]
+pub fn set_training_mode(app: AppHandle, enabled: bool, user_id: String) -> Result<(), String> {
+    command_audit::audited(
+        &app,
+        "set_training_mode",
+        &user_id,
+        serde_json::json!({ "enabled": enabled }),
+        || {
+            let conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+            db::set_config(
+                &conn,
+                TRAINING_CONFIG_KEY,
+                if enabled { "true" } else { "false" },
+            )
+        },
+    )
+}
+
+// Delete every practice ticket along with its flags, anomalies, amendments,
+// voids and approvals (admin only)
+#[tauri::command]
+pub fn purge_practice_data(app: AppHandle, user_id: String) -> Result<PurgeResult, String> {
+    command_audit::audited(
+        &app,
+        "purge_practice_data",
+        &user_id,
+        serde_json::json!({}),
+        || {
+            let mut conn = db::open(&app)?;
+            roles::require_role(&conn, &user_id, Role::Admin)?;
+
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            for table in [
+                "fraud_flags",
+                "weighment_anomalies",
+                "weighment_amendments",
+                "ticket_voids",
+                "weighment_deductions",
+                "weighment_net_adjustments",
+                "weighment_transport",
+                "weighment_direction",
+                "stock_ledger",
+                "weighment_billing",
+                "shift_payments",
+                "weighment_signatures",
+            ] {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {} WHERE weighment_id IN (SELECT weighment_id FROM practice_tickets)",
+                        table
+                    ),
+                    [],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            tx.execute(
+                "DELETE FROM approvals WHERE entity = 'weighment'
+                   AND entity_id IN (SELECT weighment_id FROM practice_tickets)",
+                [],
+            )
+            .map_err(|e| e.to_string())?;
+            let tickets = tx
+                .execute(
+                    "DELETE FROM weighments WHERE id IN (SELECT weighment_id FROM practice_tickets)",
+                    [],
+                )
+                .map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM practice_tickets", params![])
+                .map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+
+            Ok(PurgeResult { tickets })
+        },
+    )
+}
@@ -0,0 +1,169 @@
+// Transporters and freight settlement for Truckore Pro
+// Each ticket can be booked to a transporter; the transporter's rate (per
+// tonne or per km) is copied onto the ticket when assigned so later rate
+// changes don't rewrite past freight.
+
+use crate::db::{self, DateRange};
+use crate::masking;
+use crate::rounding;
+use crate::training;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transporter {
+    pub id: Option<i64>,
+    pub name: String,
+    // "PER_TONNE" or "PER_KM"
+    pub rate_type: String,
+    // Paise per tonne or per km
+    pub rate_minor: i64,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransporterSettlement {
+    pub transporter: String,
+    pub rate_type: String,
+    pub trips: i64,
+    pub tonnage: f64,
+    pub distance_km: f64,
+    pub freight_minor: i64,
+    // Per-km trips without a distance, left out of the freight total
+    pub trips_missing_distance: i64,
+}
+
+#[tauri::command]
+pub fn list_transporters(app: AppHandle) -> Result<Vec<Transporter>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare("SELECT id, name, rate_type, rate_minor, active FROM transporters ORDER BY name")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(Transporter {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                rate_type: row.get(2)?,
+                rate_minor: row.get(3)?,
+                active: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Create or update a transporter (matched by name). Returns its id.
+#[tauri::command]
+pub fn set_transporter(app: AppHandle, transporter: Transporter) -> Result<i64, String> {
+    let conn = db::open(&app)?;
+    conn.execute(
+        "INSERT INTO transporters (name, rate_type, rate_minor, active) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(name) DO UPDATE SET rate_type = excluded.rate_type,
+             rate_minor = excluded.rate_minor, active = excluded.active",
+        params![
+            transporter.name,
+            transporter.rate_type,
+            transporter.rate_minor,
+            transporter.active
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.query_row(
+        "SELECT id FROM transporters WHERE name = ?1",
+        [&transporter.name],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Book a ticket to a transporter, with the trip distance for per-km rates
+#[tauri::command]
+pub fn assign_transporter(
+    app: AppHandle,
+    weighment_id: String,
+    transporter: String,
+    distance_km: Option<f64>,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    let (transporter_id, rate_type, rate_minor): (i64, String, i64) = conn
+        .query_row(
+            "SELECT id, rate_type, rate_minor FROM transporters WHERE name = ?1 AND active = 1",
+            [&transporter],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Unknown or inactive transporter: {}", transporter))?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO weighment_transport
+             (weighment_id, transporter_id, rate_type, rate_minor, distance_km)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            weighment_id,
+            transporter_id,
+            rate_type,
+            rate_minor,
+            distance_km
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Trips, tonnage and freight per transporter for closed tickets in the
+// range, masked for the caller's role
+#[tauri::command]
+pub fn transporter_settlement_report(
+    app: AppHandle,
+    range: DateRange,
+    transporter: Option<String>,
+    session_token: Option<String>,
+) -> Result<Value, String> {
+    let conn = db::open(&app)?;
+    let rounding = rounding::load_rules(&conn)?;
+    let sql = format!(
+        "SELECT t.name, wt.rate_type,
+                COUNT(*),
+                COALESCE(SUM(w.net_weight), 0) / 1000.0,
+                COALESCE(SUM(wt.distance_km), 0),
+                COALESCE(SUM(CASE wt.rate_type
+                    WHEN 'PER_TONNE' THEN wt.rate_minor * w.net_weight / 1000.0
+                    ELSE wt.rate_minor * wt.distance_km END), 0),
+                SUM(wt.rate_type = 'PER_KM' AND wt.distance_km IS NULL)
+         FROM weighment_transport wt
+         JOIN transporters t ON t.id = wt.transporter_id
+         JOIN weighments w ON w.id = wt.weighment_id
+         WHERE w.net_weight IS NOT NULL AND {} BETWEEN ?1 AND ?2
+           AND (?3 IS NULL OR t.name = ?3)
+           AND w.id NOT IN (SELECT weighment_id FROM ticket_voids) AND {}
+         GROUP BY t.name, wt.rate_type
+         ORDER BY t.name, wt.rate_type",
+        db::local_date("w.created_at"),
+        training::exclude_practice("w.id")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to, transporter], |row| {
+            Ok(TransporterSettlement {
+                transporter: row.get(0)?,
+                rate_type: row.get(1)?,
+                trips: row.get(2)?,
+                tonnage: row.get(3)?,
+                distance_km: row.get(4)?,
+                freight_minor: rounding.amount(row.get(5)?),
+                trips_missing_distance: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    let settlements = rows
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    masking::for_session(&app, session_token.as_deref())?.masked(&settlements)
+}
@@ -0,0 +1,538 @@
+// Undelete for Truckore Pro
+// A party, product or vehicle deleted by mistake used to mean typing it in
+// again, along with its credit limit, tariffs, tags and stored tare, and the
+// audit log only said that some DELETE ran. Deleting from the master tables
+// and from the configuration keyed on them now keeps the deleted row in
+// deleted_records, where an AFTER DELETE trigger writes it as JSON along with
+// the audit log position, so each deletion can be matched with the audited
+// statement (and user) that made it. undelete_with_dependencies puts a
+// master row back together with the dependent rows deleted around the same
+// time, after checking that nothing now holds their keys and that every row
+// they reference exists; the foreign keys are checked again before the
+// restore commits.
+
+use crate::audit_log;
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::AppHandle;
+
+const MASTERS: &[&str] = &["parties", "products", "vehicles"];
+
+// (table, column, master, master column): configuration rows whose column
+// holds the master column of a master row
+const DEPENDENTS: &[(&str, &str, &str, &str)] = &[
+    (
+        "billing_instructions",
+        "party_name",
+        "parties",
+        "party_name",
+    ),
+    ("deduction_rules", "party_name", "parties", "party_name"),
+    (
+        "deduction_rules",
+        "product_name",
+        "products",
+        "product_name",
+    ),
+    (
+        "material_capture_rules",
+        "product_name",
+        "products",
+        "product_name",
+    ),
+    (
+        "material_movement_rules",
+        "product_name",
+        "products",
+        "product_name",
+    ),
+    (
+        "party_billing_currency",
+        "party_name",
+        "parties",
+        "party_name",
+    ),
+    ("party_credit_limits", "party_name", "parties", "party_name"),
+    ("purchase_orders", "party_name", "parties", "party_name"),
+    ("stored_tares", "vehicle_no", "vehicles", "vehicle_no"),
+    ("tariffs", "party_name", "parties", "party_name"),
+    ("tariffs", "product_name", "products", "product_name"),
+    (
+        "vehicle_class_members",
+        "vehicle_no",
+        "vehicles",
+        "vehicle_no",
+    ),
+    ("vehicle_tags", "vehicle_id", "vehicles", "id"),
+];
+
+// Dependent rows deleted this long before or after their master count as
+// deleted with it
+const DEPENDENT_WINDOW_SECONDS: i64 = 600;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeletedRecord {
+    pub id: i64,
+    pub table_name: String,
+    // Primary key of the deleted row
+    pub row_key: String,
+    pub row: Value,
+    pub deleted_at: String,
+    // From the audit log entry of the statement that deleted the row, when
+    // it went through an audited write
+    pub deleted_by: Option<String>,
+    pub delete_sql: Option<String>,
+    pub restored_at: Option<String>,
+    pub restored_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoredRow {
+    pub table_name: String,
+    pub row_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedRow {
+    pub table_name: String,
+    pub row_key: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndeleteResult {
+    // The master row first, then its dependents
+    pub restored: Vec<RestoredRow>,
+    // Dependents left deleted, e.g. because the site has set them up again
+    pub skipped: Vec<SkippedRow>,
+}
+
+// Tables whose deleted rows are kept
+fn recorded_tables() -> Vec<&'static str> {
+    let mut tables: Vec<&str> = MASTERS.to_vec();
+    for (table, _, _, _) in DEPENDENTS {
+        if !tables.contains(table) {
+            tables.push(table);
+        }
+    }
+    tables
+}
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<(String, i64)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT name, pk FROM pragma_table_info(?1) ORDER BY cid")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([table], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn primary_key(conn: &Connection, table: &str) -> Result<String, String> {
+    columns(conn, table)?
+        .into_iter()
+        .find(|(_, pk)| *pk == 1)
+        .map(|(name, _)| name)
+        .ok_or_else(|| format!("Table {} has no primary key", table))
+}
+
+// The trigger keeping one table's deleted rows. It names the table's columns,
+// so a migration adding a column to a recorded table calls this again.
+pub fn track(conn: &Connection, table: &str) -> Result<(), String> {
+    let columns = columns(conn, table)?;
+    let key = primary_key(conn, table)?;
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|(name, _)| format!("'{c}', OLD.\"{c}\"", c = name))
+        .collect();
+    conn.execute_batch(&format!(
+        "DROP TRIGGER IF EXISTS {t}_deleted_record;
+         CREATE TRIGGER {t}_deleted_record
+         AFTER DELETE ON {t}
+         BEGIN
+             INSERT INTO deleted_records (table_name, row_key, row_data, audit_after)
+             VALUES ('{t}', CAST(OLD.\"{key}\" AS TEXT), json_object({fields}),
+                     (SELECT COALESCE(MAX(id), 0) FROM audit_log));
+         END;",
+        t = table,
+        key = key,
+        fields = fields.join(", ")
+    ))
+    .map_err(|e| format!("Deleted record trigger for {} failed: {}", table, e))
+}
+
+// Keep deleted master and configuration rows. Schema migration 11; the
+// caller holds the transaction.
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    for table in recorded_tables() {
+        track(conn, table)?;
+    }
+    Ok(())
+}
+
+const RECORD_SELECT: &str = "SELECT d.id, d.table_name, d.row_key, d.row_data, d.deleted_at,
+            a.user_id, a.sql, d.restored_at, d.restored_by
+     FROM deleted_records d
+     LEFT JOIN audit_log a ON a.id = (
+         SELECT MIN(l.id) FROM audit_log l
+         WHERE l.id > d.audit_after AND l.table_name = d.table_name
+           AND upper(l.sql) LIKE 'DELETE%'
+           AND l.logged_at BETWEEN d.deleted_at AND datetime(d.deleted_at, '+5 seconds'))";
+
+fn row_to_record(row: &rusqlite::Row) -> rusqlite::Result<DeletedRecord> {
+    let data: String = row.get(3)?;
+    Ok(DeletedRecord {
+        id: row.get(0)?,
+        table_name: row.get(1)?,
+        row_key: row.get(2)?,
+        row: serde_json::from_str(&data).unwrap_or(Value::Null),
+        deleted_at: row.get(4)?,
+        deleted_by: row.get(5)?,
+        delete_sql: row.get(6)?,
+        restored_at: row.get(7)?,
+        restored_by: row.get(8)?,
+    })
+}
+
+fn fields(record: &DeletedRecord) -> Result<&Map<String, Value>, String> {
+    record.row.as_object().ok_or_else(|| {
+        format!(
+            "Deleted record {} of {} cannot be read",
+            record.id, record.table_name
+        )
+    })
+}
+
+// Why `row` cannot go back into `table`: a row that now holds one of its
+// unique keys, or a row it references that does not exist
+fn obstacle(
+    conn: &Connection,
+    table: &str,
+    row: &Map<String, Value>,
+) -> Result<Option<String>, String> {
+    let mut keys: Vec<Vec<String>> = Vec::new();
+    let pk: Vec<String> = columns(conn, table)?
+        .into_iter()
+        .filter(|(_, pk)| *pk > 0)
+        .map(|(name, _)| name)
+        .collect();
+    keys.push(pk);
+    let mut stmt = conn
+        .prepare(
+            r#"SELECT name FROM pragma_index_list(?1)
+               WHERE "unique" = 1 AND partial = 0 AND origin <> 'pk'"#,
+        )
+        .map_err(|e| e.to_string())?;
+    let indexes = stmt
+        .query_map([table], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for index in indexes {
+        let mut stmt = conn
+            .prepare("SELECT name FROM pragma_index_info(?1) ORDER BY seqno")
+            .map_err(|e| e.to_string())?;
+        let columns = stmt
+            .query_map([&index], |row| row.get::<_, Option<String>>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        // An index on an expression cannot be checked here
+        if let Some(columns) = columns.into_iter().collect::<Option<Vec<_>>>() {
+            keys.push(columns);
+        }
+    }
+    for key in keys.iter().filter(|k| !k.is_empty()) {
+        let values: Vec<&Value> = key
+            .iter()
+            .map(|c| row.get(c).unwrap_or(&Value::Null))
+            .collect();
+        // NULLs never collide in a unique key
+        if values.iter().any(|v| v.is_null()) {
+            continue;
+        }
+        let condition: Vec<String> = key
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("\"{}\" = ?{}", c, i + 1))
+            .collect();
+        let taken = conn
+            .query_row(
+                &format!(
+                    "SELECT 1 FROM \"{}\" WHERE {} LIMIT 1",
+                    table,
+                    condition.join(" AND ")
+                ),
+                params_from_iter(values.iter().map(|v| crate::json_to_sql_value(v))),
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if taken.is_some() {
+            let shown: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            return Ok(Some(format!(
+                "{} {} is in use by another {} row",
+                key.join(", "),
+                shown.join(", "),
+                table
+            )));
+        }
+    }
+
+    let mut stmt = conn
+        .prepare(r#"SELECT "table", "from", "to" FROM pragma_foreign_key_list(?1)"#)
+        .map_err(|e| e.to_string())?;
+    let references = stmt
+        .query_map([table], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (parent, from, to) in references {
+        let value = row.get(&from).unwrap_or(&Value::Null);
+        if value.is_null() {
+            continue;
+        }
+        let to = match to {
+            Some(to) => to,
+            None => primary_key(conn, &parent)?,
+        };
+        let found = conn
+            .query_row(
+                &format!("SELECT 1 FROM \"{}\" WHERE \"{}\" = ?1 LIMIT 1", parent, to),
+                [crate::json_to_sql_value(value)],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if found.is_none() {
+            return Ok(Some(format!(
+                "{} {} refers to a {} row that does not exist",
+                from, value, parent
+            )));
+        }
+    }
+    Ok(None)
+}
+
+// Insert a deleted row with the columns the table still has, recorded in the
+// audit log like any other write
+fn reinsert(
+    conn: &Connection,
+    record: &DeletedRecord,
+    user_id: &str,
+) -> Result<RestoredRow, String> {
+    let row = fields(record)?;
+    let present: Vec<String> = columns(conn, &record.table_name)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| row.contains_key(name))
+        .collect();
+    let placeholders: Vec<String> = (1..=present.len()).map(|i| format!("?{}", i)).collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        record.table_name,
+        present
+            .iter()
+            .map(|c| format!("\"{}\"", c))
+            .collect::<Vec<_>>()
+            .join(", "),
+        placeholders.join(", ")
+    );
+    let values: Vec<Value> = present.iter().map(|c| row[c].clone()).collect();
+    let inserted = conn
+        .execute(
+            &sql,
+            params_from_iter(values.iter().map(crate::json_to_sql_value)),
+        )
+        .map_err(|e| format!("Restoring {} {}: {}", record.table_name, record.row_key, e))?;
+    audit_log::record(
+        conn,
+        "undelete_with_dependencies",
+        Some(&record.table_name),
+        &sql,
+        &Value::from(values),
+        inserted,
+        Some(user_id),
+    )?;
+    conn.execute(
+        "UPDATE deleted_records SET restored_at = CURRENT_TIMESTAMP, restored_by = ?2
+         WHERE id = ?1",
+        params![record.id, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(RestoredRow {
+        table_name: record.table_name.clone(),
+        row_key: record.row_key.clone(),
+    })
+}
+
+// Dependent rows deleted around the time `master` was
+fn dependents(conn: &Connection, master: &DeletedRecord) -> Result<Vec<DeletedRecord>, String> {
+    let row = fields(master)?;
+    let mut found: Vec<DeletedRecord> = Vec::new();
+    for (table, column, _, master_column) in DEPENDENTS
+        .iter()
+        .filter(|(_, _, m, _)| *m == master.table_name)
+    {
+        let Some(value) = row.get(*master_column).filter(|v| !v.is_null()) else {
+            continue;
+        };
+        let mut stmt = conn
+            .prepare(&format!(
+                "{} WHERE d.table_name = ?1 AND d.restored_at IS NULL
+                   AND json_extract(d.row_data, '$.' || ?2) = ?3
+                   AND ABS(strftime('%s', d.deleted_at) - strftime('%s', ?4)) <= ?5
+                 ORDER BY d.id",
+                RECORD_SELECT
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(
+                params![
+                    table,
+                    column,
+                    crate::json_to_sql_value(value),
+                    master.deleted_at,
+                    DEPENDENT_WINDOW_SECONDS
+                ],
+                row_to_record,
+            )
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        for record in rows {
+            // A row that was deleted more than once goes back as it was last
+            found.retain(|r| !(r.table_name == record.table_name && r.row_key == record.row_key));
+            if !found.iter().any(|r| r.id == record.id) {
+                found.push(record);
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn restore(
+    tx: &Connection,
+    table: &str,
+    id: &str,
+    user_id: &str,
+) -> Result<UndeleteResult, String> {
+    let master = tx
+        .query_row(
+            &format!(
+                "{} WHERE d.table_name = ?1 AND d.row_key = ?2 AND d.restored_at IS NULL
+                 ORDER BY d.id DESC LIMIT 1",
+                RECORD_SELECT
+            ),
+            [table, id],
+            row_to_record,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No deleted {} row {} is waiting to be restored", table, id))?;
+    if let Some(reason) = obstacle(tx, table, fields(&master)?)? {
+        return Err(format!("Cannot restore {} {}: {}", table, id, reason));
+    }
+    let mut result = UndeleteResult {
+        restored: vec![reinsert(tx, &master, user_id)?],
+        skipped: Vec::new(),
+    };
+    for record in dependents(tx, &master)? {
+        match obstacle(tx, &record.table_name, fields(&record)?)? {
+            Some(reason) => result.skipped.push(SkippedRow {
+                table_name: record.table_name.clone(),
+                row_key: record.row_key.clone(),
+                reason,
+            }),
+            None => result.restored.push(reinsert(tx, &record, user_id)?),
+        }
+    }
+    let mut touched: Vec<&str> = result
+        .restored
+        .iter()
+        .map(|r| r.table_name.as_str())
+        .collect();
+    touched.sort_unstable();
+    touched.dedup();
+    for table in touched {
+        let violations: i64 = tx
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_foreign_key_check(?1)",
+                [table],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if violations > 0 {
+            return Err(format!(
+                "Restoring would leave {} {} row(s) referring to missing rows",
+                violations, table
+            ));
+        }
+    }
+    Ok(result)
+}
+
+// Deleted master and configuration rows, newest first (admin only)
+#[tauri::command]
+pub fn list_deleted_records(
+    app: AppHandle,
+    table_name: Option<String>,
+    include_restored: Option<bool>,
+    limit: Option<i64>,
+) -> Result<Vec<DeletedRecord>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} WHERE (?1 IS NULL OR d.table_name = ?1) AND (?2 OR d.restored_at IS NULL)
+             ORDER BY d.id DESC LIMIT ?3",
+            RECORD_SELECT
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                table_name,
+                include_restored.unwrap_or(false),
+                limit.unwrap_or(500)
+            ],
+            row_to_record,
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Put a deleted row back (admin only): `table` and `id` name it by its
+// primary key. A party, product or vehicle brings back the configuration
+// deleted with it; dependents that can no longer go back are reported and
+// stay deleted. Nothing is restored when the row itself cannot be.
+#[tauri::command]
+pub fn undelete_with_dependencies(
+    app: AppHandle,
+    table: String,
+    id: String,
+    user_id: String,
+) -> Result<UndeleteResult, String> {
+    let args = serde_json::json!({ "table": table, "id": id });
+    command_audit::audited(&app, "undelete_with_dependencies", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        drop(conn);
+        if !recorded_tables().contains(&table.as_str()) {
+            return Err(format!("Deleted rows of {} are not kept", table));
+        }
+        let (table, id, user_id) = (table.clone(), id.clone(), user_id.clone());
+        db::write(&app, move |tx| restore(tx, &table, &id, &user_id))
+    })
+}
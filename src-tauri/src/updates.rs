@@ -0,0 +1,413 @@
+// Application updates for Truckore Pro
+// Sites follow a release channel (stable or beta). A release manifest in the
+// Tauri updater format is published per channel, optionally with a rollout
+// percentage so a release reaches a share of sites first. The database is
+// backed up before installing, and the first start on a new version
+// re-runs the schema and checks the database before operators continue.
+
+use crate::backup;
+use crate::command_audit;
+use crate::db;
+use crate::encryption;
+use crate::idempotency;
+use crate::migrations;
+use crate::notifications;
+use crate::roles::{self, Role};
+use crate::shutdown;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use tauri::{AppHandle, Manager};
+
+const CHANNEL_CONFIG_KEY: &str = "update_channel";
+const INSTALLED_VERSION_KEY: &str = "installed_version";
+const CHANNELS: [&str; 2] = ["stable", "beta"];
+
+// Emitted as `update_status` at each step of a check, install or verification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateStatus {
+    // CHECKING, UP_TO_DATE, AVAILABLE, NOT_IN_ROLLOUT, BACKED_UP, VERIFIED or FAILED
+    pub status: String,
+    pub current_version: String,
+    pub version: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlatformRelease {
+    pub url: String,
+    pub signature: String,
+}
+
+// Channel manifest, the Tauri updater's static JSON plus a rollout percentage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseManifest {
+    pub version: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub pub_date: Option<String>,
+    #[serde(default)]
+    pub platforms: BTreeMap<String, PlatformRelease>,
+    // Share of sites (0-100) offered this release; absent means everyone
+    #[serde(default)]
+    pub rollout_percent: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateCheck {
+    pub channel: String,
+    pub current_version: String,
+    pub available: bool,
+    pub release: Option<ReleaseManifest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRecord {
+    pub id: i64,
+    pub from_version: String,
+    pub to_version: String,
+    pub channel: String,
+    pub backup_path: Option<String>,
+    // PREPARED, VERIFIED or FAILED
+    pub status: String,
+    pub detail: Option<String>,
+    pub created_at: String,
+    pub verified_at: Option<String>,
+}
+
+fn emit(app: &AppHandle, status: &str, version: Option<&str>, message: Option<String>) {
+    let event = UpdateStatus {
+        status: status.to_string(),
+        current_version: current_version(app),
+        version: version.map(|v| v.to_string()),
+        message,
+    };
+    // Delivery to windows is best effort
+    let _ = app.emit_all("update_status", &event);
+}
+
+fn current_version(app: &AppHandle) -> String {
+    app.package_info().version.to_string()
+}
+
+fn load_channel(conn: &Connection) -> Result<String, String> {
+    Ok(db::get_config(conn, CHANNEL_CONFIG_KEY)?.unwrap_or_else(|| "stable".to_string()))
+}
+
+// "1.4.0-beta.2" -> ([1, 4, 0], true). A pre-release sorts before its release.
+fn parse_version(version: &str) -> ([u64; 3], bool) {
+    let version = version.trim().trim_start_matches('v');
+    let (core, pre) = match version.split_once('-') {
+        Some((core, _)) => (core, true),
+        None => (version, false),
+    };
+    let mut parts = [0u64; 3];
+    for (slot, part) in parts.iter_mut().zip(core.split('.')) {
+        *slot = part.parse().unwrap_or(0);
+    }
+    (parts, pre)
+}
+
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let (c, c_pre) = parse_version(candidate);
+    let (i, i_pre) = parse_version(current);
+    c > i || (c == i && i_pre && !c_pre)
+}
+
+// Sites land in a stable bucket (0-99) so a rollout widens rather than reshuffles
+fn rollout_bucket(conn: &Connection) -> Result<u32, String> {
+    let site = db::get_config(conn, "site_id")?.unwrap_or_default();
+    let digest = idempotency::digest(&serde_json::json!(site));
+    u32::from_str_radix(&digest[..8], 16)
+        .map(|n| n % 100)
+        .map_err(|e| e.to_string())
+}
+
+fn manifest_location(conn: &Connection, channel: &str) -> Result<String, String> {
+    db::get_config(conn, &format!("update_manifest_{}", channel))?
+        .ok_or_else(|| format!("No release manifest configured for the {} channel", channel))
+}
+
+#[tauri::command]
+pub fn get_update_channel(app: AppHandle) -> Result<String, String> {
+    let conn = db::open(&app)?;
+    load_channel(&conn)
+}
+
+#[tauri::command]
+pub fn set_update_channel(app: AppHandle, channel: String, user_id: String) -> Result<(), String> {
+    let args = serde_json::json!({ "channel": channel });
+    command_audit::audited(&app, "set_update_channel", &user_id, args, || {
+        if !CHANNELS.contains(&channel.as_str()) {
+            return Err(format!("Unknown update channel {}", channel));
+        }
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::SuperAdmin)?;
+        db::set_config(&conn, CHANNEL_CONFIG_KEY, &channel)
+    })
+}
+
+// Where each channel's manifest is published (a path on the update share)
+#[tauri::command]
+pub fn set_update_manifest(
+    app: AppHandle,
+    channel: String,
+    location: String,
+    user_id: String,
+) -> Result<(), String> {
+    let args = serde_json::json!({ "channel": channel, "location": location });
+    command_audit::audited(&app, "set_update_manifest", &user_id, args, || {
+        if !CHANNELS.contains(&channel.as_str()) {
+            return Err(format!("Unknown update channel {}", channel));
+        }
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::SuperAdmin)?;
+        db::set_config(&conn, &format!("update_manifest_{}", channel), &location)
+    })
+}
+
+// Read the channel manifest and report whether a newer release is offered to this site
+#[tauri::command]
+pub fn check_for_updates(app: AppHandle) -> Result<UpdateCheck, String> {
+    let current = current_version(&app);
+    emit(&app, "CHECKING", None, None);
+
+    let outcome = (|| {
+        let conn = db::open(&app)?;
+        let channel = load_channel(&conn)?;
+        let json = fs::read_to_string(manifest_location(&conn, &channel)?)
+            .map_err(|e| format!("Failed to read release manifest: {}", e))?;
+        let release: ReleaseManifest =
+            serde_json::from_str(&json).map_err(|e| format!("Invalid release manifest: {}", e))?;
+        let in_rollout = match release.rollout_percent {
+            Some(percent) => rollout_bucket(&conn)? < percent,
+            None => true,
+        };
+        Ok::<_, String>((channel, release, in_rollout))
+    })();
+
+    match outcome {
+        Ok((channel, release, in_rollout)) => {
+            let newer = is_newer(&release.version, &current);
+            let status = match (newer, in_rollout) {
+                (false, _) => "UP_TO_DATE",
+                (true, false) => "NOT_IN_ROLLOUT",
+                (true, true) => "AVAILABLE",
+            };
+            emit(&app, status, Some(&release.version), release.notes.clone());
+            Ok(UpdateCheck {
+                channel,
+                current_version: current,
+                available: newer && in_rollout,
+                release: if newer && in_rollout {
+                    Some(release)
+                } else {
+                    None
+                },
+            })
+        }
+        Err(e) => {
+            emit(&app, "FAILED", None, Some(e.clone()));
+            Err(e)
+        }
+    }
+}
+
+// Back up and verify the database before `to_version` is installed. The
+// installer must not run unless this succeeds.
+#[tauri::command]
+pub fn prepare_update(
+    app: AppHandle,
+    to_version: String,
+    user_id: String,
+) -> Result<UpdateRecord, String> {
+    let args = serde_json::json!({ "to_version": to_version });
+    command_audit::audited(&app, "prepare_update", &user_id, args, || {
+        let conn = db::open(&app)?;
+        roles::require_role(&conn, &user_id, Role::Admin)?;
+        let _operation = shutdown::begin(&app, "backup", Some(&to_version))?;
+        let from_version = current_version(&app);
+        let channel = load_channel(&conn)?;
+
+        let backup_dir = crate::get_db_path(&app)?
+            .parent()
+            .ok_or("Failed to resolve the data directory")?
+            .join("backups");
+        fs::create_dir_all(&backup_dir).map_err(|e| e.to_string())?;
+        let stamp: String = conn
+            .query_row("SELECT strftime('%Y%m%d%H%M%S', 'now')", [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        let backup_path = backup_dir
+            .join(format!("pre-update-{}-{}.db", from_version, stamp))
+            .to_string_lossy()
+            .to_string();
+
+        let result = conn
+            .execute("VACUUM INTO ?1", [&backup_path])
+            .map_err(|e| format!("Pre-update backup failed: {}", e))
+            .and_then(|_| backup::verify_backup(app.clone(), backup_path.clone()));
+        let (status, detail) = match &result {
+            Ok(v) if v.ok => ("PREPARED", None),
+            Ok(v) => (
+                "FAILED",
+                Some(
+                    v.error
+                        .clone()
+                        .unwrap_or_else(|| format!("Backup check failed: {}", v.integrity)),
+                ),
+            ),
+            Err(e) => ("FAILED", Some(e.clone())),
+        };
+
+        conn.execute(
+            "INSERT INTO update_history (from_version, to_version, channel, backup_path, status, detail)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![from_version, to_version, channel, backup_path, status, detail],
+        )
+        .map_err(|e| e.to_string())?;
+        let record = load_record(&conn, conn.last_insert_rowid())?;
+
+        if status == "FAILED" {
+            emit(&app, "FAILED", Some(&to_version), detail.clone());
+            return Err(detail.unwrap_or_default());
+        }
+        emit(&app, "BACKED_UP", Some(&to_version), Some(backup_path));
+        Ok(record)
+    })
+}
+
+// Schema re-application and integrity checks after the version changed
+fn verify_database(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(db::SCHEMA)
+        .map_err(|e| format!("Schema migration failed: {}", e))?;
+    migrations::run(conn)?;
+
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if integrity != "ok" {
+        return Err(format!("Integrity check failed: {}", integrity));
+    }
+    let broken_keys: i64 = conn
+        .query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    if broken_keys > 0 {
+        return Err(format!("{} rows fail foreign key checks", broken_keys));
+    }
+    Ok(())
+}
+
+// Called on startup. When the installed version changed since the last run,
+// migrate and check the database, and record the outcome against the update.
+pub fn verify_after_update(app: &AppHandle) -> Result<(), String> {
+    let db_path = crate::get_db_path(app)?;
+    if !db_path.exists() {
+        // Fresh install, init_database creates the schema
+        return Ok(());
+    }
+    let conn =
+        encryption::open_file(app, &db_path, OpenFlags::default()).map_err(|e| e.to_string())?;
+    conn.execute_batch(db::SCHEMA).map_err(|e| e.to_string())?;
+
+    let version = current_version(app);
+    let previous = db::get_config(&conn, INSTALLED_VERSION_KEY)?;
+    if previous.as_deref() == Some(version.as_str()) {
+        return Ok(());
+    }
+
+    let result = verify_database(&conn);
+    let (status, detail) = match &result {
+        Ok(()) => ("VERIFIED", None),
+        Err(e) => ("FAILED", Some(e.clone())),
+    };
+    let pending: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM update_history WHERE to_version = ?1 AND status = 'PREPARED'
+             ORDER BY id DESC LIMIT 1",
+            [&version],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(id) = pending {
+        conn.execute(
+            "UPDATE update_history SET status = ?2, detail = ?3, verified_at = CURRENT_TIMESTAMP
+             WHERE id = ?1",
+            params![id, status, detail],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    match result {
+        Ok(()) => {
+            db::set_config(&conn, INSTALLED_VERSION_KEY, &version)?;
+            emit(app, "VERIFIED", Some(&version), None);
+        }
+        Err(e) => {
+            // Version stays unrecorded so the check runs again on the next start
+            notifications::notify(
+                app,
+                &conn,
+                "admin",
+                &format!("Update to {} needs attention", version),
+                &format!(
+                    "{}. Restore the pre-update backup if the problem persists.",
+                    e
+                ),
+                None,
+            )?;
+            emit(app, "FAILED", Some(&version), Some(e));
+        }
+    }
+    Ok(())
+}
+
+fn load_record(conn: &Connection, id: i64) -> Result<UpdateRecord, String> {
+    conn.query_row(
+        "SELECT id, from_version, to_version, channel, backup_path, status, detail,
+                created_at, verified_at
+         FROM update_history WHERE id = ?1",
+        [id],
+        row_to_record,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn row_to_record(row: &rusqlite::Row) -> rusqlite::Result<UpdateRecord> {
+    Ok(UpdateRecord {
+        id: row.get(0)?,
+        from_version: row.get(1)?,
+        to_version: row.get(2)?,
+        channel: row.get(3)?,
+        backup_path: row.get(4)?,
+        status: row.get(5)?,
+        detail: row.get(6)?,
+        created_at: row.get(7)?,
+        verified_at: row.get(8)?,
+    })
+}
+
+// Update attempts, newest first
+#[tauri::command]
+pub fn list_update_history(app: AppHandle) -> Result<Vec<UpdateRecord>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, from_version, to_version, channel, backup_path, status, detail,
+                    created_at, verified_at
+             FROM update_history ORDER BY id DESC LIMIT 100",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_record)
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,470 @@
+// Chunked, resumable uploads for Truckore Pro
+// Backups and attachments go to the cloud over connections that drop
+// mid-upload. A file is split into chunks, each with its own SHA-256, and
+// the sync loop (sync_engine.rs) sends them one at a time, acknowledging
+// each with the hash the server received. Progress lives in
+// upload_sessions, so after a failure or a restart only the missing chunks
+// are sent again. Attachments referenced by pushed tickets are queued here
+// by the sync loop itself.
+
+use crate::attachments;
+use crate::bandwidth;
+use crate::db;
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri::AppHandle;
+
+const DEFAULT_CHUNK_SIZE: i64 = 1024 * 1024;
+const MIN_CHUNK_SIZE: i64 = 64 * 1024;
+const MAX_CHUNK_SIZE: i64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    // Path or attachment reference the upload was started with
+    pub source: String,
+    pub size_bytes: i64,
+    pub sha256: String,
+    pub chunk_size: i64,
+    pub chunk_count: i64,
+    // PENDING, COMPLETE or ABANDONED
+    pub status: String,
+    pub remote_id: Option<String>,
+    pub failures: i64,
+    pub last_error: Option<String>,
+    // Chunks not yet acknowledged, in order
+    pub pending_chunks: Vec<i64>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadChunk {
+    pub index: i64,
+    pub offset: i64,
+    pub size: i64,
+    pub sha256: String,
+    // Base64
+    pub data: String,
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn resolve(app: &AppHandle, source: &str) -> Result<PathBuf, String> {
+    if source.starts_with(attachments::REF_PREFIX) {
+        attachments::path_of(app, source)
+    } else {
+        Ok(PathBuf::from(source))
+    }
+}
+
+// (size, modification time in Unix seconds)
+fn identity(path: &Path) -> Result<(i64, i64), String> {
+    let metadata = std::fs::metadata(path).map_err(|e| e.to_string())?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64);
+    Ok((metadata.len() as i64, modified))
+}
+
+fn load_session(conn: &Connection, session_id: &str) -> Result<UploadSession, String> {
+    let mut session = conn
+        .query_row(
+            "SELECT id, source, size_bytes, sha256, chunk_size, chunk_count, status,
+                    remote_id, failures, last_error, created_at, completed_at
+             FROM upload_sessions WHERE id = ?1",
+            params![session_id],
+            |row| {
+                Ok(UploadSession {
+                    id: row.get(0)?,
+                    source: row.get(1)?,
+                    size_bytes: row.get(2)?,
+                    sha256: row.get(3)?,
+                    chunk_size: row.get(4)?,
+                    chunk_count: row.get(5)?,
+                    status: row.get(6)?,
+                    remote_id: row.get(7)?,
+                    failures: row.get(8)?,
+                    last_error: row.get(9)?,
+                    pending_chunks: Vec::new(),
+                    created_at: row.get(10)?,
+                    completed_at: row.get(11)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Upload {} not found", session_id))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT chunk_index FROM upload_chunks
+             WHERE session_id = ?1 AND uploaded_at IS NULL ORDER BY chunk_index",
+        )
+        .map_err(|e| e.to_string())?;
+    let pending = stmt
+        .query_map(params![session_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    session.pending_chunks = pending
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(session)
+}
+
+fn pending_session(conn: &Connection, session_id: &str) -> Result<UploadSession, String> {
+    let session = load_session(conn, session_id)?;
+    if session.status != "PENDING" {
+        return Err(format!(
+            "Upload {} is {}",
+            session_id,
+            session.status.to_lowercase()
+        ));
+    }
+    Ok(session)
+}
+
+pub fn record_failure(conn: &Connection, session_id: &str, error: &str) -> Result<(), String> {
+    conn.execute(
+        "UPDATE upload_sessions SET failures = failures + 1, last_error = ?2 WHERE id = ?1",
+        params![session_id, error],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Hash the file chunk by chunk and record the new session
+fn create_session(
+    conn: &Connection,
+    source: &str,
+    path: &Path,
+    chunk_size: i64,
+) -> Result<String, String> {
+    let (size, modified) = identity(path)?;
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut whole = Sha256::new();
+    let mut chunks = Vec::new();
+    let mut buffer = vec![0; chunk_size as usize];
+    let mut offset = 0i64;
+    loop {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let read = file
+                .read(&mut buffer[filled..])
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        if filled == 0 {
+            break;
+        }
+        let bytes = &buffer[..filled];
+        whole.update(bytes);
+        chunks.push((offset, filled as i64, hex(&Sha256::digest(bytes))));
+        offset += filled as i64;
+    }
+    if offset != size {
+        return Err("The file changed while it was being read".to_string());
+    }
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO upload_sessions
+             (id, source, path, size_bytes, modified_at, sha256, chunk_size, chunk_count)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            session_id,
+            source,
+            path.to_string_lossy(),
+            size,
+            modified,
+            hex(&whole.finalize()),
+            chunk_size,
+            chunks.len() as i64
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    for (index, (offset, size, sha256)) in chunks.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO upload_chunks (session_id, chunk_index, offset_bytes, size_bytes, sha256)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![session_id, index as i64, offset, size, sha256],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(session_id)
+}
+
+// Start uploading a file path or an attachment reference. An unfinished
+// upload of the same, unchanged file is resumed; if the file has changed
+// since, that upload is abandoned and a new one started.
+pub fn start(
+    app: &AppHandle,
+    conn: &Connection,
+    source: &str,
+    chunk_size: Option<i64>,
+) -> Result<UploadSession, String> {
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+    if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(format!(
+            "Chunk size must be between {} and {} bytes",
+            MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+        ));
+    }
+    let path = resolve(app, source)?;
+    let (size, modified) = identity(&path)?;
+
+    let existing: Option<(String, i64, i64)> = conn
+        .query_row(
+            "SELECT id, size_bytes, modified_at FROM upload_sessions
+             WHERE source = ?1 AND status = 'PENDING'
+             ORDER BY created_at DESC LIMIT 1",
+            params![source],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some((session_id, old_size, old_modified)) = existing {
+        if (old_size, old_modified) == (size, modified) {
+            return load_session(conn, &session_id);
+        }
+        conn.execute(
+            "UPDATE upload_sessions SET status = 'ABANDONED', last_error = 'File changed'
+             WHERE id = ?1",
+            params![session_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let session_id = create_session(conn, source, &path, chunk_size)?;
+    load_session(conn, &session_id)
+}
+
+#[tauri::command]
+pub fn start_upload(
+    app: AppHandle,
+    source: String,
+    chunk_size: Option<i64>,
+) -> Result<UploadSession, String> {
+    let conn = db::open(&app)?;
+    start(&app, &conn, &source, chunk_size)
+}
+
+// Queue an attachment for upload unless it is on its way or already sent.
+// Attachments are content-addressed, so a sent one never changes.
+pub fn queue_attachment(app: &AppHandle, conn: &Connection, reference: &str) -> Result<(), String> {
+    let queued: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM upload_sessions
+                           WHERE source = ?1 AND status IN ('PENDING', 'COMPLETE'))",
+            params![reference],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if !queued {
+        start(app, conn, reference, None)?;
+    }
+    Ok(())
+}
+
+// Chunk bytes to send. Checked against the hash taken when the upload
+// started; a mismatch means the file changed and the upload is abandoned.
+pub fn read_chunk(
+    app: &AppHandle,
+    conn: &Connection,
+    session_id: &str,
+    chunk_index: i64,
+) -> Result<UploadChunk, String> {
+    pending_session(conn, session_id)?;
+    let path: String = conn
+        .query_row(
+            "SELECT path FROM upload_sessions WHERE id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let (offset, size, sha256): (i64, i64, String) = conn
+        .query_row(
+            "SELECT offset_bytes, size_bytes, sha256 FROM upload_chunks
+             WHERE session_id = ?1 AND chunk_index = ?2",
+            params![session_id, chunk_index],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Upload {} has no chunk {}", session_id, chunk_index))?;
+    // Refused while the link is busy; the sync layer retries after the wait
+    bandwidth::reserve(app, conn, size as u64)?;
+
+    let mut bytes = vec![0; size as usize];
+    let read = File::open(&path)
+        .and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.read_exact(&mut bytes)
+        })
+        .map_err(|e| e.to_string());
+    if let Err(e) = read {
+        record_failure(conn, session_id, &e)?;
+        return Err(e);
+    }
+    if hex(&Sha256::digest(&bytes)) != sha256 {
+        conn.execute(
+            "UPDATE upload_sessions SET status = 'ABANDONED', last_error = 'File changed'
+             WHERE id = ?1",
+            params![session_id],
+        )
+        .map_err(|e| e.to_string())?;
+        return Err("The file changed since the upload started; start it again".to_string());
+    }
+    Ok(UploadChunk {
+        index: chunk_index,
+        offset,
+        size,
+        sha256,
+        data: general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+#[tauri::command]
+pub fn read_upload_chunk(
+    app: AppHandle,
+    session_id: String,
+    chunk_index: i64,
+) -> Result<UploadChunk, String> {
+    let conn = db::open(&app)?;
+    read_chunk(&app, &conn, &session_id, chunk_index)
+}
+
+// Called once the server has a chunk, with the SHA-256 the server computed.
+// A different hash leaves the chunk pending to be resent.
+pub fn ack_chunk(
+    conn: &Connection,
+    session_id: &str,
+    chunk_index: i64,
+    sha256: &str,
+) -> Result<UploadSession, String> {
+    pending_session(conn, session_id)?;
+    let expected: String = conn
+        .query_row(
+            "SELECT sha256 FROM upload_chunks WHERE session_id = ?1 AND chunk_index = ?2",
+            params![session_id, chunk_index],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Upload {} has no chunk {}", session_id, chunk_index))?;
+    if !sha256.trim().eq_ignore_ascii_case(&expected) {
+        let error = format!("Chunk {} arrived corrupted", chunk_index);
+        record_failure(conn, session_id, &error)?;
+        return Err(error);
+    }
+    conn.execute(
+        "UPDATE upload_chunks SET uploaded_at = COALESCE(uploaded_at, CURRENT_TIMESTAMP)
+         WHERE session_id = ?1 AND chunk_index = ?2",
+        params![session_id, chunk_index],
+    )
+    .map_err(|e| e.to_string())?;
+    load_session(conn, session_id)
+}
+
+#[tauri::command]
+pub fn ack_upload_chunk(
+    app: AppHandle,
+    session_id: String,
+    chunk_index: i64,
+    sha256: String,
+) -> Result<UploadSession, String> {
+    let conn = db::open(&app)?;
+    ack_chunk(&conn, &session_id, chunk_index, &sha256)
+}
+
+// Remember the server's id for the upload so a resumed upload continues the
+// same one
+pub fn set_remote_id(conn: &Connection, session_id: &str, remote_id: &str) -> Result<(), String> {
+    pending_session(conn, session_id)?;
+    conn.execute(
+        "UPDATE upload_sessions SET remote_id = ?2 WHERE id = ?1",
+        params![session_id, remote_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_upload_remote_id(
+    app: AppHandle,
+    session_id: String,
+    remote_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    set_remote_id(&conn, &session_id, &remote_id)
+}
+
+// A failed send, kept for diagnostics; the chunk stays pending
+#[tauri::command]
+pub fn report_upload_failure(
+    app: AppHandle,
+    session_id: String,
+    error: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    record_failure(&conn, &session_id, &error)
+}
+
+// Close an upload once every chunk is acknowledged. The whole-file SHA-256
+// in the result is what the server should hold after joining the chunks.
+pub fn complete(conn: &Connection, session_id: &str) -> Result<UploadSession, String> {
+    let session = pending_session(conn, session_id)?;
+    if !session.pending_chunks.is_empty() {
+        return Err(format!(
+            "{} of {} chunks have not been uploaded",
+            session.pending_chunks.len(),
+            session.chunk_count
+        ));
+    }
+    conn.execute(
+        "UPDATE upload_sessions SET status = 'COMPLETE', completed_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![session_id],
+    )
+    .map_err(|e| e.to_string())?;
+    load_session(conn, session_id)
+}
+
+#[tauri::command]
+pub fn complete_upload(app: AppHandle, session_id: String) -> Result<UploadSession, String> {
+    let conn = db::open(&app)?;
+    complete(&conn, &session_id)
+}
+
+// Unfinished uploads to resume, oldest first
+pub fn pending(conn: &Connection) -> Result<Vec<UploadSession>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id FROM upload_sessions WHERE status = 'PENDING' ORDER BY created_at")
+        .map_err(|e| e.to_string())?;
+    let ids = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    ids.iter().map(|id| load_session(conn, id)).collect()
+}
+
+#[tauri::command]
+pub fn pending_uploads(app: AppHandle) -> Result<Vec<UploadSession>, String> {
+    let conn = db::open(&app)?;
+    pending(&conn)
+}
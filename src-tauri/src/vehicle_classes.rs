@@ -0,0 +1,511 @@
+// Vehicle classes for Truckore Pro
+// A six-wheeler that weighs 3 t empty, or grosses 40 t, has been misread, is
+// on someone else's tare, or is overloaded. A vehicle class records a kind of
+// truck's permitted gross vehicle weight (GVW), its plausible tare range and
+// its axle count, entered in kilograms or tonnes; vehicles are assigned a
+// class by registration number. Captured weights are checked against the
+// class at both weighings. Validation is OFF, WARN (the weighment goes ahead
+// and the violation is flagged) or BLOCK (it needs a supervisor override,
+// overrides.rs). Vehicles without a class are not checked.
+
+use crate::db::{self, DateRange};
+use crate::overrides;
+use crate::roles::{self, Role};
+use crate::training;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+const SETTINGS_CONFIG_KEY: &str = "vehicle_class_validation";
+const ENFORCEMENTS: &[&str] = &["OFF", "WARN", "BLOCK"];
+const UNITS: &[&str] = &["KG", "TONNE"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValidationSettings {
+    // OFF, WARN or BLOCK
+    pub enforcement: String,
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        ValidationSettings {
+            enforcement: "OFF".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VehicleClass {
+    pub name: String,
+    // KG or TONNE; the weights below are in it
+    pub unit: String,
+    pub gvw: f64,
+    pub min_tare: Option<f64>,
+    pub max_tare: Option<f64>,
+    pub axle_count: Option<i64>,
+    pub description: Option<String>,
+    // Vehicles assigned to the class
+    #[serde(default, skip_deserializing)]
+    pub vehicles: i64,
+}
+
+impl VehicleClass {
+    fn kg(&self, value: f64) -> f64 {
+        match self.unit.as_str() {
+            "TONNE" => value * 1000.0,
+            _ => value,
+        }
+    }
+}
+
+// A weight that does not fit the vehicle's class
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassViolation {
+    pub class_name: String,
+    // TARE_BELOW_CLASS, TARE_ABOVE_CLASS or GROSS_ABOVE_GVW
+    pub reason: String,
+    pub weight_kg: f64,
+    pub limit_kg: f64,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedViolation {
+    pub weighment_id: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    // FIRST_WEIGHT or SECOND_WEIGHT
+    pub event: String,
+    pub class_name: String,
+    pub reason: String,
+    pub weight_kg: f64,
+    pub limit_kg: f64,
+    // FLAGGED or OVERRIDDEN
+    pub outcome: String,
+    pub override_id: Option<i64>,
+    pub occurred_at: String,
+}
+
+pub fn load_settings(conn: &Connection) -> Result<ValidationSettings, String> {
+    match db::get_config(conn, SETTINGS_CONFIG_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(ValidationSettings::default()),
+    }
+}
+
+const CLASS_SELECT: &str = "SELECT c.name, c.unit, c.gvw, c.min_tare, c.max_tare, c.axle_count,
+            c.description,
+            (SELECT COUNT(*) FROM vehicle_class_members m WHERE m.class_name = c.name)
+     FROM vehicle_classes c";
+
+fn row_to_class(row: &rusqlite::Row) -> rusqlite::Result<VehicleClass> {
+    Ok(VehicleClass {
+        name: row.get(0)?,
+        unit: row.get(1)?,
+        gvw: row.get(2)?,
+        min_tare: row.get(3)?,
+        max_tare: row.get(4)?,
+        axle_count: row.get(5)?,
+        description: row.get(6)?,
+        vehicles: row.get(7)?,
+    })
+}
+
+pub fn class_of(conn: &Connection, vehicle_no: &str) -> Result<Option<VehicleClass>, String> {
+    conn.query_row(
+        &format!(
+            "{} JOIN vehicle_class_members v ON v.class_name = c.name
+             WHERE v.vehicle_no = ?1",
+            CLASS_SELECT
+        ),
+        [vehicle_no.trim()],
+        row_to_class,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// `gross` and `tare` (kg, either may be unknown yet) against `class`
+pub fn violations(
+    class: &VehicleClass,
+    gross: Option<f64>,
+    tare: Option<f64>,
+) -> Vec<ClassViolation> {
+    let violation = |reason: &str, weight_kg: f64, limit_kg: f64, what: &str| ClassViolation {
+        class_name: class.name.clone(),
+        reason: reason.to_string(),
+        weight_kg,
+        limit_kg,
+        detail: format!(
+            "{} {:.0} kg is {} for a {} ({:.0} kg)",
+            if reason == "GROSS_ABOVE_GVW" {
+                "Gross"
+            } else {
+                "Tare"
+            },
+            weight_kg,
+            what,
+            class.name,
+            limit_kg
+        ),
+    };
+    let mut found = Vec::new();
+    if let Some(tare) = tare {
+        if let Some(min) = class
+            .min_tare
+            .map(|t| class.kg(t))
+            .filter(|min| tare < *min)
+        {
+            found.push(violation(
+                "TARE_BELOW_CLASS",
+                tare,
+                min,
+                "below the lowest tare",
+            ));
+        }
+        if let Some(max) = class
+            .max_tare
+            .map(|t| class.kg(t))
+            .filter(|max| tare > *max)
+        {
+            found.push(violation(
+                "TARE_ABOVE_CLASS",
+                tare,
+                max,
+                "above the highest tare",
+            ));
+        }
+    }
+    let gvw = class.kg(class.gvw);
+    if let Some(gross) = gross.filter(|g| *g > gvw) {
+        found.push(violation("GROSS_ABOVE_GVW", gross, gvw, "above the GVW"));
+    }
+    found
+}
+
+// True when weighing `vehicle_no` may need a vehicle class override: BLOCK
+// is on and the vehicle has a class. An override granted on the strength of
+// this stays unused when the weights turn out to fit.
+pub fn may_block(conn: &Connection, vehicle_no: &str) -> Result<bool, String> {
+    Ok(load_settings(conn)?.enforcement == "BLOCK" && class_of(conn, vehicle_no)?.is_some())
+}
+
+// Check a weighing of a ticket against its vehicle's class. Violations are
+// recorded and returned; BLOCK refuses unless `override_id` carries a granted
+// override. Practice tickets are left alone.
+pub fn check(
+    conn: &Connection,
+    weighment_id: &str,
+    event: &str,
+    gross: Option<f64>,
+    tare: Option<f64>,
+    override_id: Option<i64>,
+) -> Result<Vec<ClassViolation>, String> {
+    let settings = load_settings(conn)?;
+    if settings.enforcement == "OFF" || training::is_practice(conn, weighment_id)? {
+        return Ok(Vec::new());
+    }
+    let vehicle_no: String = conn
+        .query_row(
+            "SELECT vehicle_no FROM weighments WHERE id = ?1",
+            [weighment_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let Some(class) = class_of(conn, &vehicle_no)? else {
+        return Ok(Vec::new());
+    };
+    // A weight already let through at the first weighing is not asked about
+    // again at the second
+    let mut known = conn
+        .prepare(
+            "SELECT EXISTS(SELECT 1 FROM vehicle_class_violations
+                           WHERE weighment_id = ?1 AND event <> ?2 AND reason = ?3
+                             AND weight_kg = ?4)",
+        )
+        .map_err(|e| e.to_string())?;
+    let mut found = Vec::new();
+    for violation in violations(&class, gross, tare) {
+        let seen: bool = known
+            .query_row(
+                params![weighment_id, event, violation.reason, violation.weight_kg],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !seen {
+            found.push(violation);
+        }
+    }
+    if found.is_empty() {
+        return Ok(found);
+    }
+
+    let outcome = match (settings.enforcement.as_str(), override_id) {
+        ("BLOCK", None) => {
+            let details: Vec<&str> = found.iter().map(|v| v.detail.as_str()).collect();
+            return Err(format!(
+                "{}. A supervisor override is required.",
+                details.join("; ")
+            ));
+        }
+        ("BLOCK", Some(_)) => "OVERRIDDEN",
+        _ => "FLAGGED",
+    };
+    let override_id = override_id.filter(|_| outcome == "OVERRIDDEN");
+    for violation in &found {
+        conn.execute(
+            "INSERT OR REPLACE INTO vehicle_class_violations
+                 (weighment_id, event, class_name, reason, weight_kg, limit_kg, outcome,
+                  override_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                weighment_id,
+                event,
+                violation.class_name,
+                violation.reason,
+                violation.weight_kg,
+                violation.limit_kg,
+                outcome,
+                override_id
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    if let Some(id) = override_id {
+        overrides::mark_used(conn, id)?;
+    }
+    Ok(found)
+}
+
+#[tauri::command]
+pub fn get_vehicle_class_validation(app: AppHandle) -> Result<ValidationSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+#[tauri::command]
+pub fn set_vehicle_class_validation(
+    app: AppHandle,
+    settings: ValidationSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let mut settings = settings;
+    settings.enforcement = settings.enforcement.trim().to_ascii_uppercase();
+    if !ENFORCEMENTS.contains(&settings.enforcement.as_str()) {
+        return Err(format!(
+            "Unknown enforcement {} (OFF, WARN or BLOCK)",
+            settings.enforcement
+        ));
+    }
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    db::set_config(&conn, SETTINGS_CONFIG_KEY, &json)
+}
+
+#[tauri::command]
+pub fn list_vehicle_classes(app: AppHandle) -> Result<Vec<VehicleClass>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!("{} ORDER BY c.name", CLASS_SELECT))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_class)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Add a class, or replace the one with that name
+#[tauri::command]
+pub fn set_vehicle_class(
+    app: AppHandle,
+    class: VehicleClass,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let name = class.name.trim();
+    if name.is_empty() {
+        return Err("A vehicle class needs a name".to_string());
+    }
+    let unit = class.unit.trim().to_ascii_uppercase();
+    if !UNITS.contains(&unit.as_str()) {
+        return Err(format!("Unknown weight unit {} (KG or TONNE)", class.unit));
+    }
+    if class.gvw <= 0.0 {
+        return Err("The GVW must be above zero".to_string());
+    }
+    if let Some(tare) = [class.min_tare, class.max_tare]
+        .into_iter()
+        .flatten()
+        .find(|t| *t < 0.0 || *t >= class.gvw)
+    {
+        return Err(format!(
+            "A tare of {} is not below the GVW of {}",
+            tare, class.gvw
+        ));
+    }
+    if let (Some(min), Some(max)) = (class.min_tare, class.max_tare) {
+        if min > max {
+            return Err("The lowest tare is above the highest".to_string());
+        }
+    }
+    if class.axle_count.is_some_and(|a| a < 1) {
+        return Err("A vehicle has at least one axle".to_string());
+    }
+    conn.execute(
+        "INSERT INTO vehicle_classes
+             (name, unit, gvw, min_tare, max_tare, axle_count, description, updated_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(name) DO UPDATE SET unit = excluded.unit, gvw = excluded.gvw,
+             min_tare = excluded.min_tare, max_tare = excluded.max_tare,
+             axle_count = excluded.axle_count, description = excluded.description,
+             updated_by = excluded.updated_by, updated_at = CURRENT_TIMESTAMP",
+        params![
+            name,
+            unit,
+            class.gvw,
+            class.min_tare,
+            class.max_tare,
+            class.axle_count,
+            class.description,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Classes still assigned to vehicles are kept
+#[tauri::command]
+pub fn delete_vehicle_class(app: AppHandle, name: String, user_id: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let members: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM vehicle_class_members WHERE class_name = ?1",
+            [&name],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if members > 0 {
+        return Err(format!(
+            "{} vehicle(s) are in class {}; assign them elsewhere first",
+            members, name
+        ));
+    }
+    match conn
+        .execute("DELETE FROM vehicle_classes WHERE name = ?1", [&name])
+        .map_err(|e| e.to_string())?
+    {
+        0 => Err(format!("Vehicle class {} not found", name)),
+        _ => Ok(()),
+    }
+}
+
+// Put a vehicle in a class, or take it out of its class with None
+#[tauri::command]
+pub fn assign_vehicle_class(
+    app: AppHandle,
+    vehicle_no: String,
+    class_name: Option<String>,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let vehicle_no = vehicle_no.trim();
+    if vehicle_no.is_empty() {
+        return Err("A vehicle number is required".to_string());
+    }
+    let Some(class_name) = class_name else {
+        conn.execute(
+            "DELETE FROM vehicle_class_members WHERE vehicle_no = ?1",
+            [vehicle_no],
+        )
+        .map_err(|e| e.to_string())?;
+        return Ok(());
+    };
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM vehicle_classes WHERE name = ?1)",
+            [&class_name],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if !exists {
+        return Err(format!("Vehicle class {} not found", class_name));
+    }
+    conn.execute(
+        "INSERT INTO vehicle_class_members (vehicle_no, class_name, assigned_by)
+         VALUES (?1, ?2, ?3)
+         ON CONFLICT(vehicle_no) DO UPDATE SET class_name = excluded.class_name,
+             assigned_by = excluded.assigned_by, assigned_at = CURRENT_TIMESTAMP",
+        params![vehicle_no, class_name, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_vehicle_class(
+    app: AppHandle,
+    vehicle_no: String,
+) -> Result<Option<VehicleClass>, String> {
+    let conn = db::open(&app)?;
+    class_of(&conn, &vehicle_no)
+}
+
+// What the weighing screen should warn about before saving, whatever the
+// enforcement
+#[tauri::command]
+pub fn check_vehicle_weights(
+    app: AppHandle,
+    vehicle_no: String,
+    gross_weight: Option<f64>,
+    tare_weight: Option<f64>,
+) -> Result<Vec<ClassViolation>, String> {
+    let conn = db::open(&app)?;
+    Ok(class_of(&conn, &vehicle_no)?
+        .map(|class| violations(&class, gross_weight, tare_weight))
+        .unwrap_or_default())
+}
+
+// Recorded violations in the range, oldest first; voided tickets included
+#[tauri::command]
+pub fn list_vehicle_class_violations(
+    app: AppHandle,
+    range: DateRange,
+) -> Result<Vec<RecordedViolation>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT v.weighment_id, w.ticket_no, w.vehicle_no, v.event, v.class_name, v.reason,
+                    v.weight_kg, v.limit_kg, v.outcome, v.override_id,
+                    datetime(v.occurred_at, 'localtime')
+             FROM vehicle_class_violations v JOIN weighments w ON w.id = v.weighment_id
+             WHERE {} BETWEEN ?1 AND ?2 ORDER BY v.occurred_at, v.id",
+            db::local_date("v.occurred_at")
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![range.from, range.to], |row| {
+            Ok(RecordedViolation {
+                weighment_id: row.get(0)?,
+                ticket_no: row.get(1)?,
+                vehicle_no: row.get(2)?,
+                event: row.get(3)?,
+                class_name: row.get(4)?,
+                reason: row.get(5)?,
+                weight_kg: row.get(6)?,
+                limit_kg: row.get(7)?,
+                outcome: row.get(8)?,
+                override_id: row.get(9)?,
+                occurred_at: row.get(10)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,203 @@
+// Ticket voiding for Truckore Pro
+// A voided ticket keeps its row and number; the void is recorded in ticket_voids.
+// Supervisors void directly, operators queue a request in the approval queue.
+
+use crate::approvals::{self, Approval, NewApproval};
+use crate::command_audit;
+use crate::db;
+use crate::roles::{self, Role};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+// Slip data for the "VOID" file copy
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VoidSlip {
+    pub ticket_id: String,
+    pub bill_no: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    pub party_name: String,
+    pub product_name: String,
+    pub gross_weight: Option<f64>,
+    pub tare_weight: Option<f64>,
+    pub net_weight: Option<f64>,
+    // Paise
+    pub charges_minor: i64,
+    pub created_at: String,
+    pub void_reason: String,
+    pub voided_by: String,
+    pub voided_at: String,
+    pub copy_label: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VoidResult {
+    // "VOIDED" or "PENDING_APPROVAL"
+    pub status: String,
+    pub approval_id: Option<i64>,
+    pub slip: Option<VoidSlip>,
+}
+
+pub fn is_voided(conn: &Connection, ticket_id: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT 1 FROM ticket_voids WHERE weighment_id = ?1",
+        [ticket_id],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|r| r.is_some())
+    .map_err(|e| e.to_string())
+}
+
+pub fn record_void(
+    conn: &Connection,
+    ticket_id: &str,
+    reason: &str,
+    voided_by: &str,
+    approval_id: Option<i64>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO ticket_voids (weighment_id, reason, voided_by, approval_id)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![ticket_id, reason, voided_by, approval_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn load_slip(conn: &Connection, ticket_id: &str) -> Result<VoidSlip, String> {
+    conn.query_row(
+        "SELECT w.id, w.bill_no, w.ticket_no, w.vehicle_no, w.party_name, w.product_name,
+                w.gross_weight, w.tare_weight, w.net_weight, w.charges_minor, w.created_at,
+                v.reason, v.voided_by, v.voided_at
+         FROM ticket_voids v JOIN weighments w ON w.id = v.weighment_id
+         WHERE v.weighment_id = ?1",
+        [ticket_id],
+        |row| {
+            Ok(VoidSlip {
+                ticket_id: row.get(0)?,
+                bill_no: row.get(1)?,
+                ticket_no: row.get(2)?,
+                vehicle_no: row.get(3)?,
+                party_name: row.get(4)?,
+                product_name: row.get(5)?,
+                gross_weight: row.get(6)?,
+                tare_weight: row.get(7)?,
+                net_weight: row.get(8)?,
+                charges_minor: row.get(9)?,
+                created_at: row.get(10)?,
+                void_reason: row.get(11)?,
+                voided_by: row.get(12)?,
+                voided_at: row.get(13)?,
+                copy_label: "VOID".to_string(),
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Ticket {} is not voided", ticket_id))
+}
+
+// Approval handler for operator-requested voids
+pub fn apply_decision(
+    conn: &Connection,
+    approval: &Approval,
+    approved: bool,
+) -> Result<(), String> {
+    if !approved {
+        return Ok(());
+    }
+    let reason = approval
+        .payload
+        .as_deref()
+        .and_then(|p| serde_json::from_str::<serde_json::Value>(p).ok())
+        .and_then(|p| p["reason"].as_str().map(str::to_string))
+        .unwrap_or_else(|| approval.summary.clone());
+    let voided_by = approval
+        .decided_by
+        .as_deref()
+        .unwrap_or(&approval.requested_by);
+    record_void(
+        conn,
+        &approval.entity_id,
+        &reason,
+        voided_by,
+        Some(approval.id),
+    )
+}
+
+// Void a ticket with a mandatory reason. Supervisors void immediately and get
+// the VOID slip back; other users queue a supervisor approval.
+#[tauri::command]
+pub fn void_ticket(
+    app: AppHandle,
+    ticket_id: String,
+    reason: String,
+    user_id: String,
+) -> Result<VoidResult, String> {
+    command_audit::audited(
+        &app,
+        "void_ticket",
+        &user_id,
+        serde_json::json!({ "ticket_id": ticket_id, "reason": reason }),
+        || {
+            if reason.trim().is_empty() {
+                return Err("A reason is required to void a ticket".to_string());
+            }
+            let mut conn = db::open(&app)?;
+            let role = roles::user_role(&conn, &user_id)?;
+
+            let ticket_no: String = conn
+                .query_row(
+                    "SELECT ticket_no FROM weighments WHERE id = ?1",
+                    [&ticket_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Ticket {} not found", ticket_id))?;
+            if is_voided(&conn, &ticket_id)? {
+                return Err(format!("Ticket {} is already voided", ticket_no));
+            }
+
+            if role >= Role::Admin {
+                record_void(&conn, &ticket_id, &reason, &user_id, None)?;
+                return Ok(VoidResult {
+                    status: "VOIDED".to_string(),
+                    approval_id: None,
+                    slip: Some(load_slip(&conn, &ticket_id)?),
+                });
+            }
+
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            let approval = approvals::submit(
+                &tx,
+                NewApproval {
+                    kind: "void",
+                    entity: "weighment",
+                    entity_id: &ticket_id,
+                    summary: &format!("Void ticket {}: {}", ticket_no, reason),
+                    payload: Some(serde_json::json!({ "reason": reason })),
+                    approver_role: Role::Admin,
+                    requested_by: &user_id,
+                },
+            )?;
+            tx.commit().map_err(|e| e.to_string())?;
+            approvals::announce(&app, &conn, &approval)?;
+
+            Ok(VoidResult {
+                status: "PENDING_APPROVAL".to_string(),
+                approval_id: Some(approval.id),
+                slip: None,
+            })
+        },
+    )
+}
+
+// Slip data for reprinting the VOID copy of a voided ticket
+#[tauri::command]
+pub fn get_void_slip(app: AppHandle, ticket_id: String) -> Result<VoidSlip, String> {
+    let conn = db::open(&app)?;
+    load_slip(&conn, &ticket_id)
+}
@@ -0,0 +1,219 @@
+// Snapshot watermarking for Truckore Pro
+// When enabled, camera snapshots get a band along the bottom edge with the
+// site name, ticket number, time and weight burned into the pixels, so a
+// photo copied out of the system still says where and when it was taken.
+// Text is drawn with a built-in 5x7 bitmap font (upper case, digits and
+// common punctuation).
+
+use crate::attachments;
+use crate::db;
+use crate::roles::{self, Role};
+use base64::{engine::general_purpose, Engine as _};
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageFormat, Rgb, RgbImage};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tauri::AppHandle;
+
+const SETTINGS_KEY: &str = "snapshot_watermark";
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatermarkSettings {
+    pub enabled: bool,
+    pub site_name: String,
+}
+
+pub fn load_settings(conn: &Connection) -> Result<WatermarkSettings, String> {
+    match db::get_config(conn, SETTINGS_KEY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        None => Ok(WatermarkSettings::default()),
+    }
+}
+
+// Rows of a glyph, leftmost pixel in bit 4
+pub fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        ' ' => [0x00; 7],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+// Top-left corner at (x, y); pixels past the edge are dropped
+pub fn draw_text(img: &mut RgbImage, text: &str, x: u32, y: u32, scale: u32, colour: Rgb<u8>) {
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as u32 * advance;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (left + col * scale + dx, y + row as u32 * scale + dy);
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, colour);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Darken a band along the bottom edge and write one line of text per entry
+fn burn(img: &mut RgbImage, lines: &[String]) {
+    let scale = (img.width() / 320).max(1);
+    let line_height = (GLYPH_HEIGHT + 3) * scale;
+    let band = line_height * lines.len() as u32 + 3 * scale;
+    let top = img.height().saturating_sub(band);
+    for y in top..img.height() {
+        for x in 0..img.width() {
+            let Rgb([r, g, b]) = *img.get_pixel(x, y);
+            img.put_pixel(x, y, Rgb([r / 3, g / 3, b / 3]));
+        }
+    }
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            img,
+            line,
+            3 * scale,
+            top + 3 * scale + i as u32 * line_height,
+            scale,
+            Rgb([255, 255, 255]),
+        );
+    }
+}
+
+// Burn watermark lines into a base64 data URL image; JPEG stays JPEG
+pub fn apply(data_url: &str, lines: &[String]) -> Result<String, String> {
+    let data = attachments::parse_data_url(data_url).ok_or("Snapshot is not a base64 data URL")?;
+    let mut img = image::load_from_memory(&data.bytes)
+        .map_err(|e| e.to_string())?
+        .to_rgb8();
+    burn(&mut img, lines);
+
+    let mut out = Vec::new();
+    let mime = if data.mime == "image/jpeg" {
+        JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY)
+            .encode_image(&img)
+            .map_err(|e| e.to_string())?;
+        "image/jpeg"
+    } else {
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+        "image/png"
+    };
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        general_purpose::STANDARD.encode(out)
+    ))
+}
+
+// Watermark a snapshot for a ticket when watermarking is enabled; otherwise
+// the snapshot is returned as is
+pub fn snapshot(
+    conn: &Connection,
+    image: Option<String>,
+    ticket_no: &str,
+    weight: Option<f64>,
+) -> Result<Option<String>, String> {
+    let image = match image {
+        Some(i) if i.starts_with("data:") => i,
+        other => return Ok(other),
+    };
+    let settings = load_settings(conn)?;
+    if !settings.enabled {
+        return Ok(Some(image));
+    }
+    let taken_at: String = conn
+        .query_row("SELECT datetime('now', 'localtime')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let mut lines = Vec::new();
+    if !settings.site_name.is_empty() {
+        lines.push(settings.site_name.clone());
+    }
+    lines.push(format!("TICKET {}  {}", ticket_no, taken_at));
+    if let Some(weight) = weight {
+        lines.push(format!("{:.0} KG", weight));
+    }
+    apply(&image, &lines).map(Some)
+}
+
+// Watermark a snapshot saved outside create_weighment (e.g. an open ticket's camera image)
+#[tauri::command]
+pub fn watermark_snapshot(
+    app: AppHandle,
+    image: String,
+    ticket_no: String,
+    weight: Option<f64>,
+) -> Result<String, String> {
+    let conn = db::open(&app)?;
+    Ok(snapshot(&conn, Some(image.clone()), &ticket_no, weight)?.unwrap_or(image))
+}
+
+#[tauri::command]
+pub fn get_watermark_settings(app: AppHandle) -> Result<WatermarkSettings, String> {
+    let conn = db::open(&app)?;
+    load_settings(&conn)
+}
+
+#[tauri::command]
+pub fn set_watermark_settings(
+    app: AppHandle,
+    settings: WatermarkSettings,
+    user_id: String,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    roles::require_role(&conn, &user_id, Role::Admin)?;
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    db::set_config(&conn, SETTINGS_KEY, &json)
+}
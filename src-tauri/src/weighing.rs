@@ -0,0 +1,868 @@
+// Weight capture and weighment completion for Truckore Pro
+// Capture criteria depend on the material: a liquid tanker needs a longer
+// settle time than a scrap load, and some materials must always be weighed
+// twice. Rules live in material_capture_rules; materials without a row use
+// the defaults below. A captured weight must also pass the
+// partial-positioning check (positioning.rs).
+
+use crate::attachments;
+use crate::calendar::{self, OutOfHoursActivity};
+use crate::cameras::{self, CameraSnapshot};
+use crate::credit::{self, CreditBreach};
+use crate::currency::{self, WeighmentBilling};
+use crate::db;
+use crate::deductions::{self, NetAdjustment};
+use crate::fraud;
+use crate::idempotency;
+use crate::inventory;
+use crate::money;
+use crate::movements;
+use crate::notifications;
+use crate::overrides::{self, SupervisorOverride};
+use crate::period_lock;
+use crate::positioning;
+use crate::purchase_orders::{self, PoConsumption};
+use crate::rounding;
+use crate::scale::{self, ScaleConfig};
+use crate::scale_listener::ReadingFeed;
+use crate::scripting;
+use crate::serial_numbers;
+use crate::shutdown;
+use crate::signatures;
+use crate::slip_layout::{self, SlipLayout};
+use crate::tariffs;
+use crate::ticket_parties;
+use crate::training;
+use crate::vehicle_classes::{self, ClassViolation};
+use crate::voids;
+use crate::watermark;
+use crate::weighing_steps;
+use crate::weight_history;
+use crate::workflow_journal;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const DEFAULT_CAPTURE_TIMEOUT_SECONDS: f64 = 15.0;
+// Readings within this band count as steady for indicators without a stability flag
+const STEADY_BAND_KG: f64 = 10.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRule {
+    pub product_name: String,
+    pub min_weight_kg: f64,
+    // Weight must stay stable this long before it is captured
+    pub stability_seconds: f64,
+    // Stored-tare (one-time) tickets are refused for this material
+    pub second_weighing_required: bool,
+}
+
+impl CaptureRule {
+    fn default_for(product_name: &str) -> Self {
+        CaptureRule {
+            product_name: product_name.to_string(),
+            min_weight_kg: 0.0,
+            stability_seconds: 2.0,
+            second_weighing_required: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedWeight {
+    pub weight_kg: f64,
+    pub stable_for_ms: u64,
+    pub rule: CaptureRule,
+}
+
+// First weighing of a new ticket; mirrors the frontend Bill type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewWeighment {
+    pub id: Option<String>,
+    pub bill_no: String,
+    pub ticket_no: String,
+    pub vehicle_no: String,
+    pub party_name: String,
+    pub product_name: String,
+    pub gross_weight: Option<f64>,
+    pub tare_weight: Option<f64>,
+    // Paise
+    #[serde(default)]
+    pub charges_minor: i64,
+    pub front_image: Option<String>,
+    pub rear_image: Option<String>,
+    // "gross", "tare" or "one-time"
+    pub first_weight_type: String,
+    pub first_vehicle_status: Option<String>,
+    pub remarks: Option<String>,
+    // Lane camera snapshots from the weight capture
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snapshots: Vec<CameraSnapshot>,
+    // Where the goods come from and go to, when not the billed party
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consignor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consignee: Option<String>,
+    // Token from reserve_ticket_number when ticket_no was reserved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reservation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedWeighment {
+    pub weighment_id: String,
+    pub bill_no: String,
+    pub ticket_no: String,
+    // True when this call was a retry answered from the idempotency key
+    pub replayed: bool,
+    // Set when the first weighing was outside the working calendar
+    pub out_of_hours: Option<OutOfHoursActivity>,
+    // Weights that did not fit the vehicle's class
+    pub class_violations: Vec<ClassViolation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletedWeighment {
+    pub weighment_id: String,
+    pub gross_weight: f64,
+    pub tare_weight: f64,
+    pub net_weight: f64,
+    // Contract deductions, when a rule applied
+    pub adjustment: Option<NetAdjustment>,
+    // Purchase order the ticket was booked against
+    pub purchase_order: Option<PoConsumption>,
+    pub billing: WeighmentBilling,
+    // "INBOUND" or "OUTBOUND"
+    pub direction: String,
+    pub fraud_rules_fired: Vec<String>,
+    // Set when the ticket took its party over the credit limit
+    pub credit_breach: Option<CreditBreach>,
+    // Set when the second weighing was outside the working calendar
+    pub out_of_hours: Option<OutOfHoursActivity>,
+    // Weights that did not fit the vehicle's class
+    pub class_violations: Vec<ClassViolation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuickWeighment {
+    pub bill_no: String,
+    pub ticket_no: String,
+    pub captured: CapturedWeight,
+    pub completed: CompletedWeighment,
+    // Ready for the print dialog
+    pub slip: SlipLayout,
+}
+
+pub fn rule_for(conn: &Connection, product_name: &str) -> Result<CaptureRule, String> {
+    let rule = conn
+        .query_row(
+            "SELECT product_name, min_weight_kg, stability_seconds, second_weighing_required
+             FROM material_capture_rules WHERE product_name = ?1",
+            [product_name],
+            row_to_rule,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(rule.unwrap_or_else(|| CaptureRule::default_for(product_name)))
+}
+
+fn row_to_rule(row: &rusqlite::Row) -> rusqlite::Result<CaptureRule> {
+    Ok(CaptureRule {
+        product_name: row.get(0)?,
+        min_weight_kg: row.get(1)?,
+        stability_seconds: row.get(2)?,
+        second_weighing_required: row.get(3)?,
+    })
+}
+
+fn check_min_weight(rule: &CaptureRule, label: &str, weight: f64) -> Result<(), String> {
+    if weight < rule.min_weight_kg {
+        return Err(format!(
+            "{} {:.0} kg is below the {:.0} kg minimum for {}",
+            label, weight, rule.min_weight_kg, rule.product_name
+        ));
+    }
+    Ok(())
+}
+
+// Wait for a reading that has stayed stable for the rule's duration and
+// meets its minimum weight, with `vehicle_no` fully on the deck
+pub fn capture(
+    app: &AppHandle,
+    config: &ScaleConfig,
+    rule: &CaptureRule,
+    vehicle_no: Option<&str>,
+    timeout: Duration,
+) -> Result<CapturedWeight, String> {
+    let mut feed = ReadingFeed::open(app, config)?;
+    let required = Duration::from_secs_f64(rule.stability_seconds.max(0.0));
+
+    // Start of the current steady stretch and its first weight
+    let mut steady: Option<(Instant, f64)> = None;
+    let mut captured = None;
+    let mut last_weight = None;
+    let mut curve = Vec::new();
+
+    let started = Instant::now();
+    let deadline = started + timeout;
+    // Reader stops early when the app is shutting down
+    while captured.is_none() && Instant::now() < deadline && !shutdown::requested() {
+        // Short read windows so a capture returns as soon as it qualifies
+        for reading in feed.read(Duration::from_millis(200))? {
+            last_weight = Some(reading.weight_kg);
+            if captured.is_none() {
+                curve.push(reading.weight_kg);
+            }
+            let stable = match (reading.stable, steady) {
+                (Some(flag), _) => flag,
+                (None, Some((_, base))) => (reading.weight_kg - base).abs() <= STEADY_BAND_KG,
+                (None, None) => true,
+            };
+            if !stable {
+                steady = None;
+                continue;
+            }
+            let (since, _) = *steady.get_or_insert((Instant::now(), reading.weight_kg));
+            let held = since.elapsed();
+            if held >= required && reading.weight_kg >= rule.min_weight_kg && captured.is_none() {
+                captured = Some(CapturedWeight {
+                    weight_kg: reading.weight_kg,
+                    stable_for_ms: held.as_millis() as u64,
+                    rule: rule.clone(),
+                });
+            }
+        }
+    }
+
+    if let Some(captured) = &captured {
+        // The listener's history also covers the approach before the capture began
+        let history = weight_history::recent(app, &config.endpoint(), positioning::CURVE_SECONDS);
+        let curve = if history.is_empty() { curve } else { history };
+        let conn = db::open(app)?;
+        positioning::check(&conn, vehicle_no, captured.weight_kg, &curve)?;
+    }
+    captured.ok_or_else(|| match last_weight {
+        Some(w) if w < rule.min_weight_kg => format!(
+            "Weight {:.0} kg is below the {:.0} kg minimum for {}",
+            w, rule.min_weight_kg, rule.product_name
+        ),
+        Some(_) => format!(
+            "Weight did not stay stable for {:.1} s within {:.0} s",
+            rule.stability_seconds,
+            timeout.as_secs_f64()
+        ),
+        None => "No readings received from the indicator".to_string(),
+    })
+}
+
+// Capture a stable weight from the indicator using the material's rule.
+// `vehicle_no`, when known, lets the weight be checked against its tare.
+#[tauri::command]
+pub async fn capture_weight(
+    app: AppHandle,
+    product_name: String,
+    vehicle_no: Option<String>,
+    timeout_seconds: Option<f64>,
+) -> Result<CapturedWeight, String> {
+    let (config, rule, rounding) = {
+        let conn = db::open(&app)?;
+        (
+            scale::load_config(&conn)?,
+            rule_for(&conn, &product_name)?,
+            rounding::load_rules(&conn)?,
+        )
+    };
+    let timeout = Duration::from_secs_f64(
+        timeout_seconds
+            .unwrap_or(DEFAULT_CAPTURE_TIMEOUT_SECONDS)
+            .clamp(1.0, 120.0),
+    );
+    let mut captured = tauri::async_runtime::spawn_blocking({
+        let app = app.clone();
+        let detail = serde_json::json!({ "product_name": product_name });
+        move || {
+            let vehicle = vehicle_no.as_deref();
+            workflow_journal::journalled(&app, "capture", vehicle, None, detail, || {
+                capture(&app, &config, &rule, vehicle, timeout)
+            })
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    captured.weight_kg = rounding.weight(captured.weight_kg);
+    let conn = db::open(&app)?;
+    scripting::call(
+        &conn,
+        scripting::WEIGHT_CAPTURED,
+        None,
+        &serde_json::json!({
+            "product_name": product_name,
+            "weight_kg": captured.weight_kg,
+            "stable_for_ms": captured.stable_for_ms,
+        }),
+    )?;
+    Ok(captured)
+}
+
+// Site rules may refuse a new ticket or derive its party, product, charges,
+// remarks, consignor and consignee
+fn apply_ticket_script(conn: &Connection, weighment: &mut NewWeighment) -> Result<(), String> {
+    let input = serde_json::json!({
+        "ticket_no": weighment.ticket_no,
+        "vehicle_no": weighment.vehicle_no,
+        "party_name": weighment.party_name,
+        "product_name": weighment.product_name,
+        "gross_weight": weighment.gross_weight,
+        "tare_weight": weighment.tare_weight,
+        "charges_minor": weighment.charges_minor,
+        "first_weight_type": weighment.first_weight_type,
+        "first_vehicle_status": weighment.first_vehicle_status,
+        "remarks": weighment.remarks,
+        "consignor": weighment.consignor,
+        "consignee": weighment.consignee,
+    });
+    let result = scripting::call(
+        conn,
+        scripting::TICKET_CREATE,
+        Some(&weighment.ticket_no),
+        &input,
+    )?;
+    let Value::Object(mut fields) = input else {
+        return Ok(());
+    };
+    scripting::merge(&mut fields, result);
+    let text = |key: &str| fields.get(key).and_then(Value::as_str).map(str::to_string);
+    if let Some(party_name) = text("party_name") {
+        weighment.party_name = party_name;
+    }
+    if let Some(product_name) = text("product_name") {
+        weighment.product_name = product_name;
+    }
+    if let Some(charges_minor) = fields.get("charges_minor").and_then(Value::as_i64) {
+        weighment.charges_minor = charges_minor;
+    }
+    weighment.remarks = text("remarks").or(weighment.remarks.take());
+    weighment.consignor = text("consignor").or(weighment.consignor.take());
+    weighment.consignee = text("consignee").or(weighment.consignee.take());
+    Ok(())
+}
+
+// Create an OPEN ticket from its first weighing. A retry carrying the same
+// `idempotency_key` returns the ticket created by the first call. Stored-tare
+// tickets are created with both weights and closed via complete_weighment.
+// Weighing outside the working calendar, or weights that do not fit the
+// vehicle's class, when they are blocked, need `supervisor_override`.
+#[tauri::command]
+pub fn create_weighment(
+    app: AppHandle,
+    mut weighment: NewWeighment,
+    idempotency_key: Option<String>,
+    supervisor_override: Option<SupervisorOverride>,
+) -> Result<CreatedWeighment, String> {
+    const COMMAND: &str = "create_weighment";
+    let _operation = shutdown::begin(&app, "ticket_write", weighment.id.as_deref())?;
+    // Concurrent retries queue on the write lock (pool busy timeout)
+    let mut conn = db::open(&app)?;
+    // Authorized outside the transaction so refused attempts stay on record
+    let mut override_id = None;
+    let mut class_override_id = None;
+    if let Some(grant) = supervisor_override {
+        if calendar::blocks(&conn)? {
+            override_id = Some(overrides::authorize(
+                &conn,
+                overrides::OUT_OF_HOURS,
+                None,
+                &grant,
+            )?);
+        }
+        if vehicle_classes::may_block(&conn, &weighment.vehicle_no)? {
+            class_override_id = Some(overrides::authorize(
+                &conn,
+                overrides::VEHICLE_CLASS,
+                None,
+                &grant,
+            )?);
+        }
+    }
+    let tx = conn
+        .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+        .map_err(|e| e.to_string())?;
+
+    let request_digest =
+        idempotency::digest(&serde_json::to_value(&weighment).map_err(|e| e.to_string())?);
+    if let Some(key) = idempotency_key.as_deref() {
+        if let Some(id) = idempotency::lookup(&tx, key, COMMAND, &request_digest)? {
+            let (bill_no, ticket_no) = tx
+                .query_row(
+                    "SELECT bill_no, ticket_no FROM weighments WHERE id = ?1",
+                    [&id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(|e| e.to_string())?;
+            return Ok(CreatedWeighment {
+                weighment_id: id,
+                bill_no,
+                ticket_no,
+                replayed: true,
+                out_of_hours: None,
+                class_violations: Vec::new(),
+            });
+        }
+    }
+
+    apply_ticket_script(&tx, &mut weighment)?;
+    let rounding = rounding::load_rules(&tx)?;
+    // Snapshots show the first weighing
+    let first_weight = weighment.gross_weight.or(weighment.tare_weight);
+    let front_image = watermark::snapshot(
+        &tx,
+        weighment.front_image,
+        &weighment.ticket_no,
+        first_weight,
+    )?;
+    let rear_image = watermark::snapshot(
+        &tx,
+        weighment.rear_image,
+        &weighment.ticket_no,
+        first_weight,
+    )?;
+    let front_image = attachments::store_if_inline(&app, &tx, front_image)?;
+    let rear_image = attachments::store_if_inline(&app, &tx, rear_image)?;
+    let id = weighment
+        .id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let gross = weighment.gross_weight.map(|w| rounding.weight(w));
+    let tare = weighment.tare_weight.map(|w| rounding.weight(w));
+    tx.execute(
+        "INSERT INTO weighments (id, bill_no, ticket_no, vehicle_no, party_name, product_name,
+             gross_weight, tare_weight, charges_minor, front_camera_image, back_camera_image,
+             status, first_weight_type, first_vehicle_status, remarks)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, 'OPEN', ?12, ?13, ?14)",
+        params![
+            id,
+            weighment.bill_no,
+            weighment.ticket_no,
+            weighment.vehicle_no,
+            weighment.party_name,
+            weighment.product_name,
+            gross,
+            tare,
+            weighment.charges_minor,
+            front_image,
+            rear_image,
+            weighment.first_weight_type,
+            weighment.first_vehicle_status,
+            weighment.remarks
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    serial_numbers::claim(
+        &tx,
+        &weighment.ticket_no,
+        weighment.reservation.as_deref(),
+        &id,
+    )?;
+    let out_of_hours = calendar::check(&tx, &id, calendar::FIRST_WEIGHT, override_id)?;
+    let class_violations = vehicle_classes::check(
+        &tx,
+        &id,
+        calendar::FIRST_WEIGHT,
+        gross,
+        tare,
+        class_override_id,
+    )?;
+    cameras::link(&tx, &id, &weighment.snapshots)?;
+    let snapshot_later = weighment.snapshots.is_empty();
+    ticket_parties::link(
+        &tx,
+        &id,
+        weighment.consignor.as_deref(),
+        weighment.consignee.as_deref(),
+    )?;
+    if let Some(key) = idempotency_key.as_deref() {
+        idempotency::record(&tx, key, COMMAND, &request_digest, &id)?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    if snapshot_later {
+        cameras::capture_after_save(&app, &id);
+    }
+    Ok(CreatedWeighment {
+        weighment_id: id,
+        bill_no: weighment.bill_no,
+        ticket_no: weighment.ticket_no,
+        replayed: false,
+        out_of_hours,
+        class_violations,
+    })
+}
+
+// Record the second weight of an open weighment and close it. For stored-tare
+// (one-time) tickets `second_weight` is omitted; materials that require a second
+// weighing refuse that. Tickets weighed in a sequence (weighing_steps.rs) are
+// closed from their steps, also without `second_weight`. The ticket consumes
+// against `po_number`, or the party's open PO. Tickets dated inside a locked
+// period, taking the party over a BLOCK credit limit, or weighed outside
+// the working calendar or outside the vehicle's class when those are
+// blocked, need `supervisor_override`.
+// Fraud rules are evaluated once the ticket is closed.
+#[tauri::command]
+pub fn complete_weighment(
+    app: AppHandle,
+    weighment_id: String,
+    second_weight: Option<f64>,
+    po_number: Option<String>,
+    supervisor_override: Option<SupervisorOverride>,
+) -> Result<CompletedWeighment, String> {
+    let _operation = shutdown::begin(&app, "ticket_write", Some(&weighment_id))?;
+    let mut conn = db::open(&app)?;
+    // Authorized outside the transaction so refused attempts stay on record
+    let mut override_id = None;
+    let mut credit_override_id = None;
+    let mut calendar_override_id = None;
+    let mut class_override_id = None;
+    if let Some(grant) = supervisor_override {
+        if period_lock::is_weighment_locked(&conn, &weighment_id)? {
+            override_id = Some(overrides::authorize(
+                &conn,
+                overrides::LOCKED_PERIOD,
+                Some(("weighment", &weighment_id)),
+                &grant,
+            )?);
+        }
+        // The charge is only known inside the transaction; an override granted
+        // here stays unused when the ticket turns out to be within the limit
+        if credit::blocks(&conn, &weighment_id)? {
+            credit_override_id = Some(overrides::authorize(
+                &conn,
+                overrides::CREDIT_LIMIT,
+                Some(("weighment", &weighment_id)),
+                &grant,
+            )?);
+        }
+        if calendar::blocks(&conn)? {
+            calendar_override_id = Some(overrides::authorize(
+                &conn,
+                overrides::OUT_OF_HOURS,
+                Some(("weighment", &weighment_id)),
+                &grant,
+            )?);
+        }
+        let vehicle_no: Option<String> = conn
+            .query_row(
+                "SELECT vehicle_no FROM weighments WHERE id = ?1",
+                [&weighment_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some(vehicle_no) = vehicle_no {
+            if vehicle_classes::may_block(&conn, &vehicle_no)? {
+                class_override_id = Some(overrides::authorize(
+                    &conn,
+                    overrides::VEHICLE_CLASS,
+                    Some(("weighment", &weighment_id)),
+                    &grant,
+                )?);
+            }
+        }
+    }
+    // Immediate, so a second window completing the same ticket waits here and
+    // then finds it closed, rather than both reading it open
+    let tx = conn
+        .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+        .map_err(|e| e.to_string())?;
+    if voids::is_voided(&tx, &weighment_id)? {
+        return Err("Voided tickets cannot be completed".to_string());
+    }
+    let (party_name, product_name, status, first_type, gross, tare): (
+        String,
+        String,
+        String,
+        Option<String>,
+        Option<f64>,
+        Option<f64>,
+    ) = tx
+        .query_row(
+            "SELECT party_name, product_name, status, first_weight_type, gross_weight,
+                    tare_weight
+             FROM weighments WHERE id = ?1",
+            [&weighment_id],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Weighment {} not found", weighment_id))?;
+    if status != "OPEN" {
+        return Err(format!("Weighment {} is already {}", weighment_id, status));
+    }
+
+    let rule = rule_for(&tx, &product_name)?;
+    let stepped = weighing_steps::final_weights(&tx, &weighment_id)?;
+    let (gross, tare) = match (stepped, first_type.as_deref(), second_weight) {
+        (Some(_), _, Some(_)) => {
+            return Err("This ticket is weighed in steps; record its last step instead".to_string())
+        }
+        (Some((gross, tare)), _, None) => (Some(gross), Some(tare)),
+        (None, Some("one-time"), None) => {
+            if rule.second_weighing_required {
+                return Err(format!(
+                    "{} must be weighed twice; stored tare is not allowed",
+                    product_name
+                ));
+            }
+            (gross, tare)
+        }
+        (None, Some("tare"), Some(second)) => (Some(second), tare),
+        (None, _, Some(second)) => (gross, Some(second)),
+        (None, _, None) => return Err("Second weight is required".to_string()),
+    };
+    // Weights recorded before the rules changed are brought in line too
+    let rounding = rounding::load_rules(&tx)?;
+    let (gross, tare) = match (gross, tare) {
+        (Some(g), Some(t)) => (rounding.weight(g), rounding.weight(t)),
+        _ => return Err("Both gross and tare weights are required".to_string()),
+    };
+    check_min_weight(&rule, "Gross weight", gross)?;
+    check_min_weight(&rule, "Tare weight", tare)?;
+    if tare > gross {
+        return Err(format!("Tare {:.0} kg exceeds gross {:.0} kg", tare, gross));
+    }
+    let class_violations = vehicle_classes::check(
+        &tx,
+        &weighment_id,
+        calendar::SECOND_WEIGHT,
+        Some(gross),
+        Some(tare),
+        class_override_id,
+    )?;
+
+    let net = gross - tare;
+    tx.execute(
+        "UPDATE weighments SET gross_weight = ?2, tare_weight = ?3, net_weight = ?4,
+                status = 'CLOSED', second_weight_timestamp = CURRENT_TIMESTAMP,
+                closed_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![weighment_id, gross, tare, net],
+    )
+    .map_err(|e| e.to_string())?;
+    signatures::sign(&tx, &weighment_id)?;
+    let out_of_hours = calendar::check(
+        &tx,
+        &weighment_id,
+        calendar::SECOND_WEIGHT,
+        calendar_override_id,
+    )?;
+    let adjustment = deductions::apply(&tx, &weighment_id, &party_name, &product_name, net)?;
+    let direction = movements::classify(&tx, &weighment_id)?;
+    inventory::post_weighment(&tx, &weighment_id)?;
+    tariffs::apply(&tx, &weighment_id)?;
+    let billing = currency::bill_weighment(&tx, &weighment_id)?;
+    let credit_breach = credit::check(
+        &tx,
+        &weighment_id,
+        &party_name,
+        billing.base_amount_minor,
+        credit_override_id,
+    )?;
+    if let Some(breach) = &credit_breach {
+        notifications::notify(
+            &app,
+            &tx,
+            "admin",
+            "Credit limit exceeded",
+            &format!(
+                "{} ticket {} takes the balance to {} against a limit of {}",
+                breach.party_name,
+                breach.ticket_no,
+                money::format_minor(breach.outstanding_minor + breach.charge_minor),
+                money::format_minor(breach.limit_minor)
+            ),
+            Some(("weighment", &weighment_id)),
+        )?;
+    }
+
+    // Practice tickets never draw down a real purchase order
+    let purchase_order = if training::is_practice(&tx, &weighment_id)? {
+        None
+    } else {
+        let quantity = adjustment.as_ref().map_or(net, |a| a.adjusted_net);
+        purchase_orders::consume(
+            &tx,
+            &weighment_id,
+            &party_name,
+            &product_name,
+            po_number.as_deref(),
+            quantity,
+        )?
+    };
+    if let Some(warning) = purchase_order.as_ref().and_then(|po| po.warning.as_ref()) {
+        notifications::notify(
+            &app,
+            &tx,
+            "admin",
+            "Purchase order exhausted",
+            warning,
+            Some(("weighment", &weighment_id)),
+        )?;
+    }
+    if let Some(id) = override_id {
+        overrides::mark_used(&tx, id)?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let fraud_rules_fired = fraud::evaluate_fraud_rules(app.clone(), weighment_id.clone())?;
+    Ok(CompletedWeighment {
+        weighment_id,
+        gross_weight: gross,
+        tare_weight: tare,
+        net_weight: net,
+        adjustment,
+        purchase_order,
+        billing,
+        direction,
+        fraud_rules_fired,
+        credit_breach,
+        out_of_hours,
+        class_violations,
+    })
+}
+
+// Single-pass fast path: weigh a vehicle against its stored tare, close the
+// ticket and lay out its slip in one call. Party and product default to the
+// vehicle's last ticket.
+#[tauri::command]
+pub async fn quick_weigh(
+    app: AppHandle,
+    vehicle_no: String,
+    party_name: Option<String>,
+    product_name: Option<String>,
+    printer: Option<String>,
+    timeout_seconds: Option<f64>,
+) -> Result<QuickWeighment, String> {
+    let (stored_tare, party_name, product_name) = {
+        let conn = db::open(&app)?;
+        let stored_tare: f64 = conn
+            .query_row(
+                "SELECT tare_weight FROM stored_tares
+                 WHERE vehicle_no = ?1 AND expires_at > CURRENT_TIMESTAMP",
+                [&vehicle_no],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("{} has no valid stored tare; weigh it twice", vehicle_no))?;
+        let last: Option<(String, String)> = conn
+            .query_row(
+                "SELECT party_name, product_name FROM weighments
+                 WHERE vehicle_no = ?1 ORDER BY created_at DESC LIMIT 1",
+                [&vehicle_no],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let (last_party, last_product) = last.unzip();
+        let party_name = party_name
+            .or(last_party)
+            .ok_or("Party is required for a vehicle's first ticket")?;
+        let product_name = product_name
+            .or(last_product)
+            .ok_or("Product is required for a vehicle's first ticket")?;
+        (stored_tare, party_name, product_name)
+    };
+
+    let captured = capture_weight(
+        app.clone(),
+        product_name.clone(),
+        Some(vehicle_no.clone()),
+        timeout_seconds,
+    )
+    .await?;
+
+    let serial = db::write(&app, |tx| serial_numbers::next(tx))?;
+    let created = create_weighment(
+        app.clone(),
+        NewWeighment {
+            id: None,
+            bill_no: serial.clone(),
+            ticket_no: serial,
+            vehicle_no,
+            party_name,
+            product_name,
+            gross_weight: Some(captured.weight_kg),
+            tare_weight: Some(stored_tare),
+            charges_minor: 0,
+            front_image: None,
+            rear_image: None,
+            first_weight_type: "one-time".to_string(),
+            first_vehicle_status: None,
+            remarks: None,
+            snapshots: Vec::new(),
+            consignor: None,
+            consignee: None,
+            reservation: None,
+        },
+        None,
+        None,
+    )?;
+    let completed =
+        complete_weighment(app.clone(), created.weighment_id.clone(), None, None, None)?;
+    let slip = slip_layout::render_slip_layout(app, created.weighment_id, printer, None, None)?;
+
+    Ok(QuickWeighment {
+        bill_no: created.bill_no,
+        ticket_no: created.ticket_no,
+        captured,
+        completed,
+        slip,
+    })
+}
+
+#[tauri::command]
+pub fn list_capture_rules(app: AppHandle) -> Result<Vec<CaptureRule>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT product_name, min_weight_kg, stability_seconds, second_weighing_required
+             FROM material_capture_rules ORDER BY product_name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], row_to_rule).map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_capture_rule(app: AppHandle, rule: CaptureRule) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    conn.execute(
+        "INSERT INTO material_capture_rules
+             (product_name, min_weight_kg, stability_seconds, second_weighing_required)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(product_name) DO UPDATE SET min_weight_kg = excluded.min_weight_kg,
+             stability_seconds = excluded.stability_seconds,
+             second_weighing_required = excluded.second_weighing_required",
+        params![
+            rule.product_name,
+            rule.min_weight_kg,
+            rule.stability_seconds,
+            rule.second_weighing_required
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,339 @@
+// Multi-interval weighing for Truckore Pro
+// Some tickets take more than two weighings: a trailer rig is weighed as the
+// prime mover alone, then with its trailer, then loaded. A weighing sequence
+// names the steps in order and marks which step is the tare and which the
+// gross; the others are kept for the record. A ticket keeps a copy of the
+// sequence it was started with, so later edits do not change it. Each step's
+// weight is stored in weighment_steps, the tare and gross steps also fill the ticket's own
+// weights, and complete_weighment closes the ticket from them once every
+// step is in. The slip lists every step.
+
+use crate::db;
+use crate::rounding;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepRole {
+    Tare,
+    Gross,
+    // Weighed for the record only, e.g. the prime mover alone
+    Info,
+}
+
+impl StepRole {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StepRole::Tare => "tare",
+            StepRole::Gross => "gross",
+            StepRole::Info => "info",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequenceStep {
+    pub label: String,
+    pub role: StepRole,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeighingSequence {
+    pub name: String,
+    pub steps: Vec<SequenceStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeighmentStep {
+    pub step_no: i64,
+    pub label: String,
+    pub role: StepRole,
+    pub weight_kg: f64,
+    pub captured_by: String,
+    pub captured_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WeighmentSteps {
+    pub weighment_id: String,
+    pub sequence: WeighingSequence,
+    pub steps: Vec<WeighmentStep>,
+    // Label of the step to weigh next, None once all are in
+    pub next_step: Option<String>,
+}
+
+fn validate(sequence: &WeighingSequence) -> Result<(), String> {
+    if sequence.name.trim().is_empty() {
+        return Err("A weighing sequence needs a name".to_string());
+    }
+    if sequence.steps.len() < 2 || sequence.steps.iter().any(|s| s.label.trim().is_empty()) {
+        return Err("A weighing sequence needs at least two named steps".to_string());
+    }
+    for role in [StepRole::Tare, StepRole::Gross] {
+        if sequence.steps.iter().filter(|s| s.role == role).count() != 1 {
+            return Err(format!(
+                "A weighing sequence needs exactly one {} step",
+                role.as_str()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn parse_sequence(name: String, steps: &str) -> Result<WeighingSequence, String> {
+    Ok(WeighingSequence {
+        name,
+        steps: serde_json::from_str(steps).map_err(|e| e.to_string())?,
+    })
+}
+
+pub fn load_sequence(conn: &Connection, name: &str) -> Result<WeighingSequence, String> {
+    let steps: String = conn
+        .query_row(
+            "SELECT steps FROM weighing_sequences WHERE name = ?1",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Weighing sequence {} not found", name))?;
+    parse_sequence(name.to_string(), &steps)
+}
+
+fn recorded(conn: &Connection, weighment_id: &str) -> Result<Vec<WeighmentStep>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT step_no, label, role, weight_kg, captured_by, captured_at
+             FROM weighment_steps WHERE weighment_id = ?1 ORDER BY step_no",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([weighment_id], |row| {
+            let role: String = row.get(2)?;
+            Ok(WeighmentStep {
+                step_no: row.get(0)?,
+                label: row.get(1)?,
+                role: match role.as_str() {
+                    "tare" => StepRole::Tare,
+                    "gross" => StepRole::Gross,
+                    _ => StepRole::Info,
+                },
+                weight_kg: row.get(3)?,
+                captured_by: row.get(4)?,
+                captured_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rows)
+}
+
+// Sequence the ticket was started with
+fn sequence_of(conn: &Connection, weighment_id: &str) -> Result<Option<WeighingSequence>, String> {
+    let row: Option<(String, String)> = conn
+        .query_row(
+            "SELECT sequence_name, steps FROM weighment_sequences WHERE weighment_id = ?1",
+            [weighment_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    row.map(|(name, steps)| parse_sequence(name, &steps))
+        .transpose()
+}
+
+fn progress(
+    conn: &Connection,
+    weighment_id: &str,
+    sequence: WeighingSequence,
+) -> Result<WeighmentSteps, String> {
+    let steps = recorded(conn, weighment_id)?;
+    let next_step = sequence.steps.get(steps.len()).map(|s| s.label.clone());
+    Ok(WeighmentSteps {
+        weighment_id: weighment_id.to_string(),
+        sequence,
+        steps,
+        next_step,
+    })
+}
+
+// Gross and tare of a ticket weighed in steps, for complete_weighment.
+// None for an ordinary two-weighing ticket; an error while steps are missing.
+pub fn final_weights(conn: &Connection, weighment_id: &str) -> Result<Option<(f64, f64)>, String> {
+    let Some(sequence) = sequence_of(conn, weighment_id)? else {
+        return Ok(None);
+    };
+    let progress = progress(conn, weighment_id, sequence)?;
+    if let Some(next) = &progress.next_step {
+        return Err(format!(
+            "{} of {} steps weighed; {} is still to be weighed",
+            progress.steps.len(),
+            progress.sequence.steps.len(),
+            next
+        ));
+    }
+    let weight = |role: StepRole| {
+        progress
+            .steps
+            .iter()
+            .find(|s| s.role == role)
+            .map(|s| s.weight_kg)
+    };
+    match (weight(StepRole::Gross), weight(StepRole::Tare)) {
+        (Some(gross), Some(tare)) => Ok(Some((gross, tare))),
+        _ => Err(format!("Ticket {} has no gross or tare step", weighment_id)),
+    }
+}
+
+// (label, weight) per recorded step, for the slip
+pub fn slip_lines(conn: &Connection, weighment_id: &str) -> Result<Vec<(String, f64)>, String> {
+    Ok(recorded(conn, weighment_id)?
+        .into_iter()
+        .map(|s| (s.label, s.weight_kg))
+        .collect())
+}
+
+#[tauri::command]
+pub fn list_weighing_sequences(app: AppHandle) -> Result<Vec<WeighingSequence>, String> {
+    let conn = db::open(&app)?;
+    let mut stmt = conn
+        .prepare("SELECT name FROM weighing_sequences ORDER BY name")
+        .map_err(|e| e.to_string())?;
+    let names: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    names
+        .iter()
+        .map(|name| load_sequence(&conn, name))
+        .collect()
+}
+
+// Tickets already started keep the steps they were started with
+#[tauri::command]
+pub fn set_weighing_sequence(app: AppHandle, sequence: WeighingSequence) -> Result<(), String> {
+    validate(&sequence)?;
+    let conn = db::open(&app)?;
+    let steps = serde_json::to_string(&sequence.steps).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO weighing_sequences (name, steps) VALUES (?1, ?2)
+         ON CONFLICT(name) DO UPDATE SET steps = excluded.steps, updated_at = CURRENT_TIMESTAMP",
+        params![sequence.name.trim(), steps],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_weighing_sequence(app: AppHandle, name: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    conn.execute("DELETE FROM weighing_sequences WHERE name = ?1", [&name])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Record the next step of an open ticket. The first step ties the ticket to
+// `sequence_name`; the tare and gross steps also set the ticket's weights.
+#[tauri::command]
+pub fn record_weighing_step(
+    app: AppHandle,
+    weighment_id: String,
+    sequence_name: String,
+    weight_kg: f64,
+    user_id: String,
+) -> Result<WeighmentSteps, String> {
+    let conn = db::open(&app)?;
+    // Immediate, so two windows weighing the same ticket take turns and the
+    // second sees the first one's step
+    let tx = rusqlite::Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)
+        .map_err(|e| e.to_string())?;
+    let status: String = tx
+        .query_row(
+            "SELECT status FROM weighments WHERE id = ?1",
+            [&weighment_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Weighment {} not found", weighment_id))?;
+    if status != "OPEN" {
+        return Err(format!("Weighment {} is already {}", weighment_id, status));
+    }
+    if !weight_kg.is_finite() || weight_kg < 0.0 {
+        return Err("Step weight must be a non-negative number".to_string());
+    }
+    let sequence = match sequence_of(&tx, &weighment_id)? {
+        Some(current) if current.name != sequence_name => {
+            return Err(format!(
+                "Weighment {} is being weighed as {}",
+                weighment_id, current.name
+            ))
+        }
+        Some(current) => current,
+        None => {
+            let sequence = load_sequence(&tx, &sequence_name)?;
+            let steps = serde_json::to_string(&sequence.steps).map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT INTO weighment_sequences (weighment_id, sequence_name, steps)
+                 VALUES (?1, ?2, ?3)",
+                params![weighment_id, sequence.name, steps],
+            )
+            .map_err(|e| e.to_string())?;
+            sequence
+        }
+    };
+    let done = recorded(&tx, &weighment_id)?.len();
+    let step = sequence
+        .steps
+        .get(done)
+        .ok_or_else(|| format!("All {} steps are already weighed", sequence.steps.len()))?;
+    let weight_kg = rounding::load_rules(&tx)?.weight(weight_kg);
+    tx.execute(
+        "INSERT INTO weighment_steps
+             (weighment_id, step_no, label, role, weight_kg, captured_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            weighment_id,
+            done as i64 + 1,
+            step.label,
+            step.role.as_str(),
+            weight_kg,
+            user_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let column = match step.role {
+        StepRole::Tare => Some("tare_weight"),
+        StepRole::Gross => Some("gross_weight"),
+        StepRole::Info => None,
+    };
+    if let Some(column) = column {
+        tx.execute(
+            &format!(
+                "UPDATE weighments SET {} = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                column
+            ),
+            params![weighment_id, weight_kg],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    progress(&conn, &weighment_id, sequence)
+}
+
+// Steps weighed so far; None for a ticket not weighed in a sequence
+#[tauri::command]
+pub fn get_weighment_steps(
+    app: AppHandle,
+    weighment_id: String,
+) -> Result<Option<WeighmentSteps>, String> {
+    let conn = db::open(&app)?;
+    match sequence_of(&conn, &weighment_id)? {
+        Some(sequence) => progress(&conn, &weighment_id, sequence).map(Some),
+        None => Ok(None),
+    }
+}
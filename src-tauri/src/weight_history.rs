@@ -0,0 +1,128 @@
+// Recent weight readings for Truckore Pro
+// The scale listener keeps the last few minutes of readings in memory, so
+// the weighing screen can draw the loading curve: a truck braking hard on
+// the deck or only partly on it shows as spikes or a slow climb before the
+// weight settles. Nothing is written to the database; the history starts
+// empty with each listener run and is cleared when the indicator changes.
+
+use crate::scale_listener::WeightUpdate;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+// How far back the history reaches
+const SPAN: Duration = Duration::from_secs(10 * 60);
+// Cap on readings held, for indicators streaming far faster than usual
+const MAX_SAMPLES: usize = 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightSample {
+    // Unix time in milliseconds
+    pub at_ms: u64,
+    pub weight_kg: f64,
+    pub stable: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WeightHistory {
+    // Indicator the samples came from, None before the first reading
+    pub port: Option<String>,
+    // Oldest first
+    pub samples: Vec<WeightSample>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    port: Option<String>,
+    samples: VecDeque<WeightSample>,
+}
+
+// Managed state: the rolling buffer
+#[derive(Default)]
+pub struct WeightBuffer(Mutex<Buffer>);
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// Add a reading; called by the listener for each one it emits
+pub fn record(app: &AppHandle, update: &WeightUpdate) {
+    let Some(state) = app.try_state::<WeightBuffer>() else {
+        return;
+    };
+    let Ok(mut buffer) = state.0.lock() else {
+        return;
+    };
+    if buffer.port.as_deref() != Some(update.port.as_str()) {
+        buffer.port = Some(update.port.clone());
+        buffer.samples.clear();
+    }
+    let at_ms = now_ms();
+    let oldest = at_ms.saturating_sub(SPAN.as_millis() as u64);
+    while buffer
+        .samples
+        .front()
+        .is_some_and(|s| s.at_ms < oldest || buffer.samples.len() >= MAX_SAMPLES)
+    {
+        buffer.samples.pop_front();
+    }
+    buffer.samples.push_back(WeightSample {
+        at_ms,
+        weight_kg: update.weight_kg,
+        stable: update.stable,
+    });
+}
+
+// Forget the readings; called when a listener starts
+pub fn clear(app: &AppHandle) {
+    if let Some(state) = app.try_state::<WeightBuffer>() {
+        if let Ok(mut buffer) = state.0.lock() {
+            *buffer = Buffer::default();
+        }
+    }
+}
+
+// Weights from `port` in the last `seconds`, oldest first; empty when the
+// listener is not streaming from it
+pub fn recent(app: &AppHandle, port: &str, seconds: u64) -> Vec<f64> {
+    let Some(state) = app.try_state::<WeightBuffer>() else {
+        return Vec::new();
+    };
+    let Ok(buffer) = state.0.lock() else {
+        return Vec::new();
+    };
+    if buffer.port.as_deref() != Some(port) {
+        return Vec::new();
+    }
+    let oldest = now_ms().saturating_sub(seconds.saturating_mul(1000));
+    buffer
+        .samples
+        .iter()
+        .filter(|s| s.at_ms >= oldest)
+        .map(|s| s.weight_kg)
+        .collect()
+}
+
+// Readings from the last `seconds` (at most ten minutes), oldest first
+#[tauri::command]
+pub fn get_weight_history(
+    buffer: State<'_, WeightBuffer>,
+    seconds: u64,
+) -> Result<WeightHistory, String> {
+    let buffer = buffer.0.lock().map_err(|e| e.to_string())?;
+    let span_ms = seconds.saturating_mul(1000).min(SPAN.as_millis() as u64);
+    let oldest = now_ms().saturating_sub(span_ms);
+    Ok(WeightHistory {
+        port: buffer.port.clone(),
+        samples: buffer
+            .samples
+            .iter()
+            .filter(|s| s.at_ms >= oldest)
+            .cloned()
+            .collect(),
+    })
+}
@@ -0,0 +1,348 @@
+// Write-ahead journal of physical workflow steps for Truckore Pro
+// A weight capture, a slip print or a barrier movement is recorded as
+// PENDING before it starts and marked DONE or FAILED once it returns. The
+// intent is committed first, and connections keep SQLite's default full
+// sync, so after a power cut the steps that never reported back are still
+// listed. Startup turns them INTERRUPTED and tells the operator what to
+// redo; they stay listed until an operator resolves them. Steps the frontend drives itself,
+// such as a barrier on its own controller, are journalled through
+// record_workflow_intent and finish_workflow_step.
+
+use crate::db;
+use crate::notifications;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+pub const STEPS: &[&str] = &["capture", "print", "barrier"];
+
+// Finished steps are kept this long for the ticket's journal
+const RETENTION_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    pub step: String,
+    // Vehicle for captures and barriers, ticket number for prints
+    pub entity: Option<String>,
+    pub lane_id: Option<String>,
+    pub detail: Value,
+    // PENDING, DONE, FAILED, INTERRUPTED or RESOLVED
+    pub status: String,
+    pub error: Option<String>,
+    pub requested_at: String,
+    pub finished_at: Option<String>,
+    pub resolved_by: Option<String>,
+    // What the operator must do for an interrupted step
+    pub redo: Option<String>,
+}
+
+fn redo_hint(step: &str) -> &'static str {
+    match step {
+        "capture" => "Weigh the vehicle again; the captured weight was not recorded",
+        "print" => "Reprint the slip if it did not come out",
+        _ => "Check the barrier and move it by hand if it did not",
+    }
+}
+
+fn check_step(step: &str) -> Result<(), String> {
+    match STEPS.contains(&step) {
+        true => Ok(()),
+        false => Err(format!("Unknown workflow step {}", step)),
+    }
+}
+
+// Record a step before its side effect runs
+pub fn intend(
+    conn: &Connection,
+    step: &str,
+    entity: Option<&str>,
+    lane_id: Option<&str>,
+    detail: &Value,
+) -> Result<String, String> {
+    check_step(step)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO workflow_journal (id, step, entity, lane_id, detail)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, step, entity, lane_id, detail.to_string()],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+pub fn finish(conn: &Connection, id: &str, error: Option<&str>) -> Result<(), String> {
+    let status = match error {
+        Some(_) => "FAILED",
+        None => "DONE",
+    };
+    conn.execute(
+        "UPDATE workflow_journal SET status = ?2, error = ?3, finished_at = CURRENT_TIMESTAMP
+         WHERE id = ?1 AND status = 'PENDING'",
+        params![id, status, error],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Run `f` as a journalled step. The step is refused when its intent cannot
+// be recorded; an outcome that cannot be recorded leaves it PENDING, and it
+// is reported as interrupted on the next start.
+pub fn journalled<T>(
+    app: &AppHandle,
+    step: &str,
+    entity: Option<&str>,
+    lane_id: Option<&str>,
+    detail: Value,
+    f: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let id = {
+        let conn = db::open(app)?;
+        intend(&conn, step, entity, lane_id, &detail)?
+    };
+    let result = f();
+    let error = result.as_ref().err().map(|e| e.as_str());
+    let _ = db::open(app).and_then(|conn| finish(&conn, &id, error));
+    result
+}
+
+// Steps left PENDING by the previous run never reported back. Called by
+// startup recovery before anything else runs.
+pub fn mark_interrupted(app: &AppHandle, conn: &Connection) -> Result<usize, String> {
+    let count = interrupt_pending(conn)?;
+    if count > 0 {
+        notifications::notify(
+            app,
+            conn,
+            "operator",
+            "Weighing steps were interrupted",
+            &format!(
+                "{} capture, print or barrier step(s) did not finish before the last shutdown; \
+                 check the interrupted steps before continuing",
+                count
+            ),
+            None,
+        )?;
+    }
+    Ok(count)
+}
+
+// Drop finished steps past retention and turn PENDING ones INTERRUPTED
+fn interrupt_pending(conn: &Connection) -> Result<usize, String> {
+    conn.execute(
+        "DELETE FROM workflow_journal
+         WHERE status IN ('DONE', 'FAILED', 'RESOLVED')
+           AND requested_at < datetime('now', ?1)",
+        [format!("-{} days", RETENTION_DAYS)],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE workflow_journal SET status = 'INTERRUPTED' WHERE status = 'PENDING'",
+        [],
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn row_to_entry(row: &rusqlite::Row) -> rusqlite::Result<JournalEntry> {
+    let step: String = row.get(1)?;
+    let status: String = row.get(5)?;
+    let detail: String = row.get(4)?;
+    Ok(JournalEntry {
+        id: row.get(0)?,
+        redo: (status == "INTERRUPTED").then(|| redo_hint(&step).to_string()),
+        step,
+        entity: row.get(2)?,
+        lane_id: row.get(3)?,
+        detail: serde_json::from_str(&detail).unwrap_or(Value::Null),
+        status,
+        error: row.get(6)?,
+        requested_at: row.get(7)?,
+        finished_at: row.get(8)?,
+        resolved_by: row.get(9)?,
+    })
+}
+
+fn query(conn: &Connection, filter: &str, args: &[&str]) -> Result<Vec<JournalEntry>, String> {
+    let sql = format!(
+        "SELECT id, step, entity, lane_id, detail, status, error, requested_at,
+                finished_at, resolved_by
+         FROM workflow_journal WHERE {} ORDER BY requested_at, rowid",
+        filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(args), row_to_entry)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Journal a step the frontend performs; returns the id for finish_workflow_step
+#[tauri::command]
+pub fn record_workflow_intent(
+    app: AppHandle,
+    step: String,
+    entity: Option<String>,
+    lane_id: Option<String>,
+    detail: Option<Value>,
+) -> Result<String, String> {
+    let conn = db::open(&app)?;
+    intend(
+        &conn,
+        &step,
+        entity.as_deref(),
+        lane_id.as_deref(),
+        &detail.unwrap_or(Value::Null),
+    )
+}
+
+// `error` marks the step FAILED, otherwise it is DONE
+#[tauri::command]
+pub fn finish_workflow_step(
+    app: AppHandle,
+    id: String,
+    error: Option<String>,
+) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    finish(&conn, &id, error.as_deref())
+}
+
+// Steps cut short by the last shutdown that no operator has resolved yet
+#[tauri::command]
+pub fn interrupted_workflow_steps(app: AppHandle) -> Result<Vec<JournalEntry>, String> {
+    let conn = db::open(&app)?;
+    query(&conn, "status = 'INTERRUPTED'", &[])
+}
+
+// Every journalled step for a vehicle or ticket number, oldest first
+#[tauri::command]
+pub fn workflow_journal(app: AppHandle, entity: String) -> Result<Vec<JournalEntry>, String> {
+    let conn = db::open(&app)?;
+    query(&conn, "entity = ?1", &[&entity])
+}
+
+// The operator has redone or checked an interrupted step
+#[tauri::command]
+pub fn resolve_workflow_step(app: AppHandle, id: String, user_id: String) -> Result<(), String> {
+    let conn = db::open(&app)?;
+    let changed = conn
+        .execute(
+            "UPDATE workflow_journal
+             SET status = 'RESOLVED', resolved_by = ?2, resolved_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND status = 'INTERRUPTED'",
+            params![id, user_id],
+        )
+        .map_err(|e| e.to_string())?;
+    match changed {
+        0 => Err(format!("No interrupted step {}", id)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::Duration;
+
+    const LANES: usize = 8;
+    const STEPS_PER_LANE: usize = 30;
+
+    fn temp_db() -> PathBuf {
+        let path = std::env::temp_dir().join(format!("journal-{}.db", uuid::Uuid::new_v4()));
+        let conn = Connection::open(&path).unwrap();
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))
+            .unwrap();
+        conn.execute_batch(db::SCHEMA).unwrap();
+        path
+    }
+
+    fn remove_db(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    // One lane's steps, each on its own connection as the lane commands
+    // open them. Every fifth step never reports back, as if the power went;
+    // the others report twice, and only the first report may count.
+    fn run_lane(path: &Path, lane: usize) -> Vec<(String, &'static str)> {
+        let connect = || {
+            let conn = Connection::open(path).unwrap();
+            conn.busy_timeout(Duration::from_secs(5)).unwrap();
+            conn
+        };
+        let lane_id = format!("lane{}", lane);
+        (0..STEPS_PER_LANE)
+            .map(|i| {
+                let step = STEPS[i % STEPS.len()];
+                let vehicle = format!("KA01AB{:04}", lane * 100 + i);
+                let id = intend(
+                    &connect(),
+                    step,
+                    Some(&vehicle),
+                    Some(&lane_id),
+                    &serde_json::json!({ "attempt": i }),
+                )
+                .unwrap();
+                let status = match i % 5 {
+                    4 => return (id, "PENDING"),
+                    0 => "FAILED",
+                    _ => "DONE",
+                };
+                let (first, late) = match status {
+                    "FAILED" => (Some("Scale not stable"), None),
+                    _ => (None, Some("Timed out")),
+                };
+                finish(&connect(), &id, first).unwrap();
+                finish(&connect(), &id, late).unwrap();
+                (id, status)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn concurrent_lanes_keep_each_step_in_its_first_outcome() {
+        let path = temp_db();
+        let lanes: Vec<_> = (0..LANES)
+            .map(|lane| {
+                let path = path.clone();
+                thread::spawn(move || run_lane(&path, lane))
+            })
+            .collect();
+        let expected: Vec<(String, &str)> = lanes
+            .into_iter()
+            .flat_map(|lane| lane.join().unwrap())
+            .collect();
+        assert_eq!(expected.len(), LANES * STEPS_PER_LANE);
+
+        let conn = Connection::open(&path).unwrap();
+        let entries = query(&conn, "1", &[]).unwrap();
+        assert_eq!(entries.len(), expected.len());
+        for (id, status) in &expected {
+            let entry = entries.iter().find(|e| &e.id == id).unwrap();
+            assert_eq!(entry.status, *status, "{}", id);
+            assert_eq!(entry.finished_at.is_some(), *status != "PENDING");
+        }
+
+        // Startup after the power cut
+        let pending = expected.iter().filter(|(_, s)| *s == "PENDING").count();
+        assert_eq!(interrupt_pending(&conn).unwrap(), pending);
+        let interrupted = query(&conn, "status = 'INTERRUPTED'", &[]).unwrap();
+        assert_eq!(interrupted.len(), pending);
+        assert!(interrupted
+            .iter()
+            .all(|e| e.redo.as_deref() == Some(redo_hint(&e.step))));
+        // A report arriving after the restart does not clear the step
+        finish(&conn, &interrupted[0].id, None).unwrap();
+        assert_eq!(
+            query(&conn, "status = 'INTERRUPTED'", &[]).unwrap().len(),
+            pending
+        );
+        assert_eq!(interrupt_pending(&conn).unwrap(), 0);
+        drop(conn);
+        remove_db(&path);
+    }
+}
@@ -0,0 +1,286 @@
+// Minimal XLSX writer for Truckore Pro exports
+// One worksheet, written row by row straight into the zip archive so large
+// registers never sit in memory. Numbers and dates are typed cells; dates use
+// Excel serial numbers with a date or date-time format.
+
+use std::fs::File;
+use std::io::Write;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+// Excel's sheet limits
+pub const MAX_ROWS: usize = 1_048_576;
+pub const MAX_COLUMNS: usize = 16_384;
+
+// cellXfs indexes in STYLES
+const STYLE_DATE: u8 = 1;
+const STYLE_DATE_TIME: u8 = 2;
+const STYLE_HEADER: u8 = 3;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Export" sheetId="1" r:id="rId1"/></sheets></workbook>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="2"><numFmt numFmtId="164" formatCode="yyyy-mm-dd"/><numFmt numFmtId="165" formatCode="yyyy-mm-dd hh:mm:ss"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border/></borders><cellStyleXfs count="1"><xf/></cellStyleXfs><cellXfs count="4"><xf/><xf numFmtId="164" applyNumberFormat="1"/><xf numFmtId="165" applyNumberFormat="1"/><xf fontId="1" applyFont="1"/></cellXfs></styleSheet>"#;
+
+pub enum Cell {
+    Empty,
+    Number(f64),
+    Text(String),
+}
+
+pub struct SheetWriter {
+    zip: ZipWriter<File>,
+    rows: usize,
+}
+
+fn io_error(e: impl std::fmt::Display) -> String {
+    format!("Failed to write spreadsheet: {}", e)
+}
+
+// Column letters: 0 -> A, 25 -> Z, 26 -> AA
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+// Text safe inside an XML element; control characters XML cannot carry are dropped
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// Excel serial for "YYYY-MM-DD" or "YYYY-MM-DD HH:MM:SS" (T separator and
+// fractional seconds accepted), with the style to show it in
+fn date_serial(text: &str) -> Option<(f64, u8)> {
+    let bytes = text.as_bytes();
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let part = text.get(range)?;
+        part.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| part.parse().ok())?
+    };
+    if bytes.len() < 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1900 {
+        return None;
+    }
+    // Serial 0 is 1899-12-30, which lines up with Excel's 1900 leap-year quirk
+    let days = (days_from_civil(year, month, day) - days_from_civil(1899, 12, 30)) as f64;
+    if bytes.len() == 10 {
+        return Some((days, STYLE_DATE));
+    }
+
+    let rest = &text[19.min(text.len())..];
+    if bytes.len() < 19
+        || !matches!(bytes[10], b' ' | b'T')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || !(rest.is_empty() || rest.starts_with('.'))
+    {
+        return None;
+    }
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let seconds = (hour * 3600 + minute * 60 + second) as f64;
+    Some((days + seconds / 86_400.0, STYLE_DATE_TIME))
+}
+
+impl SheetWriter {
+    // Start a workbook in `file` with a bold header row
+    pub fn create(file: File, header: &[String]) -> Result<SheetWriter, String> {
+        if header.len() > MAX_COLUMNS {
+            return Err(format!("Excel sheets hold at most {} columns", MAX_COLUMNS));
+        }
+        let mut zip = ZipWriter::new(file);
+        let options = FileOptions::default();
+        for (name, content) in [
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("_rels/.rels", ROOT_RELS),
+            ("xl/workbook.xml", WORKBOOK),
+            ("xl/_rels/workbook.xml.rels", WORKBOOK_RELS),
+            ("xl/styles.xml", STYLES),
+        ] {
+            zip.start_file(name, options).map_err(io_error)?;
+            zip.write_all(content.as_bytes()).map_err(io_error)?;
+        }
+        zip.start_file("xl/worksheets/sheet1.xml", options)
+            .map_err(io_error)?;
+        zip.write_all(
+            br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews><sheetData>"#,
+        )
+        .map_err(io_error)?;
+
+        let mut writer = SheetWriter { zip, rows: 0 };
+        let header: Vec<Cell> = header.iter().map(|h| Cell::Text(h.clone())).collect();
+        writer.write_row(&header, Some(STYLE_HEADER))?;
+        Ok(writer)
+    }
+
+    // Text that looks like a date or timestamp is written as a date cell
+    pub fn row(&mut self, cells: &[Cell]) -> Result<(), String> {
+        self.write_row(cells, None)
+    }
+
+    fn write_row(&mut self, cells: &[Cell], style: Option<u8>) -> Result<(), String> {
+        if self.rows >= MAX_ROWS {
+            return Err(format!("Excel sheets hold at most {} rows", MAX_ROWS));
+        }
+        self.rows += 1;
+        let mut xml = format!("<row r=\"{}\">", self.rows);
+        for (i, cell) in cells.iter().enumerate() {
+            let reference = format!("{}{}", column_name(i), self.rows);
+            let style_attr = |s: u8| format!(" s=\"{}\"", s);
+            match cell {
+                Cell::Empty => {}
+                Cell::Number(n) if n.is_finite() => xml.push_str(&format!(
+                    "<c r=\"{}\"{}><v>{}</v></c>",
+                    reference,
+                    style.map(style_attr).unwrap_or_default(),
+                    n
+                )),
+                Cell::Number(n) => xml.push_str(&format!(
+                    "<c r=\"{}\" t=\"inlineStr\"><is><t>{}</t></is></c>",
+                    reference, n
+                )),
+                Cell::Text(text) => match date_serial(text).filter(|_| style.is_none()) {
+                    Some((serial, date_style)) => xml.push_str(&format!(
+                        "<c r=\"{}\" s=\"{}\"><v>{}</v></c>",
+                        reference, date_style, serial
+                    )),
+                    None => xml.push_str(&format!(
+                        "<c r=\"{}\"{} t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>",
+                        reference,
+                        style.map(style_attr).unwrap_or_default(),
+                        escape(text)
+                    )),
+                },
+            }
+        }
+        xml.push_str("</row>");
+        self.zip.write_all(xml.as_bytes()).map_err(io_error)
+    }
+
+    // Close the sheet and the archive; returns the data rows written
+    pub fn finish(mut self) -> Result<usize, String> {
+        self.zip
+            .write_all(b"</sheetData></worksheet>")
+            .map_err(io_error)?;
+        self.zip.finish().map_err(io_error)?;
+        Ok(self.rows - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn columns_are_named_like_excel() {
+        for (index, name) in [(0, "A"), (25, "Z"), (26, "AA"), (701, "ZZ"), (702, "AAA")] {
+            assert_eq!(column_name(index), name);
+        }
+        assert_eq!(column_name(MAX_COLUMNS - 1), "XFD");
+    }
+
+    #[test]
+    fn dates_become_serials_and_other_text_stays_text() {
+        assert_eq!(date_serial("2026-01-10"), Some((46032.0, STYLE_DATE)));
+        assert_eq!(date_serial("1900-03-01"), Some((61.0, STYLE_DATE)));
+        assert_eq!(
+            date_serial("2026-01-10T12:00:00.250"),
+            Some((46032.5, STYLE_DATE_TIME))
+        );
+        for text in [
+            "2026-13-01",
+            "2026-01-10 25:00:00",
+            "2026-01-10x",
+            "1899-12-31",
+            "KA-01-AB-1234",
+        ] {
+            assert_eq!(date_serial(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn the_workbook_holds_typed_escaped_cells() {
+        let path = std::env::temp_dir().join(format!("xlsx-{}.xlsx", uuid::Uuid::new_v4()));
+        let header = vec!["Ticket".to_string(), "Date".to_string(), "Net".to_string()];
+        let mut sheet = SheetWriter::create(File::create(&path).unwrap(), &header).unwrap();
+        sheet
+            .row(&[
+                Cell::Text("T<1> & \"2\"\u{1}".to_string()),
+                Cell::Text("2026-01-10".to_string()),
+                Cell::Number(1250.5),
+            ])
+            .unwrap();
+        sheet
+            .row(&[Cell::Empty, Cell::Empty, Cell::Number(f64::NAN)])
+            .unwrap();
+        assert_eq!(sheet.finish().unwrap(), 2);
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        for part in ["[Content_Types].xml", "xl/workbook.xml", "xl/styles.xml"] {
+            archive.by_name(part).unwrap();
+        }
+        let mut xml = String::new();
+        archive
+            .by_name("xl/worksheets/sheet1.xml")
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(xml.contains(r#"<c r="A1" s="3" t="inlineStr">"#));
+        assert!(xml.contains("T&lt;1&gt; &amp; &quot;2&quot;</t>"));
+        assert!(xml.contains(r#"<c r="B2" s="1"><v>46032</v></c>"#));
+        assert!(xml.contains(r#"<c r="C2"><v>1250.5</v></c>"#));
+        assert!(xml.contains(r#"<row r="3"><c r="C3" t="inlineStr"><is><t>NaN</t></is></c></row>"#));
+        assert!(xml.ends_with("</sheetData></worksheet>"));
+    }
+}
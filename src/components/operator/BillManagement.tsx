@@ -7,6 +7,7 @@ import { Table, TableBody, TableCell, TableHead, TableHeader, TableRow } from '@
 import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from '@/components/ui/select';
 import { Download, FileSpreadsheet, FileText, Printer, Search } from 'lucide-react';
 import { Bill, BillStatus } from '@/types/weighment';
+import { formatMinor } from '@/lib/money';
 import { getBills, searchBills } from '@/services/billService';
 import { exportBillsToExcel, exportBillsToCSV, exportBillsToPDF } from '@/utils/exportUtils';
 import BillPrintView from './BillPrintView';
@@ -175,7 +176,7 @@ export default function BillManagement() {
                         {bill.netWeight !== null ? `${bill.netWeight.toLocaleString()} KG` : '-'}
                       </TableCell>
                       <TableCell className="text-right font-semibold">
-                        ₹{bill.charges.toFixed(2)}
+                        ₹{formatMinor(bill.chargesMinor)}
                       </TableCell>
                       <TableCell>{getStatusBadge(bill.status)}</TableCell>
                       <TableCell className="text-right">
@@ -210,7 +211,7 @@ export default function BillManagement() {
               <div className="text-sm">
                 <span className="text-muted-foreground">Total Charges: </span>
                 <span className="font-bold">
-                  ₹{filteredBills.reduce((sum, b) => sum + b.charges, 0).toFixed(2)}
+                  ₹{formatMinor(filteredBills.reduce((sum, b) => sum + b.chargesMinor, 0))}
                 </span>
               </div>
             </div>
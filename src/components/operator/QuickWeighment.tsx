@@ -49,7 +49,7 @@ export default function QuickWeighment({ liveWeight, isStable }: QuickWeighmentP
         grossWeight: liveWeight,
         tareWeight: 0,
         netWeight: liveWeight,
-        charges: 0,
+        chargesMinor: 0,
         capturedImage: null,
         frontImage: null,
         rearImage: null,
@@ -14,6 +14,7 @@ import { Command, CommandEmpty, CommandGroup, CommandInput, CommandItem, Command
 import { Check, ChevronsUpDown } from 'lucide-react';
 import { useToast } from '@/hooks/use-toast';
 import { cn } from '@/lib/utils';
+import { toMinor } from '@/lib/money';
 import { mockVehicles, mockParties, mockProducts } from '@/utils/mockData';
 import OpenTicketsTable from './OpenTicketsTable';
 import BillPrintView from './BillPrintView';
@@ -191,7 +192,15 @@ export default function UnifiedWeighmentForm({
       setCapturedRearImage(null);
     }
 
-    const chargesAmount = charges ? parseFloat(charges) : 0;
+    const chargesMinor = charges ? toMinor(charges) : 0;
+    if (Number.isNaN(chargesMinor)) {
+      toast({
+        title: "Invalid Charges",
+        description: "Enter the charges in rupees, e.g. 150 or 150.50",
+        variant: "destructive"
+      });
+      return;
+    }
     const timestamp = new Date().toISOString();
 
     // NEW OPERATION - Two-Trip or Single-Trip
@@ -221,7 +230,7 @@ export default function UnifiedWeighmentForm({
           tareWeight: null,
           firstWeightType: 'gross',
           date: new Date().toLocaleString('en-IN'),
-          charges: chargesAmount,
+          chargesMinor,
           capturedImage: capturedFrontImage || capturedRearImage,
           frontImage: capturedFrontImage,
           rearImage: capturedRearImage
@@ -237,7 +246,7 @@ export default function UnifiedWeighmentForm({
           grossWeight: liveWeight,
           tareWeight: null,
           netWeight: null,
-          charges: chargesAmount,
+          chargesMinor,
           capturedImage: capturedFrontImage || capturedRearImage,
           frontImage: capturedFrontImage,
           rearImage: capturedRearImage,
@@ -270,7 +279,7 @@ export default function UnifiedWeighmentForm({
           grossWeight: liveWeight,
           tareWeight: 0,
           netWeight: liveWeight,
-          charges: chargesAmount,
+          chargesMinor,
           capturedImage: frontImage || rearImage,
           frontImage: frontImage,
           rearImage: rearImage,
@@ -349,7 +358,7 @@ export default function UnifiedWeighmentForm({
         grossWeight,
         tareWeight,
         netWeight,
-        charges: ticket.charges,
+        chargesMinor: ticket.chargesMinor,
         capturedImage: frontImage || rearImage || ticket.capturedImage,
         frontImage: frontImage || ticket.frontImage,
         rearImage: rearImage || ticket.rearImage,
@@ -456,7 +465,7 @@ export default function UnifiedWeighmentForm({
           grossWeight: liveWeight,
           tareWeight: validTare.tareWeight,
           netWeight,
-          charges: chargesAmount,
+          chargesMinor,
           capturedImage: frontImage || rearImage,
           frontImage: frontImage,
           rearImage: rearImage,
@@ -1,5 +1,6 @@
 import { forwardRef, useState, useCallback } from 'react';
 import { Bill } from '@/types/weighment';
+import { formatMinor } from '@/lib/money';
 import { PrintTemplate } from '@/types/printTemplate';
 import { format } from 'date-fns';
 import { Move } from 'lucide-react';
@@ -22,8 +23,8 @@ export const PrintTemplateComponent = forwardRef<HTMLDivElement, PrintTemplatePr
       return weight ? `${weight.toFixed(2)} kg` : '-';
     };
 
-    const formatCurrency = (amount: number) => {
-      return `₹${amount.toLocaleString()}`;
+    const formatCurrency = (minor: number) => {
+      return `₹${formatMinor(minor)}`;
     };
 
     const formatDateTime = (dateStr: string) => {
@@ -163,7 +164,7 @@ export const PrintTemplateComponent = forwardRef<HTMLDivElement, PrintTemplatePr
         )}
         {renderField('netWeight', formatWeight(bill.netWeight), 'Net Weight')}
         {renderField('dateTime', formatDateTime(bill.createdAt), 'Date & Time')}
-        {renderField('amount', formatCurrency(bill.charges), 'Amount')}
+        {renderField('amount', formatCurrency(bill.chargesMinor), 'Amount')}
 
         {/* Front Camera Image */}
         {(editMode || bill.frontImage) && (
@@ -24,6 +24,24 @@ export const AuthProvider: React.FC<{ children: React.ReactNode }> = ({ children
   const [user, setUser] = useState<User | null>(null);
 
   const login = async (username: string, password: string) => {
+    const { isDevelopmentMode } = await import('@/services/database/localStorageAdapter');
+    if (!isDevelopmentMode()) {
+      // Desktop: the backend checks the password and issues the session
+      // token every other command needs
+      const { signIn, getUserById } = await import('@/services/database/userRepository');
+      const sessionUser = await signIn(username, password);
+      const dbUser = await getUserById(sessionUser.id);
+      setUser({
+        id: sessionUser.id,
+        username: sessionUser.username,
+        email: dbUser?.email || '',
+        role: sessionUser.role as UserRole,
+        isActive: true,
+        lastLoginAt: new Date().toISOString()
+      });
+      return;
+    }
+
     const { getUserByUsername, verifyPassword, incrementFailedAttempts, resetFailedAttempts, lockUserAccount, updateLastLogin } = await import('@/services/database/userRepository');
     const { logSecurityEvent } = await import('@/services/database/securityLogger');
 
@@ -101,6 +119,8 @@ export const AuthProvider: React.FC<{ children: React.ReactNode }> = ({ children
       const { logSecurityEvent } = await import('@/services/database/securityLogger');
       await logSecurityEvent('LOGOUT', user.id, 'User logged out');
     }
+    const { signOut } = await import('@/services/database/userRepository');
+    await signOut();
     setUser(null);
   };
 
@@ -0,0 +1,24 @@
+// Amounts are integer paise, as the backend stores them (money.rs). Rupees
+// typed in are converted with toMinor; paise are shown with formatMinor.
+
+// Rupees as typed ("150", "150.5", "-12.05") to paise, half-up like the
+// backend's to_minor; NaN when not an amount
+export function toMinor(rupees: string): number {
+  const match = /^\s*(-)?(\d*)(?:\.(\d*))?\s*$/.exec(rupees);
+  if (!match || (!match[2] && !match[3])) {
+    return NaN;
+  }
+  const [, sign, whole, fraction = ''] = match;
+  const paise = Number(fraction.slice(0, 2).padEnd(2, '0'));
+  const roundUp = Number(fraction.charAt(2) || '0') >= 5 ? 1 : 0;
+  const minor = Number(whole || '0') * 100 + paise + roundUp;
+  return sign ? -minor : minor;
+}
+
+// Paise as a rupee figure, e.g. -1205 -> "-12.05"
+export function formatMinor(minor: number): string {
+  const sign = minor < 0 ? '-' : '';
+  const abs = Math.abs(Math.trunc(minor));
+  const rupees = Math.floor(abs / 100).toLocaleString('en-IN');
+  return `${sign}${rupees}.${String(abs % 100).padStart(2, '0')}`;
+}
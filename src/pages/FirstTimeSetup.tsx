@@ -8,7 +8,8 @@ import { Label } from '@/components/ui/label';
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '@/components/ui/card';
 import { Alert, AlertDescription } from '@/components/ui/alert';
 import { PasswordStrength, validatePassword } from '@/components/setup/PasswordStrength';
-import { createUser } from '@/services/database/userRepository';
+import { createInitialAdmin, signIn, signOut } from '@/services/database/userRepository';
+import { isDevelopmentMode } from '@/services/database/localStorageAdapter';
 import { markSetupCompleted } from '@/services/database/connection';
 import { logSecurityEvent } from '@/services/database/securityLogger';
 import { useToast } from '@/hooks/use-toast';
@@ -79,15 +80,22 @@ export default function FirstTimeSetup({ onSetupComplete }: FirstTimeSetupProps)
 
     setLoading(true);
     try {
-      const userId = await createUser({
+      const userId = await createInitialAdmin({
         username: username.trim(),
         email: email.trim() || null,
         password,
-        role: 'super_admin',
       });
 
-      await markSetupCompleted();
-      await logSecurityEvent('SETUP_COMPLETED', userId, 'Initial super admin account created');
+      // The desktop backend needs a session to record setup as done
+      if (!isDevelopmentMode()) {
+        await signIn(username.trim(), password);
+      }
+      try {
+        await markSetupCompleted();
+        await logSecurityEvent('SETUP_COMPLETED', userId, 'Initial super admin account created');
+      } finally {
+        await signOut();
+      }
 
       if (onSetupComplete) {
         onSetupComplete();
@@ -342,7 +350,7 @@ export default function FirstTimeSetup({ onSetupComplete }: FirstTimeSetupProps)
                   <Alert>
                     <Shield className="h-4 w-4" />
                     <AlertDescription>
-                      <strong>Security Reminder:</strong> Your credentials are stored securely with bcrypt hashing.
+                      <strong>Security Reminder:</strong> Your password is stored only as a secure hash.
                       There is no password recovery - keep your credentials safe.
                     </AlertDescription>
                   </Alert>
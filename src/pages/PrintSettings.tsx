@@ -24,7 +24,7 @@ const SAMPLE_BILL: Bill = {
   grossWeight: 2500,
   tareWeight: 500,
   netWeight: 2000,
-  charges: 150,
+  chargesMinor: 15000,
   capturedImage: null,
   frontImage: 'https://images.unsplash.com/photo-1511527844068-006b95d162c8?w=400',
   rearImage: 'https://images.unsplash.com/photo-1519003722824-194d4455a60c?w=400',
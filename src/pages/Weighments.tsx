@@ -9,6 +9,7 @@ import { Tooltip, TooltipContent, TooltipProvider, TooltipTrigger } from '@/comp
 import { useAuth } from '@/contexts/AuthContext';
 import { getBills } from '@/services/unifiedServices';
 import { Bill } from '@/types/weighment';
+import { formatMinor } from '@/lib/money';
 import BillPrintView from '@/components/operator/BillPrintView';
 
 export default function Weighments() {
@@ -147,7 +148,7 @@ export default function Weighments() {
                         {bill.netWeight !== null ? `${bill.netWeight.toLocaleString()} KG` : '-'}
                       </td>
                       <td className="p-3 text-sm text-right font-semibold">
-                        ₹{bill.charges.toFixed(2)}
+                        ₹{formatMinor(bill.chargesMinor)}
                       </td>
                       <td className="p-3 text-right">
                         <Badge
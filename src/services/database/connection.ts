@@ -43,8 +43,34 @@ function isTauriAvailable(): boolean {
   }
 }
 
-// Type-safe invoke wrapper
-async function invoke<T = any>(cmd: string, args?: any): Promise<T> {
+// Session token issued by verify_login. The backend refuses every command
+// but the sign-in ones without it. Kept in memory only, like the signed-in user.
+let sessionToken: string | null = null;
+
+export function setSessionToken(token: string | null): void {
+  sessionToken = token;
+}
+
+export function getSessionToken(): string | null {
+  return sessionToken;
+}
+
+// Thrown by invoke when the Tauri bridge cannot be reached. Only then may a
+// database call fall back to localStorage: anything the backend answers,
+// a refusal included, is final.
+class TauriUnavailableError extends Error {
+  constructor() {
+    super('Tauri not available - should use localStorage adapter');
+    this.name = 'TauriUnavailableError';
+  }
+}
+
+function isTauriUnavailable(error: unknown): boolean {
+  return error instanceof TauriUnavailableError;
+}
+
+// Type-safe invoke wrapper; adds the session token to every command
+export async function invoke<T = any>(cmd: string, args?: any): Promise<T> {
   // Initialize Tauri API if not already done
   if (!tauriInvoke && isTauriAvailable()) {
     await initTauriAPI();
@@ -53,7 +79,8 @@ async function invoke<T = any>(cmd: string, args?: any): Promise<T> {
   if (tauriInvoke) {
     try {
       console.log(`[Tauri] Invoking command: ${cmd}`);
-      const result = await tauriInvoke(cmd, args);
+      const payload = sessionToken ? { ...args, sessionToken } : args;
+      const result = await tauriInvoke(cmd, payload);
       console.log(`[Tauri] Command ${cmd} succeeded`);
       return result as T;
     } catch (error) {
@@ -62,7 +89,7 @@ async function invoke<T = any>(cmd: string, args?: any): Promise<T> {
     }
   }
   
-  throw new Error('Tauri not available - should use localStorage adapter');
+  throw new TauriUnavailableError();
 }
 
 /**
@@ -115,40 +142,65 @@ export async function executeQuery<T = any>(
     return result as T[];
   } catch (error) {
     console.error('❌ [DB Query] Tauri backend failed:', error);
+    if (!isTauriUnavailable(error)) throw error;
     console.warn('⚠️ [DB Query] Attempting localStorage fallback...');
     return localStorageExecuteQuery<T>(query, params);
   }
 }
 
+export interface WriteResult {
+  last_insert_id: number;
+  rows_affected: number;
+}
+
 /**
  * Execute a SQL query that doesn't return results (INSERT, UPDATE, DELETE)
+ * and return the inserted row id and affected row count. Both are read on
+ * the connection that ran the statement, so no follow-up
+ * `SELECT last_insert_rowid()` is needed.
  * @param query - SQL query string
  * @param params - Query parameters
  */
-export async function executeNonQuery(
+export async function executeWrite(
   query: string,
   params: any[] = []
-): Promise<void> {
+): Promise<WriteResult> {
   const inDesktopMode = isTauriAvailable();
   
   console.log(`[DB NonQuery] Desktop mode: ${inDesktopMode}`);
   
   if (isDevelopmentMode() || !inDesktopMode) {
     console.log('[DB NonQuery] Using localStorage adapter');
-    return localStorageExecuteNonQuery(query, params);
+    await localStorageExecuteNonQuery(query, params);
+    // The localStorage adapter has no row ids
+    return { last_insert_id: 0, rows_affected: 0 };
   }
 
   try {
     console.log('[DB NonQuery] Using Tauri SQLite backend');
-    await invoke('execute_non_query', { query, params });
+    const result = await invoke<WriteResult>('execute_non_query', { query, params });
     console.log('[DB NonQuery] ✅ Success');
+    return result;
   } catch (error) {
     console.error('❌ [DB NonQuery] Tauri backend failed:', error);
+    if (!isTauriUnavailable(error)) throw error;
     console.warn('⚠️ [DB NonQuery] Attempting localStorage fallback...');
-    return localStorageExecuteNonQuery(query, params);
+    await localStorageExecuteNonQuery(query, params);
+    return { last_insert_id: 0, rows_affected: 0 };
   }
 }
 
+/**
+ * @deprecated Use executeWrite, which also returns the inserted row id and
+ * affected row count.
+ */
+export async function executeNonQuery(
+  query: string,
+  params: any[] = []
+): Promise<void> {
+  await executeWrite(query, params);
+}
+
 /**
  * Check if the database setup is completed
  */
@@ -0,0 +1,4 @@
+-- Baseline: the tables and triggers in schema.sql as of the first versioned
+-- release. schema.sql is still applied before migrations run, so this
+-- script only records the starting version.
+SELECT 1;
@@ -0,0 +1,4 @@
+-- ONVIF PTZ preset recalled before a lane camera's snapshot
+ALTER TABLE lane_cameras ADD COLUMN ptz_url TEXT;
+ALTER TABLE lane_cameras ADD COLUMN ptz_profile TEXT;
+ALTER TABLE lane_cameras ADD COLUMN ptz_preset TEXT;
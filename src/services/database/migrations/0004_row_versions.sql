@@ -0,0 +1,196 @@
+-- Change tracking for delta sync. Every tracked row carries the sync clock
+-- value of its last write in row_version; deletes leave a tombstone. Run by
+-- delta_sync::migrate, which then numbers the rows already there.
+CREATE TABLE IF NOT EXISTS sync_clock (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    version INTEGER NOT NULL
+);
+INSERT OR IGNORE INTO sync_clock (id, version) VALUES (1, 0);
+
+CREATE TABLE IF NOT EXISTS sync_tombstones (
+    table_name TEXT NOT NULL,
+    row_key TEXT NOT NULL,
+    row_version INTEGER NOT NULL,
+    deleted_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (table_name, row_key)
+);
+
+ALTER TABLE weighments ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_weighments_row_version ON weighments(row_version);
+
+CREATE TRIGGER weighments_row_version_insert
+AFTER INSERT ON weighments
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE weighments SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+    DELETE FROM sync_tombstones WHERE table_name = 'weighments' AND row_key = CAST(NEW.id AS TEXT);
+END;
+
+-- Writes that set row_version themselves (and this trigger's own) are left alone
+CREATE TRIGGER weighments_row_version_update
+AFTER UPDATE ON weighments
+WHEN NEW.row_version = OLD.row_version
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE weighments SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+END;
+
+CREATE TRIGGER weighments_row_version_delete
+AFTER DELETE ON weighments
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    INSERT OR REPLACE INTO sync_tombstones (table_name, row_key, row_version)
+    VALUES ('weighments', CAST(OLD.id AS TEXT), (SELECT version FROM sync_clock WHERE id = 1));
+END;
+
+ALTER TABLE open_tickets ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_open_tickets_row_version ON open_tickets(row_version);
+
+CREATE TRIGGER open_tickets_row_version_insert
+AFTER INSERT ON open_tickets
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE open_tickets SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+    DELETE FROM sync_tombstones WHERE table_name = 'open_tickets' AND row_key = CAST(NEW.id AS TEXT);
+END;
+
+-- Writes that set row_version themselves (and this trigger's own) are left alone
+CREATE TRIGGER open_tickets_row_version_update
+AFTER UPDATE ON open_tickets
+WHEN NEW.row_version = OLD.row_version
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE open_tickets SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+END;
+
+CREATE TRIGGER open_tickets_row_version_delete
+AFTER DELETE ON open_tickets
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    INSERT OR REPLACE INTO sync_tombstones (table_name, row_key, row_version)
+    VALUES ('open_tickets', CAST(OLD.id AS TEXT), (SELECT version FROM sync_clock WHERE id = 1));
+END;
+
+ALTER TABLE vehicles ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_vehicles_row_version ON vehicles(row_version);
+
+CREATE TRIGGER vehicles_row_version_insert
+AFTER INSERT ON vehicles
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE vehicles SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+    DELETE FROM sync_tombstones WHERE table_name = 'vehicles' AND row_key = CAST(NEW.id AS TEXT);
+END;
+
+-- Writes that set row_version themselves (and this trigger's own) are left alone
+CREATE TRIGGER vehicles_row_version_update
+AFTER UPDATE ON vehicles
+WHEN NEW.row_version = OLD.row_version
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE vehicles SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+END;
+
+CREATE TRIGGER vehicles_row_version_delete
+AFTER DELETE ON vehicles
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    INSERT OR REPLACE INTO sync_tombstones (table_name, row_key, row_version)
+    VALUES ('vehicles', CAST(OLD.id AS TEXT), (SELECT version FROM sync_clock WHERE id = 1));
+END;
+
+ALTER TABLE parties ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_parties_row_version ON parties(row_version);
+
+CREATE TRIGGER parties_row_version_insert
+AFTER INSERT ON parties
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE parties SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+    DELETE FROM sync_tombstones WHERE table_name = 'parties' AND row_key = CAST(NEW.id AS TEXT);
+END;
+
+-- Writes that set row_version themselves (and this trigger's own) are left alone
+CREATE TRIGGER parties_row_version_update
+AFTER UPDATE ON parties
+WHEN NEW.row_version = OLD.row_version
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE parties SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+END;
+
+CREATE TRIGGER parties_row_version_delete
+AFTER DELETE ON parties
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    INSERT OR REPLACE INTO sync_tombstones (table_name, row_key, row_version)
+    VALUES ('parties', CAST(OLD.id AS TEXT), (SELECT version FROM sync_clock WHERE id = 1));
+END;
+
+ALTER TABLE products ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_products_row_version ON products(row_version);
+
+CREATE TRIGGER products_row_version_insert
+AFTER INSERT ON products
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE products SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+    DELETE FROM sync_tombstones WHERE table_name = 'products' AND row_key = CAST(NEW.id AS TEXT);
+END;
+
+-- Writes that set row_version themselves (and this trigger's own) are left alone
+CREATE TRIGGER products_row_version_update
+AFTER UPDATE ON products
+WHEN NEW.row_version = OLD.row_version
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE products SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+END;
+
+CREATE TRIGGER products_row_version_delete
+AFTER DELETE ON products
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    INSERT OR REPLACE INTO sync_tombstones (table_name, row_key, row_version)
+    VALUES ('products', CAST(OLD.id AS TEXT), (SELECT version FROM sync_clock WHERE id = 1));
+END;
+
+ALTER TABLE transporters ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_transporters_row_version ON transporters(row_version);
+
+CREATE TRIGGER transporters_row_version_insert
+AFTER INSERT ON transporters
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE transporters SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+    DELETE FROM sync_tombstones WHERE table_name = 'transporters' AND row_key = CAST(NEW.id AS TEXT);
+END;
+
+-- Writes that set row_version themselves (and this trigger's own) are left alone
+CREATE TRIGGER transporters_row_version_update
+AFTER UPDATE ON transporters
+WHEN NEW.row_version = OLD.row_version
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    UPDATE transporters SET row_version = (SELECT version FROM sync_clock WHERE id = 1)
+    WHERE rowid = NEW.rowid;
+END;
+
+CREATE TRIGGER transporters_row_version_delete
+AFTER DELETE ON transporters
+BEGIN
+    UPDATE sync_clock SET version = version + 1 WHERE id = 1;
+    INSERT OR REPLACE INTO sync_tombstones (table_name, row_key, row_version)
+    VALUES ('transporters', CAST(OLD.id AS TEXT), (SELECT version FROM sync_clock WHERE id = 1));
+END;
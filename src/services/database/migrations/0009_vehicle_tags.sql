@@ -0,0 +1,10 @@
+-- RFID and proximity tags bound to vehicles (rfid.rs). A vehicle can carry
+-- several tags; a tag identifies one vehicle.
+CREATE TABLE IF NOT EXISTS vehicle_tags (
+    tag TEXT PRIMARY KEY,
+    vehicle_id TEXT NOT NULL,
+    bound_by TEXT,
+    bound_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (vehicle_id) REFERENCES vehicles(id)
+);
+CREATE INDEX IF NOT EXISTS idx_vehicle_tags_vehicle ON vehicle_tags(vehicle_id);
@@ -0,0 +1,5 @@
+-- Site whose key signed a weighment pulled by sync; NULL when signed here
+ALTER TABLE weighment_signatures ADD COLUMN origin_site TEXT;
+-- Why a pulled row was not applied when it is not the later write,
+-- e.g. 'period_locked'
+ALTER TABLE sync_conflicts ADD COLUMN reason TEXT;
@@ -7,7 +7,7 @@ CREATE TABLE IF NOT EXISTS users (
     username TEXT UNIQUE NOT NULL,
     email TEXT,
     password_hash TEXT NOT NULL,
-    role TEXT CHECK(role IN ('super_admin', 'admin', 'operator')) NOT NULL,
+    role TEXT CHECK(role IN ('super_admin', 'admin', 'operator', 'auditor')) NOT NULL,
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
     updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
     is_active INTEGER DEFAULT 1,
@@ -44,7 +44,8 @@ CREATE TABLE IF NOT EXISTS weighments (
     gross_weight REAL,
     tare_weight REAL,
     net_weight REAL,
-    charges REAL DEFAULT 0,
+    -- Paise
+    charges_minor INTEGER NOT NULL DEFAULT 0,
     front_camera_image TEXT,
     back_camera_image TEXT,
     status TEXT CHECK(status IN ('OPEN', 'CLOSED', 'PRINTED')) NOT NULL,
@@ -72,6 +73,20 @@ CREATE TABLE IF NOT EXISTS open_tickets (
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP
 );
 
+-- Ticket numbers shown to drivers before capture. A released or expired
+-- number is handed out again, so abandoned flows leave no gaps.
+CREATE TABLE IF NOT EXISTS ticket_number_reservations (
+    serial TEXT PRIMARY KEY,
+    token TEXT UNIQUE,
+    status TEXT NOT NULL CHECK (status IN ('RESERVED', 'RELEASED', 'USED')),
+    purpose TEXT,
+    reserved_by TEXT,
+    reserved_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    expires_at DATETIME NOT NULL,
+    weighment_id TEXT,
+    closed_at DATETIME
+);
+
 -- Stored tares table
 CREATE TABLE IF NOT EXISTS stored_tares (
     id TEXT PRIMARY KEY,
@@ -110,3 +125,1401 @@ INSERT OR IGNORE INTO app_config (key, value) VALUES ('auto_backup_enabled', 'tr
 INSERT OR IGNORE INTO app_config (key, value) VALUES ('auto_backup_time', '02:00');
 INSERT OR IGNORE INTO app_config (key, value) VALUES ('backup_retention_days', '30');
 INSERT OR IGNORE INTO app_config (key, value) VALUES ('serial_number_config', '{"prefix":"WB","separator":"-","includeYear":true,"includeMonth":false,"yearFormat":"YYYY","counterStart":1,"counterPadding":3,"currentCounter":1,"resetFrequency":"yearly"}');
+
+-- Anomaly review queue populated by backend analytics
+CREATE TABLE IF NOT EXISTS weighment_anomalies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    details TEXT NOT NULL,
+    score REAL NOT NULL,
+    status TEXT CHECK(status IN ('PENDING', 'CONFIRMED', 'DISMISSED')) NOT NULL DEFAULT 'PENDING',
+    detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    reviewed_by TEXT,
+    reviewed_at DATETIME,
+    review_note TEXT,
+    UNIQUE(weighment_id, kind),
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Backend notifications addressed to a user role
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    audience TEXT NOT NULL,
+    title TEXT NOT NULL,
+    message TEXT NOT NULL,
+    entity TEXT,
+    entity_id TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    read_at DATETIME
+);
+
+-- Fraud rule hits raised at ticket completion
+CREATE TABLE IF NOT EXISTS fraud_flags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    rule TEXT NOT NULL,
+    details TEXT NOT NULL,
+    flagged_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(weighment_id, rule),
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Tickets weighed while training mode was on; excluded from reports, exports and sync
+CREATE TABLE IF NOT EXISTS practice_tickets (
+    weighment_id TEXT PRIMARY KEY,
+    tagged_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Tickets being moved to or from the archive database; filled and emptied
+-- inside one transaction, and lets the move past the period lock and the
+-- immutable-record triggers
+CREATE TABLE IF NOT EXISTS archive_moves (
+    weighment_id TEXT PRIMARY KEY
+);
+
+-- Tickets restored from the archive keep the tag they had
+DROP TRIGGER IF EXISTS weighments_tag_practice;
+CREATE TRIGGER weighments_tag_practice
+AFTER INSERT ON weighments
+WHEN (SELECT value FROM app_config WHERE key = 'training_mode') = 'true'
+  AND NEW.id NOT IN (SELECT weighment_id FROM archive_moves)
+BEGIN
+    INSERT OR IGNORE INTO practice_tickets (weighment_id) VALUES (NEW.id);
+END;
+
+-- Period locking: reject writes to weighments dated on or before app_config.period_lock_date
+DROP TRIGGER IF EXISTS weighments_period_lock_insert;
+CREATE TRIGGER weighments_period_lock_insert
+BEFORE INSERT ON weighments
+WHEN date(NEW.created_at, 'localtime') <= (SELECT value FROM app_config WHERE key = 'period_lock_date')
+  AND NEW.id NOT IN (SELECT weighment_id FROM archive_moves)
+BEGIN
+    SELECT RAISE(ABORT, 'PERIOD_LOCKED: weighment date falls within a locked period');
+END;
+
+-- A granted, unused LOCKED_PERIOD supervisor override lets one ticket through,
+-- and tickets restored from the archive pass
+DROP TRIGGER IF EXISTS weighments_period_lock_update;
+CREATE TRIGGER weighments_period_lock_update
+BEFORE UPDATE ON weighments
+WHEN (date(OLD.created_at, 'localtime') <= (SELECT value FROM app_config WHERE key = 'period_lock_date')
+   OR date(NEW.created_at, 'localtime') <= (SELECT value FROM app_config WHERE key = 'period_lock_date'))
+  AND OLD.id NOT IN (SELECT entity_id FROM supervisor_overrides
+                     WHERE action = 'LOCKED_PERIOD' AND entity = 'weighment'
+                       AND granted = 1 AND used_at IS NULL)
+  AND OLD.id NOT IN (SELECT weighment_id FROM archive_moves)
+BEGIN
+    SELECT RAISE(ABORT, 'PERIOD_LOCKED: weighment date falls within a locked period');
+END;
+
+-- Practice tickets may always be purged, and archived tickets moved out
+-- (recreated so existing databases pick this up)
+DROP TRIGGER IF EXISTS weighments_period_lock_delete;
+CREATE TRIGGER weighments_period_lock_delete
+BEFORE DELETE ON weighments
+WHEN date(OLD.created_at, 'localtime') <= (SELECT value FROM app_config WHERE key = 'period_lock_date')
+  AND OLD.id NOT IN (SELECT weighment_id FROM practice_tickets)
+  AND OLD.id NOT IN (SELECT weighment_id FROM archive_moves)
+BEGIN
+    SELECT RAISE(ABORT, 'PERIOD_LOCKED: weighment date falls within a locked period');
+END;
+
+-- Generic approval queue (amendments, voids, overrides)
+CREATE TABLE IF NOT EXISTS approvals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    entity TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    payload TEXT,
+    approver_role TEXT CHECK(approver_role IN ('operator', 'admin', 'super_admin')) NOT NULL,
+    requested_by TEXT NOT NULL,
+    requested_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    status TEXT CHECK(status IN ('PENDING', 'APPROVED', 'REJECTED')) NOT NULL DEFAULT 'PENDING',
+    decided_by TEXT,
+    decided_at DATETIME,
+    comment TEXT
+);
+
+-- Correction requests against weighments; applied only on supervisor approval
+CREATE TABLE IF NOT EXISTS weighment_amendments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    reason TEXT NOT NULL,
+    requested_by TEXT NOT NULL,
+    requested_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    status TEXT CHECK(status IN ('PENDING', 'APPROVED', 'REJECTED')) NOT NULL DEFAULT 'PENDING',
+    decided_by TEXT,
+    decided_at DATETIME,
+    decision_comment TEXT,
+    approval_id INTEGER,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (approval_id) REFERENCES approvals(id)
+);
+
+-- Amendments are immutable: never deleted (except when purging practice tickets
+-- or moving them to the archive), and only a pending request may be decided
+DROP TRIGGER IF EXISTS weighment_amendments_no_delete;
+CREATE TRIGGER weighment_amendments_no_delete
+BEFORE DELETE ON weighment_amendments
+WHEN OLD.weighment_id NOT IN (SELECT weighment_id FROM practice_tickets)
+  AND OLD.weighment_id NOT IN (SELECT weighment_id FROM archive_moves)
+BEGIN
+    SELECT RAISE(ABORT, 'Amendment records cannot be deleted');
+END;
+
+CREATE TRIGGER IF NOT EXISTS weighment_amendments_immutable
+BEFORE UPDATE ON weighment_amendments
+WHEN OLD.status != 'PENDING'
+  OR NEW.weighment_id IS NOT OLD.weighment_id
+  OR NEW.field IS NOT OLD.field
+  OR NEW.old_value IS NOT OLD.old_value
+  OR NEW.new_value IS NOT OLD.new_value
+  OR NEW.reason IS NOT OLD.reason
+  OR NEW.requested_by IS NOT OLD.requested_by
+  OR NEW.requested_at IS NOT OLD.requested_at
+  OR NEW.approval_id IS NOT OLD.approval_id
+BEGIN
+    SELECT RAISE(ABORT, 'Decided amendments cannot be modified');
+END;
+
+-- Voided tickets: the weighment row and its number are kept, the void is recorded here
+CREATE TABLE IF NOT EXISTS ticket_voids (
+    weighment_id TEXT PRIMARY KEY,
+    reason TEXT NOT NULL,
+    voided_by TEXT NOT NULL,
+    voided_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    approval_id INTEGER,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (approval_id) REFERENCES approvals(id)
+);
+
+CREATE TRIGGER IF NOT EXISTS ticket_voids_period_lock
+BEFORE INSERT ON ticket_voids
+WHEN (SELECT date(created_at, 'localtime') FROM weighments WHERE id = NEW.weighment_id)
+     <= (SELECT value FROM app_config WHERE key = 'period_lock_date')
+BEGIN
+    SELECT RAISE(ABORT, 'PERIOD_LOCKED: weighment date falls within a locked period');
+END;
+
+DROP TRIGGER IF EXISTS ticket_voids_no_delete;
+CREATE TRIGGER ticket_voids_no_delete
+BEFORE DELETE ON ticket_voids
+WHEN OLD.weighment_id NOT IN (SELECT weighment_id FROM practice_tickets)
+BEGIN
+    SELECT RAISE(ABORT, 'Voids cannot be undone');
+END;
+
+-- Access tokens issued to parties for the mobile companion API (SHA-256 hashes only)
+CREATE TABLE IF NOT EXISTS party_access_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token_hash TEXT UNIQUE NOT NULL,
+    party_name TEXT NOT NULL,
+    label TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_used_at DATETIME,
+    revoked_at DATETIME
+);
+
+-- Paper size and calibration per configured printer
+CREATE TABLE IF NOT EXISTS printer_profiles (
+    name TEXT PRIMARY KEY,
+    paper_size TEXT CHECK(paper_size IN ('a4', 'a5', 'continuous')) NOT NULL,
+    offset_x_mm REAL NOT NULL DEFAULT 0,
+    offset_y_mm REAL NOT NULL DEFAULT 0
+);
+
+-- Results of restoring backups into a scratch location and checking them
+CREATE TABLE IF NOT EXISTS backup_verifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    backup_path TEXT NOT NULL,
+    ok INTEGER NOT NULL,
+    integrity TEXT NOT NULL,
+    tables TEXT NOT NULL,
+    error TEXT,
+    verified_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Privileged command invocations (append-only), separate from security_logs
+CREATE TABLE IF NOT EXISTS command_audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT,
+    command TEXT NOT NULL,
+    args_digest TEXT NOT NULL,
+    decision TEXT CHECK(decision IN ('ALLOW', 'DENY')) NOT NULL,
+    error TEXT,
+    duration_ms INTEGER NOT NULL,
+    invoked_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_command_audit_log_user ON command_audit_log(user_id);
+
+CREATE TRIGGER IF NOT EXISTS command_audit_log_no_update
+BEFORE UPDATE ON command_audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'Command audit log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS command_audit_log_no_delete
+BEFORE DELETE ON command_audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'Command audit log is append-only');
+END;
+
+-- Data writes made through the generic SQL commands (append-only). Written in
+-- the same transaction as the write, so a rolled-back write leaves no entry.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    command TEXT NOT NULL,
+    table_name TEXT,
+    sql TEXT NOT NULL,
+    params_digest TEXT NOT NULL,
+    rows_affected INTEGER NOT NULL,
+    user_id TEXT,
+    logged_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_table ON audit_log(table_name, logged_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_user ON audit_log(user_id);
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update
+BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'Audit log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete
+BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'Audit log is append-only');
+END;
+
+-- Feature flags per site ('*' = all sites); remote rows come from synced config
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT NOT NULL,
+    site_id TEXT NOT NULL DEFAULT '*',
+    source TEXT CHECK(source IN ('local', 'remote')) NOT NULL DEFAULT 'local',
+    enabled INTEGER NOT NULL,
+    updated_by TEXT,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (name, site_id, source)
+);
+
+INSERT OR IGNORE INTO feature_flags (name, enabled) VALUES ('sync', 0);
+INSERT OR IGNORE INTO feature_flags (name, enabled) VALUES ('kiosk_mode', 0);
+INSERT OR IGNORE INTO feature_flags (name, enabled) VALUES ('anpr', 0);
+
+-- Per-material weight capture criteria (materials without a row use backend defaults)
+CREATE TABLE IF NOT EXISTS material_capture_rules (
+    product_name TEXT PRIMARY KEY,
+    min_weight_kg REAL NOT NULL DEFAULT 0,
+    stability_seconds REAL NOT NULL DEFAULT 2,
+    second_weighing_required INTEGER NOT NULL DEFAULT 0
+);
+
+-- Weighing sequences for tickets weighed more than twice; `steps` is a JSON
+-- array of {label, role} with role 'tare', 'gross' or 'info'
+CREATE TABLE IF NOT EXISTS weighing_sequences (
+    name TEXT PRIMARY KEY,
+    steps TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Sequence a ticket was started with, copied so later edits leave it alone
+CREATE TABLE IF NOT EXISTS weighment_sequences (
+    weighment_id TEXT PRIMARY KEY,
+    sequence_name TEXT NOT NULL,
+    steps TEXT NOT NULL,
+    started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+CREATE TABLE IF NOT EXISTS weighment_steps (
+    weighment_id TEXT NOT NULL,
+    step_no INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    role TEXT NOT NULL CHECK(role IN ('tare', 'gross', 'info')),
+    weight_kg REAL NOT NULL,
+    captured_by TEXT NOT NULL,
+    captured_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (weighment_id, step_no),
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Contract deductions (moisture, dust, quality) per party and/or material
+CREATE TABLE IF NOT EXISTS deduction_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    party_name TEXT,
+    product_name TEXT,
+    moisture_pct REAL NOT NULL DEFAULT 0,
+    dust_pct REAL NOT NULL DEFAULT 0,
+    quality_penalty_pct REAL NOT NULL DEFAULT 0,
+    quality_penalty_kg REAL NOT NULL DEFAULT 0,
+    description TEXT,
+    active INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Measured vs payable net for weighments that had deductions applied
+CREATE TABLE IF NOT EXISTS weighment_net_adjustments (
+    weighment_id TEXT PRIMARY KEY,
+    original_net REAL NOT NULL,
+    adjusted_net REAL NOT NULL,
+    rule_id INTEGER,
+    applied_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Individual deduction lines with the reason each was applied
+CREATE TABLE IF NOT EXISTS weighment_deductions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    kind TEXT CHECK(kind IN ('MOISTURE', 'DUST', 'QUALITY')) NOT NULL,
+    rate_pct REAL,
+    amount_kg REAL NOT NULL,
+    reason TEXT NOT NULL,
+    rule_id INTEGER,
+    applied_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Purchase orders / contracts with a quantity limit per party (and optionally material)
+CREATE TABLE IF NOT EXISTS purchase_orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    po_number TEXT UNIQUE NOT NULL,
+    party_name TEXT NOT NULL,
+    product_name TEXT,
+    quantity_kg REAL NOT NULL,
+    valid_from DATE,
+    valid_to DATE,
+    enforcement TEXT CHECK(enforcement IN ('WARN', 'BLOCK')) NOT NULL DEFAULT 'WARN',
+    status TEXT CHECK(status IN ('OPEN', 'CLOSED')) NOT NULL DEFAULT 'OPEN',
+    created_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Quantity each completed weighment consumed against a purchase order
+CREATE TABLE IF NOT EXISTS po_consumptions (
+    weighment_id TEXT PRIMARY KEY,
+    po_id INTEGER NOT NULL,
+    quantity_kg REAL NOT NULL,
+    consumed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (po_id) REFERENCES purchase_orders(id)
+);
+
+-- Transporters and their freight rates
+CREATE TABLE IF NOT EXISTS transporters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT UNIQUE NOT NULL,
+    rate_type TEXT CHECK(rate_type IN ('PER_TONNE', 'PER_KM')) NOT NULL,
+    -- Paise per tonne or per km
+    rate_minor INTEGER NOT NULL,
+    active INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Transporter per ticket, with the rate in force when it was assigned
+CREATE TABLE IF NOT EXISTS weighment_transport (
+    weighment_id TEXT PRIMARY KEY,
+    transporter_id INTEGER NOT NULL,
+    rate_type TEXT NOT NULL,
+    rate_minor INTEGER NOT NULL,
+    distance_km REAL,
+    assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (transporter_id) REFERENCES transporters(id)
+);
+
+-- Consignor and consignee on a ticket, separate from the billed party
+CREATE TABLE IF NOT EXISTS weighment_parties (
+    weighment_id TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('consignor', 'consignee')),
+    party_name TEXT NOT NULL,
+    assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (weighment_id, role),
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Default flow direction per material (raw material in, finished goods out)
+CREATE TABLE IF NOT EXISTS material_movement_rules (
+    product_name TEXT PRIMARY KEY,
+    direction TEXT CHECK(direction IN ('INBOUND', 'OUTBOUND')) NOT NULL
+);
+
+-- Direction fixed per ticket at completion (RULE) or set by an operator (MANUAL)
+CREATE TABLE IF NOT EXISTS weighment_direction (
+    weighment_id TEXT PRIMARY KEY,
+    direction TEXT CHECK(direction IN ('INBOUND', 'OUTBOUND')) NOT NULL,
+    source TEXT CHECK(source IN ('RULE', 'MANUAL')) NOT NULL,
+    classified_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Stock ledger: signed quantities per material (WEIGHMENT postings and manual ADJUSTMENTs)
+CREATE TABLE IF NOT EXISTS stock_ledger (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    product_name TEXT NOT NULL,
+    weighment_id TEXT,
+    entry_type TEXT CHECK(entry_type IN ('WEIGHMENT', 'ADJUSTMENT')) NOT NULL,
+    quantity_kg REAL NOT NULL,
+    reason TEXT,
+    created_by TEXT,
+    posted_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stock_ledger_product ON stock_ledger(product_name, posted_at);
+
+-- Exchange rates: base currency units per one unit of `currency`, effective from a date
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    currency TEXT NOT NULL,
+    rate REAL NOT NULL CHECK(rate > 0),
+    effective_from DATE NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_exchange_rates_currency ON exchange_rates(currency, effective_from);
+
+-- Parties billed in a currency other than the base currency
+CREATE TABLE IF NOT EXISTS party_billing_currency (
+    party_name TEXT PRIMARY KEY,
+    currency TEXT NOT NULL
+);
+
+-- Ticket charges converted at completion, with the rate used
+CREATE TABLE IF NOT EXISTS weighment_billing (
+    weighment_id TEXT PRIMARY KEY,
+    currency TEXT NOT NULL,
+    exchange_rate REAL NOT NULL,
+    -- Paise of the base currency / minor units of the billing currency
+    base_amount_minor INTEGER NOT NULL,
+    billed_amount_minor INTEGER NOT NULL,
+    billed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Credit limit per party in base currency paise. WARN lets tickets over the
+-- limit complete with a warning; BLOCK needs a supervisor override.
+CREATE TABLE IF NOT EXISTS party_credit_limits (
+    party_name TEXT PRIMARY KEY,
+    limit_minor INTEGER NOT NULL CHECK (limit_minor >= 0),
+    mode TEXT NOT NULL CHECK (mode IN ('WARN', 'BLOCK')),
+    updated_by TEXT,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Tickets completed over their party's credit limit
+CREATE TABLE IF NOT EXISTS credit_limit_breaches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL UNIQUE,
+    party_name TEXT NOT NULL,
+    limit_minor INTEGER NOT NULL,
+    outstanding_minor INTEGER NOT NULL,
+    charge_minor INTEGER NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('WARNED', 'OVERRIDDEN')),
+    override_id INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (override_id) REFERENCES supervisor_overrides(id)
+);
+
+-- Standing instruction: bill the party once a month instead of per ticket
+CREATE TABLE IF NOT EXISTS billing_instructions (
+    party_name TEXT PRIMARY KEY,
+    cycle TEXT NOT NULL DEFAULT 'MONTHLY' CHECK (cycle IN ('MONTHLY')),
+    active INTEGER NOT NULL DEFAULT 1,
+    updated_by TEXT,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One consolidated invoice per party per month ("YYYY-MM"); totals as billed
+CREATE TABLE IF NOT EXISTS consolidated_invoices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    invoice_no TEXT NOT NULL UNIQUE,
+    party_name TEXT NOT NULL,
+    period TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    ticket_count INTEGER NOT NULL,
+    net_weight_kg REAL NOT NULL,
+    total_base_minor INTEGER NOT NULL,
+    total_billed_minor INTEGER NOT NULL,
+    -- NULL when generated by the monthly job
+    generated_by TEXT,
+    generated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (party_name, period)
+);
+
+-- A ticket is invoiced at most once
+CREATE TABLE IF NOT EXISTS consolidated_invoice_lines (
+    weighment_id TEXT PRIMARY KEY,
+    invoice_id INTEGER NOT NULL,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (invoice_id) REFERENCES consolidated_invoices(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_consolidated_invoice_lines_invoice
+    ON consolidated_invoice_lines(invoice_id);
+
+-- Site script revisions; every save of a hook's script is kept
+CREATE TABLE IF NOT EXISTS script_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    hook TEXT NOT NULL CHECK (hook IN ('on_ticket_create', 'on_weight_captured', 'on_before_print')),
+    version INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    notes TEXT,
+    created_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (hook, version)
+);
+
+-- The revision each hook runs; no row means the hook is off
+CREATE TABLE IF NOT EXISTS active_scripts (
+    hook TEXT PRIMARY KEY,
+    version_id INTEGER NOT NULL,
+    activated_by TEXT NOT NULL,
+    activated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (version_id) REFERENCES script_versions(id)
+);
+
+-- Script failures other than deliberate rejections; the action went ahead
+CREATE TABLE IF NOT EXISTS script_errors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    version_id INTEGER NOT NULL,
+    hook TEXT NOT NULL,
+    subject TEXT,
+    message TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (version_id) REFERENCES script_versions(id)
+);
+
+-- Operator shifts; amounts in paise
+CREATE TABLE IF NOT EXISTS shifts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operator_id TEXT NOT NULL,
+    opening_float_minor INTEGER NOT NULL DEFAULT 0,
+    status TEXT CHECK(status IN ('OPEN', 'CLOSED')) NOT NULL DEFAULT 'OPEN',
+    opened_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    closed_at DATETIME,
+    FOREIGN KEY (operator_id) REFERENCES users(id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_shifts_one_open ON shifts(operator_id) WHERE status = 'OPEN';
+
+CREATE TABLE IF NOT EXISTS shift_payments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    shift_id INTEGER NOT NULL,
+    weighment_id TEXT,
+    method TEXT CHECK(method IN ('CASH', 'UPI', 'CARD', 'CREDIT')) NOT NULL,
+    amount_minor INTEGER NOT NULL,
+    recorded_by TEXT NOT NULL,
+    recorded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (shift_id) REFERENCES shifts(id),
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_shift_payments_shift ON shift_payments(shift_id);
+
+-- Drawer count at shift close
+CREATE TABLE IF NOT EXISTS shift_cash_counts (
+    shift_id INTEGER NOT NULL,
+    denomination_minor INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (shift_id, denomination_minor),
+    FOREIGN KEY (shift_id) REFERENCES shifts(id)
+);
+
+CREATE TABLE IF NOT EXISTS shift_reconciliations (
+    shift_id INTEGER PRIMARY KEY,
+    cash_payments_minor INTEGER NOT NULL,
+    other_payments_minor INTEGER NOT NULL,
+    expected_minor INTEGER NOT NULL,
+    counted_minor INTEGER NOT NULL,
+    variance_minor INTEGER NOT NULL,
+    reason TEXT,
+    reconciled_by TEXT NOT NULL,
+    reconciled_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (shift_id) REFERENCES shifts(id)
+);
+
+-- Supervisor override PINs (bcrypt), separate from login passwords
+CREATE TABLE IF NOT EXISTS supervisor_pins (
+    user_id TEXT PRIMARY KEY,
+    pin_hash TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Every supervisor override attempt, granted or refused
+CREATE TABLE IF NOT EXISTS supervisor_overrides (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    action TEXT NOT NULL,
+    entity TEXT,
+    entity_id TEXT,
+    requested_by TEXT NOT NULL,
+    authorized_by TEXT,
+    method TEXT CHECK(method IN ('PIN', 'PASSWORD')) NOT NULL,
+    reason TEXT NOT NULL,
+    granted INTEGER NOT NULL,
+    refusal TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    used_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_supervisor_overrides_entity ON supervisor_overrides(action, entity_id);
+
+-- Open tickets flagged as stale, and how they were resolved
+CREATE TABLE IF NOT EXISTS stale_ticket_flags (
+    weighment_id TEXT PRIMARY KEY,
+    flagged_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME,
+    resolution TEXT CHECK(resolution IN ('CLOSE', 'VOID')),
+    resolved_by TEXT,
+    reason TEXT,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Idempotency keys: retried commands return the first call's result
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    key TEXT PRIMARY KEY,
+    command TEXT NOT NULL,
+    request_digest TEXT NOT NULL,
+    result_id TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Bulk operations run in the background, with per-ticket outcomes
+CREATE TABLE IF NOT EXISTS bulk_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    status TEXT CHECK(status IN ('RUNNING', 'COMPLETED', 'CANCELLED', 'FAILED')) NOT NULL DEFAULT 'RUNNING',
+    total INTEGER NOT NULL,
+    processed INTEGER NOT NULL DEFAULT 0,
+    succeeded INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    requested_by TEXT NOT NULL,
+    reason TEXT,
+    summary TEXT,
+    started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    finished_at DATETIME
+);
+
+CREATE TABLE IF NOT EXISTS bulk_job_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    job_id INTEGER NOT NULL,
+    weighment_id TEXT NOT NULL,
+    outcome TEXT CHECK(outcome IN ('OK', 'SKIPPED', 'FAILED')) NOT NULL,
+    message TEXT NOT NULL,
+    FOREIGN KEY (job_id) REFERENCES bulk_jobs(id)
+);
+
+-- Long operations run by the background worker pool; `request` and
+-- `result` are JSON
+CREATE TABLE IF NOT EXISTS background_tasks (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    request TEXT NOT NULL,
+    status TEXT CHECK(status IN ('QUEUED', 'RUNNING', 'COMPLETED', 'FAILED', 'CANCELLED')) NOT NULL DEFAULT 'QUEUED',
+    requested_by TEXT NOT NULL,
+    done INTEGER NOT NULL DEFAULT 0,
+    total INTEGER,
+    message TEXT,
+    result TEXT,
+    error TEXT,
+    cancel_requested INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    started_at DATETIME,
+    finished_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_background_tasks_created ON background_tasks(created_at);
+
+-- Weighing tariffs in paise; NULL party/product means "any"
+CREATE TABLE IF NOT EXISTS tariffs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    party_name TEXT,
+    product_name TEXT,
+    flat_minor INTEGER NOT NULL DEFAULT 0,
+    per_tonne_minor INTEGER NOT NULL DEFAULT 0,
+    active INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Charges changed by re-rating, with the tariff used
+CREATE TABLE IF NOT EXISTS charge_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    before_minor INTEGER NOT NULL,
+    after_minor INTEGER NOT NULL,
+    tariff_id INTEGER,
+    revised_by TEXT NOT NULL,
+    revised_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Configuration packages applied from head office, with the configuration each replaced
+CREATE TABLE IF NOT EXISTS config_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    version INTEGER NOT NULL,
+    publisher TEXT NOT NULL,
+    published_at TEXT NOT NULL,
+    bundle TEXT NOT NULL,
+    previous TEXT NOT NULL,
+    status TEXT CHECK(status IN ('APPLIED', 'ROLLED_BACK')) NOT NULL DEFAULT 'APPLIED',
+    applied_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    rolled_back_at DATETIME,
+    rolled_back_by TEXT
+);
+
+-- Application updates: pre-update backup and post-update verification
+CREATE TABLE IF NOT EXISTS update_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_version TEXT NOT NULL,
+    to_version TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    backup_path TEXT,
+    status TEXT CHECK(status IN ('PREPARED', 'VERIFIED', 'FAILED')) NOT NULL,
+    detail TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    verified_at DATETIME
+);
+
+-- Crash log files indexed on startup; uploaded only when the site opts in
+CREATE TABLE IF NOT EXISTS crash_reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_name TEXT NOT NULL UNIQUE,
+    report TEXT NOT NULL,
+    status TEXT CHECK(status IN ('LOCAL', 'PENDING_UPLOAD', 'UPLOADED')) NOT NULL DEFAULT 'LOCAL',
+    collected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    uploaded_at DATETIME
+);
+
+-- Anonymous daily usage summaries, only created when the site opts in
+CREATE TABLE IF NOT EXISTS telemetry_batches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    day TEXT NOT NULL UNIQUE,
+    payload TEXT NOT NULL,
+    status TEXT CHECK(status IN ('PENDING_UPLOAD', 'UPLOADED')) NOT NULL DEFAULT 'PENDING_UPLOAD',
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    uploaded_at DATETIME
+);
+
+-- Weighing lanes; scale_config NULL uses the site's indicator settings
+CREATE TABLE IF NOT EXISTS lanes (
+    lane_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    scale_config TEXT
+);
+
+-- Operations in flight; rows left behind were interrupted by a crash or kill
+CREATE TABLE IF NOT EXISTS operation_journal (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    entity TEXT,
+    started_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Outcome of resolving operations interrupted at the previous shutdown
+CREATE TABLE IF NOT EXISTS recovery_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    entity TEXT,
+    action TEXT CHECK(action IN ('ROLLED_BACK', 'COMPLETED', 'VERIFIED', 'REPRINT', 'FAILED')) NOT NULL,
+    detail TEXT NOT NULL,
+    started_at DATETIME NOT NULL,
+    recovered_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Capture, print and barrier steps, recorded before they run; PENDING rows
+-- left at startup were cut short and become INTERRUPTED
+CREATE TABLE IF NOT EXISTS workflow_journal (
+    id TEXT PRIMARY KEY,
+    step TEXT CHECK(step IN ('capture', 'print', 'barrier')) NOT NULL,
+    entity TEXT,
+    lane_id TEXT,
+    detail TEXT NOT NULL DEFAULT 'null',
+    status TEXT CHECK(status IN ('PENDING', 'DONE', 'FAILED', 'INTERRUPTED', 'RESOLVED')) NOT NULL DEFAULT 'PENDING',
+    error TEXT,
+    requested_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    finished_at DATETIME,
+    resolved_by TEXT,
+    resolved_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_workflow_journal_status ON workflow_journal(status);
+CREATE INDEX IF NOT EXISTS idx_workflow_journal_entity ON workflow_journal(entity);
+
+-- Daily database size and disk space samples for growth forecasting
+CREATE TABLE IF NOT EXISTS storage_samples (
+    sampled_on TEXT PRIMARY KEY,
+    db_bytes INTEGER NOT NULL,
+    attachment_bytes INTEGER NOT NULL,
+    free_bytes INTEGER,
+    total_bytes INTEGER
+);
+
+-- Every support console query, in full
+CREATE TABLE IF NOT EXISTS support_query_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    query TEXT NOT NULL,
+    row_count INTEGER,
+    error TEXT,
+    duration_ms INTEGER NOT NULL,
+    ran_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Master data version, bumped on every change so forms can skip unchanged fetches
+INSERT OR IGNORE INTO app_config (key, value) VALUES ('master_data_version', '0');
+
+CREATE TRIGGER IF NOT EXISTS vehicles_master_version_insert
+AFTER INSERT ON vehicles
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS vehicles_master_version_update
+AFTER UPDATE ON vehicles
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS vehicles_master_version_delete
+AFTER DELETE ON vehicles
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS parties_master_version_insert
+AFTER INSERT ON parties
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS parties_master_version_update
+AFTER UPDATE ON parties
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS parties_master_version_delete
+AFTER DELETE ON parties
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_master_version_insert
+AFTER INSERT ON products
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_master_version_update
+AFTER UPDATE ON products
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_master_version_delete
+AFTER DELETE ON products
+BEGIN
+    UPDATE app_config SET value = CAST(value AS INTEGER) + 1, updated_at = CURRENT_TIMESTAMP
+    WHERE key = 'master_data_version';
+END;
+
+-- Content-addressed attachment files (attachments/<hash[0..2]>/<hash>)
+CREATE TABLE IF NOT EXISTS attachments (
+    hash TEXT PRIMARY KEY,
+    mime TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Files operators attach to tickets and masters; `attachment` is an
+-- attachment store reference
+CREATE TABLE IF NOT EXISTS entity_attachments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    entity TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    mime TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    attachment TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    added_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_entity_attachments_entity ON entity_attachments(entity, entity_id);
+
+-- Chunked uploads of backups and attachments, resumable across restarts.
+-- The file is identified by size, modification time and SHA-256 so a changed
+-- file is never resumed.
+CREATE TABLE IF NOT EXISTS upload_sessions (
+    id TEXT PRIMARY KEY,
+    source TEXT NOT NULL,
+    path TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    modified_at INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    chunk_count INTEGER NOT NULL,
+    status TEXT CHECK(status IN ('PENDING', 'COMPLETE', 'ABANDONED')) NOT NULL DEFAULT 'PENDING',
+    -- Id the cloud gave the upload, set by the sync layer
+    remote_id TEXT,
+    failures INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    completed_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_upload_sessions_source ON upload_sessions(source, status);
+
+CREATE TABLE IF NOT EXISTS upload_chunks (
+    session_id TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    offset_bytes INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    uploaded_at DATETIME,
+    PRIMARY KEY (session_id, chunk_index),
+    FOREIGN KEY (session_id) REFERENCES upload_sessions(id)
+);
+
+-- Highest row_version each sync target has confirmed receiving (the
+-- change-tracking columns themselves come from migration 0004)
+CREATE TABLE IF NOT EXISTS sync_watermarks (
+    target TEXT PRIMARY KEY,
+    version INTEGER NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- When and where each tracked row last changed, for last-write-wins against
+-- the head-office server (triggers from sync_engine::migrate)
+CREATE TABLE IF NOT EXISTS sync_change_log (
+    table_name TEXT NOT NULL,
+    row_key TEXT NOT NULL,
+    op TEXT NOT NULL CHECK (op IN ('upsert', 'delete')),
+    changed_at TEXT NOT NULL,
+    -- local, or remote for changes pulled from the server
+    origin TEXT NOT NULL DEFAULT 'local',
+    PRIMARY KEY (table_name, row_key)
+);
+
+-- Rows both this site and the server changed since the last sync; the
+-- later write was kept
+CREATE TABLE IF NOT EXISTS sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    row_key TEXT NOT NULL,
+    winner TEXT NOT NULL CHECK (winner IN ('local', 'remote')),
+    local_changed_at TEXT NOT NULL,
+    remote_changed_at TEXT NOT NULL,
+    local_row TEXT,
+    remote_row TEXT,
+    detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    reviewed_by TEXT,
+    reviewed_at DATETIME
+);
+CREATE INDEX IF NOT EXISTS idx_sync_conflicts_open ON sync_conflicts(reviewed_at, detected_at);
+
+-- Applied schema migrations (see src-tauri/src/migrations.rs)
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Cameras per lane, one per role; snapshots are fetched over HTTP
+CREATE TABLE IF NOT EXISTS lane_cameras (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    lane_id TEXT NOT NULL,
+    role TEXT CHECK(role IN ('front_plate', 'rear_plate', 'load_top')) NOT NULL,
+    snapshot_url TEXT NOT NULL,
+    username TEXT,
+    password TEXT,
+    UNIQUE(lane_id, role)
+);
+
+-- Snapshots taken at weight capture, linked to the ticket once it is saved
+CREATE TABLE IF NOT EXISTS ticket_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    camera_role TEXT NOT NULL,
+    image TEXT NOT NULL,
+    captured_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_ticket_snapshots_weighment ON ticket_snapshots(weighment_id);
+
+-- Ticket disputes raised by a party or operator
+CREATE TABLE IF NOT EXISTS disputes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    raised_by TEXT NOT NULL,
+    raised_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    status TEXT CHECK(status IN ('OPEN', 'RESOLVED', 'ADJUSTED')) NOT NULL DEFAULT 'OPEN',
+    resolution TEXT,
+    resolved_by TEXT,
+    resolved_at DATETIME,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_disputes_one_open ON disputes(weighment_id) WHERE status = 'OPEN';
+
+-- Stored files still referenced by rows in the archive database
+CREATE TABLE IF NOT EXISTS archived_attachments (
+    hash TEXT PRIMARY KEY,
+    archived_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Evidence for a dispute; `attachment` is an attachment store reference
+CREATE TABLE IF NOT EXISTS dispute_attachments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    dispute_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    attachment TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    added_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (dispute_id) REFERENCES disputes(id)
+);
+
+-- Amendments made to settle a dispute
+CREATE TABLE IF NOT EXISTS dispute_amendments (
+    dispute_id INTEGER NOT NULL,
+    amendment_id INTEGER NOT NULL,
+    PRIMARY KEY (dispute_id, amendment_id),
+    FOREIGN KEY (dispute_id) REFERENCES disputes(id),
+    FOREIGN KEY (amendment_id) REFERENCES weighment_amendments(id)
+);
+
+-- CA certificates trusted for outbound HTTPS on top of the system store,
+-- e.g. a corporate proxy's inspection root
+CREATE TABLE IF NOT EXISTS ca_certificates (
+    fingerprint TEXT PRIMARY KEY,
+    label TEXT NOT NULL,
+    pem TEXT NOT NULL,
+    installed_by TEXT NOT NULL,
+    installed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Weighment signatures: HMAC-SHA256 over each ticket's identity, weights and
+-- times, written by a trigger on insert (migration 5) and renewed only by
+-- the app's own weight changes. No foreign key: a signature outliving its
+-- ticket shows the ticket was deleted.
+CREATE TABLE IF NOT EXISTS weighment_signatures (
+    weighment_id TEXT PRIMARY KEY,
+    signature TEXT,
+    signed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Reports composed in the report builder; `definition` is the JSON
+-- ReportDefinition run by report_builder.rs
+CREATE TABLE IF NOT EXISTS report_definitions (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    definition TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_by TEXT,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- End-of-day closings, one per local date; emailed_to and warnings are JSON
+-- string arrays
+CREATE TABLE IF NOT EXISTS day_closings (
+    date TEXT PRIMARY KEY,
+    closed_by TEXT NOT NULL,
+    closed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    tickets INTEGER NOT NULL,
+    net_weight_kg REAL NOT NULL,
+    amount_minor INTEGER NOT NULL,
+    resolved_open INTEGER NOT NULL DEFAULT 0,
+    backup_path TEXT,
+    register_path TEXT,
+    printed INTEGER NOT NULL DEFAULT 0,
+    emailed_to TEXT NOT NULL DEFAULT '[]',
+    warnings TEXT NOT NULL DEFAULT '[]',
+    FOREIGN KEY (closed_by) REFERENCES users(id)
+);
+
+-- Per-operator limits and preferences, keyed by the operator's users.id and
+-- synced between sites (migration 0008 adds the triggers). allowed_lanes and
+-- allowed_payment_methods are JSON string arrays, empty for no limit;
+-- preferences is a JSON object the UI owns
+CREATE TABLE IF NOT EXISTS operator_profiles (
+    id TEXT PRIMARY KEY,
+    allowed_lanes TEXT NOT NULL DEFAULT '[]',
+    default_material TEXT,
+    allowed_payment_methods TEXT NOT NULL DEFAULT '[]',
+    preferences TEXT NOT NULL DEFAULT '{}',
+    updated_by TEXT,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    row_version INTEGER NOT NULL DEFAULT 0
+);
+
+-- Number plate lookups of ticket snapshots (anpr.rs), one per front or rear
+-- plate snapshot. PENDING requests are retried from next_attempt_at while
+-- the ANPR service cannot be reached; outcome records what the plate did to
+-- the ticket: FILLED, MATCHED, MISMATCH, UNSURE, LOCKED or MISSING
+CREATE TABLE IF NOT EXISTS anpr_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    snapshot_id INTEGER UNIQUE NOT NULL,
+    weighment_id TEXT NOT NULL,
+    status TEXT CHECK(status IN ('PENDING', 'RECOGNIZED', 'NO_PLATE', 'FAILED')) NOT NULL DEFAULT 'PENDING',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    plate TEXT,
+    confidence REAL,
+    source TEXT CHECK(source IN ('CLOUD', 'LOCAL')),
+    outcome TEXT,
+    error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME
+);
+CREATE INDEX IF NOT EXISTS idx_anpr_requests_pending ON anpr_requests(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_anpr_requests_weighment ON anpr_requests(weighment_id);
+
+-- Every message the app sends out (communications.rs): emails, SMS,
+-- WhatsApp messages and webhooks, with where it went and whether it was
+-- delivered. request holds what is needed to send it again; NULL when it
+-- cannot be retried
+CREATE TABLE IF NOT EXISTS communications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    channel TEXT CHECK(channel IN ('EMAIL', 'SMS', 'WHATSAPP', 'WEBHOOK')) NOT NULL,
+    destination TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    weighment_id TEXT,
+    party_name TEXT,
+    status TEXT CHECK(status IN ('PENDING', 'SENT', 'FAILED')) NOT NULL DEFAULT 'PENDING',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    request TEXT,
+    created_by TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_attempt_at DATETIME,
+    sent_at DATETIME
+);
+CREATE INDEX IF NOT EXISTS idx_communications_weighment ON communications(weighment_id);
+CREATE INDEX IF NOT EXISTS idx_communications_party ON communications(party_name);
+
+-- Days the site does not work (calendar.rs), as local dates YYYY-MM-DD
+CREATE TABLE IF NOT EXISTS holidays (
+    date TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Weighings taken outside the working calendar while it was enforced;
+-- OVERRIDDEN rows carry the supervisor override that let a blocked one through
+CREATE TABLE IF NOT EXISTS out_of_hours_activity (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    event TEXT NOT NULL CHECK (event IN ('FIRST_WEIGHT', 'SECOND_WEIGHT')),
+    reason TEXT NOT NULL CHECK (reason IN ('HOLIDAY', 'WEEKLY_OFF', 'OUTSIDE_HOURS')),
+    detail TEXT NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('WARNED', 'OVERRIDDEN')),
+    override_id INTEGER,
+    occurred_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (weighment_id, event),
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (override_id) REFERENCES supervisor_overrides(id)
+);
+CREATE INDEX IF NOT EXISTS idx_out_of_hours_activity_occurred ON out_of_hours_activity(occurred_at);
+
+-- Weighbridge upkeep (scale_maintenance.rs): work scheduled for a local
+-- due_date, then recorded when it is done
+CREATE TABLE IF NOT EXISTS scale_maintenance (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('CALIBRATION', 'STAMPING', 'INSPECTION', 'REPAIR', 'CLEANING', 'OTHER')),
+    description TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'SCHEDULED' CHECK (status IN ('SCHEDULED', 'DONE', 'CANCELLED')),
+    due_date TEXT,
+    performed_at DATETIME,
+    performed_by TEXT,
+    notes TEXT,
+    reminded_at DATETIME,
+    created_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_scale_maintenance_due ON scale_maintenance(status, due_date);
+
+-- Windows the bridge was out of use; ended_at is NULL while it still is
+CREATE TABLE IF NOT EXISTS scale_downtime (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    reason TEXT NOT NULL,
+    started_at DATETIME NOT NULL,
+    ended_at DATETIME,
+    maintenance_id INTEGER,
+    recorded_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    CHECK (ended_at IS NULL OR ended_at >= started_at),
+    FOREIGN KEY (maintenance_id) REFERENCES scale_maintenance(id)
+);
+CREATE INDEX IF NOT EXISTS idx_scale_downtime_started ON scale_downtime(started_at);
+
+-- Driver's acknowledgement of a ticket (driver_signatures.rs): the
+-- signature image is an attachment reference; source is TOUCH or PAD
+CREATE TABLE IF NOT EXISTS driver_signatures (
+    weighment_id TEXT PRIMARY KEY,
+    signer_name TEXT,
+    attachment TEXT NOT NULL,
+    source TEXT NOT NULL CHECK (source IN ('TOUCH', 'PAD')),
+    captured_by TEXT NOT NULL,
+    captured_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+
+-- Vehicle classes (vehicle_classes.rs): the permitted gross vehicle weight
+-- and plausible tare range of a kind of truck, in the class's unit
+CREATE TABLE IF NOT EXISTS vehicle_classes (
+    name TEXT PRIMARY KEY,
+    unit TEXT NOT NULL DEFAULT 'KG' CHECK (unit IN ('KG', 'TONNE')),
+    gvw REAL NOT NULL CHECK (gvw > 0),
+    min_tare REAL,
+    max_tare REAL,
+    axle_count INTEGER CHECK (axle_count IS NULL OR axle_count > 0),
+    description TEXT,
+    updated_by TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    CHECK (min_tare IS NULL OR max_tare IS NULL OR min_tare <= max_tare)
+);
+
+-- The class each vehicle belongs to, by registration number
+CREATE TABLE IF NOT EXISTS vehicle_class_members (
+    vehicle_no TEXT PRIMARY KEY,
+    class_name TEXT NOT NULL,
+    assigned_by TEXT NOT NULL,
+    assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (class_name) REFERENCES vehicle_classes(name)
+);
+
+-- Weighings whose weights did not fit the vehicle's class while validation
+-- was on; OVERRIDDEN rows carry the supervisor override that let one through
+CREATE TABLE IF NOT EXISTS vehicle_class_violations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    weighment_id TEXT NOT NULL,
+    event TEXT NOT NULL CHECK (event IN ('FIRST_WEIGHT', 'SECOND_WEIGHT')),
+    class_name TEXT NOT NULL,
+    reason TEXT NOT NULL CHECK (reason IN ('TARE_BELOW_CLASS', 'TARE_ABOVE_CLASS', 'GROSS_ABOVE_GVW')),
+    weight_kg REAL NOT NULL,
+    limit_kg REAL NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('FLAGGED', 'OVERRIDDEN')),
+    override_id INTEGER,
+    occurred_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (weighment_id, event, reason),
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id),
+    FOREIGN KEY (override_id) REFERENCES supervisor_overrides(id)
+);
+CREATE INDEX IF NOT EXISTS idx_vehicle_class_violations_occurred ON vehicle_class_violations(occurred_at);
+
+-- Prints of tickets (print_jobs.rs): SENT until the printer or the operator
+-- confirms the output; FAILED and unconfirmed jobs make up the reprint queue
+CREATE TABLE IF NOT EXISTS print_jobs (
+    id TEXT PRIMARY KEY,
+    weighment_id TEXT,
+    ticket_no TEXT NOT NULL,
+    channel TEXT NOT NULL CHECK (channel IN ('THERMAL', 'SLIP')),
+    printer TEXT,
+    status TEXT NOT NULL DEFAULT 'SENT' CHECK (status IN ('SENT', 'CONFIRMED', 'FAILED', 'DISMISSED')),
+    confirmed_by TEXT CHECK (confirmed_by IN ('PRINTER', 'OPERATOR')),
+    error TEXT,
+    requested_by TEXT,
+    operator TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    finished_at DATETIME,
+    FOREIGN KEY (weighment_id) REFERENCES weighments(id)
+);
+CREATE INDEX IF NOT EXISTS idx_print_jobs_ticket ON print_jobs(ticket_no);
+CREATE INDEX IF NOT EXISTS idx_print_jobs_status ON print_jobs(status);
+
+-- Named places exports go (export_destinations.rs); config holds the
+-- kind's settings as JSON, secrets stay in the settings store
+CREATE TABLE IF NOT EXISTS export_destinations (
+    name TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('FILE', 'USB', 'EMAIL', 'FTP', 'S3', 'WEBHOOK')),
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    config TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Every export handed to a destination, delivered or not
+CREATE TABLE IF NOT EXISTS export_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    destination TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    source TEXT NOT NULL,
+    format TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    rows INTEGER,
+    size_bytes INTEGER NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('DELIVERED', 'FAILED')),
+    location TEXT,
+    error TEXT,
+    requested_by TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_export_deliveries_destination ON export_deliveries(destination);
+
+-- What auditors' sessions invoked and the records their queries returned
+-- (auditor.rs)
+CREATE TABLE IF NOT EXISTS auditor_views (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    command TEXT NOT NULL,
+    args TEXT,
+    records TEXT,
+    viewed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_auditor_views_user ON auditor_views(user_id, viewed_at);
+
+-- Deleted master and configuration rows, kept by AFTER DELETE triggers so
+-- they can be restored (undelete.rs). audit_after is the newest audit log id
+-- when the row went, to find the audited statement that deleted it.
+CREATE TABLE IF NOT EXISTS deleted_records (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    row_key TEXT NOT NULL,
+    row_data TEXT NOT NULL,
+    audit_after INTEGER NOT NULL,
+    deleted_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    restored_at DATETIME,
+    restored_by TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_deleted_records_row ON deleted_records(table_name, row_key);
+
+-- Tickets sent, or waiting to be sent, to the state mining portal
+-- (mining_portal.rs)
+CREATE TABLE IF NOT EXISTS mining_portal_submissions (
+    weighment_id TEXT PRIMARY KEY,
+    ticket_no TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'PENDING'
+        CHECK (status IN ('PENDING', 'SUBMITTED', 'FAILED', 'REJECTED')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    portal_reference TEXT,
+    payload TEXT,
+    queued_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_attempt_at DATETIME,
+    next_attempt_at DATETIME,
+    submitted_at DATETIME
+);
+CREATE INDEX IF NOT EXISTS idx_mining_portal_submissions_status ON mining_portal_submissions(status, next_attempt_at);
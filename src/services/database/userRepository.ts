@@ -3,7 +3,14 @@
 
 import { v4 as uuidv4 } from 'uuid';
 import bcrypt from 'bcryptjs';
-import { executeQuery, executeNonQuery } from './connection';
+import {
+  executeQuery,
+  executeNonQuery,
+  invoke,
+  getSessionToken,
+  setSessionToken,
+} from './connection';
+import { isDevelopmentMode } from './localStorageAdapter';
 
 export interface User {
   id: string;
@@ -32,6 +39,17 @@ interface UserRow {
   updated_at: string;
 }
 
+export interface SessionUser {
+  id: string;
+  username: string;
+  role: 'super_admin' | 'admin' | 'operator';
+}
+
+interface LoginResult {
+  token: string;
+  user: SessionUser;
+}
+
 const BCRYPT_ROUNDS = 12;
 const LOCK_DURATION_MINUTES = 30;
 const MAX_FAILED_ATTEMPTS = 5;
@@ -178,6 +196,47 @@ export async function verifyUserCredentials(
   return { user };
 }
 
+/**
+ * Sign in through the backend (desktop mode). The backend checks the
+ * password, counts failures and issues the session token every later
+ * command needs.
+ */
+export async function signIn(username: string, password: string): Promise<SessionUser> {
+  const result = await invoke<LoginResult>('verify_login', { username, password });
+  setSessionToken(result.token);
+  return result.user;
+}
+
+/**
+ * End the backend session, if there is one
+ */
+export async function signOut(): Promise<void> {
+  const token = getSessionToken();
+  setSessionToken(null);
+  if (token && !isDevelopmentMode()) {
+    await invoke('logout', { token }).catch(() => undefined);
+  }
+}
+
+/**
+ * Create the first super admin. On the desktop the backend does this, as
+ * nobody is signed in yet to write the users table.
+ */
+export async function createInitialAdmin(params: {
+  username: string;
+  password: string;
+  email?: string | null;
+}): Promise<string> {
+  if (isDevelopmentMode()) {
+    return createUser({ ...params, role: 'super_admin' });
+  }
+  return invoke<string>('create_initial_admin', {
+    username: params.username,
+    password: params.password,
+    email: params.email || null,
+  });
+}
+
 /**
  * Increment failed login attempts and lock account if needed
  */
@@ -319,6 +378,19 @@ export async function changePassword(
   oldPassword: string,
   newPassword: string
 ): Promise<{ success: boolean; error?: string }> {
+  if (!isDevelopmentMode()) {
+    try {
+      await invoke('change_password', {
+        token: getSessionToken(),
+        currentPassword: oldPassword,
+        newPassword,
+      });
+      return { success: true };
+    } catch (error: any) {
+      return { success: false, error: error?.message || 'Failed to change password' };
+    }
+  }
+
   const rows = await executeQuery<UserRow>(
     'SELECT password_hash FROM users WHERE id = ?',
     [userId]
@@ -1,7 +1,7 @@
 // Desktop Bill Service - Tauri Commands
 // Replaces HTTP API calls with SQLite database operations via Tauri
 
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '@/services/database/connection';
 import { Bill, BillStatus } from '@/types/weighment';
 
 // Verify Tauri is available before any operation
@@ -28,7 +28,7 @@ export const getBills = async (): Promise<Bill[]> => {
         vehicle_no as vehicleNo, party_name as partyName,
         product_name as productName, gross_weight as grossWeight,
         tare_weight as tareWeight, net_weight as netWeight,
-        charges, front_camera_image as frontImage,
+        charges_minor as chargesMinor, front_camera_image as frontImage,
         back_camera_image as rearImage, status,
         created_at as createdAt, updated_at as updatedAt,
         first_weight_type as firstWeightType,
@@ -64,7 +64,7 @@ export const saveBill = async (bill: Bill): Promise<{ success: boolean; error: s
     await invoke('execute_non_query', {
       query: `INSERT INTO weighments (
         id, bill_no, ticket_no, vehicle_no, party_name, product_name,
-        gross_weight, tare_weight, net_weight, charges,
+        gross_weight, tare_weight, net_weight, charges_minor,
         front_camera_image, back_camera_image, status,
         first_weight_type, first_vehicle_status, second_vehicle_status,
         second_weight_timestamp, created_at, updated_at, closed_at, remarks
@@ -79,7 +79,7 @@ export const saveBill = async (bill: Bill): Promise<{ success: boolean; error: s
         bill.grossWeight,
         bill.tareWeight,
         bill.netWeight,
-        bill.charges,
+        bill.chargesMinor,
         bill.frontImage,
         bill.rearImage,
         bill.status,
@@ -143,7 +143,7 @@ export const getBillById = async (billId: string): Promise<Bill | null> => {
         vehicle_no as vehicleNo, party_name as partyName,
         product_name as productName, gross_weight as grossWeight,
         tare_weight as tareWeight, net_weight as netWeight,
-        charges, front_camera_image as frontImage,
+        charges_minor as chargesMinor, front_camera_image as frontImage,
         back_camera_image as rearImage, status,
         created_at as createdAt, updated_at as updatedAt,
         first_weight_type as firstWeightType,
@@ -195,7 +195,7 @@ export const searchBills = async (query: string): Promise<Bill[]> => {
         vehicle_no as vehicleNo, party_name as partyName,
         product_name as productName, gross_weight as grossWeight,
         tare_weight as tareWeight, net_weight as netWeight,
-        charges, front_camera_image as frontImage,
+        charges_minor as chargesMinor, front_camera_image as frontImage,
         back_camera_image as rearImage, status,
         created_at as createdAt, updated_at as updatedAt,
         first_weight_type as firstWeightType,
@@ -234,7 +234,7 @@ export const filterBillsByDateRange = async (
         vehicle_no as vehicleNo, party_name as partyName,
         product_name as productName, gross_weight as grossWeight,
         tare_weight as tareWeight, net_weight as netWeight,
-        charges, front_camera_image as frontImage,
+        charges_minor as chargesMinor, front_camera_image as frontImage,
         back_camera_image as rearImage, status,
         created_at as createdAt, updated_at as updatedAt,
         first_weight_type as firstWeightType,
@@ -1,5 +1,5 @@
 // Desktop Master Data Service - Uses Tauri SQLite Database
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '@/services/database/connection';
 import { Vehicle, Party, Product } from '@/utils/mockData';
 import * as serialNumberService from './serialNumberService';
 
@@ -1,5 +1,5 @@
 // Desktop Open Ticket Service - Uses Tauri SQLite Database
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '@/services/database/connection';
 import { OpenTicket } from '@/types/weighment';
 
 /**
@@ -23,7 +23,7 @@ export const getOpenTickets = async (): Promise<OpenTicket[]> => {
       tareWeight: row.tare_weight,
       firstWeightType: row.first_weight_type as 'gross' | 'tare',
       date: row.date,
-      charges: row.charges || 0,
+      chargesMinor: row.charges_minor || 0,
       capturedImage: row.captured_image,
       frontImage: row.front_image,
       rearImage: row.rear_image,
@@ -43,7 +43,7 @@ export const saveOpenTicket = async (ticket: OpenTicket): Promise<{ success: boo
       query: `
         INSERT INTO open_tickets (
           id, ticket_no, vehicle_no, party_name, product_name, vehicle_status,
-          gross_weight, tare_weight, first_weight_type, date, charges,
+          gross_weight, tare_weight, first_weight_type, date, charges_minor,
           captured_image, front_image, rear_image
         ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
       `,
@@ -58,7 +58,7 @@ export const saveOpenTicket = async (ticket: OpenTicket): Promise<{ success: boo
         ticket.tareWeight,
         ticket.firstWeightType,
         ticket.date,
-        ticket.charges,
+        ticket.chargesMinor,
         ticket.capturedImage,
         ticket.frontImage,
         ticket.rearImage,
@@ -113,7 +113,7 @@ export const getOpenTicketById = async (ticketId: string): Promise<OpenTicket |
       tareWeight: row.tare_weight,
       firstWeightType: row.first_weight_type as 'gross' | 'tare',
       date: row.date,
-      charges: row.charges || 0,
+      chargesMinor: row.charges_minor || 0,
       capturedImage: row.captured_image,
       frontImage: row.front_image,
       rearImage: row.rear_image,
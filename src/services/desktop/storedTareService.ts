@@ -1,5 +1,5 @@
 // Desktop Stored Tare Service - Uses Tauri SQLite Database
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '@/services/database/connection';
 import { StoredTare } from '@/types/weighment';
 
 /**
@@ -15,7 +15,7 @@ export interface Bill {
   grossWeight: number | null;
   tareWeight: number | null;
   netWeight: number | null;
-  charges: number;
+  chargesMinor: number; // paise
   capturedImage: string | null; // Deprecated - kept for backward compatibility
   frontImage: string | null;
   rearImage: string | null;
@@ -42,7 +42,7 @@ export interface OpenTicket {
   tareWeight: number | null;
   firstWeightType: 'gross' | 'tare';
   date: string;
-  charges: number;
+  chargesMinor: number; // paise
   capturedImage: string | null; // Deprecated - kept for backward compatibility
   frontImage: string | null;
   rearImage: string | null;
@@ -1,4 +1,5 @@
 import { Bill } from '@/types/weighment';
+import { formatMinor } from '@/lib/money';
 import * as XLSX from 'xlsx';
 import jsPDF from 'jspdf';
 import 'jspdf-autotable';
@@ -16,7 +17,7 @@ export const exportBillsToExcel = (bills: Bill[], filename: string = 'weighment-
     'Gross Weight (KG)': bill.grossWeight || 'N/A',
     'Tare Weight (KG)': bill.tareWeight || 'N/A',
     'Net Weight (KG)': bill.netWeight || 'N/A',
-    'Charges (₹)': bill.charges,
+    'Charges (₹)': formatMinor(bill.chargesMinor),
     'Status': bill.status
   }));
 
@@ -45,7 +46,7 @@ export const exportBillsToCSV = (bills: Bill[], filename: string = 'weighment-bi
     'Gross Weight (KG)': bill.grossWeight || 'N/A',
     'Tare Weight (KG)': bill.tareWeight || 'N/A',
     'Net Weight (KG)': bill.netWeight || 'N/A',
-    'Charges (₹)': bill.charges,
+    'Charges (₹)': formatMinor(bill.chargesMinor),
     'Status': bill.status
   }));
 
@@ -99,11 +100,11 @@ export const exportBillsToPDF = (bills: Bill[], filename: string = 'weighment-re
   // Summary
   const finalY = (doc as any).lastAutoTable.finalY || 45;
   const totalNet = bills.reduce((sum, bill) => sum + (bill.netWeight || 0), 0);
-  const totalCharges = bills.reduce((sum, bill) => sum + bill.charges, 0);
+  const totalChargesMinor = bills.reduce((sum, bill) => sum + bill.chargesMinor, 0);
   
   doc.setFontSize(12);
   doc.text(`Total Net Weight: ${totalNet.toLocaleString()} KG`, 14, finalY + 15);
-  doc.text(`Total Charges: ₹${totalCharges.toFixed(2)}`, 14, finalY + 22);
+  doc.text(`Total Charges: ₹${formatMinor(totalChargesMinor)}`, 14, finalY + 22);
   
   doc.save(`${filename}-${new Date().toISOString().split('T')[0]}.pdf`);
 };